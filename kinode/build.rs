@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     fs::{self, File},
     io::{BufReader, Cursor, Read, Write},
     path::{Path, PathBuf},
@@ -108,6 +109,23 @@ fn get_kinode_book(packages_dir: &Path) -> anyhow::Result<()> {
     })
 }
 
+/// read hashes of package zips in the format of `b3sum`: a hash and a
+/// filename on each line. lines starting with `#` are comments.
+fn read_hashes(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+    fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("couldn't read {}: {e}", path.display()))?
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once(char::is_whitespace) {
+            Some((hash, filename)) => Ok((filename.trim().to_string(), hash.to_string())),
+            None => Err(anyhow::anyhow!(
+                "{} has a malformed line: {line}",
+                path.display()
+            )),
+        })
+        .collect()
+}
+
 fn build_and_zip_package(
     entry_path: PathBuf,
     parent_pkg_path: &str,
@@ -134,13 +152,16 @@ fn build_and_zip_package(
         .map_err(|e| anyhow::anyhow!("{:?}", e))?;
 
         let mut writer = Cursor::new(Vec::new());
+        // a fixed timestamp and order, so that the same package always makes
+        // the same zip, and a release's hashes can be reproduced
         let options = FileOptions::default()
             .compression_method(zip::CompressionMethod::Deflated)
-            .unix_permissions(0o755);
+            .unix_permissions(0o755)
+            .last_modified_time(zip::DateTime::default());
         {
             let mut zip = zip::ZipWriter::new(&mut writer);
 
-            for sub_entry in walkdir::WalkDir::new(parent_pkg_path) {
+            for sub_entry in walkdir::WalkDir::new(parent_pkg_path).sort_by_file_name() {
                 let sub_entry = sub_entry?;
                 let path = sub_entry.path();
                 let name = path.strip_prefix(Path::new(parent_pkg_path))?;
@@ -222,9 +243,19 @@ fn main() -> anyhow::Result<()> {
         })
         .collect();
 
+    // a release's hashes, given in KINODE_DISTRO_HASHES, to check that this
+    // build reproduces it: a zip that doesn't match fails the build
+    println!("cargo::rerun-if-env-changed=KINODE_DISTRO_HASHES");
+    let release_hashes = match std::env::var("KINODE_DISTRO_HASHES") {
+        Ok(path) => Some(read_hashes(Path::new(&path))?),
+        Err(_) => None,
+    };
+    let mut mismatched = vec![];
+
     // Process results, e.g., write to `bootstrapped_processes.rs`
     // This part remains sequential
     let mut bootstrapped_processes = vec![];
+    let mut hashes = vec![];
     writeln!(
         bootstrapped_processes,
         "pub static BOOTSTRAPPED_PROCESSES: &[(&str, &[u8], &[u8])] = &["
@@ -238,6 +269,39 @@ fn main() -> anyhow::Result<()> {
                 let zip_path = format!("{}/target/{}", parent_dir.display(), zip_filename);
                 fs::write(&zip_path, &zip_contents)?;
 
+                let hash = blake3::hash(&zip_contents).to_hex().to_string();
+                if let Some(ref release_hashes) = release_hashes {
+                    if release_hashes.get(&zip_filename) != Some(&hash) {
+                        mismatched.push(format!(
+                            "{zip_filename}: built {hash}, expected {}",
+                            release_hashes
+                                .get(&zip_filename)
+                                .map_or("none", String::as_str)
+                        ));
+                    }
+                }
+                writeln!(hashes, "{hash}  {zip_filename}")?;
+
+                // a package's metadata leaves the hash of its current version
+                // empty, as it is only known once built: fill it in
+                let mut metadata: serde_json::Value =
+                    serde_json::from_slice(&fs::read(&metadata_path)?)?;
+                let properties = &mut metadata["properties"];
+                let current_version = properties["current_version"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                let code_hash = &mut properties["code_hashes"][current_version];
+                if code_hash.as_str().map_or(true, str::is_empty) {
+                    *code_hash = serde_json::Value::String(hash.clone());
+                }
+                let metadata_path = format!(
+                    "{}/target/{}.metadata.json",
+                    parent_dir.display(),
+                    zip_filename.trim_end_matches(".zip"),
+                );
+                fs::write(&metadata_path, serde_json::to_vec_pretty(&metadata)?)?;
+
                 writeln!(
                     bootstrapped_processes,
                     "    (\"{}\", include_bytes!(\"{}\"), include_bytes!(\"{}\")),",
@@ -247,6 +311,12 @@ fn main() -> anyhow::Result<()> {
             Err(e) => return Err(e),
         }
     }
+    if !mismatched.is_empty() {
+        return Err(anyhow::anyhow!(
+            "distro packages don't reproduce KINODE_DISTRO_HASHES:\n{}",
+            mismatched.join("\n")
+        ));
+    }
 
     writeln!(bootstrapped_processes, "];")?;
     let target_dir = pwd.join("../target");
@@ -255,6 +325,10 @@ fn main() -> anyhow::Result<()> {
     }
     let bootstrapped_processes_path = target_dir.join("bootstrapped_processes.rs");
     fs::write(&bootstrapped_processes_path, bootstrapped_processes)?;
+    // in the format of `b3sum`, so that a release can publish it, for a node
+    // to check its packages against with `--distro-hashes`, and a build to
+    // be checked against with KINODE_DISTRO_HASHES
+    fs::write(target_dir.join("distro_hashes.txt"), hashes)?;
 
     Ok(())
 }
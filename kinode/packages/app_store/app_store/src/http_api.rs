@@ -1,11 +1,15 @@
 use crate::{
-    kinode::process::chain::{ChainRequests, ChainResponses},
+    kinode::process::chain::{ChainRequests, ChainResponses, SearchRequest},
     kinode::process::downloads::{
-        DownloadRequests, DownloadResponses, LocalDownloadRequest, RemoveFileRequest,
+        DownloadRequests, DownloadResponses, FetchRequest, LocalDownloadRequest, MirrorConfig,
+        RemoveFileRequest, SetMirrorsRequest,
     },
+    kinode::process::main::{RequestedCapability, UpdatePolicy},
     state::{MirrorCheck, PackageState, State},
+    utils,
 };
 
+use base64::Engine;
 use kinode_process_lib::{
     http::{self, server, Method, StatusCode},
     Address, LazyLoadBlob, PackageId, Request,
@@ -16,6 +20,9 @@ use std::{collections::HashMap, str::FromStr};
 
 const ICON: &str = include_str!("icon");
 
+/// search results given when no limit is asked for
+const DEFAULT_SEARCH_LIMIT: u32 = 20;
+
 /// Bind static and dynamic HTTP paths for the app store,
 /// bind to our WS updates path, and add icon and widget to homepage.
 pub fn init_frontend(our: &Address, http_server: &mut server::HttpServer) {
@@ -26,16 +33,25 @@ pub fn init_frontend(our: &Address, http_server: &mut server::HttpServer) {
         "/downloads",     // all downloads
         "/installed",     // all installed apps
         "/ourapps",       // all apps we've published
+        "/search",        // search on-chain apps, by query and category
+        "/categories",    // the categories on-chain apps are in
         "/apps/:id",      // detail about an on-chain app
         "/downloads/:id", // local downloads for an app
         "/installed/:id", // detail about an installed app
         // actions
-        "/apps/:id/download",    // download a listed app
-        "/apps/:id/install",     // install a downloaded app
-        "/downloads/:id/mirror", // start mirroring a version of a downloaded app
-        "/downloads/:id/remove", // remove a downloaded app
-        "/apps/:id/auto-update", // set auto-updating a version of a downloaded app
-        "/mirrorcheck/:node",    // check if a node/mirror is online/offline
+        "/apps/:id/download",      // download a listed app
+        "/apps/:id/install",       // install a downloaded app
+        "/apps/:id/caps",          // review capabilities requested by a downloaded app
+        "/downloads/:id/mirror",   // start mirroring a version of a downloaded app
+        "/downloads/:id/remove",   // remove a downloaded app
+        "/apps/:id/auto-update",   // set auto-updating a version of a downloaded app
+        "/apps/:id/update-policy", // set how an installed app is kept up to date
+        "/mirrorcheck/:node",      // check if a node/mirror is online/offline
+        "/mirrors",                // health of all known mirrors
+        "/mirrors/:id",            // configured mirrors for an app, and their health
+        "/lint",                   // check a package zip before publishing it
+        "/permissions",            // what every installed app can do
+        "/permissions/:id",        // what an installed app can do
     ] {
         http_server
             .bind_http_path(path, config.clone())
@@ -185,13 +201,19 @@ fn make_widget() -> String {
 /// - remove a downloaded app: POST /downloads/:id/remove
 
 /// - get online/offline mirrors for a listed app: GET /mirrorcheck/:node
+/// - get health of all known mirrors: GET /mirrors
+/// - get configured mirrors for an app and their health: GET /mirrors/:id
+/// - set configured mirrors for an app: PUT /mirrors/:id
 /// - download a listed app: POST /apps/:id/download
+/// - get capabilities requested by a downloaded app: GET /apps/:id/caps?version_hash=...
 /// - install a downloaded app: POST /apps/:id/install
 /// - uninstall/delete a downloaded app: DELETE /apps/:id
+/// - report what uninstalling an app would remove: DELETE /apps/:id?dry_run=true
 /// - start mirroring a downloaded app: PUT /apps/:id/mirror
 /// - stop mirroring a downloaded app: DELETE /apps/:id/mirror
 /// - start auto-updating a downloaded app: PUT /apps/:id/auto-update
 /// - stop auto-updating a downloaded app: DELETE /apps/:id/auto-update
+/// - set the update policy of an installed app: PUT /apps/:id/update-policy
 ///
 /// - RebuildIndex: POST /apps/rebuild-index // TODO, this could be just terminal I think?
 pub fn handle_http_request(
@@ -226,7 +248,11 @@ fn get_package_id(url_params: &HashMap<String, String>) -> anyhow::Result<Packag
     Ok(id)
 }
 
-fn gen_package_info(id: &PackageId, state: &PackageState) -> serde_json::Value {
+fn gen_package_info(
+    id: &PackageId,
+    state: &PackageState,
+    update_policy: UpdatePolicy,
+) -> serde_json::Value {
     // installed package info
     json!({
         "package_id": {
@@ -238,6 +264,8 @@ fn gen_package_info(id: &PackageId, state: &PackageState) -> serde_json::Value {
         "our_version_hash": state.our_version_hash,
         "verified": state.verified,
         "caps_approved": state.caps_approved,
+        "denied_caps": state.denied_caps,
+        "update_policy": update_policy,
     })
 }
 
@@ -265,6 +293,45 @@ fn serve_paths(
                 _ => Err(anyhow::anyhow!("Invalid response from chain: {:?}", msg)),
             }
         }
+        // GET apps matching ?q=<words>, in ?category=<category>, at most
+        // ?limit=<n> of them, with install counts unless ?counts=false
+        "/search" => {
+            let params = req.query_params();
+            let limit = params
+                .get("limit")
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_SEARCH_LIMIT);
+            let install_counts = params.get("counts").map(|v| v.as_str()) != Some("false");
+            let request = ChainRequests::Search(SearchRequest {
+                query: params.get("q").cloned(),
+                category: params.get("category").cloned(),
+                limit,
+                install_counts,
+            });
+            let resp = Request::to(("our", "chain", "app_store", "sys"))
+                .body(serde_json::to_vec(&request)?)
+                .send_and_await_response(5)??;
+            let msg = serde_json::from_slice::<ChainResponses>(resp.body())?;
+            match msg {
+                ChainResponses::Search(results) => {
+                    Ok((StatusCode::OK, None, serde_json::to_vec(&results)?))
+                }
+                _ => Err(anyhow::anyhow!("Invalid response from chain: {:?}", msg)),
+            }
+        }
+        // GET the categories apps are in, with how many are in each
+        "/categories" => {
+            let resp = Request::to(("our", "chain", "app_store", "sys"))
+                .body(serde_json::to_vec(&ChainRequests::GetCategories)?)
+                .send_and_await_response(5)??;
+            let msg = serde_json::from_slice::<ChainResponses>(resp.body())?;
+            match msg {
+                ChainResponses::GetCategories(categories) => {
+                    Ok((StatusCode::OK, None, serde_json::to_vec(&categories)?))
+                }
+                _ => Err(anyhow::anyhow!("Invalid response from chain: {:?}", msg)),
+            }
+        }
         // GET detail about a specific app
         // update a downloaded app: PUT
         "/apps/:id" => {
@@ -292,6 +359,12 @@ fn serve_paths(
                     }
                 }
                 Method::DELETE => {
+                    // with ?dry_run=true, only report what would be removed
+                    if req.query_params().get("dry_run").map(|v| v.as_str()) == Some("true") {
+                        let report =
+                            crate::utils::uninstall_report(our.node(), state, &package_id)?;
+                        return Ok((StatusCode::OK, None, serde_json::to_vec(&report)?));
+                    }
                     // uninstall an app
                     crate::utils::uninstall(state, &package_id)?;
                     Ok((
@@ -361,7 +434,9 @@ fn serve_paths(
             let all: Vec<serde_json::Value> = state
                 .packages
                 .iter()
-                .map(|(package_id, listing)| gen_package_info(package_id, listing))
+                .map(|(package_id, listing)| {
+                    gen_package_info(package_id, listing, state.update_policy(package_id))
+                })
                 .collect();
             return Ok((StatusCode::OK, None, serde_json::to_vec(&all)?));
         }
@@ -376,7 +451,9 @@ fn serve_paths(
             let specific_package_info = state
                 .packages
                 .get(&package_id)
-                .map(|listing| gen_package_info(&package_id, listing))
+                .map(|listing| {
+                    gen_package_info(&package_id, listing, state.update_policy(&package_id))
+                })
                 .ok_or_else(|| {
                     anyhow::Error::new(std::io::Error::new(
                         std::io::ErrorKind::NotFound,
@@ -411,27 +488,36 @@ fn serve_paths(
                     format!("Missing id").into_bytes(),
                 ));
             };
-            // from POST body, look for download_from field and use that as the mirror
+            // from POST body, look for download_from field and use that as the mirror.
+            // without one, fetch by hash from the configured mirrors, falling back
+            // to any listed in the `mirrors` field.
             let body = crate::get_blob()
                 .ok_or(anyhow::anyhow!("missing blob"))?
                 .bytes;
             let body_json: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
-            let download_from = body_json
-                .get("download_from")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string())
-                .ok_or_else(|| anyhow::anyhow!("No download_from specified!"))?;
             let version_hash = body_json
                 .get("version_hash")
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string())
                 .ok_or_else(|| anyhow::anyhow!("No version_hash specified!"))?;
+            let package_id = crate::kinode::process::main::PackageId::from_process_lib(package_id);
 
-            let download_request = DownloadRequests::LocalDownload(LocalDownloadRequest {
-                package_id: crate::kinode::process::main::PackageId::from_process_lib(package_id),
-                download_from: download_from.clone(),
-                desired_version_hash: version_hash,
-            });
+            let download_request = match body_json.get("download_from").and_then(|v| v.as_str()) {
+                Some(download_from) => DownloadRequests::LocalDownload(LocalDownloadRequest {
+                    package_id,
+                    download_from: download_from.to_string(),
+                    desired_version_hash: version_hash,
+                }),
+                None => DownloadRequests::Fetch(FetchRequest {
+                    package_id,
+                    version_hash,
+                    extra_mirrors: body_json
+                        .get("mirrors")
+                        .map(|v| serde_json::from_value::<Vec<String>>(v.clone()))
+                        .transpose()?
+                        .unwrap_or_default(),
+                }),
+            };
 
             Request::to(("our", "downloads", "app_store", "sys"))
                 .body(serde_json::to_vec(&download_request)?)
@@ -464,6 +550,28 @@ fn serve_paths(
                 .map(|s| s.to_string())
                 .ok_or_else(|| anyhow::anyhow!("No version_hash specified!"))?;
 
+            // the user must have reviewed and approved the requested capabilities
+            if !body_json
+                .get("caps_approved")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false)
+            {
+                let manifest = crate::utils::fetch_downloaded_manifest(&package_id, &version_hash)?;
+                return Ok((
+                    StatusCode::FORBIDDEN,
+                    None,
+                    serde_json::to_vec(&json!({
+                        "error": "capabilities must be approved before install",
+                        "requested_caps": crate::utils::requested_capabilities(&manifest),
+                    }))?,
+                ));
+            }
+            let denied_caps: Vec<RequestedCapability> = body_json
+                .get("denied_caps")
+                .map(|v| serde_json::from_value(v.clone()))
+                .transpose()?
+                .unwrap_or_default();
+
             let process_package_id =
                 crate::kinode::process::main::PackageId::from_process_lib(package_id);
 
@@ -471,6 +579,7 @@ fn serve_paths(
                 &process_package_id,
                 None,
                 &version_hash,
+                &denied_caps,
                 state,
                 &our.node().to_string(),
             ) {
@@ -482,6 +591,43 @@ fn serve_paths(
                 )),
             }
         }
+        // GET the capabilities requested by a downloaded app, for user review.
+        // if no version_hash query param is given, use our installed version.
+        "/apps/:id/caps" => {
+            let Ok(package_id) = get_package_id(url_params) else {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    None,
+                    format!("Missing id").into_bytes(),
+                ));
+            };
+            if method != Method::GET {
+                return Ok((
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    None,
+                    format!("Invalid method {method} for {bound_path}").into_bytes(),
+                ));
+            }
+            let version_hash = match req.query_params().get("version_hash") {
+                Some(version_hash) => version_hash.clone(),
+                None => match state.packages.get(&package_id) {
+                    Some(package) => package.our_version_hash.clone(),
+                    None => {
+                        return Ok((
+                            StatusCode::BAD_REQUEST,
+                            None,
+                            format!("Missing version_hash").into_bytes(),
+                        ))
+                    }
+                },
+            };
+            let manifest = crate::utils::fetch_downloaded_manifest(&package_id, &version_hash)?;
+            Ok((
+                StatusCode::OK,
+                None,
+                serde_json::to_vec(&crate::utils::requested_capabilities(&manifest))?,
+            ))
+        }
         // start mirroring a downloaded app: PUT
         // stop mirroring a downloaded app: DELETE
         "/downloads/:id/mirror" => {
@@ -583,12 +729,24 @@ fn serve_paths(
         "/apps/:id/auto-update" => {
             let package_id = get_package_id(url_params)?;
 
-            let chain_request = match method {
-                Method::PUT => ChainRequests::StartAutoUpdate(
-                    crate::kinode::process::main::PackageId::from_process_lib(package_id),
+            // the auto-update toggle also sets the package's update policy,
+            // which decides whether fetched updates are installed
+            let (chain_request, policy) = match method {
+                Method::PUT => (
+                    ChainRequests::StartAutoUpdate(
+                        crate::kinode::process::main::PackageId::from_process_lib(
+                            package_id.clone(),
+                        ),
+                    ),
+                    UpdatePolicy::Auto,
                 ),
-                Method::DELETE => ChainRequests::StopAutoUpdate(
-                    crate::kinode::process::main::PackageId::from_process_lib(package_id),
+                Method::DELETE => (
+                    ChainRequests::StopAutoUpdate(
+                        crate::kinode::process::main::PackageId::from_process_lib(
+                            package_id.clone(),
+                        ),
+                    ),
+                    UpdatePolicy::NotifyOnly,
                 ),
                 _ => {
                     return Ok((
@@ -605,9 +763,11 @@ fn serve_paths(
 
             let msg = serde_json::from_slice::<ChainResponses>(resp.body())?;
             match msg {
-                ChainResponses::AutoUpdateStarted
-                | ChainResponses::AutoUpdateStopped
-                | ChainResponses::Error(_) => Ok((StatusCode::OK, None, serde_json::to_vec(&msg)?)),
+                ChainResponses::AutoUpdateStarted | ChainResponses::AutoUpdateStopped => {
+                    utils::set_update_policy(state, &package_id, policy);
+                    Ok((StatusCode::OK, None, serde_json::to_vec(&msg)?))
+                }
+                ChainResponses::Error(_) => Ok((StatusCode::OK, None, serde_json::to_vec(&msg)?)),
                 _ => Ok((
                     StatusCode::INTERNAL_SERVER_ERROR,
                     None,
@@ -615,6 +775,203 @@ fn serve_paths(
                 )),
             }
         }
+        // PUT the update policy of an installed app:
+        // body is {"policy": "auto" | "notify-only" | "pinned"}
+        "/apps/:id/update-policy" => {
+            if method != Method::PUT {
+                return Ok((
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    None,
+                    format!("Invalid method {method} for {bound_path}").into_bytes(),
+                ));
+            }
+            let package_id = get_package_id(url_params)?;
+            let body = crate::get_blob()
+                .ok_or(anyhow::anyhow!("missing blob"))?
+                .bytes;
+            let body_json: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+            let policy = match body_json.get("policy").and_then(|p| p.as_str()) {
+                Some("auto") => UpdatePolicy::Auto,
+                Some("notify-only") => UpdatePolicy::NotifyOnly,
+                Some("pinned") => UpdatePolicy::Pinned,
+                _ => {
+                    return Ok((
+                        StatusCode::BAD_REQUEST,
+                        None,
+                        format!("policy must be one of auto, notify-only, pinned").into_bytes(),
+                    ))
+                }
+            };
+            if !utils::set_update_policy(state, &package_id, policy) {
+                return Ok((
+                    StatusCode::NOT_FOUND,
+                    None,
+                    format!("Package {package_id} is not installed").into_bytes(),
+                ));
+            }
+            Ok((StatusCode::OK, None, vec![]))
+        }
+        // GET health of all known mirrors
+        "/mirrors" => {
+            if method != Method::GET {
+                return Ok((
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    None,
+                    format!("Invalid method {method} for {bound_path}").into_bytes(),
+                ));
+            }
+            let resp = Request::to(("our", "downloads", "app_store", "sys"))
+                .body(serde_json::to_vec(&DownloadRequests::GetMirrors(None))?)
+                .send_and_await_response(5)??;
+            let msg = serde_json::from_slice::<DownloadResponses>(resp.body())?;
+            match msg {
+                DownloadResponses::GetMirrors(mirrors) => {
+                    Ok((StatusCode::OK, None, serde_json::to_vec(&mirrors)?))
+                }
+                _ => Err(anyhow::anyhow!(
+                    "Invalid response from downloads: {:?}",
+                    msg
+                )),
+            }
+        }
+        // GET configured mirrors for an app, with their health
+        // PUT replace configured mirrors for an app, body: {"mirrors": [{"mirror", "priority"}]}
+        "/mirrors/:id" => {
+            let Ok(package_id) = get_package_id(url_params) else {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    None,
+                    format!("Missing id").into_bytes(),
+                ));
+            };
+            let package_id = crate::kinode::process::main::PackageId::from_process_lib(package_id);
+            let request = match method {
+                Method::GET => DownloadRequests::GetMirrors(Some(package_id)),
+                Method::PUT => {
+                    let body = crate::get_blob()
+                        .ok_or(anyhow::anyhow!("missing blob"))?
+                        .bytes;
+                    let body_json: serde_json::Value =
+                        serde_json::from_slice(&body).unwrap_or_default();
+                    let Some(mirrors) = body_json
+                        .get("mirrors")
+                        .and_then(|v| serde_json::from_value::<Vec<MirrorConfig>>(v.clone()).ok())
+                    else {
+                        return Ok((
+                            StatusCode::BAD_REQUEST,
+                            None,
+                            format!("Missing or invalid mirrors").into_bytes(),
+                        ));
+                    };
+                    DownloadRequests::SetMirrors(SetMirrorsRequest {
+                        package_id,
+                        mirrors,
+                    })
+                }
+                _ => {
+                    return Ok((
+                        StatusCode::METHOD_NOT_ALLOWED,
+                        None,
+                        format!("Invalid method {method} for {bound_path}").into_bytes(),
+                    ))
+                }
+            };
+            let resp = Request::to(("our", "downloads", "app_store", "sys"))
+                .body(serde_json::to_vec(&request)?)
+                .send_and_await_response(5)??;
+            let msg = serde_json::from_slice::<DownloadResponses>(resp.body())?;
+            match msg {
+                DownloadResponses::GetMirrors(mirrors) => {
+                    Ok((StatusCode::OK, None, serde_json::to_vec(&mirrors)?))
+                }
+                DownloadResponses::Success => Ok((StatusCode::OK, None, vec![])),
+                _ => Err(anyhow::anyhow!(
+                    "Invalid response from downloads: {:?}",
+                    msg
+                )),
+            }
+        }
+        // POST a package zip to check it before publishing, as ?id=<package_id>
+        // if given. the body is the zip, or, to check the metadata it is to be
+        // published with too, a JSON object of the zip in base64 as `zip` and
+        // the metadata as `metadata`. otherwise the zip's metadata.json is
+        // checked, if it has one, save for the zip's hash, which it can't list.
+        "/lint" => {
+            if method != Method::POST {
+                return Ok((
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    None,
+                    format!("Invalid method {method} for {bound_path}").into_bytes(),
+                ));
+            }
+            let package_id = match req.query_params().get("id") {
+                Some(id) => match id.parse::<PackageId>() {
+                    Ok(package_id) => Some(package_id),
+                    Err(_) => {
+                        return Ok((
+                            StatusCode::BAD_REQUEST,
+                            None,
+                            format!("Invalid id {id}").into_bytes(),
+                        ))
+                    }
+                },
+                None => None,
+            };
+            let body = crate::get_blob()
+                .ok_or(anyhow::anyhow!("missing blob"))?
+                .bytes;
+            let (zip, metadata) = match serde_json::from_slice::<serde_json::Value>(&body) {
+                Ok(body_json) => {
+                    let Some(Ok(zip)) = body_json
+                        .get("zip")
+                        .and_then(|v| v.as_str())
+                        .map(|zip| base64::engine::general_purpose::STANDARD.decode(zip))
+                    else {
+                        return Ok((
+                            StatusCode::BAD_REQUEST,
+                            None,
+                            format!("Missing or invalid base64 zip").into_bytes(),
+                        ));
+                    };
+                    // given as a JSON object, or as a string of one
+                    let metadata = body_json.get("metadata").map(|metadata| {
+                        metadata
+                            .as_str()
+                            .map_or_else(|| metadata.to_string(), str::to_string)
+                    });
+                    (zip, metadata)
+                }
+                Err(_) => (body, None),
+            };
+            let report = crate::lint::lint(&zip, package_id, metadata);
+            Ok((StatusCode::OK, None, serde_json::to_vec(&report)?))
+        }
+        // GET what installed apps can do: capabilities, bindings, drives and
+        // network usage, by process
+        "/permissions" | "/permissions/:id" => {
+            let package_id = if bound_path == "/permissions" {
+                None
+            } else {
+                let Ok(package_id) = get_package_id(url_params) else {
+                    return Ok((
+                        StatusCode::BAD_REQUEST,
+                        None,
+                        format!("Missing id").into_bytes(),
+                    ));
+                };
+                if !state.packages.contains_key(&package_id) {
+                    return Ok((
+                        StatusCode::NOT_FOUND,
+                        None,
+                        format!("Package with id {package_id} not found").into_bytes(),
+                    ));
+                }
+                Some(package_id)
+            };
+            let permissions =
+                crate::permissions::permissions(state, our.node(), package_id.as_ref())?;
+            Ok((StatusCode::OK, None, serde_json::to_vec(&permissions)?))
+        }
         // GET online/offline mirrors for a listed app
         "/mirrorcheck/:node" => {
             if method != Method::GET {
@@ -15,20 +15,34 @@
 //! installed packages can be managed:
 //! - given permissions (necessary to complete install)
 //! - uninstalled + deleted
-//! - set to automatically update if a new version is available
+//! - set to automatically update if a new version is available, to notify
+//!   the user only, or pinned to their current version
+//! - rolled back to their previous version if an upgrade crash loops
+//!
+//! an install that fails partway is undone in every module it touched,
+//! so that a package is never left half-installed: see [`transaction`].
+//!
+//! packages under development can also be installed straight from the host
+//! filesystem, and optionally watched so that every rebuild is reinstalled,
+//! and checked for problems before they are published: see [`lint`].
+//!
+//! what each installed package can do, from the capabilities its processes
+//! hold to its use of the network, is gathered for review: see [`permissions`].
 use crate::kinode::process::downloads::{
     DownloadCompleteRequest, DownloadResponses, ProgressUpdate,
 };
 use crate::kinode::process::main::{
-    ApisResponse, GetApiResponse, InstallPackageRequest, InstallResponse, LocalRequest,
-    LocalResponse, NewPackageRequest, NewPackageResponse, UninstallResponse,
+    ApisResponse, DevInstallRequest, GetApiResponse, GetRequestedCapsRequest,
+    InstallPackageRequest, InstallResponse, LintRequest, LocalRequest, LocalResponse,
+    NewPackageRequest, NewPackageResponse, SetUpdatePolicyRequest, UninstallResponse,
+    UpdatePackageRequest, UpdatePolicy, UpdateResponse,
 };
 use kinode_process_lib::{
-    await_message, call_init, get_blob, http, print_to_terminal, println, vfs, Address,
-    LazyLoadBlob, Message, PackageId, Response,
+    await_message, call_init, get_blob, http, print_to_terminal, println, timer, vfs, Address,
+    LazyLoadBlob, Message, PackageId, Request, Response,
 };
 use serde::{Deserialize, Serialize};
-use state::State;
+use state::{ProcessCrash, State};
 
 wit_bindgen::generate!({
     path: "target/wit",
@@ -38,10 +52,21 @@ wit_bindgen::generate!({
 });
 
 mod http_api;
+mod lint;
+mod permissions;
 pub mod state;
+mod transaction;
 pub mod utils;
 
 const VFS_TIMEOUT: u64 = 10;
+/// how often to check watched host paths for changes
+const DEV_WATCH_INTERVAL_MS: u64 = 1_000;
+/// context attached to the dev watch timer, to tell it apart from other responses
+const DEV_WATCH_CONTEXT: &[u8] = b"dev_watch";
+/// how often to check installed packages for new onchain versions
+const UPDATE_CHECK_INTERVAL_MS: u64 = 60 * 60 * 1000;
+/// context attached to the update check timer
+const UPDATE_CHECK_CONTEXT: &[u8] = b"update_check";
 
 // internal types
 
@@ -51,6 +76,7 @@ pub enum Req {
     LocalRequest(LocalRequest),
     Progress(ProgressUpdate),
     DownloadComplete(DownloadCompleteRequest),
+    Crash(ProcessCrash),
     Http(http::server::HttpServerRequest),
 }
 
@@ -70,6 +96,18 @@ fn init(our: Address) {
 
     let mut state = State::load().expect("state loading failed");
 
+    // watch for crash loops in freshly upgraded packages.
+    // `SubscribeToCrashes` is not yet exposed by process_lib, so we build it by hand.
+    Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!("SubscribeToCrashes")).unwrap())
+        .send()
+        .expect("failed to subscribe to crashes");
+
+    timer::set_timer(
+        UPDATE_CHECK_INTERVAL_MS,
+        Some(UPDATE_CHECK_CONTEXT.to_vec()),
+    );
+
     loop {
         match await_message() {
             Err(send_error) => {
@@ -145,6 +183,48 @@ fn handle_message(
                     },
                 );
             }
+            Req::Crash(crash) => {
+                if !message.is_local(&our) || message.source().process != "kernel:distro:sys" {
+                    return Err(anyhow::anyhow!("crash report from non-kernel source"));
+                }
+                let (package_id, rollback) = match utils::handle_crash(state, crash, &our.node) {
+                    Ok(Some(rolled_back)) => rolled_back,
+                    Ok(None) => return Ok(()),
+                    Err(e) => {
+                        println!("failed to roll back crash looping package: {e}");
+                        return Ok(());
+                    }
+                };
+                let crashes = rollback
+                    .crashes
+                    .iter()
+                    .map(|crash| format!("{}: {}", crash.process, crash.error))
+                    .collect::<Vec<_>>();
+                println!(
+                    "\x1b[38;5;196m{package_id} crashed {} times after upgrading, rolled back to {}:\x1b[0m\n{}",
+                    crashes.len(),
+                    rollback.previous_version_hash,
+                    crashes.join("\n"),
+                );
+                http_server.ws_push_all_channels(
+                    "/",
+                    http::server::WsMessageType::Text,
+                    LazyLoadBlob {
+                        mime: Some("application/json".to_string()),
+                        bytes: serde_json::json!({
+                            "kind": "rollback",
+                            "data": {
+                                "package_id": crate::kinode::process::main::PackageId::from_process_lib(package_id),
+                                "version_hash": rollback.previous_version_hash,
+                                "crashes": rollback.crashes,
+                            }
+                        })
+                        .to_string()
+                        .as_bytes()
+                        .to_vec(),
+                    },
+                );
+            }
             Req::DownloadComplete(req) => {
                 if !message.is_local(&our) {
                     return Err(anyhow::anyhow!("download complete from non-local node"));
@@ -176,36 +256,89 @@ fn handle_message(
                 // capabilities as the old one, and if so, auto-install it.
                 if let Some(context) = message.context() {
                     let manifest_hash = String::from_utf8(context.to_vec())?;
-                    if let Some(package) =
-                        state.packages.get(&req.package_id.clone().to_process_lib())
-                    {
-                        if package.manifest_hash == Some(manifest_hash) {
-                            print_to_terminal(1, "auto_install:main, manifest_hash match");
-                            if let Err(e) = utils::install(
-                                &req.package_id,
-                                None,
-                                &req.version_hash,
-                                state,
-                                &our.node,
-                            ) {
+                    let package_id = req.package_id.clone().to_process_lib();
+                    if let Some(package) = state.packages.get(&package_id) {
+                        match state.update_policy(&package_id) {
+                            UpdatePolicy::Pinned => {
+                                print_to_terminal(1, "auto_install:main, package is pinned");
+                            }
+                            UpdatePolicy::Auto if package.manifest_hash == Some(manifest_hash) => {
+                                print_to_terminal(1, "auto_install:main, manifest_hash match");
+                                // capabilities are unchanged, so the user's previous
+                                // approval (and any denials) carry over to the update
+                                let denied_caps = package.denied_caps.clone();
+                                if let Err(e) = utils::install(
+                                    &req.package_id,
+                                    None,
+                                    &req.version_hash,
+                                    &denied_caps,
+                                    state,
+                                    &our.node,
+                                ) {
+                                    print_to_terminal(
+                                        1,
+                                        &format!("error auto_installing package: {e}"),
+                                    );
+                                } else {
+                                    notify_update(
+                                        http_server,
+                                        &package_id,
+                                        &req.version_hash,
+                                        true,
+                                    );
+                                }
+                            }
+                            UpdatePolicy::Auto => {
+                                // the user must approve the new capabilities first
                                 print_to_terminal(
                                     1,
-                                    &format!("error auto_installing package: {e}"),
-                                );
-                            } else {
-                                println!(
-                                    "auto_installed update for package: {:?}",
-                                    &req.package_id.to_process_lib()
+                                    "auto_install:main, manifest_hash do not match",
                                 );
+                                notify_update(http_server, &package_id, &req.version_hash, false);
+                            }
+                            UpdatePolicy::NotifyOnly => {
+                                if state.notified_updates.get(&package_id)
+                                    != Some(&req.version_hash)
+                                {
+                                    notify_update(
+                                        http_server,
+                                        &package_id,
+                                        &req.version_hash,
+                                        false,
+                                    );
+                                    state
+                                        .notified_updates
+                                        .insert(package_id, req.version_hash.clone());
+                                }
                             }
-                        } else {
-                            print_to_terminal(1, "auto_install:main, manifest_hash do not match");
                         }
                     }
                 }
             }
         }
     } else {
+        if message.source().process == "timer:distro:sys"
+            && message.context() == Some(DEV_WATCH_CONTEXT)
+        {
+            utils::poll_dev_watches(state, &our.node);
+            if !state.dev_watches.is_empty() {
+                timer::set_timer(DEV_WATCH_INTERVAL_MS, Some(DEV_WATCH_CONTEXT.to_vec()));
+            }
+            return Ok(());
+        }
+        if message.source().process == "timer:distro:sys"
+            && message.context() == Some(UPDATE_CHECK_CONTEXT)
+        {
+            utils::expire_rollbacks(state);
+            for update in utils::check_for_updates(state) {
+                notify_update(http_server, &update.package_id, &update.version_hash, false);
+            }
+            timer::set_timer(
+                UPDATE_CHECK_INTERVAL_MS,
+                Some(UPDATE_CHECK_CONTEXT.to_vec()),
+            );
+            return Ok(());
+        }
         match serde_json::from_slice::<Resp>(message.body())? {
             Resp::LocalResponse(_) => {
                 // don't need to handle these at the moment
@@ -216,6 +349,41 @@ fn handle_message(
     Ok(())
 }
 
+/// tell the user, in the terminal and over WS to the UI, about a new
+/// version of an installed package: either available, or already applied
+fn notify_update(
+    http_server: &mut http::server::HttpServer,
+    package_id: &PackageId,
+    version_hash: &str,
+    applied: bool,
+) {
+    if applied {
+        println!("auto_installed update for package: {package_id}");
+    } else {
+        println!("update available for package: {package_id}, run `pkg update {package_id}` to install it");
+    }
+    http_server.ws_push_all_channels(
+        "/",
+        http::server::WsMessageType::Text,
+        LazyLoadBlob {
+            mime: Some("application/json".to_string()),
+            bytes: serde_json::json!({
+                "kind": "update",
+                "data": {
+                    "package_id": crate::kinode::process::main::PackageId::from_process_lib(
+                        package_id.clone(),
+                    ),
+                    "version_hash": version_hash,
+                    "applied": applied,
+                }
+            })
+            .to_string()
+            .as_bytes()
+            .to_vec(),
+        },
+    );
+}
+
 /// fielding requests to download packages and APIs from us
 /// only `our.node` can call this
 fn handle_local_request(
@@ -243,22 +411,86 @@ fn handle_local_request(
             package_id,
             metadata,
             version_hash,
+            caps_approved,
+            denied_capabilities,
         }) => (
-            match utils::install(&package_id, metadata, &version_hash, state, &our.node) {
-                Ok(()) => {
-                    println!(
-                        "successfully installed package: {:?}",
-                        &package_id.to_process_lib()
-                    );
-                    LocalResponse::InstallResponse(InstallResponse::Success)
-                }
-                Err(e) => {
-                    println!("error installing package: {e}");
-                    LocalResponse::InstallResponse(InstallResponse::Failure)
+            if !caps_approved {
+                LocalResponse::InstallResponse(InstallResponse::CapsNotApproved)
+            } else {
+                match utils::install(
+                    &package_id,
+                    metadata,
+                    &version_hash,
+                    &denied_capabilities,
+                    state,
+                    &our.node,
+                ) {
+                    Ok(()) => {
+                        println!(
+                            "successfully installed package: {:?}",
+                            &package_id.to_process_lib()
+                        );
+                        LocalResponse::InstallResponse(InstallResponse::Success)
+                    }
+                    Err(e) => {
+                        println!("error installing package: {e}");
+                        LocalResponse::InstallResponse(InstallResponse::Failure)
+                    }
                 }
             },
             None,
         ),
+        LocalRequest::DevInstall(DevInstallRequest {
+            package_id,
+            host_path,
+            watch,
+        }) => {
+            // only arm the timer if no watch is already running,
+            // since each tick re-arms it while watches remain
+            let start_timer = watch && state.dev_watches.is_empty();
+            (
+                match utils::dev_install(&package_id, &host_path, watch, state, &our.node) {
+                    Ok(()) => {
+                        println!(
+                            "successfully installed package {:?} from {host_path}",
+                            &package_id.to_process_lib()
+                        );
+                        if start_timer {
+                            timer::set_timer(
+                                DEV_WATCH_INTERVAL_MS,
+                                Some(DEV_WATCH_CONTEXT.to_vec()),
+                            );
+                        }
+                        LocalResponse::DevInstallResponse(InstallResponse::Success)
+                    }
+                    Err(e) => {
+                        println!("error installing package from {host_path}: {e}");
+                        LocalResponse::DevInstallResponse(InstallResponse::Failure)
+                    }
+                },
+                None,
+            )
+        }
+        LocalRequest::StopDevWatch(package_id) => (
+            LocalResponse::StopDevWatchResponse(
+                state
+                    .dev_watches
+                    .remove(&package_id.to_process_lib())
+                    .is_some(),
+            ),
+            None,
+        ),
+        LocalRequest::GetRequestedCaps(GetRequestedCapsRequest {
+            package_id,
+            version_hash,
+        }) => (
+            LocalResponse::GetRequestedCapsResponse(
+                utils::fetch_downloaded_manifest(&package_id.to_process_lib(), &version_hash)
+                    .ok()
+                    .map(|manifest| utils::requested_capabilities(&manifest)),
+            ),
+            None,
+        ),
         LocalRequest::Uninstall(package_id) => (
             match utils::uninstall(state, &package_id.clone().to_process_lib()) {
                 Ok(()) => {
@@ -278,6 +510,91 @@ fn handle_local_request(
             },
             None,
         ),
+        LocalRequest::UninstallReport(package_id) => (
+            LocalResponse::UninstallReportResponse(
+                utils::uninstall_report(our.node(), state, &package_id.to_process_lib()).ok(),
+            ),
+            None,
+        ),
+        LocalRequest::ListInstalled => (
+            LocalResponse::ListInstalledResponse(utils::list_installed(state)),
+            None,
+        ),
+        LocalRequest::Update(UpdatePackageRequest {
+            package_id,
+            caps_approved,
+        }) => (
+            LocalResponse::UpdateResponse(
+                match utils::update(&package_id, caps_approved, state, &our.node) {
+                    Ok(response) => {
+                        if let UpdateResponse::Success = response {
+                            println!(
+                                "successfully updated package: {:?}",
+                                &package_id.to_process_lib()
+                            );
+                        }
+                        response
+                    }
+                    Err(e) => {
+                        println!(
+                            "error updating package: {:?}: {e}",
+                            &package_id.to_process_lib()
+                        );
+                        UpdateResponse::Failure
+                    }
+                },
+            ),
+            None,
+        ),
+        LocalRequest::SetUpdatePolicy(SetUpdatePolicyRequest { package_id, policy }) => (
+            LocalResponse::SetUpdatePolicyResponse(utils::set_update_policy(
+                state,
+                &package_id.to_process_lib(),
+                policy,
+            )),
+            None,
+        ),
+        LocalRequest::Gc(dry_run) => (
+            LocalResponse::GcResponse(match utils::gc(state, dry_run) {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    println!("error collecting garbage: {e}");
+                    None
+                }
+            }),
+            None,
+        ),
+        LocalRequest::Lint(LintRequest {
+            package_id,
+            metadata,
+        }) => (
+            LocalResponse::LintResponse(get_blob().map(|blob| {
+                lint::lint(
+                    &blob.bytes,
+                    package_id.map(|package_id| package_id.to_process_lib()),
+                    metadata,
+                )
+            })),
+            None,
+        ),
+        LocalRequest::Permissions(package_id) => (
+            LocalResponse::PermissionsResponse(
+                match permissions::permissions(
+                    state,
+                    &our.node,
+                    package_id
+                        .map(|package_id| package_id.to_process_lib())
+                        .as_ref(),
+                ) {
+                    Ok(permissions) => permissions,
+                    Err(e) => {
+                        println!("error gathering permissions: {e}");
+                        vec![]
+                    }
+                },
+            ),
+            None,
+        ),
         LocalRequest::Apis => (list_apis(state), None),
         LocalRequest::GetApi(package_id) => get_api(state, &package_id.to_process_lib()),
     }
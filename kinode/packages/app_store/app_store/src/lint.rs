@@ -0,0 +1,648 @@
+//! preflight checks on a package zip, for dev tooling to run before
+//! publishing.
+//!
+//! a zip is checked for everything that would stop it installing, or its
+//! processes running: `manifest.json` and `scripts.json` must parse and point
+//! at wasm components built against the wit version the package's metadata
+//! names, and the metadata it is to be published with must list the zip's
+//! hash. a `metadata.json` in the zip can't list the hash of the zip it is
+//! in, so only metadata given alongside the zip is held to that. the
+//! capabilities the package asks for are also looked over, and any that give
+//! it more access than a package usually needs are warned about.
+use crate::kinode::process::main::{LintFinding, LintReport, LintSeverity};
+use crate::utils;
+use kinode_process_lib::{kernel_types as kt, PackageId, ProcessId};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use wasmparser::{Encoding, Parser, Payload};
+
+const MANIFEST: &str = "manifest.json";
+const SCRIPTS: &str = "scripts.json";
+const METADATA: &str = "metadata.json";
+/// most bytes unzipped from one file, and from the whole zip, so that a
+/// small zip can't unpack into more than memory holds
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+const MAX_UNZIPPED_SIZE: u64 = 256 * 1024 * 1024;
+
+/// the version of the `kinode:process` wit that processes of each supported
+/// `wit_version` are run against
+const WIT_VERSIONS: [(Option<u32>, &str); 2] = [(None, "0.7.0"), (Some(0), "0.8.0")];
+
+/// check a package zip, along with the metadata it is to be published with
+pub fn lint(
+    zip_bytes: &[u8],
+    package_id: Option<PackageId>,
+    metadata: Option<String>,
+) -> LintReport {
+    let version_hash = utils::sha_256_hash(zip_bytes);
+    let mut lint = Lint {
+        package_id,
+        findings: vec![],
+    };
+    match lint.unzip(zip_bytes) {
+        Ok(files) => {
+            // only metadata given alongside the zip can list its hash
+            let (metadata, version_hash) = match metadata {
+                Some(metadata) => (Some(metadata.into_bytes()), Some(version_hash.as_str())),
+                None => (files.get(METADATA).cloned(), None),
+            };
+            let wit_version = lint.metadata(metadata.as_deref(), version_hash);
+            lint.manifest(&files, wit_version);
+            lint.scripts(&files);
+        }
+        Err(e) => lint.error("zip", format!("not a readable zip: {e}")),
+    }
+    LintReport {
+        ok: !lint
+            .findings
+            .iter()
+            .any(|finding| matches!(finding.severity, LintSeverity::Error)),
+        version_hash,
+        findings: lint.findings,
+    }
+}
+
+struct Lint {
+    package_id: Option<PackageId>,
+    findings: Vec<LintFinding>,
+}
+
+impl Lint {
+    fn error(&mut self, location: impl Into<String>, message: impl Into<String>) {
+        self.findings.push(LintFinding {
+            severity: LintSeverity::Error,
+            location: location.into(),
+            message: message.into(),
+        });
+    }
+
+    fn warning(&mut self, location: impl Into<String>, message: impl Into<String>) {
+        self.findings.push(LintFinding {
+            severity: LintSeverity::Warning,
+            location: location.into(),
+            message: message.into(),
+        });
+    }
+
+    /// every file in the zip, by path
+    fn unzip(&mut self, zip_bytes: &[u8]) -> anyhow::Result<HashMap<String, Vec<u8>>> {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zip_bytes))?;
+        let mut files = HashMap::new();
+        let mut unzipped = 0;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let name = file.name().to_string();
+            if file.enclosed_name().is_none() {
+                self.error(&name, "path leaves the package drive");
+                continue;
+            }
+            if file.is_dir() {
+                continue;
+            }
+            // the size a zip claims for a file can be false, so read no
+            // more than the limit whatever it says
+            let limit = MAX_FILE_SIZE.min(MAX_UNZIPPED_SIZE - unzipped);
+            let mut bytes = vec![];
+            (&mut file).take(limit + 1).read_to_end(&mut bytes)?;
+            if bytes.len() as u64 > limit {
+                return Err(anyhow::anyhow!(
+                    "{name} unzips to more than {MAX_FILE_SIZE} bytes, or the zip to more than {MAX_UNZIPPED_SIZE}"
+                ));
+            }
+            unzipped += bytes.len() as u64;
+            files.insert(name, bytes);
+        }
+        Ok(files)
+    }
+
+    /// check the metadata, returning the `wit_version` it names if it is
+    /// supported. without metadata, processes are checked against every
+    /// supported version. its code hashes are checked against the zip's
+    /// `version_hash` if given.
+    fn metadata(
+        &mut self,
+        metadata: Option<&[u8]>,
+        version_hash: Option<&str>,
+    ) -> Option<Option<u32>> {
+        let Some(metadata) = metadata else {
+            self.warning(
+                METADATA,
+                "no metadata given, so the zip's hash and wit version cannot be checked",
+            );
+            return None;
+        };
+        let metadata = match serde_json::from_slice::<kt::Erc721Metadata>(metadata) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.error(METADATA, format!("does not parse: {e}"));
+                return None;
+            }
+        };
+        let properties = &metadata.properties;
+
+        if let Some(package_id) = self.package_id.clone() {
+            if properties.package_name != package_id.package()
+                || properties.publisher != package_id.publisher()
+            {
+                self.error(
+                    METADATA,
+                    format!(
+                        "names package {}:{}, not {package_id}",
+                        properties.package_name, properties.publisher
+                    ),
+                );
+            }
+        } else {
+            let package_id = format!("{}:{}", properties.package_name, properties.publisher);
+            match package_id.parse() {
+                Ok(package_id) => self.package_id = Some(package_id),
+                Err(_) => self.error(METADATA, format!("{package_id} is not a valid package id")),
+            }
+        }
+
+        match properties.code_hashes.get(&properties.current_version) {
+            _ if version_hash.is_none() => {}
+            None => self.error(
+                format!("{METADATA}: code_hashes"),
+                format!(
+                    "lists no hash for current_version {}",
+                    properties.current_version
+                ),
+            ),
+            Some(hash) if Some(hash.as_str()) != version_hash => self.error(
+                format!("{METADATA}: code_hashes"),
+                format!(
+                    "lists {hash} for current_version {}, but the zip's hash is {}",
+                    properties.current_version,
+                    version_hash.unwrap_or_default(),
+                ),
+            ),
+            Some(_) => {}
+        }
+        if properties.mirrors.is_empty() {
+            self.warning(
+                format!("{METADATA}: mirrors"),
+                "lists no mirrors, so only the publisher will serve the package",
+            );
+        }
+        if metadata.name.is_none() || metadata.description.is_none() {
+            self.warning(
+                METADATA,
+                "has no name or description to show users in the app store",
+            );
+        }
+
+        if wit_kinode_process(properties.wit_version).is_none() {
+            self.error(
+                format!("{METADATA}: wit_version"),
+                format!(
+                    "{:?} is not supported: use one of {}",
+                    properties.wit_version,
+                    supported_wit_versions()
+                ),
+            );
+            return None;
+        }
+        Some(properties.wit_version)
+    }
+
+    fn manifest(&mut self, files: &HashMap<String, Vec<u8>>, wit_version: Option<Option<u32>>) {
+        let Some(manifest) = files.get(MANIFEST) else {
+            self.error(MANIFEST, "missing");
+            return;
+        };
+        let manifest = match serde_json::from_slice::<Vec<kt::PackageManifestEntry>>(manifest) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                self.error(MANIFEST, format!("does not parse: {e}"));
+                return;
+            }
+        };
+        if manifest.is_empty() {
+            self.warning(MANIFEST, "lists no processes");
+        }
+
+        let names: HashSet<&str> = manifest
+            .iter()
+            .map(|entry| entry.process_name.as_str())
+            .collect();
+        let mut seen = HashSet::new();
+        for entry in &manifest {
+            let location = format!("{MANIFEST}: {}", entry.process_name);
+            if entry.process_name.is_empty() || entry.process_name.contains(':') {
+                self.error(&location, "process names must be non-empty, with no `:`");
+            }
+            if !seen.insert(&entry.process_name) {
+                self.error(&location, "process name is used more than once");
+            }
+
+            let wasm_path = entry.process_wasm_path.trim_start_matches('/');
+            match files.get(wasm_path) {
+                Some(wasm) => self.wasm(&location, wasm, wit_version),
+                None => self.error(
+                    &location,
+                    format!(
+                        "process_wasm_path {} is not in the zip",
+                        entry.process_wasm_path
+                    ),
+                ),
+            }
+
+            let requested = self.capabilities(&location, &entry.request_capabilities);
+            self.capabilities(&location, &entry.grant_capabilities);
+            self.requested_capabilities(&location, &requested, &names);
+            if entry.public && requested.iter().any(|(_, params)| is_root(params)) {
+                self.warning(
+                    &location,
+                    "is public but has root capabilities: any process may ask it to use them",
+                );
+            }
+        }
+    }
+
+    fn scripts(&mut self, files: &HashMap<String, Vec<u8>>) {
+        let Some(scripts) = files.get(SCRIPTS) else {
+            return;
+        };
+        let scripts = match serde_json::from_slice::<HashMap<String, kt::DotScriptsEntry>>(scripts)
+        {
+            Ok(scripts) => scripts,
+            Err(e) => {
+                self.error(SCRIPTS, format!("does not parse: {e}"));
+                return;
+            }
+        };
+        for (wasm_path, entry) in &scripts {
+            let location = format!("{SCRIPTS}: {wasm_path}");
+            if !wasm_path.ends_with(".wasm") {
+                self.error(&location, "scripts must be named after their .wasm file");
+            }
+            match files.get(wasm_path.trim_start_matches('/')) {
+                Some(wasm) => self.wasm(&location, wasm, Some(entry.wit_version)),
+                None => self.error(&location, "is not in the zip"),
+            }
+            if wit_kinode_process(entry.wit_version).is_none() {
+                self.error(
+                    &location,
+                    format!(
+                        "wit_version {:?} is not supported: use one of {}",
+                        entry.wit_version,
+                        supported_wit_versions()
+                    ),
+                );
+            }
+            if entry.root {
+                self.warning(
+                    &location,
+                    "runs with root: every capability the terminal has",
+                );
+            }
+            let requested = self.capabilities(
+                &location,
+                entry.request_capabilities.as_deref().unwrap_or_default(),
+            );
+            self.capabilities(
+                &location,
+                entry.grant_capabilities.as_deref().unwrap_or_default(),
+            );
+            self.requested_capabilities(&location, &requested, &HashSet::new());
+        }
+    }
+
+    /// check a process or script is a wasm component built against the
+    /// `kinode:process` wit of its `wit_version`
+    fn wasm(&mut self, location: &str, wasm: &[u8], wit_version: Option<Option<u32>>) {
+        let versions = match kinode_process_imports(wasm) {
+            Ok(versions) => versions,
+            Err(e) => {
+                self.error(location, e);
+                return;
+            }
+        };
+        let built_against = match versions.as_slice() {
+            [] => {
+                self.error(
+                    location,
+                    "imports no kinode:process interface: is it built as a process?",
+                );
+                return;
+            }
+            [version] => version.as_str(),
+            versions => {
+                self.error(
+                    location,
+                    format!(
+                        "imports kinode:process at more than one version: {}",
+                        versions.join(", ")
+                    ),
+                );
+                return;
+            }
+        };
+        match wit_version {
+            Some(wit_version) => {
+                let Some(expected) = wit_kinode_process(wit_version) else {
+                    // an unsupported wit_version is reported where it is set
+                    return;
+                };
+                if built_against != expected {
+                    self.error(
+                        location,
+                        format!(
+                            "built against kinode:process@{built_against}, but wit_version \
+                             {wit_version:?} runs it against kinode:process@{expected}"
+                        ),
+                    );
+                }
+            }
+            None => {
+                if !WIT_VERSIONS
+                    .iter()
+                    .any(|(_, version)| *version == built_against)
+                {
+                    self.error(
+                        location,
+                        format!(
+                            "built against kinode:process@{built_against}, which is not supported"
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// check capabilities, as listed in a manifest or scripts.json, parse,
+    /// returning those that do with their issuers
+    fn capabilities(
+        &mut self,
+        location: &str,
+        capabilities: &[serde_json::Value],
+    ) -> Vec<(ProcessId, serde_json::Value)> {
+        let mut parsed: Vec<(ProcessId, serde_json::Value)> = vec![];
+        for value in capabilities {
+            let (process, params) = match value {
+                serde_json::Value::String(process) => {
+                    (process.as_str(), serde_json::json!("messaging"))
+                }
+                serde_json::Value::Object(map) => {
+                    match (
+                        map.get("process").and_then(|p| p.as_str()),
+                        map.get("params"),
+                    ) {
+                        (Some(process), Some(params)) => (process, params.clone()),
+                        _ => {
+                            self.error(
+                                location,
+                                format!("capability {value} needs a `process` and `params`"),
+                            );
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    self.error(
+                        location,
+                        format!("capability {value} is neither a process id nor an object"),
+                    );
+                    continue;
+                }
+            };
+            let Ok(process) = process.parse::<ProcessId>() else {
+                self.error(location, format!("{process} is not a valid process id"));
+                continue;
+            };
+            if parsed.contains(&(process.clone(), params.clone())) {
+                self.warning(location, format!("capability {value} is listed twice"));
+                continue;
+            }
+            parsed.push((process, params));
+        }
+        parsed
+    }
+
+    /// warn about requested capabilities that give more access than a
+    /// package usually needs, or that can never be granted
+    fn requested_capabilities(
+        &mut self,
+        location: &str,
+        requested: &[(ProcessId, serde_json::Value)],
+        processes: &HashSet<&str>,
+    ) {
+        for (issuer, params) in requested {
+            if is_root(params) {
+                self.warning(location, format!("asks for root access to {issuer}"));
+            }
+            if issuer.process() == "kernel" && issuer.package() == "distro" {
+                self.warning(
+                    location,
+                    format!("asks for a kernel capability, with params {params}"),
+                );
+            }
+            let own = self.package_id.as_ref().is_some_and(|package_id| {
+                issuer.package() == package_id.package()
+                    && issuer.publisher() == package_id.publisher()
+            });
+            if own && !processes.contains(issuer.process()) {
+                self.warning(
+                    location,
+                    format!("asks for a capability from {issuer}, which is not in the package"),
+                );
+            }
+        }
+    }
+}
+
+/// the `kinode:process` wit a `wit_version` runs processes against, if supported
+fn wit_kinode_process(wit_version: Option<u32>) -> Option<&'static str> {
+    WIT_VERSIONS
+        .iter()
+        .find(|(version, _)| *version == wit_version)
+        .map(|(_, wit)| *wit)
+}
+
+fn supported_wit_versions() -> String {
+    WIT_VERSIONS
+        .iter()
+        .map(|(version, wit)| match version {
+            Some(version) => format!("{version} (kinode:process@{wit})"),
+            None => format!("none (kinode:process@{wit})"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_root(params: &serde_json::Value) -> bool {
+    params
+        .get("root")
+        .and_then(|root| root.as_bool())
+        .unwrap_or(false)
+}
+
+/// the versions of `kinode:process` a wasm component imports, or why it
+/// cannot be run as a process
+fn kinode_process_imports(wasm: &[u8]) -> Result<Vec<String>, String> {
+    let mut versions: Vec<String> = vec![];
+    let mut exports_init = false;
+    let mut first = true;
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.map_err(|e| format!("is not valid wasm: {e}"))? {
+            Payload::Version { encoding, .. } if first => {
+                first = false;
+                if encoding == Encoding::Module {
+                    return Err("is a core wasm module, not a component: build it with \
+                         cargo component, or kit"
+                        .to_string());
+                }
+            }
+            Payload::ComponentImportSection(imports) => {
+                for import in imports {
+                    let import = import.map_err(|e| format!("is not valid wasm: {e}"))?;
+                    let version = import
+                        .name
+                        .0
+                        .strip_prefix("kinode:process/")
+                        .and_then(|interface| interface.split_once('@'))
+                        .map(|(_, version)| version);
+                    if let Some(version) = version {
+                        if !versions.iter().any(|v| v == version) {
+                            versions.push(version.to_string());
+                        }
+                    }
+                }
+            }
+            Payload::ComponentExportSection(exports) => {
+                for export in exports {
+                    let export = export.map_err(|e| format!("is not valid wasm: {e}"))?;
+                    exports_init |= export.name.0 == "init";
+                }
+            }
+            _ => {}
+        }
+    }
+    if !exports_init {
+        return Err("does not export `init`: is it built as a process?".to_string());
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(package_id: Option<&str>) -> Lint {
+        Lint {
+            package_id: package_id.map(|id| id.parse().unwrap()),
+            findings: vec![],
+        }
+    }
+
+    fn errors(lint: &Lint) -> Vec<&str> {
+        lint.findings
+            .iter()
+            .filter(|finding| matches!(finding.severity, LintSeverity::Error))
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    fn metadata(wit_version: Option<u32>, hash: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "name": "Chess",
+            "description": "chess with friends",
+            "image": "",
+            "properties": {
+                "package_name": "chess",
+                "current_version": "0.2.0",
+                "publisher": "sys",
+                "mirrors": ["mirror.os"],
+                "code_hashes": { "0.2.0": hash },
+                "wit_version": wit_version,
+                "dependencies": [],
+            },
+            "external_url": "",
+            "animation_url": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn metadata_is_held_to_the_zip() {
+        let mut clean = lint(Some("chess:sys"));
+        let wit_version = clean.metadata(Some(&metadata(Some(0), "0xab")), Some("0xab"));
+        assert_eq!(wit_version, Some(Some(0)));
+        assert!(clean.findings.is_empty());
+
+        // the hash is only checked against metadata given alongside the zip
+        let mut unchecked = lint(None);
+        unchecked.metadata(Some(&metadata(Some(0), "0xab")), None);
+        assert!(unchecked.findings.is_empty());
+        assert_eq!(unchecked.package_id, Some("chess:sys".parse().unwrap()));
+
+        let mut wrong = lint(Some("checkers:sys"));
+        let wit_version = wrong.metadata(Some(&metadata(Some(9), "0xab")), Some("0xcd"));
+        assert_eq!(wit_version, None);
+        let errors = errors(&wrong);
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("names package chess:sys"));
+        assert!(errors[1].contains("the zip's hash is 0xcd"));
+        assert!(errors[2].starts_with("Some(9) is not supported"));
+    }
+
+    #[test]
+    fn capabilities_parse_and_are_looked_over() {
+        let mut lint = lint(Some("chess:sys"));
+        let requested = lint.capabilities(
+            "manifest.json: chess",
+            &[
+                serde_json::json!("vfs:distro:sys"),
+                serde_json::json!("vfs:distro:sys"),
+                serde_json::json!({ "process": "kernel:distro:sys", "params": { "root": true } }),
+                serde_json::json!({ "process": "helper:chess:sys", "params": "messaging" }),
+                serde_json::json!({ "process": "net:distro:sys" }),
+                serde_json::json!("not a process id"),
+                serde_json::json!(7),
+            ],
+        );
+        assert_eq!(requested.len(), 3);
+        assert_eq!(errors(&lint).len(), 3);
+        lint.findings.clear();
+
+        lint.requested_capabilities(
+            "manifest.json: chess",
+            &requested,
+            &HashSet::from(["chess"]),
+        );
+        let warnings: Vec<&str> = lint
+            .findings
+            .iter()
+            .map(|finding| finding.message.as_str())
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "asks for root access to kernel:distro:sys",
+                "asks for a kernel capability, with params {\"root\":true}",
+                "asks for a capability from helper:chess:sys, which is not in the package",
+            ]
+        );
+    }
+
+    #[test]
+    fn wasm_must_be_a_component() {
+        assert!(kinode_process_imports(b"not wasm").is_err());
+        let core_module = b"\0asm\x01\0\0\0";
+        assert!(kinode_process_imports(core_module)
+            .unwrap_err()
+            .starts_with("is a core wasm module"));
+        assert_eq!(wit_kinode_process(Some(0)), Some("0.8.0"));
+        assert_eq!(wit_kinode_process(Some(1)), None);
+    }
+
+    #[test]
+    fn unreadable_zip_is_reported() {
+        let report = super::lint(b"not a zip", None, None);
+        assert!(!report.ok);
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].location, "zip");
+    }
+}
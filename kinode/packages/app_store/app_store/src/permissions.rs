@@ -0,0 +1,376 @@
+//! what each installed package can do, gathered in one place for the user:
+//! the capabilities its processes hold, from the kernel; the paths they have
+//! bound, from http_server; the drives they reach, from the VFS and their
+//! capabilities; and how much they have used the network, from the kernel.
+//! reading network usage needs the kernel's `"network-usage"` capability.
+use crate::kinode::process::main::{
+    DriveAccess, HeldCapability, HttpBinding, NetworkUsage, PackagePermissions, ProcessPermissions,
+};
+use crate::{state::State, utils, VFS_TIMEOUT};
+use kinode_process_lib::{get_blob, Address, PackageId, ProcessId, Request};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// the drive listed for a VFS root capability, which reaches every drive
+const EVERY_DRIVE: &str = "/";
+
+// the kernel's and http_server's responses, as far as they are needed here.
+// process_lib doesn't know the newer ones yet.
+
+#[derive(Deserialize)]
+enum KernelResponse {
+    Debug(KernelPrintResponse),
+}
+
+#[derive(Deserialize)]
+enum KernelPrintResponse {
+    ProcessMap(HashMap<ProcessId, Process>),
+    NetworkUsage(Vec<(ProcessId, KernelNetworkUsage)>),
+}
+
+#[derive(Deserialize)]
+struct Process {
+    capabilities: Vec<Capability>,
+    public: bool,
+}
+
+#[derive(Deserialize)]
+struct Capability {
+    issuer: Address,
+    params: String,
+}
+
+#[derive(Deserialize)]
+struct KernelNetworkUsage {
+    messages_sent: u64,
+    messages_received: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    last_message: u64,
+}
+
+#[derive(Deserialize)]
+struct BindingInfo {
+    process: ProcessId,
+    path: String,
+    websocket: bool,
+    secure_subdomain: Option<String>,
+    authenticated: bool,
+    local_only: bool,
+}
+
+/// the permissions of `package_id` if given, or of every installed package
+pub fn permissions(
+    state: &State,
+    our_node: &str,
+    package_id: Option<&PackageId>,
+) -> anyhow::Result<Vec<PackagePermissions>> {
+    let packages: Vec<&PackageId> = match package_id {
+        Some(package_id) if state.packages.contains_key(package_id) => vec![package_id],
+        Some(_) => vec![],
+        None => state.packages.keys().collect(),
+    };
+    if packages.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut process_map = process_map()?;
+    let mut network_usage = network_usage()?;
+    packages
+        .into_iter()
+        .map(|package_id| {
+            package_permissions(our_node, package_id, &mut process_map, &mut network_usage)
+        })
+        .collect()
+}
+
+fn package_permissions(
+    our_node: &str,
+    package_id: &PackageId,
+    process_map: &mut HashMap<ProcessId, Process>,
+    network_usage: &mut HashMap<ProcessId, KernelNetworkUsage>,
+) -> anyhow::Result<PackagePermissions> {
+    let manifest = utils::fetch_package_manifest(package_id)?;
+    let mut bindings = bindings(package_id)?;
+
+    // those in the manifest, and any others of the package that are running,
+    // such as processes spawned by them
+    let process_ids: BTreeSet<ProcessId> = manifest
+        .iter()
+        .map(|entry| {
+            ProcessId::new(
+                Some(&entry.process_name),
+                package_id.package(),
+                package_id.publisher(),
+            )
+        })
+        .chain(
+            process_map
+                .keys()
+                .filter(|process_id| {
+                    process_id.package() == package_id.package()
+                        && process_id.publisher() == package_id.publisher()
+                })
+                .cloned(),
+        )
+        .collect();
+
+    // by drive, whether the package can write to it
+    let mut drives: BTreeMap<String, bool> = BTreeMap::new();
+    let mut processes = vec![];
+    for process_id in process_ids {
+        let process = process_map.remove(&process_id);
+        let capabilities: Vec<HeldCapability> = process
+            .as_ref()
+            .map(|process| held_capabilities(our_node, &process.capabilities))
+            .unwrap_or_default();
+        add_drives(&capabilities, &mut drives);
+        let usage = network_usage.remove(&process_id);
+        processes.push(ProcessPermissions {
+            process_id: process_id.to_string(),
+            running: process.is_some(),
+            public: process.as_ref().is_some_and(|process| process.public),
+            network: NetworkUsage {
+                allowed: capabilities
+                    .iter()
+                    .any(|cap| cap.issuer == "kernel:distro:sys" && cap.params == "\"network\""),
+                messages_sent: usage.as_ref().map_or(0, |usage| usage.messages_sent),
+                messages_received: usage.as_ref().map_or(0, |usage| usage.messages_received),
+                bytes_sent: usage.as_ref().map_or(0, |usage| usage.bytes_sent),
+                bytes_received: usage.as_ref().map_or(0, |usage| usage.bytes_received),
+                last_message: usage.as_ref().map_or(0, |usage| usage.last_message),
+            },
+            capabilities,
+            bindings: bindings.remove(&process_id).unwrap_or_default(),
+        });
+    }
+
+    let owned = utils::package_drives(package_id)?;
+    for drive in &owned {
+        drives.insert(drive.clone(), true);
+    }
+    Ok(PackagePermissions {
+        package_id: crate::kinode::process::main::PackageId::from_process_lib(package_id.clone()),
+        processes,
+        drives: drives
+            .into_iter()
+            .map(|(drive, write)| DriveAccess {
+                owned: owned.contains(&drive),
+                drive,
+                write,
+            })
+            .collect(),
+    })
+}
+
+/// add the drives the VFS capabilities among `capabilities` reach, by
+/// whether they can be written to. a root capability is listed as
+/// [`EVERY_DRIVE`].
+fn add_drives(capabilities: &[HeldCapability], drives: &mut BTreeMap<String, bool>) {
+    for capability in capabilities {
+        if capability.issuer != "vfs:distro:sys" {
+            continue;
+        }
+        if capability.root {
+            drives.insert(EVERY_DRIVE.to_string(), true);
+            continue;
+        }
+        let Ok(params) = serde_json::from_str::<serde_json::Value>(&capability.params) else {
+            continue;
+        };
+        let (Some(kind), Some(drive)) = (params["kind"].as_str(), params["drive"].as_str()) else {
+            continue;
+        };
+        *drives.entry(drive.to_string()).or_default() |= kind == "write";
+    }
+}
+
+fn held_capabilities(our_node: &str, capabilities: &[Capability]) -> Vec<HeldCapability> {
+    let mut held: Vec<HeldCapability> = capabilities
+        .iter()
+        .map(|cap| HeldCapability {
+            // capabilities issued on other nodes are shown with their node
+            issuer: if cap.issuer.node() == our_node {
+                cap.issuer.process.to_string()
+            } else {
+                cap.issuer.to_string()
+            },
+            root: serde_json::from_str::<serde_json::Value>(&cap.params)
+                .ok()
+                .and_then(|params| params.get("root").and_then(|root| root.as_bool()))
+                .unwrap_or(false),
+            params: cap.params.clone(),
+        })
+        .collect();
+    held.sort_by(|a, b| (&a.issuer, &a.params).cmp(&(&b.issuer, &b.params)));
+    held
+}
+
+pub fn process_map() -> anyhow::Result<HashMap<ProcessId, Process>> {
+    let response = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "Debug": "ProcessMap" }),
+        )?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    match serde_json::from_slice(response.body())? {
+        KernelResponse::Debug(KernelPrintResponse::ProcessMap(process_map)) => Ok(process_map),
+        _ => Err(anyhow::anyhow!("unexpected response from kernel")),
+    }
+}
+
+fn network_usage() -> anyhow::Result<HashMap<ProcessId, KernelNetworkUsage>> {
+    let response = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "Debug": "NetworkUsage" }),
+        )?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    match serde_json::from_slice(response.body())? {
+        KernelResponse::Debug(KernelPrintResponse::NetworkUsage(usage)) => {
+            Ok(usage.into_iter().collect())
+        }
+        _ => Err(anyhow::anyhow!("unexpected response from kernel")),
+    }
+}
+
+/// the capabilities the processes of a package issued to processes of other
+/// packages, as the holder and the capability, which the kernel revokes when
+/// the issuers are killed
+pub fn issued_capabilities(
+    our_node: &str,
+    package_id: &PackageId,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let in_package = |process_id: &ProcessId| {
+        process_id.package() == package_id.package()
+            && process_id.publisher() == package_id.publisher()
+    };
+    let mut issued: Vec<(String, String)> = process_map()?
+        .into_iter()
+        .filter(|(holder, _)| !in_package(holder))
+        .flat_map(|(holder, process)| {
+            process
+                .capabilities
+                .into_iter()
+                .filter(|cap| cap.issuer.node() == our_node && in_package(&cap.issuer.process))
+                .map(move |cap| {
+                    (
+                        holder.to_string(),
+                        format!("{} {}", cap.issuer.process, cap.params),
+                    )
+                })
+        })
+        .collect();
+    issued.sort();
+    Ok(issued)
+}
+
+/// the paths bound by each process of a package. requires http_server root,
+/// which we have.
+pub fn bindings(package_id: &PackageId) -> anyhow::Result<HashMap<ProcessId, Vec<HttpBinding>>> {
+    // `ListBindings` is not yet exposed by process_lib, so we build it by hand.
+    Request::to(("our", "http_server", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "ListBindings": { "package_id": package_id } }),
+        )?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    let Some(blob) = get_blob() else {
+        return Err(anyhow::anyhow!("http_server listed no bindings"));
+    };
+    let mut bindings: HashMap<ProcessId, Vec<HttpBinding>> = HashMap::new();
+    for binding in serde_json::from_slice::<Vec<BindingInfo>>(&blob.bytes)? {
+        bindings
+            .entry(binding.process)
+            .or_default()
+            .push(HttpBinding {
+                path: binding.path,
+                websocket: binding.websocket,
+                authenticated: binding.authenticated,
+                local_only: binding.local_only,
+                secure_subdomain: binding.secure_subdomain,
+            });
+    }
+    Ok(bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capability(issuer: &str, params: &str) -> HeldCapability {
+        HeldCapability {
+            issuer: issuer.to_string(),
+            params: params.to_string(),
+            root: params.contains("\"root\":true"),
+        }
+    }
+
+    #[test]
+    fn drives_are_read_from_vfs_capabilities() {
+        let mut drives = BTreeMap::new();
+        add_drives(
+            &[
+                capability(
+                    "vfs:distro:sys",
+                    r#"{"kind":"read","drive":"/chess:sys/games"}"#,
+                ),
+                capability(
+                    "vfs:distro:sys",
+                    r#"{"kind":"read","drive":"/chat:sys/logs"}"#,
+                ),
+                capability(
+                    "vfs:distro:sys",
+                    r#"{"kind":"write","drive":"/chat:sys/logs"}"#,
+                ),
+                capability(
+                    "kv:distro:sys",
+                    r#"{"kind":"write","drive":"/chess:sys/other"}"#,
+                ),
+            ],
+            &mut drives,
+        );
+        assert_eq!(
+            drives.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("/chat:sys/logs".to_string(), true),
+                ("/chess:sys/games".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn vfs_root_reaches_every_drive() {
+        let mut drives = BTreeMap::new();
+        add_drives(
+            &[
+                capability("vfs:distro:sys", r#"{"root":true}"#),
+                capability("kv:distro:sys", r#"{"root":true}"#),
+            ],
+            &mut drives,
+        );
+        assert_eq!(
+            drives.into_iter().collect::<Vec<_>>(),
+            vec![(EVERY_DRIVE.to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn held_capabilities_show_remote_issuers_and_root() {
+        let our: Address = "us.os@vfs:distro:sys".parse().unwrap();
+        let theirs: Address = "them.os@chat:chat:sys".parse().unwrap();
+        let held = held_capabilities(
+            "us.os",
+            &[
+                Capability {
+                    issuer: theirs,
+                    params: "\"messaging\"".to_string(),
+                },
+                Capability {
+                    issuer: our,
+                    params: r#"{"root":true}"#.to_string(),
+                },
+            ],
+        );
+        assert_eq!(held[0].issuer, "them.os@chat:chat:sys");
+        assert!(!held[0].root);
+        assert_eq!(held[1].issuer, "vfs:distro:sys");
+        assert!(held[1].root);
+    }
+}
@@ -1,5 +1,8 @@
-use crate::{utils, VFS_TIMEOUT};
-use kinode_process_lib::{kimap, vfs, PackageId};
+use crate::{
+    kinode::process::main::{RequestedCapability, UpdatePolicy},
+    utils, VFS_TIMEOUT,
+};
+use kinode_process_lib::{get_state, kimap, set_state, vfs, PackageId, ProcessId};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
@@ -54,6 +57,40 @@ pub struct PackageState {
     /// capabilities have changed. if they have changed, auto-install must fail
     /// and the user must approve the new capabilities.
     pub manifest_hash: Option<String>,
+    /// optional capabilities the user withheld when approving the install.
+    /// re-applied when the package is auto-updated.
+    pub denied_caps: Vec<RequestedCapability>,
+}
+
+/// a package installed from the host filesystem that we reinstall on change
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DevWatch {
+    pub host_path: String,
+    /// hash of the host contents as of our last install
+    pub version_hash: String,
+    /// when the host contents were last modified as of our last install, in
+    /// milliseconds since the unix epoch
+    #[serde(default)]
+    pub modified: u64,
+}
+
+/// sent to us by the kernel whenever a process crashes
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProcessCrash {
+    pub process: ProcessId,
+    pub error: String,
+}
+
+/// what we need to undo an upgrade, kept while the new version is on
+/// probation. the previous zip and process state are saved in our drive.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rollback {
+    pub previous_version_hash: String,
+    pub previous_denied_caps: Vec<RequestedCapability>,
+    /// seconds since the epoch at which the upgrade was installed
+    pub upgraded_at: u64,
+    /// crashes of the package's processes since the upgrade
+    pub crashes: Vec<ProcessCrash>,
 }
 
 /// this process's saved state
@@ -62,22 +99,104 @@ pub struct State {
     pub packages: HashMap<PackageId, PackageState>,
     /// the APIs we have
     pub installed_apis: HashSet<PackageId>,
+    /// packages under local development, being watched for rebuilds.
+    /// not persisted: watches end when the node restarts.
+    pub dev_watches: HashMap<PackageId, DevWatch>,
+    /// per-package update policies, keyed by package id string.
+    /// packages without one are notify-only.
+    pub update_policies: HashMap<String, UpdatePolicy>,
+    /// the latest version we have told the user about, for each package,
+    /// so that an update is only announced once
+    pub notified_updates: HashMap<PackageId, String>,
+    /// recently upgraded packages that will be rolled back if they crash
+    /// repeatedly. persisted, so that a restart doesn't end the probation.
+    pub rollbacks: HashMap<PackageId, Rollback>,
+}
+
+/// the part of [`State`] that is persisted: what the user chose, which
+/// can't be rebuilt from the filesystem
+#[derive(Default, Serialize, Deserialize)]
+struct SavedState {
+    /// per-package update policies, keyed by package id string
+    update_policies: HashMap<String, UpdatePolicy>,
+    /// the optional capabilities withheld from each installed package,
+    /// keyed by package id string
+    denied_caps: HashMap<String, Vec<RequestedCapability>>,
+    /// packages on probation after an upgrade, keyed by package id string
+    #[serde(default)]
+    rollbacks: HashMap<String, Rollback>,
+}
+
+impl SavedState {
+    fn load() -> anyhow::Result<Self> {
+        let Some(bytes) = get_state() else {
+            return Ok(SavedState::default());
+        };
+        Ok(serde_json::from_slice(&bytes)?)
+    }
 }
 
 impl State {
     /// To load state, we populate the downloaded_packages map
     /// with all packages parseable from our filesystem.
     pub fn load() -> anyhow::Result<Self> {
+        let SavedState {
+            update_policies,
+            mut denied_caps,
+            rollbacks,
+        } = SavedState::load()?;
         let mut state = State {
             packages: HashMap::new(),
             installed_apis: HashSet::new(),
+            dev_watches: HashMap::new(),
+            update_policies,
+            notified_updates: HashMap::new(),
+            rollbacks: HashMap::new(),
         };
-        state.populate_packages_from_filesystem()?;
+        state.populate_packages_from_filesystem(&mut denied_caps)?;
+        // a package uninstalled or rolled back meanwhile has none
+        state.rollbacks = rollbacks
+            .into_iter()
+            .filter_map(|(package_id, rollback)| Some((package_id.parse().ok()?, rollback)))
+            .filter(|(package_id, _)| state.packages.contains_key(package_id))
+            .collect();
         Ok(state)
     }
 
-    /// saves state
-    pub fn populate_packages_from_filesystem(&mut self) -> anyhow::Result<()> {
+    /// everything else is rebuilt from the filesystem on boot, so only
+    /// update policies, denied capabilities and rollbacks need to be persisted
+    pub fn save(&self) -> anyhow::Result<()> {
+        let saved = SavedState {
+            update_policies: self.update_policies.clone(),
+            denied_caps: self
+                .packages
+                .iter()
+                .filter(|(_, package)| !package.denied_caps.is_empty())
+                .map(|(package_id, package)| (package_id.to_string(), package.denied_caps.clone()))
+                .collect(),
+            rollbacks: self
+                .rollbacks
+                .iter()
+                .map(|(package_id, rollback)| (package_id.to_string(), rollback.clone()))
+                .collect(),
+        };
+        set_state(&serde_json::to_vec(&saved)?);
+        Ok(())
+    }
+
+    pub fn update_policy(&self, package_id: &PackageId) -> UpdatePolicy {
+        self.update_policies
+            .get(&package_id.to_string())
+            .copied()
+            .unwrap_or(UpdatePolicy::NotifyOnly)
+    }
+
+    /// saves state. `denied_caps` are the persisted denials, which carry
+    /// over to the packages found.
+    pub fn populate_packages_from_filesystem(
+        &mut self,
+        denied_caps: &mut HashMap<String, Vec<RequestedCapability>>,
+    ) -> anyhow::Result<()> {
         // call VFS and ask for all directories in our root drive
         // (we have root VFS capability so this is allowed)
         // we will interpret any that are package dirs and ingest them
@@ -122,6 +241,9 @@ impl State {
                     verified: true,       // implicitly verified (TODO re-evaluate)
                     caps_approved: false, // must re-approve if you want to do something ??
                     manifest_hash: Some(manifest_hash),
+                    denied_caps: denied_caps
+                        .remove(&package_id.to_string())
+                        .unwrap_or_default(),
                 },
             );
 
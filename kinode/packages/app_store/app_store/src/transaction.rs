@@ -0,0 +1,130 @@
+//! installing a package changes three runtime modules: the VFS, where its
+//! drives are written; the kernel, where its processes are started and given
+//! capabilities; and http_server, where those processes bind paths. an
+//! [`Install`] prepares each of them before anything changes, so that an
+//! install failing partway is aborted back to how the node was, rather than
+//! leaving the package half-installed.
+//!
+//! none of this is known to process_lib yet, so requests are built by hand.
+use crate::VFS_TIMEOUT;
+use kinode_process_lib::{println, PackageId, Request};
+
+/// how long, in seconds, the kernel has to restart a package's processes on abort
+const ABORT_TIMEOUT: u64 = 60;
+
+#[derive(Clone, Copy, Debug)]
+enum Participant {
+    HttpServer,
+    Drive,
+    Kernel,
+}
+
+pub struct Install {
+    id: u64,
+    package_id: PackageId,
+    /// in the order prepared, which is the order aborted: paths are unbound,
+    /// then the drives put back, then processes restarted from the wasm in
+    /// them. each step is answered before the next is sent, and the VFS
+    /// handles a process's requests in the order sent, so none overtakes
+    /// another.
+    prepared: Vec<Participant>,
+}
+
+impl Install {
+    /// prepare each module for the install of `package_id`. if one can't be,
+    /// those already prepared are aborted.
+    pub fn begin(package_id: &PackageId) -> anyhow::Result<Self> {
+        let mut install = Install {
+            id: rand::random(),
+            package_id: package_id.clone(),
+            prepared: vec![],
+        };
+        for participant in [
+            Participant::HttpServer,
+            Participant::Drive,
+            Participant::Kernel,
+        ] {
+            if let Err(e) = install.step(participant, "Prepare") {
+                install.abort();
+                return Err(anyhow::anyhow!("couldn't prepare {participant:?}: {e}"));
+            }
+            install.prepared.push(participant);
+        }
+        Ok(install)
+    }
+
+    /// keep everything the install changed
+    pub fn commit(self) {
+        for participant in &self.prepared {
+            if let Err(e) = self.step(*participant, "Commit") {
+                println!("couldn't commit install of {}: {e}", self.package_id);
+            }
+        }
+    }
+
+    /// undo everything the install changed. each module is aborted even if
+    /// another fails to be, to put back as much as can be.
+    pub fn abort(self) {
+        for participant in &self.prepared {
+            if let Err(e) = self.step(*participant, "Abort") {
+                println!(
+                    "couldn't abort install of {} in {participant:?}: {e}",
+                    self.package_id
+                );
+            }
+        }
+    }
+
+    fn step(&self, participant: Participant, step: &str) -> anyhow::Result<()> {
+        let package_id = serde_json::json!({
+            "package_name": self.package_id.package(),
+            "publisher_node": self.package_id.publisher(),
+        });
+        let timeout = if step == "Abort" {
+            ABORT_TIMEOUT
+        } else {
+            VFS_TIMEOUT
+        };
+        let (target, body) = match participant {
+            Participant::HttpServer => (
+                ("our", "http_server", "distro", "sys"),
+                serde_json::json!({ "BindingsTransaction": {
+                    "id": self.id,
+                    "package_id": package_id,
+                    "step": step,
+                } }),
+            ),
+            // covers every drive of the package, not only pkg
+            Participant::Drive => (
+                ("our", "vfs", "distro", "sys"),
+                serde_json::json!({
+                    "path": format!("/{}/pkg", self.package_id),
+                    "action": { "DriveTransaction": { "id": self.id, "step": step } },
+                }),
+            ),
+            Participant::Kernel => (
+                ("our", "kernel", "distro", "sys"),
+                serde_json::json!({ "PackageTransaction": {
+                    "id": self.id,
+                    "package_id": package_id,
+                    "step": step,
+                } }),
+            ),
+        };
+        let response = Request::to(target)
+            .body(serde_json::to_vec(&body)?)
+            .send_and_await_response(timeout)??;
+        let response: serde_json::Value = serde_json::from_slice(response.body())?;
+        let ok = match participant {
+            Participant::HttpServer => response == serde_json::json!({ "Ok": null }),
+            Participant::Drive => response == serde_json::json!("Ok"),
+            Participant::Kernel => {
+                response == serde_json::json!({ "PackageTransaction": { "Ok": null } })
+            }
+        };
+        if !ok {
+            return Err(anyhow::anyhow!("{response}"));
+        }
+        Ok(())
+    }
+}
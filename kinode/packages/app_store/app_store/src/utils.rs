@@ -1,19 +1,70 @@
 use {
     crate::{
         kinode::process::{
-            chain::{ChainRequests, ChainResponses, OnchainMetadata},
-            downloads::{AddDownloadRequest, DownloadRequests, DownloadResponses},
+            chain::{ChainRequests, ChainResponses, OnchainMetadata, OnchainProperties},
+            downloads::{
+                AddDownloadRequest, Artifact, AutoUpdateRequest, DownloadRequests,
+                DownloadResponses, FetchRequest, GcReport, GcRequest, OrphanReport,
+            },
+            main::{
+                InstalledPackage, RequestedCapability, UninstallReport, UpdatePolicy,
+                UpdateResponse,
+            },
         },
-        state::{PackageState, State},
+        state::{DevWatch, PackageState, ProcessCrash, Rollback, State},
+        transaction::Install,
         VFS_TIMEOUT,
     },
     kinode_process_lib::{
         get_blob, kernel_types as kt, println, vfs, Address, LazyLoadBlob, PackageId, ProcessId,
         Request,
     },
+    serde::{Deserialize, Serialize},
     std::collections::{HashMap, HashSet},
 };
 
+/// default time, in seconds, a process has to respond to a lifecycle hook
+const DEFAULT_HOOK_TIMEOUT: u64 = 30;
+/// how long, in seconds, an upgraded package is watched for crash loops
+const ROLLBACK_WINDOW_SECS: u64 = 10 * 60;
+/// how many crashes within the window trigger a rollback
+const ROLLBACK_CRASH_THRESHOLD: usize = 3;
+
+/// the lifecycle hooks a process opts in to in its manifest entry.
+/// process_lib's `PackageManifestEntry` predates these fields,
+/// so they are parsed from the manifest separately.
+#[derive(Debug, Deserialize)]
+pub struct ManifestHooks {
+    pub process_name: String,
+    #[serde(default)]
+    pub on_install: bool,
+    #[serde(default)]
+    pub on_upgrade: bool,
+    #[serde(default)]
+    pub on_uninstall: bool,
+    #[serde(default)]
+    pub hook_timeout: Option<u64>,
+}
+
+/// the request sent to a process for each lifecycle hook it opts in to in its
+/// manifest entry. the process must respond, with any body, within its hook
+/// timeout. a failed or timed-out install or upgrade hook fails the install;
+/// a failed uninstall hook is logged and the uninstall proceeds.
+///
+/// hooks are only run for packages installed by the app store: packages
+/// bootstrapped from the distro do not receive them.
+#[derive(Debug, Serialize)]
+pub enum LifecycleHook {
+    Install {
+        version_hash: String,
+    },
+    Upgrade {
+        from_version_hash: String,
+        version_hash: String,
+    },
+    Uninstall,
+}
+
 // quite annoyingly, we must convert from our gen'd version of PackageId
 // to the process_lib's gen'd version. this is in order to access custom
 // Impls that we want to use
@@ -66,6 +117,81 @@ pub fn fetch_package_manifest(
     )?)
 }
 
+/// read the manifest.json stored alongside a downloaded (but not necessarily
+/// installed) package version in our downloads drive.
+pub fn fetch_downloaded_manifest(
+    package_id: &PackageId,
+    version_hash: &str,
+) -> anyhow::Result<Vec<kt::PackageManifestEntry>> {
+    let file = vfs::open_file(
+        &format!("/app_store:sys/downloads/{package_id}/{version_hash}.json"),
+        false,
+        Some(VFS_TIMEOUT),
+    )?;
+    Ok(serde_json::from_slice::<Vec<kt::PackageManifestEntry>>(
+        &file.read()?,
+    )?)
+}
+
+/// flatten every capability requested in a manifest into a list that
+/// can be presented to the user for review before install.
+///
+/// read/write caps for the package's own drive are not included: they
+/// are always granted and cannot be denied.
+pub fn requested_capabilities(manifest: &[kt::PackageManifestEntry]) -> Vec<RequestedCapability> {
+    let mut requested = vec![];
+    for entry in manifest {
+        if entry.request_networking {
+            requested.push(RequestedCapability {
+                process_name: entry.process_name.clone(),
+                issuer: "kernel:distro:sys".to_string(),
+                params: "\"network\"".to_string(),
+                root: false,
+            });
+        }
+        for value in &entry.request_capabilities {
+            let (issuer, params) = match value {
+                serde_json::Value::String(process_name) => {
+                    (process_name.clone(), serde_json::json!("messaging"))
+                }
+                serde_json::Value::Object(map) => {
+                    match (
+                        map.get("process").and_then(|p| p.as_str()),
+                        map.get("params"),
+                    ) {
+                        (Some(process_name), Some(params)) => {
+                            (process_name.to_string(), params.clone())
+                        }
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            requested.push(RequestedCapability {
+                process_name: entry.process_name.clone(),
+                issuer,
+                root: params
+                    .get("root")
+                    .and_then(|root| root.as_bool())
+                    .unwrap_or(false),
+                params: params.to_string(),
+            });
+        }
+    }
+    requested
+}
+
+/// check whether the user withheld `cap` from `process_name` at install time
+fn is_denied(denied: &[RequestedCapability], process_name: &str, cap: &kt::Capability) -> bool {
+    let cap_params = serde_json::from_str::<serde_json::Value>(&cap.params).unwrap_or_default();
+    denied.iter().any(|d| {
+        d.process_name == process_name
+            && d.issuer == cap.issuer.process.to_string()
+            && serde_json::from_str::<serde_json::Value>(&d.params).unwrap_or_default()
+                == cap_params
+    })
+}
+
 pub fn fetch_package_metadata(
     package_id: &crate::kinode::process::main::PackageId,
 ) -> anyhow::Result<OnchainMetadata> {
@@ -123,11 +249,197 @@ pub fn new_package(
     Ok(())
 }
 
+/// read a package zip, or a directory to be zipped, from the host filesystem.
+/// requires VFS root, which we have.
+pub fn read_host_path(host_path: &str) -> anyhow::Result<Vec<u8>> {
+    // `ReadHostPath` is a runtime-only VFS action, not yet exposed by
+    // process_lib, so we build the request body by hand.
+    let resp = Request::to(("our", "vfs", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({
+            "path": "/",
+            "action": { "ReadHostPath": { "host_path": host_path } },
+        }))?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    let vfs::VfsResponse::Read = serde_json::from_slice::<vfs::VfsResponse>(resp.body())? else {
+        return Err(anyhow::anyhow!("couldn't read host path {host_path}"));
+    };
+    let Some(blob) = get_blob() else {
+        return Err(anyhow::anyhow!("no blob"));
+    };
+    Ok(blob.bytes)
+}
+
+/// when a file or directory on the host filesystem, or anything in it, was
+/// last modified, in milliseconds since the unix epoch. requires VFS root.
+fn host_path_modified(host_path: &str) -> anyhow::Result<u64> {
+    // `HostPathModified` is a runtime-only VFS action, not yet exposed by
+    // process_lib, so we build the request body, and read the response, by hand.
+    let resp = Request::to(("our", "vfs", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({
+            "path": "/",
+            "action": { "HostPathModified": { "host_path": host_path } },
+        }))?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    serde_json::from_slice::<serde_json::Value>(resp.body())?["Modified"]
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("couldn't stat host path {host_path}"))
+}
+
+/// copy a file, sharing its blocks with the copy where the host filesystem
+/// supports it
+/// unpack a package's zip to `dir`, unless it already is
+fn unpack(dir: &str, zip: &LazyLoadBlob) -> anyhow::Result<()> {
+    if vfs::metadata(dir, Some(VFS_TIMEOUT)).is_ok() {
+        return Ok(());
+    }
+    // unpacked beside it first, so that one cut short is never taken for whole
+    let partial = format!("{dir}.part");
+    let _ =
+        vfs_request(&partial, vfs::VfsAction::RemoveDirAll).send_and_await_response(VFS_TIMEOUT);
+    let vfs::VfsResponse::Ok = serde_json::from_slice::<vfs::VfsResponse>(
+        vfs_request(&partial, vfs::VfsAction::AddZip)
+            .blob(zip.clone())
+            .send_and_await_response(VFS_TIMEOUT)??
+            .body(),
+    )?
+    else {
+        return Err(anyhow::anyhow!("couldn't unpack to {partial}"));
+    };
+    vfs_request(
+        &partial,
+        vfs::VfsAction::Rename {
+            new_path: dir.to_string(),
+        },
+    )
+    .send_and_await_response(VFS_TIMEOUT)??;
+    Ok(())
+}
+
+fn clone_file(from: &str, to: &str) -> anyhow::Result<()> {
+    // `CloneFile` is a runtime-only VFS action, not yet exposed by
+    // process_lib, so we build the request body by hand.
+    let resp = Request::to(("our", "vfs", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({
+            "path": from,
+            "action": { "CloneFile": { "new_path": to } },
+        }))?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    let vfs::VfsResponse::Ok = serde_json::from_slice::<vfs::VfsResponse>(resp.body())? else {
+        return Err(anyhow::anyhow!("couldn't clone {from} to {to}"));
+    };
+    Ok(())
+}
+
+/// metadata for a package installed from the host, which has no on-chain listing
+fn dev_metadata(package_id: &crate::kinode::process::main::PackageId) -> OnchainMetadata {
+    OnchainMetadata {
+        name: Some(package_id.package_name.clone()),
+        description: None,
+        image: None,
+        external_url: None,
+        animation_url: None,
+        properties: OnchainProperties {
+            package_name: package_id.package_name.clone(),
+            publisher: package_id.publisher_node.clone(),
+            current_version: "dev".to_string(),
+            mirrors: vec![],
+            code_hashes: vec![],
+            license: None,
+            screenshots: None,
+            // packages under development are assumed to target the latest wit
+            wit_version: Some(0),
+            dependencies: None,
+        },
+    }
+}
+
+/// install a package from the host filesystem, bypassing mirrors entirely.
+/// the package is still added to our downloads so it can be reinstalled.
+/// if `watch` is set, `host_path` will be polled and the package reinstalled
+/// whenever its contents change.
+///
+/// dev installs are made by the developer themselves, so requested
+/// capabilities are implicitly approved.
+pub fn dev_install(
+    package_id: &crate::kinode::process::main::PackageId,
+    host_path: &str,
+    watch: bool,
+    state: &mut State,
+    our_node: &str,
+) -> anyhow::Result<()> {
+    // taken before reading, so that a change made while we read is caught
+    // by the next poll
+    let modified = if watch {
+        host_path_modified(host_path)?
+    } else {
+        0
+    };
+    let bytes = read_host_path(host_path)?;
+    let version_hash = sha_256_hash(&bytes);
+    new_package(package_id.clone(), false, bytes.clone())?;
+    // no rollback protection: a crashing dev build should stay installed
+    install_bytes(
+        package_id,
+        Some(dev_metadata(package_id)),
+        &version_hash,
+        bytes,
+        &[],
+        state,
+        our_node,
+    )?;
+    if watch {
+        state.dev_watches.insert(
+            package_id.clone().to_process_lib(),
+            DevWatch {
+                host_path: host_path.to_string(),
+                version_hash,
+                modified,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// check every watched host path, reinstalling any package whose contents
+/// changed. a path is only read, and zipped if a directory, once it has been
+/// modified since we last installed from it.
+pub fn poll_dev_watches(state: &mut State, our_node: &str) {
+    let watches: Vec<(PackageId, DevWatch)> = state
+        .dev_watches
+        .iter()
+        .map(|(id, watch)| (id.clone(), watch.clone()))
+        .collect();
+    for (package_id, watch) in watches {
+        let Ok(modified) = host_path_modified(&watch.host_path) else {
+            continue;
+        };
+        if modified == watch.modified {
+            continue;
+        }
+        let Ok(bytes) = read_host_path(&watch.host_path) else {
+            continue;
+        };
+        if sha_256_hash(&bytes) == watch.version_hash {
+            // touched, but the same: no need to read it again until it changes
+            if let Some(watch) = state.dev_watches.get_mut(&package_id) {
+                watch.modified = modified;
+            }
+            continue;
+        }
+        let wit_package_id = crate::kinode::process::main::PackageId::from_process_lib(package_id);
+        match dev_install(&wit_package_id, &watch.host_path, true, state, our_node) {
+            Ok(()) => println!("reloaded {}", wit_package_id.to_process_lib()),
+            Err(e) => println!("failed to reload {}: {e}", wit_package_id.to_process_lib()),
+        }
+    }
+}
+
 /// create a new package drive in VFS and add the package zip to it.
 /// if an `api.zip` is present, unzip and stow in `/api`.
 /// returns a string representing the manfifest hash.
 pub fn create_package_drive(
     package_id: &PackageId,
+    version_hash: &str,
     package_bytes: Vec<u8>,
 ) -> anyhow::Result<String> {
     let drive_name = format!("/{package_id}/pkg");
@@ -146,26 +458,41 @@ pub fn create_package_drive(
     vfs_request(drive_name.clone(), vfs::VfsAction::RemoveDirAll)
         .send_and_await_response(VFS_TIMEOUT)??;
 
-    // convert the zip to a new package drive
-    let vfs::VfsResponse::Ok = serde_json::from_slice::<vfs::VfsResponse>(
-        vfs_request(drive_name.clone(), vfs::VfsAction::AddZip)
-            .blob(blob.clone())
-            .send_and_await_response(VFS_TIMEOUT)??
-            .body(),
-    )?
-    else {
-        return Err(anyhow::anyhow!(
-            "cannot add NewPackage: do not have capability to access vfs"
-        ));
-    };
+    // where we hold a download of this version, its contents are unpacked
+    // once, next to it, and the drive is cloned from there, so that the host
+    // filesystem can share their blocks with the drive, and with the drive
+    // of every later install of the version. the zip itself is likewise
+    // cloned from the download.
+    let download_path = format!("/app_store:sys/downloads/{package_id}/{version_hash}.zip");
+    let downloaded = vfs::metadata(&download_path, Some(VFS_TIMEOUT)).is_ok();
+    let unpacked = format!("/app_store:sys/downloads/{package_id}/{version_hash}");
+    if !downloaded
+        || unpack(&unpacked, &blob)
+            .and_then(|()| clone_file(&unpacked, &drive_name))
+            .is_err()
+    {
+        // convert the zip to a new package drive
+        let vfs::VfsResponse::Ok = serde_json::from_slice::<vfs::VfsResponse>(
+            vfs_request(drive_name.clone(), vfs::VfsAction::AddZip)
+                .blob(blob.clone())
+                .send_and_await_response(VFS_TIMEOUT)??
+                .body(),
+        )?
+        else {
+            return Err(anyhow::anyhow!(
+                "cannot add NewPackage: do not have capability to access vfs"
+            ));
+        };
+    }
 
-    // be careful, this is technically a duplicate.. but..
     // save the zip file itself in VFS for sharing with other nodes
     // call it <package_id>.zip
     let zip_path = format!("{}/{}.zip", drive_name, package_id);
-    vfs_request(zip_path, vfs::VfsAction::Write)
-        .blob(blob)
-        .send_and_await_response(VFS_TIMEOUT)??;
+    if !downloaded || clone_file(&download_path, &zip_path).is_err() {
+        vfs_request(zip_path, vfs::VfsAction::Write)
+            .blob(blob)
+            .send_and_await_response(VFS_TIMEOUT)??;
+    }
 
     let manifest_file = vfs::File {
         path: format!("/{}/pkg/manifest.json", package_id),
@@ -207,10 +534,16 @@ pub fn extract_api(package_id: &PackageId) -> anyhow::Result<bool> {
 /// which we can only do if we were the process to create that drive.
 /// note also that each capability will only be granted if we, the process
 /// using this function, own that capability ourselves.
+///
+/// any capability in `denied_caps` is withheld from the process that requested it.
+///
+/// when this upgrades a package, the previous version is kept so that it can
+/// be restored if the new one crash loops: see [`handle_crash`].
 pub fn install(
     package_id: &crate::kinode::process::main::PackageId,
     metadata: Option<OnchainMetadata>,
     version_hash: &str,
+    denied_caps: &[RequestedCapability],
     state: &mut State,
     our_node: &str,
 ) -> anyhow::Result<()> {
@@ -221,13 +554,111 @@ pub fn install(
         Some(VFS_TIMEOUT),
     )?;
     let bytes = file.read()?;
-    let manifest_hash = create_package_drive(&process_package_id, bytes)?;
+
+    let rollback = match state.packages.get(&process_package_id) {
+        Some(previous) if previous.our_version_hash != version_hash => {
+            match save_rollback(&process_package_id, previous) {
+                Ok(rollback) => Some(rollback),
+                Err(e) => {
+                    println!("couldn't save {process_package_id} for rollback: {e}");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    install_bytes(
+        package_id,
+        metadata,
+        version_hash,
+        bytes,
+        denied_caps,
+        state,
+        our_node,
+    )?;
+    if let Some(rollback) = rollback {
+        state.rollbacks.insert(process_package_id, rollback);
+        state.save()?;
+    }
+    Ok(())
+}
+
+/// install a package from the bytes of its zip. see [`install`].
+///
+/// the install either completes or, if any step of it fails, is undone:
+/// see [`Install`].
+fn install_bytes(
+    package_id: &crate::kinode::process::main::PackageId,
+    metadata: Option<OnchainMetadata>,
+    version_hash: &str,
+    bytes: Vec<u8>,
+    denied_caps: &[RequestedCapability],
+    state: &mut State,
+    our_node: &str,
+) -> anyhow::Result<()> {
+    let process_package_id = package_id.clone().to_process_lib();
+    let install = Install::begin(&process_package_id)?;
+    let previous = state.packages.get(&process_package_id).cloned();
+    let had_api = state.installed_apis.contains(&process_package_id);
+    match install_package(
+        package_id,
+        metadata,
+        version_hash,
+        bytes,
+        denied_caps,
+        state,
+        our_node,
+    ) {
+        Ok(()) => {
+            install.commit();
+            if let Err(e) = state.save() {
+                println!("error saving denied capabilities of {process_package_id}: {e}");
+            }
+            Ok(())
+        }
+        Err(e) => {
+            install.abort();
+            match previous {
+                Some(previous) => state.packages.insert(process_package_id.clone(), previous),
+                None => state.packages.remove(&process_package_id),
+            };
+            if !had_api {
+                state.installed_apis.remove(&process_package_id);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// the steps of [`install_bytes`]: write the package drive, then initialize,
+/// grant capabilities to, and start each process, then run lifecycle hooks
+fn install_package(
+    package_id: &crate::kinode::process::main::PackageId,
+    metadata: Option<OnchainMetadata>,
+    version_hash: &str,
+    bytes: Vec<u8>,
+    denied_caps: &[RequestedCapability],
+    state: &mut State,
+    our_node: &str,
+) -> anyhow::Result<()> {
+    let process_package_id = package_id.clone().to_process_lib();
+    let manifest_hash = create_package_drive(&process_package_id, version_hash, bytes)?;
+
+    // if a version was already installed, this is an upgrade, unless it is
+    // the same version reinstalled, which runs no hook
+    let previous_version_hash = state
+        .packages
+        .get(&process_package_id)
+        .map(|package| package.our_version_hash.clone());
+    let reinstall = previous_version_hash.as_deref() == Some(version_hash);
 
     let package_state = PackageState {
         our_version_hash: version_hash.to_string(),
         verified: true, // sideloaded apps are implicitly verified because there is no "source" to verify against
-        caps_approved: true, // TODO see if we want to auto-approve local installs
+        caps_approved: true,
         manifest_hash: Some(manifest_hash),
+        denied_caps: denied_caps.to_vec(),
     };
 
     if let Ok(extracted) = extract_api(&process_package_id) {
@@ -243,6 +674,9 @@ pub fn install(
     // get the package manifest
     let drive_path = format!("/{process_package_id}/pkg");
     let manifest = fetch_package_manifest(&process_package_id)?;
+    let manifest_hooks = serde_json::from_slice::<Vec<ManifestHooks>>(
+        &get_blob().ok_or(anyhow::anyhow!("no blob"))?.bytes,
+    )?;
     // get wit version from metadata if local or chain if remote.
     let metadata = if let Some(metadata) = metadata {
         metadata
@@ -307,6 +741,9 @@ pub fn install(
             });
         }
 
+        // withhold anything the user chose to deny during review
+        requested_capabilities.retain(|cap| !is_denied(denied_caps, &entry.process_name, cap));
+
         // always grant read/write to their drive, which we created for them
         requested_capabilities.push(kt::Capability {
             issuer: Address::new(our_node, ("vfs", "distro", "sys")),
@@ -401,22 +838,254 @@ pub fn install(
             return Err(anyhow::anyhow!("failed to start process"));
         };
     }
+
+    // finally, with every process running, run the hooks they opted in to
+    for hooks in manifest_hooks.iter().filter(|_| !reinstall) {
+        let hook = match &previous_version_hash {
+            None if hooks.on_install => LifecycleHook::Install {
+                version_hash: version_hash.to_string(),
+            },
+            Some(from_version_hash) if hooks.on_upgrade => LifecycleHook::Upgrade {
+                from_version_hash: from_version_hash.clone(),
+                version_hash: version_hash.to_string(),
+            },
+            _ => continue,
+        };
+        run_lifecycle_hook(our_node, &process_package_id, hooks, &hook)?;
+    }
+    reload_schedules();
+    Ok(())
+}
+
+fn rollback_dir(package_id: &PackageId) -> String {
+    format!("/app_store:sys/rollback/{package_id}")
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// save the installed version of a package, about to be upgraded: its zip,
+/// and the persisted state of each of its processes
+fn save_rollback(package_id: &PackageId, previous: &PackageState) -> anyhow::Result<Rollback> {
+    let dir = rollback_dir(package_id);
+    // only the most recent upgrade can be rolled back
+    let _ = vfs_request(&dir, vfs::VfsAction::RemoveDirAll).send_and_await_response(VFS_TIMEOUT);
+    vfs_request(format!("{dir}/state"), vfs::VfsAction::CreateDirAll)
+        .send_and_await_response(VFS_TIMEOUT)??;
+
+    let zip = vfs::File {
+        path: format!("/{package_id}/pkg/{package_id}.zip"),
+        timeout: VFS_TIMEOUT,
+    }
+    .read()?;
+    vfs::open_file(&format!("{dir}/package.zip"), true, Some(VFS_TIMEOUT))?.write(&zip)?;
+
+    for entry in fetch_package_manifest(package_id)? {
+        let process_id = ProcessId::new(
+            Some(&entry.process_name),
+            package_id.package(),
+            package_id.publisher(),
+        );
+        // processes that never saved state have nothing to restore
+        let response = Request::to(("our", "state", "distro", "sys"))
+            .body(serde_json::to_vec(
+                &serde_json::json!({ "GetState": process_id }),
+            )?)
+            .send_and_await_response(VFS_TIMEOUT)??;
+        if serde_json::from_slice::<serde_json::Value>(response.body())?
+            != serde_json::json!("GetState")
+        {
+            continue;
+        }
+        let Some(blob) = get_blob() else {
+            continue;
+        };
+        vfs::open_file(
+            &format!("{dir}/state/{}", entry.process_name),
+            true,
+            Some(VFS_TIMEOUT),
+        )?
+        .write(&blob.bytes)?;
+    }
+
+    Ok(Rollback {
+        previous_version_hash: previous.our_version_hash.clone(),
+        previous_denied_caps: previous.denied_caps.clone(),
+        upgraded_at: now_secs(),
+        crashes: vec![],
+    })
+}
+
+/// record a crash against the package it belongs to. if that package was
+/// upgraded within the last [`ROLLBACK_WINDOW_SECS`] and has now crashed
+/// [`ROLLBACK_CRASH_THRESHOLD`] times, reinstall its previous version and
+/// restore the state its processes had before the upgrade.
+///
+/// returns the rollback performed, if any.
+pub fn handle_crash(
+    state: &mut State,
+    crash: ProcessCrash,
+    our_node: &str,
+) -> anyhow::Result<Option<(PackageId, Rollback)>> {
+    let package_id = crash.process.package_id();
+    expire_rollbacks(state);
+    let Some(rollback) = state.rollbacks.get_mut(&package_id) else {
+        return Ok(None);
+    };
+    rollback.crashes.push(crash);
+    if rollback.crashes.len() < ROLLBACK_CRASH_THRESHOLD {
+        state.save()?;
+        return Ok(None);
+    }
+    let rollback = state.rollbacks.remove(&package_id).unwrap();
+    state.save()?;
+    rollback_package(state, &package_id, &rollback, our_node)?;
+    Ok(Some((package_id, rollback)))
+}
+
+/// forget the previous versions of packages that have outlived their window
+pub fn expire_rollbacks(state: &mut State) {
+    let now = now_secs();
+    let probations = state.rollbacks.len();
+    state.rollbacks.retain(|package_id, rollback| {
+        if now < rollback.upgraded_at + ROLLBACK_WINDOW_SECS {
+            return true;
+        }
+        let _ = vfs_request(rollback_dir(package_id), vfs::VfsAction::RemoveDirAll)
+            .send_and_await_response(VFS_TIMEOUT);
+        false
+    });
+    if state.rollbacks.len() < probations {
+        if let Err(e) = state.save() {
+            println!("couldn't save state: {e}");
+        }
+    }
+}
+
+fn rollback_package(
+    state: &mut State,
+    package_id: &PackageId,
+    rollback: &Rollback,
+    our_node: &str,
+) -> anyhow::Result<()> {
+    let dir = rollback_dir(package_id);
+    let zip = vfs::File {
+        path: format!("{dir}/package.zip"),
+        timeout: VFS_TIMEOUT,
+    }
+    .read()?;
+
+    // stop the crashing version first, so it can't overwrite the restored state
+    for entry in fetch_package_manifest(package_id)? {
+        let process_id = ProcessId::new(
+            Some(&entry.process_name),
+            package_id.package(),
+            package_id.publisher(),
+        );
+        kernel_request(kt::KernelCommand::KillProcess(process_id)).send()?;
+    }
+
+    for entry in vfs::open_dir(&format!("{dir}/state"), false, Some(VFS_TIMEOUT))?.read()? {
+        let Some(process_name) = entry.path.rsplit('/').next() else {
+            continue;
+        };
+        let process_id = ProcessId::new(
+            Some(process_name),
+            package_id.package(),
+            package_id.publisher(),
+        );
+        let bytes = vfs::File {
+            path: format!("/{}", entry.path.trim_start_matches('/')),
+            timeout: VFS_TIMEOUT,
+        }
+        .read()?;
+        Request::to(("our", "state", "distro", "sys"))
+            .body(serde_json::to_vec(
+                &serde_json::json!({ "SetState": process_id }),
+            )?)
+            .blob_bytes(bytes)
+            .send_and_await_response(VFS_TIMEOUT)??;
+    }
+
+    // treat the crashing version as already announced, so that
+    // the update check doesn't offer or auto-install it again
+    if let Some(package) = state.packages.get(package_id) {
+        state
+            .notified_updates
+            .insert(package_id.clone(), package.our_version_hash.clone());
+    }
+
+    let wit_package_id =
+        crate::kinode::process::main::PackageId::from_process_lib(package_id.clone());
+    // sideloaded packages have no listing: assume the latest wit version
+    let metadata =
+        fetch_package_metadata(&wit_package_id).unwrap_or_else(|_| dev_metadata(&wit_package_id));
+    install_bytes(
+        &wit_package_id,
+        Some(metadata),
+        &rollback.previous_version_hash,
+        zip,
+        &rollback.previous_denied_caps,
+        state,
+        our_node,
+    )?;
+    let _ = vfs_request(&dir, vfs::VfsAction::RemoveDirAll).send_and_await_response(VFS_TIMEOUT);
     Ok(())
 }
 
+/// send a lifecycle hook to a process and wait for it to respond
+fn run_lifecycle_hook(
+    our_node: &str,
+    package_id: &PackageId,
+    hooks: &ManifestHooks,
+    hook: &LifecycleHook,
+) -> anyhow::Result<()> {
+    let process_id = ProcessId::new(
+        Some(&hooks.process_name),
+        package_id.package(),
+        package_id.publisher(),
+    );
+    Request::to(Address::new(our_node, process_id.clone()))
+        .body(serde_json::to_vec(hook)?)
+        .send_and_await_response(hooks.hook_timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT))?
+        .map_err(|e| anyhow::anyhow!("{process_id} failed to handle {hook:?} hook: {e}"))?;
+    Ok(())
+}
+
+/// have the scheduler read the schedules in package manifests again, after
+/// one is installed or uninstalled
+fn reload_schedules() {
+    if let Err(e) = Request::to(("our", "scheduler", "scheduler", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!("Reload")).unwrap())
+        .send()
+    {
+        println!("couldn't reload schedules: {e}");
+    }
+}
+
 /// given a `PackageId`, read its manifest, kill all processes declared in it,
-/// then remove its drive in the virtual filesystem.
+/// then remove everything the package leaves behind: the persisted state and
+/// HTTP bindings of each process, all of its VFS drives, and all of its kv and
+/// sqlite databases. the kernel revokes capabilities issued by each process
+/// when it is killed. see [`uninstall_report`] for a dry run.
+///
+/// processes that opt in to the uninstall hook are given a chance to clean up
+/// first. a failing hook is logged, but never blocks the uninstall.
+///
+/// cleanup is best-effort: a failed step is logged and the rest still run,
+/// so that one stuck store does not leave residue in all the others.
 pub fn uninstall(state: &mut State, package_id: &PackageId) -> anyhow::Result<()> {
     if !state.packages.contains_key(package_id) {
         return Err(anyhow::anyhow!("package not found"));
     }
 
-    // the drive corresponding to the package we will be removing
-    let drive_path = format!("/{package_id}/pkg");
-
     // get manifest.json from drive
     vfs_request(
-        format!("{}/manifest.json", drive_path),
+        format!("/{package_id}/pkg/manifest.json"),
         vfs::VfsAction::Read,
     )
     .send_and_await_response(VFS_TIMEOUT)??;
@@ -426,30 +1095,407 @@ pub fn uninstall(state: &mut State, package_id: &PackageId) -> anyhow::Result<()
         ));
     };
     let manifest = serde_json::from_slice::<Vec<kt::PackageManifestEntry>>(&blob.bytes)?;
+    let manifest_hooks = serde_json::from_slice::<Vec<ManifestHooks>>(&blob.bytes)?;
 
-    // reading from the package manifest, kill every process named
+    for hooks in manifest_hooks.iter().filter(|hooks| hooks.on_uninstall) {
+        if let Err(e) = run_lifecycle_hook("our", package_id, hooks, &LifecycleHook::Uninstall) {
+            println!("uninstall hook failed for {package_id}: {e}");
+        }
+    }
+
+    // list drives before anything is removed
+    let drives = package_drives(package_id).unwrap_or_else(|e| {
+        println!("uninstall: couldn't list drives for {package_id}: {e}");
+        vec![format!("/{package_id}/pkg")]
+    });
+
+    // reading from the package manifest, kill every process named,
+    // then clear out its persisted state and HTTP bindings
     for entry in &manifest {
-        kernel_request(kt::KernelCommand::KillProcess(ProcessId::new(
+        let process_id = ProcessId::new(
             Some(&entry.process_name),
             package_id.package(),
             package_id.publisher(),
-        )))
-        .send()?;
+        );
+        if let Err(e) = remove_process(&process_id) {
+            println!("uninstall: error cleaning up {process_id}: {e}");
+        }
     }
 
-    // then, delete the drive
-    vfs_request(drive_path, vfs::VfsAction::RemoveDirAll)
-        .send_and_await_response(VFS_TIMEOUT)??;
+    for drive in &drives {
+        if let Err(e) =
+            vfs_request(drive, vfs::VfsAction::RemoveDirAll).send_and_await_response(VFS_TIMEOUT)
+        {
+            println!("uninstall: error removing drive {drive}: {e}");
+        }
+    }
+
+    for module in ["kv", "sqlite"] {
+        if let Err(e) = remove_all_dbs(module, package_id) {
+            println!("uninstall: error removing {module} databases for {package_id}: {e}");
+        }
+    }
 
     // Remove the package from the state
     state.packages.remove(package_id);
+    state.notified_updates.remove(package_id);
+    if state.rollbacks.remove(package_id).is_some() {
+        let _ = vfs_request(rollback_dir(package_id), vfs::VfsAction::RemoveDirAll)
+            .send_and_await_response(VFS_TIMEOUT);
+    }
 
     // If this package had an API, remove it from installed_apis
     state.installed_apis.remove(package_id);
 
+    reload_schedules();
+
+    state.update_policies.remove(&package_id.to_string());
+    state.save()?;
+
+    Ok(())
+}
+
+/// every package we have installed, for headless management
+pub fn list_installed(state: &State) -> Vec<InstalledPackage> {
+    state
+        .packages
+        .iter()
+        .map(|(package_id, package)| InstalledPackage {
+            package_id: crate::kinode::process::main::PackageId::from_process_lib(
+                package_id.clone(),
+            ),
+            version_hash: package.our_version_hash.clone(),
+            verified: package.verified,
+            caps_approved: package.caps_approved,
+            denied_capabilities: package.denied_caps.clone(),
+            update_policy: state.update_policy(package_id),
+        })
+        .collect()
+}
+
+/// remove the downloads that no installed package refers to, unless mirrored
+/// or pinned, and the vfs directories of packages that are gone. a dry run
+/// only reports what would be removed.
+pub fn gc(state: &State, dry_run: bool) -> anyhow::Result<GcReport> {
+    let installed = state
+        .packages
+        .iter()
+        .map(|(package_id, package)| Artifact {
+            package_id: crate::kinode::process::main::PackageId::from_process_lib(
+                package_id.clone(),
+            ),
+            version_hash: package.our_version_hash.clone(),
+        })
+        .collect();
+    let resp = Request::to(("our", "downloads", "app_store", "sys"))
+        .body(serde_json::to_vec(&DownloadRequests::Gc(GcRequest {
+            dry_run,
+            installed,
+        }))?)
+        .send_and_await_response(30)??;
+    let mut report = match serde_json::from_slice::<DownloadResponses>(resp.body())? {
+        DownloadResponses::Gc(report) => report,
+        other => return Err(anyhow::anyhow!("downloads failed to gc: {other:?}")),
+    };
+    for package_id in orphaned_packages(state)? {
+        let dir = format!("/{package_id}");
+        let size = dir_size(&dir)?;
+        if !dry_run {
+            vfs_request(&dir, vfs::VfsAction::RemoveDirAll)
+                .send_and_await_response(VFS_TIMEOUT)??;
+        }
+        report.reclaimed_bytes += size;
+        report.orphans.push(OrphanReport {
+            package_id: crate::kinode::process::main::PackageId::from_process_lib(package_id),
+            size,
+        });
+    }
+    Ok(report)
+}
+
+/// the packages with a directory in the vfs that are neither installed nor
+/// have a process running, as when left behind by an uninstall that failed
+/// partway. the runtime's own are never among them.
+fn orphaned_packages(state: &State) -> anyhow::Result<Vec<PackageId>> {
+    let running: HashSet<PackageId> = crate::permissions::process_map()?
+        .into_keys()
+        .map(|process_id| PackageId::new(process_id.package(), process_id.publisher()))
+        .collect();
+    Ok(vfs::open_dir("/", false, Some(VFS_TIMEOUT))?
+        .read()?
+        .into_iter()
+        .filter(|entry| entry.file_type == vfs::FileType::Directory)
+        .filter_map(|entry| entry.path.trim_matches('/').parse::<PackageId>().ok())
+        .filter(|package_id| {
+            package_id.to_string() != "distro:sys"
+                && !state.packages.contains_key(package_id)
+                && !running.contains(package_id)
+        })
+        .collect())
+}
+
+/// the size of every file under a vfs directory
+fn dir_size(path: &str) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for entry in vfs::open_dir(path, false, Some(VFS_TIMEOUT))?.read()? {
+        size += match entry.file_type {
+            vfs::FileType::Directory => dir_size(&entry.path)?,
+            vfs::FileType::File => vfs::metadata(&entry.path, Some(VFS_TIMEOUT))?.len,
+            _ => 0,
+        };
+    }
+    Ok(size)
+}
+
+/// the hash of the version a listing currently points to
+fn current_version_hash(metadata: &OnchainMetadata) -> Option<String> {
+    metadata
+        .properties
+        .code_hashes
+        .iter()
+        .find(|(version, _)| version == &metadata.properties.current_version)
+        .map(|(_, hash)| hash.clone())
+}
+
+/// set how an installed package is kept up to date.
+/// returns false if the package is not installed.
+pub fn set_update_policy(state: &mut State, package_id: &PackageId, policy: UpdatePolicy) -> bool {
+    if !state.packages.contains_key(package_id) {
+        return false;
+    }
+    state.update_policies.insert(package_id.to_string(), policy);
+    // a fresh policy should announce updates it would previously have skipped
+    state.notified_updates.remove(package_id);
+    if let Err(e) = state.save() {
+        println!("error saving update policy for {package_id}: {e}");
+    }
+    true
+}
+
+/// a newer onchain version of an installed package
+pub struct AvailableUpdate {
+    pub package_id: PackageId,
+    pub version_hash: String,
+}
+
+/// compare every installed package that is not pinned against its onchain
+/// listing. auto-updating packages are handed to downloads, which reports
+/// back once the new version is fetched so that it can be installed.
+/// returns the updates for notify-only packages that the user has not
+/// yet been told about.
+pub fn check_for_updates(state: &mut State) -> Vec<AvailableUpdate> {
+    let mut available = vec![];
+    let package_ids: Vec<PackageId> = state.packages.keys().cloned().collect();
+    for package_id in package_ids {
+        let policy = state.update_policy(&package_id);
+        if policy == UpdatePolicy::Pinned {
+            continue;
+        }
+        // sideloaded packages have no listing to compare against
+        let wit_package_id =
+            crate::kinode::process::main::PackageId::from_process_lib(package_id.clone());
+        let Ok(metadata) = fetch_package_metadata(&wit_package_id) else {
+            continue;
+        };
+        let Some(version_hash) = current_version_hash(&metadata) else {
+            continue;
+        };
+        if state
+            .packages
+            .get(&package_id)
+            .is_some_and(|package| package.our_version_hash == version_hash)
+            || state.notified_updates.get(&package_id) == Some(&version_hash)
+        {
+            continue;
+        }
+        if policy == UpdatePolicy::Auto {
+            if let Err(e) = Request::to(("our", "downloads", "app_store", "sys"))
+                .body(
+                    serde_json::to_vec(&DownloadRequests::AutoUpdate(AutoUpdateRequest {
+                        package_id: wit_package_id,
+                        metadata,
+                    }))
+                    .unwrap(),
+                )
+                .send()
+            {
+                println!("update check: failed to start auto-update of {package_id}: {e}");
+                continue;
+            }
+        } else {
+            available.push(AvailableUpdate {
+                package_id: package_id.clone(),
+                version_hash: version_hash.clone(),
+            });
+        }
+        state.notified_updates.insert(package_id, version_hash);
+    }
+    available
+}
+
+/// update an installed package to the version currently listed onchain.
+///
+/// if that version has not been downloaded yet, a fetch is started and
+/// the caller should try again once it completes. if the new version's
+/// manifest differs from the installed one, `caps_approved` is required.
+pub fn update(
+    package_id: &crate::kinode::process::main::PackageId,
+    caps_approved: bool,
+    state: &mut State,
+    our_node: &str,
+) -> anyhow::Result<UpdateResponse> {
+    let process_package_id = package_id.clone().to_process_lib();
+    let Some(package) = state.packages.get(&process_package_id) else {
+        return Err(anyhow::anyhow!("package not installed"));
+    };
+    let metadata = fetch_package_metadata(package_id)?;
+    let properties = &metadata.properties;
+    let Some(version_hash) = current_version_hash(&metadata) else {
+        return Err(anyhow::anyhow!(
+            "no hash listed for current version {}",
+            properties.current_version
+        ));
+    };
+    if package.our_version_hash == version_hash {
+        return Ok(UpdateResponse::UpToDate);
+    }
+
+    let manifest_path =
+        format!("/app_store:sys/downloads/{process_package_id}/{version_hash}.json");
+    let Ok(manifest_file) = vfs::open_file(&manifest_path, false, Some(VFS_TIMEOUT)) else {
+        // not downloaded yet: fetch from the publisher, then onchain mirrors
+        let mut extra_mirrors = vec![properties.publisher.clone()];
+        extra_mirrors.extend(properties.mirrors.clone());
+        Request::to(("our", "downloads", "app_store", "sys"))
+            .body(serde_json::to_vec(&DownloadRequests::Fetch(
+                FetchRequest {
+                    package_id: package_id.clone(),
+                    version_hash,
+                    extra_mirrors,
+                },
+            ))?)
+            .send()?;
+        return Ok(UpdateResponse::Downloading);
+    };
+    let manifest_hash = keccak_256_hash(&manifest_file.read()?);
+    if !caps_approved && package.manifest_hash.as_ref() != Some(&manifest_hash) {
+        return Ok(UpdateResponse::CapsNotApproved);
+    }
+
+    let denied_caps = package.denied_caps.clone();
+    install(
+        package_id,
+        Some(metadata),
+        &version_hash,
+        &denied_caps,
+        state,
+        our_node,
+    )?;
+    Ok(UpdateResponse::Success)
+}
+
+/// dry run of [`uninstall`]: everything that uninstalling a package would remove
+pub fn uninstall_report(
+    our_node: &str,
+    state: &State,
+    package_id: &PackageId,
+) -> anyhow::Result<UninstallReport> {
+    if !state.packages.contains_key(package_id) {
+        return Err(anyhow::anyhow!("package not found"));
+    }
+    let manifest = fetch_package_manifest(package_id)?;
+    let mut http_bindings: Vec<String> = crate::permissions::bindings(package_id)?
+        .into_values()
+        .flatten()
+        .map(|binding| match binding.websocket {
+            true => format!("{} (websocket)", binding.path),
+            false => binding.path,
+        })
+        .collect();
+    http_bindings.sort();
+    Ok(UninstallReport {
+        processes: manifest
+            .iter()
+            .map(|entry| {
+                ProcessId::new(
+                    Some(&entry.process_name),
+                    package_id.package(),
+                    package_id.publisher(),
+                )
+                .to_string()
+            })
+            .collect(),
+        drives: package_drives(package_id)?,
+        http_bindings,
+        kv_databases: list_dbs("kv", package_id)?,
+        sqlite_databases: list_dbs("sqlite", package_id)?,
+        revoked_capabilities: crate::permissions::issued_capabilities(our_node, package_id)?,
+    })
+}
+
+/// every VFS drive belonging to a package. requires VFS root, which we have.
+pub fn package_drives(package_id: &PackageId) -> anyhow::Result<Vec<String>> {
+    let resp = vfs_request(format!("/{package_id}"), vfs::VfsAction::ReadDir)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    let vfs::VfsResponse::ReadDir(entries) = serde_json::from_slice(resp.body())? else {
+        return Err(anyhow::anyhow!("couldn't read drives of {package_id}"));
+    };
+    Ok(entries
+        .into_iter()
+        .map(|entry| format!("/{}", entry.path.trim_start_matches('/')))
+        .collect())
+}
+
+/// kill a process, then delete its persisted state and HTTP bindings.
+/// the kernel revokes the capabilities it issued as part of the kill.
+fn remove_process(process_id: &ProcessId) -> anyhow::Result<()> {
+    // await the kill so nothing is recreated after it is cleaned up.
+    // a process that is already dead gets no response: clean up regardless.
+    let _ = kernel_request(kt::KernelCommand::KillProcess(process_id.clone()))
+        .send_and_await_response(VFS_TIMEOUT);
+    Request::to(("our", "state", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "DeleteState": process_id }),
+        )?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    // `UnbindAll` is not yet exposed by process_lib, so we build it by hand.
+    Request::to(("our", "http_server", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "UnbindAll": { "process": process_id } }),
+        )?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    Ok(())
+}
+
+/// remove every database a package has in the kv or sqlite module.
+/// requires that module's root capability, which we have.
+fn remove_all_dbs(module: &str, package_id: &PackageId) -> anyhow::Result<()> {
+    Request::to(("our", module, "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({
+            "package_id": package_id,
+            "db": "",
+            "action": "RemoveAllDbs",
+        }))?)
+        .send_and_await_response(VFS_TIMEOUT)??;
     Ok(())
 }
 
+/// the names of every database a package has in the kv or sqlite module.
+/// requires that module's root capability, which we have.
+fn list_dbs(module: &str, package_id: &PackageId) -> anyhow::Result<Vec<String>> {
+    Request::to(("our", module, "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({
+            "package_id": package_id,
+            "db": "",
+            "action": "ListDbs",
+        }))?)
+        .send_and_await_response(VFS_TIMEOUT)??;
+    let Some(blob) = get_blob() else {
+        return Err(anyhow::anyhow!("{module} listed no databases"));
+    };
+    Ok(serde_json::from_slice(&blob.bytes)?)
+}
+
 pub fn _extract_caps_hashes(manifest_bytes: &[u8]) -> anyhow::Result<HashMap<String, String>> {
     let manifest = serde_json::from_slice::<Vec<kt::PackageManifestEntry>>(manifest_bytes)?;
     let mut caps_hashes = HashMap::new();
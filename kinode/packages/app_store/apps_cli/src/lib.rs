@@ -0,0 +1,148 @@
+//! apps: discover the apps listed onchain from the terminal.
+//!
+//! a thin wrapper around the search API of chain:app_store:sys.
+use crate::kinode::process::chain::{ChainRequests, ChainResponses, SearchRequest, SearchResult};
+use kinode_process_lib::{await_next_message_body, call_init, println, Address, Message, Request};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    generate_unused_types: true,
+    world: "app-store-sys-v0",
+    additional_derives: [PartialEq, serde::Deserialize, serde::Serialize],
+});
+
+const USAGE: &str = "usage:
+  apps search [<words>...] [--category <category>] [--limit <n>] [--no-counts]
+  apps categories";
+
+const DEFAULT_LIMIT: u32 = 10;
+
+call_init!(init);
+fn init(our: Address) {
+    let Ok(body) = await_next_message_body() else {
+        println!("apps: failed to get args!");
+        return;
+    };
+
+    let arg = String::from_utf8(body).unwrap_or_default();
+    let args: Vec<&str> = arg.split_whitespace().collect();
+
+    let (request, timeout) = match args.as_slice() {
+        ["categories"] => (ChainRequests::GetCategories, 5),
+        ["search", rest @ ..] => {
+            let Some(search) = parse_search(rest) else {
+                println!("{USAGE}");
+                return;
+            };
+            // the publisher of each result is asked for its install count,
+            // and has up to 2s to answer
+            let timeout = if search.install_counts {
+                5 + 2 * search.limit as u64
+            } else {
+                5
+            };
+            (ChainRequests::Search(search), timeout)
+        }
+        _ => {
+            println!("{USAGE}");
+            return;
+        }
+    };
+
+    let Ok(Ok(Message::Response { body, .. })) =
+        Request::to((our.node(), ("chain", "app_store", "sys")))
+            .body(serde_json::to_vec(&request).unwrap())
+            .send_and_await_response(timeout)
+    else {
+        println!("apps: failed to get a response from app_store..!");
+        return;
+    };
+
+    match serde_json::from_slice::<ChainResponses>(&body) {
+        Ok(ChainResponses::Search(results)) if results.is_empty() => {
+            println!("no apps found");
+        }
+        Ok(ChainResponses::Search(results)) => {
+            println!("{}", display_results(results));
+        }
+        Ok(ChainResponses::GetCategories(categories)) if categories.is_empty() => {
+            println!("no apps list a category");
+        }
+        Ok(ChainResponses::GetCategories(categories)) => {
+            println!(
+                "{}",
+                categories
+                    .into_iter()
+                    .map(|(category, count)| format!("{category} ({count})"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        _ => println!("apps: unexpected response from app_store..!"),
+    }
+}
+
+fn parse_search(args: &[&str]) -> Option<SearchRequest> {
+    let mut words = vec![];
+    let mut category = None;
+    let mut limit = DEFAULT_LIMIT;
+    let mut install_counts = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--category" => category = Some(args.next()?.to_string()),
+            "--limit" => limit = args.next()?.parse().ok()?,
+            "--no-counts" => install_counts = false,
+            flag if flag.starts_with("--") => {
+                println!("apps: unrecognized flag {flag}");
+                return None;
+            }
+            word => words.push(word),
+        }
+    }
+    Some(SearchRequest {
+        query: (!words.is_empty()).then(|| words.join(" ")),
+        category,
+        limit,
+        install_counts,
+    })
+}
+
+fn display_results(results: Vec<SearchResult>) -> String {
+    results
+        .into_iter()
+        .map(|result| {
+            let metadata = result.app.metadata.as_ref();
+            let mut line = format!(
+                "{}:{}",
+                result.app.package_id.package_name, result.app.package_id.publisher_node
+            );
+            if let Some(name) = metadata.and_then(|metadata| metadata.name.as_ref()) {
+                line.push_str(&format!(" \"{name}\""));
+            }
+            if let Some(metadata) = metadata {
+                line.push_str(&format!(" v{}", metadata.properties.current_version));
+            }
+            if !result.categories.is_empty() {
+                line.push_str(&format!(" [{}]", result.categories.join(", ")));
+            }
+            if let Some(count) = result.install_count {
+                line.push_str(&format!(
+                    ", {count} install{}",
+                    if count == 1 { "" } else { "s" }
+                ));
+            }
+            if let Some(description) = metadata.and_then(|metadata| metadata.description.as_ref()) {
+                line.push_str(&format!("\n    {description}"));
+            }
+            line.push_str(&format!(
+                "\n    publisher has listed {} app{} since block {}",
+                result.publisher_apps,
+                if result.publisher_apps == 1 { "" } else { "s" },
+                result.publisher_since_block,
+            ));
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
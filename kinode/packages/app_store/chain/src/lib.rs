@@ -6,7 +6,7 @@
 use crate::kinode::process::chain::{
     ChainError, ChainRequests, OnchainApp, OnchainMetadata, OnchainProperties,
 };
-use crate::kinode::process::downloads::{AutoUpdateRequest, DownloadRequests};
+use crate::kinode::process::downloads::{AutoUpdateRequest, DownloadRequests, DownloadResponses};
 use alloy_primitives::keccak256;
 use alloy_sol_types::SolEvent;
 use kinode::process::chain::ChainResponses;
@@ -28,6 +28,8 @@ wit_bindgen::generate!({
     additional_derives: [serde::Deserialize, serde::Serialize],
 });
 
+mod search;
+
 #[cfg(not(feature = "simulation-mode"))]
 const CHAIN_ID: u64 = kimap::KIMAP_CHAIN_ID;
 #[cfg(feature = "simulation-mode")]
@@ -42,8 +44,17 @@ const KIMAP_ADDRESS: &str = "0xcA92476B2483aBD5D82AEBF0b56701Bb2e9be658";
 
 const DELAY_MS: u64 = 1_000; // 1s
 
+/// the version of [`State`]. a state saved by an older version is brought
+/// up to this one by [`migrate`], filling in what the older one didn't keep.
+const STATE_VERSION: u32 = 1;
+/// how long to wait on a listing's metadata when fetching it again to migrate
+const MIGRATE_METADATA_TIMEOUT: u64 = 5;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct State {
+    /// see [`STATE_VERSION`]; states from before it was kept have none
+    #[serde(default)]
+    pub version: u32,
     /// the kimap helper we are using
     pub kimap: kimap::Kimap,
     /// the last block at which we saved the state of the listings to disk.
@@ -54,6 +65,10 @@ pub struct State {
     pub listings: HashMap<PackageId, PackageListing>,
     /// set of packages that we have published
     pub published: HashSet<PackageId>,
+    /// install counts publishers gave when searched, with when they were
+    /// asked, in seconds since the unix epoch
+    #[serde(skip)]
+    pub install_counts: HashMap<PackageId, (Option<u64>, u64)>,
 }
 
 /// listing information derived from metadata hash in listing event
@@ -66,6 +81,14 @@ pub struct PackageListing {
     // relegate to only valid apps maybe?
     pub metadata: Option<kt::Erc721Metadata>,
     pub auto_update: bool,
+    /// the categories the metadata lists, which kt::Erc721Metadata omits
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// the blocks the package was first listed and last updated at
+    #[serde(default)]
+    pub listed_block: u64,
+    #[serde(default)]
+    pub updated_block: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -104,6 +127,15 @@ fn init(our: Address) {
 
 fn handle_message(our: &Address, state: &mut State, message: &Message) -> anyhow::Result<()> {
     if !message.is_request() {
+        if message.source().process == "downloads:app_store:sys" {
+            // install counts asked of a publisher when searching
+            if let Ok(DownloadResponses::InstallCounts(counts)) =
+                serde_json::from_slice(message.body())
+            {
+                search::install_counts_given(state, &message.source().node, counts);
+            }
+            return Ok(());
+        }
         if message.is_local(&our) && message.source().process == "timer:distro:sys" {
             // handling of ETH RPC subscriptions delayed by DELAY_MS
             // to allow kns to have a chance to process block: handle now
@@ -210,6 +242,18 @@ fn handle_local_request(state: &mut State, req: ChainRequests) -> anyhow::Result
                     .send()?;
             }
         }
+        ChainRequests::Search(search_request) => {
+            let response = ChainResponses::Search(search::search(state, search_request));
+            Response::new()
+                .body(serde_json::to_vec(&response)?)
+                .send()?;
+        }
+        ChainRequests::GetCategories => {
+            let response = ChainResponses::GetCategories(search::categories(state));
+            Response::new()
+                .body(serde_json::to_vec(&response)?)
+                .send()?;
+        }
         ChainRequests::StopAutoUpdate(package_id) => {
             if let Some(listing) = state.listings.get_mut(&package_id.to_process_lib()) {
                 listing.auto_update = false;
@@ -292,7 +336,7 @@ fn handle_eth_log(our: &Address, state: &mut State, log: eth::Log) -> anyhow::Re
 
     // fetch metadata from the URI (currently only handling HTTP(S) URLs!)
     // assert that the metadata hash matches the fetched data
-    let metadata = fetch_metadata_from_url(&metadata_uri, &metadata_hash, 30)?;
+    let (metadata, categories) = fetch_metadata_from_url(&metadata_uri, &metadata_hash, 30)?;
 
     match state.listings.entry(package_id.clone()) {
         std::collections::hash_map::Entry::Occupied(mut listing) => {
@@ -301,6 +345,13 @@ fn handle_eth_log(our: &Address, state: &mut State, log: eth::Log) -> anyhow::Re
             listing.tba = tba;
             listing.metadata_hash = metadata_hash;
             listing.metadata = Some(metadata.clone());
+            listing.categories = categories;
+            if listing.listed_block == 0 {
+                // kept by a version that didn't note it: this is the
+                // first log read again for the package
+                listing.listed_block = block_number;
+            }
+            listing.updated_block = block_number;
         }
         std::collections::hash_map::Entry::Vacant(listing) => {
             listing.insert(PackageListing {
@@ -309,6 +360,9 @@ fn handle_eth_log(our: &Address, state: &mut State, log: eth::Log) -> anyhow::Re
                 metadata_hash,
                 metadata: Some(metadata.clone()),
                 auto_update: false,
+                categories,
+                listed_block: block_number,
+                updated_block: block_number,
             });
         }
     }
@@ -382,12 +436,13 @@ fn fetch_logs(eth_provider: &eth::Provider, filter: &eth::Filter) -> Vec<eth::Lo
     }
 }
 
-/// fetch metadata from url and verify it matches metadata_hash
+/// fetch metadata from url and verify it matches metadata_hash,
+/// returning it along with the categories it lists
 pub fn fetch_metadata_from_url(
     metadata_url: &str,
     metadata_hash: &str,
     timeout: u64,
-) -> Result<kt::Erc721Metadata, anyhow::Error> {
+) -> Result<(kt::Erc721Metadata, Vec<String>), anyhow::Error> {
     if let Ok(url) = url::Url::parse(metadata_url) {
         if let Ok(_) =
             http::client::send_request_await_response(http::Method::GET, url, None, timeout, vec![])
@@ -395,8 +450,9 @@ pub fn fetch_metadata_from_url(
             if let Some(body) = get_blob() {
                 let hash = keccak_256_hash(&body.bytes);
                 if &hash == metadata_hash {
-                    return Ok(serde_json::from_slice::<kt::Erc721Metadata>(&body.bytes)
-                        .map_err(|_| anyhow::anyhow!("metadata not found"))?);
+                    let metadata = serde_json::from_slice::<kt::Erc721Metadata>(&body.bytes)
+                        .map_err(|_| anyhow::anyhow!("metadata not found"))?;
+                    return Ok((metadata, search::categories_of(&body.bytes)));
                 } else {
                     return Err(anyhow::anyhow!("metadata hash mismatch"));
                 }
@@ -418,8 +474,11 @@ pub fn keccak_256_hash(bytes: &[u8]) -> String {
 pub fn fetch_state(provider: eth::Provider) -> State {
     if let Some(state_bytes) = get_state() {
         match serde_json::from_slice::<State>(&state_bytes) {
-            Ok(state) => {
+            Ok(mut state) => {
                 if state.kimap.address().to_string() == KIMAP_ADDRESS {
+                    if state.version < STATE_VERSION {
+                        migrate(&mut state);
+                    }
                     return state;
                 } else {
                     println!(
@@ -433,11 +492,37 @@ pub fn fetch_state(provider: eth::Provider) -> State {
         }
     }
     State {
+        version: STATE_VERSION,
         kimap: kimap::Kimap::new(provider, eth::Address::from_str(KIMAP_ADDRESS).unwrap()),
         last_saved_block: 0,
         listings: HashMap::new(),
         published: HashSet::new(),
+        install_counts: HashMap::new(),
+    }
+}
+
+/// bring a state saved by an older version up to [`STATE_VERSION`], without
+/// reading the logs again
+fn migrate(state: &mut State) {
+    if state.version < 1 {
+        // categories weren't kept, so fetch each listing's metadata again for
+        // them. nor were the blocks listings were made at: the block the state
+        // was saved at is the latest they can have been.
+        println!("state from an older version: fetching listing metadata again");
+        let saved_block = state.last_saved_block;
+        for listing in state.listings.values_mut() {
+            if let Ok((_, categories)) = fetch_metadata_from_url(
+                &listing.metadata_uri,
+                &listing.metadata_hash,
+                MIGRATE_METADATA_TIMEOUT,
+            ) {
+                listing.categories = categories;
+            }
+            listing.listed_block = saved_block;
+            listing.updated_block = saved_block;
+        }
     }
+    state.version = STATE_VERSION;
 }
 
 // quite annoyingly, we must convert from our gen'd version of PackageId
@@ -0,0 +1,289 @@
+//! search and category browsing over the apps listed onchain.
+//!
+//! apps are matched against every word of a query, and ranked by where the
+//! words appear: in the package name counts most, in the description least.
+//! install counts come from each app's publisher, which counts the distinct
+//! nodes that have downloaded the app from it. a search gives the counts
+//! kept, and asks the publishers of those that are missing or old for new
+//! ones in the background, one request per publisher, so that it never waits
+//! on them.
+use crate::kinode::process::chain::{SearchRequest, SearchResult};
+use crate::kinode::process::downloads::DownloadRequests;
+use crate::kinode::process::main::PackageId as WitPackageId;
+use crate::{PackageListing, State};
+use kinode_process_lib::{PackageId, Request};
+use std::collections::HashMap;
+
+/// most results a search gives
+pub const MAX_LIMIT: u32 = 100;
+/// how long a publisher has to give install counts
+const INSTALL_COUNT_TIMEOUT: u64 = 30;
+/// how long an install count is used before its publisher is asked again
+const INSTALL_COUNT_MAX_AGE: u64 = 60 * 60;
+/// most categories kept for an app, and longest name kept for one
+const MAX_CATEGORIES: usize = 8;
+const MAX_CATEGORY_LEN: usize = 32;
+
+pub fn search(state: &mut State, request: SearchRequest) -> Vec<SearchResult> {
+    let terms: Vec<String> = request
+        .query
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect();
+    let category = request.category.map(|category| category.to_lowercase());
+
+    let mut matches: Vec<(u32, &PackageId, &PackageListing)> = state
+        .listings
+        .iter()
+        .filter(|(_, listing)| {
+            category
+                .as_ref()
+                .map_or(true, |category| listing.categories.contains(category))
+        })
+        .filter_map(|(id, listing)| Some((score(&terms, id, listing)?, id, listing)))
+        .collect();
+    matches.sort_by(|(a_score, _, a), (b_score, _, b)| {
+        b_score
+            .cmp(a_score)
+            .then(b.updated_block.cmp(&a.updated_block))
+    });
+    matches.truncate(request.limit.min(MAX_LIMIT) as usize);
+
+    // each publisher's count of apps, and the block it first listed one at
+    let mut publishers: HashMap<&str, (u32, u64)> = HashMap::new();
+    for (id, listing) in &state.listings {
+        let (apps, since) = publishers.entry(id.publisher()).or_insert((0, u64::MAX));
+        *apps += 1;
+        *since = (*since).min(listing.listed_block);
+    }
+
+    let mut results: Vec<(PackageId, SearchResult)> = matches
+        .into_iter()
+        .map(|(_, id, listing)| {
+            let (publisher_apps, publisher_since_block) = publishers[id.publisher()];
+            (
+                id.clone(),
+                SearchResult {
+                    app: listing.to_onchain_app(id),
+                    categories: listing.categories.clone(),
+                    updated_block: listing.updated_block,
+                    publisher_apps,
+                    publisher_since_block,
+                    install_count: None,
+                },
+            )
+        })
+        .collect();
+    if request.install_counts {
+        for (id, result) in &mut results {
+            result.install_count = state.install_counts.get(id).and_then(|(count, _)| *count);
+        }
+        ask_install_counts(state, results.iter().map(|(id, _)| id));
+    }
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// every category apps are listed in, with how many are in each, the
+/// fullest first
+pub fn categories(state: &State) -> Vec<(String, u32)> {
+    let mut counts: HashMap<&str, u32> = HashMap::new();
+    for listing in state.listings.values() {
+        for category in &listing.categories {
+            *counts.entry(category.as_str()).or_default() += 1;
+        }
+    }
+    let mut categories: Vec<(String, u32)> = counts
+        .into_iter()
+        .map(|(category, count)| (category.to_string(), count))
+        .collect();
+    categories.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    categories
+}
+
+/// the categories app metadata lists under `properties.categories`,
+/// lowercased
+pub fn categories_of(metadata: &[u8]) -> Vec<String> {
+    let Ok(metadata) = serde_json::from_slice::<serde_json::Value>(metadata) else {
+        return vec![];
+    };
+    let Some(categories) = metadata["properties"]["categories"].as_array() else {
+        return vec![];
+    };
+    let mut categories: Vec<String> = categories
+        .iter()
+        .filter_map(|category| category.as_str())
+        .map(|category| category.trim().to_lowercase())
+        .filter(|category| !category.is_empty() && category.len() <= MAX_CATEGORY_LEN)
+        .collect();
+    categories.sort();
+    categories.dedup();
+    categories.truncate(MAX_CATEGORIES);
+    categories
+}
+
+/// how well an app matches the terms of a query, or `None` if any term
+/// matches nothing. every app matches an empty query equally.
+fn score(terms: &[String], id: &PackageId, listing: &PackageListing) -> Option<u32> {
+    let metadata = listing.metadata.as_ref();
+    let name = metadata
+        .and_then(|metadata| metadata.name.as_deref())
+        .unwrap_or_default()
+        .to_lowercase();
+    let description = metadata
+        .and_then(|metadata| metadata.description.as_deref())
+        .unwrap_or_default()
+        .to_lowercase();
+    let package = id.package().to_lowercase();
+    let publisher = id.publisher().to_lowercase();
+    terms
+        .iter()
+        .map(|term| {
+            let score = [
+                (package == *term, 10),
+                (package.contains(term.as_str()), 5),
+                (name.contains(term.as_str()), 4),
+                (publisher.contains(term.as_str()), 3),
+                (description.contains(term.as_str()), 1),
+            ]
+            .into_iter()
+            .filter(|(matched, _)| *matched)
+            .map(|(_, score)| score)
+            .sum::<u32>();
+            (score > 0).then_some(score)
+        })
+        .sum()
+}
+
+/// ask the publishers of the apps whose install counts are missing or old
+/// for new ones, each publisher once for all of its apps. an app is noted as
+/// asked about at once, so that searches meanwhile don't ask again; the
+/// counts it had are kept until the answer comes, in [`install_counts_given`].
+fn ask_install_counts<'a>(state: &mut State, package_ids: impl Iterator<Item = &'a PackageId>) {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut by_publisher: HashMap<&str, Vec<WitPackageId>> = HashMap::new();
+    for package_id in package_ids {
+        let (_, asked) = state
+            .install_counts
+            .entry(package_id.clone())
+            .or_insert((None, 0));
+        if now.saturating_sub(*asked) < INSTALL_COUNT_MAX_AGE {
+            continue;
+        }
+        *asked = now;
+        by_publisher
+            .entry(package_id.publisher())
+            .or_default()
+            .push(WitPackageId::from_process_lib(package_id.clone()));
+    }
+    for (publisher, package_ids) in by_publisher {
+        let request = DownloadRequests::InstallCounts(package_ids);
+        let _ = Request::to((publisher, ("downloads", "app_store", "sys")))
+            .body(serde_json::to_vec(&request).unwrap())
+            .expects_response(INSTALL_COUNT_TIMEOUT)
+            .send();
+    }
+}
+
+/// keep the install counts a publisher gave, for its own apps only
+pub fn install_counts_given(
+    state: &mut State,
+    publisher: &str,
+    counts: Vec<(WitPackageId, Option<u64>)>,
+) {
+    for (package_id, count) in counts {
+        let package_id = package_id.to_process_lib();
+        if package_id.publisher() != publisher {
+            continue;
+        }
+        if let Some((kept, _)) = state.install_counts.get_mut(&package_id) {
+            *kept = count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kinode_process_lib::{eth, kernel_types as kt};
+
+    fn listing(name: &str, description: &str) -> PackageListing {
+        let metadata = serde_json::json!({
+            "name": name,
+            "description": description,
+            "image": "",
+            "properties": {
+                "package_name": "chess",
+                "current_version": "0.1.0",
+                "publisher": "sys",
+                "mirrors": [],
+                "code_hashes": { "0.1.0": "" },
+                "wit_version": 0,
+                "dependencies": [],
+            },
+            "external_url": "",
+            "animation_url": "",
+        });
+        PackageListing {
+            tba: eth::Address::ZERO,
+            metadata_uri: String::new(),
+            metadata_hash: String::new(),
+            metadata: Some(serde_json::from_value::<kt::Erc721Metadata>(metadata).unwrap()),
+            auto_update: false,
+            categories: vec![],
+            listed_block: 0,
+            updated_block: 0,
+        }
+    }
+
+    fn terms(query: &str) -> Vec<String> {
+        query.split_whitespace().map(str::to_lowercase).collect()
+    }
+
+    #[test]
+    fn score_weighs_where_terms_appear() {
+        let id = PackageId::new("chess", "sys");
+        let listing = listing("Chess", "play a board game with friends");
+        assert_eq!(score(&[], &id, &listing), Some(0));
+        // the package name, exactly and as part of it, and the app name
+        assert_eq!(score(&terms("chess"), &id, &listing), Some(19));
+        assert_eq!(score(&terms("che"), &id, &listing), Some(9));
+        assert_eq!(score(&terms("sys"), &id, &listing), Some(3));
+        assert_eq!(score(&terms("board"), &id, &listing), Some(1));
+        assert_eq!(score(&terms("board che"), &id, &listing), Some(10));
+        // every term must match something
+        assert_eq!(score(&terms("board checkers"), &id, &listing), None);
+    }
+
+    #[test]
+    fn categories_are_cleaned_up() {
+        let metadata = serde_json::json!({
+            "properties": {
+                "categories": [
+                    " Games ",
+                    "games",
+                    "",
+                    7,
+                    "Social",
+                    "x".repeat(MAX_CATEGORY_LEN + 1),
+                ],
+            },
+        });
+        assert_eq!(
+            categories_of(&serde_json::to_vec(&metadata).unwrap()),
+            vec!["games", "social"]
+        );
+        let many: Vec<String> = (0..MAX_CATEGORIES + 2).map(|i| format!("c{i}")).collect();
+        let metadata = serde_json::json!({ "properties": { "categories": many } });
+        assert_eq!(
+            categories_of(&serde_json::to_vec(&metadata).unwrap()).len(),
+            MAX_CATEGORIES
+        );
+        assert!(categories_of(b"{\"properties\":{}}").is_empty());
+        assert!(categories_of(b"not json").is_empty());
+    }
+}
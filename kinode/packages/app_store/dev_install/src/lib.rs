@@ -0,0 +1,99 @@
+use crate::kinode::process::main::{
+    DevInstallRequest, InstallResponse, LocalRequest, LocalResponse,
+};
+use kinode_process_lib::{
+    await_next_message_body, call_init, println, Address, Message, PackageId, Request,
+};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    generate_unused_types: true,
+    world: "app-store-sys-v0",
+    additional_derives: [PartialEq, serde::Deserialize, serde::Serialize],
+});
+
+call_init!(init);
+fn init(our: Address) {
+    let Ok(body) = await_next_message_body() else {
+        println!("dev-install: failed to get args!");
+        return;
+    };
+
+    let arg = String::from_utf8(body).unwrap_or_default();
+    let args: Vec<&str> = arg.split_whitespace().collect();
+
+    if args.len() < 2 {
+        println!("dev-install: 2 arguments required, the package id of the app and a host path");
+        println!("example: dev-install app:publisher.os /home/me/app/pkg --watch");
+        println!("the path may be a package zip or a built pkg directory");
+        println!("add --watch to reinstall the package whenever the path changes");
+        println!("to stop watching: dev-install app:publisher.os --stop");
+        return;
+    }
+
+    let Ok(package_id) = args[0].parse::<PackageId>() else {
+        println!(
+            "dev-install: invalid package id, make sure to include package name and publisher"
+        );
+        println!("example: app_name:publisher_name");
+        return;
+    };
+
+    let wit_package_id = crate::kinode::process::main::PackageId {
+        package_name: package_id.package_name.clone(),
+        publisher_node: package_id.publisher_node.clone(),
+    };
+
+    let request = if args[1] == "--stop" {
+        LocalRequest::StopDevWatch(wit_package_id)
+    } else {
+        let watch = match args.get(2) {
+            None => false,
+            Some(&"--watch") => true,
+            Some(other) => {
+                println!("dev-install: unrecognized argument {other}");
+                return;
+            }
+        };
+        LocalRequest::DevInstall(DevInstallRequest {
+            package_id: wit_package_id,
+            host_path: args[1].to_string(),
+            watch,
+        })
+    };
+
+    let Ok(Ok(Message::Response { body, .. })) =
+        Request::to((our.node(), ("main", "app_store", "sys")))
+            .body(serde_json::to_vec(&request).unwrap())
+            .send_and_await_response(15)
+    else {
+        println!("dev-install: failed to get a response from app_store..!");
+        return;
+    };
+
+    let Ok(response) = serde_json::from_slice::<LocalResponse>(&body) else {
+        println!("dev-install: failed to parse response from app_store..!");
+        return;
+    };
+
+    match response {
+        LocalResponse::DevInstallResponse(InstallResponse::Success) => {
+            println!(
+                "successfully installed package {package_id} from {}",
+                args[1]
+            );
+        }
+        LocalResponse::DevInstallResponse(_) => {
+            println!("failed to install package {package_id} from {}", args[1]);
+        }
+        LocalResponse::StopDevWatchResponse(true) => {
+            println!("stopped watching {package_id}");
+        }
+        LocalResponse::StopDevWatchResponse(false) => {
+            println!("dev-install: {package_id} was not being watched");
+        }
+        _ => {
+            println!("dev-install: unexpected response from app_store..!");
+        }
+    }
+}
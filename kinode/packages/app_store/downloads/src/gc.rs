@@ -0,0 +1,148 @@
+//! pinning and garbage collection of downloaded packages.
+//!
+//! each zip we hold is kept for as long as something refers to it: an
+//! installed package at that version, mirroring its package, a pin set by
+//! the user, or a download still writing it. a gc removes the rest, along
+//! with their manifests and the copies main:app_store:sys unpacks from them
+//! to install from.
+use crate::kinode::process::downloads::{
+    Artifact, ArtifactReference, ArtifactReport, GcReport, GcRequest, PackageId as WitPackageId,
+};
+use crate::{mirrors, State};
+use kinode_process_lib::{set_state, vfs, PackageId, Request};
+use std::collections::HashSet;
+
+const DOWNLOADS_PATH: &str = "/app_store:sys/downloads";
+
+pub fn collect(
+    state: &mut State,
+    request: GcRequest,
+    downloading: &HashSet<(PackageId, String)>,
+    fetcher: &mirrors::Fetcher,
+) -> anyhow::Result<GcReport> {
+    let installed: HashSet<(PackageId, String)> = request
+        .installed
+        .into_iter()
+        .map(|artifact| (artifact.package_id.to_process_lib(), artifact.version_hash))
+        .collect();
+    let mut report = GcReport {
+        dry_run: request.dry_run,
+        kept: vec![],
+        removed: vec![],
+        reclaimed_bytes: 0,
+        // left to main:app_store:sys, which knows what is installed
+        orphans: vec![],
+    };
+
+    for package_dir in vfs::open_dir(DOWNLOADS_PATH, false, None)?.read()? {
+        if package_dir.file_type != vfs::FileType::Directory {
+            continue;
+        }
+        // skips tmp/, the only directory not named for a package
+        let Some(Ok(package_id)) = package_dir
+            .path
+            .rsplit('/')
+            .next()
+            .map(|name| name.parse::<PackageId>())
+        else {
+            continue;
+        };
+        let entries = vfs::open_dir(&package_dir.path, false, None)?.read()?;
+        // unpacked copies of zips that are gone
+        for entry in &entries {
+            if entry.file_type != vfs::FileType::Directory
+                || entries
+                    .iter()
+                    .any(|zip| zip.path == format!("{}.zip", entry.path))
+            {
+                continue;
+            }
+            let size = dir_size(&entry.path);
+            if !request.dry_run {
+                remove_dir_all(&entry.path)?;
+            }
+            report.reclaimed_bytes += size;
+        }
+        for entry in entries {
+            let Some(version_hash) = entry
+                .path
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.strip_suffix(".zip"))
+            else {
+                continue;
+            };
+            let key = (package_id.clone(), version_hash.to_string());
+            let mut references = vec![];
+            if installed.contains(&key) {
+                references.push(ArtifactReference::Installed);
+            }
+            if state.mirroring.contains(&package_id) {
+                references.push(ArtifactReference::Mirrored);
+            }
+            if state.pins.contains(&key) {
+                references.push(ArtifactReference::Pinned);
+            }
+            if downloading.contains(&key) || fetcher.is_fetching(&package_id, version_hash) {
+                references.push(ArtifactReference::Downloading);
+            }
+
+            let manifest_path = format!("{}/{version_hash}.json", package_dir.path);
+            let unpacked_path = format!("{}/{version_hash}", package_dir.path);
+            let size =
+                file_size(&entry.path) + file_size(&manifest_path) + dir_size(&unpacked_path);
+            let artifact_report = ArtifactReport {
+                artifact: Artifact {
+                    package_id: WitPackageId::from_process_lib(package_id.clone()),
+                    version_hash: version_hash.to_string(),
+                },
+                size,
+                references,
+            };
+            if !artifact_report.references.is_empty() {
+                report.kept.push(artifact_report);
+                continue;
+            }
+            if !request.dry_run {
+                vfs::remove_file(&entry.path, None)?;
+                let _ = vfs::remove_file(&manifest_path, None);
+                let _ = remove_dir_all(&unpacked_path);
+                state.verified.remove(version_hash);
+            }
+            report.reclaimed_bytes += size;
+            report.removed.push(artifact_report);
+        }
+    }
+    if !request.dry_run && !report.removed.is_empty() {
+        set_state(&serde_json::to_vec(&state)?);
+    }
+    Ok(report)
+}
+
+fn file_size(path: &str) -> u64 {
+    vfs::metadata(path, None).map(|meta| meta.len).unwrap_or(0)
+}
+
+/// the size of every file under a directory, or 0 if there is none
+fn dir_size(path: &str) -> u64 {
+    let Ok(entries) = vfs::open_dir(path, false, None).and_then(|dir| dir.read()) else {
+        return 0;
+    };
+    entries
+        .iter()
+        .map(|entry| match entry.file_type {
+            vfs::FileType::Directory => dir_size(&entry.path),
+            _ => file_size(&entry.path),
+        })
+        .sum()
+}
+
+pub fn remove_dir_all(path: &str) -> anyhow::Result<()> {
+    Request::to(("our", "vfs", "distro", "sys"))
+        .body(serde_json::to_vec(&vfs::VfsRequest {
+            path: path.to_string(),
+            action: vfs::VfsAction::RemoveDirAll,
+        })?)
+        .send_and_await_response(5)??;
+    Ok(())
+}
@@ -2,12 +2,22 @@
 //! downloads:app_store:sys
 //! manages downloading and sharing of versioned packages.
 //!
+//! packages are content-addressed by the sha256 hash of their zip. a fetch
+//! pulls from the nodes already holding the zip, its swarm, if there are any,
+//! and otherwise tries each of a package's mirrors in priority order until
+//! one serves a zip matching the requested hash.
+//!
 use crate::kinode::process::downloads::{
-    AutoUpdateRequest, DirEntry, DownloadCompleteRequest, DownloadError, DownloadRequests,
-    DownloadResponses, Entry, FileEntry, HashMismatch, LocalDownloadRequest, RemoteDownloadRequest,
-    RemoveFileRequest,
+    Artifact, AutoUpdateRequest, DirEntry, DownloadCompleteRequest, DownloadError,
+    DownloadRequests, DownloadResponses, Entry, FetchRequest, FileEntry, HashMismatch,
+    LocalDownloadRequest, MirrorConfig, RemoteDownloadRequest, RemoveFileRequest,
+    SetMirrorsRequest,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Seek},
+    str::FromStr,
 };
-use std::{collections::HashSet, io::Read, str::FromStr};
 
 use ft_worker_lib::{spawn_receive_transfer, spawn_send_transfer};
 use kinode_process_lib::{
@@ -15,7 +25,7 @@ use kinode_process_lib::{
     http::client,
     print_to_terminal, println, set_state,
     vfs::{self, Directory, File},
-    Address, Message, PackageId, ProcessId, Request, Response,
+    Address, Message, PackageId, ProcessId, Request, Response, SendError, SendErrorKind,
 };
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
@@ -28,9 +38,14 @@ wit_bindgen::generate!({
 });
 
 mod ft_worker_lib;
+mod gc;
+mod mirrors;
+mod swarm;
 
 pub const VFS_TIMEOUT: u64 = 5; // 5s
 pub const APP_SHARE_TIMEOUT: u64 = 120; // 120s
+/// most packages whose install counts are given in one response
+const MAX_INSTALL_COUNTS: usize = 100;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)] // untagged as a meta-type for all incoming responses
@@ -43,6 +58,31 @@ pub enum Resp {
 pub struct State {
     // persisted metadata about which packages we are mirroring
     mirroring: HashSet<PackageId>,
+    // mirrors configured by the user, keyed by package id.
+    // fetches try these in priority order before any others.
+    #[serde(default)]
+    mirrors: HashMap<String, Vec<MirrorConfig>>,
+    // versions the user has pinned, kept by gc even when nothing else
+    // refers to them.
+    #[serde(default)]
+    pins: HashSet<(PackageId, String)>,
+    // the nodes that have downloaded each package from us, keyed by
+    // package id, for install counts
+    #[serde(default)]
+    downloaders: HashMap<String, HashSet<String>>,
+    // the nodes that hold each version of our packages, each checked by
+    // serving us a chunk of it, keyed by version hash. renamed from the
+    // unchecked `seeders` of before, which are dropped.
+    #[serde(default, rename = "checked_seeders")]
+    seeders: HashMap<String, Vec<swarm::Seeder>>,
+    // the hashes of the zips we hold, each checked once when it was
+    // stored. only these are served to the swarm.
+    #[serde(default)]
+    verified: HashSet<String>,
+    // whether we serve the zips we hold to the swarm. off unless the user
+    // turns it on.
+    #[serde(default)]
+    swarm: bool,
     // note, pending auto_updates are not persisted.
 }
 
@@ -53,10 +93,22 @@ impl State {
                 Ok(state) => state,
                 Err(_) => State {
                     mirroring: HashSet::new(),
+                    mirrors: HashMap::new(),
+                    pins: HashSet::new(),
+                    downloaders: HashMap::new(),
+                    seeders: HashMap::new(),
+                    verified: HashSet::new(),
+                    swarm: false,
                 },
             },
             None => State {
                 mirroring: HashSet::new(),
+                mirrors: HashMap::new(),
+                pins: HashSet::new(),
+                downloaders: HashMap::new(),
+                seeders: HashMap::new(),
+                verified: HashSet::new(),
+                swarm: false,
             },
         }
     }
@@ -78,11 +130,32 @@ fn init(our: Address) {
     let mut tmp = open_or_create_dir("/app_store:sys/downloads/tmp").expect("could not open tmp");
 
     let mut auto_updates: HashSet<(PackageId, String)> = HashSet::new();
+    // local downloads in progress, so that gc leaves their zips alone
+    let mut downloading: HashSet<(PackageId, String)> = HashSet::new();
+    let mut fetcher = mirrors::Fetcher::default();
+    let mut swarm = swarm::Swarm::default();
+    if state.swarm {
+        if let Err(e) = swarm.announce_held(&our, &state) {
+            print_to_terminal(
+                1,
+                &format!("downloads: could not announce held zips: {e:?}"),
+            );
+        }
+    }
 
     loop {
         match await_message() {
             Err(send_error) => {
                 print_to_terminal(1, &format!("got network error: {send_error}"));
+                if let Err(e) = handle_send_error(
+                    &our,
+                    &send_error,
+                    &mut downloading,
+                    &mut fetcher,
+                    &mut swarm,
+                ) {
+                    print_to_terminal(1, &format!("error handling send error: {:?}", e));
+                }
             }
             Ok(message) => {
                 if let Err(e) = handle_message(
@@ -92,6 +165,9 @@ fn init(our: Address) {
                     &mut downloads,
                     &mut tmp,
                     &mut auto_updates,
+                    &mut downloading,
+                    &mut fetcher,
+                    &mut swarm,
                 ) {
                     print_to_terminal(1, &format!("error handling message: {:?}", e));
                 }
@@ -111,6 +187,9 @@ fn handle_message(
     downloads: &mut Directory,
     _tmp: &mut Directory,
     auto_updates: &mut HashSet<(PackageId, String)>,
+    downloading: &mut HashSet<(PackageId, String)>,
+    fetcher: &mut mirrors::Fetcher,
+    swarm: &mut swarm::Swarm,
 ) -> anyhow::Result<()> {
     if message.is_request() {
         match serde_json::from_slice::<DownloadRequests>(message.body())? {
@@ -119,50 +198,87 @@ fn handle_message(
                 if !message.is_local(our) {
                     return Err(anyhow::anyhow!("not local"));
                 }
-
-                let LocalDownloadRequest {
+                start_download(our, &download_request)?;
+                downloading.insert((
+                    download_request.package_id.to_process_lib(),
+                    download_request.desired_version_hash,
+                ));
+            }
+            DownloadRequests::Fetch(fetch_request) => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                let FetchRequest {
                     package_id,
-                    download_from,
-                    desired_version_hash,
-                } = download_request.clone();
-
-                if download_from.starts_with("http") {
-                    // use http_client to GET it
-                    Request::to(("our", "http_client", "distro", "sys"))
-                        .body(
-                            serde_json::to_vec(&client::HttpClientAction::Http(
-                                client::OutgoingHttpRequest {
-                                    method: "GET".to_string(),
-                                    version: None,
-                                    url: download_from.clone(),
-                                    headers: std::collections::HashMap::new(),
-                                },
-                            ))
-                            .unwrap(),
-                        )
-                        .context(serde_json::to_vec(&download_request)?)
-                        .expects_response(60)
+                    version_hash,
+                    extra_mirrors,
+                } = fetch_request;
+                let process_lib_package_id = package_id.clone().to_process_lib();
+
+                // content-addressed: if we already hold a zip with this hash, we're done.
+                let error = if have_verified_zip(&process_lib_package_id, &version_hash) {
+                    Some(None)
+                } else {
+                    let candidates =
+                        mirrors::candidates(state, &process_lib_package_id, extra_mirrors);
+                    // the swarm first, falling back to the mirrors
+                    if swarm.start(our, &package_id, &version_hash, candidates.clone()) {
+                        None
+                    } else {
+                        match fetcher.start(our, &package_id, &version_hash, candidates) {
+                            Ok(()) => None,
+                            Err(e) => Some(Some(e)),
+                        }
+                    }
+                };
+                // resolved without a download: report back to ourselves so
+                // that any pending auto-update is applied as usual.
+                if let Some(error) = error {
+                    Request::to(("our", "downloads", "app_store", "sys"))
+                        .body(serde_json::to_vec(&DownloadRequests::DownloadComplete(
+                            DownloadCompleteRequest {
+                                package_id,
+                                version_hash,
+                                error,
+                            },
+                        ))?)
                         .send()?;
-                    return Ok(());
                 }
-
-                // go download from the node or url
-                // spawn a worker, and send a downlaod to the node.
-                let our_worker = spawn_receive_transfer(
-                    our,
-                    &package_id,
-                    &desired_version_hash,
-                    &download_from,
-                    APP_SHARE_TIMEOUT,
-                )?;
-
-                Request::to((&download_from, "downloads", "app_store", "sys"))
-                    .body(serde_json::to_vec(&DownloadRequests::RemoteDownload(
-                        RemoteDownloadRequest {
-                            package_id,
-                            desired_version_hash,
-                            worker_address: our_worker.to_string(),
-                        },
+                Response::new()
+                    .body(serde_json::to_vec(&Resp::Download(
+                        DownloadResponses::Success,
+                    ))?)
+                    .send()?;
+            }
+            DownloadRequests::SetMirrors(set_mirrors_request) => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                let SetMirrorsRequest {
+                    package_id,
+                    mirrors,
+                } = set_mirrors_request;
+                let key = package_id.to_process_lib().to_string();
+                if mirrors.is_empty() {
+                    state.mirrors.remove(&key);
+                } else {
+                    state.mirrors.insert(key, mirrors);
+                }
+                set_state(&serde_json::to_vec(&state)?);
+                Response::new()
+                    .body(serde_json::to_vec(&Resp::Download(
+                        DownloadResponses::Success,
+                    ))?)
+                    .send()?;
+            }
+            DownloadRequests::GetMirrors(maybe_id) => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                let package_id = maybe_id.map(|id| id.to_process_lib());
+                Response::new()
+                    .body(serde_json::to_vec(&DownloadResponses::GetMirrors(
+                        fetcher.status(state, package_id.as_ref()),
                     ))?)
                     .send()?;
             }
@@ -178,6 +294,14 @@ fn handle_message(
                 } = download_request;
 
                 let target_worker = Address::from_str(&worker_address)?;
+                if state
+                    .downloaders
+                    .entry(package_id.clone().to_process_lib().to_string())
+                    .or_default()
+                    .insert(message.source().node.clone())
+                {
+                    set_state(&serde_json::to_vec(&state)?);
+                }
                 let _ = spawn_send_transfer(
                     our,
                     &package_id,
@@ -185,6 +309,40 @@ fn handle_message(
                     APP_SHARE_TIMEOUT,
                     &target_worker,
                 )?;
+                // let the requester know we're alive and sending, so it can tell
+                // us apart from an offline mirror. older nodes do not expect this.
+                let _ = Response::new()
+                    .body(serde_json::to_vec(&Resp::Download(
+                        DownloadResponses::Success,
+                    ))?)
+                    .send();
+            }
+            DownloadRequests::SwarmPeers(artifact) => {
+                // asked of us as the package's publisher
+                let seeders = swarm.seeders(state, &message.source().node, artifact);
+                Response::new()
+                    .body(serde_json::to_vec(&DownloadResponses::SwarmPeers(seeders))?)
+                    .send()?;
+            }
+            DownloadRequests::SwarmAnnounce(artifact) => {
+                swarm.announce(our, state, &message.source().node, artifact);
+            }
+            DownloadRequests::SwarmWithdraw(artifact) => {
+                swarm.withdraw(state, &message.source().node, artifact);
+            }
+            DownloadRequests::SwarmChunk(chunk) => {
+                swarm.serve(state, &message.source().node, chunk)?;
+            }
+            DownloadRequests::SetSwarm(serving) => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                swarm.set_serving(our, state, serving)?;
+                Response::new()
+                    .body(serde_json::to_vec(&Resp::Download(
+                        DownloadResponses::Success,
+                    ))?)
+                    .send()?;
             }
             DownloadRequests::Progress(progress) => {
                 // forward progress to main:app_store:sys,
@@ -192,11 +350,51 @@ fn handle_message(
                 let _ = Request::to(("our", "main", "app_store", "sys"))
                     .body(serde_json::to_vec(&progress)?)
                     .send();
+                // and to progress:distro:sys, for the terminal and homepage
+                let package_id = progress.package_id.to_process_lib();
+                let percent = match progress.total {
+                    0 => 100,
+                    total => progress.downloaded.min(total) * 100 / total,
+                };
+                let _ = Request::to(("our", "progress", "distro", "sys"))
+                    .body(serde_json::to_vec(&serde_json::json!({
+                        "Report": {
+                            "operation": format!("{package_id}@{}", progress.version_hash),
+                            "initiator": our.to_string(),
+                            "percent": percent,
+                            "label": format!("downloading {package_id}"),
+                        }
+                    }))?)
+                    .send();
             }
             DownloadRequests::DownloadComplete(req) => {
                 if !message.is_local(our) {
                     return Err(anyhow::anyhow!("got non local download complete"));
                 }
+                downloading.remove(&(
+                    req.package_id.clone().to_process_lib(),
+                    req.version_hash.clone(),
+                ));
+                // if this download was one attempt of a fetch and it failed,
+                // the fetcher moves on to the next mirror, and we hold off
+                // on reporting until the fetch as a whole is done.
+                let Some(error) = fetcher.complete(
+                    our,
+                    &req.package_id,
+                    &req.version_hash,
+                    None,
+                    req.error.clone(),
+                ) else {
+                    return Ok(());
+                };
+                let req = DownloadCompleteRequest { error, ..req };
+                // every way a download completes checks the zip against its
+                // hash before storing it
+                if req.error.is_none() {
+                    set_verified(state, &req.version_hash);
+                    swarm.hold(our, state, &req.package_id, &req.version_hash);
+                }
+
                 // if we have a pending auto_install, forward that context to the main process.
                 // it will check if the caps_hashes match (no change in capabilities), and auto_install if it does.
 
@@ -241,7 +439,12 @@ fn handle_message(
                         let package_path =
                             format!("{}/{}", downloads.path, id.to_process_lib().to_string());
                         let dir = vfs::open_dir(&package_path, false, None)?;
-                        let dir = dir.read()?;
+                        // leaving out the copies unpacked to install from
+                        let dir = dir
+                            .read()?
+                            .into_iter()
+                            .filter(|entry| entry.file_type == vfs::FileType::File)
+                            .collect();
                         format_entries(dir, state)
                     }
                     None => {
@@ -265,12 +468,17 @@ fn handle_message(
                 let package_dir = format!(
                     "{}/{}",
                     downloads.path,
-                    package_id.to_process_lib().to_string()
+                    package_id.clone().to_process_lib().to_string()
                 );
                 let zip_path = format!("{}/{}.zip", package_dir, version_hash);
                 let _ = vfs::remove_file(&zip_path, None);
                 let manifest_path = format!("{}/{}.json", package_dir, version_hash);
                 let _ = vfs::remove_file(&manifest_path, None);
+                let _ = gc::remove_dir_all(&format!("{package_dir}/{version_hash}"));
+                if state.verified.remove(&version_hash) {
+                    set_state(&serde_json::to_vec(&state)?);
+                    swarm.unhold(our, state, &package_id, &version_hash);
+                }
                 Response::new()
                     .body(serde_json::to_vec(&Resp::Download(
                         DownloadResponses::Success,
@@ -285,6 +493,13 @@ fn handle_message(
                     return Err(anyhow::anyhow!("could not get blob"));
                 };
                 let bytes = blob.bytes;
+                let calculated_hash = format!("{:x}", Sha256::digest(&bytes));
+                if calculated_hash != add_req.version_hash {
+                    return Err(anyhow::anyhow!(
+                        "zip hash {calculated_hash} does not match {}",
+                        add_req.version_hash
+                    ));
+                }
 
                 let package_dir = format!(
                     "{}/{}",
@@ -300,7 +515,8 @@ fn handle_message(
 
                 // Extract and write the manifest
                 let manifest_path = format!("{}/{}.json", package_dir, add_req.version_hash);
-                extract_and_write_manifest(&bytes, &manifest_path)?;
+                extract_and_write_manifest(std::io::Cursor::new(&bytes), &manifest_path)?;
+                set_verified(state, &add_req.version_hash);
 
                 // add mirrors if applicable and save:
                 if add_req.mirror {
@@ -334,6 +550,96 @@ fn handle_message(
                     ))?)
                     .send()?;
             }
+            DownloadRequests::Pin(Artifact {
+                package_id,
+                version_hash,
+            }) => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                state
+                    .pins
+                    .insert((package_id.to_process_lib(), version_hash));
+                set_state(&serde_json::to_vec(&state)?);
+                Response::new()
+                    .body(serde_json::to_vec(&Resp::Download(
+                        DownloadResponses::Success,
+                    ))?)
+                    .send()?;
+            }
+            DownloadRequests::Unpin(Artifact {
+                package_id,
+                version_hash,
+            }) => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                state
+                    .pins
+                    .remove(&(package_id.to_process_lib(), version_hash));
+                set_state(&serde_json::to_vec(&state)?);
+                Response::new()
+                    .body(serde_json::to_vec(&Resp::Download(
+                        DownloadResponses::Success,
+                    ))?)
+                    .send()?;
+            }
+            DownloadRequests::GetPins => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                let pins = state
+                    .pins
+                    .iter()
+                    .map(|(package_id, version_hash)| Artifact {
+                        package_id: crate::kinode::process::main::PackageId::from_process_lib(
+                            package_id.clone(),
+                        ),
+                        version_hash: version_hash.clone(),
+                    })
+                    .collect();
+                Response::new()
+                    .body(serde_json::to_vec(&DownloadResponses::GetPins(pins))?)
+                    .send()?;
+            }
+            DownloadRequests::Gc(gc_request) => {
+                if !message.is_local(our) {
+                    return Err(anyhow::anyhow!("not local"));
+                }
+                let response = match gc::collect(state, gc_request, downloading, fetcher) {
+                    Ok(report) => DownloadResponses::Gc(report),
+                    Err(e) => {
+                        print_to_terminal(1, &format!("gc: {e:?}"));
+                        DownloadResponses::Error(DownloadError::VfsError)
+                    }
+                };
+                Response::new()
+                    .body(serde_json::to_vec(&response)?)
+                    .send()?;
+            }
+            DownloadRequests::InstallCount(package_id) => {
+                // asked by other nodes' app stores when searching, so
+                // answered to anyone
+                let count = install_count(state, &package_id.to_process_lib());
+                Response::new()
+                    .body(serde_json::to_vec(&DownloadResponses::InstallCount(count))?)
+                    .send()?;
+            }
+            DownloadRequests::InstallCounts(package_ids) => {
+                let counts = package_ids
+                    .into_iter()
+                    .take(MAX_INSTALL_COUNTS)
+                    .map(|package_id| {
+                        let count = install_count(state, &package_id.clone().to_process_lib());
+                        (package_id, count)
+                    })
+                    .collect();
+                Response::new()
+                    .body(serde_json::to_vec(&DownloadResponses::InstallCounts(
+                        counts,
+                    ))?)
+                    .send()?;
+            }
             DownloadRequests::AutoUpdate(auto_update_request) => {
                 if !message.is_local(&our)
                     && message.source().process != ProcessId::new(Some("chain"), "app_store", "sys")
@@ -349,8 +655,9 @@ fn handle_message(
                 } = auto_update_request.clone();
                 let process_lib_package_id = package_id.clone().to_process_lib();
 
-                // default auto_update to publisher. TODO: more config here.
-                let download_from = metadata.properties.publisher;
+                // try configured mirrors first, then the publisher, then on-chain mirrors.
+                let mut extra_mirrors = vec![metadata.properties.publisher];
+                extra_mirrors.extend(metadata.properties.mirrors);
                 let current_version = metadata.properties.current_version;
                 let code_hashes = metadata.properties.code_hashes;
 
@@ -360,16 +667,14 @@ fn handle_message(
                     .map(|(_, hash)| hash.clone())
                     .ok_or_else(|| anyhow::anyhow!("auto_update: error for package_id: {}, current_version: {}, no matching hash found", process_lib_package_id.to_string(), current_version))?;
 
-                let download_request = LocalDownloadRequest {
-                    package_id,
-                    download_from,
-                    desired_version_hash: version_hash.clone(),
-                };
-
-                // kick off local download to ourselves.
+                // kick off a fetch to ourselves.
                 Request::to(("our", "downloads", "app_store", "sys"))
-                    .body(serde_json::to_vec(&DownloadRequests::LocalDownload(
-                        download_request,
+                    .body(serde_json::to_vec(&DownloadRequests::Fetch(
+                        FetchRequest {
+                            package_id,
+                            version_hash: version_hash.clone(),
+                            extra_mirrors,
+                        },
                     ))?)
                     .send()?;
 
@@ -379,6 +684,20 @@ fn handle_message(
         }
     } else {
         match serde_json::from_slice::<Resp>(message.body())? {
+            Resp::Download(DownloadResponses::Success) => {
+                // a mirror acknowledging a remote download, or
+                // a fetch we sent ourselves being accepted
+            }
+            Resp::Download(
+                download_response @ (DownloadResponses::SwarmPeers(_)
+                | DownloadResponses::SwarmChunk(_)),
+            ) => {
+                let Some(context) = message.context() else {
+                    return Err(anyhow::anyhow!("swarm response without context"));
+                };
+                let context = serde_json::from_slice::<swarm::Context>(context)?;
+                swarm.handle_response(our, state, fetcher, context, download_response);
+            }
             Resp::Download(download_response) => {
                 // these are handled in line.
                 print_to_terminal(
@@ -391,29 +710,27 @@ fn handle_message(
                     return Err(anyhow::anyhow!("http_client response without context"));
                 };
                 let download_request = serde_json::from_slice::<LocalDownloadRequest>(context)?;
-                if let Ok(client::HttpClientResponse::Http(client::HttpResponse {
-                    status, ..
-                })) = resp
-                {
-                    if status == 200 {
-                        if let Err(e) = handle_receive_http_download(&download_request) {
-                            print_to_terminal(
-                                1,
-                                &format!("error handling http_client response: {:?}", e),
-                            );
-                            Request::to(("our", "main", "app_store", "sys"))
-                                .body(serde_json::to_vec(&DownloadRequests::DownloadComplete(
-                                    DownloadCompleteRequest {
-                                        package_id: download_request.package_id.clone(),
-                                        version_hash: download_request.desired_version_hash.clone(),
-                                        error: Some(e),
-                                    },
-                                ))?)
-                                .send()?;
-                        }
+                let result = match resp {
+                    Ok(client::HttpClientResponse::Http(client::HttpResponse {
+                        status: 200,
+                        ..
+                    })) => handle_receive_http_download(&download_request),
+                    _ => {
+                        println!("got http_client error: {resp:?}");
+                        Err(DownloadError::HttpClientError)
                     }
-                } else {
-                    println!("got http_client error: {resp:?}");
+                };
+                if let Err(e) = result {
+                    print_to_terminal(1, &format!("error handling http_client response: {:?}", e));
+                    Request::to(("our", "downloads", "app_store", "sys"))
+                        .body(serde_json::to_vec(&DownloadRequests::DownloadComplete(
+                            DownloadCompleteRequest {
+                                package_id: download_request.package_id.clone(),
+                                version_hash: download_request.desired_version_hash.clone(),
+                                error: Some(e),
+                            },
+                        ))?)
+                        .send()?;
                 }
             }
         }
@@ -458,15 +775,20 @@ fn handle_receive_http_download(
     // Write the manifest file
     // Extract and write the manifest
     let manifest_path = format!("{}/{}.json", package_dir, version_hash);
-    extract_and_write_manifest(&bytes, &manifest_path).map_err(|_| DownloadError::VfsError)?;
+    extract_and_write_manifest(std::io::Cursor::new(&bytes), &manifest_path)
+        .map_err(|_| DownloadError::VfsError)?;
 
-    Request::to(("our", "main", "app_store", "sys"))
+    // report back to ourselves, so that a pending fetch is resolved
+    // before the completion is forwarded to main:app_store:sys.
+    Request::to(("our", "downloads", "app_store", "sys"))
         .body(
-            serde_json::to_vec(&DownloadCompleteRequest {
-                package_id: download_request.package_id.clone(),
-                version_hash,
-                error: None,
-            })
+            serde_json::to_vec(&DownloadRequests::DownloadComplete(
+                DownloadCompleteRequest {
+                    package_id: download_request.package_id.clone(),
+                    version_hash,
+                    error: None,
+                },
+            ))
             .unwrap(),
         )
         .send()
@@ -475,6 +797,153 @@ fn handle_receive_http_download(
     Ok(())
 }
 
+/// start downloading a package from a node or an http(s) url.
+/// completion is reported back to us with a DownloadComplete request.
+pub fn start_download(
+    our: &Address,
+    download_request: &LocalDownloadRequest,
+) -> anyhow::Result<()> {
+    let LocalDownloadRequest {
+        package_id,
+        download_from,
+        desired_version_hash,
+    } = download_request.clone();
+
+    if download_from.starts_with("http") {
+        // use http_client to GET it
+        Request::to(("our", "http_client", "distro", "sys"))
+            .body(
+                serde_json::to_vec(&client::HttpClientAction::Http(
+                    client::OutgoingHttpRequest {
+                        method: "GET".to_string(),
+                        version: None,
+                        url: download_from.clone(),
+                        headers: std::collections::HashMap::new(),
+                    },
+                ))
+                .unwrap(),
+            )
+            .context(serde_json::to_vec(&download_request)?)
+            .expects_response(60)
+            .send()?;
+        return Ok(());
+    }
+
+    // go download from the node or url
+    // spawn a worker, and send a downlaod to the node.
+    let our_worker = spawn_receive_transfer(
+        our,
+        &package_id,
+        &desired_version_hash,
+        &download_from,
+        APP_SHARE_TIMEOUT,
+    )?;
+
+    // the request carries its download as context, so that if the
+    // mirror is offline, the resulting send error can be attributed.
+    Request::to((&download_from, "downloads", "app_store", "sys"))
+        .body(serde_json::to_vec(&DownloadRequests::RemoteDownload(
+            RemoteDownloadRequest {
+                package_id,
+                desired_version_hash,
+                worker_address: our_worker.to_string(),
+            },
+        ))?)
+        .context(serde_json::to_vec(&download_request)?)
+        .expects_response(APP_SHARE_TIMEOUT)
+        .send()?;
+    Ok(())
+}
+
+/// a mirror we were fetching from could not be reached: move on to the next one.
+/// a seeder or publisher that could not be reached is left to the swarm.
+fn handle_send_error(
+    our: &Address,
+    send_error: &SendError,
+    downloading: &mut HashSet<(PackageId, String)>,
+    fetcher: &mut mirrors::Fetcher,
+    swarm: &mut swarm::Swarm,
+) -> anyhow::Result<()> {
+    let Some(context) = send_error.context() else {
+        return Ok(());
+    };
+    if let Ok(context) = serde_json::from_slice::<swarm::Context>(context) {
+        swarm.handle_send_error(our, fetcher, context);
+        return Ok(());
+    }
+    let download_request = serde_json::from_slice::<LocalDownloadRequest>(context)?;
+    let LocalDownloadRequest {
+        package_id,
+        download_from,
+        desired_version_hash,
+    } = download_request;
+    let error = match send_error.kind {
+        SendErrorKind::Offline => DownloadError::MirrorOffline,
+        SendErrorKind::Timeout => DownloadError::Timeout,
+    };
+    if !fetcher.is_trying(&package_id, &desired_version_hash, &download_from) {
+        // a plain local download from a url has no worker to report that it
+        // failed, so it is reported here. one from a node is reported by its
+        // worker, once that times out. fetches that already moved on are
+        // left alone.
+        let key = (package_id.clone().to_process_lib(), desired_version_hash);
+        if download_from.starts_with("http") && downloading.contains(&key) {
+            Request::to(("our", "downloads", "app_store", "sys"))
+                .body(serde_json::to_vec(&DownloadRequests::DownloadComplete(
+                    DownloadCompleteRequest {
+                        package_id,
+                        version_hash: key.1,
+                        error: Some(error),
+                    },
+                ))?)
+                .send()?;
+        }
+        return Ok(());
+    }
+    if let Some(error) = fetcher.complete(
+        our,
+        &package_id,
+        &desired_version_hash,
+        Some(&download_from),
+        Some(error),
+    ) {
+        Request::to(("our", "downloads", "app_store", "sys"))
+            .body(serde_json::to_vec(&DownloadRequests::DownloadComplete(
+                DownloadCompleteRequest {
+                    package_id,
+                    version_hash: desired_version_hash,
+                    error,
+                },
+            ))?)
+            .send()?;
+    }
+    Ok(())
+}
+
+/// how many distinct nodes have downloaded a package from us, or `None` if
+/// we don't mirror it
+fn install_count(state: &State, package_id: &PackageId) -> Option<u64> {
+    state.mirroring.contains(package_id).then(|| {
+        state
+            .downloaders
+            .get(&package_id.to_string())
+            .map_or(0, |nodes| nodes.len() as u64)
+    })
+}
+
+/// note that we hold the zip for a version, having checked it against its hash
+fn set_verified(state: &mut State, version_hash: &str) {
+    if state.verified.insert(version_hash.to_string()) {
+        set_state(&serde_json::to_vec(&state).unwrap());
+    }
+}
+
+/// whether we already hold the zip for a version, with its contents matching its hash
+fn have_verified_zip(package_id: &PackageId, version_hash: &str) -> bool {
+    let zip_path = format!("/app_store:sys/downloads/{package_id}/{version_hash}.zip");
+    swarm::hash_file(&zip_path).is_ok_and(|actual| actual == version_hash)
+}
+
 fn format_entries(entries: Vec<vfs::DirEntry>, state: &State) -> Vec<Entry> {
     entries
         .into_iter()
@@ -509,8 +978,7 @@ fn format_entries(entries: Vec<vfs::DirEntry>, state: &State) -> Vec<Entry> {
         .collect()
 }
 
-fn extract_and_write_manifest(file_contents: &[u8], manifest_path: &str) -> anyhow::Result<()> {
-    let reader = std::io::Cursor::new(file_contents);
+fn extract_and_write_manifest(reader: impl Read + Seek, manifest_path: &str) -> anyhow::Result<()> {
     let mut archive = zip::ZipArchive::new(reader)?;
 
     for i in 0..archive.len() {
@@ -0,0 +1,216 @@
+//! mirror selection for content-addressed fetches.
+//!
+//! a fetch names a package and the hash of the zip it wants. we walk the
+//! package's mirrors in priority order, starting a regular local download
+//! from each until one completes with a matching hash. every attempt is
+//! recorded against the mirror so its health can be surfaced to the user.
+use crate::kinode::process::downloads::{
+    DownloadError, LocalDownloadRequest, MirrorConfig, MirrorStatus, PackageId as WitPackageId,
+};
+use crate::State;
+use kinode_process_lib::{print_to_terminal, Address, PackageId};
+use std::collections::HashMap;
+
+/// an in-progress fetch, and the mirrors left to try if the current one fails
+struct PendingFetch {
+    current: String,
+    remaining: Vec<String>,
+}
+
+#[derive(Default)]
+struct MirrorHealth {
+    successes: u32,
+    failures: u32,
+    last_error: Option<DownloadError>,
+}
+
+/// tracks pending fetches and per-mirror health. not persisted:
+/// health is rebuilt from scratch every time the node boots.
+#[derive(Default)]
+pub struct Fetcher {
+    pending: HashMap<(PackageId, String), PendingFetch>,
+    health: HashMap<String, MirrorHealth>,
+}
+
+impl Fetcher {
+    /// begin fetching a package from the first of `candidates`
+    pub fn start(
+        &mut self,
+        our: &Address,
+        package_id: &WitPackageId,
+        version_hash: &str,
+        mut candidates: Vec<String>,
+    ) -> Result<(), DownloadError> {
+        if candidates.is_empty() {
+            return Err(DownloadError::NoMirrors);
+        }
+        let current = candidates.remove(0);
+        self.pending.insert(
+            (
+                package_id.clone().to_process_lib(),
+                version_hash.to_string(),
+            ),
+            PendingFetch {
+                current: current.clone(),
+                remaining: candidates,
+            },
+        );
+        let Err(error) = self.try_mirror(our, package_id, version_hash, current.clone()) else {
+            return Ok(());
+        };
+        match self.complete(our, package_id, version_hash, Some(&current), Some(error)) {
+            Some(Some(error)) => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    /// whether `mirror` is the one currently being tried for this fetch
+    pub fn is_trying(&self, package_id: &WitPackageId, version_hash: &str, mirror: &str) -> bool {
+        self.pending
+            .get(&(
+                package_id.clone().to_process_lib(),
+                version_hash.to_string(),
+            ))
+            .is_some_and(|pending| pending.current == mirror)
+    }
+
+    /// whether a fetch of this version is in progress
+    pub fn is_fetching(&self, package_id: &PackageId, version_hash: &str) -> bool {
+        self.pending
+            .contains_key(&(package_id.clone(), version_hash.to_string()))
+    }
+
+    /// record the outcome of a download. if it belonged to a fetch and failed,
+    /// move on to the next mirror. returns the final outcome once the fetch is
+    /// done, or `None` if another mirror is being tried.
+    pub fn complete(
+        &mut self,
+        our: &Address,
+        package_id: &WitPackageId,
+        version_hash: &str,
+        download_from: Option<&str>,
+        error: Option<DownloadError>,
+    ) -> Option<Option<DownloadError>> {
+        let key = (
+            package_id.clone().to_process_lib(),
+            version_hash.to_string(),
+        );
+        let Some(pending) = self.pending.get_mut(&key) else {
+            // not one of ours: a plain local download
+            return Some(error);
+        };
+        // a stale failure from a mirror we have already moved on from
+        if download_from.is_some_and(|from| from != pending.current) {
+            return None;
+        }
+        let current = pending.current.clone();
+        let health = self.health.entry(current.clone()).or_default();
+        let Some(error) = error else {
+            health.successes += 1;
+            self.pending.remove(&key);
+            return Some(None);
+        };
+        health.failures += 1;
+        health.last_error = Some(error.clone());
+        print_to_terminal(
+            1,
+            &format!("downloads: mirror {current} failed to serve {version_hash}: {error:?}"),
+        );
+
+        loop {
+            let Some(pending) = self.pending.get_mut(&key) else {
+                return Some(Some(error));
+            };
+            if pending.remaining.is_empty() {
+                self.pending.remove(&key);
+                return Some(Some(error));
+            }
+            let next = pending.remaining.remove(0);
+            pending.current = next.clone();
+            match self.try_mirror(our, package_id, version_hash, next.clone()) {
+                Ok(()) => return None,
+                Err(e) => {
+                    let health = self.health.entry(next).or_default();
+                    health.failures += 1;
+                    health.last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// health of the given mirrors, or of every mirror we know of
+    pub fn status(&self, state: &State, package_id: Option<&PackageId>) -> Vec<MirrorStatus> {
+        let mut configured: Vec<MirrorConfig> = match package_id {
+            Some(package_id) => state
+                .mirrors
+                .get(&package_id.to_string())
+                .cloned()
+                .unwrap_or_default(),
+            None => state.mirrors.values().flatten().cloned().collect(),
+        };
+        configured.sort_by_key(|config| config.priority);
+
+        let mut statuses: Vec<MirrorStatus> = vec![];
+        for config in configured {
+            if statuses.iter().any(|status| status.mirror == config.mirror) {
+                continue;
+            }
+            statuses.push(self.mirror_status(&config.mirror, Some(config.priority)));
+        }
+        if package_id.is_none() {
+            for mirror in self.health.keys() {
+                if !statuses.iter().any(|status| &status.mirror == mirror) {
+                    statuses.push(self.mirror_status(mirror, None));
+                }
+            }
+        }
+        statuses
+    }
+
+    fn mirror_status(&self, mirror: &str, priority: Option<u32>) -> MirrorStatus {
+        let health = self.health.get(mirror);
+        MirrorStatus {
+            mirror: mirror.to_string(),
+            priority,
+            successes: health.map(|h| h.successes).unwrap_or(0),
+            failures: health.map(|h| h.failures).unwrap_or(0),
+            last_error: health.and_then(|h| h.last_error.clone()),
+        }
+    }
+
+    fn try_mirror(
+        &self,
+        our: &Address,
+        package_id: &WitPackageId,
+        version_hash: &str,
+        mirror: String,
+    ) -> Result<(), DownloadError> {
+        crate::start_download(
+            our,
+            &LocalDownloadRequest {
+                package_id: package_id.clone(),
+                download_from: mirror,
+                desired_version_hash: version_hash.to_string(),
+            },
+        )
+        .map_err(|_| DownloadError::WorkerSpawnFailed)
+    }
+}
+
+/// the mirrors to try for a package, configured ones first in priority
+/// order, followed by any extras not already configured
+pub fn candidates(state: &State, package_id: &PackageId, extra: Vec<String>) -> Vec<String> {
+    let mut configured = state
+        .mirrors
+        .get(&package_id.to_string())
+        .cloned()
+        .unwrap_or_default();
+    configured.sort_by_key(|config| config.priority);
+    let mut candidates: Vec<String> = configured.into_iter().map(|c| c.mirror).collect();
+    for mirror in extra {
+        if !candidates.contains(&mirror) {
+            candidates.push(mirror);
+        }
+    }
+    candidates
+}
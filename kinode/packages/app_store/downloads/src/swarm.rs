@@ -0,0 +1,1190 @@
+//! swarm distribution of package zips.
+//!
+//! a node holding a zip it has verified serves chunks of it to any node that
+//! asks by content hash, and tells the package's publisher that it holds it.
+//! the publisher keeps track of these seeders, so that a fetch can ask it for
+//! them and pull chunks from all of them at once, going to the mirrors only
+//! if there are none, or they cannot finish the zip. popular packages are then
+//! served mostly by the nodes that already have them, not by their publisher.
+//!
+//! serving is opt-in, with `pkg swarm on`: whoever asks a publisher for
+//! seeders learns that they hold the package, so a node that has not opted
+//! in neither serves nor announces what it holds. those that have are held
+//! to a rate limit on what they serve, and a publisher gives out only a
+//! random few of the seeders of a version at a time.
+//!
+//! seeders are trusted with nothing: the publisher gives out the hash of each
+//! chunk along with them, and a seeder that serves a chunk not matching its
+//! hash is dropped. anyone may announce, so a publisher only lists a node
+//! once it has served a random chunk of the version that checks out, and a
+//! full list only takes a newcomer in place of a seeder not checked for a
+//! while, so that a flood of announcements can't push out those it has.
+use crate::kinode::process::downloads::{
+    Artifact, ChunkRequest, DownloadCompleteRequest, DownloadError, DownloadRequests,
+    DownloadResponses, PackageId as WitPackageId, ProgressUpdate, SwarmSeeders,
+};
+use crate::{mirrors, State};
+use kinode_process_lib::{
+    get_blob, print_to_terminal, set_state,
+    vfs::{self, SeekFrom},
+    Address, PackageId, Request, Response,
+};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+/// chunks are as large as those ft_worker sends
+const CHUNK_SIZE: u64 = 262144; // 256KB
+/// chunks asked of each seeder at once
+const PER_SEEDER: usize = 2;
+/// seeders a publisher keeps for each version, and gives out when asked
+const MAX_SEEDERS: usize = 64;
+const MAX_GIVEN: usize = 8;
+/// versions a publisher keeps seeders for
+const MAX_VERSIONS: usize = 256;
+/// how long a seeder's check stands: a full list takes a newcomer only in
+/// place of a seeder checked longer ago than this
+const SEEDER_TTL: u64 = 7 * 24 * 60 * 60; // 7 days, in seconds
+/// a seeder announcing again is only checked again this long after its
+/// last check
+const RECHECK_AFTER: u64 = 60 * 60; // 1h, in seconds
+/// announcing nodes a publisher checks at once. announcements past this
+/// are dropped, and taken when the node next announces.
+const MAX_PROBES: usize = 16;
+/// versions a publisher keeps the chunk hashes of at once
+const MAX_HASHED: usize = 32;
+/// chunks served and seeders given out, per second, to each asker and to
+/// everyone at once, with bursts of up to twice as many
+const PER_ASKER_RATE: f64 = 8.0;
+const TOTAL_RATE: f64 = 32.0;
+/// askers kept track of for the rate limit. past this, the one that asked
+/// least recently is forgotten.
+const MAX_ASKERS: usize = 256;
+/// announcements a publisher takes before saving its seeders. those since
+/// the last save are lost if the node stops, which only costs seeders.
+const SAVE_EVERY: usize = 16;
+const PUBLISHER_TIMEOUT: u64 = 5; // 5s
+const CHUNK_TIMEOUT: u64 = 30; // 30s
+
+const DOWNLOADS_PATH: &str = "/app_store:sys/downloads";
+
+/// what a swarm request we sent was for, kept as its context
+#[derive(Serialize, Deserialize)]
+pub enum Context {
+    Seeders {
+        version_hash: String,
+    },
+    Chunk {
+        version_hash: String,
+        seeder: String,
+        offset: u64,
+    },
+    /// as a publisher, a chunk asked of a node that announced holding a
+    /// version, to check that it does
+    Probe {
+        package_id: WitPackageId,
+        version_hash: String,
+        seeder: String,
+        offset: u64,
+    },
+}
+
+/// a node that holds a version, as its publisher keeps track of it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Seeder {
+    pub node: String,
+    /// when it last served us a chunk of the version that checked out, in
+    /// seconds since the epoch
+    pub checked: u64,
+}
+
+/// swarm fetches in progress. not persisted: a fetch interrupted by a
+/// reboot is started over. the versions we can serve are the verified ones
+/// in [`State`].
+#[derive(Default)]
+pub struct Swarm {
+    /// by version hash
+    fetches: HashMap<String, SwarmFetch>,
+    /// as a publisher, the size and chunk hashes of versions we have been
+    /// asked about, by version hash, and the order they were hashed in
+    chunk_hashes: HashMap<String, (u64, Vec<String>)>,
+    hashed: VecDeque<String>,
+    /// as a publisher, the announcing nodes being checked, by version hash
+    /// and node
+    probing: HashSet<(String, String)>,
+    limit: RateLimit,
+    /// announcements taken since we last saved our seeders
+    unsaved: usize,
+}
+
+struct SwarmFetch {
+    package_id: WitPackageId,
+    /// the mirrors to fall back to
+    candidates: Vec<String>,
+    /// seeders yet to fail us
+    seeders: Vec<String>,
+    /// the size of the zip and the hash of each chunk, once the publisher
+    /// has told us
+    size: u64,
+    chunk_hashes: Vec<String>,
+    /// offsets of the chunks still to ask for
+    queued: VecDeque<u64>,
+    /// offsets of the chunks asked for, and of which seeder
+    asked: HashMap<u64, String>,
+    received: u64,
+}
+
+impl Swarm {
+    /// start fetching a version from its seeders, asking its publisher who
+    /// they are. returns false if there is no one to ask, in which case the
+    /// caller should go to the mirrors.
+    pub fn start(
+        &mut self,
+        our: &Address,
+        package_id: &WitPackageId,
+        version_hash: &str,
+        candidates: Vec<String>,
+    ) -> bool {
+        if self.fetches.contains_key(version_hash) {
+            // its completion is reported as for any other fetch
+            return true;
+        }
+        let publisher = package_id.publisher_node.as_str();
+        if publisher == our.node() {
+            return false;
+        }
+        let sent = Request::to((publisher, "downloads", "app_store", "sys"))
+            .body(
+                serde_json::to_vec(&DownloadRequests::SwarmPeers(Artifact {
+                    package_id: package_id.clone(),
+                    version_hash: version_hash.to_string(),
+                }))
+                .unwrap(),
+            )
+            .context(
+                serde_json::to_vec(&Context::Seeders {
+                    version_hash: version_hash.to_string(),
+                })
+                .unwrap(),
+            )
+            .expects_response(PUBLISHER_TIMEOUT)
+            .send();
+        if sent.is_err() {
+            return false;
+        }
+        self.fetches.insert(
+            version_hash.to_string(),
+            SwarmFetch {
+                package_id: package_id.clone(),
+                candidates,
+                seeders: vec![],
+                size: 0,
+                chunk_hashes: vec![],
+                queued: VecDeque::new(),
+                asked: HashMap::new(),
+                received: 0,
+            },
+        );
+        true
+    }
+
+    /// handle the response to a swarm request we sent
+    pub fn handle_response(
+        &mut self,
+        our: &Address,
+        state: &mut State,
+        fetcher: &mut mirrors::Fetcher,
+        context: Context,
+        response: DownloadResponses,
+    ) {
+        match (context, response) {
+            (Context::Seeders { version_hash }, DownloadResponses::SwarmPeers(Some(seeders))) => {
+                self.got_seeders(our, fetcher, &version_hash, seeders)
+            }
+            (
+                Context::Chunk {
+                    version_hash,
+                    seeder,
+                    offset,
+                },
+                DownloadResponses::SwarmChunk(size),
+            ) => self.got_chunk(our, fetcher, &version_hash, &seeder, offset, size),
+            (
+                Context::Probe {
+                    package_id,
+                    version_hash,
+                    seeder,
+                    offset,
+                },
+                DownloadResponses::SwarmChunk(size),
+            ) => self.probed(state, package_id, &version_hash, &seeder, offset, size),
+            (Context::Seeders { version_hash }, _) => {
+                self.fall_back(our, fetcher, &version_hash, "publisher gave no seeders")
+            }
+            (
+                Context::Chunk {
+                    version_hash,
+                    seeder,
+                    offset,
+                },
+                _,
+            ) => self.drop_seeder(our, fetcher, &version_hash, &seeder, offset),
+            (
+                Context::Probe {
+                    version_hash,
+                    seeder,
+                    ..
+                },
+                _,
+            ) => {
+                self.probing.remove(&(version_hash, seeder));
+            }
+        }
+    }
+
+    /// a swarm request we sent could not be delivered, or went unanswered
+    pub fn handle_send_error(
+        &mut self,
+        our: &Address,
+        fetcher: &mut mirrors::Fetcher,
+        context: Context,
+    ) {
+        match context {
+            Context::Seeders { version_hash } => {
+                self.fall_back(our, fetcher, &version_hash, "publisher unreachable")
+            }
+            Context::Chunk {
+                version_hash,
+                seeder,
+                offset,
+            } => self.drop_seeder(our, fetcher, &version_hash, &seeder, offset),
+            Context::Probe {
+                version_hash,
+                seeder,
+                ..
+            } => {
+                self.probing.remove(&(version_hash, seeder));
+            }
+        }
+    }
+
+    fn got_seeders(
+        &mut self,
+        our: &Address,
+        fetcher: &mut mirrors::Fetcher,
+        version_hash: &str,
+        seeders: SwarmSeeders,
+    ) {
+        let Some(fetch) = self.fetches.get_mut(version_hash) else {
+            return;
+        };
+        fetch.seeders = seeders
+            .seeders
+            .into_iter()
+            .filter(|seeder| seeder != our.node())
+            .collect();
+        if fetch.seeders.is_empty() {
+            return self.fall_back(our, fetcher, version_hash, "no seeders");
+        }
+        if seeders.size == 0 || seeders.chunk_hashes.len() as u64 != chunk_count(seeders.size) {
+            return self.fall_back(
+                our,
+                fetcher,
+                version_hash,
+                "publisher gave bad chunk hashes",
+            );
+        }
+        if vfs::create_file(&tmp_path(version_hash), None).is_err() {
+            return self.fall_back(our, fetcher, version_hash, "could not create file");
+        }
+        fetch.size = seeders.size;
+        fetch.chunk_hashes = seeders.chunk_hashes;
+        fetch.queued.extend(chunk_offsets(seeders.size));
+        self.ask(our, fetcher, version_hash);
+    }
+
+    fn got_chunk(
+        &mut self,
+        our: &Address,
+        fetcher: &mut mirrors::Fetcher,
+        version_hash: &str,
+        seeder: &str,
+        offset: u64,
+        size: Option<u64>,
+    ) {
+        let Some(fetch) = self.fetches.get_mut(version_hash) else {
+            return;
+        };
+        if fetch.asked.get(&offset).map(|asked| asked.as_str()) != Some(seeder) {
+            return;
+        }
+        let (Some(size), Some(blob)) = (size, get_blob()) else {
+            // the seeder no longer serves the version
+            return self.drop_seeder(our, fetcher, version_hash, seeder, offset);
+        };
+        // a chunk is only kept if it is the one the publisher hashed
+        if size != fetch.size || !chunk_matches(&fetch.chunk_hashes, offset, &blob.bytes) {
+            print_to_terminal(
+                1,
+                &format!("downloads: seeder {seeder} served a bad chunk of {version_hash}"),
+            );
+            return self.drop_seeder(our, fetcher, version_hash, seeder, offset);
+        }
+        if write_chunk(version_hash, offset, &blob.bytes).is_err() {
+            return self.fall_back(our, fetcher, version_hash, "could not write chunk");
+        }
+        fetch.asked.remove(&offset);
+        fetch.received += blob.bytes.len() as u64;
+
+        let _ = Request::to(("our", "downloads", "app_store", "sys"))
+            .body(
+                serde_json::to_vec(&DownloadRequests::Progress(ProgressUpdate {
+                    package_id: fetch.package_id.clone(),
+                    version_hash: version_hash.to_string(),
+                    downloaded: fetch.received,
+                    total: size,
+                }))
+                .unwrap(),
+            )
+            .send();
+
+        if fetch.received >= size {
+            return self.finish(our, fetcher, version_hash);
+        }
+        self.ask(our, fetcher, version_hash);
+    }
+
+    /// ask each seeder for as many chunks as it may be asked for at once
+    fn ask(&mut self, our: &Address, fetcher: &mut mirrors::Fetcher, version_hash: &str) {
+        let Some(fetch) = self.fetches.get_mut(version_hash) else {
+            return;
+        };
+        if fetch.seeders.is_empty() {
+            return self.fall_back(our, fetcher, version_hash, "every seeder failed");
+        }
+        for seeder in &fetch.seeders {
+            while fetch
+                .asked
+                .values()
+                .filter(|asked| *asked == seeder)
+                .count()
+                < PER_SEEDER
+            {
+                let Some(offset) = fetch.queued.pop_front() else {
+                    return;
+                };
+                let sent = Request::to((seeder.as_str(), "downloads", "app_store", "sys"))
+                    .body(
+                        serde_json::to_vec(&DownloadRequests::SwarmChunk(ChunkRequest {
+                            package_id: fetch.package_id.clone(),
+                            version_hash: version_hash.to_string(),
+                            offset,
+                            length: CHUNK_SIZE,
+                        }))
+                        .unwrap(),
+                    )
+                    .context(
+                        serde_json::to_vec(&Context::Chunk {
+                            version_hash: version_hash.to_string(),
+                            seeder: seeder.clone(),
+                            offset,
+                        })
+                        .unwrap(),
+                    )
+                    .expects_response(CHUNK_TIMEOUT)
+                    .send();
+                if sent.is_err() {
+                    fetch.queued.push_front(offset);
+                    break;
+                }
+                fetch.asked.insert(offset, seeder.clone());
+            }
+        }
+    }
+
+    /// stop asking a seeder that failed to serve a chunk, and ask another
+    fn drop_seeder(
+        &mut self,
+        our: &Address,
+        fetcher: &mut mirrors::Fetcher,
+        version_hash: &str,
+        seeder: &str,
+        offset: u64,
+    ) {
+        let Some(fetch) = self.fetches.get_mut(version_hash) else {
+            return;
+        };
+        if fetch.asked.get(&offset).map(|asked| asked.as_str()) != Some(seeder) {
+            return;
+        }
+        fetch.asked.remove(&offset);
+        fetch.queued.push_front(offset);
+        fetch.seeders.retain(|kept| kept != seeder);
+        print_to_terminal(
+            1,
+            &format!("downloads: seeder {seeder} failed to serve {version_hash}"),
+        );
+        self.ask(our, fetcher, version_hash);
+    }
+
+    /// every chunk is in: check the zip against its hash, and keep it
+    fn finish(&mut self, our: &Address, fetcher: &mut mirrors::Fetcher, version_hash: &str) {
+        let Some(fetch) = self.fetches.get(version_hash) else {
+            return;
+        };
+        let package_id = fetch.package_id.clone().to_process_lib();
+        if let Err(e) = keep_verified(&package_id, version_hash) {
+            return self.fall_back(our, fetcher, version_hash, &e.to_string());
+        }
+        let fetch = self.fetches.remove(version_hash).unwrap();
+        complete(fetch.package_id, version_hash, None);
+    }
+
+    /// give up on the swarm, and fetch from the mirrors instead
+    fn fall_back(
+        &mut self,
+        our: &Address,
+        fetcher: &mut mirrors::Fetcher,
+        version_hash: &str,
+        why: &str,
+    ) {
+        let Some(fetch) = self.fetches.remove(version_hash) else {
+            return;
+        };
+        let _ = vfs::remove_file(&tmp_path(version_hash), None);
+        print_to_terminal(
+            1,
+            &format!("downloads: swarm could not serve {version_hash} ({why}), trying mirrors"),
+        );
+        if let Err(e) = fetcher.start(our, &fetch.package_id, version_hash, fetch.candidates) {
+            complete(fetch.package_id, version_hash, Some(e));
+        }
+    }
+
+    /// a version we now hold, verified when it was stored: if we serve the
+    /// swarm, tell its publisher, so that others can fetch it from us
+    pub fn hold(
+        &mut self,
+        our: &Address,
+        state: &State,
+        package_id: &WitPackageId,
+        version_hash: &str,
+    ) {
+        if state.swarm {
+            tell_publisher(our, package_id, version_hash, true);
+        }
+    }
+
+    /// a version we no longer hold: if we served it, tell its publisher
+    pub fn unhold(
+        &mut self,
+        our: &Address,
+        state: &State,
+        package_id: &WitPackageId,
+        version_hash: &str,
+    ) {
+        if state.swarm {
+            tell_publisher(our, package_id, version_hash, false);
+        }
+    }
+
+    /// opt in to serving the swarm, or out, telling the publisher of each
+    /// version we hold
+    pub fn set_serving(
+        &mut self,
+        our: &Address,
+        state: &mut State,
+        serving: bool,
+    ) -> anyhow::Result<()> {
+        if state.swarm == serving {
+            return Ok(());
+        }
+        state.swarm = serving;
+        set_state(&serde_json::to_vec(&state)?);
+        self.announce_held(our, state)
+    }
+
+    /// tell the publisher of each version we hold and have verified whether
+    /// we serve it. done on boot as well, so that publishers check us again
+    /// before our checks lapse.
+    pub fn announce_held(&mut self, our: &Address, state: &State) -> anyhow::Result<()> {
+        for package_dir in vfs::open_dir(DOWNLOADS_PATH, false, None)?.read()? {
+            if package_dir.file_type != vfs::FileType::Directory {
+                continue;
+            }
+            // skips tmp/, the only directory not named for a package
+            let Some(Ok(package_id)) = package_dir
+                .path
+                .rsplit('/')
+                .next()
+                .map(|name| name.parse::<PackageId>())
+            else {
+                continue;
+            };
+            let package_id = WitPackageId::from_process_lib(package_id);
+            for entry in vfs::open_dir(&package_dir.path, false, None)?.read()? {
+                let Some(version_hash) = entry
+                    .path
+                    .rsplit('/')
+                    .next()
+                    .and_then(|name| name.strip_suffix(".zip"))
+                else {
+                    continue;
+                };
+                if state.verified.contains(version_hash) {
+                    tell_publisher(our, &package_id, version_hash, state.swarm);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// answer a node asking for a chunk of a version, with the chunk if we
+    /// serve the swarm and hold the version, and the node has not asked too
+    /// often
+    pub fn serve(&mut self, state: &State, asker: &str, chunk: ChunkRequest) -> anyhow::Result<()> {
+        let package_id = chunk.package_id.to_process_lib();
+        let read = if state.swarm
+            && state.verified.contains(&chunk.version_hash)
+            && self.limit.allow(asker, Instant::now())
+        {
+            read_chunk(&package_id, &chunk.version_hash, chunk.offset, chunk.length).ok()
+        } else {
+            None
+        };
+        match read {
+            Some((size, bytes)) => Response::new()
+                .body(serde_json::to_vec(&DownloadResponses::SwarmChunk(Some(
+                    size,
+                )))?)
+                .blob_bytes(bytes)
+                .send()?,
+            None => Response::new()
+                .body(serde_json::to_vec(&DownloadResponses::SwarmChunk(None))?)
+                .send()?,
+        }
+        Ok(())
+    }
+
+    /// as a package's publisher, take note that `seeder` says it holds a
+    /// version of it, by asking it for a random chunk of the version. it is
+    /// only listed once the chunk checks out. only versions we published
+    /// and hold are kept track of.
+    pub fn announce(&mut self, our: &Address, state: &State, seeder: &str, artifact: Artifact) {
+        if artifact.package_id.publisher_node != our.node()
+            || seeder == our.node()
+            || !state.verified.contains(&artifact.version_hash)
+            || self.probing.len() >= MAX_PROBES
+        {
+            return;
+        }
+        let listed = state.seeders.get(&artifact.version_hash);
+        if !needs_check(listed.map_or(&[][..], Vec::as_slice), seeder, now_secs()) {
+            return;
+        }
+        let key = (artifact.version_hash.clone(), seeder.to_string());
+        if self.probing.contains(&key) {
+            return;
+        }
+        let Some((size, _)) = self.hashes(&artifact) else {
+            return;
+        };
+        let offset = rand::random::<u64>() % chunk_count(size) * CHUNK_SIZE;
+        let sent = Request::to((seeder, "downloads", "app_store", "sys"))
+            .body(
+                serde_json::to_vec(&DownloadRequests::SwarmChunk(ChunkRequest {
+                    package_id: artifact.package_id.clone(),
+                    version_hash: artifact.version_hash.clone(),
+                    offset,
+                    length: CHUNK_SIZE,
+                }))
+                .unwrap(),
+            )
+            .context(
+                serde_json::to_vec(&Context::Probe {
+                    package_id: artifact.package_id,
+                    version_hash: artifact.version_hash,
+                    seeder: seeder.to_string(),
+                    offset,
+                })
+                .unwrap(),
+            )
+            .expects_response(CHUNK_TIMEOUT)
+            .send();
+        if sent.is_ok() {
+            self.probing.insert(key);
+        }
+    }
+
+    /// as a package's publisher, the chunk asked of an announcing node is
+    /// in: list the node if it checks out
+    fn probed(
+        &mut self,
+        state: &mut State,
+        package_id: WitPackageId,
+        version_hash: &str,
+        seeder: &str,
+        offset: u64,
+        size: Option<u64>,
+    ) {
+        if !self
+            .probing
+            .remove(&(version_hash.to_string(), seeder.to_string()))
+        {
+            return;
+        }
+        let (Some(size), Some(blob)) = (size, get_blob()) else {
+            return;
+        };
+        let artifact = Artifact {
+            package_id,
+            version_hash: version_hash.to_string(),
+        };
+        let Some((expected_size, chunk_hashes)) = self.hashes(&artifact) else {
+            return;
+        };
+        if size != expected_size || !chunk_matches(&chunk_hashes, offset, &blob.bytes) {
+            print_to_terminal(
+                1,
+                &format!("downloads: {seeder} announced {version_hash} but served a bad chunk"),
+            );
+            return;
+        }
+        if !state.seeders.contains_key(version_hash) && state.seeders.len() >= MAX_VERSIONS {
+            // make room by forgetting the version with the fewest seeders
+            let fewest = state
+                .seeders
+                .iter()
+                .min_by_key(|(_, seeders)| seeders.len())
+                .map(|(version_hash, _)| version_hash.clone());
+            if let Some(fewest) = fewest {
+                state.seeders.remove(&fewest);
+            }
+        }
+        let seeders = state.seeders.entry(version_hash.to_string()).or_default();
+        if admit(seeders, seeder, now_secs()) {
+            self.changed(state);
+        }
+    }
+
+    /// as a package's publisher, stop listing `seeder` as holding a version
+    pub fn withdraw(&mut self, state: &mut State, seeder: &str, artifact: Artifact) {
+        let Some(seeders) = state.seeders.get_mut(&artifact.version_hash) else {
+            return;
+        };
+        let before = seeders.len();
+        seeders.retain(|kept| kept.node != seeder);
+        if seeders.len() == before {
+            return;
+        }
+        if seeders.is_empty() {
+            state.seeders.remove(&artifact.version_hash);
+        }
+        self.changed(state);
+    }
+
+    /// save our seeders every so often
+    fn changed(&mut self, state: &State) {
+        self.unsaved += 1;
+        if self.unsaved >= SAVE_EVERY {
+            self.unsaved = 0;
+            set_state(&serde_json::to_vec(&state).unwrap());
+        }
+    }
+
+    /// as a package's publisher, a random few of the seeders of a version,
+    /// other than `asker`, with the hashes to check their chunks against.
+    /// none if we don't hold the version, or `asker` has asked too often.
+    pub fn seeders(
+        &mut self,
+        state: &State,
+        asker: &str,
+        artifact: Artifact,
+    ) -> Option<SwarmSeeders> {
+        if !state.verified.contains(&artifact.version_hash) {
+            return None;
+        }
+        let listed: Vec<&Seeder> = state
+            .seeders
+            .get(&artifact.version_hash)?
+            .iter()
+            .filter(|seeder| seeder.node != asker)
+            .collect();
+        if listed.is_empty() || !self.limit.allow(asker, Instant::now()) {
+            return None;
+        }
+        let seeders = listed
+            .choose_multiple(&mut rand::thread_rng(), MAX_GIVEN)
+            .map(|seeder| seeder.node.clone())
+            .collect();
+        let (size, chunk_hashes) = self.hashes(&artifact)?;
+        Some(SwarmSeeders {
+            seeders,
+            size,
+            chunk_hashes,
+        })
+    }
+
+    /// the size and chunk hashes of a version we hold, hashing it if they
+    /// are not among the last few hashed
+    fn hashes(&mut self, artifact: &Artifact) -> Option<(u64, Vec<String>)> {
+        if let Some(hashed) = self.chunk_hashes.get(&artifact.version_hash) {
+            return Some(hashed.clone());
+        }
+        let package_id = artifact.package_id.clone().to_process_lib();
+        let zip_path = zip_path(&package_id, &artifact.version_hash);
+        let hashed = hash_chunks(&zip_path).ok()?;
+        if self.hashed.len() >= MAX_HASHED {
+            if let Some(oldest) = self.hashed.pop_front() {
+                self.chunk_hashes.remove(&oldest);
+            }
+        }
+        self.hashed.push_back(artifact.version_hash.clone());
+        self.chunk_hashes
+            .insert(artifact.version_hash.clone(), hashed.clone());
+        Some(hashed)
+    }
+}
+
+/// token buckets limiting how often we answer each asker, and everyone at
+/// once, so that serving the swarm can't take all of our bandwidth
+#[derive(Default)]
+struct RateLimit {
+    askers: HashMap<String, Bucket>,
+    total: Option<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+}
+
+impl Bucket {
+    fn full(rate: f64, now: Instant) -> Self {
+        Bucket {
+            tokens: rate * 2.0,
+            at: now,
+        }
+    }
+
+    fn refill(&mut self, rate: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate * 2.0);
+        self.at = now;
+    }
+}
+
+impl RateLimit {
+    fn allow(&mut self, asker: &str, now: Instant) -> bool {
+        if !self.askers.contains_key(asker) && self.askers.len() >= MAX_ASKERS {
+            let idlest = self
+                .askers
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.at)
+                .map(|(asker, _)| asker.clone());
+            if let Some(idlest) = idlest {
+                self.askers.remove(&idlest);
+            }
+        }
+        let total = self
+            .total
+            .get_or_insert_with(|| Bucket::full(TOTAL_RATE, now));
+        let bucket = self
+            .askers
+            .entry(asker.to_string())
+            .or_insert_with(|| Bucket::full(PER_ASKER_RATE, now));
+        total.refill(TOTAL_RATE, now);
+        bucket.refill(PER_ASKER_RATE, now);
+        if total.tokens < 1.0 || bucket.tokens < 1.0 {
+            return false;
+        }
+        total.tokens -= 1.0;
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// whether a node announcing a version should be checked before it is
+/// listed, or listed again
+fn needs_check(seeders: &[Seeder], node: &str, now: u64) -> bool {
+    !seeders
+        .iter()
+        .any(|seeder| seeder.node == node && now.saturating_sub(seeder.checked) < RECHECK_AFTER)
+}
+
+/// list a node that has just checked out as a seeder of a version, or
+/// note when it was checked if it is listed. a full list makes room only by
+/// dropping the seeder checked longest ago, if its check has lapsed.
+/// returns whether the list changed.
+fn admit(seeders: &mut Vec<Seeder>, node: &str, now: u64) -> bool {
+    if let Some(seeder) = seeders.iter_mut().find(|seeder| seeder.node == node) {
+        seeder.checked = now;
+        return true;
+    }
+    if seeders.len() >= MAX_SEEDERS {
+        let Some(stalest) = seeders
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, seeder)| seeder.checked)
+            .map(|(index, _)| index)
+        else {
+            return false;
+        };
+        if now.saturating_sub(seeders[stalest].checked) < SEEDER_TTL {
+            return false;
+        }
+        seeders.remove(stalest);
+    }
+    seeders.push(Seeder {
+        node: node.to_string(),
+        checked: now,
+    });
+    true
+}
+
+/// tell a version's publisher that we serve it, or no longer do
+fn tell_publisher(our: &Address, package_id: &WitPackageId, version_hash: &str, serving: bool) {
+    let publisher = package_id.publisher_node.as_str();
+    if publisher == our.node() {
+        return;
+    }
+    let artifact = Artifact {
+        package_id: package_id.clone(),
+        version_hash: version_hash.to_string(),
+    };
+    let request = if serving {
+        DownloadRequests::SwarmAnnounce(artifact)
+    } else {
+        DownloadRequests::SwarmWithdraw(artifact)
+    };
+    let _ = Request::to((publisher, "downloads", "app_store", "sys"))
+        .body(serde_json::to_vec(&request).unwrap())
+        .send();
+}
+
+/// how many chunks a zip of `size` bytes is served in
+fn chunk_count(size: u64) -> u64 {
+    size.div_ceil(CHUNK_SIZE)
+}
+
+/// the offset of each chunk of a zip of `size` bytes
+fn chunk_offsets(size: u64) -> impl Iterator<Item = u64> {
+    (0..size).step_by(CHUNK_SIZE as usize)
+}
+
+/// how many bytes to serve of a zip of `size` bytes when asked for
+/// `length` of them at `offset`, at most a chunk. none if `offset` is past
+/// the end.
+fn chunk_len(size: u64, offset: u64, length: u64) -> Option<u64> {
+    (offset < size).then(|| length.min(CHUNK_SIZE).min(size - offset))
+}
+
+/// whether `bytes` is the chunk at `offset` that the publisher hashed
+fn chunk_matches(chunk_hashes: &[String], offset: u64, bytes: &[u8]) -> bool {
+    if offset % CHUNK_SIZE != 0 {
+        return false;
+    }
+    let Some(expected) = chunk_hashes.get((offset / CHUNK_SIZE) as usize) else {
+        return false;
+    };
+    *expected == format!("{:x}", Sha256::digest(bytes))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn tmp_path(version_hash: &str) -> String {
+    format!("{DOWNLOADS_PATH}/tmp/{version_hash}.zip")
+}
+
+fn zip_path(package_id: &PackageId, version_hash: &str) -> String {
+    format!("{DOWNLOADS_PATH}/{package_id}/{version_hash}.zip")
+}
+
+fn write_chunk(version_hash: &str, offset: u64, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut file = vfs::open_file(&tmp_path(version_hash), false, None)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(bytes)?;
+    Ok(())
+}
+
+/// `length` bytes of a file, from `offset`
+fn read_range(file: &mut vfs::File, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+    let mut buffer = vec![0; length as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_at(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// the size of a zip, and the sha256 hash of each of its chunks, read a
+/// chunk at a time
+fn hash_chunks(path: &str) -> anyhow::Result<(u64, Vec<String>)> {
+    let mut file = vfs::open_file(path, false, None)?;
+    let size = file.metadata()?.len;
+    let mut chunk_hashes = vec![];
+    for offset in chunk_offsets(size) {
+        let length = chunk_len(size, offset, CHUNK_SIZE).unwrap_or(0);
+        let chunk = read_range(&mut file, offset, length)?;
+        chunk_hashes.push(format!("{:x}", Sha256::digest(&chunk)));
+    }
+    Ok((size, chunk_hashes))
+}
+
+/// the sha256 hash of a whole file, read a chunk at a time
+pub fn hash_file(path: &str) -> anyhow::Result<String> {
+    let mut file = vfs::open_file(path, false, None)?;
+    let size = file.metadata()?.len;
+    let mut hasher = Sha256::new();
+    for offset in chunk_offsets(size) {
+        let length = chunk_len(size, offset, CHUNK_SIZE).unwrap_or(0);
+        hasher.update(read_range(&mut file, offset, length)?);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// the size of a zip we hold, and the chunk of it asked for
+fn read_chunk(
+    package_id: &PackageId,
+    version_hash: &str,
+    offset: u64,
+    length: u64,
+) -> anyhow::Result<(u64, Vec<u8>)> {
+    let mut file = vfs::open_file(&zip_path(package_id, version_hash), false, None)?;
+    let size = file.metadata()?.len;
+    let Some(length) = chunk_len(size, offset, length) else {
+        return Err(anyhow::anyhow!("offset past end of zip"));
+    };
+    Ok((size, read_range(&mut file, offset, length)?))
+}
+
+/// move a fetched zip into place, with its manifest, if it matches its hash
+fn keep_verified(package_id: &PackageId, version_hash: &str) -> anyhow::Result<()> {
+    let tmp_path = tmp_path(version_hash);
+    let actual = hash_file(&tmp_path)?;
+    if actual != version_hash {
+        return Err(anyhow::anyhow!("hash mismatch: got {actual}"));
+    }
+    let package_dir = format!("{DOWNLOADS_PATH}/{package_id}");
+    crate::open_or_create_dir(&package_dir)?;
+    let zip_path = zip_path(package_id, version_hash);
+    rename(&tmp_path, &zip_path)?;
+    let zip = ZipReader::open(&zip_path)?;
+    crate::extract_and_write_manifest(
+        std::io::BufReader::with_capacity(CHUNK_SIZE as usize, zip),
+        &format!("{package_dir}/{version_hash}.json"),
+    )?;
+    Ok(())
+}
+
+fn rename(path: &str, new_path: &str) -> anyhow::Result<()> {
+    let response = Request::to(("our", "vfs", "distro", "sys"))
+        .body(serde_json::to_vec(&vfs::VfsRequest {
+            path: path.to_string(),
+            action: vfs::VfsAction::Rename {
+                new_path: new_path.to_string(),
+            },
+        })?)
+        .send_and_await_response(crate::VFS_TIMEOUT)??;
+    match serde_json::from_slice::<vfs::VfsResponse>(response.body())? {
+        vfs::VfsResponse::Ok => Ok(()),
+        vfs::VfsResponse::Err(e) => Err(anyhow::anyhow!("could not move zip: {e}")),
+        _ => Err(anyhow::anyhow!(
+            "could not move zip: unexpected vfs response"
+        )),
+    }
+}
+
+/// a zip in the vfs, read as it is needed, so that its manifest can be
+/// taken out without holding all of it
+struct ZipReader {
+    file: vfs::File,
+    size: u64,
+    position: u64,
+}
+
+impl ZipReader {
+    fn open(path: &str) -> anyhow::Result<Self> {
+        let file = vfs::open_file(path, false, None)?;
+        let size = file.metadata()?.len;
+        Ok(ZipReader {
+            file,
+            size,
+            position: 0,
+        })
+    }
+}
+
+impl std::io::Read for ZipReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = (buf.len() as u64).min(self.size.saturating_sub(self.position));
+        if length == 0 {
+            return Ok(0);
+        }
+        let bytes =
+            read_range(&mut self.file, self.position, length).map_err(std::io::Error::other)?;
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        self.position += bytes.len() as u64;
+        Ok(bytes.len())
+    }
+}
+
+impl std::io::Seek for ZipReader {
+    fn seek(&mut self, from: std::io::SeekFrom) -> std::io::Result<u64> {
+        let position = match from {
+            std::io::SeekFrom::Start(offset) => Some(offset),
+            std::io::SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+            std::io::SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        let Some(position) = position else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek before start of zip",
+            ));
+        };
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// report a finished fetch back to ourselves, as a download would be
+fn complete(package_id: WitPackageId, version_hash: &str, error: Option<DownloadError>) {
+    let _ = Request::to(("our", "downloads", "app_store", "sys"))
+        .body(
+            serde_json::to_vec(&DownloadRequests::DownloadComplete(
+                DownloadCompleteRequest {
+                    package_id,
+                    version_hash: version_hash.to_string(),
+                    error,
+                },
+            ))
+            .unwrap(),
+        )
+        .send();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn seeder(node: &str, checked: u64) -> Seeder {
+        Seeder {
+            node: node.to_string(),
+            checked,
+        }
+    }
+
+    #[test]
+    fn chunks_cover_the_zip() {
+        assert_eq!(chunk_count(1), 1);
+        assert_eq!(chunk_count(CHUNK_SIZE), 1);
+        assert_eq!(chunk_count(CHUNK_SIZE + 1), 2);
+        assert_eq!(chunk_offsets(0).count(), 0);
+        let size = 2 * CHUNK_SIZE + 10;
+        let offsets: Vec<u64> = chunk_offsets(size).collect();
+        assert_eq!(offsets, vec![0, CHUNK_SIZE, 2 * CHUNK_SIZE]);
+        assert_eq!(offsets.len() as u64, chunk_count(size));
+        let total: u64 = offsets
+            .iter()
+            .map(|offset| chunk_len(size, *offset, CHUNK_SIZE).unwrap())
+            .sum();
+        assert_eq!(total, size);
+    }
+
+    #[test]
+    fn chunk_len_is_bounded() {
+        let size = CHUNK_SIZE + 10;
+        assert_eq!(chunk_len(size, 0, CHUNK_SIZE), Some(CHUNK_SIZE));
+        assert_eq!(chunk_len(size, 0, 4 * CHUNK_SIZE), Some(CHUNK_SIZE));
+        assert_eq!(chunk_len(size, 0, 5), Some(5));
+        assert_eq!(chunk_len(size, CHUNK_SIZE, CHUNK_SIZE), Some(10));
+        assert_eq!(chunk_len(size, size, CHUNK_SIZE), None);
+        assert_eq!(chunk_len(size, u64::MAX, CHUNK_SIZE), None);
+    }
+
+    #[test]
+    fn chunks_are_checked_by_offset() {
+        let hashes = vec![
+            format!("{:x}", Sha256::digest(b"first")),
+            format!("{:x}", Sha256::digest(b"second")),
+        ];
+        assert!(chunk_matches(&hashes, 0, b"first"));
+        assert!(chunk_matches(&hashes, CHUNK_SIZE, b"second"));
+        assert!(!chunk_matches(&hashes, CHUNK_SIZE, b"first"));
+        assert!(!chunk_matches(&hashes, 1, b"first"));
+        assert!(!chunk_matches(&hashes, 2 * CHUNK_SIZE, b"first"));
+    }
+
+    #[test]
+    fn full_list_keeps_fresh_seeders() {
+        let now = 10 * SEEDER_TTL;
+        let mut seeders: Vec<Seeder> = (0..MAX_SEEDERS)
+            .map(|i| seeder(&format!("node{i}.os"), now - i as u64))
+            .collect();
+        // a flood of newcomers pushes out no one checked recently
+        for i in 0..MAX_SEEDERS {
+            assert!(!admit(&mut seeders, &format!("sybil{i}.os"), now));
+        }
+        assert_eq!(seeders.len(), MAX_SEEDERS);
+        assert!(seeders.iter().all(|seeder| seeder.node.starts_with("node")));
+        // a listed seeder is refreshed, not added again
+        assert!(admit(&mut seeders, "node5.os", now + 1));
+        assert_eq!(seeders.len(), MAX_SEEDERS);
+        assert_eq!(seeders[5].checked, now + 1);
+    }
+
+    #[test]
+    fn full_list_drops_the_stalest_lapsed_seeder() {
+        let now = 10 * SEEDER_TTL;
+        let mut seeders: Vec<Seeder> = (0..MAX_SEEDERS)
+            .map(|i| seeder(&format!("node{i}.os"), now))
+            .collect();
+        seeders[3].checked = now - SEEDER_TTL - 5;
+        seeders[7].checked = now - SEEDER_TTL - 1;
+        assert!(admit(&mut seeders, "new.os", now));
+        assert_eq!(seeders.len(), MAX_SEEDERS);
+        assert!(!seeders.iter().any(|seeder| seeder.node == "node3.os"));
+        assert!(seeders.iter().any(|seeder| seeder.node == "node7.os"));
+        assert!(seeders.iter().any(|seeder| seeder.node == "new.os"));
+    }
+
+    #[test]
+    fn announcing_again_is_checked_only_after_a_while() {
+        let seeders = vec![seeder("node.os", 1000)];
+        assert!(needs_check(&seeders, "other.os", 1000));
+        assert!(!needs_check(&seeders, "node.os", 1000 + RECHECK_AFTER - 1));
+        assert!(needs_check(&seeders, "node.os", 1000 + RECHECK_AFTER));
+    }
+
+    #[test]
+    fn askers_are_rate_limited() {
+        let mut limit = RateLimit::default();
+        let start = Instant::now();
+        let burst = (PER_ASKER_RATE * 2.0) as usize;
+        for _ in 0..burst {
+            assert!(limit.allow("node.os", start));
+        }
+        assert!(!limit.allow("node.os", start));
+        // others have their own share, until the total runs out
+        assert!(limit.allow("other.os", start));
+        assert!(limit.allow("node.os", start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn total_is_rate_limited() {
+        let mut limit = RateLimit::default();
+        let start = Instant::now();
+        let total = (TOTAL_RATE * 2.0) as usize;
+        for i in 0..total {
+            assert!(limit.allow(&format!("node{i}.os"), start));
+        }
+        assert!(!limit.allow("late.os", start));
+        assert!(limit.askers.len() <= MAX_ASKERS);
+    }
+}
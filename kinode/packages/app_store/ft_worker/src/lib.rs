@@ -21,6 +21,8 @@ wit_bindgen::generate!({
 });
 
 const CHUNK_SIZE: u64 = 262144; // 256KB
+/// how long a transfer may go without progress before it is given up on
+const KILLSWITCH_MS: u64 = 120_000; // 2 minutes
 
 call_init!(init);
 fn init(our: Address) {
@@ -38,7 +40,7 @@ fn init(our: Address) {
     }
 
     // killswitch timer, 2 minutes. sender or receiver gets killed/cleaned up.
-    timer::set_timer(120000, None);
+    timer::set_timer(KILLSWITCH_MS, None);
 
     let start = std::time::Instant::now();
 
@@ -140,10 +142,29 @@ fn handle_receiver(
     let mut file = open_or_create_file(&format!("{}{}.zip", &package_dir.path, version_hash))?;
     let mut size: Option<u64> = None;
     let mut hasher = Sha256::new();
+    let mut last_progress = std::time::Instant::now();
 
     loop {
         let message = await_message()?;
         if *message.source() == timer_address {
+            // a transfer still making progress, however slowly, is given the
+            // killswitch's full time again from its last chunk
+            let idle = last_progress.elapsed().as_millis() as u64;
+            if idle < KILLSWITCH_MS {
+                timer::set_timer(KILLSWITCH_MS - idle, None);
+                continue;
+            }
+            // killswitch: report the transfer as failed so another mirror can be tried
+            Request::new()
+                .body(serde_json::to_vec(&DownloadRequests::DownloadComplete(
+                    DownloadCompleteRequest {
+                        package_id: package_id.clone().into(),
+                        version_hash: version_hash.to_string(),
+                        error: Some(DownloadError::Timeout),
+                    },
+                ))?)
+                .target(parent_process.clone())
+                .send()?;
             return Ok(());
         }
         let Message::Request { body, .. } = message else {
@@ -154,6 +175,7 @@ fn handle_receiver(
 
         match req {
             DownloadRequests::Chunk(chunk) => {
+                last_progress = std::time::Instant::now();
                 handle_chunk(&mut file, &chunk, parent_process, &mut size, &mut hasher)?;
                 if let Some(s) = size {
                     if chunk.offset + chunk.length >= s {
@@ -183,6 +205,12 @@ fn handle_receiver(
                                 ))?)
                                 .target(parent_process.clone())
                                 .send()?;
+                            // never leave unverified bytes where they could be installed
+                            let _ = kinode_process_lib::vfs::remove_file(
+                                &format!("{}{}.zip", package_dir.path, version_hash),
+                                None,
+                            );
+                            return Ok(());
                         }
 
                         let manifest_filename =
@@ -1,5 +1,6 @@
 use crate::kinode::process::main::{
-    InstallPackageRequest, InstallResponse, LocalRequest, LocalResponse,
+    GetRequestedCapsRequest, InstallPackageRequest, InstallResponse, LocalRequest, LocalResponse,
+    RequestedCapability,
 };
 use kinode_process_lib::{
     await_next_message_body, call_init, println, Address, Message, PackageId, Request,
@@ -22,11 +23,13 @@ fn init(our: Address) {
     let arg = String::from_utf8(body).unwrap_or_default();
     let args: Vec<&str> = arg.split_whitespace().collect();
 
-    if args.len() != 2 {
+    if args.len() < 2 {
         println!(
             "install: 2 arguments required, the package id of the app and desired version_hash"
         );
         println!("example: install app:publisher.os f5d374ab50e66888a7c2332b22d0f909f2e3115040725cfab98dcae488916990");
+        println!("to approve the requested capabilities, add --approve");
+        println!("to withhold optional capabilities, add --deny followed by their numbers, e.g. --deny 1,3");
         return;
     }
 
@@ -38,16 +41,89 @@ fn init(our: Address) {
 
     let version_hash = args[1].to_string();
 
+    let mut caps_approved = false;
+    let mut denied_indices: Vec<usize> = vec![];
+    let mut flags = args[2..].iter();
+    while let Some(flag) = flags.next() {
+        match *flag {
+            "--approve" => caps_approved = true,
+            "--deny" => {
+                let Some(list) = flags.next() else {
+                    println!("install: --deny requires a comma-separated list of numbers");
+                    return;
+                };
+                for index in list.split(',') {
+                    let Ok(index) = index.parse::<usize>() else {
+                        println!("install: invalid capability number {index}");
+                        return;
+                    };
+                    denied_indices.push(index);
+                }
+            }
+            other => {
+                println!("install: unrecognized argument {other}");
+                return;
+            }
+        }
+    }
+
+    let wit_package_id = crate::kinode::process::main::PackageId {
+        package_name: package_id.package_name.clone(),
+        publisher_node: package_id.publisher_node.clone(),
+    };
+
+    // fetch and display the capabilities this package requests
+    let Ok(Ok(Message::Response { body, .. })) =
+        Request::to((our.node(), ("main", "app_store", "sys")))
+            .body(
+                serde_json::to_vec(&LocalRequest::GetRequestedCaps(GetRequestedCapsRequest {
+                    package_id: wit_package_id.clone(),
+                    version_hash: version_hash.clone(),
+                }))
+                .unwrap(),
+            )
+            .send_and_await_response(5)
+    else {
+        println!("install: failed to get a response from app_store..!");
+        return;
+    };
+
+    let Ok(LocalResponse::GetRequestedCapsResponse(Some(requested))) =
+        serde_json::from_slice::<LocalResponse>(&body)
+    else {
+        println!("install: could not read requested capabilities for {package_id}");
+        println!("make sure that the package has been downloaded!");
+        return;
+    };
+
+    println!(
+        "{package_id} requests the following capabilities:\n{}",
+        display_caps(&requested)
+    );
+
+    if !caps_approved {
+        println!("to install, review the capabilities above and re-run with --approve");
+        return;
+    }
+
+    let mut denied_capabilities: Vec<RequestedCapability> = vec![];
+    for index in denied_indices {
+        let Some(cap) = index.checked_sub(1).and_then(|i| requested.get(i)) else {
+            println!("install: no capability numbered {index}");
+            return;
+        };
+        denied_capabilities.push(cap.clone());
+    }
+
     let Ok(Ok(Message::Response { body, .. })) =
         Request::to((our.node(), ("main", "app_store", "sys")))
             .body(
                 serde_json::to_vec(&LocalRequest::Install(InstallPackageRequest {
-                    package_id: crate::kinode::process::main::PackageId {
-                        package_name: package_id.package_name.clone(),
-                        publisher_node: package_id.publisher_node.clone(),
-                    },
+                    package_id: wit_package_id,
                     version_hash,
                     metadata: None,
+                    caps_approved,
+                    denied_capabilities,
                 }))
                 .unwrap(),
             )
@@ -70,9 +146,29 @@ fn init(our: Address) {
             println!("failed to install package {package_id}");
             println!("make sure that the package has been downloaded!")
         }
+        LocalResponse::InstallResponse(InstallResponse::CapsNotApproved) => {
+            println!("install: capabilities for {package_id} were not approved");
+        }
         _ => {
             println!("install: unexpected response from app_store..!");
             return;
         }
     }
 }
+
+fn display_caps(caps: &[RequestedCapability]) -> String {
+    caps.iter()
+        .enumerate()
+        .map(|(i, cap)| {
+            format!(
+                "  {}. {} <- {}({}){}",
+                i + 1,
+                cap.process_name,
+                cap.issuer,
+                cap.params,
+                if cap.root { " [ROOT]" } else { "" }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
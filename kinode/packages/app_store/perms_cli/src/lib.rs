@@ -0,0 +1,142 @@
+//! perms: show what installed packages can do, from the terminal.
+//!
+//! a thin wrapper around the permissions API of main:app_store:sys.
+use crate::kinode::process::main::{LocalRequest, LocalResponse, PackagePermissions};
+use kinode_process_lib::{
+    await_next_message_body, call_init, println, Address, Message, PackageId, Request,
+};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    generate_unused_types: true,
+    world: "app-store-sys-v0",
+    additional_derives: [PartialEq, serde::Deserialize, serde::Serialize],
+});
+
+const USAGE: &str = "usage:
+  perms [<package_id>]";
+
+call_init!(init);
+fn init(our: Address) {
+    let Ok(body) = await_next_message_body() else {
+        println!("perms: failed to get args!");
+        return;
+    };
+
+    let arg = String::from_utf8(body).unwrap_or_default();
+    let args: Vec<&str> = arg.split_whitespace().collect();
+
+    let package_id = match args.as_slice() {
+        [] => None,
+        [package_id] => {
+            let Ok(package_id) = package_id.parse::<PackageId>() else {
+                println!(
+                    "perms: invalid package id, make sure to include package name and publisher"
+                );
+                println!("example: app_name:publisher_name");
+                return;
+            };
+            Some(crate::kinode::process::main::PackageId {
+                package_name: package_id.package_name,
+                publisher_node: package_id.publisher_node,
+            })
+        }
+        _ => {
+            println!("{USAGE}");
+            return;
+        }
+    };
+
+    let Ok(Ok(Message::Response { body, .. })) =
+        Request::to((our.node(), ("main", "app_store", "sys")))
+            .body(serde_json::to_vec(&LocalRequest::Permissions(package_id)).unwrap())
+            .send_and_await_response(30)
+    else {
+        println!("perms: failed to get a response from app_store..!");
+        return;
+    };
+
+    match serde_json::from_slice::<LocalResponse>(&body) {
+        Ok(LocalResponse::PermissionsResponse(packages)) if packages.is_empty() => {
+            println!("no such package installed");
+        }
+        Ok(LocalResponse::PermissionsResponse(packages)) => {
+            println!(
+                "{}",
+                packages
+                    .into_iter()
+                    .map(display_package)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        _ => println!("perms: unexpected response from app_store..!"),
+    }
+}
+
+fn display_package(package: PackagePermissions) -> String {
+    let mut printout = format!(
+        "{}:{}",
+        package.package_id.package_name, package.package_id.publisher_node
+    );
+    for process in package.processes {
+        printout.push_str(&format!(
+            "\n  {}{}{}",
+            process.process_id,
+            if process.running {
+                ""
+            } else {
+                " (not running)"
+            },
+            if process.public { " (public)" } else { "" },
+        ));
+        for cap in &process.capabilities {
+            printout.push_str(&format!(
+                "\n    {}{}({})",
+                if cap.root { "ROOT " } else { "" },
+                cap.issuer,
+                cap.params
+            ));
+        }
+        for binding in &process.bindings {
+            printout.push_str(&format!(
+                "\n    {} {}{}{}",
+                if binding.websocket { "ws" } else { "http" },
+                binding.path,
+                if binding.authenticated {
+                    ""
+                } else {
+                    " (unauthenticated)"
+                },
+                if binding.local_only {
+                    " (local only)"
+                } else {
+                    ""
+                },
+            ));
+        }
+        let network = &process.network;
+        if network.allowed || network.messages_sent > 0 || network.messages_received > 0 {
+            printout.push_str(&format!(
+                "\n    network: {} sent ({} bytes), {} received ({} bytes) since boot",
+                network.messages_sent,
+                network.bytes_sent,
+                network.messages_received,
+                network.bytes_received,
+            ));
+        }
+    }
+    for drive in package.drives {
+        printout.push_str(&format!(
+            "\n  drive {} ({}{})",
+            if drive.drive == "/" {
+                "/ (every drive)"
+            } else {
+                &drive.drive
+            },
+            if drive.write { "read/write" } else { "read" },
+            if drive.owned { ", own" } else { "" },
+        ));
+    }
+    printout
+}
@@ -0,0 +1,437 @@
+//! pkg: manage installed packages from the terminal, without the web UI.
+//!
+//! a thin wrapper around the main:app_store:sys local request API.
+use crate::kinode::process::chain::{ChainRequests, ChainResponses};
+use crate::kinode::process::downloads::{
+    Artifact, ArtifactReference, ArtifactReport, DownloadRequests, DownloadResponses, GcReport,
+};
+use crate::kinode::process::main::{
+    GetRequestedCapsRequest, InstallPackageRequest, InstallResponse, InstalledPackage,
+    LocalRequest, LocalResponse, RequestedCapability, SetUpdatePolicyRequest, UninstallResponse,
+    UpdatePackageRequest, UpdatePolicy, UpdateResponse,
+};
+use kinode_process_lib::{
+    await_next_message_body, call_init, println, Address, Message, PackageId, Request,
+};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    generate_unused_types: true,
+    world: "app-store-sys-v0",
+    additional_derives: [PartialEq, serde::Deserialize, serde::Serialize],
+});
+
+const USAGE: &str = "usage:
+  pkg list
+  pkg install <package_id> <version_hash> [--approve]
+  pkg update <package_id> [--approve]
+  pkg uninstall <package_id>
+  pkg policy <package_id> <auto|notify-only|pinned>
+  pkg gc [--dry-run]
+  pkg pins
+  pkg pin|unpin <package_id> <version_hash>
+  pkg swarm on|off";
+
+call_init!(init);
+fn init(our: Address) {
+    let Ok(body) = await_next_message_body() else {
+        println!("pkg: failed to get args!");
+        return;
+    };
+
+    let arg = String::from_utf8(body).unwrap_or_default();
+    let args: Vec<&str> = arg.split_whitespace().collect();
+
+    // pins and whether we serve the swarm are kept by
+    // downloads:app_store:sys, so are set there directly
+    let pin_request = match args.as_slice() {
+        ["pins"] => Some(DownloadRequests::GetPins),
+        ["swarm", "on"] => Some(DownloadRequests::SetSwarm(true)),
+        ["swarm", "off"] => Some(DownloadRequests::SetSwarm(false)),
+        [verb @ ("pin" | "unpin"), package_id, version_hash] => {
+            let Some(package_id) = parse_package_id(package_id) else {
+                return;
+            };
+            let artifact = Artifact {
+                package_id,
+                version_hash: version_hash.to_string(),
+            };
+            Some(if *verb == "pin" {
+                DownloadRequests::Pin(artifact)
+            } else {
+                DownloadRequests::Unpin(artifact)
+            })
+        }
+        _ => None,
+    };
+    if let Some(pin_request) = pin_request {
+        let Ok(Ok(Message::Response { body, .. })) =
+            Request::to((our.node(), ("downloads", "app_store", "sys")))
+                .body(serde_json::to_vec(&pin_request).unwrap())
+                .send_and_await_response(5)
+        else {
+            println!("pkg: failed to get a response from app_store..!");
+            return;
+        };
+        match serde_json::from_slice::<DownloadResponses>(&body) {
+            Ok(DownloadResponses::GetPins(pins)) if pins.is_empty() => {
+                println!("no versions pinned");
+            }
+            Ok(DownloadResponses::GetPins(pins)) => {
+                println!(
+                    "{}",
+                    pins.into_iter()
+                        .map(|pin| format!(
+                            "{}:{} {}",
+                            pin.package_id.package_name,
+                            pin.package_id.publisher_node,
+                            pin.version_hash
+                        ))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                );
+            }
+            Ok(DownloadResponses::Success) if args[0] == "swarm" => {
+                println!("serving the swarm: {}", args[1]);
+            }
+            Ok(DownloadResponses::Success) => {
+                println!("{}ned {} {}", args[0], args[1], args[2]);
+            }
+            _ => println!("pkg: unexpected response from app_store..!"),
+        }
+        return;
+    }
+
+    let request = match args.as_slice() {
+        ["list"] => LocalRequest::ListInstalled,
+        ["gc"] => LocalRequest::Gc(false),
+        ["gc", "--dry-run"] => LocalRequest::Gc(true),
+        ["install", package_id, version_hash, flags @ ..] => {
+            let Some(package_id) = parse_package_id(package_id) else {
+                return;
+            };
+            let Some(caps_approved) = parse_approve(flags) else {
+                return;
+            };
+            if caps_approved {
+                show_requested_caps(&our, &package_id, version_hash, "approving");
+            }
+            LocalRequest::Install(InstallPackageRequest {
+                package_id,
+                metadata: None,
+                version_hash: version_hash.to_string(),
+                caps_approved,
+                denied_capabilities: vec![],
+            })
+        }
+        ["update", package_id, flags @ ..] => {
+            let Some(package_id) = parse_package_id(package_id) else {
+                return;
+            };
+            let Some(caps_approved) = parse_approve(flags) else {
+                return;
+            };
+            if caps_approved {
+                if let Some(version_hash) = latest_version_hash(&our, &package_id) {
+                    show_requested_caps(&our, &package_id, &version_hash, "approving");
+                }
+            }
+            LocalRequest::Update(UpdatePackageRequest {
+                package_id,
+                caps_approved,
+            })
+        }
+        ["uninstall", package_id] => {
+            let Some(package_id) = parse_package_id(package_id) else {
+                return;
+            };
+            LocalRequest::Uninstall(package_id)
+        }
+        ["policy", package_id, policy] => {
+            let Some(package_id) = parse_package_id(package_id) else {
+                return;
+            };
+            let policy = match *policy {
+                "auto" => UpdatePolicy::Auto,
+                "notify-only" => UpdatePolicy::NotifyOnly,
+                "pinned" => UpdatePolicy::Pinned,
+                other => {
+                    println!("pkg: unknown update policy {other}");
+                    println!("{USAGE}");
+                    return;
+                }
+            };
+            LocalRequest::SetUpdatePolicy(SetUpdatePolicyRequest { package_id, policy })
+        }
+        _ => {
+            println!("{USAGE}");
+            return;
+        }
+    };
+
+    let Ok(Ok(Message::Response { body, .. })) =
+        Request::to((our.node(), ("main", "app_store", "sys")))
+            .body(serde_json::to_vec(&request).unwrap())
+            .send_and_await_response(30)
+    else {
+        println!("pkg: failed to get a response from app_store..!");
+        return;
+    };
+
+    let Ok(response) = serde_json::from_slice::<LocalResponse>(&body) else {
+        println!("pkg: failed to parse response from app_store..!");
+        return;
+    };
+
+    match response {
+        LocalResponse::ListInstalledResponse(packages) => {
+            if packages.is_empty() {
+                println!("no packages installed");
+            } else {
+                println!("{}", display_packages(packages));
+            }
+        }
+        LocalResponse::InstallResponse(InstallResponse::Success) => {
+            println!("successfully installed package {}", args[1]);
+        }
+        LocalResponse::InstallResponse(InstallResponse::Failure) => {
+            println!("failed to install package {}", args[1]);
+            println!("make sure that the package has been downloaded!");
+        }
+        LocalResponse::InstallResponse(InstallResponse::CapsNotApproved) => {
+            if let Some(package_id) = parse_package_id(args[1]) {
+                show_requested_caps(&our, &package_id, args[2], "requested");
+            }
+            println!("pkg: review the requested capabilities, then re-run with --approve");
+        }
+        LocalResponse::UpdateResponse(UpdateResponse::UpToDate) => {
+            println!("{} is up to date", args[1]);
+        }
+        LocalResponse::UpdateResponse(UpdateResponse::Success) => {
+            println!("successfully updated package {}", args[1]);
+        }
+        LocalResponse::UpdateResponse(UpdateResponse::Downloading) => {
+            println!("downloading the latest version of {}", args[1]);
+            println!("run the update again once the download completes");
+        }
+        LocalResponse::UpdateResponse(UpdateResponse::CapsNotApproved) => {
+            println!(
+                "the latest version of {} requests different capabilities",
+                args[1]
+            );
+            if let Some(package_id) = parse_package_id(args[1]) {
+                if let Some(version_hash) = latest_version_hash(&our, &package_id) {
+                    show_requested_caps(&our, &package_id, &version_hash, "requested");
+                }
+            }
+            println!("review them, then re-run the update with --approve");
+        }
+        LocalResponse::UpdateResponse(UpdateResponse::Failure) => {
+            println!("failed to update package {}", args[1]);
+        }
+        LocalResponse::UninstallResponse(UninstallResponse::Success) => {
+            println!("successfully uninstalled package {}", args[1]);
+        }
+        LocalResponse::UninstallResponse(UninstallResponse::Failure) => {
+            println!("failed to uninstall package {}", args[1]);
+        }
+        LocalResponse::SetUpdatePolicyResponse(true) => {
+            println!("set update policy of {} to {}", args[1], args[2]);
+        }
+        LocalResponse::SetUpdatePolicyResponse(false) => {
+            println!("pkg: {} is not installed", args[1]);
+        }
+        LocalResponse::GcResponse(Some(report)) => {
+            println!("{}", display_gc_report(report));
+        }
+        LocalResponse::GcResponse(None) => {
+            println!("pkg: failed to collect garbage, see the app_store logs");
+        }
+        _ => {
+            println!("pkg: unexpected response from app_store..!");
+        }
+    }
+}
+
+fn parse_package_id(package_id: &str) -> Option<crate::kinode::process::main::PackageId> {
+    let Ok(package_id) = package_id.parse::<PackageId>() else {
+        println!("pkg: invalid package id, make sure to include package name and publisher");
+        println!("example: app_name:publisher_name");
+        return None;
+    };
+    Some(crate::kinode::process::main::PackageId {
+        package_name: package_id.package_name,
+        publisher_node: package_id.publisher_node,
+    })
+}
+
+fn parse_approve(flags: &[&str]) -> Option<bool> {
+    match flags {
+        [] => Some(false),
+        ["--approve"] => Some(true),
+        _ => {
+            println!("pkg: unrecognized arguments {}", flags.join(" "));
+            None
+        }
+    }
+}
+
+/// the hash of the version of a package currently listed onchain
+fn latest_version_hash(
+    our: &Address,
+    package_id: &crate::kinode::process::main::PackageId,
+) -> Option<String> {
+    let Ok(Ok(Message::Response { body, .. })) =
+        Request::to((our.node(), ("chain", "app_store", "sys")))
+            .body(serde_json::to_vec(&ChainRequests::GetApp(package_id.clone())).unwrap())
+            .send_and_await_response(5)
+    else {
+        return None;
+    };
+    let Ok(ChainResponses::GetApp(Some(app))) = serde_json::from_slice(&body) else {
+        return None;
+    };
+    let properties = app.metadata?.properties;
+    properties
+        .code_hashes
+        .into_iter()
+        .find(|(version, _)| version == &properties.current_version)
+        .map(|(_, hash)| hash)
+}
+
+/// print the capabilities a downloaded version of a package requests
+fn show_requested_caps(
+    our: &Address,
+    package_id: &crate::kinode::process::main::PackageId,
+    version_hash: &str,
+    heading: &str,
+) {
+    let request = LocalRequest::GetRequestedCaps(GetRequestedCapsRequest {
+        package_id: package_id.clone(),
+        version_hash: version_hash.to_string(),
+    });
+    let Ok(Ok(Message::Response { body, .. })) =
+        Request::to((our.node(), ("main", "app_store", "sys")))
+            .body(serde_json::to_vec(&request).unwrap())
+            .send_and_await_response(5)
+    else {
+        println!("pkg: failed to get the requested capabilities from app_store..!");
+        return;
+    };
+    match serde_json::from_slice::<LocalResponse>(&body) {
+        Ok(LocalResponse::GetRequestedCapsResponse(Some(caps))) if caps.is_empty() => {
+            println!("{heading}: no capabilities");
+        }
+        Ok(LocalResponse::GetRequestedCapsResponse(Some(caps))) => {
+            println!("{heading}:\n{}", display_requested_caps(caps));
+        }
+        // not downloaded yet: nothing to show
+        _ => {}
+    }
+}
+
+fn display_requested_caps(caps: Vec<RequestedCapability>) -> String {
+    caps.into_iter()
+        .map(|cap| {
+            format!(
+                "  {} -> {} {}{}",
+                cap.process_name,
+                cap.issuer,
+                cap.params,
+                if cap.root { " [root]" } else { "" },
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn display_packages(packages: Vec<InstalledPackage>) -> String {
+    packages
+        .into_iter()
+        .map(|package| {
+            format!(
+                "{}:{} {} ({}){}",
+                package.package_id.package_name,
+                package.package_id.publisher_node,
+                package.version_hash,
+                match package.update_policy {
+                    UpdatePolicy::Auto => "auto",
+                    UpdatePolicy::NotifyOnly => "notify-only",
+                    UpdatePolicy::Pinned => "pinned",
+                },
+                if package.verified {
+                    ""
+                } else {
+                    " [unverified]"
+                },
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn display_gc_report(report: GcReport) -> String {
+    let display_artifact = |artifact: &ArtifactReport| {
+        format!(
+            "  {}:{} {} ({} bytes){}",
+            artifact.artifact.package_id.package_name,
+            artifact.artifact.package_id.publisher_node,
+            artifact.artifact.version_hash,
+            artifact.size,
+            if artifact.references.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    " [{}]",
+                    artifact
+                        .references
+                        .iter()
+                        .map(|reference| match reference {
+                            ArtifactReference::Installed => "installed",
+                            ArtifactReference::Mirrored => "mirrored",
+                            ArtifactReference::Pinned => "pinned",
+                            ArtifactReference::Downloading => "downloading",
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            },
+        )
+    };
+    let mut lines = vec![];
+    if !report.removed.is_empty() {
+        lines.push(if report.dry_run {
+            "would remove:".to_string()
+        } else {
+            "removed:".to_string()
+        });
+        lines.extend(report.removed.iter().map(display_artifact));
+    }
+    if !report.kept.is_empty() {
+        lines.push("kept:".to_string());
+        lines.extend(report.kept.iter().map(display_artifact));
+    }
+    if !report.orphans.is_empty() {
+        lines.push(if report.dry_run {
+            "would remove the drives of packages no longer installed:".to_string()
+        } else {
+            "removed the drives of packages no longer installed:".to_string()
+        });
+        lines.extend(report.orphans.iter().map(|orphan| {
+            format!(
+                "  {}:{} ({} bytes)",
+                orphan.package_id.package_name, orphan.package_id.publisher_node, orphan.size
+            )
+        }));
+    }
+    lines.push(format!(
+        "{} {} bytes",
+        if report.dry_run {
+            "would reclaim"
+        } else {
+            "reclaimed"
+        },
+        report.reclaimed_bytes
+    ));
+    lines.join("\n")
+}
@@ -0,0 +1,80 @@
+import React, { useState, useEffect } from 'react';
+import { FaSpinner } from "react-icons/fa";
+import useAppsStore from "../store";
+import { RequestedCapability } from '../types/Apps';
+
+interface CapApprovalProps {
+    packageId: string;
+    versionHash: string;
+    isInstalling?: boolean;
+    onCancel: () => void;
+    onApprove: (deniedCaps: RequestedCapability[]) => void;
+}
+
+const describeCap = (cap: RequestedCapability): string => {
+    if (cap.issuer === 'kernel:distro:sys' && cap.params === '"network"') {
+        return 'send and receive messages over the network';
+    }
+    if (cap.params === '"messaging"') {
+        return `message ${cap.issuer}`;
+    }
+    return `${cap.issuer} ${cap.params}`;
+};
+
+const CapApproval: React.FC<CapApprovalProps> = ({ packageId, versionHash, isInstalling, onCancel, onApprove }) => {
+    const { getCaps } = useAppsStore();
+    const [caps, setCaps] = useState<RequestedCapability[] | null>(null);
+    const [denied, setDenied] = useState<Set<number>>(new Set());
+
+    useEffect(() => {
+        getCaps(packageId, versionHash).then(setCaps);
+    }, [packageId, versionHash, getCaps]);
+
+    const toggle = (index: number) => {
+        setDenied(prev => {
+            const next = new Set(prev);
+            next.has(index) ? next.delete(index) : next.add(index);
+            return next;
+        });
+    };
+
+    return (
+        <div className="cap-approval-popup">
+            <div className="cap-approval-content">
+                <h3>Approve Capabilities</h3>
+                {caps === null ? (
+                    <p>Loading requested capabilities...</p>
+                ) : caps.length === 0 ? (
+                    <p>This package requests no capabilities.</p>
+                ) : (
+                    <ul className="cap-list">
+                        {caps.map((cap, index) => (
+                            <li key={index} className={cap.root ? 'cap-root' : ''}>
+                                <label>
+                                    <input
+                                        type="checkbox"
+                                        checked={!denied.has(index)}
+                                        onChange={() => toggle(index)}
+                                    />
+                                    <strong>{cap.process_name}</strong>: {describeCap(cap)}
+                                    {cap.root && <span className="cap-root-badge">root</span>}
+                                </label>
+                            </li>
+                        ))}
+                    </ul>
+                )}
+                <div className="approval-buttons">
+                    <button onClick={onCancel}>Cancel</button>
+                    <button
+                        onClick={() => onApprove((caps || []).filter((_, i) => denied.has(i)))}
+                        disabled={caps === null || isInstalling}
+                    >
+                        {isInstalling ? <FaSpinner className="fa-spin" /> : 'Approve and Install'}
+                    </button>
+                </div>
+            </div>
+        </div>
+    );
+};
+
+export default CapApproval;
@@ -1,2 +1,3 @@
 export { default as Header } from './Header';
-export { default as MirrorSelector } from './MirrorSelector';
\ No newline at end of file
+export { default as MirrorSelector } from './MirrorSelector';
+export { default as CapApproval } from './CapApproval';
\ No newline at end of file
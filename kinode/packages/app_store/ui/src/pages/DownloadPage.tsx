@@ -2,7 +2,8 @@ import React, { useState, useEffect, useCallback, useMemo } from "react";
 import { useParams } from "react-router-dom";
 import { FaDownload, FaCheck, FaSpinner, FaRocket, FaChevronDown, FaChevronUp, FaTrash } from "react-icons/fa";
 import useAppsStore from "../store";
-import { MirrorSelector } from '../components';
+import { MirrorSelector, CapApproval } from '../components';
+import { RequestedCapability } from '../types/Apps';
 
 export default function DownloadPage() {
     const { id } = useParams<{ id: string }>();
@@ -23,7 +24,6 @@ export default function DownloadPage() {
 
     const [showCapApproval, setShowCapApproval] = useState(false);
     const [selectedVersion, setSelectedVersion] = useState<{ version: string, hash: string } | null>(null);
-    const [manifest, setManifest] = useState<any>(null);
 
     const app = useMemo(() => listings[id || ""], [listings, id]);
     const appDownloads = useMemo(() => downloads[id || ""] || [], [downloads, id]);
@@ -49,27 +49,15 @@ export default function DownloadPage() {
 
     const handleInstall = useCallback((version: string, hash: string) => {
         if (!id || !app) return;
-        const download = appDownloads.find(d => d.File && d.File.name === `${hash}.zip`);
-        if (download?.File?.manifest) {
-            try {
-                const manifestData = JSON.parse(download.File.manifest);
-                setManifest(manifestData);
-                setSelectedVersion({ version, hash });
-                setShowCapApproval(true);
-            } catch (error) {
-                console.error('Failed to parse manifest:', error);
-            }
-        } else {
-            console.error('Manifest not found for the selected version');
-        }
-    }, [id, app, appDownloads]);
+        setSelectedVersion({ version, hash });
+        setShowCapApproval(true);
+    }, [id, app]);
 
-    const confirmInstall = useCallback(() => {
+    const confirmInstall = useCallback((deniedCaps: RequestedCapability[]) => {
         if (!id || !selectedVersion) return;
-        installApp(id, selectedVersion.hash).then(() => {
+        installApp(id, selectedVersion.hash, deniedCaps).then(() => {
             fetchData(id);
             setShowCapApproval(false);
-            setManifest(null);
         });
     }, [id, selectedVersion, installApp, fetchData]);
 
@@ -178,21 +166,13 @@ export default function DownloadPage() {
                 )}
             </div>
 
-            {showCapApproval && manifest && (
-                <div className="cap-approval-popup">
-                    <div className="cap-approval-content">
-                        <h3>Approve Capabilities</h3>
-                        <pre className="json-display">
-                            {JSON.stringify(manifest[0]?.request_capabilities || [], null, 2)}
-                        </pre>
-                        <div className="approval-buttons">
-                            <button onClick={() => setShowCapApproval(false)}>Cancel</button>
-                            <button onClick={confirmInstall}>
-                                Approve and Install
-                            </button>
-                        </div>
-                    </div>
-                </div>
+            {showCapApproval && id && selectedVersion && (
+                <CapApproval
+                    packageId={id}
+                    versionHash={selectedVersion.hash}
+                    onCancel={() => setShowCapApproval(false)}
+                    onApprove={confirmInstall}
+                />
             )}
         </div>
     );
@@ -1,7 +1,8 @@
 import React, { useState, useEffect } from "react";
 import { FaFolder, FaFile, FaChevronLeft, FaSync, FaRocket, FaSpinner, FaCheck, FaTrash } from "react-icons/fa";
 import useAppsStore from "../store";
-import { DownloadItem, PackageManifest, PackageState } from "../types/Apps";
+import { DownloadItem, PackageState, RequestedCapability } from "../types/Apps";
+import { CapApproval } from "../components";
 
 export default function MyDownloadsPage() {
     const { fetchDownloads, fetchDownloadsForApp, startMirroring, stopMirroring, installApp, removeDownload, fetchInstalled, installed } = useAppsStore();
@@ -10,7 +11,6 @@ export default function MyDownloadsPage() {
     const [isInstalling, setIsInstalling] = useState(false);
     const [error, setError] = useState<string | null>(null);
     const [showCapApproval, setShowCapApproval] = useState(false);
-    const [manifest, setManifest] = useState<PackageManifest | null>(null);
     const [selectedItem, setSelectedItem] = useState<DownloadItem | null>(null);
 
     useEffect(() => {
@@ -63,32 +63,29 @@ export default function MyDownloadsPage() {
     const handleInstall = async (item: DownloadItem) => {
         if (item.File) {
             setSelectedItem(item);
-            try {
-                const manifestData = JSON.parse(item.File.manifest);
-                setManifest(manifestData);
-                setShowCapApproval(true);
-            } catch (error) {
-                console.error('Failed to parse manifest:', error);
-                setError(`Failed to parse manifest: ${error instanceof Error ? error.message : String(error)}`);
-            }
+            setShowCapApproval(true);
         }
     };
 
-    const confirmInstall = async () => {
+    const selectedPackage = () => {
+        if (!selectedItem?.File) return null;
+        const parts = selectedItem.File.name.split(':');
+        const versionHash = parts.pop()?.replace('.zip', '');
+        if (!versionHash) return null;
+        // Construct packageId by combining currentPath and remaining parts of the filename
+        return { packageId: [...currentPath, ...parts].join(':'), versionHash };
+    };
+
+    const confirmInstall = async (deniedCaps: RequestedCapability[]) => {
         if (!selectedItem?.File) return;
         setIsInstalling(true);
         setError(null);
         try {
-            const fileName = selectedItem.File.name;
-            const parts = fileName.split(':');
-            const versionHash = parts.pop()?.replace('.zip', '');
-
-            if (!versionHash) throw new Error('Invalid file name format');
+            const selected = selectedPackage();
+            if (!selected) throw new Error('Invalid file name format');
+            const { packageId, versionHash } = selected;
 
-            // Construct packageId by combining currentPath and remaining parts of the filename
-            const packageId = [...currentPath, ...parts].join(':');
-
-            await installApp(packageId, versionHash);
+            await installApp(packageId, versionHash, deniedCaps);
             await fetchInstalled();
             setShowCapApproval(false);
             await loadItems();
@@ -186,21 +183,14 @@ export default function MyDownloadsPage() {
                 </div>
             )}
 
-            {showCapApproval && manifest && (
-                <div className="cap-approval-popup">
-                    <div className="cap-approval-content">
-                        <h3>Approve Capabilities</h3>
-                        <pre className="json-display">
-                            {JSON.stringify(manifest[0]?.request_capabilities || [], null, 2)}
-                        </pre>
-                        <div className="approval-buttons">
-                            <button onClick={() => setShowCapApproval(false)}>Cancel</button>
-                            <button onClick={confirmInstall} disabled={isInstalling}>
-                                {isInstalling ? <FaSpinner className="fa-spin" /> : 'Approve and Install'}
-                            </button>
-                        </div>
-                    </div>
-                </div>
+            {showCapApproval && selectedPackage() && (
+                <CapApproval
+                    packageId={selectedPackage()!.packageId}
+                    versionHash={selectedPackage()!.versionHash}
+                    isInstalling={isInstalling}
+                    onCancel={() => setShowCapApproval(false)}
+                    onApprove={confirmInstall}
+                />
             )}
         </div>
     );
@@ -1,6 +1,6 @@
 import { create } from 'zustand'
 import { persist } from 'zustand/middleware'
-import { PackageState, AppListing, MirrorCheckFile, PackageManifest, DownloadItem } from '../types/Apps'
+import { PackageState, AppListing, MirrorCheckFile, DownloadItem, RequestedCapability } from '../types/Apps'
 import { HTTP_STATUS } from '../constants/http'
 import KinodeClientApi from "@kinode/client-api"
 import { WEBSOCKET_URL } from '../utils/ws'
@@ -25,12 +25,11 @@ interface AppsStore {
   fetchDownloadsForApp: (id: string) => Promise<DownloadItem[]>
   checkMirror: (node: string) => Promise<MirrorCheckFile | null>
 
-  installApp: (id: string, version_hash: string) => Promise<void>
+  installApp: (id: string, version_hash: string, denied_caps: RequestedCapability[]) => Promise<void>
   uninstallApp: (id: string) => Promise<void>
   downloadApp: (id: string, version_hash: string, downloadFrom: string) => Promise<void>
   removeDownload: (packageId: string, versionHash: string) => Promise<void>
-  getCaps: (id: string) => Promise<PackageManifest | null>
-  approveCaps: (id: string) => Promise<void>
+  getCaps: (id: string, version_hash: string) => Promise<RequestedCapability[] | null>
   startMirroring: (id: string) => Promise<void>
   stopMirroring: (id: string) => Promise<void>
   setAutoUpdate: (id: string, version_hash: string, autoUpdate: boolean) => Promise<void>
@@ -186,11 +185,11 @@ const useAppsStore = create<AppsStore>()(
         return null;
       },
 
-      installApp: async (id: string, version_hash: string) => {
+      installApp: async (id: string, version_hash: string, denied_caps: RequestedCapability[]) => {
         try {
           const res = await fetch(`${BASE_URL}/apps/${id}/install`, {
             method: 'POST',
-            body: JSON.stringify({ version_hash })
+            body: JSON.stringify({ version_hash, caps_approved: true, denied_caps })
           });
           if (res.status === HTTP_STATUS.CREATED) {
             await get().fetchInstalled();
@@ -257,11 +256,11 @@ const useAppsStore = create<AppsStore>()(
         }
       },
 
-      getCaps: async (id: string) => {
+      getCaps: async (id: string, version_hash: string) => {
         try {
-          const res = await fetch(`${BASE_URL}/apps/${id}/caps`);
+          const res = await fetch(`${BASE_URL}/apps/${id}/caps?version_hash=${version_hash}`);
           if (res.status === HTTP_STATUS.OK) {
-            return await res.json() as PackageManifest;
+            return await res.json() as RequestedCapability[];
           }
         } catch (error) {
           console.error("Error getting caps:", error);
@@ -269,17 +268,6 @@ const useAppsStore = create<AppsStore>()(
         return null;
       },
 
-      approveCaps: async (id: string) => {
-        try {
-          const res = await fetch(`${BASE_URL}/apps/${id}/caps`, { method: 'POST' });
-          if (res.status === HTTP_STATUS.OK) {
-            await get().fetchListing(id);
-          }
-        } catch (error) {
-          console.error("Error approving caps:", error);
-        }
-      },
-
       startMirroring: async (id: string) => {
         try {
           const res = await fetch(`${BASE_URL}/downloads/${id}/mirror`, {
@@ -59,6 +59,14 @@ export interface PackageState {
     our_version_hash: string;
     verified: boolean;
     caps_approved: boolean;
+    denied_caps: RequestedCapability[];
+}
+
+export interface RequestedCapability {
+    process_name: string;
+    issuer: string;
+    params: string;
+    root: boolean;
 }
 
 export interface PackageManifest {
@@ -1,4 +1,6 @@
-use crate::kinode::process::main::{LocalRequest, LocalResponse, UninstallResponse};
+use crate::kinode::process::main::{
+    LocalRequest, LocalResponse, UninstallReport, UninstallResponse,
+};
 use kinode_process_lib::{
     await_next_message_body, call_init, println, Address, Message, PackageId, Request,
 };
@@ -18,31 +20,46 @@ fn init(our: Address) {
     };
 
     let arg = String::from_utf8(body).unwrap_or_default();
+    let args: Vec<&str> = arg.split_whitespace().collect();
 
-    if arg.is_empty() {
+    if args.is_empty() {
         println!("uninstall: 1 argument required, the package id of the app");
         println!("example: uninstall app:publisher.os");
+        println!("add --dry-run to list what would be removed, without removing it");
         return;
     };
 
-    let Ok(package_id) = arg.parse::<PackageId>() else {
+    let dry_run = match args.get(1) {
+        None => false,
+        Some(&"--dry-run") => true,
+        Some(other) => {
+            println!("uninstall: unrecognized argument {other}");
+            return;
+        }
+    };
+
+    let Ok(package_id) = args[0].parse::<PackageId>() else {
         println!("uninstall: invalid package id, make sure to include package name and publisher");
         println!("example: app_name:publisher_name");
         return;
     };
 
+    let wit_package_id = crate::kinode::process::main::PackageId {
+        package_name: package_id.package_name.clone(),
+        publisher_node: package_id.publisher_node.clone(),
+    };
+
     let Ok(Ok(Message::Response { body, .. })) =
         Request::to((our.node(), ("main", "app_store", "sys")))
             .body(
-                serde_json::to_vec(&LocalRequest::Uninstall(
-                    crate::kinode::process::main::PackageId {
-                        package_name: package_id.package_name.clone(),
-                        publisher_node: package_id.publisher_node.clone(),
-                    },
-                ))
+                serde_json::to_vec(&if dry_run {
+                    LocalRequest::UninstallReport(wit_package_id)
+                } else {
+                    LocalRequest::Uninstall(wit_package_id)
+                })
                 .unwrap(),
             )
-            .send_and_await_response(5)
+            .send_and_await_response(30)
     else {
         println!("uninstall: failed to get a response from app_store..!");
         return;
@@ -60,9 +77,40 @@ fn init(our: Address) {
         LocalResponse::UninstallResponse(UninstallResponse::Failure) => {
             println!("failed to uninstall package {package_id}!");
         }
+        LocalResponse::UninstallReportResponse(Some(report)) => {
+            println!(
+                "uninstalling {package_id} would remove:{}",
+                describe(report)
+            );
+        }
+        LocalResponse::UninstallReportResponse(None) => {
+            println!("uninstall: {package_id} is not installed");
+        }
         _ => {
             println!("uninstall: unexpected response from app_store..!");
             return;
         }
     }
 }
+
+/// each kind of thing a report lists, under a heading, leaving out kinds
+/// with nothing to remove
+fn describe(report: UninstallReport) -> String {
+    let revoked: Vec<String> = report
+        .revoked_capabilities
+        .into_iter()
+        .map(|(holder, capability)| format!("{capability}, held by {holder}"))
+        .collect();
+    [
+        ("processes (with their state)", report.processes),
+        ("drives", report.drives),
+        ("HTTP bindings", report.http_bindings),
+        ("kv databases", report.kv_databases),
+        ("sqlite databases", report.sqlite_databases),
+        ("capabilities issued to other packages", revoked),
+    ]
+    .into_iter()
+    .filter(|(_, items)| !items.is_empty())
+    .map(|(heading, items)| format!("\n  {heading}:\n    {}", items.join("\n    ")))
+    .collect()
+}
@@ -159,8 +159,14 @@ fn init(our: Address) {
         .bind_http_path("/favorite", http_config.clone())
         .expect("failed to bind /favorite");
     http_server
-        .bind_http_path("/order", http_config)
+        .bind_http_path("/order", http_config.clone())
         .expect("failed to bind /order");
+    http_server
+        .bind_http_path("/notifications", http_config.clone())
+        .expect("failed to bind /notifications");
+    http_server
+        .bind_http_path("/progress", http_config)
+        .expect("failed to bind /progress");
 
     loop {
         let Ok(ref message) = await_message() else {
@@ -245,6 +251,88 @@ fn init(our: Address) {
                                 }
                                 (server::HttpResponse::new(http::StatusCode::OK), None)
                             }
+                            "/notifications" => {
+                                // GET lists notifications, most recent first;
+                                // POST of `{"id": <id or null>}` marks one, or all, read
+                                let request = match incoming.method() {
+                                    Ok(http::Method::GET) => serde_json::json!("List"),
+                                    Ok(http::Method::POST) => {
+                                        let Some(id) = get_blob().and_then(|body| {
+                                            serde_json::from_slice::<serde_json::Value>(&body.bytes)
+                                                .ok()?
+                                                .get("id")
+                                                .cloned()
+                                        }) else {
+                                            return (
+                                                server::HttpResponse::new(
+                                                    http::StatusCode::BAD_REQUEST,
+                                                ),
+                                                None,
+                                            );
+                                        };
+                                        serde_json::json!({ "Dismiss": { "id": id } })
+                                    }
+                                    _ => {
+                                        return (
+                                            server::HttpResponse::new(
+                                                http::StatusCode::METHOD_NOT_ALLOWED,
+                                            ),
+                                            None,
+                                        )
+                                    }
+                                };
+                                match ask_runtime("notify", &request, "Notifications") {
+                                    Ok(response) => (
+                                        server::HttpResponse::new(http::StatusCode::OK),
+                                        Some(LazyLoadBlob::new(
+                                            Some("application/json"),
+                                            serde_json::to_vec(&response).unwrap(),
+                                        )),
+                                    ),
+                                    Err(e) => {
+                                        println!("couldn't reach notify: {e}");
+                                        (
+                                            server::HttpResponse::new(
+                                                http::StatusCode::SERVICE_UNAVAILABLE,
+                                            ),
+                                            None,
+                                        )
+                                    }
+                                }
+                            }
+                            "/progress" => {
+                                // GET lists the operations under way, for progress bars
+                                let Ok(http::Method::GET) = incoming.method() else {
+                                    return (
+                                        server::HttpResponse::new(
+                                            http::StatusCode::METHOD_NOT_ALLOWED,
+                                        ),
+                                        None,
+                                    );
+                                };
+                                match ask_runtime(
+                                    "progress",
+                                    &serde_json::json!("List"),
+                                    "Operations",
+                                ) {
+                                    Ok(response) => (
+                                        server::HttpResponse::new(http::StatusCode::OK),
+                                        Some(LazyLoadBlob::new(
+                                            Some("application/json"),
+                                            serde_json::to_vec(&response).unwrap(),
+                                        )),
+                                    ),
+                                    Err(e) => {
+                                        println!("couldn't reach progress: {e}");
+                                        (
+                                            server::HttpResponse::new(
+                                                http::StatusCode::SERVICE_UNAVAILABLE,
+                                            ),
+                                            None,
+                                        )
+                                    }
+                                }
+                            }
                             _ => (server::HttpResponse::new(http::StatusCode::NOT_FOUND), None),
                         }
                     },
@@ -319,6 +407,27 @@ fn init(our: Address) {
     }
 }
 
+/// send a request, given as JSON, to a runtime module process_lib doesn't
+/// know yet, such as notify:distro:sys, returning what its response holds
+/// under `key`
+fn ask_runtime(
+    module: &str,
+    request: &serde_json::Value,
+    key: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let response = kinode_process_lib::Request::to(("our", module, "distro", "sys"))
+        .body(serde_json::to_vec(request)?)
+        .send_and_await_response(5)??;
+    let response: serde_json::Value = serde_json::from_slice(response.body())?;
+    if let Some(error) = response.get("Err") {
+        return Err(anyhow::anyhow!("{error}"));
+    }
+    Ok(match response {
+        serde_json::Value::Object(mut map) if map.contains_key(key) => map.remove(key).unwrap(),
+        _ => serde_json::Value::Null,
+    })
+}
+
 fn version_from_cargo_toml() -> String {
     let version = CARGO_TOML
         .lines()
@@ -0,0 +1,79 @@
+import { useEffect, useState } from "react"
+
+export interface PostedNotification {
+  id: number
+  source: string
+  posted: number
+  read: boolean
+  notification: {
+    title: string
+    body: string
+    urgency: 'Low' | 'Normal' | 'Critical'
+    action?: string
+  }
+}
+
+// the notify module only keeps actions that are paths on this node or
+// http(s) URLs, but a link is checked again before it is shown
+const isSafeAction = (action: string) => {
+  if (action.startsWith('/')) {
+    return !action.startsWith('//') && !action.includes('\\')
+  }
+  try {
+    const url = new URL(action)
+    return url.protocol === 'http:' || url.protocol === 'https:'
+  } catch {
+    return false
+  }
+}
+
+const Notifications = () => {
+  const [notifications, setNotifications] = useState<PostedNotification[]>([])
+
+  const getNotifications = () => {
+    fetch('/notifications', { credentials: 'include' })
+      .then(res => res.json())
+      .then(data => setNotifications(data || []))
+      .catch(() => setNotifications([]))
+  }
+
+  const dismiss = (id: number | null) => {
+    fetch('/notifications', {
+      method: 'POST',
+      credentials: 'include',
+      body: JSON.stringify({ id }),
+    }).then(getNotifications)
+  }
+
+  useEffect(() => {
+    getNotifications()
+    const interval = setInterval(getNotifications, 30_000)
+    return () => clearInterval(interval)
+  }, [])
+
+  const unread = notifications.filter(n => !n.read)
+  if (unread.length === 0) {
+    return null
+  }
+
+  return (
+    <div id="notifications">
+      {unread.map(({ id, source, posted, notification }) => (
+        <div key={id} className={`notification ${notification.urgency.toLowerCase()}`}>
+          <div className="notification-header">
+            <b>{notification.title}</b>
+            <button onClick={() => dismiss(id)}>✕</button>
+          </div>
+          {notification.body && <p>{notification.body}</p>}
+          <p className="notification-footer">
+            {source}, {new Date(posted).toLocaleString()}
+            {notification.action && isSafeAction(notification.action) && <> · <a href={notification.action}>open</a></>}
+          </p>
+        </div>
+      ))}
+      {unread.length > 1 && <button onClick={() => dismiss(null)}>Dismiss all</button>}
+    </div>
+  )
+}
+
+export default Notifications
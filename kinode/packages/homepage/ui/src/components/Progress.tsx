@@ -0,0 +1,48 @@
+import { useEffect, useState } from "react"
+
+export interface ProgressUpdate {
+  reporter: string
+  updated: number
+  progress: {
+    operation: string
+    initiator: string
+    percent: number
+    label: string
+  }
+}
+
+const Progress = () => {
+  const [operations, setOperations] = useState<ProgressUpdate[]>([])
+
+  useEffect(() => {
+    const getOperations = () => {
+      fetch('/progress', { credentials: 'include' })
+        .then(res => res.json())
+        .then(data => setOperations(data || []))
+        .catch(() => setOperations([]))
+    }
+    getOperations()
+    const interval = setInterval(getOperations, 2_000)
+    return () => clearInterval(interval)
+  }, [])
+
+  if (operations.length === 0) {
+    return null
+  }
+
+  return (
+    <div id="progress">
+      {operations.map(({ reporter, progress }) => (
+        <div key={`${reporter}/${progress.operation}`} className="operation">
+          <div className="operation-header">
+            <span>{progress.label}</span>
+            <span>{progress.percent}%</span>
+          </div>
+          <progress max={100} value={progress.percent} />
+        </div>
+      ))}
+    </div>
+  )
+}
+
+export default Progress
@@ -6,6 +6,8 @@ import AppsDock from '../components/AppsDock'
 import AllApps from '../components/AllApps'
 import Widgets from '../components/Widgets'
 import WidgetsSettingsModal from '../components/WidgetsSettingsModal'
+import Notifications from '../components/Notifications'
+import Progress from '../components/Progress'
 
 function Homepage() {
   const [our, setOur] = useState('')
@@ -64,6 +66,8 @@ function Homepage() {
           [⚙]
         </a>
       </header>
+      <Notifications />
+      <Progress />
       <AppsDock />
       <Widgets />
       <footer>
@@ -1,4 +1,4 @@
-use kinode_process_lib::{call_init, http, timer, Address, Request};
+use kinode_process_lib::{await_message, call_init, http, Address, Request};
 use serde::{Deserialize, Serialize};
 
 wit_bindgen::generate!({
@@ -6,9 +6,6 @@ wit_bindgen::generate!({
     world: "process-v0",
 });
 
-/// 2 hours
-const REFRESH_INTERVAL: u64 = 120 * 60 * 1000;
-
 #[derive(Serialize, Deserialize)]
 struct KinodeBlogPost {
     slug: String,
@@ -22,29 +19,37 @@ struct KinodeBlogPost {
 }
 
 call_init!(init);
-fn init(_our: Address) {
+fn init(our: Address) {
     // updates: given a static message, the current version of the system,
     // and the kinode.org website, produce a widget for our homepage which
     // presents this information in a visually appealing way.
+    add_widget();
+
+    // the scheduler asks for a refresh every 2 hours, per our manifest
     loop {
-        // add ourselves to the homepage
-        Request::to(("our", "homepage", "homepage", "sys"))
-            .body(
-                serde_json::json!({
-                    "Add": {
-                        "label": "Updates from kinode.org",
-                        "widget": create_widget(fetch_most_recent_blog_posts(12)),
-                    }
-                })
-                .to_string(),
-            )
-            .send()
-            .unwrap();
-
-        timer::set_and_await_timer(REFRESH_INTERVAL).expect("Failed to produce a timer!");
+        match await_message() {
+            Ok(message) if message.is_request() && message.is_local(&our) => add_widget(),
+            _ => continue,
+        }
     }
 }
 
+/// add ourselves to the homepage, replacing any widget added before
+fn add_widget() {
+    Request::to(("our", "homepage", "homepage", "sys"))
+        .body(
+            serde_json::json!({
+                "Add": {
+                    "label": "Updates from kinode.org",
+                    "widget": create_widget(fetch_most_recent_blog_posts(12)),
+                }
+            })
+            .to_string(),
+        )
+        .send()
+        .unwrap();
+}
+
 fn create_widget(posts: Vec<KinodeBlogPost>) -> String {
     return format!(
         r#"<html>
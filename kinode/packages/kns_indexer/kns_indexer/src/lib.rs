@@ -38,6 +38,8 @@ const KIMAP_FIRST_BLOCK: u64 = 1; // local
 const MAX_PENDING_ATTEMPTS: u8 = 3;
 const SUBSCRIPTION_TIMEOUT: u64 = 60;
 const DELAY_MS: u64 = 1_000; // 1s
+/// most hosts a node may advertise in its `~endpoints` note
+const MAX_ENDPOINTS: usize = 8;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct State {
@@ -48,6 +50,10 @@ struct State {
     names: HashMap<String, String>,
     // human readable name to most recent on-chain routing information as json
     nodes: HashMap<String, net::KnsUpdate>,
+    // human readable name to the hosts its `~endpoints` note advertises
+    // after its `~ip`, in the order peers should try them
+    #[serde(default)]
+    endpoints: HashMap<String, Vec<String>>,
     // last block we have an update from
     last_block: u64,
 }
@@ -79,6 +85,7 @@ fn init(our: Address) {
         chain_id: CHAIN_ID,
         contract_address: KIMAP_ADDRESS.parse::<eth::Address>().unwrap(),
         nodes: HashMap::new(),
+        endpoints: HashMap::new(),
         names: HashMap::new(),
         last_block: KIMAP_FIRST_BLOCK,
     };
@@ -104,6 +111,7 @@ fn main(our: Address, mut state: State) -> anyhow::Result<()> {
         keccak256("~net-key"),
         keccak256("~routers"),
         keccak256("~ip"),
+        keccak256("~endpoints"),
     ];
 
     // sub_id: 2
@@ -206,7 +214,7 @@ fn main(our: Address, mut state: State) -> anyhow::Result<()> {
                 IndexerRequests::NodeInfo(NodeInfoRequest { ref name, block }) => {
                     Response::new()
                         .body(serde_json::to_vec(&IndexerResponses::NodeInfo(
-                            state.nodes.get(name).cloned(),
+                            advertised(&state, name),
                         ))?)
                         .send()?;
                 }
@@ -366,6 +374,12 @@ fn handle_note(state: &mut State, note: &kimap::contract::Note) -> anyhow::Resul
                 node.routers = vec![];
             }
         }
+        "~endpoints" => {
+            let endpoints = decode_endpoints(&note.data)?;
+            if state.nodes.contains_key(&node_name) {
+                state.endpoints.insert(node_name.clone(), endpoints);
+            }
+        }
         _other => {
             // Ignore unknown notes
         }
@@ -373,15 +387,13 @@ fn handle_note(state: &mut State, note: &kimap::contract::Note) -> anyhow::Resul
 
     // only send an update if we have a *full* set of data for networking:
     // a node name, plus either <routers> or <ip, port(s)>
-    if let Some(node_info) = state.nodes.get(&node_name) {
+    if let Some(node_info) = advertised(state, &node_name) {
         if !node_info.public_key.is_empty()
             && ((!node_info.ips.is_empty() && !node_info.ports.is_empty())
                 || node_info.routers.len() > 0)
         {
             Request::to(("our", "net", "distro", "sys"))
-                .body(rmp_serde::to_vec(&net::NetAction::KnsUpdate(
-                    node_info.clone(),
-                ))?)
+                .body(rmp_serde::to_vec(&net::NetAction::KnsUpdate(node_info))?)
                 .send()?;
         }
     }
@@ -550,6 +562,53 @@ fn decode_routers(data: &[u8], state: &State) -> Vec<String> {
     routers
 }
 
+/// the routing information of a node, with the hosts of its `~endpoints`
+/// note after the IP of its `~ip` note
+fn advertised(state: &State, name: &str) -> Option<net::KnsUpdate> {
+    let mut node_info = state.nodes.get(name)?.clone();
+    for endpoint in state.endpoints.get(name).into_iter().flatten() {
+        if !node_info.ips.contains(endpoint) {
+            node_info.ips.push(endpoint.clone());
+        }
+    }
+    Some(node_info)
+}
+
+/// an `~endpoints` note is a comma-separated list of hosts, each an IPv4 or
+/// IPv6 address or a domain, e.g. `203.0.113.7,2001:db8::7,node.example.com`
+pub fn decode_endpoints(bytes: &[u8]) -> anyhow::Result<Vec<String>> {
+    let endpoints: Vec<String> = std::str::from_utf8(bytes)?
+        .split(',')
+        .map(str::trim)
+        .filter(|endpoint| !endpoint.is_empty())
+        // IPv6 literals may be written bracketed, but are stored bare
+        .map(|endpoint| {
+            endpoint
+                .strip_prefix('[')
+                .and_then(|e| e.strip_suffix(']'))
+                .unwrap_or(endpoint)
+                .to_string()
+        })
+        .collect();
+    if endpoints.len() > MAX_ENDPOINTS {
+        return Err(anyhow::anyhow!(
+            "at most {MAX_ENDPOINTS} endpoints may be advertised"
+        ));
+    }
+    for endpoint in &endpoints {
+        let is_domain = endpoint.len() <= 253
+            && endpoint.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if endpoint.parse::<IpAddr>().is_err() && !is_domain {
+            return Err(anyhow::anyhow!("invalid endpoint {endpoint}"));
+        }
+    }
+    Ok(endpoints)
+}
+
 pub fn bytes_to_ip(bytes: &[u8]) -> anyhow::Result<IpAddr> {
     match bytes.len() {
         4 => {
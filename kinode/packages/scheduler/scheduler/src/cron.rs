@@ -0,0 +1,231 @@
+//! cron expressions: minute, hour, day of month, month and day of week, each
+//! `*`, a number, a range `a-b`, any of those with a step `/n`, or a list of
+//! them separated by commas. all times are UTC.
+
+const MINUTE_MS: u64 = 60 * 1000;
+const DAY_MINUTES: u64 = 24 * 60;
+/// give up looking for a next time after this many steps; every valid
+/// expression matches well within it
+const MAX_STEPS: usize = 100_000;
+
+#[derive(Clone, Debug)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// whether day of month and day of week were both restricted, in which
+    /// case a day matching either matches
+    either_day: bool,
+}
+
+impl std::str::FromStr for Cron {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(format!(
+                "{expression} should have 5 fields: minute, hour, day of month, month, day of week"
+            ));
+        };
+        let mut weekday_bits = field(weekdays, 0, 7)?;
+        // both 0 and 7 are Sunday
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits |= 1;
+        }
+        Ok(Cron {
+            minutes: field(minutes, 0, 59)?,
+            hours: field(hours, 0, 23)?,
+            days: field(days, 1, 31)?,
+            months: field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            either_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl Cron {
+    /// the first time it matches after `after`, both in milliseconds since the unix epoch
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let mut minute = after / MINUTE_MS + 1;
+        for _ in 0..MAX_STEPS {
+            let day = minute / DAY_MINUTES;
+            let (year, month, day_of_month) = civil_from_days(day);
+            if !has(self.months, month) {
+                let (year, month) = if month == 12 {
+                    (year + 1, 1)
+                } else {
+                    (year, month + 1)
+                };
+                minute = days_from_civil(year, month, 1) * DAY_MINUTES;
+                continue;
+            }
+            // the unix epoch was a Thursday
+            let weekday = (day + 4) % 7;
+            let day_matches = if self.either_day {
+                has(self.days, day_of_month) || has(self.weekdays, weekday)
+            } else {
+                has(self.days, day_of_month) && has(self.weekdays, weekday)
+            };
+            if !day_matches {
+                minute = (day + 1) * DAY_MINUTES;
+                continue;
+            }
+            if !has(self.hours, minute % DAY_MINUTES / 60) {
+                minute = (minute / 60 + 1) * 60;
+                continue;
+            }
+            if !has(self.minutes, minute % 60) {
+                minute += 1;
+                continue;
+            }
+            return Some(minute * MINUTE_MS);
+        }
+        None
+    }
+}
+
+fn has(bits: u64, value: u64) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// the values a field matches, as bits
+fn field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let number = |text: &str| {
+        text.parse::<u64>()
+            .ok()
+            .filter(|n| (min..=max).contains(n))
+            .ok_or_else(|| format!("{text} is not a number from {min} to {max}"))
+    };
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            None => (part, 1),
+            Some((range, step)) => match step.parse::<u64>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("{step} is not a step")),
+            },
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                // `a/n` runs from a to the end
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("{range} runs backwards"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// (year, month, day) of a day counted from the unix epoch, after
+/// http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// the day counted from the unix epoch of (year, month, day)
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-01T00:00Z, a Monday
+    const NEW_YEAR: u64 = 1_704_067_200_000;
+    const HOUR_MS: u64 = 60 * MINUTE_MS;
+    const DAY_MS: u64 = 24 * HOUR_MS;
+
+    fn next(expression: &str, after: u64) -> Option<u64> {
+        expression.parse::<Cron>().unwrap().next_after(after)
+    }
+
+    #[test]
+    fn next_time_is_strictly_after() {
+        assert_eq!(next("0 * * * *", NEW_YEAR), Some(NEW_YEAR + HOUR_MS));
+        assert_eq!(next("@daily", NEW_YEAR), Some(NEW_YEAR + DAY_MS));
+        assert_eq!(next("* * * * *", NEW_YEAR + 1), Some(NEW_YEAR + MINUTE_MS));
+        assert_eq!(
+            next("*/15 * * * *", NEW_YEAR + 20 * MINUTE_MS),
+            Some(NEW_YEAR + 30 * MINUTE_MS)
+        );
+    }
+
+    #[test]
+    fn weekdays_and_months() {
+        // from Saturday to the next weekday morning
+        assert_eq!(
+            next("30 9 * * 1-5", NEW_YEAR + 5 * DAY_MS),
+            Some(NEW_YEAR + 7 * DAY_MS + 9 * HOUR_MS + 30 * MINUTE_MS)
+        );
+        // 7 is Sunday, as 0 is
+        assert_eq!(next("0 0 * * 7", NEW_YEAR), Some(NEW_YEAR + 6 * DAY_MS));
+        // the next leap day after 2024-03-01
+        assert_eq!(
+            next("0 0 29 2 *", 1_709_251_200_000),
+            Some(1_835_395_200_000)
+        );
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // the 13th, or any Friday: Friday the 5th comes first
+        assert_eq!(next("0 0 13 * 5", NEW_YEAR), Some(NEW_YEAR + 4 * DAY_MS));
+    }
+
+    #[test]
+    fn bad_expressions_are_refused() {
+        for expression in [
+            "* * *",
+            "60 * * * *",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "a * * * *",
+        ] {
+            assert!(expression.parse::<Cron>().is_err(), "{expression}");
+        }
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        for day in [0, 59, 365, 11_016, 19_723, 21_243] {
+            let (year, month, day_of_month) = civil_from_days(day);
+            assert_eq!(days_from_civil(year, month, day_of_month), day);
+        }
+        assert_eq!(civil_from_days(NEW_YEAR / DAY_MS), (2024, 1, 1));
+    }
+}
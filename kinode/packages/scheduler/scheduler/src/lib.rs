@@ -0,0 +1,362 @@
+use kinode_process_lib::{
+    await_message, call_init, get_state, println, set_state, timer, vfs, Address, Message,
+    PackageId, ProcessId, Request, Response,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+mod cron;
+use cron::Cron;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const VFS_TIMEOUT: u64 = 5;
+
+/// the schedules in a process's manifest entry; process_lib's
+/// `PackageManifestEntry` predates them, so they are parsed separately
+#[derive(Deserialize)]
+struct ManifestEntry {
+    process_name: String,
+    #[serde(default)]
+    schedules: Vec<ManifestSchedule>,
+}
+
+#[derive(Deserialize)]
+struct ManifestSchedule {
+    cron: String,
+    body: serde_json::Value,
+}
+
+struct Schedule {
+    /// the process, and which of its schedules this is, e.g. `blog:kino_updates:sys#0`
+    id: String,
+    process: ProcessId,
+    expression: String,
+    cron: Cron,
+    body: Vec<u8>,
+    next_fire: Option<u64>,
+}
+
+/// what is kept across restarts: the schedules in manifests are read from
+/// them each time
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    disabled: HashSet<String>,
+    last_fired: HashMap<String, u64>,
+    /// schedules processes have added for themselves, by ID
+    #[serde(default)]
+    added: BTreeMap<String, AddedSchedule>,
+}
+
+/// a schedule added by a process at runtime, rather than in its manifest
+#[derive(Clone, Serialize, Deserialize)]
+struct AddedSchedule {
+    process: ProcessId,
+    cron: String,
+    body: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+enum SchedulerRequest {
+    /// every schedule of every installed package
+    List,
+    Enable {
+        id: String,
+    },
+    Disable {
+        id: String,
+    },
+    /// read the schedules from the manifests again, as the app store asks
+    /// after installing or uninstalling a package
+    Reload,
+    /// send the process that asks a request with this body on a schedule,
+    /// with the ID `<process>#<name>`, replacing any it added by that name
+    Add {
+        name: String,
+        cron: String,
+        body: serde_json::Value,
+    },
+    /// drop a schedule the process that asks added
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Serialize)]
+enum SchedulerResponse {
+    Ok,
+    Schedules(Vec<ScheduleInfo>),
+    Err(String),
+}
+
+#[derive(Serialize)]
+struct ScheduleInfo {
+    id: String,
+    process: ProcessId,
+    cron: String,
+    enabled: bool,
+    /// milliseconds since the unix epoch
+    next_fire: Option<u64>,
+    last_fired: Option<u64>,
+}
+
+call_init!(init);
+fn init(our: Address) {
+    let mut state: State = get_state()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    let mut schedules = load_schedules(now(), &state);
+    // when the timer set soonest will pop
+    let mut armed = arm(&schedules, &state, None);
+
+    loop {
+        let message = match await_message() {
+            Ok(message) => message,
+            Err(send_error) => {
+                println!("couldn't send scheduled request: {send_error}");
+                continue;
+            }
+        };
+        if !message.is_local(&our) {
+            continue;
+        }
+        if !message.is_request() {
+            if message.source().process == "timer:distro:sys" {
+                armed = None;
+                fire(&our, &mut schedules, &mut state);
+                armed = arm(&schedules, &state, armed);
+            }
+            continue;
+        }
+        let response = match serde_json::from_slice::<SchedulerRequest>(message.body()) {
+            Err(e) => SchedulerResponse::Err(format!("bad request: {e}")),
+            Ok(SchedulerRequest::List) => SchedulerResponse::Schedules(
+                schedules
+                    .iter()
+                    .map(|schedule| ScheduleInfo {
+                        id: schedule.id.clone(),
+                        process: schedule.process.clone(),
+                        cron: schedule.expression.clone(),
+                        enabled: !state.disabled.contains(&schedule.id),
+                        next_fire: schedule.next_fire,
+                        last_fired: state.last_fired.get(&schedule.id).copied(),
+                    })
+                    .collect(),
+            ),
+            Ok(SchedulerRequest::Enable { id }) | Ok(SchedulerRequest::Disable { id })
+                if !schedules.iter().any(|schedule| schedule.id == id) =>
+            {
+                SchedulerResponse::Err(format!("no schedule {id}"))
+            }
+            Ok(SchedulerRequest::Enable { id }) => {
+                state.disabled.remove(&id);
+                // a disabled schedule's next time may have gone by unnoticed
+                let now = now();
+                for schedule in schedules.iter_mut().filter(|schedule| schedule.id == id) {
+                    schedule.next_fire = schedule.cron.next_after(now);
+                }
+                save(&state);
+                armed = arm(&schedules, &state, armed);
+                SchedulerResponse::Ok
+            }
+            Ok(SchedulerRequest::Disable { id }) => {
+                state.disabled.insert(id);
+                save(&state);
+                SchedulerResponse::Ok
+            }
+            Ok(SchedulerRequest::Reload) => {
+                schedules = load_schedules(now(), &state);
+                let ids: HashSet<&String> = schedules.iter().map(|schedule| &schedule.id).collect();
+                state.disabled.retain(|id| ids.contains(id));
+                state.last_fired.retain(|id, _| ids.contains(id));
+                save(&state);
+                armed = arm(&schedules, &state, armed);
+                SchedulerResponse::Ok
+            }
+            Ok(SchedulerRequest::Add { name, .. })
+                if name.is_empty() || name.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                // numbers are the IDs of schedules in manifests
+                SchedulerResponse::Err(format!("{name:?} is not a name"))
+            }
+            Ok(SchedulerRequest::Add { name, cron, body }) => match cron.parse::<Cron>() {
+                Err(e) => SchedulerResponse::Err(e),
+                Ok(_) => {
+                    let process = message.source().process.clone();
+                    let id = format!("{process}#{name}");
+                    state.added.insert(
+                        id.clone(),
+                        AddedSchedule {
+                            process,
+                            cron,
+                            body,
+                        },
+                    );
+                    schedules.retain(|schedule| schedule.id != id);
+                    schedules.extend(added_schedule(&id, &state.added[&id], now()));
+                    save(&state);
+                    armed = arm(&schedules, &state, armed);
+                    SchedulerResponse::Ok
+                }
+            },
+            Ok(SchedulerRequest::Remove { name }) => {
+                let id = format!("{}#{name}", message.source().process);
+                if state.added.remove(&id).is_none() {
+                    SchedulerResponse::Err(format!("no schedule {id}"))
+                } else {
+                    schedules.retain(|schedule| schedule.id != id);
+                    state.disabled.remove(&id);
+                    state.last_fired.remove(&id);
+                    save(&state);
+                    SchedulerResponse::Ok
+                }
+            }
+        };
+        if let Message::Request {
+            expects_response: Some(_),
+            ..
+        } = message
+        {
+            Response::new()
+                .body(serde_json::to_vec(&response).unwrap())
+                .send()
+                .unwrap();
+        }
+    }
+}
+
+/// the schedules in the manifest of every installed package, and those
+/// processes have added
+fn load_schedules(now: u64, state: &State) -> Vec<Schedule> {
+    let mut schedules: Vec<Schedule> = state
+        .added
+        .iter()
+        .filter_map(|(id, added)| added_schedule(id, added, now))
+        .collect();
+    let packages = match vfs::open_dir("/", false, Some(VFS_TIMEOUT)).and_then(|dir| dir.read()) {
+        Ok(packages) => packages,
+        Err(e) => {
+            println!("couldn't list packages: {e}");
+            return schedules;
+        }
+    };
+    for package in packages {
+        let Ok(package_id) = package.path.trim_matches('/').parse::<PackageId>() else {
+            continue;
+        };
+        let Ok(manifest) = vfs::open_file(
+            &format!("/{package_id}/pkg/manifest.json"),
+            false,
+            Some(VFS_TIMEOUT),
+        )
+        .and_then(|file| file.read()) else {
+            continue;
+        };
+        let Ok(entries) = serde_json::from_slice::<Vec<ManifestEntry>>(&manifest) else {
+            println!("couldn't parse the manifest of {package_id}");
+            continue;
+        };
+        for entry in entries {
+            let process = ProcessId::new(
+                Some(&entry.process_name),
+                package_id.package(),
+                package_id.publisher(),
+            );
+            for (i, schedule) in entry.schedules.into_iter().enumerate() {
+                let id = format!("{process}#{i}");
+                let cron = match schedule.cron.parse::<Cron>() {
+                    Ok(cron) => cron,
+                    Err(e) => {
+                        println!("skipping schedule {id}: {e}");
+                        continue;
+                    }
+                };
+                schedules.push(Schedule {
+                    id,
+                    process: process.clone(),
+                    expression: schedule.cron,
+                    next_fire: cron.next_after(now),
+                    cron,
+                    body: serde_json::to_vec(&schedule.body).unwrap(),
+                });
+            }
+        }
+    }
+    schedules
+}
+
+fn added_schedule(id: &str, added: &AddedSchedule, now: u64) -> Option<Schedule> {
+    let cron = added.cron.parse::<Cron>().ok()?;
+    Some(Schedule {
+        id: id.to_string(),
+        process: added.process.clone(),
+        expression: added.cron.clone(),
+        next_fire: cron.next_after(now),
+        cron,
+        body: serde_json::to_vec(&added.body).unwrap(),
+    })
+}
+
+/// send the request of every enabled schedule that is due. times that went
+/// by while the node was down are not made up.
+fn fire(our: &Address, schedules: &mut [Schedule], state: &mut State) {
+    let now = now();
+    let mut fired = false;
+    for schedule in schedules.iter_mut() {
+        if schedule.next_fire.map_or(true, |next_fire| next_fire > now) {
+            continue;
+        }
+        schedule.next_fire = schedule.cron.next_after(now);
+        if state.disabled.contains(&schedule.id) {
+            continue;
+        }
+        Request::to(Address::new(&our.node, schedule.process.clone()))
+            .body(schedule.body.clone())
+            .send()
+            .unwrap();
+        state.last_fired.insert(schedule.id.clone(), now);
+        fired = true;
+    }
+    if fired {
+        save(state);
+    }
+}
+
+/// set a timer for the soonest enabled schedule, in place of the one set
+/// already, unless that pops by then. returns when the timer set pops.
+fn arm(schedules: &[Schedule], state: &State, armed: Option<u64>) -> Option<u64> {
+    let Some(soonest) = schedules
+        .iter()
+        .filter(|schedule| !state.disabled.contains(&schedule.id))
+        .filter_map(|schedule| schedule.next_fire)
+        .min()
+    else {
+        return armed;
+    };
+    if armed.is_some_and(|armed| armed <= soonest) {
+        return armed;
+    }
+    // `TimerAction::CancelTimers`, which process_lib has no binding for.
+    // cancelled every time, as a timer may be set while that of `armed` has
+    // popped but not yet been handled
+    Request::to(("our", "timer", "distro", "sys"))
+        .body(serde_json::to_vec("CancelTimers").unwrap())
+        .send()
+        .unwrap();
+    timer::set_timer(soonest.saturating_sub(now()), None);
+    Some(soonest)
+}
+
+fn save(state: &State) {
+    set_state(&serde_json::to_vec(state).unwrap());
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
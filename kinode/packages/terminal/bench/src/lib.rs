@@ -0,0 +1,384 @@
+//! bench: record the requests a process receives, then replay them against it
+//! on a node booted with `--bench` to measure the fuel, time, memory and blob
+//! copies it uses per message. the runtime can't see the allocations a process
+//! makes inside its own memory, only how much that memory grows, so growth is
+//! what is reported.
+//!
+//! workloads and reports are kept in `/terminal:sys/bench/`. each run is
+//! compared against the previous report for the same workload, if any.
+//!
+//! `bench throughput` instead sends a workload to several processes at once,
+//! without awaiting responses, and reports how many messages per second the
+//! node delivered. compare it across `--kernel-shards` values to see how
+//! delivery scales with cores.
+use kinode_process_lib::{script, timer, vfs, Address, LazyLoadBlob, Message, ProcessId, Request};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m
+    bench record <process_id> <- start recording requests to a process
+    bench save <process_id> <workload> <- stop recording and save the workload
+    bench run <process_id> <workload> [timeout] <- replay a workload and report
+    bench throughput <workload> <process_id>... <- replay a workload to several processes at once";
+
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// a request recorded by the kernel. capabilities attached to the original
+/// requests can't be replayed, so they are not kept.
+#[derive(Debug, Serialize, Deserialize)]
+struct WorkloadMessage {
+    source: Address,
+    request: RecordedRequest,
+    blob: Option<RecordedBlob>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedRequest {
+    expects_response: Option<u64>,
+    body: Vec<u8>,
+    metadata: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedBlob {
+    mime: Option<String>,
+    bytes: Vec<u8>,
+}
+
+/// mirrors `ProcessStats` in the runtime
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProcessStats {
+    messages: u64,
+    fuel_consumed: u64,
+    busy_micros: u64,
+    /// times linear memory grew, with `memory.grow` or at instantiation
+    memory_grows: u64,
+    memory_bytes_grown: u64,
+    blob_copies: u64,
+    blob_bytes_copied: u64,
+}
+
+impl ProcessStats {
+    /// the stats counted since `before`, or `None` if the counters went
+    /// back, as they do when the process restarts
+    fn since(&self, before: &ProcessStats) -> Option<ProcessStats> {
+        Some(ProcessStats {
+            messages: self.messages.checked_sub(before.messages)?,
+            fuel_consumed: self.fuel_consumed.checked_sub(before.fuel_consumed)?,
+            busy_micros: self.busy_micros.checked_sub(before.busy_micros)?,
+            memory_grows: self.memory_grows.checked_sub(before.memory_grows)?,
+            memory_bytes_grown: self
+                .memory_bytes_grown
+                .checked_sub(before.memory_bytes_grown)?,
+            blob_copies: self.blob_copies.checked_sub(before.blob_copies)?,
+            blob_bytes_copied: self
+                .blob_bytes_copied
+                .checked_sub(before.blob_bytes_copied)?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Report {
+    process: String,
+    workload: String,
+    messages: u64,
+    fuel_per_message: u64,
+    busy_micros_per_message: u64,
+    /// round trip of requests that expect a response, as seen by us
+    mean_round_trip_micros: Option<u64>,
+    max_round_trip_micros: Option<u64>,
+    memory_grows: u64,
+    memory_bytes_grown: u64,
+    blob_copies: u64,
+    blob_bytes_copied: u64,
+}
+
+script!(init);
+fn init(our: Address, args: String) -> String {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let result = match args.as_slice() {
+        ["record", process] => parse_process(process).and_then(record),
+        ["save", process, workload] => {
+            parse_process(process).and_then(|process| save(&our, process, workload))
+        }
+        ["run", process, workload, rest @ ..] => {
+            let timeout = match rest {
+                [] => Ok(DEFAULT_TIMEOUT),
+                [timeout] => timeout
+                    .parse::<u64>()
+                    .map_err(|_| anyhow::anyhow!("invalid timeout {timeout}")),
+                _ => Err(anyhow::anyhow!("too many arguments")),
+            };
+            timeout.and_then(|timeout| {
+                parse_process(process).and_then(|process| run(&our, process, workload, timeout))
+            })
+        }
+        ["throughput", workload, processes @ ..] if !processes.is_empty() => processes
+            .iter()
+            .map(|process| parse_process(process))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .and_then(|processes| throughput(&our, workload, processes)),
+        _ => return USAGE.to_string(),
+    };
+    match result {
+        Ok(output) => output,
+        Err(e) => format!("bench: {e}\n{USAGE}"),
+    }
+}
+
+fn parse_process(process: &str) -> anyhow::Result<ProcessId> {
+    process
+        .parse::<ProcessId>()
+        .map_err(|_| anyhow::anyhow!("invalid process id {process}"))
+}
+
+fn kernel_request(command: serde_json::Value, timeout: u64) -> anyhow::Result<Vec<u8>> {
+    // these kernel commands are not exposed by process_lib, so we build them by hand
+    let Message::Response { body, .. } = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(&command)?)
+        .send_and_await_response(timeout)??
+    else {
+        return Err(anyhow::anyhow!("unexpected request from kernel"));
+    };
+    Ok(body)
+}
+
+fn record(process: ProcessId) -> anyhow::Result<String> {
+    Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "RecordWorkload": process }),
+        )?)
+        .send()?;
+    Ok(format!(
+        "recording requests to {process}; use \x1b[1mbench save\x1b[0m to stop"
+    ))
+}
+
+fn save(our: &Address, process: ProcessId, workload: &str) -> anyhow::Result<String> {
+    let body = kernel_request(serde_json::json!({ "StopRecording": process }), 60)?;
+    let response: serde_json::Value = serde_json::from_slice(&body)?;
+    let Some(messages) = response.get("Workload") else {
+        return Err(anyhow::anyhow!("unexpected kernel response {response}"));
+    };
+    let messages: Vec<WorkloadMessage> = serde_json::from_value(messages.clone())?;
+    let drive = vfs::create_drive(our.package_id(), "bench", None)?;
+    vfs::create_file(&format!("{drive}/{workload}.json"), None)?
+        .write(&serde_json::to_vec(&messages)?)?;
+    Ok(format!(
+        "saved {} requests to {process} as workload {workload}",
+        messages.len()
+    ))
+}
+
+fn process_stats(process: &ProcessId) -> anyhow::Result<ProcessStats> {
+    let body = kernel_request(
+        serde_json::json!({ "Debug": { "ProcessStats": process } }),
+        60,
+    )?;
+    let response: serde_json::Value = serde_json::from_slice(&body)?;
+    match response.pointer("/Debug/ProcessStats") {
+        Some(serde_json::Value::Null) => Err(anyhow::anyhow!(
+            "no stats for {process}: is it running, and was the node booted with --bench?"
+        )),
+        Some(stats) => Ok(serde_json::from_value(stats.clone())?),
+        None => Err(anyhow::anyhow!("unexpected kernel response {response}")),
+    }
+}
+
+fn load_workload(our: &Address, workload: &str) -> anyhow::Result<(String, Vec<WorkloadMessage>)> {
+    let drive = vfs::create_drive(our.package_id(), "bench", None)?;
+    let messages: Vec<WorkloadMessage> = serde_json::from_slice(
+        &vfs::open_file(&format!("{drive}/{workload}.json"), false, None)?.read()?,
+    )?;
+    if messages.is_empty() {
+        return Err(anyhow::anyhow!("workload {workload} is empty"));
+    }
+    Ok((drive, messages))
+}
+
+fn replay_request(target: &Address, message: &WorkloadMessage) -> Request {
+    let mut request = Request::to(target.clone()).body(message.request.body.clone());
+    if let Some(metadata) = &message.request.metadata {
+        request = request.metadata(metadata);
+    }
+    if let Some(blob) = &message.blob {
+        request = request.blob(LazyLoadBlob {
+            mime: blob.mime.clone(),
+            bytes: blob.bytes.clone(),
+        });
+    }
+    request
+}
+
+/// wait until `process` has received `expected` messages since `before`
+fn await_received(
+    process: &ProcessId,
+    before: &ProcessStats,
+    expected: u64,
+    deadline: Instant,
+) -> anyhow::Result<ProcessStats> {
+    let mut after = process_stats(process)?;
+    while after.messages < before.messages.saturating_add(expected) {
+        if Instant::now() > deadline {
+            return Err(anyhow::anyhow!(
+                "timed out waiting for {process} to receive the workload"
+            ));
+        }
+        timer::set_and_await_timer(100).map_err(|e| anyhow::anyhow!("timer failed: {e}"))?;
+        after = process_stats(process)?;
+    }
+    Ok(after)
+}
+
+fn run(our: &Address, process: ProcessId, workload: &str, timeout: u64) -> anyhow::Result<String> {
+    let (drive, messages) = load_workload(our, workload)?;
+
+    let before = process_stats(&process)?;
+    let target = Address::new(&our.node, process.clone());
+    let mut round_trips: Vec<u64> = vec![];
+    for message in &messages {
+        let request = replay_request(&target, message);
+        if message.request.expects_response.is_some() {
+            let start = Instant::now();
+            request.send_and_await_response(timeout)??;
+            round_trips.push(start.elapsed().as_micros() as u64);
+        } else {
+            request.send()?;
+        }
+    }
+
+    // requests that expect no response may still be in flight: wait until
+    // the process has received every one of them
+    let deadline = Instant::now() + std::time::Duration::from_secs(timeout);
+    let after = await_received(&process, &before, messages.len() as u64, deadline)?;
+
+    let Some(used) = after.since(&before) else {
+        return Err(anyhow::anyhow!(
+            "{process} restarted during the run, so its stats were reset: run it again"
+        ));
+    };
+    let received = used.messages.max(1);
+    let report = Report {
+        process: process.to_string(),
+        workload: workload.to_string(),
+        messages: used.messages,
+        fuel_per_message: used.fuel_consumed / received,
+        busy_micros_per_message: used.busy_micros / received,
+        mean_round_trip_micros: (!round_trips.is_empty())
+            .then(|| round_trips.iter().sum::<u64>() / round_trips.len() as u64),
+        max_round_trip_micros: round_trips.iter().max().cloned(),
+        memory_grows: used.memory_grows,
+        memory_bytes_grown: used.memory_bytes_grown,
+        blob_copies: used.blob_copies,
+        blob_bytes_copied: used.blob_bytes_copied,
+    };
+
+    let report_path = format!("{drive}/{workload}.report.json");
+    let previous: Option<Report> = vfs::open_file(&report_path, false, None)
+        .and_then(|file| file.read())
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+    vfs::open_file(&report_path, true, None)?.write(&serde_json::to_vec(&report)?)?;
+
+    Ok(format_report(&report, previous.as_ref()))
+}
+
+/// send the workload to every process, interleaved so that the node must
+/// deliver to all of them at once, and time until all have received it.
+/// responses, if any, are not awaited.
+fn throughput(our: &Address, workload: &str, processes: Vec<ProcessId>) -> anyhow::Result<String> {
+    let (_, messages) = load_workload(our, workload)?;
+    let befores = processes
+        .iter()
+        .map(process_stats)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let targets: Vec<Address> = processes
+        .iter()
+        .map(|process| Address::new(&our.node, process.clone()))
+        .collect();
+
+    let start = Instant::now();
+    for message in &messages {
+        for target in &targets {
+            replay_request(target, message).send()?;
+        }
+    }
+    let deadline = start + std::time::Duration::from_secs(DEFAULT_TIMEOUT);
+    for (process, before) in processes.iter().zip(&befores) {
+        await_received(process, before, messages.len() as u64, deadline)?;
+    }
+    let elapsed = start.elapsed();
+
+    let total = (messages.len() * processes.len()) as u64;
+    Ok(format!(
+        "delivered {total} messages of workload \x1b[1m{workload}\x1b[0m to {} processes in {}ms: \x1b[1m{:.0}\x1b[0m messages per second",
+        processes.len(),
+        elapsed.as_millis(),
+        total as f64 / elapsed.as_secs_f64(),
+    ))
+}
+
+fn format_report(report: &Report, previous: Option<&Report>) -> String {
+    let change = |now: u64, then: Option<u64>| match then {
+        Some(then) if then > 0 => {
+            let percent = (now as f64 - then as f64) / then as f64 * 100.0;
+            let color = if percent > 5.0 {
+                "\x1b[31m"
+            } else if percent < -5.0 {
+                "\x1b[32m"
+            } else {
+                ""
+            };
+            format!(" ({color}{percent:+.1}%\x1b[0m)")
+        }
+        _ => String::new(),
+    };
+    let mut out = format!(
+        "\x1b[1m{}\x1b[0m on workload \x1b[1m{}\x1b[0m: {} messages\r\n",
+        report.process, report.workload, report.messages
+    );
+    out.push_str(&format!(
+        "    fuel per message: {}{}\r\n",
+        report.fuel_per_message,
+        change(
+            report.fuel_per_message,
+            previous.map(|p| p.fuel_per_message)
+        )
+    ));
+    out.push_str(&format!(
+        "    wall time per message: {}µs{}\r\n",
+        report.busy_micros_per_message,
+        change(
+            report.busy_micros_per_message,
+            previous.map(|p| p.busy_micros_per_message)
+        )
+    ));
+    if let (Some(mean), Some(max)) = (report.mean_round_trip_micros, report.max_round_trip_micros) {
+        out.push_str(&format!(
+            "    round trip: mean {mean}µs{}, max {max}µs\r\n",
+            change(mean, previous.and_then(|p| p.mean_round_trip_micros))
+        ));
+    }
+    out.push_str(&format!(
+        "    memory grown: {} bytes, in {} grows{}\r\n",
+        report.memory_bytes_grown,
+        report.memory_grows,
+        change(
+            report.memory_bytes_grown,
+            previous.map(|p| p.memory_bytes_grown)
+        )
+    ));
+    out.push_str(&format!(
+        "    blobs copied: {} ({} bytes){}",
+        report.blob_copies,
+        report.blob_bytes_copied,
+        change(report.blob_copies, previous.map(|p| p.blob_copies))
+    ));
+    out
+}
@@ -0,0 +1,88 @@
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{script, Args};
+use serde::Deserialize;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m caps [approve|deny <id>]";
+
+// the kernel's `QueuedCapRequest`, which process_lib doesn't know yet
+#[derive(Deserialize)]
+struct QueuedCapRequest {
+    id: u64,
+    from: Address,
+    capability: Capability,
+    reason: String,
+    age_secs: u64,
+}
+
+#[derive(Deserialize)]
+struct Capability {
+    issuer: Address,
+    params: String,
+}
+
+#[derive(Deserialize)]
+enum KernelResponse {
+    CapRequests(Vec<QueuedCapRequest>),
+    CapRequestError(String),
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    // what was done, to print before the requests still waiting
+    let mut done = String::new();
+    let action = match args.positional.as_slice() {
+        [] => serde_json::json!("List"),
+        [verb, id] if verb == "approve" || verb == "deny" => {
+            let Ok(id) = id.parse::<u64>() else {
+                return format!("invalid request id {id}\n{USAGE}");
+            };
+            if verb == "approve" {
+                done = format!("approved request {id}\r\n");
+                serde_json::json!({ "Approve": id })
+            } else {
+                done = format!("denied request {id}\r\n");
+                serde_json::json!({ "Deny": id })
+            }
+        }
+        _ => {
+            return format!(
+                "List, approve or deny the capabilities remote nodes have asked for.\n{USAGE}"
+            )
+        }
+    };
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({ "CapRequests": action })).unwrap())
+        .send_and_await_response(5)
+        .unwrap()
+    else {
+        return "failed to get response from kernel".to_string();
+    };
+    let queued = match serde_json::from_slice::<KernelResponse>(&body) {
+        Ok(KernelResponse::CapRequests(queued)) => queued,
+        Ok(KernelResponse::CapRequestError(e)) => return e,
+        Err(_) => return "failed to parse kernel response".to_string(),
+    };
+
+    if queued.is_empty() {
+        return format!("{done}no capability requests waiting");
+    }
+    let mut printout = format!("{done}{} capability requests waiting:", queued.len());
+    for request in queued {
+        printout.push_str(&format!(
+            "\r\n    {}: {} asks for {}({}) ({}s ago): \"{}\"",
+            request.id,
+            request.from,
+            request.capability.issuer,
+            request.capability.params,
+            request.age_secs,
+            request.reason,
+        ));
+    }
+    printout
+}
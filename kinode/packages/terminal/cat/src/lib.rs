@@ -1,20 +1,31 @@
-use kinode_process_lib::{println, script, vfs, Address};
+use kinode_process_lib::{println, vfs, Address};
+use script_args::{script, Args};
 
 wit_bindgen::generate!({
     path: "target/wit",
     world: "process-v0",
 });
 
-const USAGE: &str = "\x1b[1mUsage:\x1b[0m cat <file_path>";
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m cat <file_path>, or <command> | cat";
 
-script!(init);
-fn init(_our: Address, args: String) -> String {
-    if args.is_empty() {
+script!(init, raw);
+fn init(_our: Address, args: Args) -> String {
+    // the whole line is the path, so that one with spaces or quotes in it
+    // needn't be quoted. one that is quoted whole is unquoted.
+    let path = match script_args::tokenize(&args.raw) {
+        Ok(tokens) if tokens.len() == 1 => tokens.into_iter().next().unwrap(),
+        _ => args.raw.trim().to_string(),
+    };
+    if path.is_empty() {
+        // in a pipeline, pass on what the previous stage gave
+        if let Some(stdin) = args.stdin {
+            return String::from_utf8_lossy(&stdin.bytes).to_string();
+        }
         return format!("Print the contents of a file to the terminal.\n{USAGE}");
     }
 
-    match vfs::File::new(&args, 5).read() {
+    match vfs::File::new(&path, 5).read() {
         Ok(data) => String::from_utf8_lossy(&data).to_string(),
-        Err(_) => format!("failed to read file {args} from VFS.\n{USAGE}"),
+        Err(_) => format!("failed to read file {path} from VFS.\n{USAGE}"),
     }
 }
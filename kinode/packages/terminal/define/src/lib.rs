@@ -0,0 +1,46 @@
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{script, Args};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m define [<name> \"<pipeline>\" | --remove <name> | --cron <expression|off> <name> | --export <path> | --import <path> [--confirm <code>]]";
+
+script!(init, ["cron", "export", "import", "confirm"]);
+fn init(_our: Address, args: Args) -> String {
+    let export = args.value(&["export"]);
+    let import = args.value(&["import"]);
+    let action = match (args.positional.as_slice(), export, import) {
+        ([], None, None) if args.flags.is_empty() => serde_json::json!("ListServices"),
+        ([], Some(path), None) => serde_json::json!({ "ExportServices": { "path": path } }),
+        ([], None, Some(path)) => serde_json::json!({
+            "ImportServices": { "path": path, "confirm": args.value(&["confirm"]) }
+        }),
+        ([name], None, None) if args.has(&["remove"]) => {
+            serde_json::json!({ "EditService": { "name": name, "pipeline": null } })
+        }
+        ([name], None, None) if args.value(&["cron"]).is_some() => {
+            let cron = args.value(&["cron"]).filter(|cron| *cron != "off");
+            serde_json::json!({ "ScheduleService": { "name": name, "cron": cron } })
+        }
+        ([name, pipeline], None, None) if args.flags.is_empty() => {
+            serde_json::json!({ "EditService": { "name": name, "pipeline": pipeline } })
+        }
+        _ => {
+            return format!(
+                "List the pipelines saved as services, or define, remove, schedule, export or import them. A service runs when its name is typed.\n{USAGE}"
+            )
+        }
+    };
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "terminal", "terminal", "sys"))
+        .body(serde_json::to_vec(&action).unwrap())
+        .send_and_await_response(15)
+        .unwrap()
+    else {
+        return "failed to get response from terminal".to_string();
+    };
+    String::from_utf8_lossy(&body).to_string()
+}
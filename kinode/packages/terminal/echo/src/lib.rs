@@ -1,11 +1,12 @@
-use kinode_process_lib::{script, Address};
+use kinode_process_lib::Address;
+use script_args::{script, Args};
 
 wit_bindgen::generate!({
     path: "target/wit",
     world: "process-v0",
 });
 
-script!(init);
-fn init(_our: Address, args: String) -> String {
-    args
+script!(init, raw);
+fn init(_our: Address, args: Args) -> String {
+    args.raw
 }
@@ -0,0 +1,202 @@
+use kinode_process_lib::eth::{EthAction, EthResponse};
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{script, Args};
+use serde_json::{json, Value};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+#[cfg(not(feature = "simulation-mode"))]
+const CHAIN_ID: u64 = 10; // optimism
+#[cfg(feature = "simulation-mode")]
+const CHAIN_ID: u64 = 31337; // local
+
+const TIMEOUT: u64 = 30;
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m eth [--chain <id>] [--block <number|tag>] <balance <address> | call <address> <calldata> | logs <filter-json>>";
+
+fn request(chain_id: u64, method: &str, params: Value) -> Result<Value, String> {
+    let Ok(Ok(Message::Response { body, .. })) = Request::to(("our", "eth", "distro", "sys"))
+        .body(
+            serde_json::to_vec(&EthAction::Request {
+                chain_id,
+                method: method.to_string(),
+                params,
+            })
+            .unwrap(),
+        )
+        .send_and_await_response(TIMEOUT + 5)
+    else {
+        return Err("failed to get response from eth module".to_string());
+    };
+    match serde_json::from_slice(&body) {
+        Ok(EthResponse::Response { value }) => Ok(value),
+        Ok(EthResponse::Err(e)) => Err(format!("eth module returned an error: {e:?}")),
+        _ => Err("got malformed response from eth module".to_string()),
+    }
+}
+
+/// a block as the RPC takes it: a tag as is, a decimal number as hex
+fn block(arg: Option<&String>) -> Result<Value, String> {
+    let Some(arg) = arg else {
+        return Ok(json!("latest"));
+    };
+    if arg.starts_with("0x")
+        || ["latest", "earliest", "pending", "safe", "finalized"].contains(&arg.as_str())
+    {
+        return Ok(json!(arg));
+    }
+    match arg.parse::<u64>() {
+        Ok(number) => Ok(json!(format!("{number:#x}"))),
+        Err(_) => Err(format!("invalid block {arg}\n{USAGE}")),
+    }
+}
+
+fn bytes(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// a hex quantity in decimal, if it fits in a u128
+fn decimal(hex: &str) -> Option<u128> {
+    u128::from_str_radix(hex.strip_prefix("0x").unwrap_or(hex), 16).ok()
+}
+
+fn ether(wei: u128) -> String {
+    let ether = format!("{}.{:018}", wei / 10u128.pow(18), wei % 10u128.pow(18));
+    ether
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// return data read as each of the common single return types it could be
+fn decode_return(data: &[u8]) -> Vec<String> {
+    let mut readings = vec![];
+    if data.len() == 32 {
+        let word = &data[..];
+        if word[..16].iter().all(|b| *b == 0) {
+            let mut low = [0u8; 16];
+            low.copy_from_slice(&word[16..]);
+            readings.push(format!("as uint: {}", u128::from_be_bytes(low)));
+        }
+        if word[..12].iter().all(|b| *b == 0) && word[12..].iter().any(|b| *b != 0) {
+            readings.push(format!("as address: 0x{}", to_hex(&word[12..])));
+        }
+        if word[..31].iter().all(|b| *b == 0) && word[31] <= 1 {
+            readings.push(format!("as bool: {}", word[31] == 1));
+        }
+    }
+    // a dynamic string: offset, length, then the bytes
+    if data.len() >= 64 && data[..31].iter().all(|b| *b == 0) && data[31] == 32 {
+        let len = u32::from_be_bytes(data[60..64].try_into().unwrap()) as usize;
+        if data[32..60].iter().all(|b| *b == 0) && data.len() - 64 >= len {
+            if let Ok(string) = std::str::from_utf8(&data[64..64 + len]) {
+                readings.push(format!("as string: {string:?}"));
+            }
+        }
+    }
+    readings
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn balance(chain_id: u64, address: &str, block: Value) -> Result<String, String> {
+    let value = request(chain_id, "eth_getBalance", json!([address, block]))?;
+    let Some(wei) = value.as_str().and_then(decimal) else {
+        return Err(format!("got unexpected balance {value}"));
+    };
+    Ok(format!("{address}: {} ETH ({wei} wei)", ether(wei)))
+}
+
+fn call(chain_id: u64, address: &str, calldata: &str, block: Value) -> Result<String, String> {
+    if bytes(calldata).is_none() {
+        return Err(format!("calldata must be hex\n{USAGE}"));
+    }
+    let value = request(
+        chain_id,
+        "eth_call",
+        json!([{ "to": address, "input": calldata }, block]),
+    )?;
+    let Some(data) = value.as_str() else {
+        return Err(format!("got unexpected return data {value}"));
+    };
+    let mut printout = format!(
+        "returned {} bytes: {data}",
+        bytes(data).map_or(0, |b| b.len())
+    );
+    for reading in decode_return(&bytes(data).unwrap_or_default()) {
+        printout.push_str(&format!("\r\n    {reading}"));
+    }
+    Ok(printout)
+}
+
+fn logs(chain_id: u64, filter: &str) -> Result<String, String> {
+    let filter: Value = serde_json::from_str(filter)
+        .map_err(|e| format!("filter must be a JSON object: {e}\n{USAGE}"))?;
+    let value = request(chain_id, "eth_getLogs", json!([filter]))?;
+    let Some(logs) = value.as_array() else {
+        return Err(format!("got unexpected logs {value}"));
+    };
+    let mut printout = format!("{} logs", logs.len());
+    for log in logs {
+        let field = |name: &str| log[name].as_str().unwrap_or("?").to_string();
+        printout.push_str(&format!(
+            "\r\n\x1b[1mblock {} tx {} log {}\x1b[0m from {}",
+            log["blockNumber"]
+                .as_str()
+                .and_then(decimal)
+                .map_or("?".to_string(), |n| n.to_string()),
+            field("transactionHash"),
+            log["logIndex"]
+                .as_str()
+                .and_then(decimal)
+                .map_or("?".to_string(), |n| n.to_string()),
+            field("address"),
+        ));
+        for (i, topic) in log["topics"].as_array().into_iter().flatten().enumerate() {
+            printout.push_str(&format!(
+                "\r\n    topic {i}: {}",
+                topic.as_str().unwrap_or("?")
+            ));
+        }
+        printout.push_str(&format!("\r\n    data: {}", field("data")));
+    }
+    Ok(printout)
+}
+
+script!(init, ["chain", "block"]);
+fn init(_our: Address, args: Args) -> String {
+    let chain_id = match args.flags.get("chain") {
+        Some(Some(chain)) => match chain.parse::<u64>() {
+            Ok(chain_id) => chain_id,
+            Err(_) => return format!("invalid chain id {chain}\n{USAGE}"),
+        },
+        _ => CHAIN_ID,
+    };
+    let block = match block(args.flags.get("block").and_then(Option::as_ref)) {
+        Ok(block) => block,
+        Err(e) => return e,
+    };
+    let result = match args.positional.as_slice() {
+        [verb, address] if verb == "balance" => balance(chain_id, address, block),
+        [verb, address, calldata] if verb == "call" => call(chain_id, address, calldata, block),
+        [verb, filter] if verb == "logs" => logs(chain_id, filter),
+        _ => {
+            return format!(
+                "Query the chain through the eth module: an address's balance, a contract call, or logs.\n{USAGE}"
+            )
+        }
+    };
+    result.unwrap_or_else(|e| e)
+}
@@ -0,0 +1,77 @@
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{script, Args};
+use serde::Deserialize;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m flags [on|off|reset <flag>]";
+
+// the kernel's `FeatureFlag`, which process_lib doesn't know yet
+#[derive(Deserialize)]
+struct FeatureFlag {
+    name: String,
+    description: String,
+    enabled: bool,
+    default: bool,
+    set: Option<bool>,
+    forced: bool,
+}
+
+#[derive(Deserialize)]
+enum KernelResponse {
+    FeatureFlags(Result<Vec<FeatureFlag>, String>),
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    let action = match args.positional.as_slice() {
+        [] => serde_json::json!("List"),
+        [verb, flag] if verb == "on" || verb == "off" => {
+            serde_json::json!({ "Set": { "flag": flag, "enabled": verb == "on" } })
+        }
+        [verb, flag] if verb == "reset" => serde_json::json!({ "Reset": flag }),
+        _ => {
+            return format!(
+                "List the feature flags gating kernel behaviors that are rolling out, or turn one on or off.\n{USAGE}"
+            )
+        }
+    };
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({ "FeatureFlags": action })).unwrap())
+        .send_and_await_response(5)
+        .unwrap()
+    else {
+        return "failed to get response from kernel".to_string();
+    };
+    let flags = match serde_json::from_slice::<KernelResponse>(&body) {
+        Ok(KernelResponse::FeatureFlags(Ok(flags))) => flags,
+        Ok(KernelResponse::FeatureFlags(Err(e))) => return e,
+        Err(_) => return "failed to parse kernel response".to_string(),
+    };
+
+    if flags.is_empty() {
+        return "no feature flags".to_string();
+    }
+    let mut printout = "feature flags:".to_string();
+    for flag in flags {
+        let why = if flag.forced {
+            "for this boot"
+        } else if flag.set.is_some() {
+            "set"
+        } else {
+            "default"
+        };
+        printout.push_str(&format!(
+            "\r\n    {}: {} ({why}, default {}): {}",
+            flag.name,
+            if flag.enabled { "on" } else { "off" },
+            if flag.default { "on" } else { "off" },
+            flag.description,
+        ));
+    }
+    printout
+}
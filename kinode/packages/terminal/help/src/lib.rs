@@ -5,18 +5,32 @@ wit_bindgen::generate!({
     world: "process-v0",
 });
 
-const HELP_MESSAGES: [[&str; 2]; 11] = [
+const HELP_MESSAGES: [[&str; 2]; 25] = [
     ["alias", "\n\x1b[1malias\x1b[0m <shorthand> <process_id>: create an alias for a script.\n    - Example: \x1b[1malias get_block get_block:kns_indexer:sys\x1b[0m\n    - note: all of these listed commands are just default aliases for terminal scripts."],
-    ["cat", "\n\x1b[1mcat\x1b[0m <vfs-file-path>: print the contents of a file in the terminal.\n    - Example: \x1b[1mcat /terminal:sys/pkg/scripts.json\x1b[0m"],
+    ["apps", "\n\x1b[1mapps\x1b[0m search [<words>...] [--category <category>] [--limit <n>] [--no-counts] | categories: search the apps listed onchain, best matches first, or newest first without words, or list the categories apps are in. Each result shows how many nodes have downloaded it from its publisher, unless --no-counts.\n    - Example: \x1b[1mapps search chess --category games\x1b[0m"],
+    ["bench", "\n\x1b[1mbench\x1b[0m <record|save|run> <process_id> [workload]: record the requests a process receives and replay them to measure its fuel, time, memory and blob copies per message. Measuring requires booting the node with --bench.\n    - Example: \x1b[1mbench record chess:chess:sys\x1b[0m, then \x1b[1mbench save chess:chess:sys games\x1b[0m, then \x1b[1mbench run chess:chess:sys games\x1b[0m"],
+    ["caps", "\n\x1b[1mcaps\x1b[0m [approve|deny <id>]: list the capabilities remote nodes have asked this node for, or approve or deny one. An approved capability is signed for the node that asked and sent to the process that asked; a vfs drive capability granted this way only allows reads.\n    - Example: \x1b[1mcaps\x1b[0m, then \x1b[1mcaps approve 0\x1b[0m"],
+    ["cat", "\n\x1b[1mcat\x1b[0m <vfs-file-path>: print the contents of a file in the terminal. with no path, print what the previous stage of a pipeline gave.\n    - Example: \x1b[1mcat /terminal:sys/pkg/scripts.json\x1b[0m"],
+    ["define", "\n\x1b[1mdefine\x1b[0m [<name> \"<pipeline>\" | --remove <name> | --cron <expression|off> <name> | --export <path> | --import <path> [--confirm <code>]]: list the pipelines saved as services, or define one to run by typing its name. A service can be run on a cron schedule, and services exported to a file in a terminal:sys drive to share, or imported from one. An import first shows what it would define, and a code to run it again with to confirm.\n    - Example: \x1b[1mdefine todo \"cat /notes:me/pkg/todo.md\"\x1b[0m, then \x1b[1mdefine --cron @daily todo\x1b[0m"],
     ["echo", "\n\x1b[1mecho\x1b[0m <text>: print text to the terminal.\n    - Example: \x1b[1mecho foo\x1b[0m"],
+    ["eth", "\n\x1b[1meth\x1b[0m [--chain <id>] [--block <number|tag>] <balance <address> | call <address> <calldata> | logs <filter-json>>: query the chain through this node's eth providers, to check they work without writing a package. Shows balances in ETH, reads call return data as the common return types, and lists each log's block, transaction, topics and data. The chain is Optimism unless given.\n    - Example: \x1b[1meth logs '{\"address\":\"0x...\",\"fromBlock\":\"0x7a1200\"}'\x1b[0m"],
+    ["flags", "\n\x1b[1mflags\x1b[0m [on|off|reset <flag>]: list the feature flags gating kernel behaviors that are rolling out, or turn one on or off for this node, until reset to its default. \x1b[1m--feature <flag>\x1b[0m turns one on for a single boot.\n    - Example: \x1b[1mflags on cap-feedback\x1b[0m"],
     ["hi", "\n\x1b[1mhi\x1b[0m <name> <string>: send a text message to another node's command line.\n    - Example: \x1b[1mhi mothu.kino hello world\x1b[0m"],
     ["kfetch", "\n\x1b[1mkfetch\x1b[0m: print system information a la neofetch. No arguments."],
     ["kill", "\n\x1b[1mkill\x1b[0m <process-id>: terminate a running process. This will bypass any restart behavior–use judiciously.\n    - Example: \x1b[1mkill chess:chess:sys\x1b[0m"],
-    ["m", "\n\x1b[1mm\x1b[0m <address> '<json>': send an inter-process message. <address> is formatted as <node>@<process_id>. <process_id> is formatted as <process_name>:<package_name>:<publisher_node>. JSON containing spaces must be wrapped in single-quotes (\x1b[1m''\x1b[0m).\n    - Example: \x1b[1mm our@eth:distro:sys \"SetPublic\" -a 5\x1b[0m\n    - the '-a' flag is used to expect a response with a given timeout\n    - \x1b[1mour\x1b[0m will always be interpolated by the system as your node's name"],
+    ["lat", "\n\x1b[1mlat\x1b[0m [<process_id>]: show how long the kernel and each runtime module have taken to handle messages since boot: how many, the mean, p50, p99 and max.\n    - Example: \x1b[1mlat vfs:distro:sys\x1b[0m"],
+    ["m", "\n\x1b[1mm\x1b[0m <address> '<json>': send an inter-process message. <address> is formatted as <node>@<process_id>. <process_id> is formatted as <process_name>:<package_name>:<publisher_node>. JSON containing spaces must be wrapped in single-quotes (\x1b[1m''\x1b[0m).\n    - Example: \x1b[1mm our@eth:distro:sys \"SetPublic\" -a 5\x1b[0m\n    - the '-a' flag is used to expect a response with a given timeout\n    - with no body, the body is what the previous stage of a pipeline gave, as in \x1b[1mcat /app:sys/req.json | m our@app:app:sys\x1b[0m\n    - \x1b[1mour\x1b[0m will always be interpolated by the system as your node's name"],
     ["net_diagnostics", "\n\x1b[1mnet_diagnostics\x1b[0m: print some useful networking diagnostic data."],
+    ["notify", "\n\x1b[1mnotify\x1b[0m [dismiss [<id>] | forwards | forward <package_id> webhook <url> | forward <package_id> email <smtp_url> <from> <to> | forward <package_id> off]: list the notifications processes have posted, unread ones starred, mark one or all read, or set where a package's notifications are forwarded. Forwarding adds to what is set for the package; off stops it.\n    - Example: \x1b[1mnotify forward chess:sys webhook https://example.com/hook\x1b[0m"],
+    ["pending", "\n\x1b[1mpending\x1b[0m <process_id>: show the requests a process is waiting on responses to, how long ago each was sent and when it times out, and how many messages wait for the process.\n    - Example: \x1b[1mpending chess:chess:sys\x1b[0m"],
     ["peer", "\n\x1b[1mpeer\x1b[0m <name>: print the peer's PKI info, if it exists."],
     ["peers", "\n\x1b[1mpeers\x1b[0m: print the peers the node currently hold connections with."],
+    ["perms", "\n\x1b[1mperms\x1b[0m [<package_id>]: show what installed packages can do: the capabilities each process holds, the HTTP paths it has bound, its use of the network since boot, and the drives the package can reach.\n    - Example: \x1b[1mperms chess:sys\x1b[0m"],
+    ["router", "\n\x1b[1mrouter\x1b[0m [quota <routed|passthroughs|daily-bytes> <limit|off> | deny <node> | allow <node> | disconnect <node>]: for a node that routes for others, show the nodes it has served and what it relayed for each, or set a quota, deny or allow a node, or drop its connections. Quotas and denied nodes are kept across restarts.\n    - Example: \x1b[1mrouter quota passthroughs 8\x1b[0m"],
+    ["schedules", "\n\x1b[1mschedules\x1b[0m [enable <id> | disable <id>]: list the requests packages have scheduled in their manifests, when each is next sent and when it last was, or enable or disable one.\n    - Example: \x1b[1mschedules disable blog:kino_updates:sys#0\x1b[0m"],
+    ["sync", "\n\x1b[1msync\x1b[0m [add <drive> <node> [newest|ours|theirs|keep-both] | remove <drive> <node> | now <drive> <node>]: list the drives mirrored with other nodes you own, or add, remove or sync one. A drive is mirrored once both nodes add each other; a file changed on both since they last synced is settled by the conflict policy, newest by default.\n    - Example: \x1b[1msync add /chess:sys/games other-node.os keep-both\x1b[0m"],
     ["top", "\n\x1b[1mtop\x1b[0m <process_id>: display kernel debugging info about a process. Leave the process ID blank to display info about all processes and get the total number of running processes.\n    - Example: \x1b[1mtop net:distro:sys\x1b[0m\n    - Example: \x1b[1mtop\x1b[0m"],
+    ["users", "\n\x1b[1musers\x1b[0m [add <name> <guest|member|admin> | remove <name>]: list the users that can log in to the node over HTTP, or add, change or remove one. Adding asks for the user's password, which is not shown as typed. Members reach apps, guests only what apps open to guests, admins everything including the web terminal and what apps open to admins only. Users log in with their name and password; the node's owner with no name.\n    - Example: \x1b[1musers add alice member\x1b[0m"],
 ];
 
 script!(init);
@@ -37,7 +51,9 @@ fn init(_our: Address, args: String) -> String {
         }
 
         help_message.push_str(
-            "For more help, look to the documentation at book.kinode.org.\n\
+            "\nPrefix any command with \x1b[1m--dry-run\x1b[0m to see the requests it would send \
+            without sending them. It may read the VFS, but not write to it or use the network.\n\
+            For more help, look to the documentation at book.kinode.org.\n\
             ============================================================\n",
         );
 
@@ -0,0 +1,115 @@
+use kinode_process_lib::{Address, Message, ProcessId, Request};
+use script_args::{script, Args};
+use serde::Deserialize;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m lat [<process_id>]";
+
+// the kernel's `LatencyHistogram`, which process_lib doesn't know yet
+#[derive(Deserialize)]
+struct LatencyHistogram {
+    stage: ProcessId,
+    bounds_micros: Vec<u64>,
+    counts: Vec<u64>,
+    count: u64,
+    sum_micros: u64,
+    max_micros: u64,
+}
+
+#[derive(Deserialize)]
+enum KernelResponse {
+    Debug(KernelPrintResponse),
+}
+
+#[derive(Deserialize)]
+enum KernelPrintResponse {
+    Latency(Vec<LatencyHistogram>),
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    let stage = match args.positional.as_slice() {
+        [] => None,
+        [stage] => match stage.parse::<ProcessId>() {
+            Ok(stage) => Some(stage),
+            Err(e) => return format!("invalid process id: {e}\n{USAGE}"),
+        },
+        _ => {
+            return format!(
+                "Show how long the kernel and each runtime module have taken to handle messages since boot.\n{USAGE}"
+            )
+        }
+    };
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({ "Debug": "Latency" })).unwrap())
+        .send_and_await_response(5)
+        .unwrap()
+    else {
+        return "failed to get response from kernel".to_string();
+    };
+    let Ok(KernelResponse::Debug(KernelPrintResponse::Latency(histograms))) =
+        serde_json::from_slice::<KernelResponse>(&body)
+    else {
+        return "failed to parse kernel response".to_string();
+    };
+
+    let histograms: Vec<LatencyHistogram> = histograms
+        .into_iter()
+        .filter(|histogram| {
+            stage
+                .as_ref()
+                .map_or(true, |stage| histogram.stage == *stage)
+        })
+        .filter(|histogram| histogram.count > 0)
+        .collect();
+    if histograms.is_empty() {
+        return "no latencies recorded".to_string();
+    }
+    let mut printout = "latencies since boot (p50 and p99 to their bucket):".to_string();
+    for histogram in histograms {
+        printout.push_str(&format!(
+            "\r\n    {}: {} handled, mean {}, p50 {}, p99 {}, max {}",
+            histogram.stage,
+            histogram.count,
+            display_micros(histogram.sum_micros / histogram.count),
+            display_quantile(&histogram, 0.5),
+            display_quantile(&histogram, 0.99),
+            display_micros(histogram.max_micros),
+        ));
+    }
+    printout
+}
+
+/// the upper bound of the bucket holding the quantile `q`
+fn display_quantile(histogram: &LatencyHistogram, q: f64) -> String {
+    let rank = (histogram.count as f64 * q).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (i, count) in histogram.counts.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return match histogram.bounds_micros.get(i) {
+                Some(bound) => format!("≤{}", display_micros(*bound)),
+                None => format!(
+                    ">{}",
+                    display_micros(*histogram.bounds_micros.last().unwrap_or(&0))
+                ),
+            };
+        }
+    }
+    "?".to_string()
+}
+
+fn display_micros(micros: u64) -> String {
+    if micros < 1_000 {
+        format!("{micros}µs")
+    } else if micros < 1_000_000 {
+        format!("{:.1}ms", micros as f64 / 1_000.0)
+    } else {
+        format!("{:.2}s", micros as f64 / 1_000_000.0)
+    }
+}
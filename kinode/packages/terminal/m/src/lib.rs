@@ -1,69 +1,42 @@
-use clap::{Arg, Command};
-use kinode_process_lib::{println, script, Address, Request, SendErrorKind};
-use regex::Regex;
+use kinode_process_lib::{println, Address, Request, SendErrorKind};
+use script_args::{script, Args};
 
 wit_bindgen::generate!({
     path: "target/wit",
     world: "process-v0",
 });
 
-const USAGE: &str = "\x1b[1mUsage:\x1b[0m m <target> <body> [-a <await_time>]";
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m m <target> <body> [-a <await_time>], or <command> | m <target> [-a <await_time>]";
 
-script!(init);
-fn init(_our: Address, args: String) -> String {
-    if args.is_empty() {
+script!(init, ["a", "await"]);
+fn init(_our: Address, args: Args) -> String {
+    if args.positional.is_empty() {
         return format!("Send a request to a process.\n{USAGE}");
     }
 
-    let mut args: Vec<String> = Regex::new(r#"'[^']*'|\S+"#)
-        .unwrap()
-        .find_iter(&args)
-        .map(|mat| {
-            let match_str = mat.as_str();
-            // Remove the surrounding single quotes for the JSON string
-            if match_str.starts_with('\'') && match_str.ends_with('\'') {
-                match_str[1..match_str.len() - 1].to_string()
-            } else {
-                match_str.to_string()
-            }
-        })
-        .collect();
-
-    args.insert(0, "m".to_string());
-
-    let Ok(parsed) = Command::new("m")
-        .disable_help_flag(true)
-        .arg(Arg::new("target").index(1).required(true))
-        .arg(Arg::new("body").index(2).required(true))
-        .arg(
-            Arg::new("await")
-                .short('a')
-                .long("await")
-                .value_parser(clap::value_parser!(u64)),
-        )
-        .try_get_matches_from(args)
-    else {
-        return format!("Failed to parse args.\n{USAGE}");
-    };
-
-    let Some(target) = parsed.get_one::<String>("target") else {
-        return format!("No target given.\n{USAGE}");
+    // in a pipeline, the body may be what the previous stage gave instead
+    let (target, body) = match (args.positional.as_slice(), &args.stdin) {
+        ([target, body], _) => (target, body.as_bytes().to_vec()),
+        ([target], Some(stdin)) => (target, stdin.bytes.clone()),
+        _ => return format!("Failed to parse args.\n{USAGE}"),
     };
 
     let Ok(target) = target.parse::<Address>() else {
         return format!("Invalid address: \"{target}\"\n{USAGE}");
     };
 
-    let Some(body) = parsed.get_one::<String>("body") else {
-        return format!("No body given.\n{USAGE}");
+    let await_time = match args.value(&["a", "await"]).map(str::parse::<u64>) {
+        None => None,
+        Some(Ok(s)) => Some(s),
+        Some(Err(_)) => return format!("Invalid await time.\n{USAGE}"),
     };
 
-    let req = Request::new().target(target).body(body.as_bytes().to_vec());
+    let req = Request::new().target(target).body(body);
 
-    match parsed.get_one::<u64>("await") {
+    match await_time {
         Some(s) => {
             println!("Awaiting response for {s}s");
-            match req.send_and_await_response(*s).unwrap() {
+            match req.send_and_await_response(s).unwrap() {
                 Ok(res) => String::from_utf8_lossy(res.body()).to_string(),
                 Err(e) => {
                     format!(
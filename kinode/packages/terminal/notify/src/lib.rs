@@ -0,0 +1,151 @@
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{script, Args};
+use serde::Deserialize;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m notify [dismiss [<id>] | forwards | forward <package_id> webhook <url> | forward <package_id> email <smtp_url> <from> <to> | forward <package_id> off]";
+
+// the notify module's responses, which process_lib doesn't know yet
+#[derive(Deserialize)]
+enum NotifyResponse {
+    Ok,
+    Notifications(Vec<PostedNotification>),
+    Forwards(Vec<(serde_json::Value, Vec<serde_json::Value>)>),
+    Err(serde_json::Value),
+}
+
+#[derive(Deserialize)]
+struct PostedNotification {
+    id: u64,
+    source: String,
+    read: bool,
+    notification: Notification,
+}
+
+#[derive(Deserialize)]
+struct Notification {
+    title: String,
+    body: String,
+    urgency: String,
+    action: Option<String>,
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    let request = match args.positional.as_slice() {
+        [] => serde_json::json!("List"),
+        [verb] if verb == "dismiss" => serde_json::json!({ "Dismiss": { "id": null } }),
+        [verb, id] if verb == "dismiss" => {
+            let Ok(id) = id.parse::<u64>() else {
+                return format!("{id} is not a notification id\n{USAGE}");
+            };
+            serde_json::json!({ "Dismiss": { "id": id } })
+        }
+        [verb] if verb == "forwards" => serde_json::json!("GetForwards"),
+        [verb, package_id, rest @ ..] if verb == "forward" => {
+            let Some((package_name, publisher_node)) = package_id.split_once(':') else {
+                return format!("{package_id} is not a package id\n{USAGE}");
+            };
+            let package_id = serde_json::json!({
+                "package_name": package_name,
+                "publisher_node": publisher_node,
+            });
+            let forward = match rest {
+                [kind] if kind == "off" => None,
+                [kind, url] if kind == "webhook" => {
+                    Some(serde_json::json!({ "Webhook": { "url": url } }))
+                }
+                [kind, smtp_url, from, to] if kind == "email" => Some(
+                    serde_json::json!({ "Email": { "smtp_url": smtp_url, "from": from, "to": to } }),
+                ),
+                _ => return USAGE.to_string(),
+            };
+            // forwards are set per package as a whole: add to those set before
+            let mut forwards = vec![];
+            if let Some(forward) = forward {
+                match ask(&serde_json::json!("GetForwards")) {
+                    Ok(NotifyResponse::Forwards(all)) => {
+                        if let Some((_, set)) = all.into_iter().find(|(p, _)| p == &package_id) {
+                            forwards = set;
+                        }
+                    }
+                    Ok(NotifyResponse::Err(e)) => return format!("notify: {e}"),
+                    _ => return "failed to get forwards from notify".to_string(),
+                }
+                if !forwards.contains(&forward) {
+                    forwards.push(forward);
+                }
+            }
+            serde_json::json!({ "SetForwards": { "package_id": package_id, "forwards": forwards } })
+        }
+        _ => {
+            return format!(
+                "List the notifications processes have posted, mark them read, or set where a package's notifications are forwarded.\n{USAGE}"
+            )
+        }
+    };
+
+    match ask(&request) {
+        Ok(NotifyResponse::Ok) => "done".to_string(),
+        Ok(NotifyResponse::Err(e)) => format!("notify: {e}"),
+        Ok(NotifyResponse::Notifications(notifications)) if notifications.is_empty() => {
+            "no notifications".to_string()
+        }
+        Ok(NotifyResponse::Notifications(notifications)) => {
+            let unread = notifications.iter().filter(|n| !n.read).count();
+            let mut printout = format!("{} notifications, {unread} unread:", notifications.len());
+            for posted in notifications {
+                let n = posted.notification;
+                printout.push_str(&format!(
+                    "\r\n{} {}: {} ({}, {})",
+                    if posted.read { " " } else { "*" },
+                    posted.id,
+                    n.title,
+                    posted.source,
+                    n.urgency,
+                ));
+                if !n.body.is_empty() {
+                    printout.push_str(&format!("\r\n      {}", n.body));
+                }
+                if let Some(action) = n.action {
+                    printout.push_str(&format!("\r\n      -> {action}"));
+                }
+            }
+            printout
+        }
+        Ok(NotifyResponse::Forwards(all)) if all.is_empty() => {
+            "no notifications forwarded".to_string()
+        }
+        Ok(NotifyResponse::Forwards(all)) => {
+            let mut printout = "notifications forwarded:".to_string();
+            for (package_id, forwards) in all {
+                printout.push_str(&format!(
+                    "\r\n    {}:{}",
+                    package_id["package_name"].as_str().unwrap_or_default(),
+                    package_id["publisher_node"].as_str().unwrap_or_default(),
+                ));
+                for forward in forwards {
+                    printout.push_str(&format!("\r\n        {forward}"));
+                }
+            }
+            printout
+        }
+        Err(e) => e,
+    }
+}
+
+fn ask(request: &serde_json::Value) -> Result<NotifyResponse, String> {
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "notify", "distro", "sys"))
+        .body(serde_json::to_vec(request).unwrap())
+        .send_and_await_response(5)
+        .unwrap()
+    else {
+        return Err("failed to get response from notify".to_string());
+    };
+    serde_json::from_slice::<NotifyResponse>(&body)
+        .map_err(|_| "failed to parse notify response".to_string())
+}
@@ -0,0 +1,77 @@
+use kinode_process_lib::{Address, Message, ProcessId, Request};
+use script_args::{script, Args};
+use serde::Deserialize;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m pending <process_id>";
+
+// the kernel's `PendingInfo`, which process_lib doesn't know yet
+#[derive(Deserialize)]
+struct PendingInfo {
+    requests: Vec<PendingRequest>,
+    mailbox: usize,
+}
+
+#[derive(Deserialize)]
+struct PendingRequest {
+    id: u64,
+    target: Address,
+    age_ms: u64,
+    timeout_remaining_ms: u64,
+}
+
+#[derive(Deserialize)]
+enum KernelResponse {
+    Debug(KernelPrintResponse),
+}
+
+#[derive(Deserialize)]
+enum KernelPrintResponse {
+    Pending(Option<PendingInfo>),
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    let [process] = args.positional.as_slice() else {
+        return format!("Show the requests a process is waiting on.\n{USAGE}");
+    };
+    let Ok(process) = process.parse::<ProcessId>() else {
+        return format!("invalid process id {process}\n{USAGE}");
+    };
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(&serde_json::json!({ "Debug": { "Pending": process } })).unwrap())
+        .send_and_await_response(5)
+        .unwrap()
+    else {
+        return "failed to get response from kernel".to_string();
+    };
+    let Ok(KernelResponse::Debug(KernelPrintResponse::Pending(info))) =
+        serde_json::from_slice::<KernelResponse>(&body)
+    else {
+        return "failed to parse kernel response".to_string();
+    };
+    let Some(info) = info else {
+        return format!("{process} is not running, or pending lacks the capability to inspect it");
+    };
+
+    let mut printout = format!(
+        "{process}: {} outstanding requests, {} messages waiting",
+        info.requests.len(),
+        info.mailbox
+    );
+    for request in info.requests {
+        printout.push_str(&format!(
+            "\r\n    {} to {}: sent {:.1}s ago, times out in {:.1}s",
+            request.id,
+            request.target,
+            request.age_ms as f64 / 1000.0,
+            request.timeout_remaining_ms as f64 / 1000.0,
+        ));
+    }
+    printout
+}
@@ -0,0 +1,130 @@
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{script, Args};
+use serde::{Deserialize, Serialize};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m router [quota <routed|passthroughs|daily-bytes> <limit|off> | deny <node> | allow <node> | disconnect <node>]";
+
+// the router's part of the networking protocol, as the runtime defines it.
+// the runtime's types crate can't be built for wasm, so its file is included.
+#[allow(dead_code)]
+#[path = "../../../../../lib/src/router.rs"]
+mod router;
+use router::{RouterAction, RouterStats};
+
+// net's envelope for router actions, which process_lib doesn't know yet.
+// the variants before it are named so that it is encoded as net expects.
+#[allow(dead_code)]
+#[derive(Serialize)]
+enum NetAction {
+    ConnectionRequest,
+    KnsUpdate,
+    KnsBatchUpdate,
+    GetPeers,
+    GetPeer,
+    GetDiagnostics,
+    Sign,
+    Verify,
+    GetTime,
+    Router(RouterAction),
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+enum NetResponse {
+    Accepted,
+    Rejected,
+    Peers,
+    Peer,
+    Diagnostics,
+    Signed,
+    Verified,
+    Time,
+    Router(Result<RouterStats, String>),
+}
+
+fn send(action: RouterAction) -> Result<RouterStats, String> {
+    let Ok(Ok(Message::Response { body, .. })) = Request::to(("our", "net", "distro", "sys"))
+        .body(rmp_serde::to_vec(&NetAction::Router(action)).unwrap())
+        .send_and_await_response(5)
+    else {
+        return Err("failed to get response from networking module".to_string());
+    };
+    match rmp_serde::from_slice(&body) {
+        Ok(NetResponse::Router(result)) => result,
+        _ => Err("got malformed response from networking module".to_string()),
+    }
+}
+
+fn limit(limit: &Option<impl ToString>) -> String {
+    limit
+        .as_ref()
+        .map_or("unlimited".to_string(), ToString::to_string)
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    let action = match args.positional.as_slice() {
+        [] => RouterAction::GetStats,
+        [verb, quota, value] if verb == "quota" => {
+            let mut quotas = match send(RouterAction::GetStats) {
+                Ok(stats) => stats.quotas,
+                Err(e) => return e,
+            };
+            let value = if value == "off" {
+                None
+            } else {
+                match value.parse::<u64>() {
+                    Ok(value) => Some(value),
+                    Err(_) => return format!("invalid limit {value}\n{USAGE}"),
+                }
+            };
+            match quota.as_str() {
+                "routed" => quotas.max_routed = value.map(|v| v as u32),
+                "passthroughs" => quotas.max_passthroughs = value.map(|v| v as u32),
+                "daily-bytes" => quotas.max_daily_bytes = value,
+                _ => return format!("unknown quota {quota}\n{USAGE}"),
+            }
+            RouterAction::SetQuotas(quotas)
+        }
+        [verb, node] if verb == "deny" => RouterAction::DenyClient(node.clone()),
+        [verb, node] if verb == "allow" => RouterAction::AllowClient(node.clone()),
+        [verb, node] if verb == "disconnect" => RouterAction::DisconnectClient(node.clone()),
+        _ => {
+            return format!(
+                "Show the nodes this node routes for, or set their quotas, deny, allow or disconnect one.\n{USAGE}"
+            )
+        }
+    };
+    let stats = match send(action) {
+        Ok(stats) => stats,
+        Err(e) => return e,
+    };
+
+    let mut printout = format!(
+        "served {} clients, relayed {} bytes\r\nquotas: routed {}, passthroughs {}, daily bytes {}",
+        stats.clients_served,
+        stats.bytes_relayed,
+        limit(&stats.quotas.max_routed),
+        limit(&stats.quotas.max_passthroughs),
+        limit(&stats.quotas.max_daily_bytes),
+    );
+    if !stats.denied.is_empty() {
+        printout.push_str(&format!("\r\ndenied: {}", stats.denied.join(", ")));
+    }
+    for client in stats.clients {
+        printout.push_str(&format!(
+            "\r\n    {}{}: {} passthroughs, {} bytes today, {} in all",
+            client.name,
+            if client.routed { " (routed)" } else { "" },
+            client.passthroughs,
+            client.bytes_today,
+            client.bytes_relayed,
+        ));
+    }
+    printout
+}
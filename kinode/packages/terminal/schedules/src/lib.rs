@@ -0,0 +1,100 @@
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{
+    script,
+    style::{self, Color},
+    Args,
+};
+use serde::Deserialize;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m schedules [enable <id> | disable <id>]";
+
+// the scheduler's responses
+#[derive(Deserialize)]
+enum SchedulerResponse {
+    Ok,
+    Schedules(Vec<ScheduleInfo>),
+    Err(String),
+}
+
+#[derive(Deserialize)]
+struct ScheduleInfo {
+    id: String,
+    cron: String,
+    enabled: bool,
+    next_fire: Option<u64>,
+    last_fired: Option<u64>,
+}
+
+/// how far from now a time in milliseconds is, roughly
+fn relative(at: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let span = |ms: u64| {
+        let minutes = ms / 60_000;
+        match (minutes / (24 * 60), minutes / 60 % 24, minutes % 60) {
+            (0, 0, minutes) => format!("{minutes}m"),
+            (0, hours, minutes) => format!("{hours}h{minutes}m"),
+            (days, hours, _) => format!("{days}d{hours}h"),
+        }
+    };
+    if at >= now {
+        format!("in {}", span(at - now))
+    } else {
+        format!("{} ago", span(now - at))
+    }
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    let request = match args.positional.as_slice() {
+        [] => serde_json::json!("List"),
+        [verb, id] if verb == "enable" => serde_json::json!({ "Enable": { "id": id } }),
+        [verb, id] if verb == "disable" => serde_json::json!({ "Disable": { "id": id } }),
+        _ => {
+            return format!(
+                "List the requests packages have scheduled in their manifests, or enable or disable one.\n{USAGE}"
+            )
+        }
+    };
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "scheduler", "scheduler", "sys"))
+        .body(serde_json::to_vec(&request).unwrap())
+        .send_and_await_response(10)
+        .unwrap()
+    else {
+        return "failed to get response from scheduler".to_string();
+    };
+    match serde_json::from_slice::<SchedulerResponse>(&body) {
+        Ok(SchedulerResponse::Ok) => "done".to_string(),
+        Ok(SchedulerResponse::Err(e)) => format!("scheduler: {e}"),
+        Ok(SchedulerResponse::Schedules(schedules)) if schedules.is_empty() => {
+            "no schedules".to_string()
+        }
+        Ok(SchedulerResponse::Schedules(schedules)) => {
+            let rows: Vec<Vec<String>> = schedules
+                .into_iter()
+                .map(|schedule| {
+                    vec![
+                        schedule.id,
+                        schedule.cron,
+                        match (schedule.enabled, schedule.next_fire) {
+                            (false, _) => style::color("disabled", Color::Yellow),
+                            (true, Some(next_fire)) => relative(next_fire),
+                            (true, None) => style::dim("never"),
+                        },
+                        schedule.last_fired.map_or(style::dim("never"), relative),
+                    ]
+                })
+                .collect();
+            style::table(&["schedule", "cron", "next", "last sent"], &rows)
+        }
+        Err(_) => "failed to parse scheduler response".to_string(),
+    }
+}
@@ -0,0 +1,90 @@
+use kinode_process_lib::{Address, Message, Request};
+use script_args::{script, Args};
+use serde::Deserialize;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m sync [add <drive> <node> [newest|ours|theirs|keep-both] | remove <drive> <node> | now <drive> <node>]";
+
+// the sync module's responses, which process_lib doesn't know yet
+#[derive(Deserialize)]
+enum SyncResponse {
+    Ok,
+    Mirrors(Vec<MirrorInfo>),
+    Err(serde_json::Value),
+}
+
+#[derive(Deserialize)]
+struct MirrorInfo {
+    drive: String,
+    peer: String,
+    peer_key: Option<String>,
+    policy: String,
+    last_sync: Option<u64>,
+    last_error: Option<String>,
+}
+
+script!(init);
+fn init(_our: Address, args: Args) -> String {
+    let request = match args.positional.as_slice() {
+        [] => serde_json::json!("ListMirrors"),
+        [verb, drive, peer, rest @ ..] if verb == "add" && rest.len() <= 1 => {
+            let policy = match rest.first().map(String::as_str) {
+                None | Some("newest") => "Newest",
+                Some("ours") => "Ours",
+                Some("theirs") => "Theirs",
+                Some("keep-both") => "KeepBoth",
+                Some(other) => return format!("unknown conflict policy {other}\n{USAGE}"),
+            };
+            serde_json::json!({ "AddMirror": { "drive": drive, "peer": peer, "policy": policy } })
+        }
+        [verb, drive, peer] if verb == "remove" => {
+            serde_json::json!({ "RemoveMirror": { "drive": drive, "peer": peer } })
+        }
+        [verb, drive, peer] if verb == "now" => {
+            serde_json::json!({ "SyncNow": { "drive": drive, "peer": peer } })
+        }
+        _ => {
+            return format!(
+                "List the drives mirrored with other nodes, or add, remove or sync one. Each node must add the other.\n{USAGE}"
+            )
+        }
+    };
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "sync", "distro", "sys"))
+        .body(serde_json::to_vec(&request).unwrap())
+        .send_and_await_response(60)
+        .unwrap()
+    else {
+        return "failed to get response from sync".to_string();
+    };
+    match serde_json::from_slice::<SyncResponse>(&body) {
+        Ok(SyncResponse::Ok) => "done".to_string(),
+        Ok(SyncResponse::Err(e)) => format!("sync: {e}"),
+        Ok(SyncResponse::Mirrors(mirrors)) if mirrors.is_empty() => {
+            "no drives mirrored".to_string()
+        }
+        Ok(SyncResponse::Mirrors(mirrors)) => {
+            let mut printout = format!("{} drives mirrored:", mirrors.len());
+            for mirror in mirrors {
+                printout.push_str(&format!(
+                    "\r\n    {} with {} (key {}, {}): {}",
+                    mirror.drive,
+                    mirror.peer,
+                    mirror.peer_key.as_deref().unwrap_or("not yet noted"),
+                    mirror.policy,
+                    match (mirror.last_error, mirror.last_sync) {
+                        (Some(e), _) => format!("failing: {e}"),
+                        (None, Some(secs)) => format!("last synced at {secs}"),
+                        (None, None) => "not synced yet".to_string(),
+                    }
+                ));
+            }
+            printout
+        }
+        Err(_) => "failed to parse sync response".to_string(),
+    }
+}
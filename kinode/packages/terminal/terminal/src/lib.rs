@@ -1,27 +1,61 @@
 use kinode_process_lib::{
     await_message, call_init, get_typed_state, kernel_types as kt, our_capabilities, println,
-    set_state, vfs, Address, Capability, Message, ProcessId, Request,
+    set_state, vfs, Address, Capability, LazyLoadBlob, Message, ProcessId, Request, Response,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+mod services;
 
 wit_bindgen::generate!({
     path: "target/wit",
     world: "process-v0",
 });
 
+/// how long a stage of a pipeline other than the last has to give its output
+const PIPE_STAGE_TIMEOUT: u64 = 30;
+
 #[derive(Debug, Serialize, Deserialize)]
 enum TerminalAction {
     EditAlias {
         alias: String,
         process: Option<ProcessId>,
     },
+    ListServices,
+    /// define a service, or with no pipeline, remove it
+    EditService {
+        name: String,
+        pipeline: Option<String>,
+    },
+    /// run a service on a cron schedule, or with none, stop
+    ScheduleService {
+        name: String,
+        cron: Option<String>,
+    },
+    /// as the scheduler asks when a scheduled service is due
+    RunService {
+        name: String,
+    },
+    /// write every service to a file in a drive of the package asking, to share
+    ExportServices {
+        path: String,
+    },
+    /// add the services in a file written by `ExportServices`, in a drive of
+    /// the package asking. without the `confirm` code shown for the file as
+    /// it is, only shows what it would do.
+    ImportServices {
+        path: String,
+        #[serde(default)]
+        confirm: Option<String>,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 enum ScriptError {
     UnknownName,
-    FailedToReadWasm,
+    FailedToStart,
+    InvalidPipeline,
+    NoOutput,
     NoScriptsManifest,
     NoScriptInManifest,
     InvalidScriptsManifest,
@@ -35,7 +69,9 @@ impl std::fmt::Display for ScriptError {
             "{}",
             match self {
                 ScriptError::UnknownName => "script not found, either as an alias or process ID",
-                ScriptError::FailedToReadWasm => "failed to read script Wasm from VFS",
+                ScriptError::FailedToStart => "kernel failed to start script",
+                ScriptError::InvalidPipeline => "empty stage or unclosed quote in pipeline",
+                ScriptError::NoOutput => "script in pipeline gave no output",
                 ScriptError::NoScriptsManifest => "no scripts manifest in package",
                 ScriptError::NoScriptInManifest => "script not in scripts.json file",
                 ScriptError::InvalidScriptsManifest => "could not parse scripts.json file",
@@ -47,10 +83,53 @@ impl std::fmt::Display for ScriptError {
 
 impl std::error::Error for ScriptError {}
 
+/// the limits a script's entry in scripts.json may set on the resources it
+/// uses, which may only tighten those the node holds every script to.
+/// process_lib's `DotScriptsEntry` predates them, so they are parsed
+/// separately.
+#[derive(Default, Deserialize)]
+struct ScriptLimits {
+    #[serde(default)]
+    limits: ProcessLimits,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProcessLimits {
+    fuel: Option<u64>,
+    memory_bytes: Option<u64>,
+    wall_time_secs: Option<u64>,
+}
+
+/// the kernel commands process_lib's `KernelCommand` doesn't have yet, as the
+/// kernel parses them
+#[derive(Debug, Serialize, Deserialize)]
+enum KernelCommand {
+    RunTransient {
+        id: ProcessId,
+        wasm_bytes_handle: String,
+        wit_version: Option<u32>,
+        initial_capabilities: HashSet<kt::Capability>,
+        public: bool,
+        args: Vec<u8>,
+        capture_output: bool,
+        dry_run: bool,
+        limits: ProcessLimits,
+    },
+}
+
 #[derive(Serialize, Deserialize)]
 struct TerminalState {
     our: Address,
     aliases: HashMap<String, ProcessId>,
+    /// pipelines saved under a name
+    services: BTreeMap<String, services::Service>,
+}
+
+/// the state as it was before services, to read it after an upgrade
+#[derive(Deserialize)]
+struct TerminalStateV0 {
+    our: Address,
+    aliases: HashMap<String, ProcessId>,
 }
 
 impl TerminalState {
@@ -63,14 +142,38 @@ impl TerminalState {
                     "alias".to_string(),
                     ProcessId::new(Some("alias"), "terminal", "sys"),
                 ),
+                (
+                    "apps".to_string(),
+                    ProcessId::new(Some("apps"), "app_store", "sys"),
+                ),
+                (
+                    "bench".to_string(),
+                    ProcessId::new(Some("bench"), "terminal", "sys"),
+                ),
+                (
+                    "caps".to_string(),
+                    ProcessId::new(Some("caps"), "terminal", "sys"),
+                ),
                 (
                     "cat".to_string(),
                     ProcessId::new(Some("cat"), "terminal", "sys"),
                 ),
+                (
+                    "define".to_string(),
+                    ProcessId::new(Some("define"), "terminal", "sys"),
+                ),
                 (
                     "echo".to_string(),
                     ProcessId::new(Some("echo"), "terminal", "sys"),
                 ),
+                (
+                    "eth".to_string(),
+                    ProcessId::new(Some("eth"), "terminal", "sys"),
+                ),
+                (
+                    "flags".to_string(),
+                    ProcessId::new(Some("flags"), "terminal", "sys"),
+                ),
                 (
                     "help".to_string(),
                     ProcessId::new(Some("help"), "terminal", "sys"),
@@ -87,6 +190,10 @@ impl TerminalState {
                     "kfetch".to_string(),
                     ProcessId::new(Some("kfetch"), "terminal", "sys"),
                 ),
+                (
+                    "lat".to_string(),
+                    ProcessId::new(Some("lat"), "terminal", "sys"),
+                ),
                 (
                     "m".to_string(),
                     ProcessId::new(Some("m"), "terminal", "sys"),
@@ -95,6 +202,14 @@ impl TerminalState {
                     "net_diagnostics".to_string(),
                     ProcessId::new(Some("net_diagnostics"), "terminal", "sys"),
                 ),
+                (
+                    "notify".to_string(),
+                    ProcessId::new(Some("notify"), "terminal", "sys"),
+                ),
+                (
+                    "pending".to_string(),
+                    ProcessId::new(Some("pending"), "terminal", "sys"),
+                ),
                 (
                     "peer".to_string(),
                     ProcessId::new(Some("peer"), "terminal", "sys"),
@@ -103,45 +218,83 @@ impl TerminalState {
                     "peers".to_string(),
                     ProcessId::new(Some("peers"), "terminal", "sys"),
                 ),
+                (
+                    "perms".to_string(),
+                    ProcessId::new(Some("perms"), "app_store", "sys"),
+                ),
+                (
+                    "router".to_string(),
+                    ProcessId::new(Some("router"), "terminal", "sys"),
+                ),
+                (
+                    "schedules".to_string(),
+                    ProcessId::new(Some("schedules"), "terminal", "sys"),
+                ),
+                (
+                    "sync".to_string(),
+                    ProcessId::new(Some("sync"), "terminal", "sys"),
+                ),
                 (
                     "top".to_string(),
                     ProcessId::new(Some("top"), "terminal", "sys"),
                 ),
+                (
+                    "users".to_string(),
+                    ProcessId::new(Some("users"), "terminal", "sys"),
+                ),
             ]),
+            services: BTreeMap::new(),
         }
     }
 }
 
 call_init!(init);
 fn init(our: Address) {
-    let mut state: TerminalState =
-        match get_typed_state(|bytes| bincode::deserialize::<TerminalState>(bytes)) {
-            Some(mut s) => {
-                // **add** the pre-installed scripts to the terminal state
-                // in case new ones have been added or if user has deleted aliases
-                let default_state = TerminalState::new(our);
-                for (alias, process) in default_state.aliases {
-                    s.aliases.insert(alias, process);
-                }
-                s
-            }
-            None => {
-                let state = TerminalState::new(our);
-                set_state(&bincode::serialize(&state).unwrap());
-                state
+    let mut state: TerminalState = match get_typed_state(|bytes| {
+        bincode::deserialize::<TerminalState>(bytes).or_else(|_| {
+            bincode::deserialize::<TerminalStateV0>(bytes).map(|old| TerminalState {
+                our: old.our,
+                aliases: old.aliases,
+                services: BTreeMap::new(),
+            })
+        })
+    }) {
+        Some(mut s) => {
+            // **add** the pre-installed scripts to the terminal state
+            // in case new ones have been added or if user has deleted aliases
+            let default_state = TerminalState::new(our);
+            for (alias, process) in default_state.aliases {
+                s.aliases.insert(alias, process);
             }
-        };
+            s
+        }
+        None => {
+            let state = TerminalState::new(our);
+            set_state(&bincode::serialize(&state).unwrap());
+            state
+        }
+    };
 
     loop {
         let message = match await_message() {
             Err(e) => {
-                println!("net error: {e:?}!");
+                if !e
+                    .context()
+                    .is_some_and(|context| services::handle_scheduled(&mut state, context, None))
+                {
+                    println!("net error: {e:?}!");
+                }
                 continue;
             }
             Ok(message) => message,
         };
         match message {
-            Message::Request { source, body, .. } => {
+            Message::Request {
+                source,
+                body,
+                expects_response,
+                ..
+            } => {
                 // this is a message from the runtime terminal, parse as a command
                 if state.our == source {
                     if let Err(e) =
@@ -159,6 +312,24 @@ fn init(our: Address) {
                         TerminalAction::EditAlias { alias, process } => {
                             handle_alias_change(&mut state, alias, process);
                         }
+                        TerminalAction::RunService { name } => {
+                            services::run(&mut state, &name);
+                        }
+                        action => {
+                            let reply = services::handle_action(&mut state, &source, action);
+                            if expects_response.is_some() {
+                                Response::new().body(reply).send().unwrap();
+                            }
+                        }
+                    }
+                // the scheduler runs services when they are due
+                } else if state.our.node == source.node
+                    && source.process.to_string() == "scheduler:scheduler:sys"
+                {
+                    if let Ok(TerminalAction::RunService { name }) =
+                        serde_json::from_slice::<TerminalAction>(&body)
+                    {
+                        services::run(&mut state, &name);
                     }
                 } else {
                     kinode_process_lib::print_to_terminal(
@@ -167,7 +338,12 @@ fn init(our: Address) {
                     );
                 }
             }
-            Message::Response { body, .. } => {
+            Message::Response { body, context, .. } => {
+                if context.is_some_and(|context| {
+                    services::handle_scheduled(&mut state, &context, Some(&body))
+                }) {
+                    continue;
+                }
                 if let Ok(txt) = std::str::from_utf8(&body) {
                     println!("{txt}");
                 } else {
@@ -178,23 +354,63 @@ fn init(our: Address) {
     }
 }
 
+/// Run a line as a pipeline of scripts, `a | b | c`. Each stage's output is
+/// given to the next as stdin, in the blob of its arguments request; the
+/// last stage prints its output as usual.
+///
+/// A line starting with `--dry-run` runs every stage as a dry run: the
+/// kernel prints the requests it attempts instead of sending them.
 fn parse_command(state: &mut TerminalState, line: String) -> Result<(), ScriptError> {
+    let (dry_run, line) = match line.strip_prefix("--dry-run") {
+        Some(rest) if rest.is_empty() || rest.starts_with(' ') => (true, rest.trim_start()),
+        _ => (false, line.as_str()),
+    };
+    // a service runs by its name alone
+    let line = match state.services.get(line.trim()) {
+        Some(service) => service.pipeline.clone(),
+        None => line.to_string(),
+    };
     if line.is_empty() {
         return Ok(());
     }
-    let (head, args) = line.split_once(" ").unwrap_or((&line, ""));
-    match state.aliases.get(head) {
-        Some(process) => handle_run(&state.our, process, args.to_string()),
-        None => match head.parse::<ProcessId>() {
-            Ok(pid) => handle_run(&state.our, &pid, args.to_string()),
-            Err(_) => Err(ScriptError::UnknownName),
-        },
+    let stages = script_args::split_pipeline(&line).map_err(|_| ScriptError::InvalidPipeline)?;
+    if stages.iter().any(|stage| stage.is_empty()) {
+        return Err(ScriptError::InvalidPipeline);
     }
+    let mut stdin = None;
+    for (i, stage) in stages.iter().enumerate() {
+        let (head, args) = stage.split_once(" ").unwrap_or((stage, ""));
+        let process = match state.aliases.get(head) {
+            Some(process) => process.clone(),
+            None => head
+                .parse::<ProcessId>()
+                .map_err(|_| ScriptError::UnknownName)?,
+        };
+        let last = i == stages.len() - 1;
+        stdin = handle_run(
+            &state.our,
+            &process,
+            args.to_string(),
+            stdin,
+            !last,
+            dry_run,
+        )?;
+    }
+    Ok(())
 }
 
-/// Run a script by loading it from the VFS
-fn handle_run(our: &Address, process: &ProcessId, args: String) -> Result<(), ScriptError> {
-    let entry = get_entry(process)?;
+/// Run a script by loading it from the VFS. If `capture`, wait for its
+/// output and return it as a blob instead of letting it print. If `dry_run`,
+/// it is given no capabilities to write to drives or use the network.
+fn handle_run(
+    our: &Address,
+    process: &ProcessId,
+    args: String,
+    stdin: Option<LazyLoadBlob>,
+    capture: bool,
+    dry_run: bool,
+) -> Result<Option<LazyLoadBlob>, ScriptError> {
+    let (entry, limits) = get_entry(process)?;
     let wasm_path = format!(
         "/{}:{}/pkg/{}.wasm",
         process.package(),
@@ -205,19 +421,6 @@ fn handle_run(our: &Address, process: &ProcessId, args: String) -> Result<(), Sc
     // all scripts are given random process IDs
     let process_id = ProcessId::new(None, process.package(), process.publisher());
 
-    // call VFS manually so as not to fetch the blob, instead passing it to kernel request
-    Request::to(("our", "vfs", "distro", "sys"))
-        .body(
-            serde_json::to_vec(&vfs::VfsRequest {
-                path: wasm_path.clone(),
-                action: vfs::VfsAction::Read,
-            })
-            .unwrap(),
-        )
-        .send_and_await_response(5)
-        .unwrap()
-        .map_err(|_| ScriptError::FailedToReadWasm)?;
-
     // process the caps we are going to grant to other processes
     let mut granted_caps: Vec<(ProcessId, Capability)> = vec![];
     if let Some(to_grant) = &entry.grant_capabilities {
@@ -334,40 +537,85 @@ fn handle_run(our: &Address, process: &ProcessId, args: String) -> Result<(), Sc
             requested_caps.insert(kt::de_wit_capability(cap));
         }
     }
+    if dry_run {
+        requested_caps.retain(|cap| !lets_write(our, cap));
+    }
 
-    // inherits the blob from the previous request to VFS
-    // containing the wasm byte code of the process
-    Request::to(("our", "kernel", "distro", "sys"))
-        .body(
-            serde_json::to_vec(&kt::KernelCommand::InitializeProcess {
-                id: process_id.clone(),
-                wasm_bytes_handle: wasm_path,
-                wit_version: entry.wit_version,
-                on_exit: kt::OnExit::None,
-                initial_capabilities: requested_caps,
-                public: entry.public,
-            })
-            .unwrap(),
-        )
-        .inherit(true)
-        .send_and_await_response(5)
-        .unwrap()
-        .map_err(|_| ScriptError::KernelUnresponsive)?;
-
-    // run the process
-    Request::to(("our", "kernel", "distro", "sys"))
-        .body(serde_json::to_vec(&kt::KernelCommand::RunProcess(process_id.clone())).unwrap())
-        .send_and_await_response(5)
+    // the kernel reads the wasm, starts the process and sends it the
+    // arguments, with stdin as their blob, in one step. if capturing, the
+    // script's response comes back in place of the kernel's.
+    let mut request = Request::to(("our", "kernel", "distro", "sys")).body(
+        serde_json::to_vec(&KernelCommand::RunTransient {
+            id: process_id,
+            wasm_bytes_handle: wasm_path,
+            wit_version: entry.wit_version,
+            initial_capabilities: requested_caps,
+            public: entry.public,
+            args: args.into_bytes(),
+            capture_output: capture,
+            dry_run,
+            limits,
+        })
+        .unwrap(),
+    );
+    if let Some(stdin) = stdin {
+        request = request.blob(stdin);
+    }
+    let timeout = if capture { PIPE_STAGE_TIMEOUT } else { 5 };
+    let Message::Response { source, body, .. } = request
+        .send_and_await_response(timeout)
         .unwrap()
-        .map_err(|_| ScriptError::KernelUnresponsive)?;
+        .map_err(|_| ScriptError::KernelUnresponsive)?
+    else {
+        return Err(ScriptError::KernelUnresponsive);
+    };
+    if source.process.to_string() != "kernel:distro:sys" {
+        // the script's output: its blob if it gave one, or else its body
+        return Ok(Some(unstyled(kinode_process_lib::get_blob().unwrap_or(
+            LazyLoadBlob {
+                mime: Some("text/plain".into()),
+                bytes: body,
+            },
+        ))));
+    }
+    match serde_json::from_slice::<kt::KernelResponse>(&body) {
+        Ok(kt::KernelResponse::StartedProcess) if capture => Err(ScriptError::NoOutput),
+        Ok(kt::KernelResponse::StartedProcess) => Ok(None),
+        _ => Err(ScriptError::FailedToStart),
+    }
+}
 
-    // once process is running, send the arguments to it
-    Request::to(("our", process_id))
-        .body(args.into_bytes())
-        .send()
-        .unwrap();
+/// output piped on to the next stage without its styles, if it is text
+fn unstyled(mut output: LazyLoadBlob) -> LazyLoadBlob {
+    if output
+        .mime
+        .as_deref()
+        .map_or(true, |mime| mime.starts_with("text/"))
+    {
+        if let Ok(text) = std::str::from_utf8(&output.bytes) {
+            output.bytes = script_args::style::strip(text).into_bytes();
+        }
+    }
+    output
+}
 
-    Ok(())
+/// whether a capability lets a script write to a drive or use the network,
+/// which a dry run must not. root capabilities, such as the vfs's, let it
+/// write anywhere, and mounting its package directory writable lets it write
+/// around the kernel. the kernel reports any other writes it attempts.
+fn lets_write(our: &Address, cap: &kt::Capability) -> bool {
+    if cap.issuer.node != our.node {
+        return false;
+    }
+    let params = serde_json::from_str::<serde_json::Value>(&cap.params).unwrap_or_default();
+    if params["root"] == true {
+        return true;
+    }
+    match cap.issuer.process.to_string().as_str() {
+        "kernel:distro:sys" => cap.params == "\"network\"" || cap.params == "\"mount-write\"",
+        "vfs:distro:sys" => params["kind"] == "write",
+        _ => false,
+    }
 }
 
 fn handle_alias_change(state: &mut TerminalState, alias: String, process: Option<ProcessId>) {
@@ -388,7 +636,7 @@ fn handle_alias_change(state: &mut TerminalState, alias: String, process: Option
     set_state(&bincode::serialize(&state).expect("failed to serialize terminal state"));
 }
 
-fn get_entry(process: &ProcessId) -> Result<kt::DotScriptsEntry, ScriptError> {
+fn get_entry(process: &ProcessId) -> Result<(kt::DotScriptsEntry, ProcessLimits), ScriptError> {
     let file = vfs::File::new(
         format!(
             "/{}:{}/pkg/scripts.json",
@@ -402,8 +650,14 @@ fn get_entry(process: &ProcessId) -> Result<kt::DotScriptsEntry, ScriptError> {
 
     let dot_scripts = serde_json::from_slice::<HashMap<String, kt::DotScriptsEntry>>(&file)
         .map_err(|_| ScriptError::InvalidScriptsManifest)?;
-    let Some(entry) = dot_scripts.get(&format!("{}.wasm", process.process())) else {
+    let name = format!("{}.wasm", process.process());
+    let Some(entry) = dot_scripts.get(&name) else {
         return Err(ScriptError::NoScriptInManifest);
     };
-    Ok(entry.to_owned())
+    let limits = serde_json::from_slice::<HashMap<String, ScriptLimits>>(&file)
+        .map_err(|_| ScriptError::InvalidScriptsManifest)?
+        .remove(&name)
+        .unwrap_or_default()
+        .limits;
+    Ok((entry.to_owned(), limits))
 }
@@ -0,0 +1,376 @@
+//! services: pipelines saved under a name, run by typing that name, or on a
+//! schedule kept by the scheduler, which sends the terminal a request to run
+//! the service each time it is due.
+//!
+//! services are exported to and imported from the drives of the package that
+//! asks, so that the terminal's own access to the VFS isn't lent out. what an
+//! import would define is shown first, along with a code that confirms it.
+use crate::{parse_command, TerminalAction, TerminalState};
+use kinode_process_lib::{println, set_state, vfs, Address, Request};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+/// how long the vfs and the scheduler have to answer
+const TIMEOUT: u64 = 5;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Service {
+    pub pipeline: String,
+    /// when the scheduler runs it, if ever
+    pub cron: Option<String>,
+}
+
+// the scheduler's responses
+#[derive(Deserialize)]
+enum SchedulerResponse {
+    Ok,
+    Err(String),
+}
+
+/// the context of a request to the scheduler: the schedule asked for
+#[derive(Serialize, Deserialize)]
+struct Scheduling {
+    name: String,
+    cron: Option<String>,
+}
+
+/// carry out an action a `define` script at `source` asks for, returning what
+/// to tell it
+pub fn handle_action(
+    state: &mut TerminalState,
+    source: &Address,
+    action: TerminalAction,
+) -> String {
+    match action {
+        TerminalAction::EditAlias { .. } | TerminalAction::RunService { .. } => {
+            "not a service action".to_string()
+        }
+        TerminalAction::ListServices => list(state),
+        TerminalAction::EditService {
+            name,
+            pipeline: Some(pipeline),
+        } => {
+            if let Err(e) = define(state, &name, pipeline) {
+                return e;
+            }
+            save(state);
+            format!("service {name} defined")
+        }
+        TerminalAction::EditService {
+            name,
+            pipeline: None,
+        } => {
+            let Some(service) = state.services.remove(&name) else {
+                return format!("no service {name}");
+            };
+            save(state);
+            if service.cron.is_some() {
+                schedule(&name, None);
+            }
+            format!("service {name} removed")
+        }
+        TerminalAction::ScheduleService { name, cron } => {
+            if !state.services.contains_key(&name) {
+                return format!("no service {name}");
+            }
+            schedule(&name, cron.as_deref());
+            format!("asked the scheduler to schedule service {name}")
+        }
+        TerminalAction::ExportServices { path } => {
+            if let Err(e) = in_own_drive(source, &path) {
+                return e;
+            }
+            let bytes = serde_json::to_vec_pretty(&state.services).unwrap();
+            match vfs::create_file(&path, Some(TIMEOUT)).and_then(|file| file.write(&bytes)) {
+                Ok(()) => format!("{} services exported to {path}", state.services.len()),
+                Err(e) => format!("couldn't write {path}: {e}"),
+            }
+        }
+        TerminalAction::ImportServices { path, confirm } => {
+            if let Err(e) = in_own_drive(source, &path) {
+                return e;
+            }
+            let bytes =
+                match vfs::open_file(&path, false, Some(TIMEOUT)).and_then(|file| file.read()) {
+                    Ok(bytes) => bytes,
+                    Err(e) => return format!("couldn't read {path}: {e}"),
+                };
+            let Ok(services) = serde_json::from_slice::<BTreeMap<String, Service>>(&bytes) else {
+                return format!("{path} doesn't hold exported services");
+            };
+            // the code is of what was shown, so a file changed since can't
+            // be imported with it
+            let code = confirmation_code(&bytes);
+            if confirm.as_deref() != Some(code.as_str()) {
+                return preview(state, &path, &services, &code);
+            }
+            import(state, services, &path)
+        }
+    }
+}
+
+/// what importing `services` would do, and how to confirm it
+fn preview(
+    state: &TerminalState,
+    path: &str,
+    services: &BTreeMap<String, Service>,
+    code: &str,
+) -> String {
+    let mut printout = format!("{path} would define {} services:", services.len());
+    for (name, service) in services {
+        printout.push_str(&format!("\r\n    {name}: {}", service.pipeline));
+        if let Some(cron) = &service.cron {
+            printout.push_str(&format!(" (scheduled {cron})"));
+        }
+        if let Some(replaced) = state.services.get(name) {
+            printout.push_str(&format!("\r\n        replacing: {}", replaced.pipeline));
+        }
+    }
+    printout.push_str(&format!(
+        "\r\nto import them, run: define --import {path} --confirm {code}"
+    ));
+    printout
+}
+
+/// define and schedule each of `services` as if by `EditService` and
+/// `ScheduleService`, naming those replaced
+fn import(state: &mut TerminalState, services: BTreeMap<String, Service>, path: &str) -> String {
+    let mut imported = 0;
+    let mut notes = String::new();
+    for (name, service) in services {
+        let replaced = state.services.get(&name).cloned();
+        if let Err(e) = define(state, &name, service.pipeline) {
+            notes.push_str(&format!("\r\n    couldn't import {name}: {e}"));
+            continue;
+        }
+        imported += 1;
+        if let Some(replaced) = &replaced {
+            notes.push_str(&format!(
+                "\r\n    replaced {name}, which was: {}",
+                replaced.pipeline
+            ));
+        }
+        let was_scheduled = replaced.is_some_and(|replaced| replaced.cron.is_some());
+        if service.cron.is_some() || was_scheduled {
+            schedule(&name, service.cron.as_deref());
+        }
+    }
+    save(state);
+    format!("{imported} services imported from {path}{notes}")
+}
+
+/// the code that confirms importing the file holding `bytes`
+fn confirmation_code(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)[..4]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// services are exported to and imported from a drive of the package asking
+fn in_own_drive(source: &Address, path: &str) -> Result<(), String> {
+    let drive = format!("/{}:{}/", source.package(), source.publisher());
+    if !path.starts_with(&drive) || path.split('/').any(|part| part == "..") {
+        return Err(format!("{path} is not in a drive under {drive}"));
+    }
+    Ok(())
+}
+
+/// define or redefine a service, keeping its schedule if it has one
+fn define(state: &mut TerminalState, name: &str, pipeline: String) -> Result<(), String> {
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "{name} is not a name: use letters, numbers, - and _"
+        ));
+    }
+    if state.aliases.contains_key(name) {
+        return Err(format!("{name} is already an alias"));
+    }
+    if let Err(e) = script_args::split_pipeline(&pipeline) {
+        return Err(format!("{pipeline} is not a pipeline: {e}"));
+    }
+    let cron = state
+        .services
+        .get(name)
+        .and_then(|service| service.cron.clone());
+    state
+        .services
+        .insert(name.to_string(), Service { pipeline, cron });
+    Ok(())
+}
+
+/// run a service's pipeline, as if its name were typed
+pub fn run(state: &mut TerminalState, name: &str) {
+    let Some(pipeline) = state
+        .services
+        .get(name)
+        .map(|service| service.pipeline.clone())
+    else {
+        kinode_process_lib::println!("no service {name} to run");
+        return;
+    };
+    if let Err(e) = parse_command(state, pipeline) {
+        kinode_process_lib::println!("error running service {name}: {e}");
+    }
+}
+
+fn list(state: &TerminalState) -> String {
+    if state.services.is_empty() {
+        return "no services defined".to_string();
+    }
+    let mut printout = format!("{} services:", state.services.len());
+    for (name, service) in &state.services {
+        printout.push_str(&format!("\r\n    {name}: {}", service.pipeline));
+        if let Some(cron) = &service.cron {
+            printout.push_str(&format!(" (scheduled {cron})"));
+        }
+    }
+    printout
+}
+
+/// ask the scheduler to run a service on a cron schedule, or with none, to
+/// stop. the schedule is noted once it answers, in [`handle_scheduled`].
+fn schedule(name: &str, cron: Option<&str>) {
+    let request = match cron {
+        Some(cron) => serde_json::json!({
+            "Add": {
+                "name": name,
+                "cron": cron,
+                "body": TerminalAction::RunService { name: name.to_string() },
+            }
+        }),
+        None => serde_json::json!({ "Remove": { "name": name } }),
+    };
+    let context = Scheduling {
+        name: name.to_string(),
+        cron: cron.map(str::to_string),
+    };
+    Request::to(("our", "scheduler", "scheduler", "sys"))
+        .body(serde_json::to_vec(&request).unwrap())
+        .context(serde_json::to_vec(&context).unwrap())
+        .expects_response(TIMEOUT)
+        .send()
+        .unwrap();
+}
+
+/// if `context` is that of a request to the scheduler, note the schedule it
+/// took, given its response, or `None` if it didn't answer. returns whether
+/// it was.
+pub fn handle_scheduled(state: &mut TerminalState, context: &[u8], body: Option<&[u8]>) -> bool {
+    let Ok(Scheduling { name, cron }) = serde_json::from_slice::<Scheduling>(context) else {
+        return false;
+    };
+    let response = match body.map(serde_json::from_slice::<SchedulerResponse>) {
+        None => Err("scheduler didn't answer".to_string()),
+        Some(Ok(SchedulerResponse::Ok)) => Ok(()),
+        Some(Ok(SchedulerResponse::Err(e))) => Err(format!("scheduler: {e}")),
+        Some(Err(_)) => Err("failed to parse scheduler response".to_string()),
+    };
+    if let Err(e) = response {
+        println!("couldn't schedule service {name}: {e}");
+        return true;
+    }
+    // removed meanwhile: only its schedule was being removed
+    let Some(service) = state.services.get_mut(&name) else {
+        return true;
+    };
+    match &cron {
+        Some(cron) => println!("service {name} scheduled {cron}"),
+        None => println!("service {name} no longer scheduled"),
+    }
+    service.cron = cron;
+    save(state);
+    true
+}
+
+fn save(state: &TerminalState) {
+    set_state(&bincode::serialize(state).expect("failed to serialize terminal state"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn state() -> TerminalState {
+        TerminalState {
+            our: "our.os@terminal:terminal:sys".parse().unwrap(),
+            aliases: HashMap::from([("hi".to_string(), "hi:terminal:sys".parse().unwrap())]),
+            services: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn files_must_be_in_own_drives() {
+        let define: Address = "our.os@define:terminal:sys".parse().unwrap();
+        assert!(in_own_drive(&define, "/terminal:sys/services/all.json").is_ok());
+        assert!(in_own_drive(&define, "/app_store:sys/pkg/manifest.json").is_err());
+        assert!(in_own_drive(&define, "/terminal:sys/../app_store:sys/x").is_err());
+        assert!(in_own_drive(&define, "/terminal:sysx/services.json").is_err());
+        assert!(in_own_drive(&define, "terminal:sys/services.json").is_err());
+    }
+
+    #[test]
+    fn definitions_are_checked() {
+        let mut state = state();
+        assert!(define(&mut state, "todo", "cat /notes:me/pkg/todo.md".into()).is_ok());
+        assert!(define(&mut state, "to do", "echo".into()).is_err());
+        assert!(define(&mut state, "hi", "echo".into()).is_err());
+        assert!(define(&mut state, "quote", "echo \"unclosed".into()).is_err());
+        assert_eq!(state.services.len(), 1);
+    }
+
+    #[test]
+    fn redefining_keeps_schedule() {
+        let mut state = state();
+        define(&mut state, "todo", "echo one".into()).unwrap();
+        state.services.get_mut("todo").unwrap().cron = Some("@daily".into());
+        define(&mut state, "todo", "echo two".into()).unwrap();
+        let service = &state.services["todo"];
+        assert_eq!(service.pipeline, "echo two");
+        assert_eq!(service.cron.as_deref(), Some("@daily"));
+    }
+
+    #[test]
+    fn preview_shows_pipelines_and_code() {
+        let mut state = state();
+        define(&mut state, "todo", "echo old".into()).unwrap();
+        let services = BTreeMap::from([(
+            "todo".to_string(),
+            Service {
+                pipeline: "echo new".into(),
+                cron: Some("@hourly".into()),
+            },
+        )]);
+        let bytes = serde_json::to_vec(&services).unwrap();
+        let code = confirmation_code(&bytes);
+        let printout = preview(&state, "/terminal:sys/s.json", &services, &code);
+        assert!(printout.contains("todo: echo new (scheduled @hourly)"));
+        assert!(printout.contains("replacing: echo old"));
+        assert!(printout.ends_with(&format!("--confirm {code}")));
+        // nothing is defined until confirmed
+        assert_eq!(state.services["todo"].pipeline, "echo old");
+    }
+
+    #[test]
+    fn code_is_of_the_file() {
+        let code = confirmation_code(b"{}");
+        assert_eq!(code.len(), 8);
+        assert_eq!(code, confirmation_code(b"{}"));
+        assert_ne!(code, confirmation_code(b"{ }"));
+    }
+
+    #[test]
+    fn other_contexts_are_left_alone() {
+        let mut state = state();
+        assert!(!handle_scheduled(
+            &mut state,
+            b"not a schedule",
+            Some(b"\"Ok\"")
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use kinode_process_lib::{await_message, get_blob, Address, Message, Request};
+use script_args::{script, Args};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v0",
+});
+
+const USAGE: &str = "\x1b[1mUsage:\x1b[0m users [add <name> <guest|member|admin> | remove <name>]";
+
+// the http_server's users, which process_lib doesn't know yet
+#[derive(Deserialize)]
+struct User {
+    name: String,
+    role: String,
+}
+
+// the terminal's answer to a `ReadSecret` kernel command
+#[derive(Deserialize)]
+struct SecretInput {
+    input: Option<String>,
+}
+
+script!(init);
+fn init(our: Address, args: Args) -> String {
+    let request = match args.positional.as_slice() {
+        [] => serde_json::json!("ListUsers"),
+        [verb, name, role] if verb == "add" => {
+            let Some(role) = parse_role(role) else {
+                return format!("{role} is not a role\n{USAGE}");
+            };
+            // asked for rather than taken as an argument, which the terminal
+            // would show and keep in its history
+            let Some(password) = read_secret(&our, &format!("password for {name}: ")) else {
+                return "cancelled".to_string();
+            };
+            let Some(again) = read_secret(&our, "password again: ") else {
+                return "cancelled".to_string();
+            };
+            let password_hash = match check_password(&password, &again) {
+                Ok(password_hash) => password_hash,
+                Err(e) => return e.to_string(),
+            };
+            serde_json::json!({
+                "AddUser": { "name": name, "role": role, "password_hash": password_hash }
+            })
+        }
+        [verb, name] if verb == "remove" => serde_json::json!({ "RemoveUser": { "name": name } }),
+        _ => {
+            return format!(
+                "List the users that can log in to the node, or add, change or remove one.\n{USAGE}"
+            )
+        }
+    };
+    let listing = request == serde_json::json!("ListUsers");
+
+    let Ok(Message::Response { body, .. }) = Request::to(("our", "http_server", "distro", "sys"))
+        .body(serde_json::to_vec(&request).unwrap())
+        .send_and_await_response(5)
+        .unwrap()
+    else {
+        return "failed to get response from http_server".to_string();
+    };
+    match serde_json::from_slice::<Result<(), serde_json::Value>>(&body) {
+        Ok(Ok(())) if listing => {}
+        Ok(Ok(())) => return "done".to_string(),
+        Ok(Err(e)) => return format!("http_server: {e}"),
+        Err(_) => return "failed to parse http_server response".to_string(),
+    }
+
+    let Some(users) =
+        get_blob().and_then(|blob| serde_json::from_slice::<Vec<User>>(&blob.bytes).ok())
+    else {
+        return "failed to get users from http_server".to_string();
+    };
+    let mut printout = format!("{} users:", users.len());
+    for user in users {
+        printout.push_str(&format!(
+            "\r\n    {} ({})",
+            user.name,
+            user.role.to_lowercase()
+        ));
+    }
+    printout
+}
+
+fn parse_role(role: &str) -> Option<&'static str> {
+    match role {
+        "guest" => Some("Guest"),
+        "member" => Some("Member"),
+        "admin" => Some("Admin"),
+        _ => None,
+    }
+}
+
+/// the hash of a new password typed twice, hashed as the login page hashes
+/// passwords
+fn check_password(password: &str, again: &str) -> Result<String, &'static str> {
+    if password.is_empty() {
+        return Err("the password must not be empty");
+    }
+    if password != again {
+        return Err("the passwords don't match");
+    }
+    Ok(Sha256::digest(password.as_bytes())
+        .iter()
+        .fold("0x".to_string(), |hash, byte| format!("{hash}{byte:02x}")))
+}
+
+/// ask the user at the terminal for a line that isn't shown as it's typed.
+/// `None` if they cancelled.
+fn read_secret(our: &Address, prompt: &str) -> Option<String> {
+    Request::to(("our", "kernel", "distro", "sys"))
+        .body(
+            serde_json::to_vec(&serde_json::json!({ "ReadSecret": { "prompt": prompt } })).unwrap(),
+        )
+        .send()
+        .ok()?;
+    loop {
+        let Ok(Message::Request { source, body, .. }) = await_message() else {
+            continue;
+        };
+        if source.node != our.node || source.process.to_string() != "terminal:terminal:sys" {
+            continue;
+        }
+        if let Ok(SecretInput { input }) = serde_json::from_slice(&body) {
+            return input;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_parse() {
+        assert_eq!(parse_role("member"), Some("Member"));
+        assert_eq!(parse_role("Admin"), None);
+        assert_eq!(parse_role("owner"), None);
+    }
+
+    #[test]
+    fn password_is_checked_and_hashed() {
+        assert_eq!(
+            check_password("", ""),
+            Err("the password must not be empty")
+        );
+        assert_eq!(
+            check_password("hunter2", "hunter3"),
+            Err("the passwords don't match")
+        );
+        assert_eq!(
+            check_password("hunter2", "hunter2").as_deref(),
+            Ok("0xf52fbd32b2b3b86ff88ef6c490628285f482af15ddcb29541f94bcf526a3f6c7")
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use crate::kinode::process::tester::{AssertCapsStep, CapChange};
+use kinode_process_lib::kernel_types::{
+    KernelCommand, KernelPrint, KernelPrintResponse, KernelResponse,
+};
+use kinode_process_lib::{vfs, Message, Request};
+use std::collections::BTreeSet;
+
+const SNAPSHOT_DIR: &str = "tester:sys/setup";
+
+/// a capability held by a process: (holder, issuer, params)
+type HeldCap = (String, String, String);
+
+/// every capability held by every process on our node, from the kernel
+fn held_caps(timeout: u64) -> anyhow::Result<BTreeSet<HeldCap>> {
+    let Message::Response { body, .. } = Request::to(("our", "kernel", "distro", "sys"))
+        .body(serde_json::to_vec(&KernelCommand::Debug(
+            KernelPrint::ProcessMap,
+        ))?)
+        .send_and_await_response(timeout)??
+    else {
+        return Err(anyhow::anyhow!("unexpected request from kernel"));
+    };
+    let KernelResponse::Debug(KernelPrintResponse::ProcessMap(process_map)) =
+        serde_json::from_slice(&body)?
+    else {
+        return Err(anyhow::anyhow!("unexpected response from kernel"));
+    };
+    Ok(process_map
+        .into_iter()
+        .flat_map(|(process, persisted)| {
+            persisted.capabilities.into_iter().map(move |cap| {
+                (
+                    process.to_string(),
+                    cap.issuer.to_string(),
+                    cap.params.clone(),
+                )
+            })
+        })
+        .collect())
+}
+
+fn snapshot_path(name: &str) -> String {
+    format!("/{SNAPSHOT_DIR}/caps-{name}.json")
+}
+
+/// save the capabilities held on our node under a name, for a later diff
+pub fn snapshot(name: &str, timeout: u64) -> anyhow::Result<Option<String>> {
+    let caps = held_caps(timeout)?;
+    vfs::open_file(&snapshot_path(name), true, None)?.write(&serde_json::to_vec(&caps)?)?;
+    Ok(Some(format!(
+        "{} capabilities in snapshot {name}",
+        caps.len()
+    )))
+}
+
+/// the capabilities gained and lost on our node since a snapshot
+pub fn diff(name: &str, timeout: u64) -> anyhow::Result<Vec<CapChange>> {
+    let before: BTreeSet<HeldCap> = serde_json::from_slice(
+        &vfs::open_file(&snapshot_path(name), false, None)
+            .map_err(|_| anyhow::anyhow!("no capabilities snapshot named {name}"))?
+            .read()?,
+    )?;
+    let after = held_caps(timeout)?;
+    let change = |(process, issuer, params): &HeldCap, gained: bool| CapChange {
+        process: process.clone(),
+        issuer: issuer.clone(),
+        params: params.clone(),
+        gained,
+    };
+    Ok(after
+        .difference(&before)
+        .map(|cap| change(cap, true))
+        .chain(before.difference(&after).map(|cap| change(cap, false)))
+        .collect())
+}
+
+pub fn describe(change: &CapChange) -> String {
+    format!(
+        "{} {} {}({})",
+        change.process,
+        if change.gained { "gained" } else { "lost" },
+        change.issuer,
+        change.params
+    )
+}
+
+/// fail unless the capabilities changed since a snapshot are exactly those expected
+pub fn assert_caps(assert: &AssertCapsStep, timeout: u64) -> anyhow::Result<Option<String>> {
+    let changes = diff(&assert.snapshot, timeout)?;
+    let unexpected: Vec<String> = changes
+        .iter()
+        .filter(|change| !assert.expected.contains(change))
+        .map(describe)
+        .collect();
+    let missing: Vec<String> = assert
+        .expected
+        .iter()
+        .filter(|change| !changes.contains(change))
+        .map(describe)
+        .collect();
+    if !unexpected.is_empty() || !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "capabilities differ from snapshot {}: unexpected: [{}]; missing: [{}]",
+            assert.snapshot,
+            unexpected.join(", "),
+            missing.join(", "),
+        ));
+    }
+    Ok(Some(format!(
+        "{} capability changes, as expected",
+        changes.len()
+    )))
+}
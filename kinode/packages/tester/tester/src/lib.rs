@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use crate::kinode::process::tester::{
-    FailResponse, Request as TesterRequest, Response as TesterResponse, RunRequest,
+    FailResponse, Request as TesterRequest, Response as TesterResponse, RunRequest, StepRequest,
 };
 use kinode_process_lib::kernel_types as kt;
 use kinode_process_lib::{
@@ -9,6 +9,8 @@ use kinode_process_lib::{
     OnExit, ProcessId, Request, Response,
 };
 
+mod caps;
+mod scenario;
 mod tester_lib;
 
 wit_bindgen::generate!({
@@ -29,7 +31,12 @@ fn make_vfs_address(our: &Address) -> anyhow::Result<Address> {
 }
 
 fn handle_response(message: &Message) -> anyhow::Result<()> {
-    let TesterResponse::Run(_) = message.body().try_into()?;
+    let TesterResponse::Run(_) = message.body().try_into()? else {
+        return Err(anyhow::anyhow!(
+            "unexpected response from {}",
+            message.source()
+        ));
+    };
     let source = message.source();
     if (source.process.package_name != "tester") || (source.process.publisher_node != "sys") {
         println!(
@@ -67,11 +74,43 @@ fn handle_request(
     message: &Message,
     node_names: &mut Vec<String>,
 ) -> anyhow::Result<()> {
-    let TesterRequest::Run(RunRequest {
-        input_node_names,
-        ref test_names,
-        test_timeout,
-    }) = message.body().try_into()?;
+    let (input_node_names, test_names, test_timeout) = match message.body().try_into()? {
+        TesterRequest::Run(RunRequest {
+            input_node_names,
+            test_names,
+            test_timeout,
+        }) => (input_node_names, test_names, test_timeout),
+        TesterRequest::RunScenario(run_scenario) => {
+            if !message.is_local(our) {
+                return Err(anyhow::anyhow!("RunScenario from non-local node"));
+            }
+            let report = scenario::run_scenario(our, &run_scenario)?;
+            Response::new()
+                .body(TesterResponse::RunScenario(report))
+                .send()?;
+            return Ok(());
+        }
+        TesterRequest::Step(StepRequest { step, step_timeout }) => {
+            // steps are sent by a tester running a scenario on another node
+            if message.source().process != our.process {
+                return Err(anyhow::anyhow!("Step from non-tester source"));
+            }
+            let result = scenario::run_step(our, 0, &step, step_timeout, !message.is_local(our));
+            Response::new().body(TesterResponse::Step(result)).send()?;
+            return Ok(());
+        }
+        TesterRequest::CapsDiff(snapshot) => {
+            if message.source().process != our.process {
+                return Err(anyhow::anyhow!("CapsDiff from non-tester source"));
+            }
+            let result = caps::diff(&snapshot, 5).map_err(|e| e.to_string());
+            Response::new()
+                .body(TesterResponse::CapsDiff(result))
+                .send()?;
+            return Ok(());
+        }
+    };
+    let test_names = &test_names;
     println!("got Run");
 
     assert!(input_node_names.len() >= 1);
@@ -177,7 +216,9 @@ fn handle_request(
         if response.is_request() {
             fail!("tester");
         };
-        let TesterResponse::Run(result) = response.body().try_into()?;
+        let TesterResponse::Run(result) = response.body().try_into()? else {
+            fail!("tester");
+        };
         if let Err(FailResponse {
             test,
             file,
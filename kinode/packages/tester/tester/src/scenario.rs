@@ -0,0 +1,311 @@
+use crate::caps;
+use crate::kinode::process::tester::{
+    AssertStateStep, CapChange, InstallStep, NodeCapsDiff, Request as TesterRequest,
+    Response as TesterResponse, RunScenarioRequest, ScenarioReport, ScenarioStep, SendStep,
+    StepAction, StepRequest, StepResult,
+};
+use kinode_process_lib::{get_blob, println, vfs, Address, Message, PackageId, ProcessId, Request};
+use serde::Deserialize;
+
+const SETUP_DIR: &str = "tester:sys/setup";
+
+/// a multi-node scenario, as read from `/tester:sys/setup/<name>.json`
+#[derive(Debug, Deserialize)]
+struct Scenario {
+    name: String,
+    nodes: Vec<String>,
+    steps: Vec<ScenarioStep>,
+}
+
+/// run every step of a scenario in order, each on the node it names,
+/// stopping at the first failure. the capabilities held on every node are
+/// snapshotted before the first step and diffed after the last. the report
+/// is also saved next to the scenario so that CI can collect it.
+pub fn run_scenario(our: &Address, request: &RunScenarioRequest) -> anyhow::Result<ScenarioReport> {
+    let file = vfs::open_file(
+        &format!("/{SETUP_DIR}/{}.json", request.scenario_name),
+        false,
+        None,
+    )?;
+    let scenario: Scenario = serde_json::from_slice(&file.read()?)?;
+
+    let snapshot = format!("scenario-{}", request.scenario_name);
+    for node in &scenario.nodes {
+        let step = ScenarioStep {
+            node: node.clone(),
+            action: StepAction::SnapshotCaps(snapshot.clone()),
+        };
+        let result = if *node == our.node {
+            run_step(our, 0, &step, request.step_timeout, false)
+        } else {
+            forward_step(0, &step, request.step_timeout)
+        };
+        if !result.passed {
+            return Err(anyhow::anyhow!(
+                "couldn't snapshot capabilities on {node}: {}",
+                result.detail.unwrap_or_default()
+            ));
+        }
+    }
+
+    let mut report = ScenarioReport {
+        name: scenario.name,
+        passed: true,
+        steps: vec![],
+        caps_diffs: vec![],
+    };
+    for (index, step) in scenario.steps.into_iter().enumerate() {
+        let index = index as u32;
+        let result = if !scenario.nodes.contains(&step.node) {
+            StepResult {
+                index,
+                node: step.node.clone(),
+                passed: false,
+                detail: Some(format!("node {} is not part of the scenario", step.node)),
+            }
+        } else if step.node == our.node {
+            run_step(our, index, &step, request.step_timeout, false)
+        } else {
+            forward_step(index, &step, request.step_timeout)
+        };
+        let passed = result.passed;
+        report.steps.push(result);
+        if !passed {
+            report.passed = false;
+            break;
+        }
+    }
+
+    for node in &scenario.nodes {
+        let changes = if *node == our.node {
+            caps::diff(&snapshot, request.step_timeout)
+        } else {
+            remote_caps_diff(node, &snapshot, request.step_timeout)
+        };
+        match changes {
+            Ok(changes) => {
+                for change in &changes {
+                    println!("tester: {node}: {}", caps::describe(change));
+                }
+                report.caps_diffs.push(NodeCapsDiff {
+                    node: node.clone(),
+                    changes,
+                });
+            }
+            Err(e) => {
+                println!("tester: couldn't diff capabilities on {node}: {e}");
+                report.passed = false;
+            }
+        }
+    }
+
+    println!(
+        "tester: scenario {} {}",
+        report.name,
+        if report.passed { "passed" } else { "failed" }
+    );
+    vfs::open_file(
+        &format!("/{SETUP_DIR}/{}.report.json", request.scenario_name),
+        true,
+        None,
+    )?
+    .write(&serde_json::to_vec(&report)?)?;
+    Ok(report)
+}
+
+/// have the tester on another node run a step
+fn forward_step(index: u32, step: &ScenarioStep, timeout: u64) -> StepResult {
+    let failed = |detail: String| StepResult {
+        index,
+        node: step.node.clone(),
+        passed: false,
+        detail: Some(detail),
+    };
+    let response = match Request::to(Address::new(&step.node, ("tester", "tester", "sys")))
+        .body(TesterRequest::Step(StepRequest {
+            step: step.clone(),
+            step_timeout: timeout,
+        }))
+        // leave the remote tester time to report its own timeouts
+        .send_and_await_response(timeout + 5)
+    {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => return failed(format!("couldn't reach tester on {}: {e}", step.node)),
+        Err(e) => return failed(format!("couldn't send step: {e}")),
+    };
+    match response.body().try_into() {
+        Ok(TesterResponse::Step(result)) => StepResult { index, ..result },
+        _ => failed(format!("unexpected response from tester on {}", step.node)),
+    }
+}
+
+/// have the tester on another node diff its capabilities against a snapshot
+fn remote_caps_diff(node: &str, snapshot: &str, timeout: u64) -> anyhow::Result<Vec<CapChange>> {
+    let response = Request::to(Address::new(node, ("tester", "tester", "sys")))
+        .body(TesterRequest::CapsDiff(snapshot.to_string()))
+        .send_and_await_response(timeout)??;
+    match response.body().try_into()? {
+        TesterResponse::CapsDiff(result) => result.map_err(|e| anyhow::anyhow!(e)),
+        _ => Err(anyhow::anyhow!("unexpected response from tester on {node}")),
+    }
+}
+
+/// run a step on this node, sent by the tester on another node if `remote`
+pub fn run_step(
+    our: &Address,
+    index: u32,
+    step: &ScenarioStep,
+    timeout: u64,
+    remote: bool,
+) -> StepResult {
+    let outcome = match &step.action {
+        StepAction::Install(install) => install_package(our, install, timeout),
+        StepAction::Send(send) => send_message(send, timeout),
+        StepAction::AdvanceTime(_) if remote => Err(anyhow::anyhow!(
+            "only a scenario run on this node may advance its time"
+        )),
+        StepAction::AdvanceTime(ms) => advance_time(*ms, timeout),
+        StepAction::AssertState(assert) => assert_state(assert, timeout),
+        StepAction::SnapshotCaps(name) => caps::snapshot(name, timeout),
+        StepAction::AssertCaps(assert) => caps::assert_caps(assert, timeout),
+    };
+    let (passed, detail) = match outcome {
+        Ok(detail) => (true, detail),
+        Err(e) => (false, Some(e.to_string())),
+    };
+    StepResult {
+        index,
+        node: our.node.clone(),
+        passed,
+        detail,
+    }
+}
+
+/// advance the node's virtual time, popping the timers that come due. a node
+/// on the wall clock can't have its time advanced, so the step fails there.
+fn advance_time(ms: u64, timeout: u64) -> anyhow::Result<Option<String>> {
+    // `TimerAction::AdvanceTime` is not exposed by process_lib, so we build it by hand
+    let response = Request::to(("our", "timer", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "AdvanceTime": ms }),
+        )?)
+        .send_and_await_response(timeout)??;
+    if !serde_json::from_slice::<bool>(response.body()).unwrap_or(false) {
+        return Err(anyhow::anyhow!(
+            "node runs on the wall clock: boot it with --virtual-time"
+        ));
+    }
+    Ok(Some(format!("advanced virtual time by {ms}ms")))
+}
+
+/// add a package zip from our setup drive to the app store, then install it
+fn install_package(
+    our: &Address,
+    install: &InstallStep,
+    timeout: u64,
+) -> anyhow::Result<Option<String>> {
+    use sha2::{Digest, Sha256};
+
+    let package_id = install.package_id.parse::<PackageId>()?;
+    let bytes =
+        vfs::open_file(&format!("/{SETUP_DIR}/{}", install.zip_name), false, None)?.read()?;
+    let version_hash = format!("{:x}", Sha256::digest(&bytes));
+    let wit_package_id = serde_json::json!({
+        "package_name": package_id.package(),
+        "publisher_node": package_id.publisher(),
+    });
+    let app_store = Address::new(&our.node, ("main", "app_store", "sys"));
+
+    // the app store's API is not available to us as a WIT, so we build it by hand
+    let response = Request::to(app_store.clone())
+        .body(serde_json::to_vec(&serde_json::json!({
+            "NewPackage": { "package_id": wit_package_id, "mirror": false }
+        }))?)
+        .blob_bytes(bytes)
+        .send_and_await_response(timeout)??;
+    let body: serde_json::Value = serde_json::from_slice(response.body())?;
+    if body != serde_json::json!({ "NewPackageResponse": "Success" }) {
+        return Err(anyhow::anyhow!("couldn't add {package_id}: {body}"));
+    }
+
+    let response = Request::to(app_store)
+        .body(serde_json::to_vec(&serde_json::json!({
+            "Install": {
+                "package_id": wit_package_id,
+                // test packages have no onchain listing: describe one that
+                // targets the latest wit version
+                "metadata": {
+                    "name": package_id.package(),
+                    "description": null,
+                    "image": null,
+                    "external_url": null,
+                    "animation_url": null,
+                    "properties": {
+                        "package_name": package_id.package(),
+                        "publisher": package_id.publisher(),
+                        "current_version": "test",
+                        "mirrors": [],
+                        "code_hashes": [],
+                        "license": null,
+                        "screenshots": null,
+                        "wit_version": 0,
+                        "dependencies": null,
+                    },
+                },
+                "version_hash": version_hash,
+                "caps_approved": true,
+                "denied_capabilities": [],
+            }
+        }))?)
+        .send_and_await_response(timeout)??;
+    let body: serde_json::Value = serde_json::from_slice(response.body())?;
+    if body != serde_json::json!({ "InstallResponse": "Success" }) {
+        return Err(anyhow::anyhow!("couldn't install {package_id}: {body}"));
+    }
+    Ok(Some(format!("installed {package_id} {version_hash}")))
+}
+
+/// send a request, checking the response against the one expected, if any
+fn send_message(send: &SendStep, timeout: u64) -> anyhow::Result<Option<String>> {
+    let target = send.target.parse::<Address>()?;
+    let body: serde_json::Value = serde_json::from_str(&send.body)?;
+    let request = Request::to(target).body(serde_json::to_vec(&body)?);
+    let Some(expected) = &send.expect_response else {
+        request.send()?;
+        return Ok(None);
+    };
+    let expected: serde_json::Value = serde_json::from_str(expected)?;
+    let Message::Response { body, .. } = request.send_and_await_response(timeout)?? else {
+        return Err(anyhow::anyhow!("got a request, not a response"));
+    };
+    let got: serde_json::Value = serde_json::from_slice(&body)?;
+    if got != expected {
+        return Err(anyhow::anyhow!("expected response {expected}, got {got}"));
+    }
+    Ok(Some(got.to_string()))
+}
+
+/// compare the state persisted by a process against the state expected
+fn assert_state(assert: &AssertStateStep, timeout: u64) -> anyhow::Result<Option<String>> {
+    let process = assert.process.parse::<ProcessId>()?;
+    let expected: serde_json::Value = serde_json::from_str(&assert.expected)?;
+    // `StateAction` is not exposed by process_lib, so we build it by hand
+    let response = Request::to(("our", "state", "distro", "sys"))
+        .body(serde_json::to_vec(
+            &serde_json::json!({ "GetState": process }),
+        )?)
+        .send_and_await_response(timeout)??;
+    let body: serde_json::Value = serde_json::from_slice(response.body())?;
+    if body != serde_json::json!("GetState") {
+        return Err(anyhow::anyhow!("couldn't get state of {process}: {body}"));
+    }
+    let Some(blob) = get_blob() else {
+        return Err(anyhow::anyhow!("no state for {process}"));
+    };
+    let got: serde_json::Value = serde_json::from_slice(&blob.bytes)
+        .map_err(|e| anyhow::anyhow!("state of {process} is not JSON: {e}"))?;
+    if got != expected {
+        return Err(anyhow::anyhow!("expected state {expected}, got {got}"));
+    }
+    Ok(None)
+}
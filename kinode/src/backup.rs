@@ -0,0 +1,373 @@
+//! whole-node backups, taken while the node is stopped: one archive holding a
+//! checkpoint of the state DB, the VFS drives and package databases of every
+//! package (or only some), the keyfile and the node's eth providers, restored
+//! into an empty home directory to recover a node or move it to a new host.
+//!
+//! the archive is a zip of those files, laid out as in the home directory,
+//! encrypted with a key derived from the node password. it is encrypted in
+//! chunks, each sealed with its index and whether it is the last, so that a
+//! truncated, reordered or altered archive fails to restore rather than
+//! restoring part of a node.
+use crate::keygen;
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{anyhow, Context, Result};
+use lib::types::core::{ProcessMap, KERNEL_PROCESS_ID};
+use ring::{pbkdf2, rand::SecureRandom};
+use rocksdb::{checkpoint::Checkpoint, DB};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Read, Write},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAGIC: &[u8] = b"kinode-backup\x01";
+/// bytes of the zip sealed at a time
+const CHUNK_LEN: usize = 1024 * 1024;
+const SALT_LEN: usize = 32;
+/// the nonce of each chunk is this random prefix, the chunk's index and
+/// whether it is the last
+const NONCE_PREFIX_LEN: usize = 7;
+/// the home directories holding the files of each package, by package ID
+const PACKAGE_DIRS: [&str; 3] = ["vfs", "kv", "sqlite"];
+/// files in the home directory that describe the node itself
+const NODE_FILES: [&str; 2] = [".keys", ".eth_providers"];
+
+/// what a backup holds, written into it as `backup.json`
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupMeta {
+    node: String,
+    version: String,
+    /// seconds since the unix epoch
+    created: u64,
+    /// the packages whose files were kept, or `None` for all of them
+    packages: Option<Vec<String>>,
+}
+
+/// write a backup of the stopped node at `home_directory_path` to `archive`,
+/// keeping only the files of `packages` if given
+pub fn backup(
+    home_directory_path: &str,
+    archive: &str,
+    password: &str,
+    packages: Option<Vec<String>>,
+) -> Result<()> {
+    let home = Path::new(home_directory_path);
+    let keyfile = fs::read(home.join(".keys")).context("no keyfile: is this a node's home?")?;
+    let password_hash = keygen::password_hash(password);
+    let keys = keygen::decode_keyfile(&keyfile, &password_hash)
+        .map_err(|e| anyhow!("wrong password: {e}"))?;
+
+    let tmp = home.join(".backup-tmp");
+    if tmp.exists() {
+        fs::remove_dir_all(&tmp)?;
+    }
+    fs::create_dir_all(&tmp)?;
+    let result = (|| -> Result<()> {
+        // opening the DB fails while the node holds it, so the checkpoint and
+        // the other files all come from one stopped node
+        let checkpoint_dir = tmp.join("kernel");
+        {
+            let db = DB::open_default(home.join("kernel"))
+                .context("couldn't open state DB: stop the node first")?;
+            Checkpoint::new(&db)?.create_checkpoint(&checkpoint_dir)?;
+        }
+        if let Some(packages) = &packages {
+            keep_processes(&checkpoint_dir, packages)?;
+        }
+
+        let zip_path = tmp.join("backup.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zip_path)?);
+        let meta = BackupMeta {
+            node: keys.username.clone(),
+            version: crate::VERSION.to_string(),
+            created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            packages: packages.clone(),
+        };
+        zip.start_file("backup.json", zip::write::SimpleFileOptions::default())?;
+        zip.write_all(&serde_json::to_vec_pretty(&meta)?)?;
+        add_dir(&mut zip, &checkpoint_dir, "kernel")?;
+        for dir in PACKAGE_DIRS {
+            let Ok(entries) = fs::read_dir(home.join(dir)) else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if packages.as_ref().is_some_and(|keep| !keep.contains(&name)) {
+                    continue;
+                }
+                add_dir(&mut zip, &entry.path(), &format!("{dir}/{name}"))?;
+            }
+        }
+        for file in NODE_FILES {
+            if home.join(file).exists() {
+                add_file(&mut zip, &home.join(file), file)?;
+            }
+        }
+        zip.finish()?;
+
+        seal(&zip_path, Path::new(archive), &password_hash)
+    })();
+    fs::remove_dir_all(&tmp).ok();
+    result
+}
+
+/// restore a backup into the empty home directory `home_directory_path`,
+/// returning the name of the node restored
+pub fn restore(home_directory_path: &str, archive: &str, password: &str) -> Result<String> {
+    let home = Path::new(home_directory_path);
+    if fs::read_dir(home)?.next().is_some() {
+        return Err(anyhow!("{home_directory_path} is not empty"));
+    }
+    let zip_path = home.join(".restore.zip");
+    let result = (|| -> Result<String> {
+        open(
+            Path::new(archive),
+            &zip_path,
+            &keygen::password_hash(password),
+        )?;
+        let mut zip = zip::ZipArchive::new(fs::File::open(&zip_path)?)?;
+        let meta: BackupMeta = serde_json::from_reader(zip.by_name("backup.json")?)?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            if file.name() == "backup.json" {
+                continue;
+            }
+            let Some(path) = file.enclosed_name() else {
+                return Err(anyhow!("backup holds unsafe path {}", file.name()));
+            };
+            let path = home.join(path);
+            if file.is_dir() {
+                fs::create_dir_all(&path)?;
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            std::io::copy(&mut file, &mut fs::File::create(&path)?)?;
+        }
+        Ok(meta.node)
+    })();
+    fs::remove_file(&zip_path).ok();
+    if result.is_err() {
+        // leave the home empty, to be restored into again
+        for entry in fs::read_dir(home)?.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                fs::remove_dir_all(path).ok();
+            } else {
+                fs::remove_file(path).ok();
+            }
+        }
+    }
+    result
+}
+
+/// drop the processes of packages not in `packages` from the process map in
+/// the state DB at `db_path`, so that a restore doesn't boot processes whose
+/// code was left out. runtime modules are kept, and distro packages are put
+/// back at boot either way.
+fn keep_processes(db_path: &Path, packages: &[String]) -> Result<()> {
+    let db = DB::open_default(db_path)?;
+    let key = KERNEL_PROCESS_ID.to_string().into_bytes();
+    let Some(value) = db.get(&key)? else {
+        return Ok(());
+    };
+    let mut process_map = bincode::deserialize::<ProcessMap>(&value)?;
+    process_map.retain(|process, _| {
+        let package = format!("{}:{}", process.package(), process.publisher());
+        package == "distro:sys" || packages.contains(&package)
+    });
+    db.put(&key, bincode::serialize(&process_map)?)?;
+    Ok(())
+}
+
+fn cipher(password_hash: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut key = [0u8; keygen::CREDENTIAL_LEN];
+    pbkdf2::derive(
+        keygen::PBKDF2_ALG,
+        NonZeroU32::new(keygen::ITERATIONS).unwrap(),
+        salt,
+        password_hash.as_bytes(),
+        &mut key,
+    );
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
+
+fn nonce(prefix: &[u8], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// encrypt the file at `plain` into `sealed`
+fn seal(plain: &Path, sealed: &Path, password_hash: &str) -> Result<()> {
+    let rng = ring::rand::SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    rng.fill(&mut salt).map_err(|_| anyhow!("no randomness"))?;
+    rng.fill(&mut prefix)
+        .map_err(|_| anyhow!("no randomness"))?;
+    let cipher = cipher(password_hash, &salt);
+
+    let mut input = fs::File::open(plain)?;
+    let len = input.metadata()?.len();
+    let mut output = std::io::BufWriter::new(fs::File::create(sealed)?);
+    output.write_all(MAGIC)?;
+    output.write_all(&salt)?;
+    output.write_all(&prefix)?;
+    let mut chunk = vec![0u8; CHUNK_LEN];
+    let mut read = 0u64;
+    let mut index = 0u32;
+    loop {
+        let n = (len - read).min(CHUNK_LEN as u64) as usize;
+        input.read_exact(&mut chunk[..n])?;
+        read += n as u64;
+        let last = read == len;
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce(&prefix, index, last)),
+                Payload {
+                    msg: &chunk[..n],
+                    aad: MAGIC,
+                },
+            )
+            .map_err(|_| anyhow!("couldn't encrypt backup"))?;
+        output.write_all(&(ciphertext.len() as u32).to_le_bytes())?;
+        output.write_all(&ciphertext)?;
+        if last {
+            break;
+        }
+        index = index.checked_add(1).context("backup too large")?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// decrypt the archive at `sealed` into `plain`
+fn open(sealed: &Path, plain: &Path, password_hash: &str) -> Result<()> {
+    let mut input = std::io::BufReader::new(fs::File::open(sealed)?);
+    let mut magic = [0u8; MAGIC.len()];
+    let mut salt = [0u8; SALT_LEN];
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    input.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(anyhow!("not a kinode backup"));
+    }
+    input.read_exact(&mut salt)?;
+    input.read_exact(&mut prefix)?;
+    let cipher = cipher(password_hash, &salt);
+
+    let mut output = std::io::BufWriter::new(fs::File::create(plain)?);
+    let mut index = 0u32;
+    loop {
+        let mut len = [0u8; 4];
+        input.read_exact(&mut len).context("backup is truncated")?;
+        let mut ciphertext = vec![0u8; u32::from_le_bytes(len) as usize];
+        input
+            .read_exact(&mut ciphertext)
+            .context("backup is truncated")?;
+        // the last chunk is the one that opens as the last
+        let opened = [false, true].into_iter().find_map(|last| {
+            cipher
+                .decrypt(
+                    Nonce::from_slice(&nonce(&prefix, index, last)),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: MAGIC,
+                    },
+                )
+                .ok()
+                .map(|plaintext| (plaintext, last))
+        });
+        let Some((plaintext, last)) = opened else {
+            return Err(anyhow!("wrong password, or backup is corrupt"));
+        };
+        output.write_all(&plaintext)?;
+        if last {
+            break;
+        }
+        index = index.checked_add(1).context("backup too large")?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn add_file(zip: &mut zip::ZipWriter<fs::File>, path: &Path, name: &str) -> Result<()> {
+    let len = fs::metadata(path)?.len();
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .large_file(len >= u32::MAX as u64);
+    zip.start_file(name, options)?;
+    std::io::copy(&mut fs::File::open(path)?, zip)?;
+    Ok(())
+}
+
+/// add every file under `dir` to the zip, under `name`
+fn add_dir(zip: &mut zip::ZipWriter<fs::File>, dir: &Path, name: &str) -> Result<()> {
+    let mut stack: Vec<(PathBuf, String)> = vec![(dir.to_path_buf(), name.to_string())];
+    while let Some((dir, name)) = stack.pop() {
+        zip.add_directory(name.as_str(), zip::write::SimpleFileOptions::default())?;
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let entry_name = format!("{name}/{}", entry.file_name().to_string_lossy());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                stack.push((entry.path(), entry_name));
+            } else if file_type.is_file() {
+                add_file(zip, &entry.path(), &entry_name)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::{OnExit, PersistedProcess, ProcessId};
+    use std::collections::HashMap;
+
+    fn persisted() -> PersistedProcess {
+        PersistedProcess {
+            wasm_bytes_handle: String::new(),
+            wit_version: None,
+            on_exit: OnExit::Restart,
+            capabilities: HashMap::new(),
+            public: false,
+        }
+    }
+
+    #[test]
+    fn processes_of_excluded_packages_are_dropped() {
+        let db_path = std::env::temp_dir().join(format!("kinode-backup-{}", rand::random::<u64>()));
+        let key = KERNEL_PROCESS_ID.to_string().into_bytes();
+        let process_map: ProcessMap = ["vfs:distro:sys", "chess:chess:sys", "chat:chat:other.os"]
+            .into_iter()
+            .map(|process| (process.parse::<ProcessId>().unwrap(), persisted()))
+            .collect();
+        {
+            let db = DB::open_default(&db_path).unwrap();
+            db.put(&key, bincode::serialize(&process_map).unwrap())
+                .unwrap();
+        }
+
+        keep_processes(&db_path, &["chess:sys".to_string()]).unwrap();
+
+        let db = DB::open_default(&db_path).unwrap();
+        let kept = bincode::deserialize::<ProcessMap>(&db.get(&key).unwrap().unwrap()).unwrap();
+        let mut kept: Vec<String> = kept.keys().map(|process| process.to_string()).collect();
+        kept.sort();
+        assert_eq!(kept, vec!["chess:chess:sys", "vfs:distro:sys"]);
+        drop(db);
+        fs::remove_dir_all(db_path).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+//! the runtime blob store: large payloads are written to disk once, named by
+//! the blake3 hash of their bytes, and referenced by a [`BlobHandle`] in
+//! [`KernelMessage`]s. identical payloads are only ever stored once.
+//!
+//! runtime modules (vfs, http_client) hand out handles for payloads of at
+//! least [`HANDLE_THRESHOLD`] bytes instead of holding them in memory. a
+//! handle is resolved back to bytes only where the bytes are needed: when the
+//! message is delivered to a process, or when it is sent to another node.
+//!
+//! each handle given out counts as a reference to its blob until a message
+//! carrying it is resolved. [`sweeper`] removes blobs that no message
+//! refers to and that have not been written or read for [`BLOB_TTL`], so a
+//! blob backing a queued message is kept however long the queue is. a message
+//! dropped before it is resolved never gives its reference back, so a
+//! reference is let go of after [`REFERENCE_TTL`].
+use lib::types::core::{BlobHandle, KernelMessage, LazyLoadBlob};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::{fs, io::AsyncReadExt, io::AsyncWriteExt, sync::Mutex};
+
+/// payloads at least this large are handed around by handle
+pub const HANDLE_THRESHOLD: usize = 1024 * 1024;
+/// remove blobs not touched in this long
+const BLOB_TTL: Duration = Duration::from_secs(60 * 60);
+/// let go of references not given back in this long
+const REFERENCE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub struct BlobStore {
+    dir: PathBuf,
+    /// by hash, the handles given out that no message has resolved yet, and
+    /// when the last was given out. held while a blob is moved into place or
+    /// removed, so the sweeper never removes one as it is handed out.
+    references: Mutex<HashMap<String, (usize, Instant)>>,
+}
+
+impl BlobStore {
+    pub async fn new(home_directory_path: &str) -> anyhow::Result<Self> {
+        let dir = PathBuf::from(home_directory_path).join("blobs");
+        // partial writes from a previous run are never going to be finished
+        if fs::metadata(&dir).await.is_ok() {
+            let mut entries = fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_name().to_string_lossy().starts_with("tmp-") {
+                    fs::remove_file(entry.path()).await?;
+                }
+            }
+        }
+        fs::create_dir_all(&dir).await?;
+        Ok(Self {
+            dir,
+            references: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn path(&self, hash: &str) -> std::io::Result<PathBuf> {
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid blob hash {hash}"),
+            ));
+        }
+        Ok(self.dir.join(hash))
+    }
+
+    /// start writing a blob whose bytes arrive in chunks
+    pub async fn writer(&self) -> std::io::Result<BlobWriter> {
+        let tmp_path = self.dir.join(format!("tmp-{:016x}", rand::random::<u64>()));
+        Ok(BlobWriter {
+            file: fs::File::create(&tmp_path).await?,
+            tmp_path,
+            hasher: blake3::Hasher::new(),
+            len: 0,
+        })
+    }
+
+    /// move a finished blob into place under its hash, and count the handle
+    /// given out for it. if the store already holds the same bytes, the new
+    /// copy is dropped.
+    pub async fn finish(
+        &self,
+        mut writer: BlobWriter,
+        mime: Option<String>,
+    ) -> std::io::Result<BlobHandle> {
+        writer.file.flush().await?;
+        let hash = writer.hasher.finalize().to_hex().to_string();
+        let path = self.dir.join(&hash);
+        let mut references = self.references.lock().await;
+        if fs::metadata(&path).await.is_ok() {
+            fs::remove_file(&writer.tmp_path).await?;
+            touch(&path).await;
+        } else {
+            fs::rename(&writer.tmp_path, &path).await?;
+        }
+        let (count, given) = references
+            .entry(hash.clone())
+            .or_insert((0, Instant::now()));
+        *count += 1;
+        *given = Instant::now();
+        Ok(BlobHandle {
+            hash,
+            mime,
+            len: writer.len,
+        })
+    }
+
+    /// store a copy of a file without reading it all into memory
+    pub async fn put_file(&self, path: &Path, mime: Option<String>) -> std::io::Result<BlobHandle> {
+        let mut file = fs::File::open(path).await?;
+        let mut writer = self.writer().await?;
+        let mut buffer = vec![0; CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write(&buffer[..read]).await?;
+        }
+        self.finish(writer, mime).await
+    }
+
+    pub async fn read(&self, handle: &BlobHandle) -> std::io::Result<LazyLoadBlob> {
+        let path = self.path(&handle.hash)?;
+        let bytes = fs::read(&path).await?;
+        touch(&path).await;
+        Ok(LazyLoadBlob {
+            mime: handle.mime.clone(),
+            bytes,
+        })
+    }
+
+    /// replace a message's blob handle with the bytes it refers to, giving
+    /// back the handle's reference
+    pub async fn resolve(&self, km: &mut KernelMessage) -> std::io::Result<()> {
+        let Some(handle) = km.blob_handle.take() else {
+            return Ok(());
+        };
+        let read = match km.lazy_load_blob {
+            None => self.read(&handle).await.map(|blob| {
+                km.lazy_load_blob = Some(blob);
+            }),
+            Some(_) => Ok(()),
+        };
+        self.release(&handle.hash).await;
+        read
+    }
+
+    async fn release(&self, hash: &str) {
+        let mut references = self.references.lock().await;
+        if let Some((count, _)) = references.get_mut(hash) {
+            *count -= 1;
+            if *count == 0 {
+                references.remove(hash);
+            }
+        }
+    }
+
+    /// remove blobs no message refers to that have not been touched in `ttl`,
+    /// and let go of references older than [`REFERENCE_TTL`]
+    async fn sweep(&self, ttl: Duration) -> std::io::Result<()> {
+        self.references
+            .lock()
+            .await
+            .retain(|_, (_, given)| given.elapsed() < REFERENCE_TTL);
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("tmp-") {
+                continue;
+            }
+            let references = self.references.lock().await;
+            if references.contains_key(&name) {
+                continue;
+            }
+            let stale = entry
+                .metadata()
+                .await?
+                .modified()
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .map(|age| age >= ttl)
+                .unwrap_or(false);
+            if stale {
+                fs::remove_file(entry.path()).await.ok();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// a blob being written, to be passed to [`BlobStore::finish`]
+pub struct BlobWriter {
+    file: fs::File,
+    tmp_path: PathBuf,
+    hasher: blake3::Hasher,
+    len: u64,
+}
+
+impl BlobWriter {
+    pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        self.hasher.update(chunk);
+        self.len += chunk.len() as u64;
+        self.file.write_all(chunk).await
+    }
+}
+
+/// mark a blob as recently used, so the sweeper keeps it
+async fn touch(path: &Path) {
+    let Ok(file) = fs::OpenOptions::new().append(true).open(path).await else {
+        return;
+    };
+    let file = file.into_std().await;
+    tokio::task::spawn_blocking(move || file.set_modified(SystemTime::now()))
+        .await
+        .ok();
+}
+
+pub async fn sweeper(store: Arc<BlobStore>) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        store.sweep(BLOB_TTL).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::{Message, Response};
+
+    async fn store() -> (BlobStore, PathBuf) {
+        let home = std::env::temp_dir().join(format!("kinode-blobs-{}", rand::random::<u64>()));
+        (BlobStore::new(home.to_str().unwrap()).await.unwrap(), home)
+    }
+
+    async fn put(store: &BlobStore, bytes: &[u8]) -> BlobHandle {
+        let mut writer = store.writer().await.unwrap();
+        writer.write(bytes).await.unwrap();
+        store.finish(writer, None).await.unwrap()
+    }
+
+    fn message(handle: BlobHandle) -> KernelMessage {
+        KernelMessage::builder()
+            .id(1)
+            .source(("node.os", "vfs:distro:sys".parse().unwrap()))
+            .target(("node.os", "chess:chess:sys".parse().unwrap()))
+            .message(Message::Response((
+                Response {
+                    inherit: false,
+                    body: vec![],
+                    metadata: None,
+                    capabilities: vec![],
+                },
+                None,
+            )))
+            .blob_handle(Some(handle))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn same_bytes_are_stored_once() {
+        let (store, home) = store().await;
+        let first = put(&store, b"hello").await;
+        let second = put(&store, b"hello").await;
+        assert_eq!(first, second);
+        assert_eq!(store.references.lock().await[&first.hash].0, 2);
+        let mut entries = fs::read_dir(&store.dir).await.unwrap();
+        let mut count = 0;
+        while entries.next_entry().await.unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 1);
+        fs::remove_dir_all(home).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn queued_blobs_survive_the_sweep() {
+        let (store, home) = store().await;
+        let handle = put(&store, b"queued").await;
+        let mut km = message(handle.clone());
+
+        // a message still carrying the handle keeps the blob
+        store.sweep(Duration::ZERO).await.unwrap();
+        assert!(fs::metadata(store.dir.join(&handle.hash)).await.is_ok());
+
+        // resolving it gives the reference back, and the blob may go
+        store.resolve(&mut km).await.unwrap();
+        assert_eq!(km.lazy_load_blob.unwrap().bytes, b"queued");
+        assert!(km.blob_handle.is_none());
+        assert!(store.references.lock().await.is_empty());
+        store.sweep(Duration::ZERO).await.unwrap();
+        assert!(fs::metadata(store.dir.join(&handle.hash)).await.is_err());
+        fs::remove_dir_all(home).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_hashes_are_refused() {
+        let (store, home) = store().await;
+        let handle = BlobHandle {
+            hash: "../../etc/passwd".to_string(),
+            mime: None,
+            len: 0,
+        };
+        assert!(store.read(&handle).await.is_err());
+        fs::remove_dir_all(home).await.unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+//! telling a local process which capability it lacked, when the node has the
+//! `cap-feedback` feature flag on: the kernel does so for a message it drops,
+//! and the vfs, kv and sqlite modules, through [`Denials`], for a request
+//! they refuse.
+use crate::kernel::flags::FeatureFlags;
+use lib::types::core::{
+    Address, Capability, CapabilityDenied, KernelMessage, Message, MessageSender, ProcessId,
+    Request,
+};
+
+/// tell the local sender of `km` which capability it lacked, with a
+/// [`CapabilityDenied`] request from `from`
+pub async fn report(
+    from: Address,
+    km: &KernelMessage,
+    capability: Capability,
+    send_to_loop: &MessageSender,
+) {
+    send(
+        from,
+        &km.source.process,
+        km.id,
+        &km.target,
+        capability,
+        send_to_loop,
+    )
+    .await;
+}
+
+async fn send(
+    from: Address,
+    to: &ProcessId,
+    id: u64,
+    target: &Address,
+    capability: Capability,
+    send_to_loop: &MessageSender,
+) {
+    let notice = CapabilityDenied {
+        id,
+        target: target.clone(),
+        capability,
+    };
+    KernelMessage::builder()
+        .id(rand::random())
+        .source(from.clone())
+        .target((from.node.as_str(), to.clone()))
+        .message(Message::Request(Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&notice).unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .unwrap()
+        .send(send_to_loop)
+        .await;
+}
+
+/// the request a runtime module is checking the capabilities of, kept to
+/// report what its sender lacked to it
+pub struct Denials {
+    /// the module, the sender and the request's ID, or `None` if the flag is
+    /// off or the sender remote
+    request: Option<(Address, ProcessId, u64)>,
+    send_to_loop: MessageSender,
+}
+
+impl Denials {
+    pub fn new(
+        feature_flags: &FeatureFlags,
+        module: Address,
+        km: &KernelMessage,
+        send_to_loop: &MessageSender,
+    ) -> Self {
+        let request = (feature_flags.enabled("cap-feedback") && km.source.node == module.node)
+            .then(|| (module, km.source.process.clone(), km.id));
+        Self {
+            request,
+            send_to_loop: send_to_loop.clone(),
+        }
+    }
+
+    /// tell the sender it lacked `capability`, if cap feedback is on
+    pub async fn report(&self, capability: Capability) {
+        if let Some((module, sender, id)) = &self.request {
+            send(
+                module.clone(),
+                sender,
+                *id,
+                module,
+                capability,
+                &self.send_to_loop,
+            )
+            .await;
+        }
+    }
+}
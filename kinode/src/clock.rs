@@ -0,0 +1,289 @@
+//! the node's clock. the host's clock may be off, so how far off it is, the
+//! offset, is estimated every [`SYNC_INTERVAL`] by asking the NTP servers
+//! given at boot with `--ntp-server`, none by default, and, if booted with
+//! `--clock-peers`, the nodes we are connected to. [`now`] is the host's time corrected by that offset:
+//! processes read it, with the offset and how sure of it we are, with the
+//! `clock()` host call, and when the message they last received was sent, by
+//! it, with `message-time()`.
+//!
+//! until a sync succeeds, the host's time is used as is. if every source
+//! fails, the last estimate is kept. a warning is printed when the host's
+//! clock is found to be off by more than [`SKEW_WARNING`], and when it is
+//! drifting from the estimate faster than [`DRIFT_WARNING`].
+use dashmap::DashMap;
+use lib::types::core::{PrintSender, Printout};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicU8, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::{lookup_host, UdpSocket};
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const NTP_TIMEOUT: Duration = Duration::from_secs(5);
+const NTP_PORT: u16 = 123;
+/// seconds from the NTP epoch, 1900, to the unix epoch, 1970
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+/// how often connected peers are asked for their time, with `--clock-peers`
+pub const PEER_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// a peer's answer older than this is not counted
+const PEER_SAMPLE_TTL: Duration = Duration::from_secs(30 * 60);
+/// fewest peers that must answer for their times to stand in for NTP
+const MIN_PEERS: usize = 3;
+/// a host clock off by more than this many milliseconds is warned of
+const SKEW_WARNING: u64 = 1000;
+/// a host clock drifting by more than this many milliseconds an hour is
+/// warned of
+const DRIFT_WARNING: u64 = 100;
+
+/// milliseconds to add to the host's time to get ours
+static OFFSET: AtomicI64 = AtomicI64::new(0);
+/// milliseconds [`OFFSET`] may be off by, or `u64::MAX` until a sync
+static ERROR: AtomicU64 = AtomicU64::new(u64::MAX);
+/// the [`Source`] of [`OFFSET`]
+static SOURCE: AtomicU8 = AtomicU8::new(Source::Host as u8);
+/// milliseconds an hour the host's clock is drifting from ours, or
+/// `i64::MIN` until two syncs have measured it
+static DRIFT: AtomicI64 = AtomicI64::new(i64::MIN);
+static PEER_CONSENSUS: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    /// when each unanswered request for a peer's time was sent, by request ID
+    static ref ASKED: DashMap<u64, Instant> = DashMap::new();
+    /// the offset to each peer's time, how far off it may be, and when it
+    /// was measured, by peer
+    static ref PEERS: DashMap<String, (i64, u64, Instant)> = DashMap::new();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Source {
+    Host = 0,
+    Ntp = 1,
+    Peers = 2,
+}
+
+/// what the clock knows, as of the last sync
+#[derive(Clone, Copy, Debug)]
+pub struct Estimate {
+    /// milliseconds since the unix epoch
+    pub now: u64,
+    /// milliseconds added to the host's time
+    pub offset: i64,
+    /// milliseconds the offset may be off by, if it has been synced
+    pub error: Option<u64>,
+    /// milliseconds an hour the host's clock drifts from ours, if measured
+    pub drift: Option<i64>,
+    pub source: Source,
+}
+
+impl std::fmt::Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} time, host clock off by {}ms",
+            self.source, self.offset
+        )?;
+        if let Some(error) = self.error {
+            write!(f, " ±{error}ms")?;
+        }
+        if let Some(drift) = self.drift {
+            write!(f, ", drifting {drift}ms/hour")?;
+        }
+        Ok(())
+    }
+}
+
+fn host_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+/// our time, in milliseconds since the unix epoch
+pub fn now() -> u64 {
+    (host_now() + OFFSET.load(Ordering::Relaxed)) as u64
+}
+
+pub fn estimate() -> Estimate {
+    let error = ERROR.load(Ordering::Relaxed);
+    let drift = DRIFT.load(Ordering::Relaxed);
+    Estimate {
+        now: now(),
+        offset: OFFSET.load(Ordering::Relaxed),
+        error: (error != u64::MAX).then_some(error),
+        drift: (drift != i64::MIN).then_some(drift),
+        source: match SOURCE.load(Ordering::Relaxed) {
+            1 => Source::Ntp,
+            2 => Source::Peers,
+            _ => Source::Host,
+        },
+    }
+}
+
+/// whether connected peers are asked for their time
+pub fn peer_consensus() -> bool {
+    PEER_CONSENSUS.load(Ordering::Relaxed)
+}
+
+/// note that a peer was asked for its time in the request `id`
+pub fn asked_peer(id: u64) {
+    ASKED.insert(id, Instant::now());
+}
+
+/// take a peer's answer to the request `id` for its time
+pub fn peer_answered(id: u64, peer: &str, their_now: u64) {
+    let Some((_, asked)) = ASKED.remove(&id) else {
+        return;
+    };
+    // the time they read is assumed to be halfway through the round trip
+    let half_trip = asked.elapsed().as_millis() as i64 / 2;
+    let offset = their_now as i64 + half_trip - host_now();
+    PEERS.insert(peer.to_string(), (offset, half_trip as u64, Instant::now()));
+}
+
+/// sync the clock every [`SYNC_INTERVAL`]
+pub async fn sync(
+    ntp_servers: Vec<String>,
+    peer_consensus: bool,
+    print_sender: PrintSender,
+) -> anyhow::Result<()> {
+    PEER_CONSENSUS.store(peer_consensus, Ordering::Relaxed);
+    let mut interval = tokio::time::interval(SYNC_INTERVAL);
+    let mut last_sync: Option<(Instant, i64)> = None;
+    let mut skew_warned = false;
+    let mut drift_warned = false;
+    loop {
+        interval.tick().await;
+        // answers that never came are not coming
+        ASKED.retain(|_, asked| asked.elapsed() < PEER_SAMPLE_TTL);
+        PEERS.retain(|_, (_, _, at)| at.elapsed() < PEER_SAMPLE_TTL);
+
+        let mut ntp = vec![];
+        for server in &ntp_servers {
+            match query_ntp(server).await {
+                Ok(sample) => ntp.push(sample),
+                Err(e) => {
+                    Printout::new(2, format!("clock: couldn't sync with {server}: {e}"))
+                        .send(&print_sender)
+                        .await;
+                }
+            }
+        }
+        let mut peers: Vec<(i64, u64)> = PEERS
+            .iter()
+            .map(|entry| (entry.value().0, entry.value().1))
+            .collect();
+        let peers = if peers.len() >= MIN_PEERS {
+            median(&mut peers)
+        } else {
+            None
+        };
+
+        let ((offset, error), source) = match (median(&mut ntp), peers) {
+            (Some(ntp), Some(peers)) => {
+                let disagreement = ntp.0.abs_diff(peers.0);
+                if disagreement > SKEW_WARNING {
+                    Printout::new(
+                        1,
+                        format!(
+                            "clock: NTP and our peers disagree on the time by {disagreement}ms"
+                        ),
+                    )
+                    .send(&print_sender)
+                    .await;
+                }
+                (ntp, Source::Ntp)
+            }
+            (Some(ntp), None) => (ntp, Source::Ntp),
+            (None, Some(peers)) => (peers, Source::Peers),
+            (None, None) => continue,
+        };
+        OFFSET.store(offset, Ordering::Relaxed);
+        ERROR.store(error, Ordering::Relaxed);
+        SOURCE.store(source as u8, Ordering::Relaxed);
+
+        if let Some((at, last_offset)) = last_sync {
+            let hours = at.elapsed().as_secs_f64() / 3600.0;
+            let drift = ((offset - last_offset) as f64 / hours) as i64;
+            DRIFT.store(drift, Ordering::Relaxed);
+            let drifting = drift.unsigned_abs() > DRIFT_WARNING;
+            if drifting && !drift_warned {
+                Printout::new(
+                    0,
+                    format!("clock: the host's clock is drifting by {drift}ms an hour"),
+                )
+                .send(&print_sender)
+                .await;
+            }
+            drift_warned = drifting;
+        }
+        last_sync = Some((Instant::now(), offset));
+
+        let skewed = offset.unsigned_abs() > SKEW_WARNING;
+        if skewed && !skew_warned {
+            Printout::new(
+                0,
+                format!(
+                    "clock: the host's clock is off by {offset}ms, by {source:?} time; \
+                     the node will use {source:?} time"
+                ),
+            )
+            .send(&print_sender)
+            .await;
+        }
+        skew_warned = skewed;
+    }
+}
+
+/// the sample with the median offset
+fn median(samples: &mut [(i64, u64)]) -> Option<(i64, u64)> {
+    samples.sort_by_key(|(offset, _)| *offset);
+    samples.get(samples.len() / 2).copied()
+}
+
+/// ask an NTP server, as `host`, `host:port`, an IPv6 address or
+/// `[address]:port`, for the time, returning the offset to it and how far
+/// off that may be, in milliseconds
+async fn query_ntp(server: &str) -> anyhow::Result<(i64, u64)> {
+    // a bare IPv6 address has colons of its own, so is no `host:port`
+    let address = match server.parse::<IpAddr>() {
+        Ok(ip) => Some(SocketAddr::from((ip, NTP_PORT))),
+        Err(_) if server.contains(':') => lookup_host(server).await?.next(),
+        Err(_) => lookup_host((server, NTP_PORT)).await?.next(),
+    }
+    .ok_or_else(|| anyhow::anyhow!("{server} has no address"))?;
+    let socket = UdpSocket::bind(if address.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    })
+    .await?;
+    socket.connect(address).await?;
+    // version 4, client mode
+    let mut packet = [0u8; 48];
+    packet[0] = 0x23;
+
+    let sent = host_now();
+    socket.send(&packet).await?;
+    let len = tokio::time::timeout(NTP_TIMEOUT, socket.recv(&mut packet)).await??;
+    let received = host_now();
+
+    if len < 48 || packet[0] & 0x7 != 4 {
+        return Err(anyhow::anyhow!("not an NTP server response"));
+    }
+    if packet[1] == 0 {
+        return Err(anyhow::anyhow!("server refused to answer"));
+    }
+    let server_received = ntp_millis(&packet[32..40]);
+    let server_sent = ntp_millis(&packet[40..48]);
+    let offset = ((server_received - sent) + (server_sent - received)) / 2;
+    let round_trip = (received - sent) - (server_sent - server_received);
+    Ok((offset, round_trip.max(0) as u64 / 2))
+}
+
+/// an NTP timestamp as milliseconds since the unix epoch
+fn ntp_millis(timestamp: &[u8]) -> i64 {
+    let seconds = u32::from_be_bytes(timestamp[..4].try_into().unwrap()) as i64;
+    let fraction = u32::from_be_bytes(timestamp[4..8].try_into().unwrap()) as i64;
+    (seconds - NTP_UNIX_OFFSET) * 1000 + ((fraction * 1000) >> 32)
+}
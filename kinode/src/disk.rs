@@ -0,0 +1,100 @@
+//! disk-space monitoring. the space free on the filesystem holding the home
+//! directory is checked every [`CHECK_INTERVAL`].
+//!
+//! below [`LOW_SPACE`], a warning is printed and non-essential writes are
+//! paused: the terminal log, post-mortems of crashed processes, and copies
+//! of large VFS reads into the blob store. writes that would leave less than
+//! [`RESERVED_SPACE`] free are refused by the modules making them with a
+//! `NoSpace` error, rather than failing partway with a full disk.
+use lib::types::core::{PrintSender, Printout};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// below this many bytes free, non-essential writes are paused
+const LOW_SPACE: u64 = 1024 * 1024 * 1024;
+/// writes that would leave fewer bytes free than this are refused
+const RESERVED_SPACE: u64 = 128 * 1024 * 1024;
+
+/// bytes free as of the last check, less those reserved since. until the
+/// first check, there is assumed to be room.
+static FREE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// whether non-essential writes should be skipped
+pub fn is_low() -> bool {
+    FREE.load(Ordering::Relaxed) < LOW_SPACE
+}
+
+/// account for a write of `len` bytes, or fail with the bytes free if it
+/// would leave less than [`RESERVED_SPACE`]
+pub fn reserve(len: u64) -> Result<(), u64> {
+    FREE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |free| {
+        (free.saturating_sub(len) >= RESERVED_SPACE).then(|| free - len)
+    })
+    .map(|_| ())
+}
+
+pub async fn monitor(home_directory_path: String, print_sender: PrintSender) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    // 0: enough space, 1: low, 2: below the reserve
+    let mut level = 0;
+    let mut warned_unreadable = false;
+    loop {
+        interval.tick().await;
+        let path = home_directory_path.clone();
+        let free = match tokio::task::spawn_blocking(move || free_space(&path)).await? {
+            Ok(free) => free,
+            Err(e) => {
+                if !warned_unreadable {
+                    Printout::new(1, format!("disk: couldn't read free space: {e}"))
+                        .send(&print_sender)
+                        .await;
+                    warned_unreadable = true;
+                }
+                continue;
+            }
+        };
+        FREE.store(free, Ordering::Relaxed);
+
+        let new_level = if free < RESERVED_SPACE {
+            2
+        } else if free < LOW_SPACE {
+            1
+        } else {
+            0
+        };
+        if new_level > level {
+            Printout::new(
+                0,
+                format!(
+                    "disk: only {} MiB free in {home_directory_path}: {}",
+                    free / (1024 * 1024),
+                    if new_level == 2 {
+                        "refusing writes until space is freed"
+                    } else {
+                        "pausing logs and caches"
+                    },
+                ),
+            )
+            .send(&print_sender)
+            .await;
+        } else if new_level == 0 && level > 0 {
+            Printout::new(
+                0,
+                format!(
+                    "disk: {} MiB free again, resuming logs and caches",
+                    free / (1024 * 1024)
+                ),
+            )
+            .send(&print_sender)
+            .await;
+        }
+        level = new_level;
+    }
+}
+
+/// bytes available to us on the filesystem holding `path`
+fn free_space(path: &str) -> nix::Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(path)?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
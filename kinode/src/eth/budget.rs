@@ -0,0 +1,357 @@
+use dashmap::DashMap;
+use lib::types::core::{Address, ETH_PROCESS_ID};
+use lib::types::eth::{EthError, ProcessUsage, RequestBudget};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// Shares upstream RPC calls out among processes, as set by a
+/// [`RequestBudget`]. Each process has a token bucket of calls per minute;
+/// past it, a call waits for its next token. Past the limit of calls in
+/// flight, each waiting process gets the next free slot in turn, however many
+/// calls it has waiting.
+pub struct Budget {
+    scheduler: Mutex<Scheduler>,
+    usage: DashMap<Address, ProcessUsage>,
+}
+
+struct Scheduler {
+    budget: RequestBudget,
+    buckets: HashMap<Address, Bucket>,
+    in_flight: u32,
+    /// processes with calls waiting for a slot, in the order they get one
+    turns: VecDeque<Address>,
+    waiting: HashMap<Address, VecDeque<oneshot::Sender<()>>>,
+}
+
+struct Bucket {
+    /// below zero when calls have been promised tokens not yet refilled
+    tokens: f64,
+    updated: Instant,
+}
+
+/// an upstream call's slot, freed for the next waiting process when dropped
+pub struct Permit<'a> {
+    budget: &'a Budget,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.budget.release();
+    }
+}
+
+/// a call waiting for a slot. if its request times out just as it is granted
+/// one, the slot goes straight to the next in turn.
+struct Turn<'a> {
+    budget: &'a Budget,
+    recv_turn: oneshot::Receiver<()>,
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        if self.recv_turn.try_recv().is_ok() {
+            self.budget.release();
+        }
+    }
+}
+
+impl Budget {
+    pub fn new(budget: RequestBudget) -> Self {
+        Budget {
+            scheduler: Mutex::new(Scheduler {
+                budget,
+                buckets: HashMap::new(),
+                in_flight: 0,
+                turns: VecDeque::new(),
+                waiting: HashMap::new(),
+            }),
+            usage: DashMap::new(),
+        }
+    }
+
+    pub fn get(&self) -> RequestBudget {
+        self.scheduler.lock().unwrap().budget.clone()
+    }
+
+    pub fn set(&self, budget: RequestBudget) {
+        let mut scheduler = self.scheduler.lock().unwrap();
+        scheduler.budget = budget;
+        // a raised limit may free slots for those waiting
+        scheduler.grant();
+    }
+
+    /// the process a request counts against: a remote node's requests all
+    /// count as its eth module's, whichever of its processes they name
+    pub fn payer(our: &str, source: &Address) -> Address {
+        if source.node == our {
+            source.clone()
+        } else {
+            Address::new(&source.node, ETH_PROCESS_ID.clone())
+        }
+    }
+
+    pub fn requested(&self, payer: &Address) {
+        self.usage_of(payer).requests += 1;
+    }
+
+    pub fn cache_hit(&self, payer: &Address) {
+        self.usage_of(payer).cache_hits += 1;
+    }
+
+    /// wait for `payer`'s next upstream call to be allowed, failing at once
+    /// if its budget wouldn't allow it within `max_wait`
+    pub async fn acquire(
+        &self,
+        payer: &Address,
+        max_wait: Duration,
+    ) -> Result<Permit<'_>, EthError> {
+        let token_wait = self.scheduler.lock().unwrap().take_token(payer, max_wait);
+        let Some(token_wait) = token_wait else {
+            self.usage_of(payer).rejected += 1;
+            return Err(EthError::QuotaExceeded);
+        };
+        tokio::time::sleep(token_wait).await;
+        let turn = {
+            let mut scheduler = self.scheduler.lock().unwrap();
+            if scheduler.has_free_slot() {
+                scheduler.in_flight += 1;
+                None
+            } else {
+                Some(Turn {
+                    budget: self,
+                    recv_turn: scheduler.wait_turn(payer),
+                })
+            }
+        };
+        let mut usage = self.usage_of(payer);
+        usage.upstream_calls += 1;
+        if !token_wait.is_zero() || turn.is_some() {
+            usage.queued += 1;
+        }
+        drop(usage);
+        if let Some(mut turn) = turn {
+            // the scheduler only drops a turn's sender after granting it
+            (&mut turn.recv_turn)
+                .await
+                .map_err(|_| EthError::RpcTimeout)?;
+        }
+        Ok(Permit { budget: self })
+    }
+
+    fn release(&self) {
+        let mut scheduler = self.scheduler.lock().unwrap();
+        scheduler.in_flight -= 1;
+        scheduler.grant();
+    }
+
+    pub fn usage(&self) -> Vec<ProcessUsage> {
+        let mut usage: Vec<ProcessUsage> = self.usage.iter().map(|u| u.clone()).collect();
+        usage.sort_by(|a, b| b.upstream_calls.cmp(&a.upstream_calls));
+        usage
+    }
+
+    fn usage_of(&self, payer: &Address) -> dashmap::mapref::one::RefMut<'_, Address, ProcessUsage> {
+        self.usage
+            .entry(payer.clone())
+            .or_insert_with(|| ProcessUsage {
+                process: payer.clone(),
+                ..Default::default()
+            })
+    }
+}
+
+impl Scheduler {
+    /// take a token from `payer`'s bucket, returning how long until it is
+    /// refilled, or None if that is past `max_wait`
+    fn take_token(&mut self, payer: &Address, max_wait: Duration) -> Option<Duration> {
+        let Some(per_minute) = self.budget.calls_per_minute else {
+            return Some(Duration::ZERO);
+        };
+        let capacity = per_minute as f64;
+        let per_second = capacity / 60.0;
+        let bucket = self.buckets.entry(payer.clone()).or_insert(Bucket {
+            tokens: capacity,
+            updated: Instant::now(),
+        });
+        let refilled = bucket.updated.elapsed().as_secs_f64() * per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.updated = Instant::now();
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Some(Duration::ZERO);
+        }
+        if per_second == 0.0 {
+            return None;
+        }
+        let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / per_second);
+        if wait > max_wait {
+            return None;
+        }
+        bucket.tokens -= 1.0;
+        Some(wait)
+    }
+
+    fn has_free_slot(&self) -> bool {
+        self.turns.is_empty()
+            && self
+                .budget
+                .max_concurrent
+                .map_or(true, |max| self.in_flight < max)
+    }
+
+    fn wait_turn(&mut self, payer: &Address) -> oneshot::Receiver<()> {
+        let (send_turn, recv_turn) = oneshot::channel();
+        let queue = self.waiting.entry(payer.clone()).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(payer.clone());
+        }
+        queue.push_back(send_turn);
+        recv_turn
+    }
+
+    /// hand free slots to waiting processes, in turn
+    fn grant(&mut self) {
+        while self
+            .budget
+            .max_concurrent
+            .map_or(true, |max| self.in_flight < max)
+        {
+            let Some(payer) = self.turns.pop_front() else {
+                return;
+            };
+            let queue = self.waiting.get_mut(&payer).unwrap();
+            let send_turn = queue.pop_front().unwrap();
+            if queue.is_empty() {
+                self.waiting.remove(&payer);
+            } else {
+                self.turns.push_back(payer);
+            }
+            // a call whose request timed out while waiting no longer wants it
+            if send_turn.send(()).is_ok() {
+                self.in_flight += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str) -> Address {
+        Address::new("our.os", (name, "app", "publisher.os"))
+    }
+
+    fn scheduler(budget: RequestBudget) -> Scheduler {
+        Scheduler {
+            budget,
+            buckets: HashMap::new(),
+            in_flight: 0,
+            turns: VecDeque::new(),
+            waiting: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn no_budget_is_no_limit() {
+        let mut scheduler = scheduler(RequestBudget::default());
+        for _ in 0..1000 {
+            assert_eq!(
+                scheduler.take_token(&process("a"), Duration::ZERO),
+                Some(Duration::ZERO)
+            );
+        }
+        scheduler.in_flight = 1000;
+        assert!(scheduler.has_free_slot());
+    }
+
+    #[test]
+    fn tokens_run_out_then_wait() {
+        let mut scheduler = scheduler(RequestBudget {
+            calls_per_minute: Some(2),
+            max_concurrent: None,
+        });
+        let a = process("a");
+        let long = Duration::from_secs(120);
+        assert_eq!(scheduler.take_token(&a, long), Some(Duration::ZERO));
+        assert_eq!(scheduler.take_token(&a, long), Some(Duration::ZERO));
+        // a token comes every 30 seconds
+        let wait = scheduler.take_token(&a, long).unwrap();
+        assert!(wait > Duration::from_secs(29) && wait <= Duration::from_secs(30));
+        // too long a wait is refused, and takes nothing
+        assert_eq!(scheduler.take_token(&a, Duration::from_secs(1)), None);
+        // each process has its own bucket
+        assert_eq!(
+            scheduler.take_token(&process("b"), Duration::ZERO),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn zero_per_minute_refuses() {
+        let mut scheduler = scheduler(RequestBudget {
+            calls_per_minute: Some(0),
+            max_concurrent: None,
+        });
+        assert_eq!(
+            scheduler.take_token(&process("a"), Duration::from_secs(3600)),
+            None
+        );
+    }
+
+    #[test]
+    fn slots_go_round_in_turn() {
+        let mut scheduler = scheduler(RequestBudget {
+            calls_per_minute: None,
+            max_concurrent: Some(1),
+        });
+        scheduler.in_flight = 1;
+        assert!(!scheduler.has_free_slot());
+        let (a, b) = (process("a"), process("b"));
+        let mut a1 = scheduler.wait_turn(&a);
+        let mut a2 = scheduler.wait_turn(&a);
+        let mut b1 = scheduler.wait_turn(&b);
+
+        let release = |scheduler: &mut Scheduler| {
+            scheduler.in_flight -= 1;
+            scheduler.grant();
+        };
+        release(&mut scheduler);
+        assert!(a1.try_recv().is_ok());
+        assert!(b1.try_recv().is_err());
+        release(&mut scheduler);
+        // b goes before a's second, though a asked first
+        assert!(b1.try_recv().is_ok());
+        assert!(a2.try_recv().is_err());
+        release(&mut scheduler);
+        assert!(a2.try_recv().is_ok());
+        assert!(scheduler.turns.is_empty() && scheduler.waiting.is_empty());
+    }
+
+    #[test]
+    fn abandoned_turns_are_skipped() {
+        let mut scheduler = scheduler(RequestBudget {
+            calls_per_minute: None,
+            max_concurrent: Some(1),
+        });
+        scheduler.in_flight = 1;
+        drop(scheduler.wait_turn(&process("a")));
+        let mut b = scheduler.wait_turn(&process("b"));
+        scheduler.in_flight -= 1;
+        scheduler.grant();
+        assert!(b.try_recv().is_ok());
+        assert_eq!(scheduler.in_flight, 1);
+    }
+
+    #[test]
+    fn remote_nodes_pay_as_one() {
+        let remote = Address::new("other.os", ("a", "app", "publisher.os"));
+        assert_eq!(
+            Budget::payer("our.os", &remote),
+            Address::new("other.os", ETH_PROCESS_ID.clone())
+        );
+        assert_eq!(Budget::payer("our.os", &process("a")), process("a"));
+    }
+}
@@ -0,0 +1,220 @@
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+
+/// most responses the cache holds; past it, new ones aren't cached until
+/// some expire
+const MAX_ENTRIES: usize = 10_000;
+/// for answers that may change with the next block
+const MOVING_TTL: Duration = Duration::from_secs(2);
+/// for answers about a block given by number or hash, or a transaction
+const PINNED_TTL: Duration = Duration::from_secs(5 * 60);
+const CHAIN_ID_TTL: Duration = Duration::from_secs(60 * 60);
+/// blocks a block given by number must be behind the newest we have seen to
+/// be taken as final, and its answers as ones a reorg won't change
+const FINALITY_DEPTH: u64 = 64;
+
+/// Responses to [`lib::types::eth::EthAction::Request`]s shared by every
+/// process, by chain, method and params, each kept for as long as its method
+/// and block make it safe to.
+#[derive(Default)]
+pub struct Cache {
+    entries: DashMap<(u64, String), (Instant, serde_json::Value)>,
+    /// the newest block number each chain has answered `eth_blockNumber` with
+    heads: DashMap<u64, u64>,
+}
+
+impl Cache {
+    pub fn get(
+        &self,
+        chain_id: u64,
+        method: &str,
+        params: &serde_json::Value,
+    ) -> Option<serde_json::Value> {
+        let key = (chain_id, format!("{method}{params}"));
+        let entry = self.entries.get(&key)?;
+        if entry.0 > Instant::now() {
+            return Some(entry.1.clone());
+        }
+        drop(entry);
+        self.entries.remove(&key);
+        None
+    }
+
+    pub fn insert(
+        &self,
+        chain_id: u64,
+        method: &str,
+        params: &serde_json::Value,
+        value: &serde_json::Value,
+    ) {
+        if method == "eth_blockNumber" {
+            if let Some(number) = block_number(value) {
+                let mut head = self.heads.entry(chain_id).or_insert(number);
+                *head = (*head).max(number);
+            }
+        }
+        let head = self.heads.get(&chain_id).map(|head| *head);
+        // null is what we get for a transaction not yet mined
+        let Some(ttl) = ttl(method, params, head).filter(|_| !value.is_null()) else {
+            return;
+        };
+        if self.entries.len() >= MAX_ENTRIES {
+            let now = Instant::now();
+            self.entries.retain(|_, (expires, _)| *expires > now);
+            if self.entries.len() >= MAX_ENTRIES {
+                return;
+            }
+        }
+        self.entries.insert(
+            (chain_id, format!("{method}{params}")),
+            (Instant::now() + ttl, value.clone()),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// how long an answer to `method` may be reused, if at all, given the
+/// newest block number of its chain we know of
+fn ttl(method: &str, params: &serde_json::Value, head: Option<u64>) -> Option<Duration> {
+    match method {
+        "eth_chainId" => Some(CHAIN_ID_TTL),
+        "eth_getBlockByHash" | "eth_getTransactionByHash" | "eth_getTransactionReceipt" => {
+            Some(PINNED_TTL)
+        }
+        "eth_blockNumber" | "eth_gasPrice" => Some(MOVING_TTL),
+        // a process that reads logs a range at a time moves on past a range
+        // once it has its logs, so a stale answer would miss some for good
+        "eth_getLogs" => pinned(method, params, head).then_some(PINNED_TTL),
+        "eth_getBalance"
+        | "eth_getCode"
+        | "eth_call"
+        | "eth_getStorageAt"
+        | "eth_getBlockByNumber" => {
+            if pinned(method, params, head) {
+                Some(PINNED_TTL)
+            } else {
+                Some(MOVING_TTL)
+            }
+        }
+        // a stale nonce or gas estimate would break the transaction sent with
+        // it, and sending one must always go upstream
+        _ => None,
+    }
+}
+
+/// whether `params` name the block that `method` reads by hash, or by the
+/// number of a final block, rather than by a tag like "latest", by the number
+/// of a block a reorg could replace, or by leaving it out
+fn pinned(method: &str, params: &serde_json::Value, head: Option<u64>) -> bool {
+    let is_final = |value: &serde_json::Value| {
+        block_number(value).is_some_and(|number| {
+            head.is_some_and(|head| number.saturating_add(FINALITY_DEPTH) <= head)
+        })
+    };
+    let block = match method {
+        "eth_getLogs" => {
+            return params.get(0).is_some_and(|filter| {
+                filter.get("blockHash").is_some() || filter.get("toBlock").is_some_and(is_final)
+            })
+        }
+        "eth_getBlockByNumber" => params.get(0),
+        "eth_getStorageAt" => params.get(2),
+        _ => params.get(1),
+    };
+    // a block number or hash, or an EIP-1898 block object
+    block.is_some_and(|block| {
+        is_final(block)
+            || block.get("blockHash").is_some()
+            || block.get("blockNumber").is_some_and(is_final)
+    })
+}
+
+/// a block number given as a hex string
+fn block_number(value: &serde_json::Value) -> Option<u64> {
+    u64::from_str_radix(value.as_str()?.strip_prefix("0x")?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn logs_to(to_block: &str) -> serde_json::Value {
+        json!([{ "fromBlock": "0x1", "toBlock": to_block, "address": "0xabc" }])
+    }
+
+    #[test]
+    fn logs_are_cached_once_final() {
+        let cache = Cache::default();
+        let logs = json!([{ "data": "0x" }]);
+        // with no head known, no block number is final
+        cache.insert(1, "eth_getLogs", &logs_to("0x100"), &logs);
+        assert_eq!(cache.get(1, "eth_getLogs", &logs_to("0x100")), None);
+
+        cache.insert(1, "eth_blockNumber", &json!([]), &json!("0x1000"));
+        cache.insert(1, "eth_getLogs", &logs_to("0x100"), &logs);
+        assert_eq!(
+            cache.get(1, "eth_getLogs", &logs_to("0x100")),
+            Some(logs.clone())
+        );
+        // too near the head, or by tag
+        cache.insert(1, "eth_getLogs", &logs_to("0xfff"), &logs);
+        assert_eq!(cache.get(1, "eth_getLogs", &logs_to("0xfff")), None);
+        cache.insert(1, "eth_getLogs", &logs_to("latest"), &logs);
+        assert_eq!(cache.get(1, "eth_getLogs", &logs_to("latest")), None);
+        // heads are per chain
+        cache.insert(2, "eth_getLogs", &logs_to("0x100"), &logs);
+        assert_eq!(cache.get(2, "eth_getLogs", &logs_to("0x100")), None);
+    }
+
+    #[test]
+    fn heads_only_move_forward() {
+        let cache = Cache::default();
+        cache.insert(1, "eth_blockNumber", &json!([]), &json!("0x1000"));
+        cache.insert(1, "eth_blockNumber", &json!([]), &json!("0x10"));
+        assert_eq!(cache.heads.get(&1).map(|head| *head), Some(0x1000));
+    }
+
+    #[test]
+    fn ttls_follow_the_block() {
+        let head = Some(0x1000);
+        let by_hash = json!([{ "blockHash": "0xdead" }]);
+        assert_eq!(ttl("eth_getLogs", &by_hash, None), Some(PINNED_TTL));
+        assert_eq!(
+            ttl("eth_call", &json!([{}, "0x100"]), head),
+            Some(PINNED_TTL)
+        );
+        assert_eq!(
+            ttl("eth_call", &json!([{}, "0xfff"]), head),
+            Some(MOVING_TTL)
+        );
+        assert_eq!(
+            ttl("eth_getBalance", &json!(["0xabc", "latest"]), head),
+            Some(MOVING_TTL)
+        );
+        assert_eq!(
+            ttl(
+                "eth_getStorageAt",
+                &json!(["0xabc", "0x0", { "blockNumber": "0x1" }]),
+                head
+            ),
+            Some(PINNED_TTL)
+        );
+        assert_eq!(ttl("eth_sendRawTransaction", &json!(["0x"]), head), None);
+        assert_eq!(
+            ttl("eth_getTransactionCount", &json!(["0xabc"]), head),
+            None
+        );
+    }
+
+    #[test]
+    fn unmined_transactions_are_not_cached() {
+        let cache = Cache::default();
+        let params = json!(["0xhash"]);
+        cache.insert(1, "eth_getTransactionReceipt", &params, &json!(null));
+        assert_eq!(cache.get(1, "eth_getTransactionReceipt", &params), None);
+    }
+}
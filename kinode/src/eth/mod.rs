@@ -5,6 +5,7 @@ use alloy::rpc::json_rpc::RpcError;
 use anyhow::Result;
 use dashmap::DashMap;
 use lib::types::core::*;
+use lib::types::errors::ModuleError;
 use lib::types::eth::*;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
@@ -12,6 +13,9 @@ use std::sync::Arc;
 use tokio::task::JoinHandle;
 use url::Url;
 
+mod budget;
+mod cache;
+mod served;
 mod subscription;
 
 /// meta-type for all incoming requests we need to handle
@@ -26,6 +30,8 @@ enum IncomingReq {
     EthSubResult(EthSubResult),
     /// a remote node who uses our provider keeping their subscription alive
     SubKeepalive(u64),
+    /// a node provider giving us the `"serve"` capability, attached
+    ServeGranted(served::ServeGranted),
 }
 
 /// mapping of chain id to ordered lists of providers
@@ -154,6 +160,16 @@ struct ModuleState {
     active_subscriptions: ActiveSubscriptions,
     /// the set of response channels we have open for outstanding request tasks
     response_channels: ResponseChannels,
+    /// how upstream calls are shared out among processes
+    budget: Arc<budget::Budget>,
+    /// responses to requests, shared by all processes
+    cache: Arc<cache::Cache>,
+    /// what we have served the nodes that use us as their provider
+    served: Arc<served::Served>,
+    /// our networking key, that the capabilities we issue are signed with
+    our_public_key: Vec<u8>,
+    /// the `"serve"` capabilities node providers have given us
+    grants: Arc<served::Grants>,
     /// our sender for kernel event loop
     send_to_loop: MessageSender,
     /// our sender for terminal prints
@@ -197,6 +213,7 @@ fn valid_method(method: &str) -> Option<&'static str> {
 /// for the entire module.
 pub async fn provider(
     our: String,
+    our_networking_key: String,
     home_directory_path: String,
     configs: SavedConfigs,
     send_to_loop: MessageSender,
@@ -218,6 +235,7 @@ pub async fn provider(
                     public: false,
                     allow: HashSet::new(),
                     deny: HashSet::new(),
+                    require_cap: false,
                 };
                 access_settings
             }
@@ -227,6 +245,11 @@ pub async fn provider(
         &format!("eth: access settings loaded: {access_settings:?}"),
     )
     .await;
+    let budget: RequestBudget =
+        match tokio::fs::read_to_string(format!("{}/.eth_budget", home_directory_path)).await {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+            Err(_) => RequestBudget::default(),
+        };
 
     // initialize module state
     // fill out providers based on saved configs (possibly persisted, given to us)
@@ -238,6 +261,16 @@ pub async fn provider(
         providers: Arc::new(DashMap::new()),
         active_subscriptions: Arc::new(DashMap::new()),
         response_channels: Arc::new(DashMap::new()),
+        budget: Arc::new(budget::Budget::new(budget)),
+        cache: Arc::new(cache::Cache::default()),
+        served: Arc::new(served::Served::default()),
+        our_public_key: hex::decode(
+            our_networking_key
+                .strip_prefix("0x")
+                .unwrap_or(&our_networking_key),
+        )
+        .unwrap_or_default(),
+        grants: Arc::new(served::Grants::load(&home_directory_path).await),
         send_to_loop,
         print_tx,
     };
@@ -266,6 +299,7 @@ pub async fn provider(
                 ).await;
             }
             Some(km) = recv_in_client.recv() => {
+                crate::metrics::handled(&ETH_PROCESS_ID, &km);
                 let km_id = km.id;
                 let response_target = km.rsvp.as_ref().unwrap_or(&km.source).clone();
                 if let Err(e) = handle_message(
@@ -478,6 +512,23 @@ async fn handle_message(
                     .await;
                     Ok(())
                 }
+                IncomingReq::ServeGranted(_) => {
+                    let Message::Request(request) = &km.message else {
+                        return Ok(());
+                    };
+                    if km.source.process == *ETH_PROCESS_ID {
+                        state
+                            .grants
+                            .grant(
+                                &state.our,
+                                &state.home_directory_path,
+                                &km.source.node,
+                                &request.capabilities,
+                            )
+                            .await;
+                    }
+                    Ok(())
+                }
             }
         }
     }
@@ -500,6 +551,18 @@ async fn handle_eth_action(
                 "eth: got eth_action from unauthorized remote source",
             )
             .await;
+            state.served.denied(&km.source.node);
+            return Err(EthError::PermissionDenied);
+        }
+        if state.access_settings.require_cap
+            && !served::holds_serve_cap(&state.our, &state.our_public_key, &km)
+        {
+            verbose_print(
+                &state.print_tx,
+                "eth: got eth_action from remote source without the serve capability",
+            )
+            .await;
+            state.served.denied(&km.source.node);
             return Err(EthError::PermissionDenied);
         }
     }
@@ -530,6 +593,13 @@ async fn handle_eth_action(
     // before returning an error.
     match eth_action {
         EthAction::SubscribeLogs { sub_id, .. } => {
+            if km.source.node != *state.our {
+                if subscriptions_held(state, &km.source.node) >= served::MAX_SUBSCRIPTIONS_PER_NODE
+                {
+                    return Err(EthError::QuotaExceeded);
+                }
+                state.served.subscribed(&km.source.node);
+            }
             subscription::create_new_subscription(
                 state,
                 km.id,
@@ -605,9 +675,16 @@ async fn handle_eth_action(
             let providers = state.providers.clone();
             let response_channels = state.response_channels.clone();
             let print_tx = state.print_tx.clone();
+            let payer = budget::Budget::payer(&our, &km.source);
+            let budget = state.budget.clone();
+            let cache = state.cache.clone();
+            let served = state.served.clone();
+            let grants = state.grants.clone();
             tokio::spawn(async move {
-                match tokio::time::timeout(
-                    std::time::Duration::from_secs(timeout),
+                let timeout = std::time::Duration::from_secs(timeout);
+                let remote = km.source.node != our;
+                let response = tokio::time::timeout(
+                    timeout,
                     fulfill_request(
                         &our,
                         km.id,
@@ -616,10 +693,24 @@ async fn handle_eth_action(
                         providers,
                         receiver,
                         &print_tx,
+                        Upstream {
+                            payer: &payer,
+                            budget: &budget,
+                            cache: &cache,
+                            max_wait: timeout,
+                            relay: !remote,
+                            grants: &grants,
+                        },
                     ),
                 )
-                .await
-                {
+                .await;
+                if remote {
+                    served.requested(
+                        &km.source.node,
+                        matches!(response, Ok(EthResponse::Response { .. })),
+                    );
+                }
+                match response {
                     Ok(response) => {
                         kernel_message(
                             &our,
@@ -646,6 +737,20 @@ async fn handle_eth_action(
     Ok(())
 }
 
+/// what a request may take of our upstream providers, and for whom
+struct Upstream<'a> {
+    payer: &'a Address,
+    budget: &'a budget::Budget,
+    cache: &'a cache::Cache,
+    /// how long the request may wait for its turn
+    max_wait: std::time::Duration,
+    /// whether our node providers may be asked, as well as our RPC URLs. not
+    /// for requests from other nodes, lest providers ask each other in circles.
+    relay: bool,
+    /// the capabilities to show node providers
+    grants: &'a served::Grants,
+}
+
 async fn fulfill_request(
     our: &str,
     km_id: u64,
@@ -654,6 +759,7 @@ async fn fulfill_request(
     providers: Providers,
     mut remote_request_receiver: ProcessMessageReceiver,
     print_tx: &PrintSender,
+    upstream: Upstream<'_>,
 ) -> EthResponse {
     let EthAction::Request {
         chain_id,
@@ -666,6 +772,19 @@ async fn fulfill_request(
     let Some(method) = valid_method(&method) else {
         return EthResponse::Err(EthError::InvalidMethod(method.to_string()));
     };
+    upstream.budget.requested(upstream.payer);
+    if let Some(value) = upstream.cache.get(chain_id, method, params) {
+        upstream.budget.cache_hit(upstream.payer);
+        return EthResponse::Response { value };
+    }
+    let _permit = match upstream
+        .budget
+        .acquire(upstream.payer, upstream.max_wait)
+        .await
+    {
+        Ok(permit) => permit,
+        Err(e) => return EthResponse::Err(e),
+    };
     let mut urls = {
         // in code block to drop providers lock asap to avoid deadlock
         let Some(aps) = providers.get(&chain_id) else {
@@ -724,6 +843,7 @@ async fn fulfill_request(
                     )
                     .await;
                 }
+                upstream.cache.insert(chain_id, method, params, &value);
                 return EthResponse::Response { value };
             }
             Err(rpc_error) => {
@@ -764,6 +884,9 @@ async fn fulfill_request(
         }
     }
 
+    if !upstream.relay {
+        return EthResponse::Err(EthError::NoRpcForChain);
+    }
     let nodes = {
         // in code block to drop providers lock asap to avoid deadlock
         let Some(aps) = providers.get(&chain_id) else {
@@ -788,8 +911,12 @@ async fn fulfill_request(
             eth_action.clone(),
             send_to_loop,
             &mut remote_request_receiver,
+            upstream.grants,
         )
         .await;
+        if let EthResponse::Response { value } = &response {
+            upstream.cache.insert(chain_id, method, params, value);
+        }
         if let EthResponse::Err(e) = response {
             if let EthError::RpcMalformedResponse = e {
                 set_node_unusable(
@@ -816,24 +943,31 @@ async fn forward_to_node_provider(
     eth_action: EthAction,
     send_to_loop: &MessageSender,
     receiver: &mut ProcessMessageReceiver,
+    grants: &served::Grants,
 ) -> EthResponse {
     if !node_provider.usable || node_provider.kns_update.name == our {
         return EthResponse::Err(EthError::PermissionDenied);
     }
-    kernel_message(
-        our,
-        km_id,
-        Address {
-            node: node_provider.kns_update.name.clone(),
-            process: ETH_PROCESS_ID.clone(),
-        },
-        rsvp,
-        true,
-        Some(60), // TODO
-        eth_action.clone(),
-        &send_to_loop,
-    )
-    .await;
+    KernelMessage::builder()
+        .id(km_id)
+        .source((our, ETH_PROCESS_ID.clone()))
+        .target((
+            node_provider.kns_update.name.as_str(),
+            ETH_PROCESS_ID.clone(),
+        ))
+        .rsvp(rsvp)
+        .message(Message::Request(Request {
+            inherit: false,
+            expects_response: Some(60), // TODO
+            body: serde_json::to_vec(&eth_action).unwrap(),
+            metadata: None,
+            // the provider may want to see that it let us in
+            capabilities: grants.get(&node_provider.kns_update.name),
+        }))
+        .build()
+        .unwrap()
+        .send(send_to_loop)
+        .await;
     let Ok(Some(Ok(response_km))) =
         tokio::time::timeout(std::time::Duration::from_secs(30), receiver.recv()).await
     else {
@@ -881,6 +1015,7 @@ async fn handle_eth_config_action(
 
     let mut save_settings = false;
     let mut save_providers = false;
+    let mut save_budget = false;
 
     // modify our providers and access settings based on config action
     match eth_config_action {
@@ -971,6 +1106,57 @@ async fn handle_eth_config_action(
                 outstanding_requests: state.response_channels.iter().map(|e| *e.key()).collect(),
             };
         }
+        EthConfigAction::SetBudget(budget) => {
+            state.budget.set(budget);
+            save_budget = true;
+        }
+        EthConfigAction::RequireCap(require_cap) => {
+            state.access_settings.require_cap = require_cap;
+            save_settings = true;
+        }
+        EthConfigAction::GrantServe(node) => {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            caps_oracle
+                .send(CapMessage::FilterCaps {
+                    on: ETH_PROCESS_ID.clone(),
+                    caps: vec![served::serve_cap(&state.our, &node)],
+                    responder: tx,
+                })
+                .await
+                .expect("eth: capability oracle died!");
+            let Ok(capabilities) = rx.await else {
+                return EthConfigResponse::PermissionDenied;
+            };
+            KernelMessage::builder()
+                .id(rand::random())
+                .source((state.our.as_str(), ETH_PROCESS_ID.clone()))
+                .target((node.as_str(), ETH_PROCESS_ID.clone()))
+                .message(Message::Request(Request {
+                    inherit: false,
+                    expects_response: None,
+                    body: serde_json::to_vec(&served::ServeGranted::ServeGranted).unwrap(),
+                    metadata: None,
+                    capabilities,
+                }))
+                .build()
+                .unwrap()
+                .send(&state.send_to_loop)
+                .await;
+        }
+        EthConfigAction::GetServed => {
+            return EthConfigResponse::Served(
+                state
+                    .served
+                    .table(|node| subscriptions_held(state, node) as u64),
+            );
+        }
+        EthConfigAction::GetUsage => {
+            return EthConfigResponse::Usage {
+                budget: state.budget.get(),
+                cached: state.cache.len(),
+                processes: state.budget.usage(),
+            };
+        }
     }
     // save providers and/or access settings, depending on necessity, to disk
     if save_settings {
@@ -993,6 +1179,16 @@ async fn handle_eth_config_action(
             verbose_print(&state.print_tx, "eth: saved new provider settings").await;
         };
     }
+    if save_budget {
+        if let Ok(()) = tokio::fs::write(
+            format!("{}/.eth_budget", state.home_directory_path),
+            serde_json::to_string(&state.budget.get()).unwrap(),
+        )
+        .await
+        {
+            verbose_print(&state.print_tx, "eth: saved new request budget").await;
+        };
+    }
     EthConfigResponse::Ok
 }
 
@@ -1063,6 +1259,8 @@ async fn verbose_print(print_tx: &PrintSender, content: &str) {
         .send(Printout {
             verbosity: 2,
             content: content.to_string(),
+            source: None,
+            level: None,
         })
         .await;
 }
@@ -1119,17 +1317,37 @@ async fn kernel_message<T: Serialize>(
                     Response {
                         inherit: false,
                         body: serde_json::to_vec(&body).unwrap(),
-                        metadata: None,
+                        metadata: error_metadata(&body),
                         capabilities: vec![],
                     },
                     None,
                 ))
             },
             lazy_load_blob: None,
+            blob_handle: None,
+            sent_at: Some(std::time::Instant::now()),
         })
         .await;
 }
 
+/// the error envelope of a response that is an [`EthResponse::Err`]
+fn error_metadata<T: Serialize>(body: &T) -> Option<String> {
+    match serde_json::from_value(serde_json::to_value(body).ok()?).ok()? {
+        EthResponse::Err(error) => error.metadata(),
+        _ => None,
+    }
+}
+
+/// the subscriptions the processes of `node` hold with us
+fn subscriptions_held(state: &ModuleState, node: &str) -> usize {
+    state
+        .active_subscriptions
+        .iter()
+        .filter(|subs| subs.key().node == node)
+        .map(|subs| subs.len())
+        .sum()
+}
+
 fn find_index(vec: &Vec<&str>, item: &str) -> Option<usize> {
     vec.iter().enumerate().find_map(
         |(index, value)| {
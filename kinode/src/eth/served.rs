@@ -0,0 +1,208 @@
+use dashmap::DashMap;
+use lib::types::core::{Capability, KernelMessage, Message, NodeId, ETH_PROCESS_ID};
+use lib::types::eth::ServedNode;
+use serde::{Deserialize, Serialize};
+
+/// most subscriptions a node that uses us as its provider may hold at once
+pub const MAX_SUBSCRIPTIONS_PER_NODE: usize = 64;
+
+/// What we have served each node that uses us as its provider.
+#[derive(Default)]
+pub struct Served {
+    nodes: DashMap<NodeId, ServedNode>,
+}
+
+impl Served {
+    pub fn requested(&self, node: &str, ok: bool) {
+        let mut served = self.node(node);
+        served.requests += 1;
+        if !ok {
+            served.errors += 1;
+        }
+    }
+
+    pub fn denied(&self, node: &str) {
+        self.node(node).denied += 1;
+    }
+
+    pub fn subscribed(&self, node: &str) {
+        self.node(node).subscriptions += 1;
+    }
+
+    pub fn relayed(&self, node: &str) {
+        self.node(node).updates_relayed += 1;
+    }
+
+    /// `active` counts the subscriptions each node holds now
+    pub fn table(&self, active: impl Fn(&str) -> u64) -> Vec<ServedNode> {
+        let mut table: Vec<ServedNode> = self
+            .nodes
+            .iter()
+            .map(|served| ServedNode {
+                active_subscriptions: active(&served.node),
+                ..served.clone()
+            })
+            .collect();
+        table.sort_by(|a, b| b.requests.cmp(&a.requests));
+        table
+    }
+
+    fn node(&self, node: &str) -> dashmap::mapref::one::RefMut<'_, NodeId, ServedNode> {
+        self.nodes
+            .entry(node.to_string())
+            .or_insert_with(|| ServedNode {
+                node: node.to_string(),
+                ..Default::default()
+            })
+    }
+}
+
+/// the body of a request from a node provider that gives us the `"serve"`
+/// capability it issued us, attached
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServeGranted {
+    ServeGranted,
+}
+
+/// the capability that lets `node` use `our` node as its provider when our
+/// access settings require one. naming the node, it is no use to any other.
+pub fn serve_cap(our: &str, node: &str) -> Capability {
+    Capability::new(
+        (our, ETH_PROCESS_ID.clone()),
+        serde_json::json!({ "serve": node }).to_string(),
+    )
+}
+
+/// whether a request from another node carries the `"serve"` capability we
+/// issued it, with our signature
+pub fn holds_serve_cap(our: &str, our_public_key: &[u8], km: &KernelMessage) -> bool {
+    let Message::Request(request) = &km.message else {
+        return false;
+    };
+    let cap = serve_cap(our, &km.source.node);
+    request.capabilities.iter().any(|(held, sig)| {
+        *held == cap
+            && ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, our_public_key)
+                .verify(&rmp_serde::to_vec(held).unwrap_or_default(), sig)
+                .is_ok()
+    })
+}
+
+/// the `"serve"` capabilities node providers have given us, by provider,
+/// kept in `.eth_grants` in the home directory
+#[derive(Default)]
+pub struct Grants {
+    caps: DashMap<NodeId, (Capability, Vec<u8>)>,
+}
+
+impl Grants {
+    pub async fn load(home_directory_path: &str) -> Self {
+        let caps = tokio::fs::read(format!("{home_directory_path}/.eth_grants"))
+            .await
+            .ok()
+            .and_then(|bytes| {
+                serde_json::from_slice::<Vec<(NodeId, Capability, Vec<u8>)>>(&bytes).ok()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(node, cap, sig)| (node, (cap, sig)))
+            .collect();
+        Self { caps }
+    }
+
+    /// keep the `"serve"` capability `provider` issued us from among
+    /// `capabilities`, if it is there. whether it is signed as it should be
+    /// is for the provider to check.
+    pub async fn grant(
+        &self,
+        our: &str,
+        home_directory_path: &str,
+        provider: &str,
+        capabilities: &[(Capability, Vec<u8>)],
+    ) {
+        let cap = serve_cap(provider, our);
+        let Some(granted) = capabilities.iter().find(|(held, _)| *held == cap) else {
+            return;
+        };
+        self.caps.insert(provider.to_string(), granted.clone());
+        let grants: Vec<(NodeId, Capability, Vec<u8>)> = self
+            .caps
+            .iter()
+            .map(|grant| (grant.key().clone(), grant.0.clone(), grant.1.clone()))
+            .collect();
+        let path = format!("{home_directory_path}/.eth_grants");
+        let tmp = format!("{path}.tmp");
+        if tokio::fs::write(&tmp, serde_json::to_vec(&grants).unwrap())
+            .await
+            .is_ok()
+        {
+            let _ = tokio::fs::rename(&tmp, &path).await;
+        }
+    }
+
+    /// the capabilities to attach to what we ask of `provider`
+    pub fn get(&self, provider: &str) -> Vec<(Capability, Vec<u8>)> {
+        self.caps
+            .get(provider)
+            .map(|grant| vec![grant.clone()])
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::Request;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn request(from: &str, capabilities: Vec<(Capability, Vec<u8>)>) -> KernelMessage {
+        KernelMessage::builder()
+            .source((from, ETH_PROCESS_ID.clone()))
+            .target(("provider.os", ETH_PROCESS_ID.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: Some(60),
+                body: vec![],
+                metadata: None,
+                capabilities,
+            }))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn serve_cap_is_for_the_node_it_names() {
+        let rng = ring::rand::SystemRandom::new();
+        let keypair =
+            Ed25519KeyPair::from_pkcs8(Ed25519KeyPair::generate_pkcs8(&rng).unwrap().as_ref())
+                .unwrap();
+        let public_key = keypair.public_key().as_ref();
+        let cap = serve_cap("provider.os", "user.os");
+        let sig = keypair
+            .sign(&rmp_serde::to_vec(&cap).unwrap())
+            .as_ref()
+            .to_vec();
+        assert!(holds_serve_cap(
+            "provider.os",
+            public_key,
+            &request("user.os", vec![(cap.clone(), sig.clone())])
+        ));
+        // passed on to another node
+        assert!(!holds_serve_cap(
+            "provider.os",
+            public_key,
+            &request("other.os", vec![(cap.clone(), sig.clone())])
+        ));
+        // not signed by us
+        assert!(!holds_serve_cap(
+            "provider.os",
+            public_key,
+            &request("user.os", vec![(cap, vec![0; 64])])
+        ));
+        assert!(!holds_serve_cap(
+            "provider.os",
+            public_key,
+            &request("user.os", vec![])
+        ));
+    }
+}
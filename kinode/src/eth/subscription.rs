@@ -17,6 +17,8 @@ pub async fn create_new_subscription(
     let providers = state.providers.clone();
     let response_channels = state.response_channels.clone();
     let print_tx = state.print_tx.clone();
+    let served = state.served.clone();
+    let grants = state.grants.clone();
     tokio::spawn(async move {
         match tokio::time::timeout(
             std::time::Duration::from_secs(30),
@@ -29,6 +31,7 @@ pub async fn create_new_subscription(
                 &providers,
                 &response_channels,
                 &print_tx,
+                &grants,
             ),
         )
         .await
@@ -71,6 +74,7 @@ pub async fn create_new_subscription(
                 let print_tx = print_tx.clone();
                 let active_subscriptions = active_subscriptions.clone();
                 let providers = providers.clone();
+                let served = served.clone();
                 let (close_sender, close_receiver) = tokio::sync::mpsc::channel(1);
                 match maybe_raw_sub {
                     Ok((rx, chain_id)) => {
@@ -93,6 +97,7 @@ pub async fn create_new_subscription(
                                         &providers,
                                         close_receiver,
                                         &print_tx,
+                                        &served,
                                     )
                                     .await;
                                     let Err(e) = r else {
@@ -183,6 +188,7 @@ async fn build_subscription(
     providers: &Providers,
     response_channels: &ResponseChannels,
     print_tx: &PrintSender,
+    grants: &served::Grants,
 ) -> Result<Result<(RawSubscription, u64), (String, u64)>, EthError> {
     let EthAction::SubscribeLogs {
         chain_id,
@@ -294,6 +300,10 @@ async fn build_subscription(
         }
     }
 
+    if target.node != our {
+        // we serve other nodes only from our own RPC URLs
+        return Err(EthError::NoRpcForChain);
+    }
     let (sender, mut response_receiver) = tokio::sync::mpsc::channel(1);
     response_channels.insert(km_id, sender);
     // we need to create our own unique sub id because in the remote provider node,
@@ -328,6 +338,7 @@ async fn build_subscription(
             },
             &send_to_loop,
             &mut response_receiver,
+            grants,
         )
         .await
         {
@@ -385,6 +396,7 @@ async fn maintain_local_subscription(
     providers: &Providers,
     mut close_receiver: tokio::sync::mpsc::Receiver<bool>,
     print_tx: &PrintSender,
+    served: &served::Served,
 ) -> Result<(), EthSubError> {
     loop {
         tokio::select! {
@@ -416,6 +428,9 @@ async fn maintain_local_subscription(
                     &send_to_loop,
                 )
                 .await;
+                if target.node != our {
+                    served.relayed(&target.node);
+                }
             },
         }
     }
@@ -451,6 +466,8 @@ async fn unsubscribe(
                 .send(Printout {
                     verbosity: 0,
                     content: format!("unsubscribe from ETH RPC failed: {err:?}"),
+                    source: None,
+                    level: None,
                 })
                 .await;
         }
@@ -31,6 +31,7 @@ pub async fn mint_local(
     ws_port: u16,
     pubkey: &str,
     fakechain_port: u16,
+    endpoints: &[String],
 ) -> Result<(), anyhow::Error> {
     let privkey_signer = PrivateKeySigner::from_str(
         "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
@@ -116,7 +117,7 @@ pub async fn mint_local(
     let localhost = Ipv4Addr::new(127, 0, 0, 1);
     let ip = keygen::ip_to_bytes(localhost.into());
     let pubkey = hex::decode(pubkey)?;
-    let multicalls: Vec<Call> = vec![
+    let mut multicalls: Vec<Call> = vec![
         Call {
             target: kimap,
             callData: Bytes::from(
@@ -148,6 +149,18 @@ pub async fn mint_local(
             ),
         },
     ];
+    if !endpoints.is_empty() {
+        multicalls.push(Call {
+            target: kimap,
+            callData: Bytes::from(
+                noteCall {
+                    note: "~endpoints".into(),
+                    data: endpoints.join(",").into_bytes().into(),
+                }
+                .abi_encode(),
+            ),
+        });
+    }
 
     let multicall = aggregateCall { calls: multicalls }.abi_encode();
 
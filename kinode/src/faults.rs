@@ -0,0 +1,219 @@
+//! simulation-mode fault injection.
+//!
+//! booting a fake node with `--fault-script <PATH>` loads a JSON script of
+//! faults for the kernel to inject into message delivery, plus crashes to
+//! force on processes at given times, e.g.:
+//!
+//! ```json
+//! {
+//!     "seed": 7,
+//!     "rules": [
+//!         { "target": "chess:chess:sys", "fault": "Drop", "probability": 0.2 },
+//!         { "source": "fake2.dev", "fault": "Duplicate" },
+//!         { "fault": { "Delay": { "min_ms": 10, "max_ms": 500 } } },
+//!         { "target": "fake2.dev", "fault": { "Reorder": { "window_ms": 100 } } }
+//!     ],
+//!     "crashes": [{ "process": "chess:chess:sys", "after_ms": 5000 }]
+//! }
+//! ```
+//!
+//! a rule's `source` and `target` each match a node name, a process id or a
+//! full address; a missing one matches anything. remote nodes are matched by
+//! name, so rules cover networked messages as well as local ones. each message
+//! is judged once, by the first rule it matches. messages to or from the
+//! kernel are never faulted.
+use dashmap::DashMap;
+use lib::types::core::{self as t, KERNEL_PROCESS_ID};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+lazy_static::lazy_static! {
+    static ref INJECTOR: Mutex<Option<FaultInjector>> = Mutex::new(None);
+    static ref CRASH_SWITCHES: DashMap<t::ProcessId, oneshot::Sender<()>> = DashMap::new();
+}
+
+/// judged messages remembered at once, to bound memory. past this many, the
+/// oldest are forgotten first.
+const MAX_JUDGED: usize = 100_000;
+
+#[derive(Debug, Deserialize)]
+pub struct FaultScript {
+    /// seed for the random choices rules make, so that runs are reproducible
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub rules: Vec<FaultRule>,
+    #[serde(default)]
+    pub crashes: Vec<ScheduledCrash>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FaultRule {
+    pub source: Option<String>,
+    pub target: Option<String>,
+    pub fault: Fault,
+    /// chance that a matching message is faulted
+    #[serde(default = "always")]
+    pub probability: f64,
+}
+
+fn always() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+pub enum Fault {
+    Drop,
+    /// deliver the message twice
+    Duplicate,
+    /// deliver the message after a random latency in the range
+    Delay {
+        min_ms: u64,
+        max_ms: u64,
+    },
+    /// let later messages overtake this one, by holding it up to `window_ms`
+    Reorder {
+        window_ms: u64,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduledCrash {
+    pub process: t::ProcessId,
+    /// time since boot at which to crash the process
+    pub after_ms: u64,
+}
+
+/// what the kernel should do with a message
+pub enum Verdict {
+    Deliver,
+    Drop,
+    Duplicate,
+    Delay(Duration),
+}
+
+struct FaultInjector {
+    rules: Vec<FaultRule>,
+    rng: StdRng,
+    /// messages we have already judged, so that one passing through again
+    /// is delivered as-is, with the order they were judged in. requests and
+    /// responses share ids, so the kind is part of the key.
+    judged: HashSet<(u64, bool)>,
+    judged_order: VecDeque<(u64, bool)>,
+    /// duplicated and delayed messages yet to come back, which are delivered
+    /// as-is however many messages are judged meanwhile
+    in_flight: HashSet<(u64, bool)>,
+}
+
+/// load a fault script and start injecting its faults. crashes are
+/// scheduled from the time this is called.
+pub async fn load(path: &str, send_to_terminal: t::PrintSender) -> anyhow::Result<()> {
+    let script: FaultScript = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+    for crash in script.crashes {
+        let send_to_terminal = send_to_terminal.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(crash.after_ms)).await;
+            let crashed = force_crash(&crash.process);
+            t::Printout::new(
+                0,
+                if crashed {
+                    format!("fault injection: crashing {}", crash.process)
+                } else {
+                    format!(
+                        "fault injection: can't crash {}, not running",
+                        crash.process
+                    )
+                },
+            )
+            .send(&send_to_terminal)
+            .await;
+        });
+    }
+    *INJECTOR.lock().unwrap() = Some(FaultInjector {
+        rules: script.rules,
+        rng: StdRng::seed_from_u64(script.seed),
+        judged: HashSet::new(),
+        judged_order: VecDeque::new(),
+        in_flight: HashSet::new(),
+    });
+    Ok(())
+}
+
+fn matches(pattern: &Option<String>, address: &t::Address) -> bool {
+    let Some(pattern) = pattern else {
+        return true;
+    };
+    *pattern == address.node
+        || *pattern == address.process.to_string()
+        || *pattern == address.to_string()
+}
+
+/// decide the fate of a message entering the kernel event loop
+pub fn judge(km: &t::KernelMessage) -> Verdict {
+    let mut injector = INJECTOR.lock().unwrap();
+    let Some(injector) = injector.as_mut() else {
+        return Verdict::Deliver;
+    };
+    if km.source.process == *KERNEL_PROCESS_ID || km.target.process == *KERNEL_PROCESS_ID {
+        return Verdict::Deliver;
+    }
+    let key = (km.id, matches!(km.message, t::Message::Request(_)));
+    if injector.in_flight.remove(&key) || !injector.judged.insert(key) {
+        return Verdict::Deliver;
+    }
+    injector.judged_order.push_back(key);
+    if injector.judged_order.len() > MAX_JUDGED {
+        let oldest = injector.judged_order.pop_front().unwrap();
+        injector.judged.remove(&oldest);
+    }
+    let Some(rule) = injector
+        .rules
+        .iter()
+        .find(|rule| matches(&rule.source, &km.source) && matches(&rule.target, &km.target))
+    else {
+        return Verdict::Deliver;
+    };
+    let roll: f64 = injector.rng.gen();
+    if roll >= rule.probability {
+        return Verdict::Deliver;
+    }
+    let verdict = match rule.fault {
+        Fault::Drop => return Verdict::Drop,
+        Fault::Duplicate => Verdict::Duplicate,
+        Fault::Delay { min_ms, max_ms } => Verdict::Delay(Duration::from_millis(
+            injector.rng.gen_range(min_ms..=max_ms.max(min_ms)),
+        )),
+        Fault::Reorder { window_ms } => {
+            Verdict::Delay(Duration::from_millis(injector.rng.gen_range(0..=window_ms)))
+        }
+    };
+    injector.in_flight.insert(key);
+    verdict
+}
+
+/// run a process's init, ending it as a crash if one is forced on it
+pub async fn crashable<T>(
+    process: &t::ProcessId,
+    init: impl std::future::Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let (crash_sender, crash_receiver) = oneshot::channel();
+    CRASH_SWITCHES.insert(process.clone(), crash_sender);
+    let result = tokio::select! {
+        result = init => result,
+        Ok(()) = crash_receiver => Err(anyhow::anyhow!("fault injection: forced crash")),
+    };
+    CRASH_SWITCHES.remove(process);
+    result
+}
+
+/// crash a running process. returns false if it isn't running.
+pub fn force_crash(process: &t::ProcessId) -> bool {
+    match CRASH_SWITCHES.remove(process) {
+        Some((_, crash_sender)) => crash_sender.send(()).is_ok(),
+        None => false,
+    }
+}
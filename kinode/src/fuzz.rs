@@ -0,0 +1,704 @@
+//! in-process fuzzing of the runtime modules' message parsing.
+//!
+//! built with the `fuzzing` feature, `kinode <home> --fuzz <TARGET>` spawns one
+//! runtime module against a sandbox in `<home>/fuzz/<TARGET>`, then feeds it
+//! malformed requests instead of booting the node. inputs start from a set of
+//! valid requests (plus any files in `--fuzz-corpus`) and are mutated both as
+//! raw bytes and as JSON values, so that most of them still parse far enough
+//! to reach the handlers. targets:
+//!
+//! - `vfs`, `state`, `kv`, `sqlite`, `http`: request bodies, with blobs, sent
+//!   to the module with every capability check passing
+//! - `kernel`: `KernelMessage` bytes as read off the wire by networking,
+//!   handed to the kernel's request handler as if a local process sent them
+//!
+//! an input that makes the module panic is saved to `<home>/fuzz/crashes` so
+//! that it can be replayed with `--fuzz-corpus`.
+use lib::types::core::{
+    self as t, CapMessage, CapMessageReceiver, KernelMessage, Message, MessageReceiver,
+    MessageSender, ProcessId, Request, HTTP_SERVER_PROCESS_ID, KERNEL_PROCESS_ID, KV_PROCESS_ID,
+    SQLITE_PROCESS_ID, STATE_PROCESS_ID, VFS_PROCESS_ID,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::{sync::Mutex, time::Duration};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+const FUZZ_CHANNEL_CAPACITY: usize = 1_000;
+/// how long to wait for a module to answer an input before moving on
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+const OUR_NODE: &str = "fuzz.os";
+
+lazy_static::lazy_static! {
+    /// the message of the last panic on any thread, set by our panic hook
+    static ref LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Vfs,
+    State,
+    Kv,
+    Sqlite,
+    Http,
+    Kernel,
+}
+
+impl std::str::FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "vfs" => Ok(Target::Vfs),
+            "state" => Ok(Target::State),
+            "kv" => Ok(Target::Kv),
+            "sqlite" => Ok(Target::Sqlite),
+            "http" => Ok(Target::Http),
+            "kernel" => Ok(Target::Kernel),
+            _ => Err(anyhow::anyhow!(
+                "unknown fuzz target {s}: expected one of vfs, state, kv, sqlite, http, kernel"
+            )),
+        }
+    }
+}
+
+/// one input: a request body and an optional blob
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct Input {
+    body: Vec<u8>,
+    blob: Option<Vec<u8>>,
+}
+
+/// run a fuzz target for the given number of inputs
+pub async fn fuzz(
+    home_directory_path: &str,
+    target: &str,
+    corpus: Option<&String>,
+    runs: u64,
+    seed: u64,
+) -> anyhow::Result<()> {
+    let target: Target = target.parse()?;
+    let fuzz_path = format!("{home_directory_path}/fuzz");
+    let crashes_path = format!("{fuzz_path}/crashes");
+    tokio::fs::create_dir_all(&crashes_path).await?;
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        *LAST_PANIC.lock().unwrap() = Some(info.to_string());
+        default_hook(info);
+    }));
+
+    let mut seeds = seeds(target);
+    if let Some(corpus) = corpus {
+        let mut dir = tokio::fs::read_dir(corpus).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let bytes = tokio::fs::read(entry.path()).await?;
+            // crash files are saved as inputs; anything else is a raw body
+            seeds.push(serde_json::from_slice(&bytes).unwrap_or(Input {
+                body: bytes,
+                blob: None,
+            }));
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut harness = Harness::new(target, format!("{fuzz_path}/{}", target.name())).await?;
+    let (mut crashes, mut unanswered) = (0u64, 0u64);
+    for run in 0..runs {
+        // replay every seed as-is first, then mutate them
+        let input = match seeds.get(run as usize) {
+            Some(seed) => seed.clone(),
+            None => mutate(seeds.choose(&mut rng).unwrap(), &mut rng),
+        };
+        *LAST_PANIC.lock().unwrap() = None;
+        let answered = harness.feed(&input).await;
+        let panic = LAST_PANIC.lock().unwrap().take();
+        if let Some(panic) = panic {
+            crashes += 1;
+            let path = format!("{crashes_path}/{}-{run}.json", target.name());
+            tokio::fs::write(&path, serde_json::to_vec(&input)?).await?;
+            println!("fuzz: run {run} panicked, input saved to {path}: {panic}\r");
+        } else if !answered {
+            unanswered += 1;
+        }
+        if harness.is_dead() {
+            harness = Harness::new(target, format!("{fuzz_path}/{}", target.name())).await?;
+        }
+        if run > 0 && run % 1_000 == 0 {
+            println!("fuzz: {run} runs, {crashes} crashes, {unanswered} unanswered\r");
+        }
+    }
+    println!("fuzz: done: {runs} runs, {crashes} crashes, {unanswered} unanswered\r");
+    Ok(())
+}
+
+impl Target {
+    fn name(&self) -> &'static str {
+        match self {
+            Target::Vfs => "vfs",
+            Target::State => "state",
+            Target::Kv => "kv",
+            Target::Sqlite => "sqlite",
+            Target::Http => "http",
+            Target::Kernel => "kernel",
+        }
+    }
+
+    fn process(&self) -> Option<&'static ProcessId> {
+        match self {
+            Target::Vfs => Some(&VFS_PROCESS_ID),
+            Target::State => Some(&STATE_PROCESS_ID),
+            Target::Kv => Some(&KV_PROCESS_ID),
+            Target::Sqlite => Some(&SQLITE_PROCESS_ID),
+            Target::Http => Some(&HTTP_SERVER_PROCESS_ID),
+            Target::Kernel => None,
+        }
+    }
+}
+
+/// a runtime module running in a sandbox, with a fake event loop around it
+struct Harness {
+    target: Target,
+    send_to_module: Option<MessageSender>,
+    recv_from_module: MessageReceiver,
+    module: Option<JoinHandle<anyhow::Result<()>>>,
+    /// for the kernel target, the kernel's state, gone once it panics, hangs
+    /// or shuts down
+    kernel: Option<crate::kernel::fuzz::Fuzzed>,
+}
+
+impl Harness {
+    async fn new(target: Target, home_directory_path: String) -> anyhow::Result<Self> {
+        tokio::fs::create_dir_all(&home_directory_path).await?;
+        let (send_to_loop, recv_from_module) = mpsc::channel(FUZZ_CHANNEL_CAPACITY);
+        let (send_to_module, recv_in_module) = mpsc::channel(FUZZ_CHANNEL_CAPACITY);
+        let (print_tx, mut print_rx) = mpsc::channel(FUZZ_CHANNEL_CAPACITY);
+        let (caps_oracle, caps_oracle_rx) = mpsc::channel(FUZZ_CHANNEL_CAPACITY);
+        tokio::spawn(async move { while print_rx.recv().await.is_some() {} });
+        tokio::spawn(grant_everything(caps_oracle_rx));
+
+        let our = std::sync::Arc::new(OUR_NODE.to_string());
+        // no flags on, so a denial is only answered with its error
+        let feature_flags = std::sync::Arc::new(
+            crate::kernel::flags::FeatureFlags::load(&home_directory_path, vec![]).await?,
+        );
+        let module = match target {
+            Target::Vfs => {
+                let blob_store =
+                    std::sync::Arc::new(crate::blobs::BlobStore::new(&home_directory_path).await?);
+                tokio::spawn(crate::vfs::vfs(
+                    our,
+                    send_to_loop,
+                    print_tx,
+                    recv_in_module,
+                    caps_oracle,
+                    home_directory_path,
+                    blob_store,
+                    std::sync::Arc::new(vec![]),
+                    feature_flags,
+                ))
+            }
+            Target::State => {
+                let db = rocksdb::DB::open_default(format!("{home_directory_path}/kernel/db"))?;
+                tokio::spawn(crate::state::state_sender(
+                    our,
+                    send_to_loop,
+                    print_tx,
+                    recv_in_module,
+                    db,
+                    home_directory_path,
+                    caps_oracle,
+                ))
+            }
+            Target::Kv => tokio::spawn(crate::kv::kv(
+                our,
+                send_to_loop,
+                print_tx,
+                recv_in_module,
+                caps_oracle,
+                home_directory_path,
+                feature_flags,
+            )),
+            Target::Sqlite => tokio::spawn(crate::sqlite::sqlite(
+                our,
+                send_to_loop,
+                print_tx,
+                recv_in_module,
+                caps_oracle,
+                home_directory_path,
+                feature_flags,
+            )),
+            Target::Http => tokio::spawn(crate::http::server::http_server(
+                OUR_NODE.to_string(),
+                0,
+                vec![],
+                vec![0; 32],
+                recv_in_module,
+                send_to_loop,
+                print_tx,
+                caps_oracle,
+                None,
+            )),
+            Target::Kernel => {
+                let kernel = crate::kernel::fuzz::Fuzzed::new(
+                    OUR_NODE,
+                    home_directory_path,
+                    send_to_loop,
+                    print_tx,
+                    caps_oracle,
+                    feature_flags,
+                )
+                .await?;
+                return Ok(Self {
+                    target,
+                    send_to_module: None,
+                    recv_from_module,
+                    module: None,
+                    kernel: Some(kernel),
+                });
+            }
+        };
+        Ok(Self {
+            target,
+            send_to_module: Some(send_to_module),
+            recv_from_module,
+            module: Some(module),
+            kernel: None,
+        })
+    }
+
+    fn is_dead(&self) -> bool {
+        match self.target {
+            Target::Kernel => self.kernel.is_none(),
+            _ => self
+                .module
+                .as_ref()
+                .map_or(false, |module| module.is_finished()),
+        }
+    }
+
+    /// feed one input to the module, returning whether it was answered.
+    /// for the kernel target, whether it was handled in time.
+    async fn feed(&mut self, input: &Input) -> bool {
+        let (Some(send_to_module), Some(process)) = (&self.send_to_module, self.target.process())
+        else {
+            return self.feed_kernel(input).await;
+        };
+        let id: u64 = rand::random();
+        let km = KernelMessage::builder()
+            .id(id)
+            .source((OUR_NODE, ProcessId::new(Some("fuzz"), "fuzz", "sys")))
+            .target((OUR_NODE, process.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: Some(5),
+                body: input.body.clone(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .lazy_load_blob(
+                input
+                    .blob
+                    .clone()
+                    .map(|bytes| t::LazyLoadBlob { mime: None, bytes }),
+            )
+            .build()
+            .unwrap();
+        if send_to_module.send(km).await.is_err() {
+            return false;
+        }
+        let deadline = tokio::time::Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            match tokio::time::timeout_at(deadline, self.recv_from_module.recv()).await {
+                Ok(Some(km)) if km.id == id => return true,
+                Ok(Some(_)) => continue,
+                _ => return false,
+            }
+        }
+    }
+
+    /// decode bytes as networking does, then hand the message to the kernel
+    /// as if one of our processes sent it, since the event loop only passes
+    /// the kernel's handler requests from our own node. it is handled in a
+    /// task of its own, so that a panic is caught like a module's.
+    async fn feed_kernel(&mut self, input: &Input) -> bool {
+        let Ok(mut km) = rmp_serde::from_slice::<KernelMessage>(&input.body) else {
+            return false;
+        };
+        let Some(mut kernel) = self.kernel.take() else {
+            return false;
+        };
+        km.source.node = OUR_NODE.to_string();
+        km.target = t::Address::new(OUR_NODE, KERNEL_PROCESS_ID.clone());
+        if let Some(bytes) = input.blob.clone() {
+            km.lazy_load_blob = Some(t::LazyLoadBlob { mime: None, bytes });
+        }
+        let handled = tokio::time::timeout(
+            RESPONSE_TIMEOUT,
+            tokio::spawn(async move {
+                let shut_down = kernel.handle(km).await;
+                (!shut_down).then_some(kernel)
+            }),
+        )
+        .await;
+        // what the kernel sent on, as the event loop would have taken it
+        while self.recv_from_module.try_recv().is_ok() {}
+        match handled {
+            Ok(Ok(kernel)) => {
+                self.kernel = kernel;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// stand in for the capabilities oracle, so that inputs reach past cap checks
+async fn grant_everything(mut recv: CapMessageReceiver) {
+    while let Some(message) = recv.recv().await {
+        match message {
+            CapMessage::Add { responder, .. }
+            | CapMessage::Drop { responder, .. }
+            | CapMessage::RevokeAll { responder, .. }
+            | CapMessage::DropEverywhere { responder, .. } => {
+                if let Some(responder) = responder {
+                    let _ = responder.send(true);
+                }
+            }
+            CapMessage::Has { responder, .. } => {
+                let _ = responder.send(true);
+            }
+            CapMessage::GetAll { responder, .. } => {
+                let _ = responder.send(vec![]);
+            }
+            CapMessage::FilterCaps {
+                caps, responder, ..
+            } => {
+                let _ = responder.send(caps.into_iter().map(|cap| (cap, vec![])).collect());
+            }
+        }
+    }
+}
+
+fn seed(body: Value, blob: Option<&[u8]>) -> Input {
+    Input {
+        body: serde_json::to_vec(&body).unwrap(),
+        blob: blob.map(|blob| blob.to_vec()),
+    }
+}
+
+/// valid inputs to start mutating from
+fn seeds(target: Target) -> Vec<Input> {
+    let package_id = json!("fuzz:sys");
+    match target {
+        Target::Vfs => vec![
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": "CreateDrive" }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": "CreateFile" }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": "Write" }),
+                Some(b"hello"),
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": "Append" }),
+                Some(b"!"),
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": "ReadToEnd" }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": { "ReadExact": 3 } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": { "Seek": { "seek_from": { "End": -2 } } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": { "SetLen": 1 } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/a", "action": { "Rename": { "new_path": "/fuzz:sys/drive/b" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/b", "action": { "CopyFile": { "new_path": "/fuzz:sys/drive/c" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/c", "action": { "CloneFile": { "new_path": "/fuzz:sys/drive/d" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/c", "action": { "HardLink": { "new_path": "/fuzz:sys/drive/e" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": "ReadDir" }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": { "ReadDirPage": { "cursor": "0:fuzz:sys/drive/b", "limit": 1, "sort": "Size", "descending": true } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": "CountDir" }),
+                None,
+            ),
+            seed(json!({ "path": "/fuzz:sys", "action": "ListDrives" }), None),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": { "AddDriveHook": { "process": "fuzz:fuzz:sys" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": { "RemoveDriveHook": { "process": "fuzz:fuzz:sys" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": { "DriveTransaction": { "id": 1, "step": "Prepare" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": { "DriveTransaction": { "id": 1, "step": "Abort" } } }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": "DeleteDrive" }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/c", "action": "Hash" }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/c", "action": "Metadata" }),
+                None,
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive/zip", "action": "AddZip" }),
+                Some(b"PK\x03\x04"),
+            ),
+            seed(
+                json!({ "path": "/fuzz:sys/drive", "action": "RemoveDirAll" }),
+                None,
+            ),
+        ],
+        Target::State => vec![
+            seed(json!({ "SetState": "fuzz:fuzz:sys" }), Some(b"state")),
+            seed(json!({ "GetState": "fuzz:fuzz:sys" }), None),
+            seed(json!({ "DeleteState": "fuzz:fuzz:sys" }), None),
+            seed(json!("Backup"), None),
+            seed(
+                json!({ "BackupWithDrives": { "packages": ["fuzz:sys"] } }),
+                None,
+            ),
+            seed(json!("VerifyBackup"), None),
+        ],
+        Target::Kv => vec![
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": "Open" }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Set": { "key": [1], "tx_id": null } } }),
+                Some(b"value"),
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Get": { "key": [1] } } }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": "BeginTx" }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Delete": { "key": [1], "tx_id": 0 } } }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Commit": { "tx_id": 0 } } }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": "RemoveDb" }),
+                None,
+            ),
+        ],
+        Target::Sqlite => vec![
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": "Open" }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Write": { "statement": "CREATE TABLE t (a INTEGER, b TEXT)", "tx_id": null } } }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Write": { "statement": "INSERT INTO t VALUES (?, ?)", "tx_id": null } } }),
+                Some(br#"[1, "one"]"#),
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Read": { "query": "SELECT * FROM t WHERE a = ?" } } }),
+                Some(b"[1]"),
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": "BeginTx" }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": { "Commit": { "tx_id": 0 } } }),
+                None,
+            ),
+            seed(
+                json!({ "package_id": package_id, "db": "db", "action": "RemoveDb" }),
+                None,
+            ),
+        ],
+        Target::Http => vec![
+            seed(
+                json!({ "Bind": { "path": "/fuzz", "authenticated": true, "local_only": false, "cache": false } }),
+                None,
+            ),
+            seed(
+                json!({ "Bind": { "path": "/static", "authenticated": false, "local_only": true, "cache": true } }),
+                Some(b"<html></html>"),
+            ),
+            seed(
+                json!({ "SecureBind": { "path": "/secure", "cache": false } }),
+                None,
+            ),
+            seed(
+                json!({ "WebSocketBind": { "path": "/ws", "authenticated": true, "encrypted": false, "extension": false } }),
+                None,
+            ),
+            seed(
+                json!({ "WebSocketOpen": { "path": "/ws", "channel_id": 1 } }),
+                None,
+            ),
+            seed(
+                json!({ "WebSocketPush": { "channel_id": 1, "message_type": "Text" } }),
+                Some(b"push"),
+            ),
+            seed(json!({ "WebSocketClose": 1 }), None),
+            seed(json!({ "Unbind": { "path": "/fuzz" } }), None),
+        ],
+        Target::Kernel => {
+            let km = |body: Value| {
+                let km = KernelMessage::builder()
+                    .id(1)
+                    .source(("other.os", ProcessId::new(Some("fuzz"), "fuzz", "sys")))
+                    .target((OUR_NODE, ProcessId::new(Some("kernel"), "distro", "sys")))
+                    .message(Message::Request(Request {
+                        inherit: false,
+                        expects_response: Some(5),
+                        body: serde_json::to_vec(&body).unwrap(),
+                        metadata: None,
+                        capabilities: vec![],
+                    }))
+                    .build()
+                    .unwrap();
+                Input {
+                    body: rmp_serde::to_vec(&km).unwrap(),
+                    blob: None,
+                }
+            };
+            vec![
+                km(json!({ "RunProcess": "fuzz:fuzz:sys" })),
+                km(json!({ "KillProcess": "fuzz:fuzz:sys" })),
+                km(json!({ "Debug": "ProcessMap" })),
+                km(
+                    json!({ "GrantCapabilities": { "target": "fuzz:fuzz:sys", "capabilities": [] } }),
+                ),
+            ]
+        }
+    }
+}
+
+/// values that tend to find edge cases when swapped into a request
+fn interesting_value(rng: &mut StdRng) -> Value {
+    match rng.gen_range(0..12) {
+        0 => Value::Null,
+        1 => json!(u64::MAX),
+        2 => json!(-1),
+        3 => json!(""),
+        4 => json!("../../../../etc/passwd"),
+        5 => json!("/fuzz:sys/../../.."),
+        6 => json!("a".repeat(rng.gen_range(256..65_536))),
+        7 => json!("\u{0}\u{ffff}"),
+        8 => json!([]),
+        9 => json!({}),
+        10 => json!(1.5e300),
+        _ => json!(rng.gen::<bool>()),
+    }
+}
+
+/// replace a random node of a JSON value with an interesting one
+fn mutate_value(value: &mut Value, rng: &mut StdRng) {
+    let children = match value {
+        Value::Array(array) if !array.is_empty() => array.len(),
+        Value::Object(object) if !object.is_empty() => object.len(),
+        _ => 0,
+    };
+    if children == 0 || rng.gen_ratio(1, 4) {
+        *value = interesting_value(rng);
+        return;
+    }
+    let index = rng.gen_range(0..children);
+    match value {
+        Value::Array(array) => mutate_value(&mut array[index], rng),
+        Value::Object(object) => {
+            if let Some(child) = object.values_mut().nth(index) {
+                mutate_value(child, rng)
+            }
+        }
+        _ => {}
+    }
+}
+
+fn mutate_bytes(bytes: &mut Vec<u8>, rng: &mut StdRng) {
+    match rng.gen_range(0..4) {
+        0 if !bytes.is_empty() => {
+            let index = rng.gen_range(0..bytes.len());
+            bytes[index] ^= 1 << rng.gen_range(0..8);
+        }
+        1 if !bytes.is_empty() => bytes.truncate(rng.gen_range(0..bytes.len())),
+        2 => {
+            let index = rng.gen_range(0..=bytes.len());
+            bytes.insert(index, rng.gen());
+        }
+        _ => bytes.extend((0..rng.gen_range(1..64)).map(|_| rng.gen::<u8>())),
+    }
+}
+
+fn mutate(input: &Input, rng: &mut StdRng) -> Input {
+    let mut input = input.clone();
+    match rng.gen_range(0..4) {
+        // structured: keep the body valid JSON, but with a bad value in it
+        0 | 1 => match serde_json::from_slice::<Value>(&input.body) {
+            Ok(mut value) => {
+                mutate_value(&mut value, rng);
+                input.body = serde_json::to_vec(&value).unwrap();
+            }
+            Err(_) => mutate_bytes(&mut input.body, rng),
+        },
+        2 => mutate_bytes(&mut input.body, rng),
+        _ => match input.blob.as_mut() {
+            Some(blob) if rng.gen() => mutate_bytes(blob, rng),
+            _ => {
+                input.blob = if rng.gen() {
+                    None
+                } else {
+                    Some((0..rng.gen_range(0..1024)).map(|_| rng.gen()).collect())
+                }
+            }
+        },
+    }
+    input
+}
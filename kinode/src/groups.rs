@@ -0,0 +1,388 @@
+use dashmap::DashMap;
+use lib::types::core::{
+    Address, DeliveryReceipt, GroupDelivery, GroupsError, GroupsRequest, GroupsResponse,
+    KernelMessage, MemberStatus, Message, MessageReceiver, MessageSender, NodeId, ProcessId,
+    Request, Response, GROUPS_PROCESS_ID, KERNEL_PROCESS_ID,
+};
+use lib::types::errors::ModuleError;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs,
+    sync::{oneshot, Mutex},
+};
+
+const MAX_GROUP_NAME_LEN: usize = 256;
+/// most members a group may have
+const MAX_MEMBERS: usize = 1_000;
+/// longest a process may have a member's receipt waited for, per try
+const MAX_TIMEOUT: u64 = 60;
+const MAX_RETRIES: u8 = 10;
+
+type Groups = BTreeMap<String, BTreeSet<NodeId>>;
+
+/// what every task of the module shares
+struct GroupsService {
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    groups_path: PathBuf,
+    /// each process's groups, as persisted in `{home}/groups/groups.json`
+    groups: Mutex<HashMap<ProcessId, Groups>>,
+    /// the requests sent to members whose receipts we wait on, by id, with
+    /// the member each was sent to
+    awaiting: DashMap<u64, (NodeId, oneshot::Sender<bool>)>,
+}
+
+/// The groups runtime module: keeps named groups of nodes for each process,
+/// and fans a request from a process out to every member of one of its
+/// groups, telling it of each member as it is reached. A member counts as reached
+/// once its kernel sends a [`DeliveryReceipt`]; the requests fanned out ask
+/// for one, with this module as their rsvp.
+pub async fn groups(
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    mut recv_from_loop: MessageReceiver,
+    home_directory_path: String,
+) -> anyhow::Result<()> {
+    let groups_path = PathBuf::from(format!("{home_directory_path}/groups"));
+    fs::create_dir_all(&groups_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed creating groups dir! {e:?}"))?;
+    let groups: Vec<(ProcessId, Groups)> = match fs::read(groups_path.join("groups.json")).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(_) => vec![],
+    };
+
+    let state = Arc::new(GroupsService {
+        our_node,
+        send_to_loop,
+        groups_path,
+        groups: Mutex::new(groups.into_iter().collect()),
+        awaiting: DashMap::new(),
+    });
+
+    while let Some(km) = recv_from_loop.recv().await {
+        crate::metrics::handled(&GROUPS_PROCESS_ID, &km);
+        let Message::Request(ref request) = km.message else {
+            // members' responses to fanned-out requests come here, as their
+            // rsvp, but the process that sent them asked for none
+            continue;
+        };
+        if km.source.process == *KERNEL_PROCESS_ID {
+            if let Ok(receipt) = serde_json::from_slice::<DeliveryReceipt>(&request.body) {
+                state.receipt(&km.source.node, receipt);
+            }
+            continue;
+        }
+        let state = state.clone();
+        tokio::spawn(async move {
+            let id = km.id;
+            let target = km.rsvp.clone().or_else(|| match km.message {
+                Message::Request(Request {
+                    expects_response: Some(_),
+                    ..
+                }) => Some(km.source.clone()),
+                _ => None,
+            });
+            let (response, metadata, fan_out) = match state.handle_request(km).await {
+                Ok((response, fan_out)) => (response, None, fan_out),
+                Err(e) => {
+                    let metadata = e.metadata();
+                    (GroupsResponse::Err(e), metadata, None)
+                }
+            };
+            if let Some(target) = target {
+                KernelMessage::builder()
+                    .id(id)
+                    .source((state.our_node.as_str(), GROUPS_PROCESS_ID.clone()))
+                    .target(target)
+                    .message(Message::Response((
+                        Response {
+                            inherit: false,
+                            body: serde_json::to_vec(&response).unwrap(),
+                            metadata,
+                            capabilities: vec![],
+                        },
+                        None,
+                    )))
+                    .build()
+                    .unwrap()
+                    .send(&state.send_to_loop)
+                    .await;
+            }
+            // members are sent to only once the process has the send's ID,
+            // so that it knows the deliveries it is told of
+            if let Some(fan_out) = fan_out {
+                state.fan_out(&fan_out).await;
+            }
+        });
+    }
+    Err(anyhow::anyhow!("groups: loop channel closed"))
+}
+
+impl GroupsService {
+    /// handle a request, returning the response and, for a send, what to
+    /// fan out once the response is sent
+    async fn handle_request(
+        &self,
+        km: KernelMessage,
+    ) -> Result<(GroupsResponse, Option<Fanout>), GroupsError> {
+        let Message::Request(request) = km.message else {
+            unreachable!("responses are dropped before this");
+        };
+        if km.source.node != *self.our_node {
+            return Err(GroupsError::BadRequest {
+                error: "only processes on this node may use groups".into(),
+            });
+        }
+        let request: GroupsRequest =
+            serde_json::from_slice(&request.body).map_err(|e| GroupsError::BadRequest {
+                error: format!("didn't parse into GroupsRequest: {e}"),
+            })?;
+        let process = &km.source.process;
+
+        match request {
+            GroupsRequest::Create { group } => {
+                if group.is_empty() || group.len() > MAX_GROUP_NAME_LEN {
+                    return Err(GroupsError::BadRequest {
+                        error: format!("group names must be 1 to {MAX_GROUP_NAME_LEN} bytes"),
+                    });
+                }
+                let mut all = self.groups.lock().await;
+                let groups = all.entry(process.clone()).or_default();
+                if groups.contains_key(&group) {
+                    return Err(GroupsError::GroupExists { group });
+                }
+                groups.insert(group, BTreeSet::new());
+                self.persist(&all).await?;
+                Ok((GroupsResponse::Ok, None))
+            }
+            GroupsRequest::Delete { group } => {
+                let mut all = self.groups.lock().await;
+                let groups = all.entry(process.clone()).or_default();
+                if groups.remove(&group).is_none() {
+                    return Err(GroupsError::NoGroup { group });
+                }
+                if groups.is_empty() {
+                    all.remove(process);
+                }
+                self.persist(&all).await?;
+                Ok((GroupsResponse::Ok, None))
+            }
+            GroupsRequest::AddMembers { group, members } => {
+                let mut all = self.groups.lock().await;
+                let current = all
+                    .get_mut(process)
+                    .and_then(|groups| groups.get_mut(&group))
+                    .ok_or(GroupsError::NoGroup {
+                        group: group.clone(),
+                    })?;
+                // checked before adding, so a refused request leaves the group as it was
+                let new = members
+                    .iter()
+                    .filter(|member| !current.contains(*member))
+                    .collect::<BTreeSet<_>>()
+                    .len();
+                if current.len() + new > MAX_MEMBERS {
+                    return Err(GroupsError::BadRequest {
+                        error: format!("groups may have at most {MAX_MEMBERS} members"),
+                    });
+                }
+                current.extend(members);
+                self.persist(&all).await?;
+                Ok((GroupsResponse::Ok, None))
+            }
+            GroupsRequest::RemoveMembers { group, members } => {
+                let mut all = self.groups.lock().await;
+                let current = all
+                    .get_mut(process)
+                    .and_then(|groups| groups.get_mut(&group))
+                    .ok_or(GroupsError::NoGroup {
+                        group: group.clone(),
+                    })?;
+                for member in &members {
+                    current.remove(member);
+                }
+                self.persist(&all).await?;
+                Ok((GroupsResponse::Ok, None))
+            }
+            GroupsRequest::List => Ok((
+                GroupsResponse::Groups(
+                    self.groups
+                        .lock()
+                        .await
+                        .get(process)
+                        .cloned()
+                        .unwrap_or_default(),
+                ),
+                None,
+            )),
+            GroupsRequest::Send {
+                group,
+                process: target,
+                metadata,
+                timeout,
+                retries,
+            } => {
+                if timeout == 0 || timeout > MAX_TIMEOUT || retries > MAX_RETRIES {
+                    return Err(GroupsError::BadRequest {
+                        error: format!(
+                            "timeout must be 1 to {MAX_TIMEOUT} seconds, retries at most {MAX_RETRIES}"
+                        ),
+                    });
+                }
+                let members = self
+                    .groups
+                    .lock()
+                    .await
+                    .get(process)
+                    .and_then(|groups| groups.get(&group))
+                    .cloned()
+                    .ok_or(GroupsError::NoGroup {
+                        group: group.clone(),
+                    })?;
+                let fan_out = Fanout {
+                    send_id: rand::random(),
+                    group,
+                    members,
+                    source: km.source,
+                    target,
+                    body: km.lazy_load_blob.map(|blob| blob.bytes).unwrap_or_default(),
+                    metadata: with_receipt(metadata)?,
+                    timeout: Duration::from_secs(timeout),
+                    retries,
+                };
+                Ok((
+                    GroupsResponse::Sending {
+                        send_id: fan_out.send_id,
+                    },
+                    Some(fan_out),
+                ))
+            }
+        }
+    }
+
+    /// send to every member at once, telling the process that sent how it
+    /// went for each member as soon as that member is settled
+    async fn fan_out(&self, fan_out: &Fanout) {
+        futures::future::join_all(fan_out.members.iter().map(|member| async move {
+            let status = self.send_to_member(fan_out, member).await;
+            KernelMessage::builder()
+                .id(rand::random())
+                .source((self.our_node.as_str(), GROUPS_PROCESS_ID.clone()))
+                .target(fan_out.source.clone())
+                .message(Message::Request(Request {
+                    inherit: false,
+                    expects_response: None,
+                    body: serde_json::to_vec(&GroupDelivery {
+                        send_id: fan_out.send_id,
+                        group: fan_out.group.clone(),
+                        member: member.clone(),
+                        status,
+                    })
+                    .unwrap(),
+                    metadata: None,
+                    capabilities: vec![],
+                }))
+                .build()
+                .unwrap()
+                .send(&self.send_to_loop)
+                .await;
+        }))
+        .await;
+    }
+
+    /// send the request to one member until its kernel confirms delivery, or
+    /// we run out of tries. each try has its own id, so a member whose
+    /// receipt was lost may get the request twice.
+    async fn send_to_member(&self, fan_out: &Fanout, member: &NodeId) -> MemberStatus {
+        for _ in 0..=fan_out.retries {
+            let id: u64 = rand::random();
+            let (send_delivered, recv_delivered) = oneshot::channel();
+            self.awaiting.insert(id, (member.clone(), send_delivered));
+            KernelMessage::builder()
+                .id(id)
+                .source(fan_out.source.clone())
+                .target(Address::new(member, fan_out.target.clone()))
+                .rsvp(Some(Address::new(
+                    self.our_node.as_str(),
+                    GROUPS_PROCESS_ID.clone(),
+                )))
+                .message(Message::Request(Request {
+                    inherit: false,
+                    expects_response: None,
+                    body: fan_out.body.clone(),
+                    metadata: Some(fan_out.metadata.clone()),
+                    capabilities: vec![],
+                }))
+                .build()
+                .unwrap()
+                .send(&self.send_to_loop)
+                .await;
+            match tokio::time::timeout(fan_out.timeout, recv_delivered).await {
+                Ok(Ok(true)) => return MemberStatus::Delivered,
+                Ok(Ok(false)) => return MemberStatus::NoSuchProcess,
+                _ => {
+                    self.awaiting.remove(&id);
+                }
+            }
+        }
+        MemberStatus::Unreachable
+    }
+
+    /// a member's kernel confirmed, or denied, delivery. only the member a
+    /// request went to can answer for it.
+    fn receipt(&self, from: &str, receipt: DeliveryReceipt) {
+        if let Some((_, (_, send_delivered))) = self
+            .awaiting
+            .remove_if(&receipt.id, |_, (member, _)| member == from)
+        {
+            let _ = send_delivered.send(receipt.delivered);
+        }
+    }
+
+    async fn persist(&self, all: &HashMap<ProcessId, Groups>) -> Result<(), GroupsError> {
+        let all: Vec<(&ProcessId, &Groups)> = all.iter().collect();
+        fs::write(
+            self.groups_path.join("groups.json"),
+            serde_json::to_vec_pretty(&all).unwrap(),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// what a [`GroupsRequest::Send`] sends each member
+struct Fanout {
+    send_id: u64,
+    group: String,
+    members: BTreeSet<NodeId>,
+    source: Address,
+    target: ProcessId,
+    body: Vec<u8>,
+    metadata: String,
+    timeout: Duration,
+    retries: u8,
+}
+
+/// the metadata to send members: the process's own, which must then be a
+/// JSON object, with a receipt asked for
+fn with_receipt(metadata: Option<String>) -> Result<String, GroupsError> {
+    let mut object = match metadata {
+        None => serde_json::Map::new(),
+        Some(metadata) => match serde_json::from_str(&metadata) {
+            Ok(serde_json::Value::Object(object)) => object,
+            _ => {
+                return Err(GroupsError::BadRequest {
+                    error: "metadata sent to a group must be a JSON object".into(),
+                })
+            }
+        },
+    };
+    object.insert("request_receipt".into(), serde_json::Value::Bool(true));
+    Ok(serde_json::Value::Object(object).to_string())
+}
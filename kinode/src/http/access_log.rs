@@ -0,0 +1,77 @@
+//! access logs of bound HTTP paths. a process turns one on for a path it
+//! has bound with `HttpServerAction::SetAccessLog`, and every request to
+//! that path is then kept as an [`AccessLogEntry`] until its retention runs
+//! out, as well as printed to the node's log.
+//!
+//! logs are kept by bound path rather than on the binding itself, so that a
+//! process rebinding a path, as most do when they start, keeps its log.
+use crate::http::server_types::{AccessLogEntry, AccessLogSettings};
+use dashmap::DashMap;
+use std::collections::VecDeque;
+
+struct AccessLog {
+    settings: AccessLogSettings,
+    entries: VecDeque<AccessLogEntry>,
+}
+
+impl AccessLog {
+    fn prune(&mut self) {
+        let cutoff =
+            crate::clock::now().saturating_sub(self.settings.retention_secs.saturating_mul(1000));
+        while self
+            .entries
+            .front()
+            .is_some_and(|entry| entry.timestamp < cutoff)
+        {
+            self.entries.pop_front();
+        }
+        while self.entries.len() > self.settings.max_entries {
+            self.entries.pop_front();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AccessLogs {
+    logs: DashMap<String, AccessLog>,
+}
+
+impl AccessLogs {
+    /// start logging `bound_path`, change how long its entries are kept, or
+    /// stop logging it with None
+    pub fn set(&self, bound_path: &str, settings: Option<AccessLogSettings>) {
+        let Some(settings) = settings else {
+            self.logs.remove(bound_path);
+            return;
+        };
+        let mut log = self
+            .logs
+            .entry(bound_path.to_string())
+            .or_insert_with(|| AccessLog {
+                settings,
+                entries: VecDeque::new(),
+            });
+        log.settings = settings;
+        log.prune();
+    }
+
+    pub fn is_logged(&self, bound_path: &str) -> bool {
+        self.logs.contains_key(bound_path)
+    }
+
+    pub fn record(&self, entry: AccessLogEntry) {
+        if let Some(mut log) = self.logs.get_mut(&entry.bound_path) {
+            log.entries.push_back(entry);
+            log.prune();
+        }
+    }
+
+    /// the entries still kept for `bound_path`, oldest first
+    pub fn get(&self, bound_path: &str) -> Vec<AccessLogEntry> {
+        let Some(mut log) = self.logs.get_mut(bound_path) else {
+            return vec![];
+        };
+        log.prune();
+        log.entries.iter().cloned().collect()
+    }
+}
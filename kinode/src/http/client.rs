@@ -1,3 +1,7 @@
+use crate::blobs::{BlobStore, HANDLE_THRESHOLD};
+use crate::http::oauth::OAuth;
+use crate::idempotency::{Keyed, Recent};
+use crate::progress::Reporter;
 use anyhow::Result;
 use dashmap::DashMap;
 use futures::stream::{SplitSink, SplitStream};
@@ -6,11 +10,12 @@ use futures::StreamExt;
 use http::header::{HeaderMap, HeaderName, HeaderValue};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message as TungsteniteMessage};
 use tokio_tungstenite::{connect_async, tungstenite};
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 
-use lib::types::{core::*, http_client::*, http_server::*};
+use lib::types::{core::*, errors::ModuleError, http_client::*, http_server::*};
 
 // Test http_client with these commands in the terminal
 // !message our http_client {"method": "GET", "url": "https://jsonplaceholder.typicode.com/posts", "headers": {}}
@@ -28,26 +33,135 @@ type WebSocketMap = DashMap<
 /// so that both incoming and outgoing pushes can be routed appropriately
 type WebSocketStreams = Arc<WebSocketMap>;
 
+/// how long an idle pooled connection is kept open
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const MAX_IDLE_PER_HOST: usize = 16;
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// how requests to each origin have gone, for [`HttpClientAction::GetPoolMetrics`]
+#[derive(Default)]
+struct PoolMetrics {
+    hosts: DashMap<String, (HostMetrics, Duration)>,
+}
+
+impl PoolMetrics {
+    fn started(&self, origin: &str) -> Instant {
+        let mut host = self.hosts.entry(origin.to_string()).or_insert_with(|| {
+            (
+                HostMetrics {
+                    origin: origin.to_string(),
+                    ..Default::default()
+                },
+                Duration::ZERO,
+            )
+        });
+        host.0.in_flight += 1;
+        host.0.peak_in_flight = host.0.peak_in_flight.max(host.0.in_flight);
+        Instant::now()
+    }
+
+    fn finished(&self, origin: &str, started: Instant, version: Option<http::Version>) {
+        let Some(mut host) = self.hosts.get_mut(origin) else {
+            return;
+        };
+        let (metrics, total_latency) = &mut *host;
+        metrics.in_flight -= 1;
+        metrics.requests += 1;
+        match version {
+            None => metrics.errors += 1,
+            Some(version) => {
+                if version == http::Version::HTTP_2 {
+                    metrics.http2 += 1;
+                }
+                *total_latency += started.elapsed();
+                let answered = (metrics.requests - metrics.errors) as u32;
+                metrics.mean_latency_ms = (*total_latency / answered).as_millis() as u64;
+            }
+        }
+    }
+
+    fn table(&self) -> Vec<HostMetrics> {
+        let mut table: Vec<HostMetrics> = self.hosts.iter().map(|host| host.0.clone()).collect();
+        table.sort_by(|a, b| b.requests.cmp(&a.requests));
+        table
+    }
+}
+
+/// connections are pooled per host and kept alive while idle. HTTP/2 is
+/// used wherever a server offers it, multiplexing requests over one
+/// connection; `prior_knowledge` speaks it without asking, for plain-text
+/// servers that only speak HTTP/2.
+fn build_client(prior_knowledge: bool) -> reqwest::Result<reqwest::Client> {
+    let builder = reqwest::Client::builder()
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(MAX_IDLE_PER_HOST)
+        .tcp_keepalive(KEEPALIVE_INTERVAL)
+        .http2_keep_alive_interval(KEEPALIVE_INTERVAL)
+        .http2_keep_alive_while_idle(true)
+        .http2_adaptive_window(true);
+    if prior_knowledge {
+        builder.http2_prior_knowledge().build()
+    } else {
+        builder.build()
+    }
+}
+
 pub async fn http_client(
     our_name: String,
     send_to_loop: MessageSender,
     mut recv_in_client: MessageReceiver,
     print_tx: PrintSender,
+    blob_store: Arc<BlobStore>,
+    home_directory_path: String,
+    file_key: Vec<u8>,
 ) -> Result<()> {
-    let client = reqwest::Client::new();
+    let client = build_client(false)?;
+    let h2c_client = build_client(true)?;
+    let metrics = Arc::new(PoolMetrics::default());
+    let oauth = Arc::new(OAuth::load(&home_directory_path, &file_key).await);
     let our_name = Arc::new(our_name);
 
+    // providers send users back here from authorizing
+    KernelMessage::builder()
+        .id(rand::random())
+        .source((our_name.as_str(), HTTP_CLIENT_PROCESS_ID.clone()))
+        .target((our_name.as_str(), HTTP_SERVER_PROCESS_ID.clone()))
+        .message(Message::Request(Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&HttpServerAction::Bind {
+                path: "/oauth/callback".to_string(),
+                authenticated: false,
+                local_only: false,
+                cache: false,
+                min_role: Default::default(),
+                stream: false,
+            })
+            .unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .unwrap()
+        .send(&send_to_loop)
+        .await;
+
     let ws_streams: WebSocketStreams = Arc::new(DashMap::new());
+    // only HTTP requests that may change things wherever they are sent are
+    // kept by idempotency key: see `mutates`
+    let recent = Arc::new(Recent::default());
 
-    while let Some(KernelMessage {
-        id,
-        source,
-        rsvp,
-        message,
-        lazy_load_blob: blob,
-        ..
-    }) = recv_in_client.recv().await
-    {
+    while let Some(km) = recv_in_client.recv().await {
+        crate::metrics::handled(&HTTP_CLIENT_PROCESS_ID, &km);
+        let keyed = Keyed::of(&km, mutates);
+        let KernelMessage {
+            id,
+            source,
+            rsvp,
+            message,
+            lazy_load_blob: blob,
+            ..
+        } = km;
         let Message::Request(Request {
             body,
             expects_response,
@@ -56,6 +170,20 @@ pub async fn http_client(
         else {
             continue;
         };
+        if source.process == *HTTP_SERVER_PROCESS_ID {
+            if let Ok(HttpServerRequest::Http(req)) = serde_json::from_slice(&body) {
+                tokio::spawn(oauth_callback(
+                    our_name.clone(),
+                    id,
+                    source,
+                    req,
+                    oauth.clone(),
+                    client.clone(),
+                    send_to_loop.clone(),
+                ));
+            }
+            continue;
+        }
         // Check that the incoming request body is a HttpClientAction
         let Ok(request) = serde_json::from_slice::<HttpClientAction>(&body) else {
             // Send a "BadRequest" error if deserialization fails
@@ -81,22 +209,45 @@ pub async fn http_client(
         // Handle the request, returning if the request was a WS request
         let (is_ws, result) = match request {
             HttpClientAction::Http(req) => {
-                tokio::spawn(handle_http_request(
-                    our,
-                    id,
-                    target.clone(),
-                    expects_response,
-                    req,
-                    blob,
-                    client.clone(),
-                    send_to_loop.clone(),
-                    print_tx.clone(),
-                ));
+                let recent = recent.clone();
+                let target = target.clone();
+                // a plain-text request for HTTP/2 can't ask to upgrade to it
+                let client = if req.version.as_deref() == Some("HTTP/2.0")
+                    && req.url.starts_with("http://")
+                {
+                    h2c_client.clone()
+                } else {
+                    client.clone()
+                };
+                let metrics = metrics.clone();
+                let send_to_loop = send_to_loop.clone();
+                let print_tx = print_tx.clone();
+                let blob_store = blob_store.clone();
+                tokio::spawn(async move {
+                    recent
+                        .handle(keyed, &send_to_loop, |send_to_loop| {
+                            handle_http_request(
+                                our,
+                                id,
+                                target,
+                                expects_response,
+                                req,
+                                blob,
+                                client,
+                                metrics,
+                                send_to_loop,
+                                print_tx,
+                                blob_store,
+                            )
+                        })
+                        .await
+                });
                 (
                     false,
                     Ok(HttpClientResponse::Http(HttpResponse {
                         status: 200,
                         headers: HashMap::new(),
+                        stream: false,
                     })),
                 )
             }
@@ -143,42 +294,156 @@ pub async fn http_client(
                 )
                 .await,
             ),
+            HttpClientAction::GetPoolMetrics => {
+                (true, Ok(HttpClientResponse::PoolMetrics(metrics.table())))
+            }
+            HttpClientAction::OAuth(action) => {
+                let target = target.clone();
+                let oauth = oauth.clone();
+                let client = client.clone();
+                let send_to_loop = send_to_loop.clone();
+                tokio::spawn(async move {
+                    let result = oauth.handle(&target.process, action, &client).await;
+                    send_response(&our, id, target, result, &send_to_loop).await;
+                });
+                (false, Ok(HttpClientResponse::OAuthAck))
+            }
         };
 
-        // If the incoming request was a WS request, send a response
+        // If the incoming request was a WS request or for metrics, send a response
         // HTTP responses are handled in the handle_http_request function
         if is_ws {
-            let Ok(body) =
-                serde_json::to_vec::<Result<HttpClientResponse, HttpClientError>>(&result)
-            else {
-                continue;
-            };
-            let _ = send_to_loop
-                .send(KernelMessage {
-                    id,
-                    source: Address {
-                        node: our_name.to_string(),
-                        process: ProcessId::new(Some("http_client"), "distro", "sys"),
-                    },
-                    target: target.clone(),
-                    rsvp: None,
-                    message: Message::Response((
-                        Response {
-                            inherit: false,
-                            body,
-                            metadata: None,
-                            capabilities: vec![],
-                        },
-                        None,
-                    )),
-                    lazy_load_blob: None,
-                })
-                .await;
+            send_response(&our_name, id, target, result, &send_to_loop).await;
         }
     }
     Err(anyhow::anyhow!("http_client: loop died"))
 }
 
+/// whether a request body is an HTTP request that may change things wherever
+/// it is sent: any but GET, HEAD and OPTIONS, which are safe to send again
+fn mutates(body: &[u8]) -> bool {
+    let Ok(HttpClientAction::Http(request)) = serde_json::from_slice(body) else {
+        return false;
+    };
+    !["GET", "HEAD", "OPTIONS"]
+        .iter()
+        .any(|method| request.method.eq_ignore_ascii_case(method))
+}
+
+async fn send_response(
+    our: &str,
+    id: u64,
+    target: Address,
+    result: Result<HttpClientResponse, HttpClientError>,
+    send_to_loop: &MessageSender,
+) {
+    let Ok(body) = serde_json::to_vec::<Result<HttpClientResponse, HttpClientError>>(&result)
+    else {
+        return;
+    };
+    let metadata = result.as_ref().err().and_then(ModuleError::metadata);
+    KernelMessage::builder()
+        .id(id)
+        .source((our, HTTP_CLIENT_PROCESS_ID.clone()))
+        .target(target)
+        .message(Message::Response((
+            Response {
+                inherit: false,
+                body,
+                metadata,
+                capabilities: vec![],
+            },
+            None,
+        )))
+        .build()
+        .unwrap()
+        .send(send_to_loop)
+        .await;
+}
+
+/// a user sent back to [`OAUTH_CALLBACK_PATH`] by a provider: finish the
+/// authorization, show them how it went, and tell the process that began it
+async fn oauth_callback(
+    our: Arc<String>,
+    id: u64,
+    http_server: Address,
+    req: IncomingHttpRequest,
+    oauth: Arc<OAuth>,
+    client: reqwest::Client,
+    send_to_loop: MessageSender,
+) {
+    let (status, page) = match oauth.callback(&req.query_params, &client).await {
+        None => (
+            400,
+            "This authorization has expired, or was never begun.".to_string(),
+        ),
+        Some((process, name, result)) => {
+            let (status, page) = match &result {
+                Ok(()) => (
+                    200,
+                    format!("{process} is now authorized with {name}. You can close this tab."),
+                ),
+                Err(e) => (
+                    400,
+                    format!("Authorizing {process} with {name} failed: {e}"),
+                ),
+            };
+            KernelMessage::builder()
+                .id(rand::random())
+                .source((our.as_str(), HTTP_CLIENT_PROCESS_ID.clone()))
+                .target((our.as_str(), process))
+                .message(Message::Request(Request {
+                    inherit: false,
+                    expects_response: None,
+                    body: serde_json::to_vec(&OAuthAuthorized {
+                        name,
+                        error: result.err(),
+                    })
+                    .unwrap(),
+                    metadata: None,
+                    capabilities: vec![],
+                }))
+                .build()
+                .unwrap()
+                .send(&send_to_loop)
+                .await;
+            (status, page)
+        }
+    };
+    let page = format!(
+        "<!DOCTYPE html><html><body><p>{}</p></body></html>",
+        page.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    );
+    KernelMessage::builder()
+        .id(id)
+        .source((our.as_str(), HTTP_CLIENT_PROCESS_ID.clone()))
+        .target(http_server)
+        .message(Message::Response((
+            Response {
+                inherit: false,
+                body: serde_json::to_vec(&HttpResponse {
+                    status,
+                    headers: HashMap::from([("Content-Type".to_string(), "text/html".to_string())]),
+                    stream: false,
+                })
+                .unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            },
+            None,
+        )))
+        .lazy_load_blob(Some(LazyLoadBlob {
+            mime: Some("text/html".to_string()),
+            bytes: page.into_bytes(),
+        }))
+        .build()
+        .unwrap()
+        .send(&send_to_loop)
+        .await;
+}
+
 async fn connect_websocket(
     our: Arc<String>,
     id: u64,
@@ -223,6 +488,8 @@ async fn connect_websocket(
                 .send(Printout {
                     verbosity: 1,
                     content: format!("http_client: underlying lib connection error {e:?}"),
+                    source: None,
+                    level: None,
                 })
                 .await;
 
@@ -376,8 +643,10 @@ async fn handle_http_request(
     req: OutgoingHttpRequest,
     body: Option<LazyLoadBlob>,
     client: reqwest::Client,
+    metrics: Arc<PoolMetrics>,
     send_to_loop: MessageSender,
     print_tx: PrintSender,
+    blob_store: Arc<BlobStore>,
 ) {
     // Parse the HTTP Method
     let Ok(req_method) = http::Method::from_bytes(req.method.as_bytes()) else {
@@ -410,9 +679,13 @@ async fn handle_http_request(
         .send(Printout {
             verbosity: 2,
             content: format!("http_client: {req_method} request to {}", url),
+            source: None,
+            level: None,
         })
         .await;
 
+    let origin = url.origin().ascii_serialization();
+
     // Build the request
     let mut request_builder = client.request(req_method, url);
 
@@ -463,17 +736,67 @@ async fn handle_http_request(
     };
 
     // Send the HTTP request
-    match client.execute(request).await {
+    let started = metrics.started(&origin);
+    let result = client.execute(request).await;
+    metrics.finished(
+        &origin,
+        started,
+        result.as_ref().ok().map(reqwest::Response::version),
+    );
+    match result {
         Ok(response) => {
             // Handle the response and forward to the target process
             let Ok(body) = serde_json::to_vec::<Result<HttpClientResponse, HttpClientError>>(&Ok(
                 HttpClientResponse::Http(HttpResponse {
                     status: response.status().as_u16(),
                     headers: serialize_headers(response.headers()),
+                    stream: false,
                 }),
             )) else {
                 return;
             };
+            // large downloads go straight to the blob store, as do those of
+            // unknown length once they turn out to be large
+            let body = match response.content_length() {
+                Some(len) if len >= HANDLE_THRESHOLD as u64 => {
+                    let progress = Reporter::new(
+                        &our,
+                        ProcessId::new(Some("http_client"), "distro", "sys"),
+                        &send_to_loop,
+                        id.to_string(),
+                        target.clone(),
+                        format!("downloading from {origin}"),
+                    );
+                    store_body(&blob_store, response, progress)
+                        .await
+                        .map(|handle| (None, Some(handle)))
+                }
+                Some(_) => Ok((
+                    Some(LazyLoadBlob {
+                        mime: None,
+                        bytes: response.bytes().await.unwrap_or_default().to_vec(),
+                    }),
+                    None,
+                )),
+                None => buffer_body(&blob_store, response).await,
+            };
+            let (blob, blob_handle) = match body {
+                Ok(body) => body,
+                Err(e) => {
+                    http_error_message(
+                        our,
+                        id,
+                        target,
+                        expects_response,
+                        HttpClientError::RequestFailed {
+                            error: e.to_string(),
+                        },
+                        send_to_loop,
+                    )
+                    .await;
+                    return;
+                }
+            };
             let _ = send_to_loop
                 .send(KernelMessage {
                     id,
@@ -492,10 +815,9 @@ async fn handle_http_request(
                         },
                         None,
                     )),
-                    lazy_load_blob: Some(LazyLoadBlob {
-                        mime: None,
-                        bytes: response.bytes().await.unwrap_or_default().to_vec(),
-                    }),
+                    lazy_load_blob: blob,
+                    blob_handle,
+                    sent_at: Some(std::time::Instant::now()),
                 })
                 .await;
         }
@@ -504,6 +826,8 @@ async fn handle_http_request(
                 .send(Printout {
                     verbosity: 2,
                     content: "http_client: executed request but got error".to_string(),
+                    source: None,
+                    level: None,
                 })
                 .await;
             // Forward the error to the target process
@@ -565,6 +889,45 @@ fn deserialize_headers(hashmap: HashMap<String, String>) -> HeaderMap {
     header_map
 }
 
+/// stream a large body into the blob store, reporting progress
+async fn store_body(
+    blob_store: &BlobStore,
+    mut response: reqwest::Response,
+    mut progress: Reporter,
+) -> Result<BlobHandle> {
+    let total = response.content_length().unwrap_or(0);
+    let mut done = 0;
+    let mut writer = blob_store.writer().await?;
+    while let Some(chunk) = response.chunk().await? {
+        writer.write(&chunk).await?;
+        done += chunk.len() as u64;
+        progress.report(done, total).await;
+    }
+    Ok(blob_store.finish(writer, None).await?)
+}
+
+/// read a body of unknown length into memory, moving it to the blob store
+/// if it reaches [`HANDLE_THRESHOLD`]
+async fn buffer_body(
+    blob_store: &BlobStore,
+    mut response: reqwest::Response,
+) -> Result<(Option<LazyLoadBlob>, Option<BlobHandle>)> {
+    let mut bytes = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() >= HANDLE_THRESHOLD {
+            let mut writer = blob_store.writer().await?;
+            writer.write(&bytes).await?;
+            drop(bytes);
+            while let Some(chunk) = response.chunk().await? {
+                writer.write(&chunk).await?;
+            }
+            return Ok((None, Some(blob_store.finish(writer, None).await?)));
+        }
+    }
+    Ok((Some(LazyLoadBlob { mime: None, bytes }), None))
+}
+
 /// Send an HTTP error to a target
 async fn http_error_message(
     our: Arc<String>,
@@ -575,6 +938,7 @@ async fn http_error_message(
     send_to_loop: MessageSender,
 ) {
     if expects_response.is_some() {
+        let metadata = error.metadata();
         let Ok(body) = serde_json::to_vec::<Result<HttpResponse, HttpClientError>>(&Err(error))
         else {
             return;
@@ -592,12 +956,14 @@ async fn http_error_message(
                     Response {
                         inherit: false,
                         body,
-                        metadata: None,
+                        metadata,
                         capabilities: vec![],
                     },
                     None,
                 )),
                 lazy_load_blob: None,
+                blob_handle: None,
+                sent_at: Some(std::time::Instant::now()),
             })
             .await;
     }
@@ -696,6 +1062,8 @@ async fn handle_ws_message(
                 capabilities: vec![],
             }),
             lazy_load_blob: blob,
+            blob_handle: None,
+            sent_at: Some(std::time::Instant::now()),
         })
         .await;
 }
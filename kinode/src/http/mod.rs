@@ -1,6 +1,11 @@
 #![allow(unused)]
+pub mod access_log;
 pub mod client;
+pub mod oauth;
 pub mod server;
+pub mod stream;
+pub mod terminal;
+pub mod users;
 pub mod utils;
 
 pub use lib::types::http_client as client_types;
@@ -0,0 +1,356 @@
+//! OAuth2 flows run by http_client on behalf of processes, so that each
+//! package needn't carry its own token plumbing. a process registers a
+//! provider by name, and then asks for a token whenever it needs one: it
+//! gets the one kept, refreshed if near expiry, or under the
+//! client-credentials flow, a new one.
+//!
+//! the authorization-code flow sends the user to the provider with a URL
+//! from [`OAuthAction::Authorize`], and the provider sends them back to
+//! `OAUTH_CALLBACK_PATH`, bound by http_client on http_server. the code
+//! there is exchanged for a token, with PKCE, and the process told.
+//!
+//! providers and their tokens are kept in `{home}/.oauth`, encrypted with a
+//! key derived from the keyfile's file key, which survives a password change.
+use crate::http::client_types::{
+    HttpClientError, HttpClientResponse, OAuthAction, OAuthConfig, OAuthFlow, OAuthToken,
+};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use dashmap::DashMap;
+use lib::types::core::ProcessId;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// how long a user has to come back from authorizing
+const AUTHORIZE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// a token this close to expiry, in milliseconds, is refreshed rather than handed out
+const EXPIRY_MARGIN: u64 = 60_000;
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Provider {
+    config: OAuthConfig,
+    token: Option<OAuthToken>,
+    refresh_token: Option<String>,
+}
+
+/// an authorization begun with [`OAuthAction::Authorize`], by its state
+struct Pending {
+    process: ProcessId,
+    name: String,
+    /// the PKCE code verifier
+    verifier: String,
+    started: Instant,
+}
+
+/// a token endpoint's answer, as in RFC 6749 section 5.1
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default = "bearer")]
+    token_type: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+fn bearer() -> String {
+    "Bearer".to_string()
+}
+
+pub struct OAuth {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+    providers: DashMap<(ProcessId, String), Provider>,
+    pending: DashMap<String, Pending>,
+    /// one save at a time, so that an older one can't land last
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl OAuth {
+    /// load the providers kept in `{home}/.oauth`. if they can't be read,
+    /// we start without them: processes can register them again.
+    pub async fn load(home_directory_path: &str, file_key: &[u8]) -> Self {
+        let key = Sha256::digest([b"kinode-oauth".as_slice(), file_key].concat());
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let path = PathBuf::from(format!("{home_directory_path}/.oauth"));
+        let providers = tokio::fs::read(&path)
+            .await
+            .ok()
+            .filter(|sealed| sealed.len() > NONCE_LEN)
+            .and_then(|sealed| {
+                let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+                cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()
+            })
+            .and_then(|plain| {
+                serde_json::from_slice::<Vec<(ProcessId, String, Provider)>>(&plain).ok()
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(process, name, provider)| ((process, name), provider))
+            .collect();
+        OAuth {
+            path,
+            cipher,
+            providers,
+            pending: DashMap::new(),
+            saving: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    async fn save(&self) -> Result<(), HttpClientError> {
+        let _saving = self.saving.lock().await;
+        let providers: Vec<(ProcessId, String, Provider)> = self
+            .providers
+            .iter()
+            .map(|entry| {
+                (
+                    entry.key().0.clone(),
+                    entry.key().1.clone(),
+                    entry.value().clone(),
+                )
+            })
+            .collect();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, serde_json::to_vec(&providers).unwrap().as_slice())
+            .map_err(|_| failed("couldn't encrypt tokens"))?;
+        // written whole and moved into place, so a crash can't leave it torn
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, [nonce.as_slice(), &ciphertext].concat())
+            .await
+            .map_err(|e| failed(&format!("couldn't save tokens: {e}")))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| failed(&format!("couldn't save tokens: {e}")))
+    }
+
+    pub async fn handle(
+        &self,
+        process: &ProcessId,
+        action: OAuthAction,
+        http: &reqwest::Client,
+    ) -> Result<HttpClientResponse, HttpClientError> {
+        match action {
+            OAuthAction::Register { name, config } => {
+                if config.flow == OAuthFlow::AuthorizationCode
+                    && (config.authorize_url.is_none() || config.redirect_uri.is_none())
+                {
+                    return Err(failed(
+                        "the authorization-code flow needs an authorize_url and a redirect_uri",
+                    ));
+                }
+                self.providers.insert(
+                    (process.clone(), name),
+                    Provider {
+                        config,
+                        token: None,
+                        refresh_token: None,
+                    },
+                );
+                self.save().await?;
+                Ok(HttpClientResponse::OAuthAck)
+            }
+            OAuthAction::Authorize { name } => {
+                let config = self.provider(process, &name)?.config;
+                let (Some(authorize_url), Some(redirect_uri)) =
+                    (&config.authorize_url, &config.redirect_uri)
+                else {
+                    return Err(failed(&format!("{name} uses the client-credentials flow")));
+                };
+                let mut url = url::Url::parse(authorize_url)
+                    .map_err(|_| failed(&format!("bad authorize_url {authorize_url}")))?;
+                let state = random_string();
+                let verifier = random_string();
+                url.query_pairs_mut()
+                    .append_pair("response_type", "code")
+                    .append_pair("client_id", &config.client_id)
+                    .append_pair("redirect_uri", redirect_uri)
+                    .append_pair("scope", &config.scopes.join(" "))
+                    .append_pair("state", &state)
+                    .append_pair(
+                        "code_challenge",
+                        &URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())),
+                    )
+                    .append_pair("code_challenge_method", "S256");
+                self.pending
+                    .retain(|_, pending| pending.started.elapsed() < AUTHORIZE_TIMEOUT);
+                self.pending.insert(
+                    state,
+                    Pending {
+                        process: process.clone(),
+                        name,
+                        verifier,
+                        started: Instant::now(),
+                    },
+                );
+                Ok(HttpClientResponse::OAuthAuthorizeUrl(url.to_string()))
+            }
+            OAuthAction::Token { name } => {
+                let provider = self.provider(process, &name)?;
+                if let Some(token) = &provider.token {
+                    if token.expires_at.map_or(true, |expires_at| {
+                        expires_at > crate::clock::now() + EXPIRY_MARGIN
+                    }) {
+                        return Ok(HttpClientResponse::OAuthToken(token.clone()));
+                    }
+                }
+                let mut result = Err(failed(&format!("{name} has not been authorized")));
+                if let Some(refresh_token) = &provider.refresh_token {
+                    result = request_token(
+                        http,
+                        &provider.config,
+                        &[
+                            ("grant_type", "refresh_token"),
+                            ("refresh_token", refresh_token.as_str()),
+                        ],
+                    )
+                    .await;
+                }
+                if result.is_err() && provider.config.flow == OAuthFlow::ClientCredentials {
+                    let scope = provider.config.scopes.join(" ");
+                    let mut params = vec![("grant_type", "client_credentials")];
+                    if !scope.is_empty() {
+                        params.push(("scope", scope.as_str()));
+                    }
+                    result = request_token(http, &provider.config, &params).await;
+                }
+                let token = self.keep(process, &name, result?).await?;
+                Ok(HttpClientResponse::OAuthToken(token))
+            }
+            OAuthAction::Forget { name } => {
+                self.providers.remove(&(process.clone(), name));
+                self.save().await?;
+                Ok(HttpClientResponse::OAuthAck)
+            }
+        }
+    }
+
+    /// exchange the code a provider sent the user back with. returns the
+    /// process and provider the authorization was for, and how it went, or
+    /// None if it wasn't one we began.
+    pub async fn callback(
+        &self,
+        query: &HashMap<String, String>,
+        http: &reqwest::Client,
+    ) -> Option<(ProcessId, String, Result<(), String>)> {
+        let (_, pending) = self.pending.remove(query.get("state")?)?;
+        if pending.started.elapsed() >= AUTHORIZE_TIMEOUT {
+            return None;
+        }
+        let result = self.exchange(&pending, query, http).await;
+        Some((pending.process, pending.name, result))
+    }
+
+    async fn exchange(
+        &self,
+        pending: &Pending,
+        query: &HashMap<String, String>,
+        http: &reqwest::Client,
+    ) -> Result<(), String> {
+        if let Some(error) = query.get("error") {
+            return Err(error.clone());
+        }
+        let code = query.get("code").ok_or("no code given")?;
+        let config = self
+            .provider(&pending.process, &pending.name)
+            .map_err(|e| e.to_string())?
+            .config;
+        let tokens = request_token(
+            http,
+            &config,
+            &[
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+                ("redirect_uri", config.redirect_uri.as_deref().unwrap_or("")),
+                ("code_verifier", pending.verifier.as_str()),
+            ],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        self.keep(&pending.process, &pending.name, tokens)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    fn provider(&self, process: &ProcessId, name: &str) -> Result<Provider, HttpClientError> {
+        self.providers
+            .get(&(process.clone(), name.to_string()))
+            .map(|provider| provider.clone())
+            .ok_or_else(|| failed(&format!("no provider {name}")))
+    }
+
+    /// keep a token from the provider's token endpoint
+    async fn keep(
+        &self,
+        process: &ProcessId,
+        name: &str,
+        response: TokenResponse,
+    ) -> Result<OAuthToken, HttpClientError> {
+        let token = OAuthToken {
+            access_token: response.access_token,
+            token_type: response.token_type,
+            expires_at: response
+                .expires_in
+                .map(|secs| crate::clock::now() + secs * 1000),
+        };
+        {
+            let Some(mut provider) = self.providers.get_mut(&(process.clone(), name.to_string()))
+            else {
+                return Err(failed(&format!("{name} was forgotten")));
+            };
+            provider.token = Some(token.clone());
+            // a provider may not send a new refresh token with each refresh
+            if response.refresh_token.is_some() {
+                provider.refresh_token = response.refresh_token;
+            }
+        }
+        self.save().await?;
+        Ok(token)
+    }
+}
+
+async fn request_token(
+    http: &reqwest::Client,
+    config: &OAuthConfig,
+    params: &[(&str, &str)],
+) -> Result<TokenResponse, HttpClientError> {
+    let mut form = params.to_vec();
+    form.push(("client_id", config.client_id.as_str()));
+    if let Some(client_secret) = &config.client_secret {
+        form.push(("client_secret", client_secret.as_str()));
+    }
+    let response = http
+        .post(&config.token_url)
+        .header("Accept", "application/json")
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| failed(&format!("token request failed: {e}")))?;
+    let status = response.status();
+    let body = response.bytes().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(failed(&format!(
+            "token endpoint answered {status}: {}",
+            String::from_utf8_lossy(&body)
+        )));
+    }
+    serde_json::from_slice(&body).map_err(|e| failed(&format!("bad token response: {e}")))
+}
+
+fn failed(error: &str) -> HttpClientError {
+    HttpClientError::OAuthFailed {
+        error: error.to_string(),
+    }
+}
+
+fn random_string() -> String {
+    URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
@@ -1,19 +1,26 @@
+use crate::http::access_log::AccessLogs;
 use crate::http::server_types::*;
+use crate::http::stream::{BodyStream, Streams};
+use crate::http::terminal::TerminalSessions;
+use crate::http::users::Users;
 use crate::http::utils::*;
 use crate::keygen;
+use crate::terminal::remote::{WebTerminalRequest, WebTerminalSender};
 use anyhow::Result;
 use base64::{engine::general_purpose::STANDARD as base64_standard, Engine};
 use dashmap::DashMap;
 use futures::{SinkExt, StreamExt};
 use http::uri::Authority;
 use lib::types::core::*;
+use lib::types::errors::ModuleError;
 use route_recognizer::Router;
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use warp::http::{header::HeaderValue, StatusCode};
+use warp::hyper::body::{Buf, HttpBody};
 use warp::ws::{WebSocket, Ws};
 use warp::{Filter, Reply};
 
@@ -23,6 +30,8 @@ const HTTP_SELF_IMPOSED_TIMEOUT: u64 = 15;
 const HTTP_SELF_IMPOSED_TIMEOUT: u64 = 600;
 
 const LOGIN_HTML: &str = include_str!("login.html");
+/// printouts a web terminal may fall behind by before it misses some
+const WEB_TERMINAL_PRINTOUT_CAPACITY: usize = 1024;
 
 /// mapping from a given HTTP request (assigned an ID) to the oneshot
 /// channel that will get a response from the app that handles the request,
@@ -33,25 +42,57 @@ type HttpSender = tokio::sync::oneshot::Sender<(HttpResponse, Vec<u8>)>;
 /// mapping from an open websocket connection to a channel that will ingest
 /// WebSocketPush messages from the app that handles the connection, and
 /// send them to the connection.
-type WebSocketSenders = Arc<DashMap<u32, (ProcessId, WebSocketSender)>>;
+type WebSocketSenders = Arc<DashMap<u32, WsConnection>>;
 type WebSocketSender = tokio::sync::mpsc::Sender<warp::ws::Message>;
 
+struct WsConnection {
+    app: ProcessId,
+    sender: WebSocketSender,
+    /// extension connections only take pushes wrapped by WebSocketExtPushOutgoing
+    extension: bool,
+    info: WsConnectionInfo,
+}
+
 type PathBindings = Arc<RwLock<Router<BoundPath>>>;
 type WsPathBindings = Arc<RwLock<Router<BoundWsPath>>>;
 
+/// every path each process has bound. the routers can't be iterated,
+/// so this is what lets us unbind everything belonging to a process.
+type BindingsByProcess = Arc<DashMap<ProcessId, ProcessBindings>>;
+
+#[derive(Default)]
+struct ProcessBindings {
+    http: HashSet<String>,
+    ws: HashSet<String>,
+}
+
+/// the paths bound by each process of a package when a transaction over
+/// them was prepared, by transaction ID
+type BindingsTransactions = HashMap<u64, HashMap<ProcessId, PreparedBindings>>;
+
+struct PreparedBindings {
+    http: Vec<(String, BoundPath)>,
+    ws: Vec<(String, BoundWsPath)>,
+}
+
+#[derive(Clone)]
 struct BoundPath {
     pub app: Option<ProcessId>, // if None, path has been unbound
     pub path: String,
     pub secure_subdomain: Option<String>,
     pub authenticated: bool,
+    pub min_role: UserRole,
     pub local_only: bool,
     pub static_content: Option<LazyLoadBlob>, // TODO store in filesystem and cache
+    pub stream: bool,
 }
 
+#[derive(Clone)]
 struct BoundWsPath {
     pub app: Option<ProcessId>, // if None, path has been unbound
     pub secure_subdomain: Option<String>,
     pub authenticated: bool,
+    pub min_role: UserRole,
     pub encrypted: bool, // TODO use
     pub extension: bool,
 }
@@ -112,10 +153,11 @@ async fn send_push(
         }
     };
     // Send to the websocket if registered
-    if let Some(got) = ws_senders.get(&channel_id) {
-        let owner_process = &got.value().0;
-        let sender = &got.value().1;
-        if owner_process != &source.process {
+    let connection = ws_senders
+        .get(&channel_id)
+        .map(|connection| (connection.app.clone(), connection.sender.clone()));
+    if let Some((owner_process, sender)) = connection {
+        if owner_process != source.process {
             send_action_response(
                 id,
                 source,
@@ -128,7 +170,11 @@ async fn send_push(
             return true;
         }
         match sender.send(ws_message).await {
-            Ok(_) => {}
+            Ok(_) => {
+                if let Some(mut connection) = ws_senders.get_mut(&channel_id) {
+                    connection.info.messages_sent += 1;
+                }
+            }
             Err(_) => {
                 send_action_response(
                     id,
@@ -171,19 +217,32 @@ async fn send_push(
 ///
 /// In addition to binding on paths, the HTTP server can receive incoming WebSocket connections
 /// and pass them to a targeted app. The server will handle encrypting and decrypting messages
-/// over these connections.
+/// over these connections. An app can put the connections it holds in topics to broadcast
+/// to, list them, and limit what each client may send.
+///
+/// If given `web_terminal`, the server also offers the terminal itself over a WebSocket
+/// at `/terminal` to admins who open a terminal session with their password:
+/// see [`WebTerminalInput`].
+///
+/// Besides the node's owner, the users in [`Users`] can log in, each with a [`UserRole`]
+/// that decides which authenticated paths they reach. Requests from a logged-in user
+/// name them, so that processes can authorize per user.
 pub async fn http_server(
     our_name: String,
     our_port: u16,
+    home_directory_path: String,
     encoded_keyfile: Vec<u8>,
     jwt_secret_bytes: Vec<u8>,
     mut recv_in_server: MessageReceiver,
     send_to_loop: MessageSender,
     print_tx: PrintSender,
+    send_to_caps_oracle: CapMessageSender,
+    web_terminal: Option<WebTerminalSender>,
 ) -> Result<()> {
     let our_name = Arc::new(our_name);
     let encoded_keyfile = Arc::new(encoded_keyfile);
     let jwt_secret_bytes = Arc::new(jwt_secret_bytes);
+    let users = Arc::new(Users::load(&home_directory_path).await?);
     let http_response_senders: HttpResponseSenders = Arc::new(DashMap::new());
     let ws_senders: WebSocketSenders = Arc::new(DashMap::new());
     let path = format!("/rpc:distro:sys/message");
@@ -195,14 +254,20 @@ pub async fn http_server(
         path: path.clone(),
         secure_subdomain: None,
         authenticated: false,
+        min_role: UserRole::default(),
         local_only: true,
         static_content: None,
+        stream: false,
     };
     bindings_map.add(&path, rpc_bound_path);
     let path_bindings: PathBindings = Arc::new(RwLock::new(bindings_map));
 
     // ws path bindings
     let ws_path_bindings: WsPathBindings = Arc::new(RwLock::new(Router::new()));
+    let bindings_by_process: BindingsByProcess = Arc::new(DashMap::new());
+    let mut transactions: BindingsTransactions = HashMap::new();
+    let access_logs: Arc<AccessLogs> = Arc::new(AccessLogs::default());
+    let streams: Arc<Streams> = Arc::new(Streams::default());
 
     tokio::spawn(serve(
         our_name.clone(),
@@ -213,19 +278,30 @@ pub async fn http_server(
         ws_senders.clone(),
         encoded_keyfile.clone(),
         jwt_secret_bytes.clone(),
+        users.clone(),
         send_to_loop.clone(),
         print_tx.clone(),
+        web_terminal,
+        access_logs.clone(),
+        streams.clone(),
     ));
 
     while let Some(km) = recv_in_server.recv().await {
+        crate::metrics::handled(&HTTP_SERVER_PROCESS_ID, &km);
         handle_app_message(
             km,
             http_response_senders.clone(),
             path_bindings.clone(),
             ws_path_bindings.clone(),
+            bindings_by_process.clone(),
+            &mut transactions,
             ws_senders.clone(),
+            &users,
+            &access_logs,
+            &streams,
             send_to_loop.clone(),
             print_tx.clone(),
+            &send_to_caps_oracle,
         )
         .await;
     }
@@ -243,21 +319,67 @@ async fn serve(
     ws_senders: WebSocketSenders,
     encoded_keyfile: Arc<Vec<u8>>,
     jwt_secret_bytes: Arc<Vec<u8>>,
+    users: Arc<Users>,
     send_to_loop: MessageSender,
     print_tx: PrintSender,
+    web_terminal: Option<WebTerminalSender>,
+    access_logs: Arc<AccessLogs>,
+    streams: Arc<Streams>,
 ) {
     let _ = print_tx
         .send(Printout {
             verbosity: 0,
             content: format!("http_server: running on port {our_port}"),
+            source: None,
+            level: None,
         })
         .await;
 
+    // filters to open web terminal sessions and receive their connections, if enabled
+    let terminal_sessions = Arc::new(TerminalSessions::default());
+    let terminal_enabled = web_terminal.is_some();
+    let cloned_our = our.clone();
+    let cloned_encoded_keyfile = encoded_keyfile.clone();
+    let cloned_users = users.clone();
+    let cloned_terminal_sessions = terminal_sessions.clone();
+    let web_terminal_session_route = warp::path!("terminal" / "session")
+        .and(warp::post())
+        .and(warp::any().and_then(move || async move {
+            match terminal_enabled {
+                true => Ok(()),
+                false => Err(warp::reject::not_found()),
+            }
+        }))
+        .untuple_one()
+        .and(warp::body::content_length_limit(1024 * 16))
+        .and(warp::body::json())
+        .and(warp::filters::header::headers_cloned())
+        .and(warp::any().map(move || cloned_our.clone()))
+        .and(warp::any().map(move || cloned_encoded_keyfile.clone()))
+        .and(warp::any().map(move || cloned_users.clone()))
+        .and(warp::any().map(move || cloned_terminal_sessions.clone()))
+        .and_then(web_terminal_session_handler);
+    let cloned_our = our.clone();
+    let web_terminal_route = warp::path("terminal")
+        .and(warp::path::end())
+        .and(warp::ws())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::filters::header::headers_cloned())
+        .and(warp::any().map(move || cloned_our.clone()))
+        .and(warp::any().map(move || terminal_sessions.clone()))
+        .and(warp::any().and_then(move || {
+            let web_terminal = web_terminal.clone();
+            async move { web_terminal.ok_or_else(warp::reject::not_found) }
+        }))
+        .and_then(web_terminal_handler);
+
     // filter to receive websockets
     let cloned_msg_tx = send_to_loop.clone();
     let cloned_our = our.clone();
     let cloned_jwt_secret_bytes = jwt_secret_bytes.clone();
     let cloned_print_tx = print_tx.clone();
+    let accept_print_tx = print_tx.clone();
+    let cloned_users = users.clone();
     let ws_route = warp::ws()
         .and(warp::addr::remote())
         .and(warp::path::full())
@@ -265,6 +387,7 @@ async fn serve(
         .and(warp::filters::header::headers_cloned())
         .and(warp::any().map(move || cloned_our.clone()))
         .and(warp::any().map(move || cloned_jwt_secret_bytes.clone()))
+        .and(warp::any().map(move || cloned_users.clone()))
         .and(warp::any().map(move || ws_senders.clone()))
         .and(warp::any().map(move || ws_path_bindings.clone()))
         .and(warp::any().map(move || cloned_msg_tx.clone()))
@@ -283,6 +406,8 @@ async fn serve(
             .replace("${fake}", fake_node),
     );
     let cloned_our = our.clone();
+    let cloned_jwt_secret_bytes = jwt_secret_bytes.clone();
+    let cloned_users = users.clone();
     let cloned_login_html: &'static str = login_html.to_string().leak();
     let login = warp::path("login").and(warp::path::end()).and(
         warp::get()
@@ -294,6 +419,8 @@ async fn serve(
                 .and(warp::body::json())
                 .and(warp::any().map(move || cloned_our.clone()))
                 .and(warp::any().map(move || encoded_keyfile.clone()))
+                .and(warp::any().map(move || cloned_jwt_secret_bytes.clone()))
+                .and(warp::any().map(move || cloned_users.clone()))
                 .and_then(login_handler)),
     );
 
@@ -304,83 +431,115 @@ async fn serve(
         .and(warp::path::full())
         .and(warp::query::<HashMap<String, String>>())
         .and(warp::filters::header::headers_cloned())
-        .and(warp::filters::body::bytes())
+        .and(warp::body::stream())
         .and(warp::any().map(move || our.clone()))
         .and(warp::any().map(move || http_response_senders.clone()))
         .and(warp::any().map(move || path_bindings.clone()))
         .and(warp::any().map(move || jwt_secret_bytes.clone()))
+        .and(warp::any().map(move || users.clone()))
         .and(warp::any().map(move || send_to_loop.clone()))
         .and(warp::any().map(move || print_tx.clone()))
         .and(warp::any().map(move || login_html.clone()))
+        .and(warp::any().map(move || (access_logs.clone(), streams.clone())))
         .and_then(http_handler);
 
-    let filter_with_ws = ws_route.or(login).or(filter);
+    let filter_with_ws = web_terminal_session_route
+        .or(web_terminal_route)
+        .or(ws_route)
+        .or(login)
+        .or(filter);
+    let listener = bind_any(our_port).expect("http_server: couldn't bind port");
     warp::serve(filter_with_ws)
-        .run(([0, 0, 0, 0], our_port))
+        .run_incoming(incoming(listener, accept_print_tx))
         .await;
 }
 
 /// handle non-GET requests on /login. if POST, validate password
 /// and return auth token, which will be stored in a cookie.
+/// the node's owner logs in with the keyfile password; other users
+/// give their username and the password they were added with.
 async fn login_handler(
     info: LoginInfo,
     our: Arc<String>,
     encoded_keyfile: Arc<Vec<u8>>,
+    jwt_secret_bytes: Arc<Vec<u8>>,
+    users: Arc<Users>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     #[cfg(feature = "simulation-mode")]
-    let info = LoginInfo {
-        password_hash: "secret".to_string(),
-        subdomain: info.subdomain,
+    let info = if is_user_login(&info, &our) {
+        info
+    } else {
+        LoginInfo {
+            password_hash: "secret".to_string(),
+            subdomain: info.subdomain,
+            username: None,
+        }
     };
 
-    match keygen::decode_keyfile(&encoded_keyfile, &info.password_hash) {
-        Ok(keyfile) => {
-            let token = match keygen::generate_jwt(
-                &keyfile.jwt_secret_bytes,
-                our.as_ref(),
-                &info.subdomain,
-            ) {
-                Some(token) => token,
-                None => {
-                    return Ok(warp::reply::with_status(
-                        warp::reply::json(&"Failed to generate JWT"),
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                    )
-                    .into_response())
-                }
+    let (token, body) = match info.username.as_deref() {
+        Some(username) if is_user_login(&info, &our) => {
+            let Some(user) = users.verify(username, &info.password_hash) else {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&"Incorrect username or password"),
+                    StatusCode::UNAUTHORIZED,
+                )
+                .into_response());
             };
-
-            let mut response = warp::reply::with_status(
-                warp::reply::json(&base64_standard.encode(encoded_keyfile.to_vec())),
-                StatusCode::OK,
+            (
+                keygen::generate_jwt(&jwt_secret_bytes, &our, &info.subdomain, Some(&user.name)),
+                warp::reply::json(&user),
             )
-            .into_response();
-
-            let cookie = match info.subdomain.unwrap_or_default().as_str() {
-                "" => format!("kinode-auth_{our}={token};"),
-                subdomain => format!("kinode-auth_{our}@{subdomain}={token};"),
-            };
-
-            match HeaderValue::from_str(&cookie) {
-                Ok(v) => {
-                    response.headers_mut().append("set-cookie", v);
-                    Ok(response)
-                }
-                Err(e) => Ok(warp::reply::with_status(
-                    warp::reply::json(&format!("Failed to generate Auth JWT: {e}")),
+        }
+        _ => match keygen::decode_keyfile(&encoded_keyfile, &info.password_hash) {
+            Ok(keyfile) => (
+                keygen::generate_jwt(&keyfile.jwt_secret_bytes, &our, &info.subdomain, None),
+                warp::reply::json(&base64_standard.encode(encoded_keyfile.to_vec())),
+            ),
+            Err(e) => {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&format!("Failed to decode keyfile: {e}")),
                     StatusCode::INTERNAL_SERVER_ERROR,
                 )
-                .into_response()),
+                .into_response())
             }
+        },
+    };
+
+    let Some(token) = token else {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Failed to generate JWT"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response());
+    };
+
+    let mut response = warp::reply::with_status(body, StatusCode::OK).into_response();
+
+    let cookie = match info.subdomain.unwrap_or_default().as_str() {
+        "" => format!("kinode-auth_{our}={token};"),
+        subdomain => format!("kinode-auth_{our}@{subdomain}={token};"),
+    };
+
+    match HeaderValue::from_str(&cookie) {
+        Ok(v) => {
+            response.headers_mut().append("set-cookie", v);
+            Ok(response)
         }
         Err(e) => Ok(warp::reply::with_status(
-            warp::reply::json(&format!("Failed to decode keyfile: {e}")),
+            warp::reply::json(&format!("Failed to generate Auth JWT: {e}")),
             StatusCode::INTERNAL_SERVER_ERROR,
         )
         .into_response()),
     }
 }
 
+/// whether a login is by a user other than the node's owner
+fn is_user_login(info: &LoginInfo, our: &str) -> bool {
+    info.username
+        .as_deref()
+        .is_some_and(|username| !username.is_empty() && username != our)
+}
+
 async fn ws_handler(
     ws_connection: Ws,
     socket_addr: Option<SocketAddr>,
@@ -389,6 +548,7 @@ async fn ws_handler(
     headers: warp::http::HeaderMap,
     our: Arc<String>,
     jwt_secret_bytes: Arc<Vec<u8>>,
+    users: Arc<Users>,
     ws_senders: WebSocketSenders,
     ws_path_bindings: WsPathBindings,
     send_to_loop: MessageSender,
@@ -399,6 +559,8 @@ async fn ws_handler(
         .send(Printout {
             verbosity: 2,
             content: format!("http_server: got ws request for {original_path}"),
+            source: None,
+            level: None,
         })
         .await;
 
@@ -414,18 +576,24 @@ async fn ws_handler(
         return Err(warp::reject::not_found());
     };
 
-    if bound_path.authenticated {
-        let Some(auth_token) = serialized_headers.get("cookie") else {
+    let cookie = serialized_headers
+        .get("cookie")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let user = if bound_path.authenticated {
+        if cookie.is_empty() {
             return Err(warp::reject::not_found());
-        };
+        }
 
-        if let Some(ref subdomain) = bound_path.secure_subdomain {
+        let user = if let Some(ref subdomain) = bound_path.secure_subdomain {
             let _ = print_tx
                 .send(Printout {
                     verbosity: 2,
                     content: format!(
                         "http_server: ws request for {original_path} bound by subdomain {subdomain}"
                     ),
+                    source: None,
+                    level: None,
                 })
                 .await;
             // assert that host matches what this app wants it to be
@@ -435,17 +603,21 @@ async fn ws_handler(
             };
             // parse out subdomain from host (there can only be one)
             let request_subdomain = host.host().split('.').next().unwrap_or("");
-            if request_subdomain != subdomain
-                || !auth_cookie_valid(&our, Some(&app), auth_token, &jwt_secret_bytes)
-            {
+            if request_subdomain != subdomain {
                 return Err(warp::reject::not_found());
             }
+            auth_cookie_user(&our, Some(&app), cookie, &jwt_secret_bytes, &users)
         } else {
-            if !auth_cookie_valid(&our, None, auth_token, &jwt_secret_bytes) {
-                return Err(warp::reject::not_found());
-            }
+            auth_cookie_user(&our, None, cookie, &jwt_secret_bytes, &users)
+        };
+        // a user whose role is too low is told no more than one not logged in
+        match user {
+            Some(user) if user.role >= bound_path.min_role => Some(user),
+            _ => return Err(warp::reject::not_found()),
         }
-    }
+    } else {
+        auth_cookie_user(&our, None, cookie, &jwt_secret_bytes, &users)
+    };
 
     let is_local = socket_addr
         .map(|addr| addr.ip().is_loopback())
@@ -475,6 +647,9 @@ async fn ws_handler(
             our.clone(),
             app,
             formatted_path,
+            user,
+            socket_addr,
+            serialized_headers,
             jwt_secret_bytes.clone(),
             ws_senders.clone(),
             send_to_loop.clone(),
@@ -485,6 +660,171 @@ async fn ws_handler(
     }))
 }
 
+/// open a web terminal session for an admin who gives their password again:
+/// being logged in is not enough, as every app the node serves is too
+async fn web_terminal_session_handler(
+    info: LoginInfo,
+    headers: warp::http::HeaderMap,
+    our: Arc<String>,
+    encoded_keyfile: Arc<Vec<u8>>,
+    users: Arc<Users>,
+    terminal_sessions: Arc<TerminalSessions>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !same_origin(&serialize_headers(&headers)) {
+        return Err(warp::reject::not_found());
+    }
+    // the terminal can do anything the node can: admins only
+    let admin = match info.username.as_deref() {
+        Some(username) if is_user_login(&info, &our) => users
+            .verify(username, &info.password_hash)
+            .is_some_and(|user| user.role == UserRole::Admin),
+        _ => keygen::decode_keyfile(&encoded_keyfile, &info.password_hash).is_ok(),
+    };
+    if !admin {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&"Incorrect username or password"),
+            StatusCode::UNAUTHORIZED,
+        )
+        .into_response());
+    }
+    let (session, csrf) = terminal_sessions.open();
+    let mut response = warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "csrf": csrf })),
+        StatusCode::OK,
+    )
+    .into_response();
+    match HeaderValue::from_str(&TerminalSessions::set_cookie(&our, &session)) {
+        Ok(v) => {
+            response.headers_mut().append("set-cookie", v);
+            Ok(response)
+        }
+        Err(_) => Err(warp::reject::not_found()),
+    }
+}
+
+/// accept a web terminal connection with a session and its CSRF token
+async fn web_terminal_handler(
+    ws_connection: Ws,
+    query: HashMap<String, String>,
+    headers: warp::http::HeaderMap,
+    our: Arc<String>,
+    terminal_sessions: Arc<TerminalSessions>,
+    web_terminal: WebTerminalSender,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let serialized_headers = serialize_headers(&headers);
+    if !same_origin(&serialized_headers) {
+        return Err(warp::reject::not_found());
+    }
+    let (Some(cookie), Some(csrf)) = (serialized_headers.get("cookie"), query.get("csrf")) else {
+        return Err(warp::reject::not_found());
+    };
+    if !terminal_sessions.check(&our, cookie, csrf) {
+        return Err(warp::reject::not_found());
+    }
+    Ok(ws_connection.on_upgrade(move |ws: WebSocket| maintain_web_terminal(ws, web_terminal)))
+}
+
+/// whether a request comes from a page served by the node itself: its
+/// `Origin`, if it has one, names the host it was sent to. browsers always
+/// set `Origin` on websocket upgrades; other clients don't need to.
+fn same_origin(headers: &HashMap<String, String>) -> bool {
+    let Some(origin) = headers.get("origin") else {
+        return true;
+    };
+    let Some(host) = headers.get("host") else {
+        return false;
+    };
+    let origin_host = origin
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(origin)
+        .trim_end_matches('/');
+    origin_host.eq_ignore_ascii_case(host)
+}
+
+/// stream printouts to a web terminal, and pass on what it asks of the terminal
+async fn maintain_web_terminal(ws: WebSocket, web_terminal: WebTerminalSender) {
+    let (mut write_stream, mut read_stream) = ws.split();
+    let (printout_tx, mut printout_rx) = tokio::sync::mpsc::channel(WEB_TERMINAL_PRINTOUT_CAPACITY);
+    if web_terminal
+        .send(WebTerminalRequest::Attach(printout_tx))
+        .await
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        let output = tokio::select! {
+            read = read_stream.next() => {
+                let Some(Ok(msg)) = read else {
+                    break;
+                };
+                if msg.is_close() {
+                    break;
+                }
+                let Ok(input) = serde_json::from_slice::<WebTerminalInput>(msg.as_bytes()) else {
+                    continue;
+                };
+                match handle_web_terminal_input(input, &web_terminal).await {
+                    Some(output) => output,
+                    None => continue,
+                }
+            }
+            Some(printout) = printout_rx.recv() => WebTerminalOutput::Printout(printout),
+        };
+        let message = warp::ws::Message::text(serde_json::to_string(&output).unwrap());
+        if write_stream.send(message).await.is_err() {
+            break;
+        }
+    }
+    let stream = write_stream.reunite(read_stream).unwrap();
+    let _ = stream.close().await;
+}
+
+async fn handle_web_terminal_input(
+    input: WebTerminalInput,
+    web_terminal: &WebTerminalSender,
+) -> Option<WebTerminalOutput> {
+    match input {
+        WebTerminalInput::Command(command) => {
+            let _ = web_terminal
+                .send(WebTerminalRequest::Command(command))
+                .await;
+            None
+        }
+        WebTerminalInput::History => {
+            let (result, history) = tokio::sync::oneshot::channel();
+            web_terminal
+                .send(WebTerminalRequest::History(result))
+                .await
+                .ok()?;
+            Some(WebTerminalOutput::History(history.await.ok()?))
+        }
+        WebTerminalInput::Search { query, depth } => {
+            let (result, found) = tokio::sync::oneshot::channel();
+            web_terminal
+                .send(WebTerminalRequest::Search {
+                    query,
+                    depth,
+                    result,
+                })
+                .await
+                .ok()?;
+            Some(WebTerminalOutput::Search(found.await.ok()?))
+        }
+    }
+}
+
+/// what the access log of a request needs that only handling it finds out
+#[derive(Default)]
+struct AccessContext {
+    app: Option<ProcessId>,
+    bound_path: Option<String>,
+    user: Option<String>,
+    denied: bool,
+}
+
+/// handle an HTTP request, then log it if its path has an access log
 async fn http_handler(
     method: warp::http::Method,
     socket_addr: Option<SocketAddr>,
@@ -492,21 +832,105 @@ async fn http_handler(
     path: warp::path::FullPath,
     query_params: HashMap<String, String>,
     headers: warp::http::HeaderMap,
-    body: warp::hyper::body::Bytes,
+    body: impl futures::Stream<Item = Result<impl Buf, warp::Error>> + Send + 'static,
     our: Arc<String>,
     http_response_senders: HttpResponseSenders,
     path_bindings: PathBindings,
     jwt_secret_bytes: Arc<Vec<u8>>,
+    users: Arc<Users>,
     send_to_loop: MessageSender,
     print_tx: PrintSender,
     login_html: Arc<String>,
+    (access_logs, streams): (Arc<AccessLogs>, Arc<Streams>),
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    let start = std::time::Instant::now();
+    let timestamp = crate::clock::now();
+    let request_method = method.to_string();
+    let request_path = path.as_str().to_string();
+    let mut access = AccessContext::default();
+    // taken whole by handle_http_request unless the path is bound with `stream`
+    let body: BodyStream =
+        Box::pin(body.map(|frame| frame.map(|mut buf| buf.copy_to_bytes(buf.remaining()))));
+    let response = handle_http_request(
+        &mut access,
+        method,
+        socket_addr,
+        host,
+        path,
+        query_params,
+        headers,
+        body,
+        our,
+        http_response_senders,
+        path_bindings,
+        jwt_secret_bytes,
+        users,
+        send_to_loop,
+        print_tx.clone(),
+        login_html,
+        streams,
+    )
+    .await;
+    let (Some(app), Some(bound_path)) = (access.app, access.bound_path) else {
+        return response;
+    };
+    if !access_logs.is_logged(&bound_path) {
+        return response;
+    }
+    // a request rejected rather than answered is logged as warp answers it
+    let (status, bytes) = match &response {
+        Ok(response) => (
+            response.status().as_u16(),
+            response.body().size_hint().exact().unwrap_or(0),
+        ),
+        Err(_) => (StatusCode::NOT_FOUND.as_u16(), 0),
+    };
+    let entry = AccessLogEntry {
+        timestamp,
+        path: request_path,
+        bound_path,
+        method: request_method,
+        status,
+        latency_ms: start.elapsed().as_millis() as u64,
+        bytes,
+        client: socket_addr.map(|addr| addr.to_string()),
+        user: access.user,
+        denied: access.denied,
+    };
+    Printout::log(app, LogLevel::Debug, serde_json::to_string(&entry).unwrap())
+        .send(&print_tx)
+        .await;
+    access_logs.record(entry);
+    response
+}
+
+async fn handle_http_request(
+    access: &mut AccessContext,
+    method: warp::http::Method,
+    socket_addr: Option<SocketAddr>,
+    host: Option<warp::host::Authority>,
+    path: warp::path::FullPath,
+    query_params: HashMap<String, String>,
+    headers: warp::http::HeaderMap,
+    body: BodyStream,
+    our: Arc<String>,
+    http_response_senders: HttpResponseSenders,
+    path_bindings: PathBindings,
+    jwt_secret_bytes: Arc<Vec<u8>>,
+    users: Arc<Users>,
+    send_to_loop: MessageSender,
+    print_tx: PrintSender,
+    login_html: Arc<String>,
+    streams: Arc<Streams>,
+) -> Result<warp::reply::Response, warp::Rejection> {
     // trim trailing "/"
     let original_path = normalize_path(path.as_str());
     let _ = print_tx
         .send(Printout {
             verbosity: 2,
             content: format!("http_server: got request for path {original_path}"),
+            source: None,
+            level: None,
         })
         .await;
     let id: u64 = rand::random();
@@ -518,6 +942,8 @@ async fn http_handler(
             .send(Printout {
                 verbosity: 2,
                 content: format!("http_server: no route found for {original_path}"),
+                source: None,
+                level: None,
             })
             .await;
         return Ok(warp::reply::with_status(vec![], StatusCode::NOT_FOUND).into_response());
@@ -527,17 +953,25 @@ async fn http_handler(
     let Some(app) = &bound_path.app else {
         return Ok(warp::reply::with_status(vec![], StatusCode::NOT_FOUND).into_response());
     };
+    access.app = Some(app.clone());
+    access.bound_path = Some(bound_path.path.clone());
 
     let host = host.unwrap_or(warp::host::Authority::from_static("localhost"));
+    let cookie = serialized_headers
+        .get("cookie")
+        .map(String::as_str)
+        .unwrap_or_default();
 
-    if bound_path.authenticated {
-        if let Some(ref subdomain) = bound_path.secure_subdomain {
+    let user = if bound_path.authenticated {
+        let user = if let Some(ref subdomain) = bound_path.secure_subdomain {
             let _ = print_tx
                 .send(Printout {
                     verbosity: 2,
                     content: format!(
                         "http_server: request for {original_path} bound by subdomain {subdomain}"
                     ),
+                    source: None,
+                    level: None,
                 })
                 .await;
             let request_subdomain = host.host().split('.').next().unwrap_or("");
@@ -579,33 +1013,30 @@ async fn http_handler(
                     .body(vec![])
                     .into_response());
             }
-            if !auth_cookie_valid(
-                &our,
-                Some(&app),
-                serialized_headers.get("cookie").unwrap_or(&"".to_string()),
-                &jwt_secret_bytes,
-            ) {
-                // redirect to login page so they can get an auth token
-                return Ok(warp::http::Response::builder()
-                    .status(StatusCode::OK)
-                    .body(login_html.to_string())
-                    .into_response());
-            }
+            auth_cookie_user(&our, Some(&app), cookie, &jwt_secret_bytes, &users)
         } else {
-            if !auth_cookie_valid(
-                &our,
-                None,
-                serialized_headers.get("cookie").unwrap_or(&"".to_string()),
-                &jwt_secret_bytes,
-            ) {
+            auth_cookie_user(&our, None, cookie, &jwt_secret_bytes, &users)
+        };
+        access.user = user.as_ref().map(|user| user.name.clone());
+        match user {
+            Some(user) if user.role >= bound_path.min_role => Some(user),
+            Some(_) => {
+                return Ok(warp::reply::with_status(vec![], StatusCode::FORBIDDEN).into_response())
+            }
+            None => {
                 // redirect to login page so they can get an auth token
+                access.denied = true;
                 return Ok(warp::http::Response::builder()
                     .status(StatusCode::OK)
                     .body(login_html.to_string())
                     .into_response());
             }
         }
-    }
+    } else {
+        let user = auth_cookie_user(&our, None, cookie, &jwt_secret_bytes, &users);
+        access.user = user.as_ref().map(|user| user.name.clone());
+        user
+    };
 
     let is_local = socket_addr
         .map(|addr| addr.ip().is_loopback())
@@ -632,11 +1063,33 @@ async fn http_handler(
         }
     }
 
+    let app = app.clone();
+    let bound_path_string = bound_path.path.clone();
+    let stream_id = bound_path.stream.then_some(id);
+    let url_params: HashMap<String, String> = route
+        .params()
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    // unlock to avoid deadlock with .write()s, and so that no bind
+    // waits on the body coming in
+    drop(path_bindings);
+
+    let (body, stream_body) = match stream_id {
+        Some(_) => (vec![], Some(body)),
+        None => match collect_body(body).await {
+            Ok(body) => (body, None),
+            Err(_) => {
+                return Ok(warp::reply::with_status(vec![], StatusCode::BAD_REQUEST).into_response())
+            }
+        },
+    };
+
     // RPC functionality: if path is /rpc:distro:sys/message,
     // we extract message from base64 encoded bytes in data
     // and send it to the correct app.
-    let (message, is_fire_and_forget) = if app == &"rpc:distro:sys" {
-        match handle_rpc_message(our, id, body, print_tx).await {
+    let (message, is_fire_and_forget) = if app == "rpc:distro:sys" {
+        match handle_rpc_message(our.clone(), id, body.into(), print_tx).await {
             Ok((message, is_fire_and_forget)) => (message, is_fire_and_forget),
             Err(e) => {
                 return Ok(warp::reply::with_status(vec![], e).into_response());
@@ -644,11 +1097,6 @@ async fn http_handler(
         }
     } else {
         // otherwise, make a message to the correct app
-        let url_params: HashMap<String, String> = route
-            .params()
-            .into_iter()
-            .map(|(key, value)| (key.to_string(), value.to_string()))
-            .collect();
         (
             KernelMessage {
                 id,
@@ -672,10 +1120,12 @@ async fn http_handler(
                             host.host(),
                             original_path
                         ),
-                        bound_path: bound_path.path.clone(),
+                        bound_path: bound_path_string,
                         headers: serialized_headers,
                         url_params,
                         query_params,
+                        user,
+                        stream_id,
                     }))
                     .unwrap(),
                     metadata: None,
@@ -683,16 +1133,15 @@ async fn http_handler(
                 }),
                 lazy_load_blob: Some(LazyLoadBlob {
                     mime: None,
-                    bytes: body.to_vec(),
+                    bytes: body,
                 }),
+                blob_handle: None,
+                sent_at: Some(std::time::Instant::now()),
             },
             false,
         )
     };
 
-    // unlock to avoid deadlock with .write()s
-    drop(path_bindings);
-
     if is_fire_and_forget {
         match send_to_loop.send(message).await {
             Ok(_) => {}
@@ -718,6 +1167,25 @@ async fn http_handler(
         }
     }
 
+    if let Some(body) = stream_body {
+        let our = our.clone();
+        let app = app.clone();
+        let streams = streams.clone();
+        let send_to_loop = send_to_loop.clone();
+        tokio::spawn(async move {
+            streams
+                .send_body(
+                    &our,
+                    &app,
+                    id,
+                    body,
+                    &send_to_loop,
+                    HTTP_SELF_IMPOSED_TIMEOUT,
+                )
+                .await;
+        });
+    }
+
     let timeout_duration = tokio::time::Duration::from_secs(HTTP_SELF_IMPOSED_TIMEOUT);
     let result = tokio::time::timeout(timeout_duration, response_receiver).await;
 
@@ -735,11 +1203,16 @@ async fn http_handler(
         }
     };
 
-    let reply = warp::reply::with_status(
-        body,
-        StatusCode::from_u16(http_response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
-    );
-    let mut response = reply.into_response();
+    let status =
+        StatusCode::from_u16(http_response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let mut response = if http_response.stream && stream_id.is_some() {
+        warp::http::Response::builder()
+            .status(status)
+            .body(streams.open_response(id, app, body, HTTP_SELF_IMPOSED_TIMEOUT))
+            .unwrap()
+    } else {
+        warp::reply::with_status(body, status).into_response()
+    };
 
     // Merge the deserialized headers into the existing headers
     let existing_headers = response.headers_mut();
@@ -762,6 +1235,15 @@ async fn http_handler(
     Ok(response)
 }
 
+/// the whole of a request body, for paths not bound with `stream`
+async fn collect_body(mut body: BodyStream) -> Result<Vec<u8>, warp::Error> {
+    let mut bytes = vec![];
+    while let Some(chunk) = body.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+    Ok(bytes)
+}
+
 async fn handle_rpc_message(
     our: Arc<String>,
     id: u64,
@@ -780,6 +1262,8 @@ async fn handle_rpc_message(
         .send(Printout {
             verbosity: 2,
             content: format!("http_server: passing on RPC message to {target_process}"),
+            source: None,
+            level: None,
         })
         .await;
 
@@ -822,6 +1306,8 @@ async fn handle_rpc_message(
                 capabilities: vec![],
             }),
             lazy_load_blob: blob,
+            blob_handle: None,
+            sent_at: Some(std::time::Instant::now()),
         },
         rpc_message.expects_response.is_none(),
     ))
@@ -860,6 +1346,8 @@ fn make_websocket_message(
             mime: None,
             bytes: msg,
         }),
+        blob_handle: None,
+        sent_at: Some(std::time::Instant::now()),
     })
 }
 
@@ -948,6 +1436,8 @@ fn make_ext_websocket_message(
         rsvp: None,
         message,
         lazy_load_blob: blob,
+        blob_handle: None,
+        sent_at: Some(std::time::Instant::now()),
     })
 }
 
@@ -956,6 +1446,9 @@ async fn maintain_websocket(
     our: Arc<String>,
     app: ProcessId,
     path: String,
+    user: Option<User>,
+    socket_addr: Option<SocketAddr>,
+    headers: HashMap<String, String>,
     _jwt_secret_bytes: Arc<Vec<u8>>, // TODO use for encrypted channels
     ws_senders: WebSocketSenders,
     send_to_loop: MessageSender,
@@ -966,12 +1459,33 @@ async fn maintain_websocket(
 
     let channel_id: u32 = rand::random();
     let (ws_sender, mut ws_receiver) = tokio::sync::mpsc::channel(100);
-    ws_senders.insert(channel_id, (app.clone(), ws_sender));
+    let source_socket_addr = socket_addr.map(|addr| addr.to_string());
+    ws_senders.insert(
+        channel_id,
+        WsConnection {
+            app: app.clone(),
+            sender: ws_sender,
+            extension,
+            info: WsConnectionInfo {
+                channel_id,
+                path: path.clone(),
+                source_socket_addr: source_socket_addr.clone(),
+                user: user.clone(),
+                opened: crate::clock::now(),
+                topics: vec![],
+                messages_received: 0,
+                messages_sent: 0,
+                limits: WsConnectionLimits::default(),
+            },
+        },
+    );
 
     let _ = print_tx
         .send(Printout {
             verbosity: 2,
             content: format!("http_server: new websocket connection to {app} with id {channel_id}"),
+            source: None,
+            level: None,
         })
         .await;
 
@@ -990,12 +1504,20 @@ async fn maintain_websocket(
             message: Message::Request(Request {
                 inherit: false,
                 expects_response: None,
-                body: serde_json::to_vec(&HttpServerRequest::WebSocketOpen { path, channel_id })
-                    .unwrap(),
+                body: serde_json::to_vec(&HttpServerRequest::WebSocketOpen {
+                    path,
+                    channel_id,
+                    user,
+                    source_socket_addr,
+                    headers,
+                })
+                .unwrap(),
                 metadata: None,
                 capabilities: vec![],
             }),
             lazy_load_blob: None,
+            blob_handle: None,
+            sent_at: Some(std::time::Instant::now()),
         })
         .await;
 
@@ -1004,12 +1526,22 @@ async fn maintain_websocket(
     } else {
         make_websocket_message
     };
+    let mut window = RateWindow {
+        started: std::time::Instant::now(),
+        count: 0,
+    };
 
     loop {
         tokio::select! {
             read = read_stream.next() => {
                 match read {
                     Some(Ok(msg)) => {
+                        let len = msg.as_bytes().len();
+                        if let Some(close) = received(&ws_senders, channel_id, len, &mut window) {
+                            let _ = write_stream.send(close).await;
+                            websocket_close(channel_id, app.clone(), &ws_senders, &send_to_loop).await;
+                            break;
+                        }
                         let ws_msg_type = if msg.is_text() {
                             WsMessageType::Text
                         } else if msg.is_binary() {
@@ -1053,12 +1585,49 @@ async fn maintain_websocket(
         .send(Printout {
             verbosity: 2,
             content: format!("http_server: websocket connection {channel_id} closed"),
+            source: None,
+            level: None,
         })
         .await;
     let stream = write_stream.reunite(read_stream).unwrap();
     let _ = stream.close().await;
 }
 
+/// the messages received over a connection in the current minute
+struct RateWindow {
+    started: std::time::Instant,
+    count: u32,
+}
+
+/// count a message received over `channel_id`, returning the close frame to
+/// send if it breaks the limits set on the connection
+fn received(
+    ws_senders: &WebSocketSenders,
+    channel_id: u32,
+    len: usize,
+    window: &mut RateWindow,
+) -> Option<warp::ws::Message> {
+    let mut connection = ws_senders.get_mut(&channel_id)?;
+    connection.info.messages_received += 1;
+    let limits = connection.info.limits;
+    drop(connection);
+    if limits.max_message_bytes.is_some_and(|max| len as u64 > max) {
+        return Some(warp::ws::Message::close_with(1009u16, "message too big"));
+    }
+    if window.started.elapsed() >= std::time::Duration::from_secs(60) {
+        window.started = std::time::Instant::now();
+        window.count = 0;
+    }
+    window.count += 1;
+    if limits
+        .max_messages_per_minute
+        .is_some_and(|max| window.count > max)
+    {
+        return Some(warp::ws::Message::close_with(1008u16, "too many messages"));
+    }
+    None
+}
+
 async fn websocket_close(
     channel_id: u32,
     process: ProcessId,
@@ -1093,6 +1662,8 @@ async fn websocket_close(
                 })
                 .unwrap(),
             }),
+            blob_handle: None,
+            sent_at: Some(std::time::Instant::now()),
         })
         .await;
 }
@@ -1102,15 +1673,24 @@ async fn handle_app_message(
     http_response_senders: HttpResponseSenders,
     path_bindings: PathBindings,
     ws_path_bindings: WsPathBindings,
+    bindings_by_process: BindingsByProcess,
+    transactions: &mut BindingsTransactions,
     ws_senders: WebSocketSenders,
+    users: &Users,
+    access_logs: &AccessLogs,
+    streams: &Arc<Streams>,
     send_to_loop: MessageSender,
     print_tx: PrintSender,
+    send_to_caps_oracle: &CapMessageSender,
 ) {
     // when we get a Response, try to match it to an outstanding HTTP
     // request and send it there.
     // when we get a Request, parse it into an HttpServerAction and perform it.
     match km.message {
         Message::Response((response, _context)) => {
+            if streams.ack(km.id) {
+                return;
+            }
             let Some((_id, (path, sender))) = http_response_senders.remove(&km.id) else {
                 return;
             };
@@ -1128,6 +1708,7 @@ async fn handle_app_message(
                     HttpResponse {
                         status: 200,
                         headers: default_headers,
+                        stream: false,
                     },
                     serde_json::to_vec(&RpcResponseBody {
                         body: response.body,
@@ -1144,6 +1725,7 @@ async fn handle_app_message(
                     HttpResponse {
                         status: response.status,
                         headers: response.headers,
+                        stream: response.stream,
                     },
                     match km.lazy_load_blob {
                         None => vec![],
@@ -1179,8 +1761,15 @@ async fn handle_app_message(
                     authenticated,
                     local_only,
                     cache,
+                    min_role,
+                    stream,
                 } => {
                     let path = format_path_with_process(&km.source.process, &path);
+                    bindings_by_process
+                        .entry(km.source.process.clone())
+                        .or_default()
+                        .http
+                        .insert(path.clone());
                     let mut path_bindings = path_bindings.write().await;
                     let _ = print_tx
                         .send(Printout {
@@ -1195,6 +1784,8 @@ async fn handle_app_message(
                                 if local_only { "local only" } else { "open" },
                                 if cache { "cached" } else { "dynamic" },
                             ),
+                            source: None,
+                            level: None,
                         })
                         .await;
                     if !cache {
@@ -1206,8 +1797,10 @@ async fn handle_app_message(
                                 path: path.clone(),
                                 secure_subdomain: None,
                                 authenticated,
+                                min_role,
                                 local_only,
                                 static_content: None,
+                                stream,
                             },
                         );
                     } else {
@@ -1229,14 +1822,26 @@ async fn handle_app_message(
                                 path: path.clone(),
                                 secure_subdomain: None,
                                 authenticated,
+                                min_role,
                                 local_only,
                                 static_content: Some(blob),
+                                stream: false,
                             },
                         );
                     }
                 }
-                HttpServerAction::SecureBind { path, cache } => {
+                HttpServerAction::SecureBind {
+                    path,
+                    cache,
+                    min_role,
+                    stream,
+                } => {
                     let path = format_path_with_process(&km.source.process, &path);
+                    bindings_by_process
+                        .entry(km.source.process.clone())
+                        .or_default()
+                        .http
+                        .insert(path.clone());
                     let subdomain = generate_secure_subdomain(&km.source.process);
                     let mut path_bindings = path_bindings.write().await;
                     let _ = print_tx
@@ -1246,6 +1851,8 @@ async fn handle_app_message(
                                 "http: binding subdomain {subdomain} with path {path}, {}",
                                 if cache { "cached" } else { "dynamic" },
                             ),
+                            source: None,
+                            level: None,
                         })
                         .await;
                     if !cache {
@@ -1256,8 +1863,10 @@ async fn handle_app_message(
                                 path: path.clone(),
                                 secure_subdomain: Some(subdomain),
                                 authenticated: true,
+                                min_role,
                                 local_only: false,
                                 static_content: None,
+                                stream,
                             },
                         );
                     } else {
@@ -1279,14 +1888,20 @@ async fn handle_app_message(
                                 path: path.clone(),
                                 secure_subdomain: Some(subdomain),
                                 authenticated: true,
+                                min_role,
                                 local_only: false,
                                 static_content: Some(blob),
+                                stream: false,
                             },
                         );
                     }
                 }
                 HttpServerAction::Unbind { path } => {
                     let path = format_path_with_process(&km.source.process, &path);
+                    if let Some(mut bindings) = bindings_by_process.get_mut(&km.source.process) {
+                        bindings.http.remove(&path);
+                    }
+                    access_logs.set(&path, None);
                     let mut path_bindings = path_bindings.write().await;
                     path_bindings.add(
                         &path,
@@ -1295,18 +1910,70 @@ async fn handle_app_message(
                             path: path.clone(),
                             secure_subdomain: None,
                             authenticated: false,
+                            min_role: UserRole::default(),
                             local_only: false,
                             static_content: None,
+                            stream: false,
                         },
                     );
                 }
+                HttpServerAction::SetAccessLog { ref path, .. }
+                | HttpServerAction::GetAccessLog { ref path } => {
+                    let path = format_path_with_process(&km.source.process, path);
+                    let target = km.rsvp.unwrap_or(km.source.clone());
+                    if !bindings_by_process
+                        .get(&km.source.process)
+                        .is_some_and(|bindings| bindings.http.contains(&path))
+                    {
+                        send_action_response(
+                            km.id,
+                            target,
+                            &send_to_loop,
+                            Err(HttpServerError::PathBindError {
+                                error: format!("{path} is not bound by {}", km.source.process),
+                            }),
+                        )
+                        .await;
+                        return;
+                    }
+                    let blob = match message {
+                        HttpServerAction::SetAccessLog { settings, .. } => {
+                            let _ = print_tx
+                                .send(Printout {
+                                    verbosity: 2,
+                                    content: format!(
+                                        "http: access log of {path} {}",
+                                        if settings.is_some() { "on" } else { "off" }
+                                    ),
+                                    source: None,
+                                    level: None,
+                                })
+                                .await;
+                            access_logs.set(&path, settings);
+                            None
+                        }
+                        _ => Some(LazyLoadBlob {
+                            mime: Some("application/json".to_string()),
+                            bytes: serde_json::to_vec(&access_logs.get(&path)).unwrap(),
+                        }),
+                    };
+                    send_action_response_with_blob(km.id, target, &send_to_loop, Ok(()), blob)
+                        .await;
+                    return;
+                }
                 HttpServerAction::WebSocketBind {
                     path,
                     authenticated,
                     encrypted,
                     extension,
+                    min_role,
                 } => {
                     let path = format_path_with_process(&km.source.process, &path);
+                    bindings_by_process
+                        .entry(km.source.process.clone())
+                        .or_default()
+                        .ws
+                        .insert(path.clone());
                     let mut ws_path_bindings = ws_path_bindings.write().await;
                     ws_path_bindings.add(
                         &path,
@@ -1314,6 +1981,7 @@ async fn handle_app_message(
                             app: Some(km.source.process.clone()),
                             secure_subdomain: None,
                             authenticated,
+                            min_role,
                             encrypted,
                             extension,
                         },
@@ -1323,8 +1991,14 @@ async fn handle_app_message(
                     path,
                     encrypted,
                     extension,
+                    min_role,
                 } => {
                     let path = format_path_with_process(&km.source.process, &path);
+                    bindings_by_process
+                        .entry(km.source.process.clone())
+                        .or_default()
+                        .ws
+                        .insert(path.clone());
                     let subdomain = generate_secure_subdomain(&km.source.process);
                     let mut ws_path_bindings = ws_path_bindings.write().await;
                     ws_path_bindings.add(
@@ -1333,6 +2007,7 @@ async fn handle_app_message(
                             app: Some(km.source.process.clone()),
                             secure_subdomain: Some(subdomain),
                             authenticated: true,
+                            min_role,
                             encrypted,
                             extension,
                         },
@@ -1340,6 +2015,9 @@ async fn handle_app_message(
                 }
                 HttpServerAction::WebSocketUnbind { mut path } => {
                     let path = format_path_with_process(&km.source.process, &path);
+                    if let Some(mut bindings) = bindings_by_process.get_mut(&km.source.process) {
+                        bindings.ws.remove(&path);
+                    }
                     let mut ws_path_bindings = ws_path_bindings.write().await;
                     ws_path_bindings.add(
                         &path,
@@ -1347,11 +2025,274 @@ async fn handle_app_message(
                             app: None,
                             secure_subdomain: None,
                             authenticated: false,
+                            min_role: UserRole::default(),
                             encrypted: false,
                             extension: false,
                         },
                     );
                 }
+                HttpServerAction::UnbindAll { process } => {
+                    if !has_root_cap(&km, send_to_caps_oracle).await {
+                        send_action_response(
+                            km.id,
+                            km.source,
+                            &send_to_loop,
+                            Err(HttpServerError::NoCap {
+                                error: "UnbindAll requires root".to_string(),
+                            }),
+                        )
+                        .await;
+                        return;
+                    }
+                    if let Some(bindings) = bindings_by_process.get(&process) {
+                        for path in &bindings.http {
+                            access_logs.set(path, None);
+                        }
+                    }
+                    unbind_all(
+                        &process,
+                        &path_bindings,
+                        &ws_path_bindings,
+                        &bindings_by_process,
+                        &ws_senders,
+                    )
+                    .await;
+                    let _ = print_tx
+                        .send(Printout {
+                            verbosity: 2,
+                            content: format!("http: unbound all paths of {process}"),
+                            source: None,
+                            level: None,
+                        })
+                        .await;
+                }
+                HttpServerAction::BindingsTransaction {
+                    id,
+                    package_id,
+                    step,
+                } => {
+                    if !has_root_cap(&km, send_to_caps_oracle).await {
+                        send_action_response(
+                            km.id,
+                            km.source,
+                            &send_to_loop,
+                            Err(HttpServerError::NoCap {
+                                error: "BindingsTransaction requires root".to_string(),
+                            }),
+                        )
+                        .await;
+                        return;
+                    }
+                    let in_package = |process: &ProcessId| {
+                        PackageId::new(process.package(), process.publisher()) == package_id
+                    };
+                    match step {
+                        TransactionStep::Prepare => {
+                            let path_bindings = path_bindings.read().await;
+                            let ws_path_bindings = ws_path_bindings.read().await;
+                            let mut prepared = HashMap::new();
+                            for entry in bindings_by_process.iter() {
+                                let process = entry.key();
+                                if !in_package(process) {
+                                    continue;
+                                }
+                                let bound_by =
+                                    |app: &Option<ProcessId>| app.as_ref() == Some(process);
+                                let http = entry
+                                    .http
+                                    .iter()
+                                    .filter_map(|path| {
+                                        let bound =
+                                            (*path_bindings.recognize(path).ok()?.handler())
+                                                .clone();
+                                        bound_by(&bound.app).then(|| (path.clone(), bound))
+                                    })
+                                    .collect();
+                                let ws = entry
+                                    .ws
+                                    .iter()
+                                    .filter_map(|path| {
+                                        let bound =
+                                            (*ws_path_bindings.recognize(path).ok()?.handler())
+                                                .clone();
+                                        bound_by(&bound.app).then(|| (path.clone(), bound))
+                                    })
+                                    .collect();
+                                prepared.insert(process.clone(), PreparedBindings { http, ws });
+                            }
+                            transactions.insert(id, prepared);
+                        }
+                        TransactionStep::Commit => {
+                            transactions.remove(&id);
+                        }
+                        TransactionStep::Abort => {
+                            if let Some(prepared) = transactions.remove(&id) {
+                                let bound: Vec<ProcessId> = bindings_by_process
+                                    .iter()
+                                    .map(|entry| entry.key().clone())
+                                    .filter(|process| in_package(process))
+                                    .collect();
+                                for process in &bound {
+                                    unbind_all(
+                                        process,
+                                        &path_bindings,
+                                        &ws_path_bindings,
+                                        &bindings_by_process,
+                                        &ws_senders,
+                                    )
+                                    .await;
+                                }
+                                let mut path_bindings = path_bindings.write().await;
+                                let mut ws_path_bindings = ws_path_bindings.write().await;
+                                for (process, bindings) in prepared {
+                                    let mut by_process =
+                                        bindings_by_process.entry(process).or_default();
+                                    for (path, bound) in bindings.http {
+                                        by_process.http.insert(path.clone());
+                                        path_bindings.add(&path, bound);
+                                    }
+                                    for (path, bound) in bindings.ws {
+                                        by_process.ws.insert(path.clone());
+                                        ws_path_bindings.add(&path, bound);
+                                    }
+                                }
+                                let _ = print_tx
+                                    .send(Printout {
+                                        verbosity: 2,
+                                        content: format!(
+                                            "http: restored the paths bound by {package_id}"
+                                        ),
+                                        source: None,
+                                        level: None,
+                                    })
+                                    .await;
+                            }
+                        }
+                    }
+                }
+                HttpServerAction::ListBindings { package_id } => {
+                    if !has_root_cap(&km, send_to_caps_oracle).await {
+                        send_action_response(
+                            km.id,
+                            km.source,
+                            &send_to_loop,
+                            Err(HttpServerError::NoCap {
+                                error: "ListBindings requires root".to_string(),
+                            }),
+                        )
+                        .await;
+                        return;
+                    }
+                    let mut bindings: Vec<BindingInfo> = vec![];
+                    {
+                        let path_bindings = path_bindings.read().await;
+                        let ws_path_bindings = ws_path_bindings.read().await;
+                        for entry in bindings_by_process.iter() {
+                            let process = entry.key();
+                            if PackageId::new(process.package(), process.publisher()) != package_id
+                            {
+                                continue;
+                            }
+                            for path in &entry.http {
+                                let Ok(bound) = path_bindings.recognize(path) else {
+                                    continue;
+                                };
+                                let bound = bound.handler();
+                                if bound.app.as_ref() != Some(process) {
+                                    continue;
+                                }
+                                bindings.push(BindingInfo {
+                                    process: process.clone(),
+                                    path: path.clone(),
+                                    websocket: false,
+                                    secure_subdomain: bound.secure_subdomain.clone(),
+                                    authenticated: bound.authenticated,
+                                    min_role: bound.min_role,
+                                    local_only: bound.local_only,
+                                    cached: bound.static_content.is_some(),
+                                });
+                            }
+                            for path in &entry.ws {
+                                let Ok(bound) = ws_path_bindings.recognize(path) else {
+                                    continue;
+                                };
+                                let bound = bound.handler();
+                                if bound.app.as_ref() != Some(process) {
+                                    continue;
+                                }
+                                bindings.push(BindingInfo {
+                                    process: process.clone(),
+                                    path: path.clone(),
+                                    websocket: true,
+                                    secure_subdomain: bound.secure_subdomain.clone(),
+                                    authenticated: bound.authenticated,
+                                    min_role: bound.min_role,
+                                    local_only: false,
+                                    cached: false,
+                                });
+                            }
+                        }
+                    }
+                    bindings.sort_by(|a, b| a.path.cmp(&b.path));
+                    let target = km.rsvp.unwrap_or(km.source);
+                    send_action_response_with_blob(
+                        km.id,
+                        target,
+                        &send_to_loop,
+                        Ok(()),
+                        Some(LazyLoadBlob {
+                            mime: Some("application/json".to_string()),
+                            bytes: serde_json::to_vec(&bindings).unwrap(),
+                        }),
+                    )
+                    .await;
+                    return;
+                }
+                HttpServerAction::AddUser { .. }
+                | HttpServerAction::RemoveUser { .. }
+                | HttpServerAction::ListUsers => {
+                    if !has_root_cap(&km, send_to_caps_oracle).await {
+                        send_action_response(
+                            km.id,
+                            km.source,
+                            &send_to_loop,
+                            Err(HttpServerError::NoCap {
+                                error: "managing users requires root".to_string(),
+                            }),
+                        )
+                        .await;
+                        return;
+                    }
+                    let target = km.rsvp.unwrap_or(km.source);
+                    let (result, blob) = match message {
+                        HttpServerAction::AddUser {
+                            name,
+                            role,
+                            password_hash,
+                        } => (
+                            users.add(&km.target.node, name, role, password_hash).await,
+                            None,
+                        ),
+                        HttpServerAction::RemoveUser { name } => (users.remove(&name).await, None),
+                        _ => {
+                            let mut all = vec![User {
+                                name: km.target.node.clone(),
+                                role: UserRole::Admin,
+                            }];
+                            all.extend(users.list());
+                            (
+                                Ok(()),
+                                Some(LazyLoadBlob {
+                                    mime: Some("application/json".to_string()),
+                                    bytes: serde_json::to_vec(&all).unwrap(),
+                                }),
+                            )
+                        }
+                    };
+                    send_action_response_with_blob(km.id, target, &send_to_loop, result, blob)
+                        .await;
+                    return;
+                }
                 HttpServerAction::WebSocketOpen { .. } => {
                     // we cannot receive these, only send them to processes
                     send_action_response(
@@ -1414,9 +2355,126 @@ async fn handle_app_message(
                     .await;
                     return;
                 }
+                HttpServerAction::WebSocketSubscribe { channel_id, .. }
+                | HttpServerAction::WebSocketUnsubscribe { channel_id, .. }
+                | HttpServerAction::WebSocketSetLimits { channel_id, .. } => {
+                    let Some(mut connection) = ws_senders
+                        .get_mut(&channel_id)
+                        .filter(|connection| connection.app == km.source.process)
+                    else {
+                        send_action_response(
+                            km.id,
+                            km.source,
+                            &send_to_loop,
+                            Err(HttpServerError::WebSocketPushError {
+                                error: "WebSocket channel not found".to_string(),
+                            }),
+                        )
+                        .await;
+                        return;
+                    };
+                    match message {
+                        HttpServerAction::WebSocketSubscribe { topic, .. } => {
+                            if !connection.info.topics.contains(&topic) {
+                                connection.info.topics.push(topic);
+                            }
+                        }
+                        HttpServerAction::WebSocketUnsubscribe { topic, .. } => {
+                            connection.info.topics.retain(|t| t != &topic);
+                        }
+                        HttpServerAction::WebSocketSetLimits { limits, .. } => {
+                            connection.info.limits = limits;
+                        }
+                        _ => {}
+                    }
+                }
+                HttpServerAction::WebSocketBroadcast {
+                    topic,
+                    message_type,
+                } => {
+                    let Some(blob) = km.lazy_load_blob else {
+                        send_action_response(
+                            km.id,
+                            km.source,
+                            &send_to_loop,
+                            Err(HttpServerError::NoBlob),
+                        )
+                        .await;
+                        return;
+                    };
+                    let ws_message = match message_type {
+                        WsMessageType::Text => warp::ws::Message::text(
+                            String::from_utf8_lossy(&blob.bytes).to_string(),
+                        ),
+                        WsMessageType::Binary => warp::ws::Message::binary(blob.bytes),
+                        _ => {
+                            send_action_response(
+                                km.id,
+                                km.source,
+                                &send_to_loop,
+                                Err(HttpServerError::WebSocketPushError {
+                                    error: "only Text and Binary messages can be broadcast"
+                                        .to_string(),
+                                }),
+                            )
+                            .await;
+                            return;
+                        }
+                    };
+                    for mut connection in ws_senders.iter_mut() {
+                        if connection.app != km.source.process
+                            || connection.extension
+                            || topic
+                                .as_ref()
+                                .is_some_and(|topic| !connection.info.topics.contains(topic))
+                        {
+                            continue;
+                        }
+                        // a client too far behind to take it misses this one,
+                        // rather than holding up the rest
+                        if connection.sender.try_send(ws_message.clone()).is_ok() {
+                            connection.info.messages_sent += 1;
+                        }
+                    }
+                }
+                HttpServerAction::ResponseChunk { stream_id, done } => {
+                    // the client may be slow to take it: don't hold up the server
+                    let streams = streams.clone();
+                    tokio::spawn(async move {
+                        let bytes = km.lazy_load_blob.map(|blob| blob.bytes).unwrap_or_default();
+                        let result = streams
+                            .response_chunk(stream_id, &km.source.process, bytes, done)
+                            .await;
+                        if km.rsvp.is_some() || expects_response.is_some() || result.is_err() {
+                            let target = km.rsvp.unwrap_or(km.source);
+                            send_action_response(km.id, target, &send_to_loop, result).await;
+                        }
+                    });
+                    return;
+                }
+                HttpServerAction::WebSocketList => {
+                    let connections: Vec<WsConnectionInfo> = ws_senders
+                        .iter()
+                        .filter(|connection| connection.app == km.source.process)
+                        .map(|connection| connection.info.clone())
+                        .collect();
+                    let target = km.rsvp.unwrap_or(km.source);
+                    send_action_response_with_blob(
+                        km.id,
+                        target,
+                        &send_to_loop,
+                        Ok(()),
+                        Some(LazyLoadBlob {
+                            mime: Some("application/json".to_string()),
+                            bytes: serde_json::to_vec(&connections).unwrap(),
+                        }),
+                    )
+                    .await;
+                    return;
+                }
                 HttpServerAction::WebSocketClose(channel_id) => {
                     if let Some(got) = ws_senders.get(&channel_id) {
-                        if got.value().0 != km.source.process {
+                        if got.app != km.source.process {
                             send_action_response(
                                 km.id,
                                 km.source,
@@ -1429,7 +2487,9 @@ async fn handle_app_message(
                             .await;
                             return;
                         }
-                        let _ = got.value().1.send(warp::ws::Message::close()).await;
+                        let sender = got.sender.clone();
+                        drop(got);
+                        let _ = sender.send(warp::ws::Message::close()).await;
                         ws_senders.remove(&channel_id);
                     }
                 }
@@ -1442,12 +2502,85 @@ async fn handle_app_message(
     }
 }
 
+/// whether the source of `km` holds the http_server root capability
+async fn has_root_cap(km: &KernelMessage, send_to_caps_oracle: &CapMessageSender) -> bool {
+    let (send_cap_bool, recv_cap_bool) = tokio::sync::oneshot::channel();
+    send_to_caps_oracle
+        .send(CapMessage::Has {
+            on: km.source.process.clone(),
+            cap: Capability::new(
+                (km.target.node.as_str(), HTTP_SERVER_PROCESS_ID.clone()),
+                "{\"root\":true}",
+            ),
+            responder: send_cap_bool,
+        })
+        .await
+        .expect("http_server: fatal: caps oracle died");
+    recv_cap_bool.await.unwrap_or(false)
+}
+
+/// unbind every HTTP and WebSocket path bound by `process`, and close its
+/// open WebSocket connections
+async fn unbind_all(
+    process: &ProcessId,
+    path_bindings: &PathBindings,
+    ws_path_bindings: &WsPathBindings,
+    bindings_by_process: &BindingsByProcess,
+    ws_senders: &WebSocketSenders,
+) {
+    if let Some((_, bindings)) = bindings_by_process.remove(process) {
+        let mut path_bindings = path_bindings.write().await;
+        for path in bindings.http {
+            path_bindings.add(
+                &path,
+                BoundPath {
+                    app: None,
+                    path: path.clone(),
+                    secure_subdomain: None,
+                    authenticated: false,
+                    min_role: UserRole::default(),
+                    local_only: false,
+                    static_content: None,
+                    stream: false,
+                },
+            );
+        }
+        let mut ws_path_bindings = ws_path_bindings.write().await;
+        for path in bindings.ws {
+            ws_path_bindings.add(
+                &path,
+                BoundWsPath {
+                    app: None,
+                    secure_subdomain: None,
+                    authenticated: false,
+                    min_role: UserRole::default(),
+                    encrypted: false,
+                    extension: false,
+                },
+            );
+        }
+    }
+    // dropping a connection's sender closes it
+    ws_senders.retain(|_, connection| &connection.app != process);
+}
+
 pub async fn send_action_response(
     id: u64,
     target: Address,
     send_to_loop: &MessageSender,
     result: Result<(), HttpServerError>,
 ) {
+    send_action_response_with_blob(id, target, send_to_loop, result, None).await;
+}
+
+async fn send_action_response_with_blob(
+    id: u64,
+    target: Address,
+    send_to_loop: &MessageSender,
+    result: Result<(), HttpServerError>,
+    lazy_load_blob: Option<LazyLoadBlob>,
+) {
+    let metadata = result.as_ref().err().and_then(ModuleError::metadata);
     let _ = send_to_loop
         .send(KernelMessage {
             id,
@@ -1461,12 +2594,14 @@ pub async fn send_action_response(
                 Response {
                     inherit: false,
                     body: serde_json::to_vec(&result).unwrap(),
-                    metadata: None,
+                    metadata,
                     capabilities: vec![],
                 },
                 None,
             )),
-            lazy_load_blob: None,
+            lazy_load_blob,
+            blob_handle: None,
+            sent_at: Some(std::time::Instant::now()),
         })
         .await;
 }
@@ -0,0 +1,290 @@
+//! streamed bodies for paths bound with `stream`. a request's body goes to
+//! the process as [`HttpServerRequest::BodyChunk`]s, each only once the last
+//! has been responded to, so a process that reads slowly slows the client
+//! down rather than having the body pile up in its queue. a streamed response
+//! body comes back as `HttpServerAction::ResponseChunk`s, each responded to
+//! once the client has room for it.
+//!
+//! the status and headers of a streamed response are sent to the client with
+//! its first chunk, before the rest of the body is known, so they can't be
+//! changed after. a process that fails partway through, or stops sending
+//! chunks, can only cut the body off: the client sees it end early.
+use crate::http::server_types::{HttpServerError, HttpServerRequest};
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use lib::types::core::{
+    Address, KernelMessage, LazyLoadBlob, Message, MessageSender, ProcessId, Request,
+    HTTP_SERVER_PROCESS_ID,
+};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use warp::hyper::body::Bytes;
+
+/// largest chunk of a request body sent to a process at once
+const CHUNK_SIZE: usize = 256 * 1024;
+/// response chunks the server holds for a client that hasn't taken them yet
+const RESPONSE_BUFFER: usize = 4;
+
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Bytes, warp::Error>> + Send>>;
+
+struct ResponseStream {
+    app: ProcessId,
+    sender: mpsc::Sender<Result<Bytes, std::io::Error>>,
+    last_chunk: Instant,
+}
+
+#[derive(Default)]
+pub struct Streams {
+    /// request body chunks sent to processes, by message ID, waiting for a response
+    acks: DashMap<u64, oneshot::Sender<()>>,
+    responses: DashMap<u64, ResponseStream>,
+}
+
+impl Streams {
+    /// take the response to a request body chunk, if it is one
+    pub fn ack(&self, id: u64) -> bool {
+        match self.acks.remove(&id) {
+            Some((_, ack)) => {
+                let _ = ack.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// send `body` to `app` in chunks, each once the last has been taken
+    pub async fn send_body(
+        &self,
+        our: &str,
+        app: &ProcessId,
+        stream_id: u64,
+        mut body: BodyStream,
+        send_to_loop: &MessageSender,
+        timeout: u64,
+    ) {
+        while let Some(frame) = body.next().await {
+            let Ok(frame) = frame else {
+                self.send_chunk(
+                    our,
+                    app,
+                    HttpServerRequest::BodyAborted { stream_id },
+                    vec![],
+                    send_to_loop,
+                    timeout,
+                )
+                .await;
+                return;
+            };
+            for chunk in frame.chunks(CHUNK_SIZE) {
+                let request = HttpServerRequest::BodyChunk {
+                    stream_id,
+                    done: false,
+                };
+                if !self
+                    .send_chunk(our, app, request, chunk.to_vec(), send_to_loop, timeout)
+                    .await
+                {
+                    // the process stopped taking the body: leave it be
+                    return;
+                }
+            }
+        }
+        let request = HttpServerRequest::BodyChunk {
+            stream_id,
+            done: true,
+        };
+        self.send_chunk(our, app, request, vec![], send_to_loop, timeout)
+            .await;
+    }
+
+    /// whether the process responded to the chunk in time
+    async fn send_chunk(
+        &self,
+        our: &str,
+        app: &ProcessId,
+        request: HttpServerRequest,
+        bytes: Vec<u8>,
+        send_to_loop: &MessageSender,
+        timeout: u64,
+    ) -> bool {
+        let id: u64 = rand::random();
+        let (send_ack, recv_ack) = oneshot::channel();
+        self.acks.insert(id, send_ack);
+        KernelMessage::builder()
+            .id(id)
+            .source((our, HTTP_SERVER_PROCESS_ID.clone()))
+            .target(Address::new(our, app.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: Some(timeout),
+                body: serde_json::to_vec(&request).unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .lazy_load_blob(Some(LazyLoadBlob { mime: None, bytes }))
+            .build()
+            .unwrap()
+            .send(send_to_loop)
+            .await;
+        let acked = tokio::time::timeout(Duration::from_secs(timeout), recv_ack)
+            .await
+            .is_ok_and(|ack| ack.is_ok());
+        self.acks.remove(&id);
+        acked
+    }
+
+    /// start the streamed response body of request `stream_id`, beginning
+    /// with `first`. it is cut off if `app` sends no chunk for `timeout` seconds.
+    pub fn open_response(
+        self: &std::sync::Arc<Self>,
+        stream_id: u64,
+        app: ProcessId,
+        first: Vec<u8>,
+        timeout: u64,
+    ) -> warp::hyper::Body {
+        let (sender, receiver) = mpsc::channel(RESPONSE_BUFFER);
+        if !first.is_empty() {
+            let _ = sender.try_send(Ok(Bytes::from(first)));
+        }
+        self.responses.insert(
+            stream_id,
+            ResponseStream {
+                app,
+                sender,
+                last_chunk: Instant::now(),
+            },
+        );
+        let streams = self.clone();
+        tokio::spawn(async move {
+            let timeout = Duration::from_secs(timeout);
+            loop {
+                tokio::time::sleep(timeout).await;
+                let idle = match streams.responses.get(&stream_id) {
+                    None => return,
+                    Some(stream) => stream.last_chunk.elapsed() >= timeout,
+                };
+                if idle {
+                    if let Some((_, stream)) = streams.responses.remove(&stream_id) {
+                        let _ = stream.sender.try_send(Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            "process stopped sending the response body",
+                        )));
+                    }
+                    return;
+                }
+            }
+        });
+        warp::hyper::Body::wrap_stream(futures::stream::unfold(
+            receiver,
+            |mut receiver| async move { receiver.recv().await.map(|item| (item, receiver)) },
+        ))
+    }
+
+    /// pass a chunk of its streamed response from `app` on to the client,
+    /// once the client has room for it
+    pub async fn response_chunk(
+        &self,
+        stream_id: u64,
+        app: &ProcessId,
+        bytes: Vec<u8>,
+        done: bool,
+    ) -> Result<(), HttpServerError> {
+        let sender = {
+            let Some(mut stream) = self
+                .responses
+                .get_mut(&stream_id)
+                .filter(|stream| &stream.app == app)
+            else {
+                return Err(HttpServerError::StreamError {
+                    error: format!("no response stream {stream_id}"),
+                });
+            };
+            stream.last_chunk = Instant::now();
+            stream.sender.clone()
+        };
+        if !bytes.is_empty() && sender.send(Ok(Bytes::from(bytes))).await.is_err() {
+            self.responses.remove(&stream_id);
+            return Err(HttpServerError::StreamError {
+                error: "client disconnected".to_string(),
+            });
+        }
+        if done {
+            // dropping the last sender ends the body
+            self.responses.remove(&stream_id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn app() -> ProcessId {
+        ProcessId::new(Some("app"), "app", "publisher.os")
+    }
+
+    #[tokio::test]
+    async fn response_chunks_make_the_body() {
+        let streams = Arc::new(Streams::default());
+        let body = streams.open_response(1, app(), b"one ".to_vec(), 30);
+        let sending = {
+            let streams = streams.clone();
+            tokio::spawn(async move {
+                streams
+                    .response_chunk(1, &app(), b"two ".to_vec(), false)
+                    .await?;
+                streams
+                    .response_chunk(1, &app(), b"three".to_vec(), true)
+                    .await
+            })
+        };
+        let bytes = warp::hyper::body::to_bytes(body).await.unwrap();
+        assert_eq!(&bytes[..], b"one two three");
+        assert!(sending.await.unwrap().is_ok());
+        // done ends the stream
+        assert!(streams
+            .response_chunk(1, &app(), vec![], true)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn only_the_app_may_send_chunks() {
+        let streams = Arc::new(Streams::default());
+        let _body = streams.open_response(1, app(), vec![], 30);
+        let other = ProcessId::new(Some("other"), "app", "publisher.os");
+        assert!(streams
+            .response_chunk(1, &other, b"x".to_vec(), false)
+            .await
+            .is_err());
+        assert!(streams
+            .response_chunk(2, &app(), b"x".to_vec(), false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn dropped_client_ends_the_stream() {
+        let streams = Arc::new(Streams::default());
+        drop(streams.open_response(1, app(), vec![], 30));
+        assert!(streams
+            .response_chunk(1, &app(), b"x".to_vec(), false)
+            .await
+            .is_err());
+        assert!(!streams.responses.contains_key(&1));
+    }
+
+    #[test]
+    fn only_waiting_chunks_are_acked() {
+        let streams = Streams::default();
+        let (send_ack, mut recv_ack) = oneshot::channel();
+        streams.acks.insert(7, send_ack);
+        assert!(!streams.ack(8));
+        assert!(streams.ack(7));
+        assert!(recv_ack.try_recv().is_ok());
+        assert!(!streams.ack(7));
+    }
+}
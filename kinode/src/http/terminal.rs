@@ -0,0 +1,129 @@
+//! sessions of the web terminal. the login cookie is sent with requests from
+//! every page the node serves, apps included, so it can't be what opens the
+//! terminal. instead, a page asks for a terminal session by giving the
+//! password again, and gets back a session cookie scoped to `/terminal` that
+//! scripts can't read, along with a CSRF token that must be passed to the
+//! websocket upgrade as `?csrf=<token>`.
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+
+/// how long a terminal session lasts before the password must be given again
+const SESSION_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// sessions kept at once; opening another evicts the oldest
+const MAX_SESSIONS: usize = 16;
+
+struct Session {
+    csrf: String,
+    expires: Instant,
+}
+
+#[derive(Default)]
+pub struct TerminalSessions {
+    /// by session cookie value
+    sessions: DashMap<String, Session>,
+}
+
+impl TerminalSessions {
+    /// the name of the session cookie on this node
+    pub fn cookie_name(our_node: &str) -> String {
+        format!("kinode-terminal_{our_node}")
+    }
+
+    /// the `set-cookie` header value for a session
+    pub fn set_cookie(our_node: &str, session: &str) -> String {
+        format!(
+            "{}={session}; Path=/terminal; HttpOnly; SameSite=Strict; Max-Age={}",
+            Self::cookie_name(our_node),
+            SESSION_TTL.as_secs(),
+        )
+    }
+
+    /// open a session for someone who has just given the password,
+    /// returning its cookie value and CSRF token
+    pub fn open(&self) -> (String, String) {
+        let now = Instant::now();
+        self.sessions.retain(|_, session| session.expires > now);
+        while self.sessions.len() >= MAX_SESSIONS {
+            let Some(oldest) = self
+                .sessions
+                .iter()
+                .min_by_key(|entry| entry.expires)
+                .map(|entry| entry.key().clone())
+            else {
+                break;
+            };
+            self.sessions.remove(&oldest);
+        }
+        let session = hex::encode(rand::random::<[u8; 32]>());
+        let csrf = hex::encode(rand::random::<[u8; 32]>());
+        self.sessions.insert(
+            session.clone(),
+            Session {
+                csrf: csrf.clone(),
+                expires: now + SESSION_TTL,
+            },
+        );
+        (session, csrf)
+    }
+
+    /// whether a `cookie` header carries a live session that `csrf` belongs to
+    pub fn check(&self, our_node: &str, cookie: &str, csrf: &str) -> bool {
+        let name = Self::cookie_name(our_node);
+        let Some(session) = cookie.split("; ").find_map(|entry| {
+            let (key, value) = entry.split_once('=')?;
+            (key == name).then_some(value)
+        }) else {
+            return false;
+        };
+        let Some(session) = self.sessions.get(session) else {
+            return false;
+        };
+        session.expires > Instant::now()
+            && ring::constant_time::verify_slices_are_equal(
+                session.csrf.as_bytes(),
+                csrf.as_bytes(),
+            )
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie(session: &str) -> String {
+        format!("kinode-auth_node.os=jwt; kinode-terminal_node.os={session}")
+    }
+
+    #[test]
+    fn session_needs_its_own_csrf_token() {
+        let sessions = TerminalSessions::default();
+        let (session, csrf) = sessions.open();
+        let (other, other_csrf) = sessions.open();
+        assert!(sessions.check("node.os", &cookie(&session), &csrf));
+        assert!(sessions.check("node.os", &cookie(&other), &other_csrf));
+        assert!(!sessions.check("node.os", &cookie(&session), &other_csrf));
+        assert!(!sessions.check("node.os", &cookie(&session), ""));
+        assert!(!sessions.check("other.os", &cookie(&session), &csrf));
+    }
+
+    #[test]
+    fn login_cookie_alone_opens_nothing() {
+        let sessions = TerminalSessions::default();
+        let (_, csrf) = sessions.open();
+        assert!(!sessions.check("node.os", "kinode-auth_node.os=jwt", &csrf));
+        assert!(!sessions.check("node.os", &cookie("made-up"), &csrf));
+    }
+
+    #[test]
+    fn oldest_session_is_evicted() {
+        let sessions = TerminalSessions::default();
+        let (first, first_csrf) = sessions.open();
+        for _ in 0..MAX_SESSIONS {
+            sessions.open();
+        }
+        assert_eq!(sessions.sessions.len(), MAX_SESSIONS);
+        assert!(!sessions.check("node.os", &cookie(&first), &first_csrf));
+    }
+}
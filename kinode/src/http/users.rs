@@ -0,0 +1,214 @@
+//! the users other than the node's owner that can log in to the node over
+//! HTTP, each with a [`UserRole`]. they are kept in `{home}/.users` with
+//! their password hashes, salted and stretched once more, so the file alone
+//! does not let anyone log in.
+//!
+//! login cookies name the user, and the user is looked up again every time
+//! one is checked: removing a user, or changing their role, takes effect at
+//! once rather than when their cookie expires.
+use crate::http::server_types::{HttpServerError, User, UserRole};
+use crate::keygen::{CREDENTIAL_LEN, PBKDF2_ALG};
+use ring::pbkdf2;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// fewer than the keyfile's: a login checks a password every time
+const ITERATIONS: u32 = 100_000;
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredUser {
+    role: UserRole,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+pub struct Users {
+    path: PathBuf,
+    users: RwLock<BTreeMap<String, StoredUser>>,
+}
+
+impl Users {
+    pub async fn load(home_directory_path: &str) -> anyhow::Result<Self> {
+        let path = PathBuf::from(format!("{home_directory_path}/.users"));
+        let users = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(_) => BTreeMap::new(),
+        };
+        Ok(Users {
+            path,
+            users: RwLock::new(users),
+        })
+    }
+
+    /// the user `name`, if they may still log in
+    pub fn get(&self, name: &str) -> Option<User> {
+        let users = self.users.read().unwrap();
+        users.get(name).map(|stored| User {
+            name: name.to_string(),
+            role: stored.role,
+        })
+    }
+
+    /// the user `name`, if `password_hash` is theirs
+    pub fn verify(&self, name: &str, password_hash: &str) -> Option<User> {
+        let users = self.users.read().unwrap();
+        let stored = users.get(name)?;
+        pbkdf2::verify(
+            PBKDF2_ALG,
+            NonZeroU32::new(ITERATIONS).unwrap(),
+            &stored.salt,
+            password_hash.as_bytes(),
+            &stored.hash,
+        )
+        .ok()?;
+        Some(User {
+            name: name.to_string(),
+            role: stored.role,
+        })
+    }
+
+    /// every user, in order of name
+    pub fn list(&self) -> Vec<User> {
+        let users = self.users.read().unwrap();
+        users
+            .iter()
+            .map(|(name, stored)| User {
+                name: name.clone(),
+                role: stored.role,
+            })
+            .collect()
+    }
+
+    /// add a user, or change the role and password of one
+    pub async fn add(
+        &self,
+        our_node: &str,
+        name: String,
+        role: UserRole,
+        password_hash: String,
+    ) -> Result<(), HttpServerError> {
+        let valid_name = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name || name == our_node {
+            return Err(HttpServerError::UserError {
+                error: format!(
+                    "user names are 1 to {MAX_NAME_LEN} letters, digits, - or _, and not the node's name"
+                ),
+            });
+        }
+        if password_hash.is_empty() {
+            return Err(HttpServerError::UserError {
+                error: "password_hash must not be empty".to_string(),
+            });
+        }
+        let salt: [u8; 16] = rand::random();
+        let mut hash = [0u8; CREDENTIAL_LEN];
+        pbkdf2::derive(
+            PBKDF2_ALG,
+            NonZeroU32::new(ITERATIONS).unwrap(),
+            &salt,
+            password_hash.as_bytes(),
+            &mut hash,
+        );
+        self.users.write().unwrap().insert(
+            name,
+            StoredUser {
+                role,
+                salt: salt.to_vec(),
+                hash: hash.to_vec(),
+            },
+        );
+        self.persist().await
+    }
+
+    pub async fn remove(&self, name: &str) -> Result<(), HttpServerError> {
+        if self.users.write().unwrap().remove(name).is_none() {
+            return Err(HttpServerError::UserError {
+                error: format!("no user {name}"),
+            });
+        }
+        self.persist().await
+    }
+
+    async fn persist(&self) -> Result<(), HttpServerError> {
+        let bytes = serde_json::to_vec(&*self.users.read().unwrap()).unwrap();
+        tokio::fs::write(&self.path, bytes)
+            .await
+            .map_err(|e| HttpServerError::UserError {
+                error: format!("couldn't save users: {e}"),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::server_types::HttpServerAction;
+
+    #[tokio::test]
+    async fn users_log_in_with_their_own_password() {
+        let home = std::env::temp_dir().join(format!("kinode-users-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&home).unwrap();
+        let users = Users::load(home.to_str().unwrap()).await.unwrap();
+        users
+            .add("node.os", "alice".into(), UserRole::Guest, "0xaa".into())
+            .await
+            .unwrap();
+        assert_eq!(users.verify("alice", "0xaa").unwrap().role, UserRole::Guest);
+        assert!(users.verify("alice", "0xbb").is_none());
+        assert!(users.verify("bob", "0xaa").is_none());
+
+        // adding again changes the role and password, and is kept
+        users
+            .add("node.os", "alice".into(), UserRole::Admin, "0xbb".into())
+            .await
+            .unwrap();
+        let users = Users::load(home.to_str().unwrap()).await.unwrap();
+        assert!(users.verify("alice", "0xaa").is_none());
+        assert_eq!(users.get("alice").unwrap().role, UserRole::Admin);
+
+        users.remove("alice").await.unwrap();
+        assert!(users.get("alice").is_none());
+        assert!(users.remove("alice").await.is_err());
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[tokio::test]
+    async fn names_and_passwords_are_checked() {
+        let users = Users {
+            path: PathBuf::from("/nonexistent/.users"),
+            users: RwLock::new(BTreeMap::new()),
+        };
+        for name in ["", "node.os", "al ice", &"a".repeat(MAX_NAME_LEN + 1)] {
+            assert!(users
+                .add("node.os", name.into(), UserRole::Member, "0xaa".into())
+                .await
+                .is_err());
+        }
+        assert!(users
+            .add("node.os", "alice".into(), UserRole::Member, "".into())
+            .await
+            .is_err());
+        assert!(users.list().is_empty());
+    }
+
+    #[test]
+    fn paths_bound_without_a_role_are_open_to_members() {
+        let bind: HttpServerAction = serde_json::from_str(
+            r#"{"Bind":{"path":"/","authenticated":true,"local_only":false,"cache":false}}"#,
+        )
+        .unwrap();
+        let HttpServerAction::Bind { min_role, .. } = bind else {
+            panic!("not a bind");
+        };
+        assert_eq!(min_role, UserRole::Member);
+        assert!(UserRole::Member >= min_role && UserRole::Guest < min_role);
+    }
+}
@@ -3,10 +3,20 @@ use jwt::VerifyWithKey;
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::collections::HashMap;
-use tokio::net::TcpListener;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
 use warp::http::{header::HeaderName, header::HeaderValue, HeaderMap};
 
-use lib::{core::ProcessId, types::http_server::*};
+use crate::http::users::Users;
+use lib::{
+    core::{PrintSender, Printout, ProcessId},
+    types::http_server::*,
+};
+
+/// pause after a failed accept, doubled on each failure in a row
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
 
 #[derive(Serialize, Deserialize)]
 pub struct RpcMessage {
@@ -35,12 +45,15 @@ pub fn _verify_auth_token(auth_token: &str, jwt_secret: &[u8]) -> Result<String,
     }
 }
 
-pub fn auth_cookie_valid(
+/// The user a login cookie was given to, if it is valid and they may still
+/// log in. Cookies given to the node's owner name no user.
+pub fn auth_cookie_user(
     our_node: &str,
     subdomain: Option<&ProcessId>,
     cookie: &str,
     jwt_secret: &[u8],
-) -> bool {
+    users: &Users,
+) -> Option<User> {
     let cookie: Vec<&str> = cookie.split("; ").collect();
 
     let token_label = match subdomain {
@@ -59,18 +72,22 @@ pub fn auth_cookie_valid(
 
     let auth_token = match auth_token {
         Some(token) if !token.is_empty() => token,
-        _ => return false,
+        _ => return None,
     };
 
-    let Ok(secret) = Hmac::<Sha256>::new_from_slice(jwt_secret) else {
-        return false;
-    };
+    let secret = Hmac::<Sha256>::new_from_slice(jwt_secret).ok()?;
 
-    let claims: Result<JwtClaims, _> = auth_token.verify_with_key(&secret);
+    let claims: JwtClaims = auth_token.verify_with_key(&secret).ok()?;
 
-    match claims {
-        Ok(data) => data.username == our_node && data.subdomain == subdomain.map(|s| s.to_string()),
-        Err(_) => false,
+    if claims.username != our_node || claims.subdomain != subdomain.map(|s| s.to_string()) {
+        return None;
+    }
+    match claims.user {
+        None => Some(User {
+            name: our_node.to_string(),
+            role: UserRole::Admin,
+        }),
+        Some(name) => users.get(&name),
     }
 }
 
@@ -131,16 +148,63 @@ pub fn deserialize_headers(hashmap: HashMap<String, String>) -> HeaderMap {
 
 pub async fn find_open_port(start_at: u16, end_at: u16) -> Option<TcpListener> {
     for port in start_at..end_at {
-        let bind_addr = format!("0.0.0.0:{}", port);
-        if let Some(bound) = is_port_available(&bind_addr).await {
+        if let Ok(bound) = bind_any(port) {
             return Some(bound);
         }
     }
     None
 }
 
-pub async fn is_port_available(bind_addr: &str) -> Option<TcpListener> {
-    TcpListener::bind(bind_addr).await.ok()
+/// listen on `port` of every address, IPv6 and IPv4 alike, or of every IPv4
+/// address if the host has no IPv6. IPv6 sockets are made dual-stack
+/// explicitly, since some hosts default to IPv6 only.
+pub fn bind_any(port: u16) -> std::io::Result<TcpListener> {
+    let dual_stack = || -> std::io::Result<std::net::TcpListener> {
+        let socket = socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::STREAM, None)?;
+        socket.set_only_v6(false)?;
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+        socket.listen(1024)?;
+        Ok(socket.into())
+    };
+    let listener = match dual_stack() {
+        Ok(listener) => listener,
+        Err(_) => std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?,
+    };
+    listener.set_nonblocking(true)?;
+    TcpListener::from_std(listener)
+}
+
+/// the connections accepted by `listener`, for warp to serve. a failed
+/// accept, as when the node runs out of file descriptors, is printed and
+/// retried after a pause rather than passed on: hyper stops serving at the
+/// first error it is given.
+pub fn incoming(
+    listener: TcpListener,
+    print_tx: PrintSender,
+) -> impl futures::Stream<Item = std::io::Result<TcpStream>> + Send {
+    futures::stream::unfold((listener, print_tx), |(listener, print_tx)| async move {
+        let mut backoff = ACCEPT_BACKOFF_MIN;
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => return Some((Ok(stream), (listener, print_tx))),
+                Err(e) => {
+                    Printout::new(
+                        2,
+                        format!(
+                            "http_server: couldn't accept connection, retrying in {}ms: {e}",
+                            backoff.as_millis()
+                        ),
+                    )
+                    .send(&print_tx)
+                    .await;
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                }
+            }
+        }
+    })
 }
 
 pub fn _binary_encoded_string_to_bytes(s: &str) -> Vec<u8> {
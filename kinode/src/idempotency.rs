@@ -0,0 +1,271 @@
+//! the responses of runtime modules (vfs, state, sqlite, http_client) to
+//! requests made with an [`IdempotencyKey`], kept for [`KEY_TTL`] so that a
+//! process sending a request again, e.g. after it timed out, gets the response
+//! to the first one rather than having it carried out twice.
+//!
+//! keys are per requesting address, node and process, and only kept for
+//! requests that change something. a key is only matched by a request with
+//! the same body and blob, so one reused for another request is carried out
+//! rather than answered with the response to the first: a read is as good carried out again. a
+//! retry that arrives while the first request is still being handled waits for
+//! it to finish. an error the module marked retryable (see [`RuntimeError`]) is
+//! not kept, so that a retry of it is carried out again.
+use dashmap::DashMap;
+use lib::types::core::{Address, IdempotencyKey, KernelMessage, Message, MessageSender};
+use lib::types::errors::RuntimeError;
+use std::future::Future;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, OnceCell};
+
+/// how long the response to a keyed request is kept
+const KEY_TTL: Duration = Duration::from_secs(10 * 60);
+/// most responses kept by a module; the oldest are forgotten to make room
+const MAX_KEYS: usize = 4096;
+/// most bytes of responses kept by a module; the oldest are forgotten to make
+/// room, and a response larger than this is not kept at all
+const MAX_BYTES: usize = 16 * 1024 * 1024;
+const TAP_CHANNEL_CAPACITY: usize = 32;
+
+/// the response to a keyed request, once it has been handled. `None` if the
+/// module sent none, as when the request did not expect one.
+type Handled = Arc<OnceCell<Option<KernelMessage>>>;
+
+/// the requester, its idempotency key, and a hash of the request's body and blob
+type Key = (Address, String, [u8; 32]);
+
+/// a request made with an idempotency key
+pub struct Keyed {
+    key: Key,
+    id: u64,
+    reply_to: Address,
+}
+
+impl Keyed {
+    /// the key of a request, if it has one and `mutates` says its body asks
+    /// for a change
+    pub fn of(km: &KernelMessage, mutates: impl FnOnce(&[u8]) -> bool) -> Option<Self> {
+        let Message::Request(ref request) = km.message else {
+            return None;
+        };
+        let key = IdempotencyKey::from_metadata(request.metadata.as_deref())?;
+        if !mutates(&request.body) {
+            return None;
+        }
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&(request.body.len() as u64).to_le_bytes());
+        hasher.update(&request.body);
+        if let Some(ref blob) = km.lazy_load_blob {
+            hasher.update(&blob.bytes);
+        }
+        Some(Keyed {
+            key: (
+                km.source.clone(),
+                key.idempotency_key,
+                *hasher.finalize().as_bytes(),
+            ),
+            id: km.id,
+            reply_to: km.rsvp.clone().unwrap_or(km.source.clone()),
+        })
+    }
+}
+
+struct Entry {
+    handled_at: Instant,
+    handled: Handled,
+    /// the size of the response, once handled
+    bytes: usize,
+}
+
+#[derive(Default)]
+pub struct Recent {
+    handled: DashMap<Key, Entry>,
+    /// the size of all responses kept
+    bytes: AtomicUsize,
+}
+
+impl Recent {
+    /// handle a request by running `handle` with a sender for its messages,
+    /// unless it was made with a key already handled: then the response the
+    /// first request got is sent again in its place.
+    pub async fn handle<F, Fut>(
+        &self,
+        keyed: Option<Keyed>,
+        send_to_loop: &MessageSender,
+        handle: F,
+    ) where
+        F: FnOnce(MessageSender) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let Some(keyed) = keyed else {
+            return handle(send_to_loop.clone()).await;
+        };
+        let handled = self.entry(&keyed.key);
+
+        let mut ran = false;
+        let response = handled
+            .get_or_init(|| async {
+                ran = true;
+                tap(keyed.id, &keyed.reply_to, send_to_loop, handle).await
+            })
+            .await;
+
+        if !ran {
+            if let Some(response) = response {
+                let mut response = response.clone();
+                response.id = keyed.id;
+                response.target = keyed.reply_to;
+                response.send(send_to_loop).await;
+            }
+            return;
+        }
+        let bytes = response.as_ref().map_or(0, response_bytes);
+        if response.as_ref().is_some_and(is_retryable) || bytes > MAX_BYTES {
+            self.remove_if(&keyed.key, &handled);
+            return;
+        }
+        if let Some(mut entry) = self.handled.get_mut(&keyed.key) {
+            if Arc::ptr_eq(&entry.handled, &handled) {
+                entry.bytes = bytes;
+                self.bytes.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+        while self.bytes.load(Ordering::Relaxed) > MAX_BYTES && self.remove_oldest() {}
+    }
+
+    fn entry(&self, key: &Key) -> Handled {
+        self.handled.retain(|_, entry| {
+            let keep = entry.handled_at.elapsed() < KEY_TTL;
+            if !keep {
+                self.bytes.fetch_sub(entry.bytes, Ordering::Relaxed);
+            }
+            keep
+        });
+        if self.handled.len() >= MAX_KEYS && !self.handled.contains_key(key) {
+            self.remove_oldest();
+        }
+        self.handled
+            .entry(key.clone())
+            .or_insert_with(|| Entry {
+                handled_at: Instant::now(),
+                handled: Arc::new(OnceCell::new()),
+                bytes: 0,
+            })
+            .handled
+            .clone()
+    }
+
+    /// forget the response to `key`, if it is still the one `handled` holds
+    fn remove_if(&self, key: &Key, handled: &Handled) {
+        if let Some((_, entry)) = self
+            .handled
+            .remove_if(key, |_, entry| Arc::ptr_eq(&entry.handled, handled))
+        {
+            self.bytes.fetch_sub(entry.bytes, Ordering::Relaxed);
+        }
+    }
+
+    /// forget the oldest response kept. returns `false` if none are.
+    fn remove_oldest(&self) -> bool {
+        let oldest = self
+            .handled
+            .iter()
+            .min_by_key(|entry| entry.value().handled_at)
+            .map(|entry| entry.key().clone());
+        let Some((_, entry)) = oldest.and_then(|oldest| self.handled.remove(&oldest)) else {
+            return false;
+        };
+        self.bytes.fetch_sub(entry.bytes, Ordering::Relaxed);
+        true
+    }
+}
+
+/// roughly how much memory keeping a response takes
+fn response_bytes(response: &KernelMessage) -> usize {
+    let blob = response
+        .lazy_load_blob
+        .as_ref()
+        .map_or(0, |blob| blob.bytes.len());
+    match response.message {
+        Message::Response((ref response, _)) => {
+            response.body.len() + response.metadata.as_ref().map_or(0, String::len) + blob
+        }
+        Message::Request(_) => blob,
+    }
+}
+
+/// run `handle`, passing on everything it sends, and return the response it
+/// sent to the request `id`, if any
+async fn tap<F, Fut>(
+    id: u64,
+    reply_to: &Address,
+    send_to_loop: &MessageSender,
+    handle: F,
+) -> Option<KernelMessage>
+where
+    F: FnOnce(MessageSender) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let (send_to_tap, mut recv_from_tap) = mpsc::channel(TAP_CHANNEL_CAPACITY);
+    let forward = {
+        let send_to_loop = send_to_loop.clone();
+        let reply_to = reply_to.clone();
+        tokio::spawn(async move {
+            let mut response = None;
+            while let Some(km) = recv_from_tap.recv().await {
+                if km.id == id
+                    && km.target == reply_to
+                    && matches!(km.message, Message::Response(_))
+                {
+                    response = Some(km.clone());
+                }
+                km.send(&send_to_loop).await;
+            }
+            response
+        })
+    };
+    // the tap closes once `handle` is done with its sender
+    handle(send_to_tap).await;
+    forward.await.unwrap_or(None)
+}
+
+fn is_retryable(response: &KernelMessage) -> bool {
+    let Message::Response((ref response, _)) = response.message else {
+        return false;
+    };
+    RuntimeError::from_metadata(response.metadata.as_deref()).is_some_and(|e| e.retryable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::{ProcessId, Request};
+
+    fn request(body: &[u8], key: &str) -> KernelMessage {
+        KernelMessage::builder()
+            .id(rand::random())
+            .source(("node.os", "chat:chat:sys".parse::<ProcessId>().unwrap()))
+            .target(("node.os", "vfs:distro:sys".parse::<ProcessId>().unwrap()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: Some(5),
+                body: body.to_vec(),
+                metadata: Some(IdempotencyKey::new(key).metadata()),
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn key_reused_for_another_request_is_another_key() {
+        let of = |km: &KernelMessage| Keyed::of(km, |_| true).unwrap().key;
+        assert_eq!(of(&request(b"write a", "1")), of(&request(b"write a", "1")));
+        assert_ne!(of(&request(b"write a", "1")), of(&request(b"write b", "1")));
+        assert_ne!(of(&request(b"write a", "1")), of(&request(b"write a", "2")));
+        assert!(Keyed::of(&request(b"read", "1"), |_| false).is_none());
+    }
+}
@@ -0,0 +1,77 @@
+use crate::kernel::process::Meter;
+use lib::types::core as t;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// stop recording a workload past this many requests, to bound memory
+const MAX_RECORDED: usize = 10_000;
+
+/// kernel-side state for benchmarking: the stats of metered processes
+/// and the workloads being recorded.
+pub struct Bench {
+    /// whether processes are metered, i.e. the node was booted with `--bench`
+    metering: bool,
+    stats: HashMap<t::ProcessId, Arc<Mutex<t::ProcessStats>>>,
+    recordings: HashMap<t::ProcessId, Vec<t::WorkloadMessage>>,
+}
+
+impl Bench {
+    pub fn new(metering: bool) -> Self {
+        Self {
+            metering,
+            stats: HashMap::new(),
+            recordings: HashMap::new(),
+        }
+    }
+
+    /// make a meter for a process that is starting, if in benchmark mode
+    pub fn meter(&mut self, process: &t::ProcessId) -> Option<Meter> {
+        if !self.metering {
+            return None;
+        }
+        let stats = Arc::new(Mutex::new(t::ProcessStats::default()));
+        self.stats.insert(process.clone(), stats.clone());
+        Some(Meter::new(stats))
+    }
+
+    pub fn stats(&self, process: &t::ProcessId) -> Option<t::ProcessStats> {
+        self.stats
+            .get(process)
+            .map(|stats| stats.lock().unwrap().clone())
+    }
+
+    pub fn remove(&mut self, process: &t::ProcessId) {
+        self.stats.remove(process);
+        self.recordings.remove(process);
+    }
+
+    pub fn start_recording(&mut self, process: t::ProcessId) {
+        self.recordings.entry(process).or_default();
+    }
+
+    pub fn stop_recording(&mut self, process: &t::ProcessId) -> Vec<t::WorkloadMessage> {
+        self.recordings.remove(process).unwrap_or_default()
+    }
+
+    /// record a message if it is a request to a local process being recorded
+    pub fn record(&mut self, our_name: &str, km: &t::KernelMessage) {
+        if km.target.node != our_name {
+            return;
+        }
+        let t::Message::Request(request) = &km.message else {
+            return;
+        };
+        let Some(recording) = self.recordings.get_mut(&km.target.process) else {
+            return;
+        };
+        if recording.len() < MAX_RECORDED {
+            recording.push(t::WorkloadMessage {
+                source: km.source.clone(),
+                request: request.clone(),
+                blob: km.lazy_load_blob.clone(),
+            });
+        }
+    }
+}
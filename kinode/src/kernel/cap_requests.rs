@@ -0,0 +1,176 @@
+use lib::types::core::{self as t, KERNEL_PROCESS_ID};
+use std::{collections::BTreeMap, time::Instant};
+
+/// kernel capability to list, approve and deny capability requests
+pub const CAP_REQUESTS_CAP_PARAMS: &str = "\"cap-requests\"";
+/// most requests queued at once, so that remote nodes can't fill memory
+const MAX_QUEUED: usize = 64;
+/// most requests queued at once from any one node
+const MAX_QUEUED_PER_NODE: usize = 4;
+
+/// capability requests from remote nodes, held until the user approves or
+/// denies them. they are not persisted: a reboot drops them unanswered, and
+/// their senders time out.
+#[derive(Default)]
+pub struct CapRequests {
+    next_id: u64,
+    queued: BTreeMap<u64, Queued>,
+}
+
+struct Queued {
+    /// the request to answer
+    km: t::KernelMessage,
+    capability: t::Capability,
+    reason: String,
+    received: Instant,
+}
+
+impl Queued {
+    fn listed(&self, id: u64) -> t::QueuedCapRequest {
+        t::QueuedCapRequest {
+            id,
+            from: self.km.source.clone(),
+            capability: self.capability.clone(),
+            reason: self.reason.clone(),
+            age_secs: self.received.elapsed().as_secs(),
+        }
+    }
+}
+
+impl CapRequests {
+    /// queue a [`t::CapRequest`] sent to our kernel from a remote node, and
+    /// return it as queued. if it can't be queued, the sender is told why.
+    pub async fn receive(
+        &mut self,
+        our_name: &str,
+        process_map: &t::ProcessMap,
+        km: t::KernelMessage,
+        send_to_loop: &t::MessageSender,
+    ) -> Option<t::QueuedCapRequest> {
+        let t::Message::Request(ref request) = km.message else {
+            return None;
+        };
+        if request.expects_response.is_none() {
+            return None;
+        }
+        let Ok(cap_request) = serde_json::from_slice::<t::CapRequest>(&request.body) else {
+            respond(
+                our_name,
+                km,
+                t::CapRequestResponse::Rejected("not a CapRequest".into()),
+                vec![],
+                send_to_loop,
+            )
+            .await;
+            return None;
+        };
+        let from_node = self
+            .queued
+            .values()
+            .filter(|queued| queued.km.source.node == km.source.node)
+            .count();
+        let rejection = if cap_request.capability.issuer.node != our_name {
+            Some("capability is not issued by this node")
+        } else if !process_map.contains_key(&cap_request.capability.issuer.process) {
+            Some("issuer of capability does not exist")
+        } else if self.queued.len() >= MAX_QUEUED || from_node >= MAX_QUEUED_PER_NODE {
+            Some("too many capability requests queued")
+        } else {
+            None
+        };
+        if let Some(rejection) = rejection {
+            respond(
+                our_name,
+                km,
+                t::CapRequestResponse::Rejected(rejection.into()),
+                vec![],
+                send_to_loop,
+            )
+            .await;
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let queued = Queued {
+            km,
+            capability: cap_request.capability,
+            reason: cap_request.reason,
+            received: Instant::now(),
+        };
+        let listed = queued.listed(id);
+        self.queued.insert(id, queued);
+        Some(listed)
+    }
+
+    pub fn list(&self) -> Vec<t::QueuedCapRequest> {
+        self.queued
+            .iter()
+            .map(|(id, queued)| queued.listed(*id))
+            .collect()
+    }
+
+    /// answer a queued request: with the capability, signed, if approved.
+    /// returns false if there is no such request.
+    pub async fn answer(
+        &mut self,
+        our_name: &str,
+        keypair: &ring::signature::Ed25519KeyPair,
+        id: u64,
+        approve: bool,
+        send_to_loop: &t::MessageSender,
+    ) -> bool {
+        let Some(queued) = self.queued.remove(&id) else {
+            return false;
+        };
+        if approve {
+            let sig = keypair.sign(&t::remote_grant_bytes(
+                &queued.capability,
+                &queued.km.source.node,
+            ));
+            respond(
+                our_name,
+                queued.km,
+                t::CapRequestResponse::Approved,
+                vec![(queued.capability, sig.as_ref().to_vec())],
+                send_to_loop,
+            )
+            .await;
+        } else {
+            respond(
+                our_name,
+                queued.km,
+                t::CapRequestResponse::Denied,
+                vec![],
+                send_to_loop,
+            )
+            .await;
+        }
+        true
+    }
+}
+
+async fn respond(
+    our_name: &str,
+    km: t::KernelMessage,
+    response: t::CapRequestResponse,
+    capabilities: Vec<(t::Capability, Vec<u8>)>,
+    send_to_loop: &t::MessageSender,
+) {
+    t::KernelMessage::builder()
+        .id(km.id)
+        .source((our_name, KERNEL_PROCESS_ID.clone()))
+        .target(km.rsvp.unwrap_or(km.source))
+        .message(t::Message::Response((
+            t::Response {
+                inherit: false,
+                body: serde_json::to_vec(&response).unwrap(),
+                metadata: None,
+                capabilities,
+            },
+            None,
+        )))
+        .build()
+        .unwrap()
+        .send(send_to_loop)
+        .await;
+}
@@ -0,0 +1,183 @@
+use crate::net::{validate_signature, OnchainPKI};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key,
+};
+use curve25519_dalek::{edwards::CompressedEdwardsY, MontgomeryPoint};
+use generic_array::GenericArray;
+use lib::types::core as t;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// capability, issued by the kernel, to sign with the node's networking key
+pub const SIGN_CAP_PARAMS: &str = "\"sign\"";
+/// capability, issued by the kernel, to open boxes sealed to the node
+pub const DECRYPT_CAP_PARAMS: &str = "\"decrypt\"";
+
+/// ephemeral public key, then nonce, then ciphertext
+const SEALED_HEADER_LEN: usize = 32 + 12;
+/// capability signatures to remember as verified
+const CAP_CACHE_SIZE: usize = 4096;
+
+/// sign a message with our networking key. like [`t::NetAction::Sign`],
+/// the source address is prepended, so a process can't sign for another.
+pub fn sign(
+    keypair: &ring::signature::Ed25519KeyPair,
+    source: &t::Address,
+    message: &[u8],
+) -> Vec<u8> {
+    keypair
+        .sign(&[source.to_string().as_bytes(), message].concat())
+        .as_ref()
+        .to_vec()
+}
+
+/// the node's sealing key and view of the PKI, shared with every process so
+/// that crypto host functions never hand key material to userspace.
+pub struct Keyring {
+    sealing_key: [u8; 32],
+    pki: OnchainPKI,
+    caps: CapCache,
+}
+
+impl Keyring {
+    pub fn new(sealing_key: [u8; 32], pki: OnchainPKI) -> Self {
+        Self {
+            sealing_key,
+            pki,
+            caps: CapCache::new(),
+        }
+    }
+
+    /// verify our signature on a capability a remote node sent us, skipping
+    /// the ed25519 check for a capability and signature verified before
+    pub fn verify_cap(&self, our_public_key: &[u8], cap: &t::Capability, sig: &[u8]) -> bool {
+        let bytes = rmp_serde::to_vec(cap).unwrap_or_default();
+        let key: ([u8; 32], Vec<u8>) = (Sha256::digest(&bytes).into(), sig.to_vec());
+        if self.caps.verified.lock().unwrap().get(&key).is_some() {
+            self.caps.hits.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        self.caps.misses.fetch_add(1, Ordering::Relaxed);
+        let valid =
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, our_public_key)
+                .verify(&bytes, sig)
+                .is_ok();
+        // only successes are kept, so a bad signature can't evict good ones
+        // any faster than a good one would
+        if valid {
+            self.caps.verified.lock().unwrap().put(key, ());
+        }
+        valid
+    }
+
+    pub fn cap_cache_stats(&self) -> t::CapCacheStats {
+        t::CapCacheStats {
+            hits: self.caps.hits.load(Ordering::Relaxed),
+            misses: self.caps.misses.load(Ordering::Relaxed),
+            entries: self.caps.verified.lock().unwrap().len() as u64,
+        }
+    }
+
+    /// verify a signature made by `from` with [`sign`] or [`t::NetAction::Sign`].
+    /// fails if the signing node is not in our representation of the PKI.
+    pub fn verify(&self, from: &t::Address, message: &[u8], signature: &[u8]) -> bool {
+        validate_signature(
+            &from.node,
+            signature,
+            &[from.to_string().as_bytes(), message].concat(),
+            &self.pki,
+        )
+    }
+
+    /// encrypt a message so that only `node` can read it: X25519 between a
+    /// fresh ephemeral key and the Montgomery form of their networking key,
+    /// then AES-256-GCM.
+    pub fn seal(&self, node: &str, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let recipient = self
+            .pki
+            .get(node)
+            .ok_or(format!("{node} is not in our PKI"))?
+            .networking_key
+            .clone();
+        let recipient = hex::decode(recipient.strip_prefix("0x").unwrap_or(&recipient))
+            .ok()
+            .and_then(|key| CompressedEdwardsY::from_slice(&key).ok())
+            .and_then(|key| key.decompress())
+            .ok_or(format!("{node} has an invalid networking key"))?
+            .to_montgomery();
+
+        let mut ephemeral = [0u8; 32];
+        ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut ephemeral)
+            .map_err(|_| "system entropy unavailable".to_string())?;
+        let ephemeral_public = MontgomeryPoint::mul_base_clamped(ephemeral);
+        let shared = recipient.mul_clamped(ephemeral);
+
+        let cipher = cipher(&shared, &ephemeral_public, &recipient);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| "encryption failed".to_string())?;
+        Ok([ephemeral_public.as_bytes(), nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// decrypt a message sealed to our node with [`Keyring::seal`]
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, String> {
+        if sealed.len() < SEALED_HEADER_LEN {
+            return Err("sealed message too short".to_string());
+        }
+        let mut ephemeral_public = [0u8; 32];
+        ephemeral_public.copy_from_slice(&sealed[..32]);
+        let ephemeral_public = MontgomeryPoint(ephemeral_public);
+        let ours = MontgomeryPoint::mul_base_clamped(self.sealing_key);
+        let shared = ephemeral_public.mul_clamped(self.sealing_key);
+
+        let cipher = cipher(&shared, &ephemeral_public, &ours);
+        cipher
+            .decrypt(
+                GenericArray::from_slice(&sealed[32..SEALED_HEADER_LEN]),
+                &sealed[SEALED_HEADER_LEN..],
+            )
+            .map_err(|_| "failed to open sealed message".to_string())
+    }
+}
+
+/// capabilities whose signatures we've verified, keyed by a hash of the
+/// capability and the signature, with how often a lookup found one
+struct CapCache {
+    verified: Mutex<LruCache<([u8; 32], Vec<u8>), ()>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CapCache {
+    fn new() -> Self {
+        Self {
+            verified: Mutex::new(LruCache::new(NonZeroUsize::new(CAP_CACHE_SIZE).unwrap())),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+/// bind the key to both public keys, so a box can't be re-targeted
+fn cipher(
+    shared: &MontgomeryPoint,
+    ephemeral_public: &MontgomeryPoint,
+    recipient: &MontgomeryPoint,
+) -> Aes256Gcm {
+    let key = Sha256::new()
+        .chain_update(b"kinode-sealed-box")
+        .chain_update(shared.as_bytes())
+        .chain_update(ephemeral_public.as_bytes())
+        .chain_update(recipient.as_bytes())
+        .finalize();
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
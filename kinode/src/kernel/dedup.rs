@@ -0,0 +1,203 @@
+use lib::types::core as t;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// most requests remembered at once, however short the window
+const MAX_REMEMBERED: usize = 1 << 20;
+/// most bytes of responses kept for replay at once. a response that would go
+/// over is not kept, and a duplicate of its request is dropped unanswered.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// a request is known by where it came from, where it goes, and its ID. the
+/// target is part of it since a remote process may inherit one ID for
+/// requests to several of our processes.
+type Key = (t::Address, t::ProcessId, u64);
+
+/// the requests from remote nodes seen in the last `window`, so that one
+/// delivered twice, because the network or a remote app retried it, is
+/// handled once. a duplicate is answered with the response to the first,
+/// once there is one, so a sender that retried still gets its answer.
+/// responses need no such thing: only the first to arrive answers the
+/// request awaiting it.
+pub struct Dedup {
+    window: Duration,
+    /// by request, the response our process sent, if it has
+    seen: HashMap<Key, Option<t::KernelMessage>>,
+    /// the keys in `seen`, oldest first, with when they arrived
+    arrivals: VecDeque<(Instant, Key)>,
+    /// bytes of the responses in `seen`
+    response_bytes: usize,
+}
+
+/// what to do with a message from a remote node
+#[derive(Debug)]
+pub enum Arrival {
+    /// handle it
+    New,
+    /// drop it, sending back the response to the first if there is one
+    Duplicate(Option<t::KernelMessage>),
+}
+
+impl Dedup {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+            arrivals: VecDeque::new(),
+            response_bytes: 0,
+        }
+    }
+
+    /// whether `km` is a remote request seen within the window. if not, and
+    /// it is one, it is remembered.
+    pub fn arrived(&mut self, km: &t::KernelMessage) -> Arrival {
+        if !matches!(km.message, t::Message::Request(_)) {
+            return Arrival::New;
+        }
+        let now = Instant::now();
+        while let Some((arrived, _)) = self.arrivals.front() {
+            if now.duration_since(*arrived) < self.window && self.arrivals.len() < MAX_REMEMBERED {
+                break;
+            }
+            let (_, key) = self.arrivals.pop_front().unwrap();
+            if let Some(Some(response)) = self.seen.remove(&key) {
+                self.response_bytes -= size(&response);
+            }
+        }
+        let key = (km.source.clone(), km.target.process.clone(), km.id);
+        if let Some(response) = self.seen.get(&key) {
+            return Arrival::Duplicate(response.clone().map(|mut response| {
+                response.sent_at = None;
+                response
+            }));
+        }
+        self.seen.insert(key.clone(), None);
+        self.arrivals.push_back((now, key));
+        Arrival::New
+    }
+
+    /// keep a response one of our processes sends to a remote node, if it
+    /// answers a request in the window, to send again to a duplicate of that
+    /// request. a response sent by a process other than the one the request
+    /// was for, as after an inherit, is not kept, nor is one with its blob in
+    /// the blob store, which may be gone by the time it would be sent again.
+    pub fn responded(&mut self, our_node: &str, km: &t::KernelMessage) {
+        if km.target.node == our_node
+            || km.blob_handle.is_some()
+            || !matches!(km.message, t::Message::Response(_))
+        {
+            return;
+        }
+        let key = (km.target.clone(), km.source.process.clone(), km.id);
+        let Some(slot) = self.seen.get_mut(&key) else {
+            return;
+        };
+        if slot.is_some() {
+            return;
+        }
+        let size = size(km);
+        if self.response_bytes + size > MAX_RESPONSE_BYTES {
+            return;
+        }
+        self.response_bytes += size;
+        *slot = Some(km.clone());
+    }
+}
+
+fn size(km: &t::KernelMessage) -> usize {
+    let body = match &km.message {
+        t::Message::Request(request) => request.body.len(),
+        t::Message::Response((response, _)) => response.body.len(),
+    };
+    body + km
+        .lazy_load_blob
+        .as_ref()
+        .map_or(0, |blob| blob.bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u64) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(id)
+            .source(("them.os", "chat:chat:sys".parse::<t::ProcessId>().unwrap()))
+            .target(("us.os", "chat:chat:sys".parse::<t::ProcessId>().unwrap()))
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: Some(5),
+                body: b"send".to_vec(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    fn response(id: u64, from: &str) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(id)
+            .source(("us.os", from.parse::<t::ProcessId>().unwrap()))
+            .target(("them.os", "chat:chat:sys".parse::<t::ProcessId>().unwrap()))
+            .message(t::Message::Response((
+                t::Response {
+                    inherit: false,
+                    body: b"sent".to_vec(),
+                    metadata: None,
+                    capabilities: vec![],
+                },
+                None,
+            )))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn duplicate_is_answered_once_responded() {
+        let mut dedup = Dedup::new(Duration::from_secs(60));
+        assert!(matches!(dedup.arrived(&request(1)), Arrival::New));
+        // not yet answered: the duplicate is dropped, and the first answer
+        // will reach the sender
+        assert!(matches!(
+            dedup.arrived(&request(1)),
+            Arrival::Duplicate(None)
+        ));
+        dedup.responded("us.os", &response(1, "chat:chat:sys"));
+        let Arrival::Duplicate(Some(replayed)) = dedup.arrived(&request(1)) else {
+            panic!("duplicate was not answered");
+        };
+        assert_eq!(replayed.id, 1);
+        assert_eq!(replayed.target.node, "them.os");
+        assert!(replayed.sent_at.is_none());
+        assert!(matches!(dedup.arrived(&request(2)), Arrival::New));
+    }
+
+    #[test]
+    fn only_responses_to_remembered_requests_are_kept() {
+        let mut dedup = Dedup::new(Duration::from_secs(60));
+        dedup.responded("us.os", &response(1, "chat:chat:sys"));
+        assert!(dedup.seen.is_empty());
+        dedup.arrived(&request(1));
+        // answered by another process, as after an inherit
+        dedup.responded("us.os", &response(1, "other:chat:sys"));
+        assert!(matches!(
+            dedup.arrived(&request(1)),
+            Arrival::Duplicate(None)
+        ));
+        assert_eq!(dedup.response_bytes, 0);
+    }
+
+    #[test]
+    fn responses_are_forgotten_with_their_requests() {
+        let mut dedup = Dedup::new(Duration::ZERO);
+        dedup.arrived(&request(1));
+        dedup.responded("us.os", &response(1, "chat:chat:sys"));
+        assert_eq!(dedup.response_bytes, 4);
+        assert!(matches!(dedup.arrived(&request(2)), Arrival::New));
+        assert_eq!(dedup.response_bytes, 0);
+        assert!(matches!(dedup.arrived(&request(1)), Arrival::New));
+    }
+}
@@ -0,0 +1,204 @@
+use lib::types::core::{self as t, KERNEL_PROCESS_ID, VFS_PROCESS_ID};
+use std::collections::HashMap;
+
+/// the most of a request's body shown when reporting it
+const MAX_BODY_SHOWN: usize = 200;
+
+/// the processes started as dry runs, with how many requests each has
+/// attempted. a dry run's requests are reported instead of sent, save those
+/// that only read from the vfs, so that a script can be inspected before it
+/// is run for real. a dry run may run other scripts, which are dry runs too,
+/// but can't spawn processes that outlive it.
+#[derive(Default)]
+pub struct DryRuns(HashMap<t::ProcessId, usize>);
+
+impl DryRuns {
+    pub fn start(&mut self, process: t::ProcessId) {
+        self.0.insert(process, 0);
+    }
+
+    pub fn contains(&self, process: &t::ProcessId) -> bool {
+        self.0.contains_key(process)
+    }
+
+    /// if `km` is a request from a dry run that must not be sent, count it
+    /// and say what it would have done
+    pub fn intercept(&mut self, our_name: &str, km: &t::KernelMessage) -> Option<String> {
+        let t::Message::Request(request) = &km.message else {
+            return None;
+        };
+        if km.source.node != our_name {
+            return None;
+        }
+        let attempted = self.0.get_mut(&km.source.process)?;
+        if km.target.node == our_name
+            && km.target.process == *VFS_PROCESS_ID
+            && serde_json::from_slice::<t::VfsRequest>(&request.body)
+                .is_ok_and(|vfs_request| reads_only(&vfs_request.action))
+        {
+            return None;
+        }
+        // the kernel starts scripts run by a dry run as dry runs
+        if km.target.node == our_name
+            && km.target.process == *KERNEL_PROCESS_ID
+            && matches!(
+                serde_json::from_slice::<t::KernelCommand>(&request.body),
+                Ok(t::KernelCommand::RunTransient { .. })
+            )
+        {
+            return None;
+        }
+        *attempted += 1;
+        let body = String::from_utf8_lossy(&request.body);
+        let shown: String = body.chars().take(MAX_BODY_SHOWN).collect();
+        Some(format!(
+            "dry run: {} would send {} a request: {shown}{}{}",
+            km.source.process,
+            km.target,
+            if shown.len() < body.len() { "..." } else { "" },
+            match &km.lazy_load_blob {
+                Some(blob) => format!(" (with a {} byte blob)", blob.bytes.len()),
+                None => String::new(),
+            },
+        ))
+    }
+
+    /// forget a dry run that has ended, returning how many requests it attempted
+    pub fn finish(&mut self, process: &t::ProcessId) -> Option<usize> {
+        self.0.remove(process)
+    }
+}
+
+/// whether a vfs action leaves the vfs as it was
+fn reads_only(action: &t::VfsAction) -> bool {
+    matches!(
+        action,
+        t::VfsAction::ListDrives
+            | t::VfsAction::OpenFile { create: false }
+            | t::VfsAction::CloseFile
+            | t::VfsAction::Read
+            | t::VfsAction::ReadDir
+            | t::VfsAction::ReadDirPage { .. }
+            | t::VfsAction::CountDir
+            | t::VfsAction::ReadToEnd
+            | t::VfsAction::ReadExact(_)
+            | t::VfsAction::ReadToString
+            | t::VfsAction::Seek { .. }
+            | t::VfsAction::Metadata
+            | t::VfsAction::Len
+            | t::VfsAction::Hash
+            | t::VfsAction::TreeHash
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script() -> t::ProcessId {
+        t::ProcessId::new(Some("script"), "app", "publisher.os")
+    }
+
+    fn request(target: (&str, t::ProcessId), body: Vec<u8>) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(1)
+            .source(("our.os", script()))
+            .target(target)
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: None,
+                body,
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    fn vfs_request(action: t::VfsAction) -> t::KernelMessage {
+        request(
+            ("our.os", VFS_PROCESS_ID.clone()),
+            serde_json::to_vec(&t::VfsRequest {
+                path: "/app:publisher.os/pkg/file".into(),
+                action,
+            })
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn only_dry_runs_are_intercepted() {
+        let mut dry_runs = DryRuns::default();
+        let km = vfs_request(t::VfsAction::Write);
+        assert_eq!(dry_runs.intercept("our.os", &km), None);
+        dry_runs.start(script());
+        assert!(dry_runs.intercept("our.os", &km).is_some());
+        assert!(dry_runs.intercept("other.os", &km).is_none());
+        assert_eq!(dry_runs.finish(&script()), Some(1));
+        assert_eq!(dry_runs.intercept("our.os", &km), None);
+    }
+
+    #[test]
+    fn vfs_reads_and_nested_scripts_go_through() {
+        let mut dry_runs = DryRuns::default();
+        dry_runs.start(script());
+        assert_eq!(
+            dry_runs.intercept("our.os", &vfs_request(t::VfsAction::Read)),
+            None
+        );
+        let run = request(
+            ("our.os", KERNEL_PROCESS_ID.clone()),
+            serde_json::to_vec(&t::KernelCommand::RunTransient {
+                id: t::ProcessId::new(Some("nested"), "app", "publisher.os"),
+                wasm_bytes_handle: "app:publisher.os/pkg/nested.wasm".into(),
+                wit_version: None,
+                initial_capabilities: Default::default(),
+                public: false,
+                args: vec![],
+                capture_output: false,
+                dry_run: false,
+                limits: Default::default(),
+            })
+            .unwrap(),
+        );
+        assert_eq!(dry_runs.intercept("our.os", &run), None);
+        // reads on another node's vfs are still sent to another node
+        let remote = request(
+            ("other.os", VFS_PROCESS_ID.clone()),
+            serde_json::to_vec(&t::VfsRequest {
+                path: "/app:publisher.os/pkg/file".into(),
+                action: t::VfsAction::Read,
+            })
+            .unwrap(),
+        );
+        assert!(dry_runs.intercept("our.os", &remote).is_some());
+        assert_eq!(dry_runs.finish(&script()), Some(1));
+    }
+
+    #[test]
+    fn long_bodies_are_cut_short() {
+        let mut dry_runs = DryRuns::default();
+        dry_runs.start(script());
+        let km = request(
+            (
+                "our.os",
+                t::ProcessId::new(Some("other"), "app", "publisher.os"),
+            ),
+            vec![b'a'; MAX_BODY_SHOWN * 2],
+        );
+        let report = dry_runs.intercept("our.os", &km).unwrap();
+        assert!(report.ends_with(&format!("{}...", "a".repeat(MAX_BODY_SHOWN))));
+    }
+
+    #[test]
+    fn reads_only_rejects_writes() {
+        assert!(reads_only(&t::VfsAction::OpenFile { create: false }));
+        assert!(reads_only(&t::VfsAction::ReadDir));
+        assert!(reads_only(&t::VfsAction::Metadata));
+        assert!(!reads_only(&t::VfsAction::OpenFile { create: true }));
+        assert!(!reads_only(&t::VfsAction::Write));
+        assert!(!reads_only(&t::VfsAction::Append));
+        assert!(!reads_only(&t::VfsAction::RemoveFile));
+        assert!(!reads_only(&t::VfsAction::CreateDrive));
+    }
+}
@@ -0,0 +1,164 @@
+use lib::types::core as t;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::RwLock,
+};
+
+/// kernel capability to list and set feature flags
+pub const FEATURE_FLAGS_CAP_PARAMS: &str = "\"feature-flags\"";
+
+/// every feature flag: its name, the behavior it gates, and whether it is on
+/// unless set. a behavior is given a flag while it rolls out, and the flag
+/// removed once the behavior is settled for good.
+const FLAGS: &[(&str, &str, bool)] = &[(
+    "cap-feedback",
+    "tell a process which capability it lacked when the kernel drops its message",
+    false,
+)];
+
+/// Feature flags gating kernel behaviors as they roll out, so that each node
+/// can opt in to, or out of, a new behavior until it is settled. Flags set
+/// are kept in `.feature_flags` in the home directory, across reboots.
+pub struct FeatureFlags {
+    path: String,
+    /// flags turned on for this boot only, from the command line
+    forced: HashSet<String>,
+    /// flags set, and whether on
+    set: RwLock<BTreeMap<String, bool>>,
+    /// held while setting a flag, so that sets are kept in the order made
+    setting: tokio::sync::Mutex<()>,
+}
+
+impl FeatureFlags {
+    /// load the flags set on the node at `home_directory_path`, turning
+    /// `forced` on for this boot. flags set that no longer exist are dropped.
+    /// a file of flags that doesn't parse is an error, rather than every flag
+    /// going back to its default unnoticed.
+    pub async fn load(home_directory_path: &str, forced: Vec<String>) -> anyhow::Result<Self> {
+        let path = format!("{home_directory_path}/.feature_flags");
+        let mut set: BTreeMap<String, bool> = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| anyhow::anyhow!("{path} does not parse, so fix or remove it: {e}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(anyhow::anyhow!("couldn't read {path}: {e}")),
+        };
+        set.retain(|name, _| flag(name).is_some());
+        for name in &forced {
+            if flag(name).is_none() {
+                return Err(anyhow::anyhow!(
+                    "no such feature flag {name}: use one of {}",
+                    FLAGS
+                        .iter()
+                        .map(|(name, _, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        Ok(Self {
+            path,
+            forced: forced.into_iter().collect(),
+            set: RwLock::new(set),
+            setting: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// whether the behavior behind a flag is on
+    pub fn enabled(&self, name: &str) -> bool {
+        if self.forced.contains(name) {
+            return true;
+        }
+        match self.set.read().unwrap().get(name) {
+            Some(enabled) => *enabled,
+            None => flag(name).is_some_and(|(_, _, default)| default),
+        }
+    }
+
+    /// set a flag on or off, or back to its default with `None`. the flag is
+    /// kept before it takes effect, so a flag that couldn't be kept is left
+    /// as it was.
+    pub async fn set(&self, name: &str, enabled: Option<bool>) -> Result<(), String> {
+        if flag(name).is_none() {
+            return Err(format!("no such feature flag {name}"));
+        }
+        let _setting = self.setting.lock().await;
+        let mut set = self.set.read().unwrap().clone();
+        match enabled {
+            Some(enabled) => set.insert(name.to_string(), enabled),
+            None => set.remove(name),
+        };
+        // written whole and moved into place, so a crash can't leave it torn
+        let tmp = format!("{}.tmp", self.path);
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(&set).unwrap())
+            .await
+            .map_err(|e| format!("couldn't save feature flags: {e}"))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .map_err(|e| format!("couldn't save feature flags: {e}"))?;
+        *self.set.write().unwrap() = set;
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<t::FeatureFlag> {
+        let set = self.set.read().unwrap();
+        FLAGS
+            .iter()
+            .map(|(name, description, default)| t::FeatureFlag {
+                name: name.to_string(),
+                description: description.to_string(),
+                enabled: self.enabled(name),
+                default: *default,
+                set: set.get(*name).copied(),
+                forced: self.forced.contains(*name),
+            })
+            .collect()
+    }
+}
+
+fn flag(name: &str) -> Option<(&'static str, &'static str, bool)> {
+    FLAGS.iter().find(|(flag, _, _)| *flag == name).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn home() -> String {
+        let home = std::env::temp_dir().join(format!("kinode-flags-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&home).unwrap();
+        home.to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn set_flags_are_kept_across_loads() {
+        let home = home();
+        let flags = FeatureFlags::load(&home, vec![]).await.unwrap();
+        assert!(!flags.enabled("cap-feedback"));
+        flags.set("cap-feedback", Some(true)).await.unwrap();
+        assert!(flags.enabled("cap-feedback"));
+        assert!(flags.set("no-such-flag", Some(true)).await.is_err());
+
+        let flags = FeatureFlags::load(&home, vec![]).await.unwrap();
+        assert!(flags.enabled("cap-feedback"));
+        flags.set("cap-feedback", None).await.unwrap();
+        let flags = FeatureFlags::load(&home, vec!["cap-feedback".to_string()])
+            .await
+            .unwrap();
+        let listed = flags.list();
+        assert!(listed[0].enabled && listed[0].forced && listed[0].set.is_none());
+        assert!(!std::path::Path::new(&format!("{home}/.feature_flags.tmp")).exists());
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_flags_are_an_error() {
+        let home = home();
+        std::fs::write(format!("{home}/.feature_flags"), b"{\"cap-feedback\": tr").unwrap();
+        assert!(FeatureFlags::load(&home, vec![]).await.is_err());
+        std::fs::remove_file(format!("{home}/.feature_flags")).unwrap();
+        assert!(FeatureFlags::load(&home, vec!["no-such-flag".to_string()])
+            .await
+            .is_err());
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+}
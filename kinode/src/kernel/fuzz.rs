@@ -0,0 +1,121 @@
+use super::*;
+
+/// the kernel's state, with no processes running, for `--fuzz kernel` to feed
+/// requests to `handle_kernel_request` as the event loop would
+pub struct Fuzzed {
+    our_name: String,
+    keypair: Arc<ring::signature::Ed25519KeyPair>,
+    keyring: Arc<crypto::Keyring>,
+    blob_store: Arc<BlobStore>,
+    send_to_loop: t::MessageSender,
+    send_to_terminal: t::PrintSender,
+    senders: Senders,
+    process_handles: ProcessHandles,
+    process_map: t::ProcessMap,
+    crash_subscribers: HashSet<t::ProcessId>,
+    children: HashMap<t::ProcessId, HashSet<t::ProcessId>>,
+    transient: HashSet<t::ProcessId>,
+    dry_runs: dry_run::DryRuns,
+    readiness: readiness::Readiness,
+    bench: bench::Bench,
+    pending: pending::Pending,
+    public_methods: public::PublicMethods,
+    cap_requests: cap_requests::CapRequests,
+    post_mortems: post_mortem::PostMortems,
+    transactions: transactions::Transactions,
+    net_usage: net_usage::NetUsage,
+    feature_flags: Arc<flags::FeatureFlags>,
+    caps_oracle: t::CapMessageSender,
+    engine: Engine,
+    home_directory_path: String,
+}
+
+impl Fuzzed {
+    pub async fn new(
+        our_name: &str,
+        home_directory_path: String,
+        send_to_loop: t::MessageSender,
+        send_to_terminal: t::PrintSender,
+        caps_oracle: t::CapMessageSender,
+        feature_flags: Arc<flags::FeatureFlags>,
+    ) -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.wasm_component_model(true);
+        config.async_support(true);
+        let rng = ring::rand::SystemRandom::new();
+        let keypair = ring::signature::Ed25519KeyPair::from_pkcs8(
+            ring::signature::Ed25519KeyPair::generate_pkcs8(&rng)
+                .map_err(|_| anyhow::anyhow!("couldn't generate keypair"))?
+                .as_ref(),
+        )
+        .map_err(|_| anyhow::anyhow!("couldn't read keypair"))?;
+        tokio::fs::create_dir_all(format!("{home_directory_path}/vfs")).await?;
+        Ok(Self {
+            our_name: our_name.to_string(),
+            keypair: Arc::new(keypair),
+            keyring: Arc::new(crypto::Keyring::new(
+                rand::random(),
+                Arc::new(dashmap::DashMap::new()),
+            )),
+            blob_store: Arc::new(BlobStore::new(&home_directory_path).await?),
+            send_to_loop,
+            send_to_terminal,
+            senders: HashMap::new(),
+            process_handles: HashMap::new(),
+            process_map: HashMap::new(),
+            crash_subscribers: HashSet::new(),
+            children: HashMap::new(),
+            transient: HashSet::new(),
+            dry_runs: dry_run::DryRuns::default(),
+            readiness: readiness::Readiness::default(),
+            bench: bench::Bench::new(false),
+            pending: pending::Pending::default(),
+            public_methods: public::PublicMethods::default(),
+            cap_requests: cap_requests::CapRequests::default(),
+            post_mortems: post_mortem::PostMortems::default(),
+            transactions: transactions::Transactions::default(),
+            net_usage: net_usage::NetUsage::default(),
+            feature_flags,
+            caps_oracle,
+            engine: Engine::new(&config)?,
+            home_directory_path,
+        })
+    }
+
+    /// handle a request sent to the kernel, returning whether it shut the
+    /// kernel down, after which this state is not to be fed again
+    pub async fn handle(&mut self, km: t::KernelMessage) -> bool {
+        handle_kernel_request(
+            &self.our_name,
+            &self.keypair,
+            &self.keyring,
+            &self.blob_store,
+            km,
+            &self.send_to_loop,
+            &self.send_to_terminal,
+            &mut self.senders,
+            &mut self.process_handles,
+            &mut self.process_map,
+            &mut self.crash_subscribers,
+            &mut self.children,
+            &mut self.transient,
+            &mut self.dry_runs,
+            &mut self.readiness,
+            &mut self.bench,
+            &mut self.pending,
+            &mut self.public_methods,
+            &mut self.cap_requests,
+            &mut self.post_mortems,
+            &mut self.transactions,
+            &mut self.net_usage,
+            &self.feature_flags,
+            process::RequestTimeouts::new(30, 86_400),
+            &self.caps_oracle,
+            &self.engine,
+            None,
+            &self.home_directory_path,
+        )
+        .await
+        .is_some()
+    }
+}
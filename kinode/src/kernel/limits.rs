@@ -0,0 +1,122 @@
+use lib::types::core as t;
+use std::collections::HashMap;
+
+/// the largest body and blob, in bytes, a message may carry
+#[derive(Clone, Copy, Debug)]
+pub struct SizeLimit {
+    pub body: usize,
+    pub blob: usize,
+}
+
+impl SizeLimit {
+    /// the tighter of two limits
+    fn min(self, other: Self) -> Self {
+        Self {
+            body: self.body.min(other.body),
+            blob: self.blob.min(other.blob),
+        }
+    }
+}
+
+/// how large the messages passing through the kernel may be. every message
+/// must fit the node's limit, and a message to a runtime module must also fit
+/// that module's, if it has one.
+#[derive(Clone, Debug)]
+pub struct MessageLimits {
+    pub node: SizeLimit,
+    pub modules: HashMap<t::ProcessId, SizeLimit>,
+}
+
+impl MessageLimits {
+    /// describe how `km` is too large, if it is
+    pub fn exceeded_by(&self, km: &t::KernelMessage) -> Option<String> {
+        let limit = match self.modules.get(&km.target.process) {
+            Some(module) => self.node.min(*module),
+            None => self.node,
+        };
+        let body = match &km.message {
+            t::Message::Request(request) => request.body.len(),
+            t::Message::Response((response, _)) => response.body.len(),
+        };
+        let blob = km
+            .lazy_load_blob
+            .as_ref()
+            .map_or(0, |blob| blob.bytes.len());
+        if body > limit.body {
+            Some(format!(
+                "body of {body} bytes exceeds limit of {}",
+                limit.body
+            ))
+        } else if blob > limit.blob {
+            Some(format!(
+                "blob of {blob} bytes exceeds limit of {}",
+                limit.blob
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+/// parse a runtime module's limit from `<process_id>=<body bytes>,<blob bytes>`
+pub fn parse_module_limit(arg: &str) -> Result<(t::ProcessId, SizeLimit), String> {
+    let usage = || format!("expected <process_id>=<body bytes>,<blob bytes>, got {arg}");
+    let (process, sizes) = arg.split_once('=').ok_or_else(usage)?;
+    let (body, blob) = sizes.split_once(',').ok_or_else(usage)?;
+    Ok((
+        process
+            .parse()
+            .map_err(|_| format!("invalid process id {process}"))?,
+        SizeLimit {
+            body: body.parse().map_err(|_| usage())?,
+            blob: blob.parse().map_err(|_| usage())?,
+        },
+    ))
+}
+
+/// parse the limits every script is held to, from a comma-separated list of
+/// any of `fuel=<fuel>`, `memory=<bytes>` and `wall-time=<secs>`
+pub fn parse_script_limits(arg: &str) -> Result<t::ProcessLimits, String> {
+    let mut limits = t::ProcessLimits::default();
+    for limit in arg.split(',') {
+        let (name, value) = limit
+            .split_once('=')
+            .ok_or_else(|| format!("expected <limit>=<value>, got {limit}"))?;
+        let value = Some(
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("invalid {name} limit {value}"))?,
+        );
+        match name {
+            "fuel" => limits.fuel = value,
+            "memory" => limits.memory_bytes = value,
+            "wall-time" => limits.wall_time_secs = value,
+            _ => {
+                return Err(format!(
+                    "unknown limit {name}, expected fuel, memory or wall-time"
+                ))
+            }
+        }
+    }
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_limits_parse() {
+        assert_eq!(
+            parse_script_limits("fuel=1000,wall-time=30").unwrap(),
+            t::ProcessLimits {
+                fuel: Some(1000),
+                memory_bytes: None,
+                wall_time_secs: Some(30),
+            }
+        );
+        assert!(parse_script_limits("memory=lots").is_err());
+        assert!(parse_script_limits("cpu=5").is_err());
+        assert!(parse_script_limits("").is_err());
+    }
+}
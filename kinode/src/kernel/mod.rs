@@ -1,3 +1,5 @@
+use crate::blobs::BlobStore;
+use futures::StreamExt;
 use lib::types::core::{self as t, KERNEL_PROCESS_ID, STATE_PROCESS_ID, VFS_PROCESS_ID};
 use serde::{Deserialize, Serialize};
 use std::{
@@ -7,15 +9,79 @@ use std::{
 use tokio::{sync::mpsc, task::JoinHandle};
 use wasmtime::{Config, Engine, WasmBacktraceDetails};
 
+/// Meter processes and record workloads for benchmarking.
+mod bench;
+/// Queue capability requests from remote nodes for the user to approve.
+mod cap_requests;
+pub mod crypto;
+/// Drop requests from remote nodes delivered more than once.
+mod dedup;
+/// Report the requests of scripts run as dry runs instead of sending them.
+mod dry_run;
+/// Gate new kernel behaviors behind feature flags while they roll out.
+pub mod flags;
+/// Hold the kernel's state outside the event loop, to fuzz its requests.
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
+/// Cap the size of messages passing through the kernel.
+pub mod limits;
+/// Count the messages each process sends to and receives from other nodes.
+mod net_usage;
+/// Track what processes are waiting on, for debugging hung requests.
+mod pending;
+/// Dispatch messages among the instances of a pooled process.
+mod pool;
+/// Keep the state and crash report of crashed processes.
+mod post_mortem;
 /// Manipulate a single process.
 pub mod process;
+/// Let any local process make the requests a process declares public.
+mod public;
+/// Hold processes back from running until their dependencies are ready.
+mod readiness;
+/// Tell the sources of requests that were, or couldn't be, delivered.
+mod receipts;
+/// Check and route messages, and deliver them to their targets, from several tasks.
+mod shards;
 /// Implement the functions served to processes by `wit-v0.7.0/kinode.wit`.
 mod standard_host;
 /// Implement the functions served to processes by `wit-v0.8.0/kinode.wit`.
 mod standard_host_v0;
+/// Keep a package's processes as they were, to restore if its install fails.
+mod transactions;
 
 pub const LATEST_WIT_VERSION: u32 = 0;
 const PROCESS_CHANNEL_CAPACITY: usize = 100;
+/// most wasm files read from disk at once while booting
+const BOOT_READ_PARALLELISM: usize = 16;
+/// kernel capability to change what happens when other processes exit
+pub const ON_EXIT_CAP_PARAMS: &str = "\"on-exit\"";
+
+/// whether `source` may have what happens when `process` exits set to
+/// `on_exit`. a process may set its own; with the `"on-exit"` capability it
+/// may have another restart or not, but never send requests on exit, which
+/// would go out as that process, with its capabilities.
+fn may_set_on_exit(
+    source: &t::ProcessId,
+    process: &t::ProcessId,
+    on_exit: &t::OnExit,
+    has_cap: bool,
+) -> Result<(), String> {
+    if source == process || *source == *KERNEL_PROCESS_ID {
+        return Ok(());
+    }
+    if !has_cap {
+        return Err(format!(
+            "{source} lacks the capability to change what happens when {process} exits"
+        ));
+    }
+    if let t::OnExit::Requests(_) = on_exit {
+        return Err(format!(
+            "{source} may not have {process} send requests on exit: only {process} may"
+        ));
+    }
+    Ok(())
+}
 
 #[derive(Serialize, Deserialize)]
 struct StartProcessMetadata {
@@ -23,6 +89,8 @@ struct StartProcessMetadata {
     process_id: t::ProcessId,
     persisted: t::PersistedProcess,
     reboot: bool,
+    /// only ever set for transient processes, so never persisted
+    limits: t::ProcessLimits,
 }
 
 //  live in event loop
@@ -30,12 +98,25 @@ type Senders = HashMap<t::ProcessId, ProcessSender>;
 //  handles are for managing liveness, map is for persistence and metadata.
 type ProcessHandles = HashMap<t::ProcessId, JoinHandle<anyhow::Result<()>>>;
 
+#[derive(Clone)]
 enum ProcessSender {
     Runtime {
         sender: t::MessageSender,
         net_errors: Option<t::NetworkErrorSender>,
     },
     Userspace(t::ProcessMessageSender),
+    Pool(Arc<pool::Pool>),
+}
+
+impl ProcessSender {
+    /// the senders of every instance of a userspace process
+    fn instances(&self) -> &[t::ProcessMessageSender] {
+        match self {
+            ProcessSender::Userspace(sender) => std::slice::from_ref(sender),
+            ProcessSender::Pool(pool) => pool.senders(),
+            ProcessSender::Runtime { .. } => &[],
+        }
+    }
 }
 
 /// persist kernel's process_map state for next bootup
@@ -68,18 +149,47 @@ async fn persist_state(send_to_loop: &t::MessageSender, process_map: &t::Process
 async fn handle_kernel_request(
     our_name: &str,
     keypair: &Arc<ring::signature::Ed25519KeyPair>,
+    keyring: &Arc<crypto::Keyring>,
+    blob_store: &Arc<BlobStore>,
     km: t::KernelMessage,
     send_to_loop: &t::MessageSender,
     send_to_terminal: &t::PrintSender,
     senders: &mut Senders,
     process_handles: &mut ProcessHandles,
     process_map: &mut t::ProcessMap,
+    crash_subscribers: &mut HashSet<t::ProcessId>,
+    children: &mut HashMap<t::ProcessId, HashSet<t::ProcessId>>,
+    transient: &mut HashSet<t::ProcessId>,
+    dry_runs: &mut dry_run::DryRuns,
+    readiness: &mut readiness::Readiness,
+    bench: &mut bench::Bench,
+    pending: &mut pending::Pending,
+    public_methods: &mut public::PublicMethods,
+    cap_requests: &mut cap_requests::CapRequests,
+    post_mortems: &mut post_mortem::PostMortems,
+    transactions: &mut transactions::Transactions,
+    net_usage: &mut net_usage::NetUsage,
+    feature_flags: &Arc<flags::FeatureFlags>,
+    request_timeouts: process::RequestTimeouts,
     caps_oracle: &t::CapMessageSender,
     engine: &Engine,
+    script_limits: Option<&t::ProcessLimits>,
     home_directory_path: &str,
 ) -> Option<()> {
-    let t::Message::Request(request) = km.message else {
-        return None;
+    let request = match km.message {
+        t::Message::Request(ref request) => request,
+        t::Message::Response(_) => {
+            if km.source.process == *STATE_PROCESS_ID {
+                let mut km = km;
+                if let Err(e) = blob_store.resolve(&mut km).await {
+                    t::Printout::new(0, format!("kernel: couldn't read blob: {e}"))
+                        .send(send_to_terminal)
+                        .await;
+                }
+                post_mortems.state_arrived(&km, send_to_terminal);
+            }
+            return None;
+        }
     };
     let command: t::KernelCommand = match serde_json::from_slice(&request.body) {
         Err(e) => {
@@ -95,6 +205,8 @@ async fn handle_kernel_request(
             for handle in process_handles.values() {
                 handle.abort();
             }
+            // spawned children are started again by their parents on boot
+            process_map.retain(|id, _| !children.values().any(|c| c.contains(id)));
             Some(())
         }
         //
@@ -102,24 +214,13 @@ async fn handle_kernel_request(
         // now go ahead and actually start executing persisted userspace processes
         //
         t::KernelCommand::Booted => {
-            for (process_id, process_sender) in senders {
-                let ProcessSender::Userspace(sender) = process_sender else {
+            let process_ids: Vec<t::ProcessId> = senders.keys().cloned().collect();
+            for process_id in process_ids {
+                let depends_on = dependencies(home_directory_path, &process_id, senders).await;
+                if !readiness.hold(&process_id, depends_on, None).is_empty() {
                     continue;
-                };
-                sender
-                    .send(Ok(t::KernelMessage::builder()
-                        .id(km.id)
-                        .source((our_name, KERNEL_PROCESS_ID.clone()))
-                        .target((our_name, process_id.clone()))
-                        .message(t::Message::Request(t::Request {
-                            inherit: false,
-                            expects_response: None,
-                            body: b"run".to_vec(),
-                            metadata: None,
-                            capabilities: vec![],
-                        }))
-                        .build()
-                        .unwrap()))
+                }
+                run_process(our_name, &process_id, senders)
                     .await
                     .expect("fatal: kernel couldn't send run message to process");
             }
@@ -162,52 +263,17 @@ async fn handle_kernel_request(
                 return None;
             };
 
-            // check cap sigs & transform valid to unsigned to be plugged into procs
-            let parent_caps: &HashMap<t::Capability, Vec<u8>> =
-                &process_map.get(&km.source.process).unwrap().capabilities;
-            let mut valid_capabilities: HashMap<t::Capability, Vec<u8>> = HashMap::new();
-            if km.source.process == "kernel:distro:sys" {
-                for cap in initial_capabilities {
-                    let sig = keypair.sign(&rmp_serde::to_vec(&cap).unwrap());
-                    valid_capabilities.insert(cap, sig.as_ref().to_vec());
-                }
-            } else {
-                for cap in initial_capabilities {
-                    match parent_caps.get(&cap) {
-                        // NOTE: verifying sigs here would be unnecessary
-                        Some(sig) => {
-                            valid_capabilities.insert(cap, sig.to_vec());
-                        }
-                        None => {
-                            t::Printout::new(
-                                    0,
-                                    format!(
-                                        "kernel: InitializeProcess caller {} doesn't have capability {}",
-                                        km.source.process,
-                                        cap
-                                    )
-                                )
-                                .send(send_to_terminal)
-                                .await;
-                        }
-                    }
-                }
-            }
-            // give the initializer and itself the messaging cap.
-            // NOTE: we do this even if the process is public, because
-            // a process might redundantly call grant_capabilities.
-            let msg_cap = t::Capability::messaging((our_name, id.clone()));
-            let cap_sig = keypair.sign(&rmp_serde::to_vec(&msg_cap).unwrap());
-            valid_capabilities.insert(msg_cap.clone(), cap_sig.as_ref().to_vec());
-
-            caps_oracle
-                .send(t::CapMessage::Add {
-                    on: km.source.process.clone(),
-                    caps: vec![msg_cap],
-                    responder: None,
-                })
-                .await
-                .expect("event loop: fatal: sender died");
+            let valid_capabilities = initial_capabilities_for(
+                our_name,
+                keypair,
+                process_map,
+                &km.source.process,
+                &id,
+                initial_capabilities,
+                caps_oracle,
+                send_to_terminal,
+            )
+            .await;
 
             let start_process_metadata = StartProcessMetadata {
                 source: if let Some(ref rsvp) = km.rsvp {
@@ -224,15 +290,22 @@ async fn handle_kernel_request(
                     public,
                 },
                 reboot: false,
+                limits: t::ProcessLimits::default(),
             };
             let response = match start_process(
                 our_name,
                 keypair.clone(),
+                keyring,
+                blob_store,
                 blob.bytes,
                 send_to_loop,
                 send_to_terminal,
                 senders,
                 process_handles,
+                bench,
+                pending,
+                public_methods,
+                request_timeouts,
                 engine,
                 caps_oracle,
                 &start_process_metadata,
@@ -242,6 +315,11 @@ async fn handle_kernel_request(
             {
                 Ok(()) => {
                     let on_exit_none = start_process_metadata.persisted.on_exit.is_none();
+                    if on_exit_none {
+                        transient.insert(start_process_metadata.process_id.clone());
+                    } else {
+                        transient.remove(&start_process_metadata.process_id);
+                    }
                     process_map.insert(
                         start_process_metadata.process_id,
                         start_process_metadata.persisted,
@@ -278,6 +356,198 @@ async fn handle_kernel_request(
                 .await;
             None
         }
+        //
+        // initialize and run a transient process, such as a script, in one step
+        //
+        t::KernelCommand::RunTransient {
+            id,
+            wasm_bytes_handle,
+            wit_version,
+            initial_capabilities,
+            public,
+            args,
+            capture_output,
+            dry_run,
+            limits,
+        } => {
+            // the node's limits hold every script, and its own may only
+            // tighten them. without any, scripts run unlimited, as other
+            // processes do.
+            let limits =
+                script_limits.map_or_else(t::ProcessLimits::default, |node| node.tightest(&limits));
+            // the process answers in our place if asked to
+            let capture_output = capture_output && request.expects_response.is_some();
+            let response = 'run: {
+                if wasm_bytes_handle.split('/').any(|part| part == "..") {
+                    t::Printout::new(
+                        0,
+                        format!("kernel: invalid wasm path {wasm_bytes_handle} for {id}"),
+                    )
+                    .send(send_to_terminal)
+                    .await;
+                    break 'run t::KernelResponse::RunProcessError;
+                }
+                // the kernel reads the wasm in the caller's place, so the
+                // caller must be able to read it from the VFS itself
+                if !may_read_wasm(
+                    our_name,
+                    process_map,
+                    &km.source.process,
+                    &wasm_bytes_handle,
+                ) {
+                    t::Printout::new(
+                        0,
+                        format!(
+                            "kernel: {} lacks the capability to read {wasm_bytes_handle}",
+                            km.source.process
+                        ),
+                    )
+                    .send(send_to_terminal)
+                    .await;
+                    break 'run t::KernelResponse::RunProcessError;
+                }
+                // a transient process never takes the place of another
+                if process_map.contains_key(&id) {
+                    t::Printout::new(0, format!("kernel: {id} is already running"))
+                        .send(send_to_terminal)
+                        .await;
+                    break 'run t::KernelResponse::RunProcessError;
+                }
+                let wasm_bytes =
+                    match tokio::fs::read(format!("{home_directory_path}/vfs/{wasm_bytes_handle}"))
+                        .await
+                    {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            t::Printout::new(
+                                0,
+                                format!("kernel: couldn't read wasm bytes for {id}: {e}"),
+                            )
+                            .send(send_to_terminal)
+                            .await;
+                            break 'run t::KernelResponse::RunProcessError;
+                        }
+                    };
+                // whatever a dry run runs is a dry run too
+                let dry_run = dry_run || dry_runs.contains(&km.source.process);
+                let mut capabilities = initial_capabilities_for(
+                    our_name,
+                    keypair,
+                    process_map,
+                    &km.source.process,
+                    &id,
+                    initial_capabilities,
+                    caps_oracle,
+                    send_to_terminal,
+                )
+                .await;
+                if dry_run {
+                    // its writes to a mounted directory would go unseen
+                    capabilities.retain(|cap, _| {
+                        cap.issuer.process != *KERNEL_PROCESS_ID
+                            || cap.params != process::MOUNT_WRITE_CAP_PARAMS
+                    });
+                }
+                let start_process_metadata = StartProcessMetadata {
+                    source: km.rsvp.clone().unwrap_or(km.source.clone()),
+                    process_id: id.clone(),
+                    persisted: t::PersistedProcess {
+                        wasm_bytes_handle,
+                        wit_version,
+                        on_exit: t::OnExit::None,
+                        capabilities,
+                        public,
+                    },
+                    reboot: false,
+                    limits,
+                };
+                if let Err(e) = start_process(
+                    our_name,
+                    keypair.clone(),
+                    keyring,
+                    blob_store,
+                    wasm_bytes,
+                    send_to_loop,
+                    send_to_terminal,
+                    senders,
+                    process_handles,
+                    bench,
+                    pending,
+                    public_methods,
+                    request_timeouts,
+                    engine,
+                    caps_oracle,
+                    &start_process_metadata,
+                    &home_directory_path,
+                )
+                .await
+                {
+                    t::Printout::new(0, format!("kernel: error starting {id}: {e:?}"))
+                        .send(send_to_terminal)
+                        .await;
+                    break 'run t::KernelResponse::RunProcessError;
+                }
+                // never persisted: it exits for good when it ends
+                transient.insert(id.clone());
+                process_map.insert(id.clone(), start_process_metadata.persisted);
+                if dry_run {
+                    dry_runs.start(id.clone());
+                }
+                if run_process(our_name, &id, senders).await.is_err() {
+                    break 'run t::KernelResponse::RunProcessError;
+                }
+                // queued behind "run", so the arguments are the first message it
+                // gets. when capturing output, they carry this message's ID, so
+                // that the process's response answers it.
+                let args = t::KernelMessage::builder()
+                    .id(if capture_output {
+                        km.id
+                    } else {
+                        rand::random()
+                    })
+                    .source(km.source.clone())
+                    .target((our_name, id.clone()))
+                    .rsvp(km.rsvp.clone())
+                    .message(t::Message::Request(t::Request {
+                        inherit: false,
+                        expects_response: request.expects_response.filter(|_| capture_output),
+                        body: args,
+                        metadata: None,
+                        capabilities: vec![],
+                    }))
+                    .lazy_load_blob(km.lazy_load_blob)
+                    .blob_handle(km.blob_handle)
+                    .build()
+                    .unwrap();
+                match senders[&id].instances().first() {
+                    Some(sender) if sender.send(Ok(args)).await.is_ok() => {
+                        if capture_output {
+                            return None;
+                        }
+                        t::KernelResponse::StartedProcess
+                    }
+                    _ => t::KernelResponse::RunProcessError,
+                }
+            };
+            t::KernelMessage::builder()
+                .id(km.id)
+                .source(("our", KERNEL_PROCESS_ID.clone()))
+                .target(km.rsvp.unwrap_or(km.source))
+                .message(t::Message::Response((
+                    t::Response {
+                        inherit: false,
+                        body: serde_json::to_vec(&response).unwrap(),
+                        metadata: None,
+                        capabilities: vec![],
+                    },
+                    None,
+                )))
+                .build()
+                .unwrap()
+                .send(send_to_loop)
+                .await;
+            None
+        }
         t::KernelCommand::GrantCapabilities {
             target,
             capabilities,
@@ -310,51 +580,46 @@ async fn handle_kernel_request(
         // send 'run' message to a process that's already been initialized
         //
         t::KernelCommand::RunProcess(process_id) => {
-            let response =
-                if let Some(ProcessSender::Userspace(process_sender)) = senders.get(&process_id) {
-                    if let Ok(()) = process_sender
-                        .send(Ok(t::KernelMessage::builder()
-                            .id(rand::random())
-                            .source((our_name, KERNEL_PROCESS_ID.clone()))
-                            .target((our_name, process_id))
-                            .message(t::Message::Request(t::Request {
-                                inherit: false,
-                                expects_response: None,
-                                body: b"run".to_vec(),
-                                metadata: None,
-                                capabilities: vec![],
-                            }))
-                            .build()
-                            .unwrap()))
-                        .await
-                    {
-                        t::KernelResponse::StartedProcess
-                    } else {
-                        t::KernelResponse::RunProcessError
-                    }
-                } else {
-                    t::Printout::new(0, format!("kernel: no such process {process_id} to run"))
-                        .send(send_to_terminal)
-                        .await;
-                    t::KernelResponse::RunProcessError
-                };
-            t::KernelMessage::builder()
-                .id(km.id)
-                .source(("our", KERNEL_PROCESS_ID.clone()))
-                .target(km.rsvp.unwrap_or(km.source))
-                .message(t::Message::Response((
-                    t::Response {
-                        inherit: false,
-                        body: serde_json::to_vec(&response).unwrap(),
-                        metadata: None,
-                        capabilities: vec![],
-                    },
-                    None,
-                )))
-                .build()
-                .unwrap()
-                .send(send_to_loop)
+            let reply = (km.id, km.rsvp.unwrap_or(km.source));
+            if senders
+                .get(&process_id)
+                .map_or(true, |sender| sender.instances().is_empty())
+            {
+                t::Printout::new(0, format!("kernel: no such process {process_id} to run"))
+                    .send(send_to_terminal)
+                    .await;
+                answer_run_process(
+                    our_name,
+                    vec![reply],
+                    t::KernelResponse::RunProcessError,
+                    send_to_loop,
+                )
                 .await;
+                return None;
+            }
+            let depends_on = dependencies(home_directory_path, &process_id, senders).await;
+            let pending = readiness.hold(&process_id, depends_on, Some(reply.clone()));
+            if !pending.is_empty() {
+                // it will be run, and the request answered, once they are ready
+                t::Printout::new(
+                    2,
+                    format!("kernel: {process_id} will run once {pending:?} are ready"),
+                )
+                .send(send_to_terminal)
+                .await;
+                return None;
+            }
+            run_runnable(
+                our_name,
+                readiness::Runnable {
+                    process: process_id,
+                    replies: vec![reply],
+                },
+                senders,
+                send_to_loop,
+                send_to_terminal,
+            )
+            .await;
             None
         }
         //
@@ -365,18 +630,83 @@ async fn handle_kernel_request(
         // skip the capabilities-cleanup RevokeAll, pass "no-revoke" in the metadata
         //
         t::KernelCommand::KillProcess(process_id) => {
-            let process_handle = match process_handles.remove(&process_id) {
-                Some(ph) => ph,
-                None => {
-                    t::Printout::new(2, format!("kernel: no such process {process_id} to kill"))
-                        .send(send_to_terminal)
-                        .await;
-                    return None;
+            // a process killed while held back will not be run
+            let unanswered = readiness.forget(&process_id);
+            answer_run_process(
+                our_name,
+                unanswered,
+                t::KernelResponse::RunProcessError,
+                send_to_loop,
+            )
+            .await;
+            if !stop_process(
+                &process_id,
+                senders,
+                process_handles,
+                process_map,
+                crash_subscribers,
+                readiness,
+                bench,
+                pending,
+                public_methods,
+                net_usage,
+            ) {
+                t::Printout::new(2, format!("kernel: no such process {process_id} to kill"))
+                    .send(send_to_terminal)
+                    .await;
+                return None;
+            }
+            if let Some(attempted) = dry_runs.finish(&process_id) {
+                t::Printout::new(
+                    0,
+                    format!("dry run: {process_id} ended, having attempted {attempted} requests"),
+                )
+                .send(send_to_terminal)
+                .await;
+            }
+            // take any children spawned by this process, and theirs, down
+            // with it. this is done here, not with a KillProcess each, so that
+            // a parent being restarted, as this kill is the first step of,
+            // can't spawn a child again before the old one is gone, or have
+            // the new one killed in its place.
+            let mut orphans: Vec<t::ProcessId> = children
+                .remove(&process_id)
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            while let Some(child) = orphans.pop() {
+                orphans.extend(children.remove(&child).unwrap_or_default());
+                if !stop_process(
+                    &child,
+                    senders,
+                    process_handles,
+                    process_map,
+                    crash_subscribers,
+                    readiness,
+                    bench,
+                    pending,
+                    public_methods,
+                    net_usage,
+                ) {
+                    continue;
                 }
-            };
-            senders.remove(&process_id);
-            process_handle.abort();
-            process_map.remove(&process_id);
+                caps_oracle
+                    .send(t::CapMessage::RevokeAll {
+                        on: child.clone(),
+                        responder: None,
+                    })
+                    .await
+                    .expect("event loop: fatal: sender died");
+                t::Printout::new(
+                    2,
+                    format!("kernel: killing process {child}, spawned by {process_id}"),
+                )
+                .send(send_to_terminal)
+                .await;
+            }
+            for siblings in children.values_mut() {
+                siblings.remove(&process_id);
+            }
             if request.metadata != Some("no-revoke".to_string()) {
                 caps_oracle
                     .send(t::CapMessage::RevokeAll {
@@ -416,6 +746,7 @@ async fn handle_kernel_request(
             None
         }
         t::KernelCommand::Debug(kind) => {
+            let mut blob = None;
             let response = match kind {
                 t::KernelPrint::ProcessMap => t::KernelPrintResponse::ProcessMap(
                     process_map
@@ -432,6 +763,101 @@ async fn handle_kernel_request(
                         .get(&on)
                         .map(|p| p.capabilities.contains_key(&cap)),
                 ),
+                t::KernelPrint::ProcessStats(process_id) => {
+                    t::KernelPrintResponse::ProcessStats(bench.stats(&process_id))
+                }
+                t::KernelPrint::Pending(process_id) => {
+                    let allowed = km.source.process == *KERNEL_PROCESS_ID
+                        || process_map.get(&km.source.process).is_some_and(|p| {
+                            p.capabilities.contains_key(&pending::capability(our_name))
+                        });
+                    if allowed {
+                        let queued = senders.get(&process_id).map_or(0, |sender| {
+                            sender
+                                .instances()
+                                .iter()
+                                .map(|instance| instance.max_capacity() - instance.capacity())
+                                .sum()
+                        });
+                        t::KernelPrintResponse::Pending(pending.inspect(&process_id, queued))
+                    } else {
+                        t::Printout::new(
+                            0,
+                            format!(
+                                "kernel: {} lacks the capability to inspect pending requests",
+                                km.source.process
+                            ),
+                        )
+                        .send(send_to_terminal)
+                        .await;
+                        t::KernelPrintResponse::Pending(None)
+                    }
+                }
+                t::KernelPrint::CapCache => {
+                    t::KernelPrintResponse::CapCache(keyring.cap_cache_stats())
+                }
+                t::KernelPrint::PostMortems(process_id) => {
+                    if post_mortem::may_read(our_name, process_map, &km.source.process) {
+                        t::KernelPrintResponse::PostMortems(
+                            post_mortem::list(home_directory_path, &process_id).await,
+                        )
+                    } else {
+                        t::Printout::new(
+                            0,
+                            format!(
+                                "kernel: {} lacks the capability to read post-mortems",
+                                km.source.process
+                            ),
+                        )
+                        .send(send_to_terminal)
+                        .await;
+                        t::KernelPrintResponse::PostMortems(vec![])
+                    }
+                }
+                t::KernelPrint::PostMortemState { process, id } => {
+                    if post_mortem::may_read(our_name, process_map, &km.source.process) {
+                        blob = post_mortem::state(home_directory_path, &process, id)
+                            .await
+                            .map(|bytes| t::LazyLoadBlob { mime: None, bytes });
+                    } else {
+                        t::Printout::new(
+                            0,
+                            format!(
+                                "kernel: {} lacks the capability to fetch post-mortem state",
+                                km.source.process
+                            ),
+                        )
+                        .send(send_to_terminal)
+                        .await;
+                    }
+                    t::KernelPrintResponse::PostMortemState(blob.is_some())
+                }
+                t::KernelPrint::NetworkUsage => {
+                    let allowed = km.source.process == *KERNEL_PROCESS_ID
+                        || process_map.get(&km.source.process).is_some_and(|p| {
+                            p.capabilities.contains_key(&t::Capability::new(
+                                (our_name, KERNEL_PROCESS_ID.clone()),
+                                net_usage::NETWORK_USAGE_CAP_PARAMS,
+                            ))
+                        });
+                    if allowed {
+                        t::KernelPrintResponse::NetworkUsage(net_usage.list())
+                    } else {
+                        t::Printout::new(
+                            0,
+                            format!(
+                                "kernel: {} lacks the capability to read network usage",
+                                km.source.process
+                            ),
+                        )
+                        .send(send_to_terminal)
+                        .await;
+                        t::KernelPrintResponse::NetworkUsage(vec![])
+                    }
+                }
+                t::KernelPrint::Latency => {
+                    t::KernelPrintResponse::Latency(crate::metrics::latencies())
+                }
             };
             t::KernelMessage::builder()
                 .id(km.id)
@@ -446,12 +872,832 @@ async fn handle_kernel_request(
                     },
                     None,
                 )))
+                .lazy_load_blob(blob)
+                .build()
+                .unwrap()
+                .send(send_to_loop)
+                .await;
+            None
+        }
+        t::KernelCommand::SubscribeToCrashes => {
+            crash_subscribers.insert(km.source.process);
+            None
+        }
+        //
+        // sent from a process loop to kernel: a process has crashed
+        //
+        t::KernelCommand::ProcessCrashed { id, error } => {
+            if km.source.process != *KERNEL_PROCESS_ID {
+                t::Printout::new(
+                    0,
+                    format!(
+                        "kernel: got ProcessCrashed from non-kernel source {}",
+                        km.source
+                    ),
+                )
+                .send(send_to_terminal)
+                .await;
+                return None;
+            }
+            // the crashed instance reads no more messages, so until it is
+            // killed or restarted, which may wait out a backoff, messages to it
+            // fail as if it didn't exist rather than being lost in its queue.
+            // a pool keeps its other instances.
+            if let Some(ProcessSender::Userspace(_)) = senders.get(&id) {
+                senders.remove(&id);
+            }
+            post_mortems.crashed(
+                our_name,
+                home_directory_path,
+                &id,
+                &error,
+                send_to_loop,
+                send_to_terminal,
+            );
+            let body = serde_json::to_vec(&t::ProcessCrash { process: id, error }).unwrap();
+            for subscriber in crash_subscribers.iter() {
+                t::KernelMessage::builder()
+                    .id(rand::random())
+                    .source((our_name, KERNEL_PROCESS_ID.clone()))
+                    .target((our_name, subscriber.clone()))
+                    .message(t::Message::Request(t::Request {
+                        inherit: false,
+                        expects_response: None,
+                        body: body.clone(),
+                        metadata: None,
+                        capabilities: vec![],
+                    }))
+                    .build()
+                    .unwrap()
+                    .send(send_to_loop)
+                    .await;
+            }
+            None
+        }
+        //
+        // sent from a process's spawn call to kernel: tie a child to its parent
+        //
+        t::KernelCommand::AdoptChild { parent, child } => {
+            if km.source.process != *KERNEL_PROCESS_ID {
+                t::Printout::new(
+                    0,
+                    format!(
+                        "kernel: got AdoptChild from non-kernel source {}",
+                        km.source
+                    ),
+                )
+                .send(send_to_terminal)
+                .await;
+                return None;
+            }
+            children.entry(parent).or_default().insert(child);
+            None
+        }
+        //
+        // sent from a process's `ready` or first `receive` call to kernel:
+        // run the processes that were waiting on it
+        //
+        t::KernelCommand::ProcessReady(process_id) => {
+            if km.source.process != *KERNEL_PROCESS_ID {
+                t::Printout::new(
+                    0,
+                    format!(
+                        "kernel: got ProcessReady from non-kernel source {}",
+                        km.source
+                    ),
+                )
+                .send(send_to_terminal)
+                .await;
+                return None;
+            }
+            for runnable in readiness.mark_ready(process_id) {
+                run_runnable(our_name, runnable, senders, send_to_loop, send_to_terminal).await;
+            }
+            None
+        }
+        //
+        // sent from a process loop to kernel: a process has ended, and must
+        // report ready again before those that depend on it are run
+        //
+        t::KernelCommand::ProcessExited(process_id) => {
+            if km.source.process != *KERNEL_PROCESS_ID {
+                t::Printout::new(
+                    0,
+                    format!(
+                        "kernel: got ProcessExited from non-kernel source {}",
+                        km.source
+                    ),
+                )
+                .send(send_to_terminal)
+                .await;
+                return None;
+            }
+            readiness.exited(&process_id);
+            crate::socket::process_exited(&process_id);
+            crate::vfs::process_exited(&process_id);
+            None
+        }
+        t::KernelCommand::RecordWorkload(process_id) => {
+            t::Printout::new(0, format!("kernel: recording requests to {process_id}"))
+                .send(send_to_terminal)
+                .await;
+            bench.start_recording(process_id);
+            None
+        }
+        t::KernelCommand::StopRecording(process_id) => {
+            let workload = bench.stop_recording(&process_id);
+            t::Printout::new(
+                0,
+                format!(
+                    "kernel: recorded {} requests to {process_id}",
+                    workload.len()
+                ),
+            )
+            .send(send_to_terminal)
+            .await;
+            if request.expects_response.is_none() {
+                return None;
+            }
+            t::KernelMessage::builder()
+                .id(km.id)
+                .source(("our", KERNEL_PROCESS_ID.clone()))
+                .target(km.rsvp.unwrap_or(km.source))
+                .message(t::Message::Response((
+                    t::Response {
+                        inherit: false,
+                        body: serde_json::to_vec(&t::KernelResponse::Workload(workload)).unwrap(),
+                        metadata: None,
+                        capabilities: vec![],
+                    },
+                    None,
+                )))
+                .build()
+                .unwrap()
+                .send(send_to_loop)
+                .await;
+            None
+        }
+        t::KernelCommand::CapRequests(action) => {
+            let allowed = km.source.process == *KERNEL_PROCESS_ID
+                || process_map.get(&km.source.process).is_some_and(|p| {
+                    p.capabilities.contains_key(&t::Capability::new(
+                        (our_name, KERNEL_PROCESS_ID.clone()),
+                        cap_requests::CAP_REQUESTS_CAP_PARAMS,
+                    ))
+                });
+            let response = if !allowed {
+                t::KernelResponse::CapRequestError(format!(
+                    "{} lacks the capability to handle capability requests",
+                    km.source.process
+                ))
+            } else {
+                let answered = match action {
+                    t::CapRequestAction::List => true,
+                    t::CapRequestAction::Approve(id) => {
+                        cap_requests
+                            .answer(our_name, keypair, id, true, send_to_loop)
+                            .await
+                    }
+                    t::CapRequestAction::Deny(id) => {
+                        cap_requests
+                            .answer(our_name, keypair, id, false, send_to_loop)
+                            .await
+                    }
+                };
+                if answered {
+                    t::KernelResponse::CapRequests(cap_requests.list())
+                } else {
+                    t::KernelResponse::CapRequestError("no such capability request".into())
+                }
+            };
+            if request.expects_response.is_none() {
+                return None;
+            }
+            t::KernelMessage::builder()
+                .id(km.id)
+                .source(("our", KERNEL_PROCESS_ID.clone()))
+                .target(km.rsvp.unwrap_or(km.source))
+                .message(t::Message::Response((
+                    t::Response {
+                        inherit: false,
+                        body: serde_json::to_vec(&response).unwrap(),
+                        metadata: None,
+                        capabilities: vec![],
+                    },
+                    None,
+                )))
+                .build()
+                .unwrap()
+                .send(send_to_loop)
+                .await;
+            None
+        }
+        t::KernelCommand::PackageTransaction {
+            id,
+            package_id,
+            step,
+        } => {
+            let response = match step {
+                t::TransactionStep::Prepare => {
+                    transactions.prepare(id, &package_id, process_map);
+                    Ok(())
+                }
+                t::TransactionStep::Commit => {
+                    transactions.end(id);
+                    Ok(())
+                }
+                t::TransactionStep::Abort => match transactions.end(id) {
+                    None => Ok(()),
+                    Some(prepared) => {
+                        // stop whatever the package runs now, children included.
+                        // the capabilities its processes issued are put back
+                        // as they were below, so they are not revoked here.
+                        let running: Vec<t::ProcessId> = process_handles
+                            .keys()
+                            .filter(|process_id| transactions::in_package(process_id, &package_id))
+                            .cloned()
+                            .collect();
+                        for process_id in &running {
+                            stop_process(
+                                process_id,
+                                senders,
+                                process_handles,
+                                process_map,
+                                crash_subscribers,
+                                readiness,
+                                bench,
+                                pending,
+                                public_methods,
+                                net_usage,
+                            );
+                        }
+                        children.retain(|parent, _| !transactions::in_package(parent, &package_id));
+                        for siblings in children.values_mut() {
+                            siblings.retain(|child| !transactions::in_package(child, &package_id));
+                        }
+
+                        // restart the processes it had, with the wasm the VFS
+                        // has put back, and the capabilities they held
+                        let mut failed = vec![];
+                        let mut restarted = vec![];
+                        for (process_id, persisted) in prepared.processes {
+                            let path = format!(
+                                "{home_directory_path}/vfs/{}",
+                                persisted.wasm_bytes_handle
+                            );
+                            let wasm_bytes = match tokio::fs::read(&path).await {
+                                Ok(bytes) => bytes,
+                                Err(e) => {
+                                    failed.push(format!("{process_id}: {e}"));
+                                    continue;
+                                }
+                            };
+                            let start_process_metadata = StartProcessMetadata {
+                                source: t::Address {
+                                    node: our_name.to_string(),
+                                    process: KERNEL_PROCESS_ID.clone(),
+                                },
+                                process_id,
+                                persisted,
+                                reboot: true,
+                                limits: t::ProcessLimits::default(),
+                            };
+                            if let Err(e) = start_process(
+                                our_name,
+                                keypair.clone(),
+                                keyring,
+                                blob_store,
+                                wasm_bytes,
+                                send_to_loop,
+                                send_to_terminal,
+                                senders,
+                                process_handles,
+                                bench,
+                                pending,
+                                public_methods,
+                                request_timeouts,
+                                engine,
+                                caps_oracle,
+                                &start_process_metadata,
+                                home_directory_path,
+                            )
+                            .await
+                            {
+                                failed.push(format!("{}: {e}", start_process_metadata.process_id));
+                                continue;
+                            }
+                            restarted.push(start_process_metadata.process_id.clone());
+                            process_map.insert(
+                                start_process_metadata.process_id,
+                                start_process_metadata.persisted,
+                            );
+                        }
+                        for process_id in &restarted {
+                            let depends_on =
+                                dependencies(home_directory_path, process_id, senders).await;
+                            if readiness.hold(process_id, depends_on, None).is_empty() {
+                                let _ = run_process(our_name, process_id, senders).await;
+                            }
+                        }
+
+                        // and put back the capabilities they had issued to others
+                        let before = prepared.issued;
+                        let now = transactions::issued_by(&package_id, process_map);
+                        let holders: HashSet<&t::ProcessId> =
+                            before.keys().chain(now.keys()).collect();
+                        let none = HashSet::new();
+                        for holder in holders {
+                            let had = before.get(holder).unwrap_or(&none);
+                            let has = now.get(holder).unwrap_or(&none);
+                            let drop: Vec<t::Capability> = has.difference(had).cloned().collect();
+                            let add: Vec<t::Capability> = had.difference(has).cloned().collect();
+                            if !drop.is_empty() {
+                                caps_oracle
+                                    .send(t::CapMessage::Drop {
+                                        on: holder.clone(),
+                                        caps: drop,
+                                        responder: None,
+                                    })
+                                    .await
+                                    .expect("event loop: fatal: sender died");
+                            }
+                            if !add.is_empty() {
+                                caps_oracle
+                                    .send(t::CapMessage::Add {
+                                        on: holder.clone(),
+                                        caps: add,
+                                        responder: None,
+                                    })
+                                    .await
+                                    .expect("event loop: fatal: sender died");
+                            }
+                        }
+                        persist_state(send_to_loop, process_map).await;
+                        t::Printout::new(
+                            0,
+                            format!("kernel: restored the processes of {package_id}"),
+                        )
+                        .send(send_to_terminal)
+                        .await;
+                        if failed.is_empty() {
+                            Ok(())
+                        } else {
+                            Err(failed.join(", "))
+                        }
+                    }
+                },
+            };
+            if request.expects_response.is_none() {
+                return None;
+            }
+            t::KernelMessage::builder()
+                .id(km.id)
+                .source(("our", KERNEL_PROCESS_ID.clone()))
+                .target(km.rsvp.unwrap_or(km.source))
+                .message(t::Message::Response((
+                    t::Response {
+                        inherit: false,
+                        body: serde_json::to_vec(&t::KernelResponse::PackageTransaction(response))
+                            .unwrap(),
+                        metadata: None,
+                        capabilities: vec![],
+                    },
+                    None,
+                )))
+                .build()
+                .unwrap()
+                .send(send_to_loop)
+                .await;
+            None
+        }
+        t::KernelCommand::SetOnExit { process, on_exit } => {
+            let has_cap = process_map.get(&km.source.process).is_some_and(|p| {
+                p.capabilities.contains_key(&t::Capability::new(
+                    (our_name, KERNEL_PROCESS_ID.clone()),
+                    ON_EXIT_CAP_PARAMS,
+                ))
+            });
+            let allowed = may_set_on_exit(&km.source.process, &process, &on_exit, has_cap);
+            let response = if allowed.is_err() {
+                allowed
+            } else if let Some(persisted) = process_map.get_mut(&process) {
+                t::Printout::new(
+                    0,
+                    format!("kernel: process {process} now has OnExit behavior {on_exit}"),
+                )
+                .send(send_to_terminal)
+                .await;
+                // a transient process stays so until given something to do on exit
+                if !on_exit.is_none() {
+                    transient.remove(&process);
+                }
+                // the running process is told too, unless it exits first
+                if process_handles.contains_key(&process) {
+                    process::set_on_exit(&process, on_exit.clone());
+                }
+                persisted.on_exit = on_exit;
+                persist_state(send_to_loop, process_map).await;
+                Ok(())
+            } else {
+                Err(format!("no such process {process}"))
+            };
+            if request.expects_response.is_none() {
+                return None;
+            }
+            t::KernelMessage::builder()
+                .id(km.id)
+                .source(("our", KERNEL_PROCESS_ID.clone()))
+                .target(km.rsvp.unwrap_or(km.source))
+                .message(t::Message::Response((
+                    t::Response {
+                        inherit: false,
+                        body: serde_json::to_vec(&t::KernelResponse::SetOnExit(response)).unwrap(),
+                        metadata: None,
+                        capabilities: vec![],
+                    },
+                    None,
+                )))
+                .build()
+                .unwrap()
+                .send(send_to_loop)
+                .await;
+            None
+        }
+        t::KernelCommand::FeatureFlags(action) => {
+            let allowed = km.source.process == *KERNEL_PROCESS_ID
+                || process_map.get(&km.source.process).is_some_and(|p| {
+                    p.capabilities.contains_key(&t::Capability::new(
+                        (our_name, KERNEL_PROCESS_ID.clone()),
+                        flags::FEATURE_FLAGS_CAP_PARAMS,
+                    ))
+                });
+            let expects_response = request.expects_response.is_some();
+            let feature_flags = feature_flags.clone();
+            let send_to_loop = send_to_loop.clone();
+            // setting a flag writes it to disk, which the kernel loop
+            // shouldn't wait on
+            tokio::spawn(async move {
+                let response = if !allowed {
+                    Err(format!(
+                        "{} lacks the capability to use feature flags",
+                        km.source.process
+                    ))
+                } else {
+                    let set = match action {
+                        t::FeatureFlagAction::List => Ok(()),
+                        t::FeatureFlagAction::Set { flag, enabled } => {
+                            feature_flags.set(&flag, Some(enabled)).await
+                        }
+                        t::FeatureFlagAction::Reset(flag) => feature_flags.set(&flag, None).await,
+                    };
+                    set.map(|()| feature_flags.list())
+                };
+                if !expects_response {
+                    return;
+                }
+                let body = serde_json::to_vec(&t::KernelResponse::FeatureFlags(response)).unwrap();
+                t::KernelMessage::builder()
+                    .id(km.id)
+                    .source(("our", KERNEL_PROCESS_ID.clone()))
+                    .target(km.rsvp.unwrap_or(km.source))
+                    .message(t::Message::Response((
+                        t::Response {
+                            inherit: false,
+                            body,
+                            metadata: None,
+                            capabilities: vec![],
+                        },
+                        None,
+                    )))
+                    .build()
+                    .unwrap()
+                    .send(&send_to_loop)
+                    .await;
+            });
+            None
+        }
+        t::KernelCommand::ReadSecret { prompt } => {
+            if km.source.process.package() != "terminal" || km.source.process.publisher() != "sys" {
+                t::Printout::new(
+                    0,
+                    format!(
+                        "kernel: {} may not read a secret line at the terminal",
+                        km.source.process
+                    ),
+                )
+                .send(send_to_terminal)
+                .await;
+                return None;
+            }
+            let ask = crate::terminal::secret::Ask {
+                requester: km.source,
+                prompt,
+            };
+            if let Err(ask) = crate::terminal::secret::ask(ask) {
+                crate::terminal::secret::answer(our_name, ask.requester, None, send_to_loop).await;
+            }
+            None
+        }
+    }
+}
+
+/// abort a process's task and forget it. returns `false` if it wasn't running.
+fn stop_process(
+    process_id: &t::ProcessId,
+    senders: &mut Senders,
+    process_handles: &mut ProcessHandles,
+    process_map: &mut t::ProcessMap,
+    crash_subscribers: &mut HashSet<t::ProcessId>,
+    readiness: &mut readiness::Readiness,
+    bench: &mut bench::Bench,
+    pending: &mut pending::Pending,
+    public_methods: &mut public::PublicMethods,
+    net_usage: &mut net_usage::NetUsage,
+) -> bool {
+    let Some(process_handle) = process_handles.remove(process_id) else {
+        return false;
+    };
+    senders.remove(process_id);
+    process_handle.abort();
+    process_map.remove(process_id);
+    process::forget_on_exit(process_id);
+    crash_subscribers.remove(process_id);
+    readiness.forget(process_id);
+    bench.remove(process_id);
+    pending.remove(process_id);
+    public_methods.remove(process_id);
+    net_usage.remove(process_id);
+    crate::socket::process_exited(process_id);
+    crate::vfs::process_exited(process_id);
+    true
+}
+
+/// a process's entry in its package's manifest. processes spawned by another
+/// process have none.
+async fn manifest_entry(
+    home_directory_path: &str,
+    process: &t::ProcessId,
+) -> Option<t::PackageManifestEntry> {
+    let manifest_path = format!(
+        "{home_directory_path}/vfs/{}:{}/pkg/manifest.json",
+        process.package(),
+        process.publisher()
+    );
+    let manifest = tokio::fs::read(manifest_path).await.ok()?;
+    serde_json::from_slice::<Vec<t::PackageManifestEntry>>(&manifest)
+        .ok()?
+        .into_iter()
+        .find(|entry| entry.process_name == process.process())
+}
+
+/// the order to start persisted processes in at boot: each after the processes
+/// its manifest entry lists in `depends_on`, where it can be. a cycle or an
+/// unknown process in those lists loses only that ordering, never a process.
+/// runtime extensions are left out, since they have no wasm to run.
+async fn boot_order(home_directory_path: &str, process_map: &t::ProcessMap) -> Vec<t::ProcessId> {
+    let ids: Vec<&t::ProcessId> = process_map
+        .iter()
+        .filter(|(_, persisted)| !persisted.wasm_bytes_handle.is_empty())
+        .map(|(id, _)| id)
+        .collect();
+    let entries =
+        futures::future::join_all(ids.iter().map(|id| manifest_entry(home_directory_path, id)))
+            .await;
+    let depends_on: HashMap<&t::ProcessId, Vec<t::ProcessId>> = ids
+        .iter()
+        .zip(entries)
+        .map(|(id, entry)| {
+            let dependencies = entry
+                .map(|entry| entry.depends_on)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|dependency| dependency.parse().ok())
+                .collect();
+            (*id, dependencies)
+        })
+        .collect();
+
+    fn visit<'a>(
+        id: &'a t::ProcessId,
+        depends_on: &'a HashMap<&t::ProcessId, Vec<t::ProcessId>>,
+        visiting: &mut HashSet<&'a t::ProcessId>,
+        order: &mut Vec<t::ProcessId>,
+    ) {
+        // already placed, or a cycle
+        if !visiting.insert(id) {
+            return;
+        }
+        for dependency in depends_on.get(id).into_iter().flatten() {
+            if let Some((dependency, _)) = depends_on.get_key_value(dependency) {
+                visit(dependency, depends_on, visiting, order);
+            }
+        }
+        order.push(id.clone());
+    }
+
+    let mut visiting = HashSet::new();
+    let mut order = Vec::with_capacity(ids.len());
+    for id in ids {
+        visit(id, &depends_on, &mut visiting, &mut order);
+    }
+    order
+}
+
+/// check the capabilities `source` asks to give a new process `id`, and sign
+/// them: the kernel may give any, and other processes only those they hold.
+/// both `source` and the new process get the capability to message it, even
+/// if it is public, because a process might redundantly call grant_capabilities.
+async fn initial_capabilities_for(
+    our_name: &str,
+    keypair: &Arc<ring::signature::Ed25519KeyPair>,
+    process_map: &t::ProcessMap,
+    source: &t::ProcessId,
+    id: &t::ProcessId,
+    initial_capabilities: HashSet<t::Capability>,
+    caps_oracle: &t::CapMessageSender,
+    send_to_terminal: &t::PrintSender,
+) -> HashMap<t::Capability, Vec<u8>> {
+    // check cap sigs & transform valid to unsigned to be plugged into procs
+    let mut valid_capabilities: HashMap<t::Capability, Vec<u8>> = HashMap::new();
+    if *source == *KERNEL_PROCESS_ID {
+        for cap in initial_capabilities {
+            let sig = keypair.sign(&rmp_serde::to_vec(&cap).unwrap());
+            valid_capabilities.insert(cap, sig.as_ref().to_vec());
+        }
+    } else {
+        let parent_caps: &HashMap<t::Capability, Vec<u8>> =
+            &process_map.get(source).unwrap().capabilities;
+        for cap in initial_capabilities {
+            match parent_caps.get(&cap) {
+                // NOTE: verifying sigs here would be unnecessary
+                Some(sig) => {
+                    valid_capabilities.insert(cap, sig.to_vec());
+                }
+                None => {
+                    t::Printout::new(
+                        0,
+                        format!("kernel: caller {source} doesn't have capability {cap}"),
+                    )
+                    .send(send_to_terminal)
+                    .await;
+                }
+            }
+        }
+    }
+    let msg_cap = t::Capability::messaging((our_name, id.clone()));
+    let cap_sig = keypair.sign(&rmp_serde::to_vec(&msg_cap).unwrap());
+    valid_capabilities.insert(msg_cap.clone(), cap_sig.as_ref().to_vec());
+
+    caps_oracle
+        .send(t::CapMessage::Add {
+            on: source.clone(),
+            caps: vec![msg_cap],
+            responder: None,
+        })
+        .await
+        .expect("event loop: fatal: sender died");
+    valid_capabilities
+}
+
+/// whether `source` may read the wasm at `wasm_bytes_handle`, a path in the
+/// VFS, as the VFS would let it: from its own package's drives, or from
+/// another's with a read capability for the drive or the VFS root capability.
+fn may_read_wasm(
+    our_name: &str,
+    process_map: &t::ProcessMap,
+    source: &t::ProcessId,
+    wasm_bytes_handle: &str,
+) -> bool {
+    if *source == *KERNEL_PROCESS_ID {
+        return true;
+    }
+    let mut parts = wasm_bytes_handle.trim_start_matches('/').split('/');
+    let (Some(package_id), Some(drive), Some(_)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let Ok(package_id) = package_id.parse::<t::PackageId>() else {
+        return false;
+    };
+    if source.package() == package_id.package() && source.publisher() == package_id.publisher() {
+        return true;
+    }
+    let Some(process) = process_map.get(source) else {
+        return false;
+    };
+    [
+        format!("{{\"kind\": \"read\", \"drive\": \"/{package_id}/{drive}\"}}"),
+        "{\"root\":true}".to_string(),
+    ]
+    .into_iter()
+    .any(|params| {
+        process.capabilities.contains_key(&t::Capability::new(
+            (our_name, VFS_PROCESS_ID.clone()),
+            params,
+        ))
+    })
+}
+
+/// the processes the manifest entry of `process` lists in `depends_on` that
+/// are running userspace processes: runtime modules are always ready, and
+/// processes that aren't installed can't be waited on.
+async fn dependencies(
+    home_directory_path: &str,
+    process: &t::ProcessId,
+    senders: &Senders,
+) -> Vec<t::ProcessId> {
+    manifest_entry(home_directory_path, process)
+        .await
+        .map(|entry| entry.depends_on)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|dependency| dependency.parse().ok())
+        .filter(|dependency| {
+            senders
+                .get(dependency)
+                .is_some_and(|sender| !sender.instances().is_empty())
+        })
+        .collect()
+}
+
+/// send "run" to every instance of a process, which starts it
+async fn run_process(
+    our_name: &str,
+    process_id: &t::ProcessId,
+    senders: &Senders,
+) -> anyhow::Result<()> {
+    let instances = senders
+        .get(process_id)
+        .map(ProcessSender::instances)
+        .unwrap_or_default();
+    for sender in instances {
+        sender
+            .send(Ok(t::KernelMessage::builder()
+                .id(rand::random())
+                .source((our_name, KERNEL_PROCESS_ID.clone()))
+                .target((our_name, process_id.clone()))
+                .message(t::Message::Request(t::Request {
+                    inherit: false,
+                    expects_response: None,
+                    body: b"run".to_vec(),
+                    metadata: None,
+                    capabilities: vec![],
+                }))
                 .build()
-                .unwrap()
-                .send(send_to_loop)
-                .await;
-            None
-        }
+                .unwrap()))
+            .await?;
+    }
+    Ok(())
+}
+
+/// run a process that is no longer held back, and answer the `RunProcess`
+/// requests that waited on it
+async fn run_runnable(
+    our_name: &str,
+    runnable: readiness::Runnable,
+    senders: &Senders,
+    send_to_loop: &t::MessageSender,
+    send_to_terminal: &t::PrintSender,
+) {
+    let response = if run_process(our_name, &runnable.process, senders)
+        .await
+        .is_ok()
+    {
+        t::KernelResponse::StartedProcess
+    } else {
+        t::Printout::new(0, format!("kernel: couldn't run {}", runnable.process))
+            .send(send_to_terminal)
+            .await;
+        t::KernelResponse::RunProcessError
+    };
+    answer_run_process(our_name, runnable.replies, response, send_to_loop).await;
+}
+
+/// answer `RunProcess` requests, given as (id, requester)
+async fn answer_run_process(
+    our_name: &str,
+    replies: Vec<(u64, t::Address)>,
+    response: t::KernelResponse,
+    send_to_loop: &t::MessageSender,
+) {
+    for (id, target) in replies {
+        t::KernelMessage::builder()
+            .id(id)
+            .source((our_name, KERNEL_PROCESS_ID.clone()))
+            .target(target)
+            .message(t::Message::Response((
+                t::Response {
+                    inherit: false,
+                    body: serde_json::to_vec(&response).unwrap(),
+                    metadata: None,
+                    capabilities: vec![],
+                },
+                None,
+            )))
+            .build()
+            .unwrap()
+            .send(send_to_loop)
+            .await;
     }
 }
 
@@ -459,26 +1705,26 @@ async fn handle_kernel_request(
 async fn start_process(
     our_name: &str,
     keypair: Arc<ring::signature::Ed25519KeyPair>,
+    keyring: &Arc<crypto::Keyring>,
+    blob_store: &Arc<BlobStore>,
     km_blob_bytes: Vec<u8>,
     send_to_loop: &t::MessageSender,
     send_to_terminal: &t::PrintSender,
     senders: &mut Senders,
     process_handles: &mut ProcessHandles,
+    bench: &mut bench::Bench,
+    pending: &mut pending::Pending,
+    public_methods: &mut public::PublicMethods,
+    request_timeouts: process::RequestTimeouts,
     engine: &Engine,
     caps_oracle: &t::CapMessageSender,
     process_metadata: &StartProcessMetadata,
     home_directory_path: &str,
 ) -> anyhow::Result<()> {
-    let (send_to_process, recv_in_process) =
-        mpsc::channel::<Result<t::KernelMessage, t::WrappedSendError>>(PROCESS_CHANNEL_CAPACITY);
     let id = &process_metadata.process_id;
     if senders.contains_key(id) {
         return Err(anyhow::anyhow!("process with ID {id} already exists"));
     }
-    senders.insert(
-        id.clone(),
-        ProcessSender::Userspace(send_to_process.clone()),
-    );
     let metadata = t::ProcessMetadata {
         our: t::Address {
             node: our_name.to_string(),
@@ -489,20 +1735,93 @@ async fn start_process(
         on_exit: process_metadata.persisted.on_exit.clone(),
         public: process_metadata.persisted.public,
     };
-    process_handles.insert(
-        id.clone(),
-        tokio::spawn(process::make_process_loop(
+    let manifest_entry = manifest_entry(home_directory_path, id).await;
+    let request_timeouts = request_timeouts.overridden_by(manifest_entry.as_ref());
+    let hibernate_after = manifest_entry
+        .as_ref()
+        .and_then(|entry| entry.hibernate_after)
+        .map(std::time::Duration::from_secs);
+    let meter = bench.meter(id);
+    let ledger = pending.track(id);
+    public_methods.declare(id, manifest_entry.as_ref());
+    let process_loop = |send_to_loop: t::MessageSender,
+                        recv_in_process: t::ProcessMessageReceiver,
+                        send_to_process: t::ProcessMessageSender,
+                        meter: Option<process::Meter>,
+                        held: Option<pool::Held>| {
+        process::make_process_loop(
             keypair.clone(),
-            metadata,
-            send_to_loop.clone(),
+            keyring.clone(),
+            blob_store.clone(),
+            metadata.clone(),
+            send_to_loop,
             send_to_terminal.clone(),
             recv_in_process,
             send_to_process,
-            km_blob_bytes,
+            km_blob_bytes.clone(),
             caps_oracle.clone(),
             engine.clone(),
             home_directory_path.to_string(),
-        )),
+            meter,
+            request_timeouts,
+            hibernate_after,
+            ledger.clone(),
+            process_metadata.limits.clone(),
+            held,
+        )
+    };
+
+    let instances = pool::declared_instances(manifest_entry.as_ref());
+    if instances == 1 {
+        let (send_to_process, recv_in_process) = mpsc::channel::<
+            Result<t::KernelMessage, t::WrappedSendError>,
+        >(PROCESS_CHANNEL_CAPACITY);
+        senders.insert(
+            id.clone(),
+            ProcessSender::Userspace(send_to_process.clone()),
+        );
+        process_handles.insert(
+            id.clone(),
+            tokio::spawn(process_loop(
+                send_to_loop.clone(),
+                recv_in_process,
+                send_to_process,
+                meter,
+                None,
+            )),
+        );
+        return Ok(());
+    }
+
+    // a pooled process runs each instance in its own task, so they can
+    // handle requests in parallel. the instances live and die together.
+    let mut pool = pool::Pool::new(our_name);
+    let mut tasks = tokio::task::JoinSet::<anyhow::Result<()>>::new();
+    for _ in 0..instances {
+        let (send_to_process, recv_in_process) = mpsc::channel::<
+            Result<t::KernelMessage, t::WrappedSendError>,
+        >(PROCESS_CHANNEL_CAPACITY);
+        let (instance_send_to_loop, held, forward) =
+            pool.add_instance(send_to_process.clone(), send_to_loop.clone());
+        tasks.spawn(forward);
+        tasks.spawn(process_loop(
+            instance_send_to_loop,
+            recv_in_process,
+            send_to_process,
+            meter.as_ref().map(process::Meter::share),
+            Some(held),
+        ));
+    }
+    senders.insert(id.clone(), ProcessSender::Pool(Arc::new(pool)));
+    process_handles.insert(
+        id.clone(),
+        // aborting this task drops the set, aborting every instance
+        tokio::spawn(async move {
+            while let Some(result) = tasks.join_next().await {
+                result??;
+            }
+            Ok(())
+        }),
     );
     Ok(())
 }
@@ -512,6 +1831,9 @@ async fn start_process(
 pub async fn kernel(
     our: t::Identity,
     keypair: Arc<ring::signature::Ed25519KeyPair>,
+    sealing_key: [u8; 32],
+    pki: crate::net::OnchainPKI,
+    blob_store: Arc<BlobStore>,
     mut process_map: t::ProcessMap,
     mut reverse_cap_index: t::ReverseCapIndex,
     caps_oracle_sender: t::CapMessageSender,
@@ -530,12 +1852,22 @@ pub async fn kernel(
         bool,
     )>,
     default_pki_entries: Vec<t::KnsUpdate>,
+    bench_mode: bool,
+    script_limits: Option<t::ProcessLimits>,
+    request_timeouts: process::RequestTimeouts,
+    shard_count: usize,
+    message_limits: limits::MessageLimits,
+    feature_flags: Arc<flags::FeatureFlags>,
+    dedup_window: Option<std::time::Duration>,
 ) -> anyhow::Result<()> {
     let mut config = Config::new();
     config.cache_config_load_default().unwrap();
     config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
     config.wasm_component_model(true);
     config.async_support(true);
+    // counting fuel slows every process down, so it is only done to meter
+    // processes in benchmark mode, or to hold scripts to the node's limits
+    config.consume_fuel(bench_mode || script_limits.is_some());
     let engine = Engine::new(&config).unwrap();
 
     let vfs_path = format!("{home_directory_path}/vfs");
@@ -573,27 +1905,62 @@ pub async fn kernel(
     // keeping only them in the updated post-boot process map
     let mut non_rebooted_processes: HashSet<t::ProcessId> = HashSet::new();
 
-    for (process_id, persisted) in &process_map {
-        // runtime extensions will have a bytes_handle of "", because they have no
-        // Wasm code saved in filesystem.
-        if persisted.wasm_bytes_handle.is_empty() {
-            continue;
-        }
-        // read wasm bytes directly from vfs
-        let wasm_bytes =
-            match tokio::fs::read(format!("{vfs_path}/{}", persisted.wasm_bytes_handle)).await {
-                Ok(bytes) => bytes,
-                Err(e) => {
-                    t::Printout::new(
-                        0,
-                        format!("kernel: couldn't read wasm bytes for process: {process_id}: {e}"),
-                    )
-                    .send(&send_to_terminal)
-                    .await;
-                    non_rebooted_processes.insert(process_id.clone());
-                    continue;
-                }
-            };
+    // processes to notify when another process crashes
+    let mut crash_subscribers: HashSet<t::ProcessId> = HashSet::new();
+
+    // processes spawned by another process, keyed by parent
+    let mut children: HashMap<t::ProcessId, HashSet<t::ProcessId>> = HashMap::new();
+
+    // processes started with OnExit::None, which don't outlive a shutdown.
+    // those of packages set to OnExit::None later, with SetOnExit, do.
+    let mut transient: HashSet<t::ProcessId> = HashSet::new();
+
+    // scripts whose requests are reported instead of sent
+    let mut dry_runs = dry_run::DryRuns::default();
+
+    // processes waiting on others to be ready before they run
+    let mut readiness = readiness::Readiness::default();
+    let mut hold_timeouts = tokio::time::interval(readiness::HOLD_TIMEOUT / 4);
+
+    let mut bench = bench::Bench::new(bench_mode);
+
+    let mut pending = pending::Pending::default();
+    let mut public_methods = public::PublicMethods::default();
+    let mut post_mortems = post_mortem::PostMortems::default();
+    let mut cap_requests = cap_requests::CapRequests::default();
+    let mut transactions = transactions::Transactions::default();
+    let mut net_usage = net_usage::NetUsage::default();
+    let mut dedup = dedup_window.map(dedup::Dedup::new);
+
+    let (shards, mut shard_reports) =
+        shards::Shards::new(shard_count, send_to_loop.clone(), send_to_net.clone());
+
+    let keyring = Arc::new(crypto::Keyring::new(sealing_key, pki));
+
+    // read wasm bytes directly from vfs, several at once, and start each
+    // process in boot order. processes compile as soon as they are started,
+    // in parallel: see `process::make_process_loop`.
+    let boot_order = boot_order(&home_directory_path, &process_map).await;
+    let mut wasm_reads = futures::stream::iter(boot_order.iter().map(|process_id| {
+        let persisted = &process_map[process_id];
+        let path = format!("{vfs_path}/{}", persisted.wasm_bytes_handle);
+        async move { (process_id, persisted, tokio::fs::read(path).await) }
+    }))
+    .buffered(BOOT_READ_PARALLELISM);
+    while let Some((process_id, persisted, wasm_bytes)) = wasm_reads.next().await {
+        let wasm_bytes = match wasm_bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                t::Printout::new(
+                    0,
+                    format!("kernel: couldn't read wasm bytes for process: {process_id}: {e}"),
+                )
+                .send(&send_to_terminal)
+                .await;
+                non_rebooted_processes.insert(process_id.clone());
+                continue;
+            }
+        };
         if let t::OnExit::Requests(requests) = &persisted.on_exit {
             // if a persisted process had on-death-requests, we should perform them now
             // even in death, a process can only message processes it has capabilities for
@@ -628,16 +1995,23 @@ pub async fn kernel(
             process_id: process_id.clone(),
             persisted: persisted.clone(),
             reboot: true,
+            limits: t::ProcessLimits::default(),
         };
 
         match start_process(
             &our.name,
             keypair.clone(),
+            &keyring,
+            &blob_store,
             wasm_bytes,
             &send_to_loop,
             &send_to_terminal,
             &mut senders,
             &mut process_handles,
+            &mut bench,
+            &mut pending,
+            &mut public_methods,
+            request_timeouts,
             &engine,
             &caps_oracle_sender,
             &start_process_metadata,
@@ -655,8 +2029,21 @@ pub async fn kernel(
         }
     }
 
+    drop(wasm_reads);
     process_map.retain(|process_id, _| !non_rebooted_processes.contains(process_id));
 
+    // processes persisted with OnExit::None were left by an unclean shutdown,
+    // unless SetOnExit gave them a policy their manifest entry doesn't have
+    for (process_id, persisted) in &process_map {
+        if persisted.on_exit.is_none()
+            && manifest_entry(&home_directory_path, process_id)
+                .await
+                .map_or(true, |entry| entry.on_exit.is_none())
+        {
+            transient.insert(process_id.clone());
+        }
+    }
+
     // persist new state
     persist_state(&send_to_loop, &process_map).await;
 
@@ -695,9 +2082,58 @@ pub async fn kernel(
         .send(&send_to_loop)
         .await;
 
+    // in simulation mode, a seeded scheduler may choose the delivery order
+    #[cfg(feature = "simulation-mode")]
+    let mut recv_in_loop = crate::sim::Scheduled::new(recv_in_loop);
+
+    // what the shards check and route messages by, made anew whenever a
+    // process starts or exits or its capabilities change
+    let mut routes = Arc::new(shards::Routes::new(
+        &our.name,
+        &process_map,
+        &public_methods,
+        &senders,
+        &message_limits,
+    ));
+    let mut routes_stale = false;
+
     // main event loop
     loop {
         tokio::select! {
+            // a runtime module stopped taking messages: the node can't run without it
+            Some(module) = shard_reports.deaths.recv() => {
+                return Err(anyhow::anyhow!("event loop: fatal: runtime module {module} died"));
+            },
+            // a shard couldn't deliver a message: tell whoever needs to know
+            Some((kernel_message, unrouted)) = shard_reports.unrouted.recv() => {
+                throw_unrouted(
+                    &our.name,
+                    &senders,
+                    &shards,
+                    &process_map,
+                    &send_to_loop,
+                    &send_to_terminal,
+                    &feature_flags,
+                    kernel_message,
+                    unrouted,
+                ).await?;
+            },
+            // run processes held back too long for their dependencies
+            _ = hold_timeouts.tick() => {
+                for (runnable, pending) in readiness.expired() {
+                    t::Printout::new(
+                        0,
+                        format!(
+                            "kernel: {} waited {}s for {pending:?} to be ready, running it anyway",
+                            runnable.process,
+                            readiness::HOLD_TIMEOUT.as_secs(),
+                        ),
+                    )
+                    .send(&send_to_terminal)
+                    .await;
+                    run_runnable(&our.name, runnable, &senders, &send_to_loop, &send_to_terminal).await;
+                }
+            },
             // debug mode toggle: when on, this loop becomes a manual step-through
             Some(debug_command) = recv_debug_in_loop.recv() => {
                 match debug_command {
@@ -720,14 +2156,20 @@ pub async fn kernel(
                 if print_full_event_loop {
                     t::Printout::new(3, format!("{wrapped_network_error:?}")).send(&send_to_terminal).await;
                 }
-                // forward the error to the relevant process
-                match senders.get(&wrapped_network_error.source.process) {
+                // forward the error to the relevant process, behind any
+                // messages already on their way to it
+                let process = wrapped_network_error.source.process.clone();
+                match senders.get(&process) {
                     Some(ProcessSender::Userspace(sender)) => {
-                        sender.send(Err(wrapped_network_error)).await.ok();
+                        shards.error_to_process(&process, sender, wrapped_network_error).await?;
+                    }
+                    Some(ProcessSender::Pool(pool)) => {
+                        let sender = pool.route_error(wrapped_network_error.id, &wrapped_network_error.error.target);
+                        shards.error_to_process(&process, sender, wrapped_network_error).await?;
                     }
                     Some(ProcessSender::Runtime { net_errors, .. }) => {
                         if let Some(net_errors) = net_errors {
-                            net_errors.send(wrapped_network_error).await.ok();
+                            shards.error_to_runtime(&process, net_errors, wrapped_network_error).await?;
                         }
                     }
                     None => {
@@ -739,6 +2181,7 @@ pub async fn kernel(
                                 match wrapped_network_error.error.kind {
                                     t::SendErrorKind::Timeout => "due to timeout",
                                     t::SendErrorKind::Offline => "because the receiver is offline",
+                                    t::SendErrorKind::TooLarge => "because it is too large",
                                 },
                             )
                         ).send(&send_to_terminal).await;
@@ -755,110 +2198,81 @@ pub async fn kernel(
                 if kernel_message.target.node == "our" {
                     kernel_message.target.node = our.name.clone();
                 }
-                //
-                // here are the special kernel-level capabilities checks!
-                //
-                // enforce capabilities by matching from our set based on fixed format
-                // enforce that if message is directed over the network, process has capability to do so
-                if kernel_message.source.node == our.name
-                  && kernel_message.target.node != our.name {
-                    let Some(proc) = process_map.get(&kernel_message.source.process) else {
-                        continue;
-                    };
-                    if !proc.capabilities.contains_key(
-                        &t::Capability::new((&our.name, KERNEL_PROCESS_ID.clone()), "\"network\"")
-                    ) {
-                        // capabilities are not correct! skip this message.
-                        t::Printout::new(
-                            0,
-                            format!(
-                                "event loop: process {} doesn't have capability to send networked messages",
-                                kernel_message.source.process
-                            )
-                        ).send(&send_to_terminal).await;
+                // messages are stamped when sent, and one the kernel passes
+                // back to itself keeps its first stamp
+                if kernel_message.sent_at.is_none() {
+                    kernel_message.sent_at = Some(std::time::Instant::now());
+                }
+                // in simulation mode, a fault script may drop, duplicate or delay messages
+                #[cfg(feature = "simulation-mode")]
+                match crate::faults::judge(&kernel_message) {
+                    crate::faults::Verdict::Deliver => {}
+                    crate::faults::Verdict::Drop => {
                         t::Printout::new(
-                            0,
-                            format!("their capabilities: {:?}", proc.capabilities)
-                        ).send(&send_to_terminal).await;
-                        throw_timeout(&our.name, &senders, kernel_message).await;
+                            2,
+                            format!("fault injection: dropped message {}", kernel_message.id),
+                        )
+                        .send(&send_to_terminal)
+                        .await;
                         continue;
                     }
-                } else if kernel_message.source.node != our.name {
-                    // note that messaging restrictions only apply to *local* processes:
-                    // your process can be messaged by any process remotely if it has
-                    // networking capabilities.
-                    let Some(persisted) = process_map.get(&kernel_message.target.process) else {
+                    crate::faults::Verdict::Duplicate => {
                         t::Printout::new(
-                            0,
-                            format!(
-                                "event loop: got {} from network for {}, but process does not exist{}",
-                                match kernel_message.message {
-                                    t::Message::Request(_) => "Request",
-                                    t::Message::Response(_) => "Response",
-                                },
-                                kernel_message.target.process,
-                                match kernel_message.message {
-                                    t::Message::Request(_) => "",
-                                    t::Message::Response(_) =>
-                                        "\nhint: if you are using `m`, try awaiting the Response: `m --await 5 ...`",
-                                }
-                            )
-                        ).send(&send_to_terminal).await;
+                            2,
+                            format!("fault injection: duplicated message {}", kernel_message.id),
+                        )
+                        .send(&send_to_terminal)
+                        .await;
+                        kernel_message.clone().send(&send_to_loop).await;
+                    }
+                    crate::faults::Verdict::Delay(delay) => {
+                        t::Printout::new(
+                            2,
+                            format!("fault injection: delayed message {} by {delay:?}", kernel_message.id),
+                        )
+                        .send(&send_to_terminal)
+                        .await;
+                        let send_to_loop = send_to_loop.clone();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            kernel_message.send(&send_to_loop).await;
+                        });
                         continue;
-                    };
-                    if !persisted.capabilities.contains_key(
-                        &t::Capability::new((&our.name, KERNEL_PROCESS_ID.clone()), "\"network\"")
-                    ) {
-                        // capabilities are not correct! skip this message.
+                    }
+                }
+                // with a dedup window, a request from a remote node seen
+                // within it is dropped, so it isn't handled twice, and
+                // answered with the response to the first if there is one
+                if kernel_message.source.node != our.name {
+                    if let Some(dedup::Arrival::Duplicate(response)) =
+                        dedup.as_mut().map(|dedup| dedup.arrived(&kernel_message))
+                    {
                         t::Printout::new(
-                            0,
+                            2,
                             format!(
-                                "event loop: process {} got a message from over the network, but doesn't have capability to receive networked messages",
-                                kernel_message.target.process
+                                "event loop: dropping duplicate message {} from {}{}",
+                                kernel_message.id,
+                                kernel_message.source,
+                                if response.is_some() { ", answering it again" } else { "" },
                             )
                         ).send(&send_to_terminal).await;
-                        continue;
-                    }
-                } else {
-                    // enforce that local process has capability to message a target process of this name
-                    // kernel and filesystem can ALWAYS message any local process
-                    if kernel_message.source.process != *KERNEL_PROCESS_ID
-                        && kernel_message.source.process != *STATE_PROCESS_ID
-                        && kernel_message.source.process != *VFS_PROCESS_ID
-                    {
-                        let Some(persisted_source) = process_map.get(&kernel_message.source.process) else {
-                            throw_timeout(&our.name, &senders, kernel_message).await;
-                            continue;
-                        };
-                        let Some(persisted_target) = process_map.get(&kernel_message.target.process) else {
-                            t::Printout::new(
-                                2,
-                                format!(
-                                    "event loop: process {} sent message to non-existing {}; dropping message",
-                                    kernel_message.source.process, kernel_message.target.process
-                                )
-                            ).send(&send_to_terminal).await;
-                            throw_timeout(&our.name, &senders, kernel_message).await;
-                            continue;
-                        };
-                        if !persisted_target.public
-                        && !persisted_source.capabilities.contains_key(
-                            &t::Capability::messaging((&our.name, kernel_message.target.process.clone()))
-                        ) {
-                            // capabilities are not correct! skip this message.
-                            t::Printout::new(
-                                0,
-                                format!(
-                                    "event loop: process {} doesn't have capability to message process {}",
-                                    kernel_message.source.process, kernel_message.target.process
-                                )
-                            ).send(&send_to_terminal).await;
-                            throw_timeout(&our.name, &senders, kernel_message).await;
-                            continue;
+                        if let Some(response) = response {
+                            response.send(&send_to_loop).await;
                         }
+                        continue;
                     }
                 }
-                // end capabilities checks
+                // a dry run's requests are reported, not sent
+                if let Some(report) = dry_runs.intercept(&our.name, &kernel_message) {
+                    t::Printout::new(0, report).send(&send_to_terminal).await;
+                    throw_timeout(&our.name, &senders, &shards, kernel_message, t::SendErrorCause::DryRun).await?;
+                    continue;
+                }
+                if let Some(dedup) = dedup.as_mut() {
+                    dedup.responded(&our.name, &kernel_message);
+                }
+                net_usage.count(&our.name, &kernel_message, &process_map);
+                bench.record(&our.name, &kernel_message);
 
                 // if debug mode is on, wait for user to step through
                 while in_stepthrough_mode {
@@ -874,56 +2288,109 @@ pub async fn kernel(
                     t::Printout::new(3, format!("{kernel_message}")).send(&send_to_terminal).await;
                 }
 
-                if our.name != kernel_message.target.node {
-                    // handle messages sent over network
-                    send_to_net.send(kernel_message).await.expect("fatal: net module died");
-                } else if kernel_message.target.process.process() == "kernel" && kernel_message.source.node == our.name {
+                if routes_stale {
+                    routes = Arc::new(shards::Routes::new(
+                        &our.name,
+                        &process_map,
+                        &public_methods,
+                        &senders,
+                        &message_limits,
+                    ));
+                    routes_stale = false;
+                }
+                let to_kernel = kernel_message.target.node == our.name
+                    && (kernel_message.target.process == *KERNEL_PROCESS_ID
+                        || (kernel_message.target.process.process() == "kernel"
+                            && kernel_message.source.node == our.name));
+                if !to_kernel {
+                    // the shard of the target checks the message against the
+                    // routes as they are now, and passes it to the network,
+                    // or to the appropriate runtime module or process
+                    shards.route(routes.clone(), kernel_message).await?;
+                    continue;
+                }
+                // messages to the kernel change the routes, so are checked here
+                if let Err(unrouted) = routes.check(&kernel_message) {
+                    throw_unrouted(
+                        &our.name,
+                        &senders,
+                        &shards,
+                        &process_map,
+                        &send_to_loop,
+                        &send_to_terminal,
+                        &feature_flags,
+                        kernel_message,
+                        unrouted,
+                    ).await?;
+                    continue;
+                }
+                crate::metrics::handled(&KERNEL_PROCESS_ID, &kernel_message);
+
+                if kernel_message.source.node == our.name {
                     // handle messages sent to local kernel
+                    routes_stale = true;
                     if let Some(()) = handle_kernel_request(
                         &our.name,
                         &keypair,
+                        &keyring,
+                        &blob_store,
                         kernel_message,
                         &send_to_loop,
                         &send_to_terminal,
                         &mut senders,
                         &mut process_handles,
                         &mut process_map,
+                        &mut crash_subscribers,
+                        &mut children,
+                        &mut transient,
+                        &mut dry_runs,
+                        &mut readiness,
+                        &mut bench,
+                        &mut pending,
+                        &mut public_methods,
+                        &mut cap_requests,
+                        &mut post_mortems,
+                        &mut transactions,
+                        &mut net_usage,
+                        &feature_flags,
+                        request_timeouts,
                         &caps_oracle_sender,
                         &engine,
+                        script_limits.as_ref(),
                         &home_directory_path,
                     ).await {
-                        // drain process map of processes with OnExit::None
-                        process_map.retain(|_, persisted| !persisted.on_exit.is_none());
+                        // drain process map of processes started with OnExit::None
+                        process_map.retain(|process_id, _| !transient.contains(process_id));
                         // persist state
                         persist_state(&send_to_loop, &process_map).await;
                         // shut down the node
                         return Ok(());
                     }
                 } else {
-                    // pass message to appropriate runtime module or process
-                    match senders.get(&kernel_message.target.process) {
-                        Some(ProcessSender::Userspace(sender)) => {
-                            sender.send(Ok(kernel_message)).await.ok();
-                        }
-                        Some(ProcessSender::Runtime { sender, .. }) => {
-                            sender.send(kernel_message).await.expect("event loop: fatal: runtime module died");
-                        }
-                        None => {
-                            t::Printout::new(
-                                0,
-                                format!(
-                                    "event loop: got {} from {:?} for {:?}, but target doesn't exist (perhaps it terminated): {}",
-                                    match kernel_message.message {
-                                        t::Message::Request(_) => "Request",
-                                        t::Message::Response(_) => "Response",
-                                    },
-                                    kernel_message.source.process,
-                                    kernel_message.target.process,
-                                    kernel_message,
-                                )
-                            ).send(&send_to_terminal).await;
-                            throw_timeout(&our.name, &senders, kernel_message).await;
-                        }
+                    // a remote kernel may tell us that a request one of our
+                    // processes sent it has no target there
+                    if let Some(missing) = receipts::read_target_missing(&our.name, &kernel_message) {
+                        throw_send_error(
+                            &our.name,
+                            &senders,
+                            &shards,
+                            missing,
+                            t::SendErrorKind::Timeout,
+                            t::SendErrorHop::RemoteKernel,
+                            t::SendErrorCause::TargetMissing,
+                        ).await?;
+                        continue;
+                    }
+                    // the only requests remote nodes may make of the kernel
+                    // are for capabilities, which the user must approve
+                    if let Some(queued) = cap_requests.receive(&our.name, &process_map, kernel_message, &send_to_loop).await {
+                        t::Printout::new(
+                            0,
+                            format!(
+                                "kernel: {} asks for capability {}: \"{}\"\n    approve with `caps approve {}`, or deny with `caps deny {}`",
+                                queued.from, queued.capability, queued.reason, queued.id, queued.id,
+                            )
+                        ).send(&send_to_terminal).await;
                     }
                 }
             },
@@ -932,6 +2399,10 @@ pub async fn kernel(
                 if print_full_event_loop {
                     t::Printout::new(3, format!("{cap_message}")).send(&send_to_terminal).await;
                 }
+                routes_stale |= !matches!(
+                    cap_message,
+                    t::CapMessage::Has { .. } | t::CapMessage::GetAll { .. } | t::CapMessage::FilterCaps { .. }
+                );
                 match cap_message {
                     t::CapMessage::Add { on, caps, responder } => {
                         // insert cap in process map
@@ -1018,6 +2489,25 @@ pub async fn kernel(
                             responder.send(true).ok();
                         }
                     }
+                    t::CapMessage::DropEverywhere { caps, responder } => {
+                        let mut dropped = false;
+                        for entry in process_map.values_mut() {
+                            for cap in &caps {
+                                dropped |= entry.capabilities.remove(cap).is_some();
+                            }
+                        }
+                        for grantees in reverse_cap_index.values_mut() {
+                            for granted in grantees.values_mut() {
+                                granted.retain(|cap| !caps.contains(cap));
+                            }
+                        }
+                        if dropped {
+                            persist_state(&send_to_loop, &process_map).await;
+                        }
+                        if let Some(responder) = responder {
+                            responder.send(true).ok();
+                        }
+                    }
                     t::CapMessage::FilterCaps { on, caps, responder } => {
                         responder.send(
                             match process_map.get(&on) {
@@ -1044,31 +2534,318 @@ pub async fn kernel(
     }
 }
 
+/// tell whoever needs to know that a message couldn't be delivered, and why
+async fn throw_unrouted(
+    our_name: &str,
+    senders: &HashMap<t::ProcessId, ProcessSender>,
+    shards: &shards::Shards,
+    process_map: &t::ProcessMap,
+    send_to_loop: &t::MessageSender,
+    send_to_terminal: &t::PrintSender,
+    feature_flags: &flags::FeatureFlags,
+    km: t::KernelMessage,
+    unrouted: shards::Unrouted,
+) -> anyhow::Result<()> {
+    match unrouted {
+        shards::Unrouted::TooLarge(reason) => {
+            t::Printout::new(
+                1,
+                format!(
+                    "event loop: dropping message from {} to {}: {reason}",
+                    km.source, km.target
+                ),
+            )
+            .send(send_to_terminal)
+            .await;
+            throw_too_large(our_name, senders, shards, km).await
+        }
+        shards::Unrouted::SourceMissing => {
+            throw_timeout(our_name, senders, shards, km, t::SendErrorCause::Unknown).await
+        }
+        shards::Unrouted::CapabilityDenied(capability) => {
+            // capabilities are not correct! skip this message.
+            t::Printout::new(
+                0,
+                if km.target.node != our_name {
+                    format!(
+                        "event loop: process {} doesn't have capability to send networked messages",
+                        km.source.process
+                    )
+                } else {
+                    format!(
+                        "event loop: process {} doesn't have capability to message process {}",
+                        km.source.process, km.target.process
+                    )
+                },
+            )
+            .send(send_to_terminal)
+            .await;
+            if let Some(source) = process_map.get(&km.source.process) {
+                t::Printout::new(2, format!("their capabilities: {:?}", source.capabilities))
+                    .send(send_to_terminal)
+                    .await;
+            }
+            if feature_flags.enabled("cap-feedback") {
+                crate::cap_feedback::report(
+                    (our_name, KERNEL_PROCESS_ID.clone()).into(),
+                    &km,
+                    capability,
+                    send_to_loop,
+                )
+                .await;
+            }
+            throw_timeout(
+                our_name,
+                senders,
+                shards,
+                km,
+                t::SendErrorCause::CapabilityDenied,
+            )
+            .await
+        }
+        shards::Unrouted::NotNetworked => {
+            t::Printout::new(
+                0,
+                format!(
+                    "event loop: process {} got a message from over the network, but doesn't have capability to receive networked messages",
+                    km.target.process
+                ),
+            )
+            .send(send_to_terminal)
+            .await;
+            Ok(())
+        }
+        shards::Unrouted::TargetMissing => {
+            t::Printout::new(
+                0,
+                format!(
+                    "event loop: got {} from {} for {}, but target doesn't exist (perhaps it terminated){}",
+                    match km.message {
+                        t::Message::Request(_) => "Request",
+                        t::Message::Response(_) => "Response",
+                    },
+                    km.source,
+                    km.target.process,
+                    match km.message {
+                        t::Message::Response(_) if km.source.node != our_name =>
+                            "\nhint: if you are using `m`, try awaiting the Response: `m --await 5 ...`",
+                        _ => "",
+                    },
+                ),
+            )
+            .send(send_to_terminal)
+            .await;
+            if let Some(receipt) = receipts::receipt(our_name, &km, false) {
+                receipt.send(send_to_loop).await;
+            }
+            // a remote node is told its request has no target here. a local
+            // process's request fails back to it.
+            match receipts::target_missing(our_name, &km) {
+                Some(notice) => {
+                    notice.send(send_to_loop).await;
+                    Ok(())
+                }
+                None => {
+                    throw_timeout(
+                        our_name,
+                        senders,
+                        shards,
+                        km,
+                        t::SendErrorCause::TargetMissing,
+                    )
+                    .await
+                }
+            }
+        }
+    }
+}
+
+/// a request the kernel won't deliver fails back to its source as a timeout,
+/// if it expects a response, with `cause` saying why
 async fn throw_timeout(
     our_name: &str,
     senders: &HashMap<t::ProcessId, ProcessSender>,
+    shards: &shards::Shards,
     km: t::KernelMessage,
-) {
+    cause: t::SendErrorCause,
+) -> anyhow::Result<()> {
     if let t::Message::Request(req) = &km.message {
         if req.expects_response.is_some() {
-            if let Some(ProcessSender::Userspace(sender)) = senders.get(&km.source.process) {
-                sender
-                    .send(Err(t::WrappedSendError {
-                        id: km.id,
-                        source: t::Address {
-                            node: our_name.to_string(),
-                            process: KERNEL_PROCESS_ID.clone(),
-                        },
-                        error: t::SendError {
-                            kind: t::SendErrorKind::Timeout,
-                            target: km.target,
-                            lazy_load_blob: km.lazy_load_blob,
-                            message: km.message,
-                        },
-                    }))
-                    .await
-                    .ok();
+            throw_send_error(
+                our_name,
+                senders,
+                shards,
+                km,
+                t::SendErrorKind::Timeout,
+                t::SendErrorHop::LocalKernel,
+                cause,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// a request that is too large fails back to its source, if it expects a
+/// response. a response that is too large fails to the process awaiting it
+/// instead, without its body or blob, so that it need not wait out its timeout.
+async fn throw_too_large(
+    our_name: &str,
+    senders: &HashMap<t::ProcessId, ProcessSender>,
+    shards: &shards::Shards,
+    mut km: t::KernelMessage,
+) -> anyhow::Result<()> {
+    match &mut km.message {
+        t::Message::Request(req) => {
+            if req.expects_response.is_none() || km.source.node != our_name {
+                return Ok(());
+            }
+        }
+        t::Message::Response((response, _)) => {
+            if km.target.node != our_name {
+                return Ok(());
             }
+            response.body = vec![];
+            km.lazy_load_blob = None;
+            std::mem::swap(&mut km.source, &mut km.target);
         }
     }
+    throw_send_error(
+        our_name,
+        senders,
+        shards,
+        km,
+        t::SendErrorKind::TooLarge,
+        t::SendErrorHop::LocalKernel,
+        t::SendErrorCause::TooLarge,
+    )
+    .await
+}
+
+/// fail a message back to the local process at its source, behind any
+/// messages already on their way to it
+async fn throw_send_error(
+    our_name: &str,
+    senders: &HashMap<t::ProcessId, ProcessSender>,
+    shards: &shards::Shards,
+    km: t::KernelMessage,
+    kind: t::SendErrorKind,
+    hop: t::SendErrorHop,
+    cause: t::SendErrorCause,
+) -> anyhow::Result<()> {
+    let sender = match senders.get(&km.source.process) {
+        Some(ProcessSender::Userspace(sender)) => sender,
+        Some(ProcessSender::Pool(pool)) => pool.route_error(km.id, &km.target),
+        _ => return Ok(()),
+    };
+    let process = km.source.process.clone();
+    shards
+        .error_to_process(
+            &process,
+            sender,
+            t::WrappedSendError {
+                id: km.id,
+                source: t::Address {
+                    node: our_name.to_string(),
+                    process: KERNEL_PROCESS_ID.clone(),
+                },
+                error: t::SendError {
+                    kind,
+                    target: km.target,
+                    lazy_load_blob: km.lazy_load_blob,
+                    message: km.message,
+                    hop,
+                    cause,
+                    resolution: None,
+                },
+            },
+        )
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str) -> t::ProcessId {
+        t::ProcessId::new(Some(name), "on_exit", "sys")
+    }
+
+    fn requests() -> t::OnExit {
+        t::OnExit::Requests(vec![(
+            t::Address::new("our.os", process("bank")),
+            t::Request {
+                inherit: false,
+                expects_response: None,
+                body: b"transfer".to_vec(),
+                metadata: None,
+                capabilities: vec![],
+            },
+            None,
+        )])
+    }
+
+    #[test]
+    fn a_process_may_set_anything_on_itself() {
+        let app = process("app");
+        assert!(may_set_on_exit(&app, &app, &requests(), false).is_ok());
+        assert!(may_set_on_exit(&app, &app, &t::OnExit::Restart, false).is_ok());
+    }
+
+    #[test]
+    fn another_process_needs_the_cap() {
+        let (admin, app) = (process("admin"), process("app"));
+        assert!(may_set_on_exit(&admin, &app, &t::OnExit::Restart, false).is_err());
+        assert!(may_set_on_exit(&admin, &app, &t::OnExit::Restart, true).is_ok());
+        assert!(may_set_on_exit(&admin, &app, &t::OnExit::None, true).is_ok());
+    }
+
+    #[test]
+    fn the_cap_never_lets_another_process_send_requests_on_exit() {
+        let (admin, app) = (process("admin"), process("app"));
+        assert!(may_set_on_exit(&admin, &app, &requests(), true).is_err());
+        assert!(may_set_on_exit(&KERNEL_PROCESS_ID, &app, &requests(), false).is_ok());
+    }
+
+    fn holding(process_id: &t::ProcessId, params: &str) -> t::ProcessMap {
+        let cap = t::Capability::new(("our.os", VFS_PROCESS_ID.clone()), params);
+        HashMap::from([(
+            process_id.clone(),
+            t::PersistedProcess {
+                wasm_bytes_handle: String::new(),
+                wit_version: None,
+                on_exit: t::OnExit::None,
+                capabilities: HashMap::from([(cap, vec![])]),
+                public: false,
+            },
+        )])
+    }
+
+    #[test]
+    fn transient_wasm_is_read_as_the_vfs_would_let_the_caller() {
+        let terminal = t::ProcessId::new(Some("terminal"), "terminal", "sys");
+        let wasm = "/chess:sys/pkg/chess.wasm";
+        let none = holding(&terminal, "\"messaging\"");
+        assert!(!may_read_wasm("our.os", &none, &terminal, wasm));
+        assert!(may_read_wasm(
+            "our.os",
+            &none,
+            &terminal,
+            "/terminal:sys/pkg/echo.wasm"
+        ));
+        assert!(may_read_wasm("our.os", &none, &KERNEL_PROCESS_ID, wasm));
+
+        let read = holding(&terminal, r#"{"kind": "read", "drive": "/chess:sys/pkg"}"#);
+        assert!(may_read_wasm("our.os", &read, &terminal, wasm));
+        assert!(!may_read_wasm(
+            "our.os",
+            &read,
+            &terminal,
+            "/chess:sys/other/chess.wasm"
+        ));
+
+        let root = holding(&terminal, r#"{"root":true}"#);
+        assert!(may_read_wasm("our.os", &root, &terminal, wasm));
+        assert!(!may_read_wasm("our.os", &root, &terminal, "/chess.wasm"));
+    }
 }
@@ -0,0 +1,157 @@
+use lib::types::core as t;
+use std::collections::HashMap;
+
+/// kernel capability to read every process's network usage
+pub const NETWORK_USAGE_CAP_PARAMS: &str = "\"network-usage\"";
+
+/// messages each local process has sent to, and received from, other nodes
+/// since boot
+#[derive(Default)]
+pub struct NetUsage(HashMap<t::ProcessId, t::NetworkUsage>);
+
+impl NetUsage {
+    /// count a message if it crosses the network, against its local end.
+    /// messages to processes we don't run aren't counted, so that a remote
+    /// node can't fill the table with made-up targets.
+    pub fn count(&mut self, our_name: &str, km: &t::KernelMessage, process_map: &t::ProcessMap) {
+        let sent = km.target.node != our_name;
+        if sent == (km.source.node != our_name) {
+            return;
+        }
+        let body = match &km.message {
+            t::Message::Request(request) => request.body.len(),
+            t::Message::Response((response, _)) => response.body.len(),
+        } as u64;
+        let blob = match (&km.lazy_load_blob, &km.blob_handle) {
+            (Some(blob), _) => blob.bytes.len() as u64,
+            (None, Some(handle)) => handle.len,
+            (None, None) => 0,
+        };
+        let process = &if sent { &km.source } else { &km.target }.process;
+        if !process_map.contains_key(process) {
+            return;
+        }
+        let usage = self.0.entry(process.clone()).or_default();
+        if sent {
+            usage.messages_sent += 1;
+            usage.bytes_sent += body + blob;
+        } else {
+            usage.messages_received += 1;
+            usage.bytes_received += body + blob;
+        }
+        usage.last_message = crate::clock::now();
+    }
+
+    /// forget a process that has been stopped
+    pub fn remove(&mut self, process: &t::ProcessId) {
+        self.0.remove(process);
+    }
+
+    pub fn list(&self) -> Vec<(t::ProcessId, t::NetworkUsage)> {
+        self.0
+            .iter()
+            .map(|(process, usage)| (process.clone(), usage.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(source: (&str, &str), target: (&str, &str), body: &[u8]) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(1)
+            .source((source.0, source.1.parse::<t::ProcessId>().unwrap()))
+            .target((target.0, target.1.parse::<t::ProcessId>().unwrap()))
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: None,
+                body: body.to_vec(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    fn process_map(processes: &[&str]) -> t::ProcessMap {
+        processes
+            .iter()
+            .map(|process| {
+                (
+                    process.parse().unwrap(),
+                    t::PersistedProcess {
+                        wasm_bytes_handle: String::new(),
+                        wit_version: None,
+                        on_exit: t::OnExit::None,
+                        capabilities: HashMap::new(),
+                        public: false,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counts_against_local_end() {
+        let mut usage = NetUsage::default();
+        let map = process_map(&["chat:chat:sys"]);
+        usage.count(
+            "us.os",
+            &message(
+                ("us.os", "chat:chat:sys"),
+                ("them.os", "chat:chat:sys"),
+                b"hi",
+            ),
+            &map,
+        );
+        usage.count(
+            "us.os",
+            &message(
+                ("them.os", "chat:chat:sys"),
+                ("us.os", "chat:chat:sys"),
+                b"hey",
+            ),
+            &map,
+        );
+        // local messages aren't network usage
+        usage.count(
+            "us.os",
+            &message(
+                ("us.os", "chat:chat:sys"),
+                ("us.os", "chat:chat:sys"),
+                b"me",
+            ),
+            &map,
+        );
+        let list = usage.list();
+        assert_eq!(list.len(), 1);
+        let (_, counted) = &list[0];
+        assert_eq!((counted.messages_sent, counted.bytes_sent), (1, 2));
+        assert_eq!((counted.messages_received, counted.bytes_received), (1, 3));
+    }
+
+    #[test]
+    fn unknown_and_stopped_processes_are_not_kept() {
+        let mut usage = NetUsage::default();
+        let map = process_map(&["chat:chat:sys"]);
+        usage.count(
+            "us.os",
+            &message(("them.os", "chat:chat:sys"), ("us.os", "made:up:sys"), b"x"),
+            &map,
+        );
+        assert!(usage.list().is_empty());
+        usage.count(
+            "us.os",
+            &message(
+                ("them.os", "chat:chat:sys"),
+                ("us.os", "chat:chat:sys"),
+                b"x",
+            ),
+            &map,
+        );
+        usage.remove(&"chat:chat:sys".parse().unwrap());
+        assert!(usage.list().is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use lib::types::core::{self as t, KERNEL_PROCESS_ID};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// kernel capability to inspect what other processes are waiting on
+pub const PENDING_CAP_PARAMS: &str = "\"pending\"";
+
+/// the pending capability, issued by our kernel
+pub fn capability(our_name: &str) -> t::Capability {
+    t::Capability::new((our_name, KERNEL_PROCESS_ID.clone()), PENDING_CAP_PARAMS)
+}
+
+/// whether `message` only asks the kernel what a process is waiting on. a
+/// process holding the [`capability`] may ask that without the capability to
+/// message the kernel, which would let it send any other command too.
+pub fn is_query(message: &t::Message) -> bool {
+    let t::Message::Request(request) = message else {
+        return false;
+    };
+    matches!(
+        serde_json::from_slice(&request.body),
+        Ok(t::KernelCommand::Debug(t::KernelPrint::Pending(_)))
+    )
+}
+
+/// what a process is waiting on, kept where the kernel can read it, so that a
+/// hung process can be inspected without its help. the instances of a pooled
+/// process share one.
+#[derive(Clone, Default)]
+pub struct Ledger(Arc<Mutex<Entries>>);
+
+#[derive(Default)]
+struct Entries {
+    /// when each outstanding request was sent, and its timeout, by ID and target
+    requests: HashMap<(u64, t::Address), (Instant, Duration)>,
+    /// messages each instance has set aside while awaiting a response
+    set_aside: HashMap<u64, usize>,
+}
+
+impl Ledger {
+    pub fn sent(&self, id: u64, target: t::Address, timeout: Duration) {
+        let mut entries = self.0.lock().unwrap();
+        entries
+            .requests
+            .insert((id, target), (Instant::now(), timeout));
+    }
+
+    pub fn answered(&self, id: u64, target: t::Address) {
+        self.0.lock().unwrap().requests.remove(&(id, target));
+    }
+
+    /// note how many messages the instance with this key has set aside
+    pub fn set_aside(&self, instance: u64, count: usize) {
+        let mut entries = self.0.lock().unwrap();
+        if count == 0 {
+            entries.set_aside.remove(&instance);
+        } else {
+            entries.set_aside.insert(instance, count);
+        }
+    }
+
+    /// the outstanding requests, oldest first, and the messages set aside
+    fn inspect(&self) -> (Vec<t::PendingRequest>, usize) {
+        let entries = self.0.lock().unwrap();
+        let mut requests: Vec<t::PendingRequest> = entries
+            .requests
+            .iter()
+            .map(|((id, target), (sent, timeout))| t::PendingRequest {
+                id: *id,
+                target: target.clone(),
+                age_ms: sent.elapsed().as_millis() as u64,
+                timeout_remaining_ms: timeout.saturating_sub(sent.elapsed()).as_millis() as u64,
+            })
+            .collect();
+        requests.sort_by_key(|request| std::cmp::Reverse(request.age_ms));
+        (requests, entries.set_aside.values().sum())
+    }
+}
+
+/// the ledgers of every running process
+#[derive(Default)]
+pub struct Pending(HashMap<t::ProcessId, Ledger>);
+
+impl Pending {
+    /// a fresh ledger for a process that is starting
+    pub fn track(&mut self, process: &t::ProcessId) -> Ledger {
+        let ledger = Ledger::default();
+        self.0.insert(process.clone(), ledger.clone());
+        ledger
+    }
+
+    pub fn remove(&mut self, process: &t::ProcessId) {
+        self.0.remove(process);
+    }
+
+    /// what a running process is waiting on. `queued` is the number of
+    /// messages delivered to it that it hasn't taken yet.
+    pub fn inspect(&self, process: &t::ProcessId, queued: usize) -> Option<t::PendingInfo> {
+        let (requests, set_aside) = self.0.get(process)?.inspect();
+        Some(t::PendingInfo {
+            requests,
+            mailbox: queued + set_aside,
+        })
+    }
+}
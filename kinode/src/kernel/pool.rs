@@ -0,0 +1,285 @@
+use lib::types::core as t;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::mpsc;
+
+/// most instances a manifest may declare for one process
+const MAX_INSTANCES: usize = 64;
+
+/// the number of instances of a process its package manifest entry declares
+/// with `"instances": N`. processes without one run a single instance.
+pub fn declared_instances(manifest_entry: Option<&t::PackageManifestEntry>) -> usize {
+    manifest_entry
+        .and_then(|entry| entry.instances)
+        .map_or(1, |n| (n as usize).clamp(1, MAX_INSTANCES))
+}
+
+/// how many messages an instance holds: those it has set aside while awaiting
+/// a response, and the one it is handling, if it is between `receive()` calls.
+/// an instance busy with a message has none left in its queue, so this is
+/// counted with the queue in judging how loaded it is.
+#[derive(Clone, Default)]
+pub struct Held(Arc<AtomicUsize>);
+
+impl Held {
+    pub fn set(&self, held: usize) {
+        self.0.store(held, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// the instances of a pooled process. they share one address: requests to it
+/// go to the least-loaded instance, and responses go back to the instance that
+/// sent the request they answer.
+pub struct Pool {
+    /// our node's name, which stands in for "our" in request targets, as the
+    /// event loop does, so that responses from ourself match them
+    our_name: String,
+    senders: Vec<t::ProcessMessageSender>,
+    /// by instance, as `senders`
+    held: Vec<Held>,
+    /// the instance that sent each request still awaiting a response, by ID
+    /// and then by target, since inherited IDs can collide. see
+    /// [`super::process::Contexts`] for how a response is matched to a request.
+    pending: Arc<Mutex<HashMap<u64, Vec<(t::Address, usize)>>>>,
+    /// where to start looking for the least-loaded instance, so that idle
+    /// instances take turns
+    next: AtomicUsize,
+}
+
+impl Pool {
+    pub fn new(our_name: &str) -> Self {
+        Self {
+            our_name: our_name.to_string(),
+            senders: vec![],
+            held: vec![],
+            pending: Arc::default(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// add an instance. returns the sender the instance must use in place of
+    /// the event loop's, what it must keep up to date with the messages it
+    /// holds, and a task that forwards from its sender to the event loop,
+    /// noting which requests the instance expects responses to.
+    pub fn add_instance(
+        &mut self,
+        sender: t::ProcessMessageSender,
+        send_to_loop: t::MessageSender,
+    ) -> (
+        t::MessageSender,
+        Held,
+        impl Future<Output = anyhow::Result<()>> + Send + 'static,
+    ) {
+        let index = self.senders.len();
+        self.senders.push(sender);
+        let held = Held::default();
+        self.held.push(held.clone());
+        let pending = self.pending.clone();
+        let our_name = self.our_name.clone();
+        let (instance_send_to_loop, mut recv_from_instance) =
+            mpsc::channel::<t::KernelMessage>(super::PROCESS_CHANNEL_CAPACITY);
+        let forward = async move {
+            while let Some(km) = recv_from_instance.recv().await {
+                if let t::Message::Request(t::Request {
+                    expects_response: Some(_),
+                    ..
+                }) = km.message
+                {
+                    let target = super::process::resolve_our(km.target.clone(), &our_name);
+                    pending
+                        .lock()
+                        .unwrap()
+                        .entry(km.id)
+                        .or_default()
+                        .push((target, index));
+                }
+                send_to_loop.send(km).await?;
+            }
+            Ok(())
+        };
+        (instance_send_to_loop, held, forward)
+    }
+
+    pub fn senders(&self) -> &[t::ProcessMessageSender] {
+        &self.senders
+    }
+
+    /// the instance a message to the pool should be delivered to
+    pub fn route(&self, km: &t::KernelMessage) -> &t::ProcessMessageSender {
+        match km.message {
+            t::Message::Response(_) => self.route_error(km.id, &km.source),
+            t::Message::Request(_) => self.least_loaded(),
+        }
+    }
+
+    /// the instance that sent the request a response or error with this ID,
+    /// from or about `source`, answers
+    pub fn route_error(&self, id: u64, source: &t::Address) -> &t::ProcessMessageSender {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(requests) = pending.get_mut(&id) else {
+            return self.least_loaded();
+        };
+        let position = requests
+            .iter()
+            .position(|(sent_to, _)| sent_to == source)
+            .or((requests.len() == 1).then_some(0));
+        let Some(position) = position else {
+            return self.least_loaded();
+        };
+        let (_, index) = requests.swap_remove(position);
+        if requests.is_empty() {
+            pending.remove(&id);
+        }
+        &self.senders[index]
+    }
+
+    /// the instance with the fewest messages waiting in its queue or held
+    fn least_loaded(&self) -> &t::ProcessMessageSender {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = (0..self.senders.len())
+            .map(|i| (start + i) % self.senders.len())
+            .min_by_key(|&i| {
+                let sender = &self.senders[i];
+                sender.max_capacity() - sender.capacity() + self.held[i].get()
+            })
+            .expect("pool has no instances");
+        &self.senders[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR: &str = "our.os";
+
+    fn address(node: &str, process: &str) -> t::Address {
+        t::Address::new(node, t::ProcessId::new(Some(process), "pool", "sys"))
+    }
+
+    fn request(id: u64, target: t::Address) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(id)
+            .source(address(OUR, "pooled"))
+            .target(target)
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: Some(5),
+                body: vec![],
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    /// a pool of `count` instances, with the senders each instance sends on
+    /// and the receiver standing in for the event loop
+    fn pool(
+        count: usize,
+    ) -> (
+        Pool,
+        Vec<t::MessageSender>,
+        t::MessageReceiver,
+        Vec<t::ProcessMessageReceiver>,
+    ) {
+        let (send_to_loop, recv_in_loop) = mpsc::channel(16);
+        let mut pool = Pool::new(OUR);
+        let mut instances = vec![];
+        let mut receivers = vec![];
+        for _ in 0..count {
+            let (sender, receiver) = mpsc::channel(16);
+            let (instance_send_to_loop, _held, forward) =
+                pool.add_instance(sender, send_to_loop.clone());
+            tokio::spawn(forward);
+            instances.push(instance_send_to_loop);
+            receivers.push(receiver);
+        }
+        (pool, instances, recv_in_loop, receivers)
+    }
+
+    /// send a request from instance `from`, and wait for it to be forwarded
+    async fn send(
+        instances: &[t::MessageSender],
+        recv_in_loop: &mut t::MessageReceiver,
+        from: usize,
+        km: t::KernelMessage,
+    ) {
+        instances[from].send(km).await.unwrap();
+        recv_in_loop.recv().await.unwrap();
+    }
+
+    fn routed_to(pool: &Pool, id: u64, source: &t::Address) -> usize {
+        instance(pool, pool.route_error(id, source))
+    }
+
+    fn instance(pool: &Pool, sender: &t::ProcessMessageSender) -> usize {
+        pool.senders()
+            .iter()
+            .position(|s| s.same_channel(sender))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn busy_instance_is_passed_over() {
+        let (pool, _instances, _recv_in_loop, _receivers) = pool(2);
+        let km = request(1, address(OUR, "pooled"));
+        // instance 0 has taken a message off its queue, and is handling it
+        pool.held[0].set(1);
+        for _ in 0..4 {
+            assert_eq!(instance(&pool, pool.route(&km)), 1);
+        }
+        // as one set aside while awaiting a response counts against it
+        pool.held[0].set(0);
+        pool.held[1].set(2);
+        assert_eq!(instance(&pool, pool.route(&km)), 0);
+        // idle instances take turns
+        pool.held[1].set(0);
+        let first = instance(&pool, pool.route(&km));
+        assert_ne!(instance(&pool, pool.route(&km)), first);
+    }
+
+    #[tokio::test]
+    async fn colliding_ids_route_by_target() {
+        let (pool, instances, mut recv_in_loop, _receivers) = pool(2);
+        let first = address("a.os", "echo");
+        let second = address("b.os", "echo");
+        send(&instances, &mut recv_in_loop, 0, request(7, first.clone())).await;
+        send(&instances, &mut recv_in_loop, 1, request(7, second.clone())).await;
+        assert_eq!(routed_to(&pool, 7, &second), 1);
+        assert_eq!(routed_to(&pool, 7, &first), 0);
+        assert!(pool.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_request_to_our_is_matched_by_our_name() {
+        let (pool, instances, mut recv_in_loop, _receivers) = pool(2);
+        send(
+            &instances,
+            &mut recv_in_loop,
+            0,
+            request(9, address("a.os", "echo")),
+        )
+        .await;
+        send(
+            &instances,
+            &mut recv_in_loop,
+            1,
+            request(9, address("our", "echo")),
+        )
+        .await;
+        // the response comes back from our node's name, not "our"
+        assert_eq!(routed_to(&pool, 9, &address(OUR, "echo")), 1);
+        assert_eq!(routed_to(&pool, 9, &address("a.os", "echo")), 0);
+    }
+}
@@ -0,0 +1,284 @@
+use lib::types::core::{self as t, KERNEL_PROCESS_ID, STATE_PROCESS_ID};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+
+/// kernel capability to list post-mortems and fetch the state kept with them
+pub const POST_MORTEM_CAP_PARAMS: &str = "\"post-mortem\"";
+/// most post-mortems kept; the oldest are removed to make room
+const MAX_POST_MORTEMS: usize = 32;
+/// how long the state module has to answer for a crash's state, which is
+/// also how long the request for it is remembered
+const STATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// crashes kept on disk, each in `{home}/post_mortem/{id}/` as a `crash.json`
+/// [`t::PostMortem`] and, if the process had persisted any, its `state`, so
+/// that a crash can be reproduced against the exact state it happened with.
+///
+/// the files are written by spawned tasks, so the kernel loop doesn't wait
+/// on the disk for them.
+#[derive(Default)]
+pub struct PostMortems {
+    /// the directories of crashes whose state was asked of the state module,
+    /// by the ID of the request, with when it was asked. those the state
+    /// module never answers, or that were never sent because the crash
+    /// couldn't be written, are forgotten after [`STATE_TIMEOUT`].
+    awaiting_state: HashMap<u64, (PathBuf, Instant)>,
+    /// the last ID given to a crash
+    last_id: u64,
+}
+
+impl PostMortems {
+    /// record a crash, and ask the state module for the process's state once
+    /// the crash is written
+    pub fn crashed(
+        &mut self,
+        our_name: &str,
+        home_directory_path: &str,
+        process: &t::ProcessId,
+        error: &str,
+        send_to_loop: &t::MessageSender,
+        send_to_terminal: &t::PrintSender,
+    ) {
+        // post-mortems are not worth filling a disk that is running out
+        if crate::disk::is_low() {
+            return;
+        }
+        // two crashes in the same millisecond get consecutive IDs
+        let id = (SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64)
+            .max(self.last_id + 1);
+        self.last_id = id;
+        let root = root(home_directory_path);
+        let dir = root.join(id.to_string());
+        let post_mortem = t::PostMortem {
+            id,
+            process: process.clone(),
+            error: error.to_string(),
+            state_bytes: None,
+        };
+        let request_id: u64 = rand::random();
+        self.forget_unanswered();
+        self.awaiting_state
+            .insert(request_id, (dir.clone(), Instant::now()));
+        let request = t::KernelMessage::builder()
+            .id(request_id)
+            .source((our_name, KERNEL_PROCESS_ID.clone()))
+            .target((our_name, STATE_PROCESS_ID.clone()))
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: Some(STATE_TIMEOUT.as_secs()),
+                body: serde_json::to_vec(&t::StateAction::GetState(process.clone())).unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap();
+        let send_to_loop = send_to_loop.clone();
+        let send_to_terminal = send_to_terminal.clone();
+        tokio::spawn(async move {
+            let written = async {
+                fs::create_dir_all(&dir).await?;
+                fs::write(
+                    dir.join("crash.json"),
+                    serde_json::to_vec(&post_mortem).unwrap(),
+                )
+                .await?;
+                prune(&root).await
+            };
+            if let Err(e) = written.await {
+                t::Printout::new(
+                    0,
+                    format!(
+                        "kernel: couldn't keep post-mortem of {}: {e}",
+                        post_mortem.process
+                    ),
+                )
+                .send(&send_to_terminal)
+                .await;
+                return;
+            }
+            request.send(&send_to_loop).await;
+        });
+    }
+
+    fn forget_unanswered(&mut self) {
+        self.awaiting_state
+            .retain(|_, (_, asked)| asked.elapsed() < STATE_TIMEOUT);
+    }
+
+    /// keep the state in a response from the state module with the crash it
+    /// was asked for, if it is one we await
+    pub fn state_arrived(&mut self, km: &t::KernelMessage, send_to_terminal: &t::PrintSender) {
+        let Some((dir, _)) = self.awaiting_state.remove(&km.id) else {
+            return;
+        };
+        let t::Message::Response((ref response, _)) = km.message else {
+            return;
+        };
+        // a process that never persisted state has none to keep
+        let Ok(t::StateResponse::GetState) = serde_json::from_slice(&response.body) else {
+            return;
+        };
+        let Some(ref blob) = km.lazy_load_blob else {
+            return;
+        };
+        let state = blob.bytes.clone();
+        let send_to_terminal = send_to_terminal.clone();
+        tokio::spawn(async move {
+            if let Err(e) = keep_state(&dir, state).await {
+                t::Printout::new(0, format!("kernel: couldn't keep post-mortem state: {e}"))
+                    .send(&send_to_terminal)
+                    .await;
+            }
+        });
+    }
+}
+
+/// whether `source` may list post-mortems and fetch their state, which hold
+/// what other processes printed and persisted
+pub fn may_read(our_name: &str, process_map: &t::ProcessMap, source: &t::ProcessId) -> bool {
+    *source == *KERNEL_PROCESS_ID
+        || process_map.get(source).is_some_and(|process| {
+            process.capabilities.contains_key(&t::Capability::new(
+                (our_name, KERNEL_PROCESS_ID.clone()),
+                POST_MORTEM_CAP_PARAMS,
+            ))
+        })
+}
+
+async fn keep_state(dir: &Path, state: Vec<u8>) -> std::io::Result<()> {
+    // the crash may have been pruned while we waited
+    let Ok(crash) = fs::read(dir.join("crash.json")).await else {
+        return Ok(());
+    };
+    let mut post_mortem: t::PostMortem = serde_json::from_slice(&crash)?;
+    post_mortem.state_bytes = Some(state.len() as u64);
+    fs::write(dir.join("state"), &state).await?;
+    fs::write(
+        dir.join("crash.json"),
+        serde_json::to_vec(&post_mortem).unwrap(),
+    )
+    .await
+}
+
+/// the post-mortems kept for a process, newest first
+pub async fn list(home_directory_path: &str, process: &t::ProcessId) -> Vec<t::PostMortem> {
+    let mut post_mortems = vec![];
+    for id in ids(&root(home_directory_path)).await.unwrap_or_default() {
+        if let Some(post_mortem) = read(home_directory_path, id).await {
+            if &post_mortem.process == process {
+                post_mortems.push(post_mortem);
+            }
+        }
+    }
+    post_mortems.reverse();
+    post_mortems
+}
+
+/// the state kept with a process's post-mortem, if any
+pub async fn state(home_directory_path: &str, process: &t::ProcessId, id: u64) -> Option<Vec<u8>> {
+    let post_mortem = read(home_directory_path, id).await?;
+    if &post_mortem.process != process {
+        return None;
+    }
+    post_mortem.state_bytes?;
+    fs::read(root(home_directory_path).join(id.to_string()).join("state"))
+        .await
+        .ok()
+}
+
+fn root(home_directory_path: &str) -> PathBuf {
+    Path::new(home_directory_path).join("post_mortem")
+}
+
+async fn read(home_directory_path: &str, id: u64) -> Option<t::PostMortem> {
+    let path = root(home_directory_path)
+        .join(id.to_string())
+        .join("crash.json");
+    serde_json::from_slice(&fs::read(path).await.ok()?).ok()
+}
+
+/// the IDs of the post-mortems kept, oldest first
+async fn ids(root: &Path) -> std::io::Result<Vec<u64>> {
+    let mut ids = vec![];
+    let mut entries = fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            ids.push(id);
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+async fn prune(root: &Path) -> std::io::Result<()> {
+    let ids = ids(root).await?;
+    let excess = ids.len().saturating_sub(MAX_POST_MORTEMS);
+    for id in &ids[..excess] {
+        // another crash's task may have pruned it first
+        match fs::remove_dir_all(root.join(id.to_string())).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_post_mortems_needs_the_cap() {
+        let reader = t::ProcessId::new(Some("reader"), "debug", "sys");
+        let cap = t::Capability::new(
+            ("our.os", KERNEL_PROCESS_ID.clone()),
+            POST_MORTEM_CAP_PARAMS,
+        );
+        let mut process_map: t::ProcessMap = HashMap::from([(
+            reader.clone(),
+            t::PersistedProcess {
+                wasm_bytes_handle: String::new(),
+                wit_version: None,
+                on_exit: t::OnExit::None,
+                capabilities: HashMap::new(),
+                public: false,
+            },
+        )]);
+        assert!(!may_read("our.os", &process_map, &reader));
+        assert!(may_read("our.os", &process_map, &KERNEL_PROCESS_ID));
+        process_map
+            .get_mut(&reader)
+            .unwrap()
+            .capabilities
+            .insert(cap, vec![]);
+        assert!(may_read("our.os", &process_map, &reader));
+    }
+
+    #[test]
+    fn unanswered_state_requests_are_forgotten() {
+        let mut post_mortems = PostMortems::default();
+        let asked_long_ago = Instant::now().checked_sub(STATE_TIMEOUT * 2).unwrap();
+        post_mortems
+            .awaiting_state
+            .insert(1, (PathBuf::from("old"), asked_long_ago));
+        post_mortems
+            .awaiting_state
+            .insert(2, (PathBuf::from("new"), Instant::now()));
+        post_mortems.forget_unanswered();
+        assert_eq!(
+            post_mortems.awaiting_state.keys().collect::<Vec<_>>(),
+            vec![&2]
+        );
+    }
+}
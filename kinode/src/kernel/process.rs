@@ -1,31 +1,259 @@
+use crate::kernel::pending::Ledger;
 use crate::KERNEL_PROCESS_ID;
 use lib::{types::core as t, v0::ProcessV0, Process};
 use std::{
     collections::{HashMap, VecDeque},
-    sync::Arc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
-use tokio::{fs, task::JoinHandle};
+use tokio::{fs, sync::Semaphore, task::JoinHandle};
 use wasi_common::sync::Dir;
 use wasmtime::{
     component::{Component, Linker, ResourceTable as Table},
-    Engine, Store,
+    Engine, ResourceLimiter, Store, StoreContextMut,
 };
 use wasmtime_wasi::{
     pipe::MemoryOutputPipe, DirPerms, FilePerms, WasiCtx, WasiCtxBuilder, WasiView,
 };
 
 const STACK_TRACE_SIZE: usize = 5000;
+/// how much fuel a process with a wall time limit burns between yields
+const WALL_TIME_YIELD_FUEL: u64 = 10_000_000;
 
+lazy_static::lazy_static! {
+    /// compiling is CPU-bound: compile at most one process per core at once,
+    /// so that boot, when every process compiles, doesn't starve the runtime.
+    /// permits are granted in the order processes are started.
+    static ref COMPILE_PERMITS: Semaphore = Semaphore::new(
+        std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+    );
+    /// compiled wasm by hash, so that restarts, pooled instances and scripts
+    /// run again don't compile again
+    static ref COMPONENT_CACHE: Mutex<HashMap<blake3::Hash, Component>> =
+        Mutex::new(HashMap::new());
+    /// OnExit policies set by the kernel for running processes, which take
+    /// the place of their own when they exit
+    static ref ON_EXIT_SET: dashmap::DashMap<t::ProcessId, t::OnExit> = dashmap::DashMap::new();
+    /// when each OnExit::Restart process last crashed, within [`CRASH_LOOP_WINDOW`]
+    static ref CRASHES: dashmap::DashMap<t::ProcessId, VecDeque<Instant>> = dashmap::DashMap::new();
+    /// when each OnExit::Restart process last exited, crashing or not, within
+    /// [`CRASH_LOOP_WINDOW`]
+    static ref EXITS: dashmap::DashMap<t::ProcessId, VecDeque<Instant>> = dashmap::DashMap::new();
+}
+
+/// wait before restarting a process after it first exits; doubled for each
+/// further exit within [`CRASH_LOOP_WINDOW`], up to [`RESTART_BACKOFF_MAX`].
+/// a process that returns from init() at once is held back like one that
+/// crashes, rather than restarted as fast as it can exit.
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+/// a process that crashes [`CRASH_LOOP_LIMIT`] times within this window is
+/// in a crash loop, and is not restarted again
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(10 * 60);
+const CRASH_LOOP_LIMIT: usize = 5;
+/// most bytes of stderr, from its end, put in a crash loop notification
+const CRASH_EXCERPT_LEN: usize = 2000;
+
+/// have the running `process` do `on_exit` when it exits, whatever it has set
+pub fn set_on_exit(process: &t::ProcessId, on_exit: t::OnExit) {
+    ON_EXIT_SET.insert(process.clone(), on_exit);
+}
+
+/// the OnExit policy the kernel set for the running `process`, if any
+pub fn on_exit_set(process: &t::ProcessId) -> Option<t::OnExit> {
+    ON_EXIT_SET.get(process).map(|on_exit| on_exit.clone())
+}
+
+/// drop the OnExit policy the kernel set for `process`, so that its own applies
+pub fn forget_on_exit(process: &t::ProcessId) {
+    ON_EXIT_SET.remove(process);
+}
+
+/// most compiled components kept in [`COMPONENT_CACHE`]
+const COMPONENT_CACHE_SIZE: usize = 64;
+
+/// the interface of host functions the runtime offers processes beyond those
+/// of `kinode:process/standard`, declared in process_ext/wit/runtime.wit
+const EXT_INTERFACE: &str = "kinode:runtime/ext@0.1.0";
+
+/// kernel capability to mount the process's package directory read-only
+const MOUNT_READ_CAP_PARAMS: &str = "\"mount-read\"";
+/// kernel capability to mount the process's package directory read-write
+pub const MOUNT_WRITE_CAP_PARAMS: &str = "\"mount-write\"";
+
+/// what a process was handling when it sent a request that expects a response.
+///
+/// a process always has one current frame: the prompting message, which is the
+/// request it owes a response to (if any), and the blob of the last message it
+/// received. each outstanding request saves the frame it was sent from in
+/// [`ProcessState::contexts`], so nested requests form a stack that unwinds as
+/// their responses arrive:
+///
+/// - receiving a request makes it the prompting message if anyone awaits a
+///   response to it, i.e. it expects one or carries an rsvp. otherwise the
+///   prompting message is kept, so a request that expects no response, such
+///   as a notification, can't make the process forget whom it still owes one.
+/// - receiving a response restores the prompting message saved with its
+///   request, so the process can answer its own requester after any number of
+///   nested requests. the blob becomes the response's.
+/// - receiving an error in place of a response restores the prompting message
+///   the same way. the error carries the failed request's blob, so the frame
+///   keeps the blob of the last message received.
+/// - a response answers the prompting message, at its rsvp if it has one and
+///   at its source otherwise.
+/// - a request that inherits takes the prompting message's ID, and its response
+///   goes wherever a response to the prompting message would, so a chain of
+///   inheriting requests delivers the last hop's response to the first requester.
 pub struct ProcessContext {
-    // store predecessor in order to set prompting message when popped
+    /// prompting message of the frame the request was sent from
     pub prompting_message: Option<t::KernelMessage>,
-    // can be empty if a request doesn't set context, but still needs to inherit
+    /// set by the process when sending the request, handed back with the response.
+    /// can be empty if a request doesn't set context, but still needs to inherit
     pub context: Option<t::Context>,
 }
 
+/// the contexts and timeout tasks of a process's outstanding requests.
+///
+/// request IDs are chosen by whoever starts a chain of requests, so an ID a
+/// process inherits can collide with one it chose itself, or with one another
+/// node chose. requests are therefore kept by ID and by the address they were
+/// sent to. a response or error answers the request with its ID that was sent
+/// to its source or, failing that, the only request with its ID: a response at
+/// the end of a chain of inheriting requests comes from the last hop, not the
+/// first. if several requests share the ID and none was sent to the source,
+/// the message is ambiguous and answers none of them.
+///
+/// outstanding requests are also noted in a [`Ledger`] the kernel can read.
+pub struct Contexts {
+    requests: HashMap<u64, Vec<(t::Address, ProcessContext, JoinHandle<()>)>>,
+    ledger: Ledger,
+    /// tells this instance's entries in the ledger from those of others in a pool
+    instance: u64,
+}
+
+impl Contexts {
+    pub fn new(ledger: Ledger) -> Self {
+        Self {
+            requests: HashMap::new(),
+            ledger,
+            instance: rand::random(),
+        }
+    }
+
+    pub fn contains_id(&self, id: u64) -> bool {
+        self.requests.contains_key(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// track a request sent to `target`. a request with the same ID and target
+    /// replaces the old one, whose timeout is cancelled.
+    pub fn insert(
+        &mut self,
+        id: u64,
+        target: t::Address,
+        context: ProcessContext,
+        timeout_secs: u64,
+        timeout: JoinHandle<()>,
+    ) {
+        self.ledger
+            .sent(id, target.clone(), Duration::from_secs(timeout_secs));
+        let requests = self.requests.entry(id).or_default();
+        if let Some(i) = requests.iter().position(|(sent_to, ..)| *sent_to == target) {
+            let (_, _, replaced) = requests.swap_remove(i);
+            replaced.abort();
+        }
+        requests.push((target, context, timeout));
+    }
+
+    /// note how many messages we've set aside while awaiting a response
+    pub fn note_set_aside(&self, count: usize) {
+        self.ledger.set_aside(self.instance, count);
+    }
+
+    /// which request with this ID a message about `source` answers
+    fn position(&self, id: u64, source: &t::Address) -> Option<usize> {
+        let requests = self.requests.get(&id)?;
+        requests
+            .iter()
+            .position(|(sent_to, ..)| sent_to == source)
+            .or((requests.len() == 1).then_some(0))
+    }
+
+    /// whether a message with this ID about `source` answers the request sent to `target`
+    pub fn answers(&self, id: u64, source: &t::Address, target: &t::Address) -> bool {
+        self.position(id, source)
+            .is_some_and(|i| self.requests[&id][i].0 == *target)
+    }
+
+    /// cancel the timeout of the request a message answers.
+    /// returns false if it answers none.
+    pub fn cancel_timeout(&self, id: u64, source: &t::Address) -> bool {
+        let Some(i) = self.position(id, source) else {
+            return false;
+        };
+        self.requests[&id][i].2.abort();
+        true
+    }
+
+    /// stop tracking the request a message answers, returning its context
+    pub fn remove(&mut self, id: u64, source: &t::Address) -> Option<ProcessContext> {
+        let i = self.position(id, source)?;
+        let requests = self.requests.get_mut(&id)?;
+        let (target, context, _timeout) = requests.swap_remove(i);
+        if requests.is_empty() {
+            self.requests.remove(&id);
+        }
+        self.ledger.answered(id, target);
+        Some(context)
+    }
+}
+
+impl Drop for Contexts {
+    /// an instance that ends no longer waits on anything
+    fn drop(&mut self) {
+        for (id, requests) in self.requests.drain() {
+            for (target, ..) in requests {
+                self.ledger.answered(id, target);
+            }
+        }
+        self.ledger.set_aside(self.instance, 0);
+    }
+}
+
+/// `target` with "our" put as our node's name, as the event loop does, so
+/// that a response, which comes from our node's name, is matched to a
+/// request sent to it
+pub fn resolve_our(mut target: t::Address, our_node: &str) -> t::Address {
+    if target.node == "our" {
+        target.node = our_node.to_string();
+    }
+    target
+}
+
+/// the ID of a response or error, and the address it is from or about,
+/// which together identify the request it answers. `None` for requests.
+pub fn answer_key(
+    incoming: &Result<t::KernelMessage, t::WrappedSendError>,
+) -> Option<(u64, &t::Address)> {
+    match incoming {
+        Ok(km) => match km.message {
+            t::Message::Response(_) => Some((km.id, &km.source)),
+            t::Message::Request(_) => None,
+        },
+        Err(e) => Some((e.id, &e.error.target)),
+    }
+}
+
 pub struct ProcessState {
     /// our node's networking keypair
     pub keypair: Arc<ring::signature::Ed25519KeyPair>,
+    /// our node's sealing key and view of the PKI, for crypto host functions
+    pub keyring: Arc<super::crypto::Keyring>,
+    /// resolves blob handles in messages we receive
+    pub blob_store: Arc<crate::blobs::BlobStore>,
     /// information about ourself
     pub metadata: t::ProcessMetadata,
     /// pipe from which we get messages from the main event loop
@@ -45,12 +273,562 @@ pub struct ProcessState {
     pub prompting_message: Option<t::KernelMessage>,
     pub last_blob: Option<t::LazyLoadBlob>,
     /// store the contexts and timeout task of all outstanding requests
-    pub contexts: HashMap<u64, (ProcessContext, JoinHandle<()>)>,
+    pub contexts: Contexts,
     /// store the messages that we've gotten from event loop but haven't processed yet
     /// TODO make this an ordered map for O(1) retrieval by ID
     pub message_queue: VecDeque<Result<t::KernelMessage, t::WrappedSendError>>,
     /// pipe for getting info about capabilities
     pub caps_oracle: t::CapMessageSender,
+    /// in benchmark mode, the counters for this process
+    pub meter: Option<Meter>,
+    /// for a transient process, bounds on the fuel and memory it may use and
+    /// how long it may run
+    pub limits: t::ProcessLimits,
+    /// bounds on how long this process may wait for a response
+    pub timeouts: RequestTimeouts,
+    /// for a process whose manifest entry sets `hibernate_after`, how long it
+    /// may sit idle in `receive()` before its instance is dropped
+    pub hibernate_after: Option<Duration>,
+    /// set when the instance is being dropped for idleness, rather than crashing
+    pub hibernating: bool,
+    /// for an instance of a pooled process, where we tell the pool how many
+    /// messages we hold, so that it can pass over us while we are busy
+    pub held: Option<super::pool::Held>,
+    /// whether we've told the kernel we're ready, so that processes that
+    /// depend on us can run
+    pub reported_ready: bool,
+    /// in simulation mode with a seed, the generator for `random-bytes`.
+    /// otherwise, random bytes come from the system's secure generator.
+    pub rng: Option<rand::rngs::StdRng>,
+    /// where and why the message we last received failed, if it was an
+    /// error, and how net tried to reach its target, for `last-send-error()`
+    /// and `last-send-error-trace()`
+    pub last_send_error: Option<(t::SendErrorHop, t::SendErrorCause, Option<t::Resolution>)>,
+    /// when the message we last received was sent, by the node's clock, for
+    /// `message-time()`
+    pub last_message_time: u64,
+}
+
+impl ProcessState {
+    /// make an incoming message the current frame, as described on [`ProcessContext`].
+    /// returns the message with its blob moved into `last_blob`, and the context
+    /// saved with the request it answers, if any.
+    pub fn enter_message(
+        &mut self,
+        incoming: Result<t::KernelMessage, t::WrappedSendError>,
+    ) -> Result<(t::KernelMessage, Option<t::Context>), (t::SendError, Option<t::Context>)> {
+        let mut km = match incoming {
+            Ok(km) => {
+                self.last_send_error = None;
+                self.last_message_time = km.sent_at.map_or(0, |sent_at| {
+                    crate::clock::now().saturating_sub(sent_at.elapsed().as_millis() as u64)
+                });
+                km
+            }
+            Err(e) => {
+                self.last_send_error =
+                    Some((e.error.hop, e.error.cause, e.error.resolution.clone()));
+                self.last_message_time = 0;
+                let context = self
+                    .contexts
+                    .remove(e.id, &e.error.target)
+                    .and_then(|saved| {
+                        self.prompting_message = saved.prompting_message;
+                        saved.context
+                    });
+                return Err((e.error, context));
+            }
+        };
+        self.last_blob = km.lazy_load_blob.take();
+        let context = match km.message {
+            t::Message::Request(ref request) => {
+                // update the prompting message iff there is someone to reply to
+                if request.expects_response.is_some() || km.rsvp.is_some() {
+                    self.prompting_message = Some(km.clone());
+                }
+                None
+            }
+            t::Message::Response(_) => match self.contexts.remove(km.id, &km.source) {
+                Some(saved) => {
+                    self.prompting_message = saved.prompting_message;
+                    saved.context
+                }
+                // a response no request is waiting on: nothing to answer
+                None => {
+                    self.prompting_message = None;
+                    None
+                }
+            },
+        };
+        Ok((km, context))
+    }
+
+    /// tell our pool, if we are an instance of one, how many messages we
+    /// hold: those set aside, and, if `handling`, the one being handled
+    pub fn note_held(&self, handling: bool) {
+        if let Some(held) = &self.held {
+            held.set(self.message_queue.len() + handling as usize);
+        }
+    }
+
+    /// tell the kernel we're ready, the first time we're asked to
+    pub async fn report_ready(&mut self) {
+        if self.reported_ready {
+            return;
+        }
+        self.reported_ready = true;
+        t::KernelMessage::builder()
+            .id(rand::random())
+            .source((&self.metadata.our.node, KERNEL_PROCESS_ID.clone()))
+            .target((&self.metadata.our.node, KERNEL_PROCESS_ID.clone()))
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: None,
+                body: serde_json::to_vec(&t::KernelCommand::ProcessReady(
+                    self.metadata.our.process.clone(),
+                ))
+                .unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+            .send(&self.send_to_loop)
+            .await;
+    }
+
+    /// called before `receive()`: if we hibernate and have nothing to do, wait
+    /// at most our idle period for a message, and queue it. if none arrives,
+    /// errors to end the instance; [`make_process_loop`] then waits for the
+    /// next message and starts a new instance with it. an instance with
+    /// requests outstanding is never idle, since their responses need it.
+    pub async fn hibernate_if_idle(&mut self) -> anyhow::Result<()> {
+        let Some(idle) = self.hibernate_after else {
+            return Ok(());
+        };
+        if !self.message_queue.is_empty() || !self.contexts.is_empty() {
+            return Ok(());
+        }
+        // only the wait is timed out: a message taken in is always queued
+        let received = tokio::select! {
+            received = self.recv_in_process.recv() => received,
+            _ = idle_for(idle) => {
+                self.hibernating = true;
+                return Err(anyhow::anyhow!("hibernating after {idle:?} idle"));
+            }
+        };
+        // if our channel closed, let `receive()` handle it
+        if let Some(message) = received {
+            self.queue_resolved(message).await;
+        }
+        Ok(())
+    }
+
+    /// after hibernating, wait for the message that wakes us and queue it for
+    /// the new instance. `None` if our channel closed while we slept.
+    async fn wake(mut self) -> Option<Self> {
+        self.hibernating = false;
+        self.prompting_message = None;
+        self.last_blob = None;
+        let message = self.recv_in_process.recv().await?;
+        self.queue_resolved(message).await;
+        Some(self)
+    }
+
+    /// queue a message from the event loop for `receive()`, with its blob
+    /// resolved. responses and errors are queued too: `receive()` hands them
+    /// over as it would any other.
+    async fn queue_resolved(&mut self, mut message: Result<t::KernelMessage, t::WrappedSendError>) {
+        if let Ok(km) = &mut message {
+            if let Err(e) = self.blob_store.resolve(km).await {
+                t::Printout::new(
+                    2,
+                    format!("{}: couldn't read blob: {e}", self.metadata.our.process),
+                )
+                .send(&self.send_to_terminal)
+                .await;
+            }
+        }
+        self.message_queue.push_back(message);
+    }
+
+    /// whether a message answers the request with this ID sent to `target`
+    pub fn answers(
+        &self,
+        message: &Result<t::KernelMessage, t::WrappedSendError>,
+        id: u64,
+        target: &t::Address,
+    ) -> bool {
+        answer_key(message).is_some_and(|(answer_id, source)| {
+            answer_id == id && self.contexts.answers(id, source, target)
+        })
+    }
+
+    /// the ID of an outgoing request: the prompting message's if it inherits
+    /// one, and otherwise one that no outstanding request is using
+    pub fn outgoing_request_id(&self, inherit: bool) -> u64 {
+        if let (true, Some(prompt)) = (inherit, &self.prompting_message) {
+            return prompt.id;
+        }
+        loop {
+            let id = rand::random();
+            if !self.contexts.contains_id(id) {
+                break id;
+            }
+        }
+    }
+
+    /// where the response to an outgoing request should go: to us if we expect
+    /// it, or wherever the prompting message's response would go if inheriting
+    pub fn outgoing_request_rsvp(
+        &self,
+        expects_response: bool,
+        inherit: bool,
+    ) -> Option<t::Address> {
+        if expects_response {
+            // make sure to use the real source, not a fake injected-by-kernel source
+            return Some(self.metadata.our.clone());
+        }
+        if inherit {
+            return self.response_id_target().map(|(_id, target)| target);
+        }
+        None
+    }
+
+    /// the ID and target a response emitted now should have, if the process
+    /// has a prompting message to answer
+    pub fn response_id_target(&self) -> Option<(u64, t::Address)> {
+        let prompt = self.prompting_message.as_ref()?;
+        Some((
+            prompt.id,
+            prompt.rsvp.clone().unwrap_or_else(|| prompt.source.clone()),
+        ))
+    }
+
+    /// make a request generated by the process ready to send: give it its ID,
+    /// blob and rsvp, filter the capabilities it carries to those the process
+    /// holds, and bound its timeout. if it expects a response, note its
+    /// context and start a task that returns a timeout error if it expires.
+    /// the host functions of every version send requests through this.
+    pub async fn outgoing_request(
+        &mut self,
+        source: t::Address,
+        target: t::Address,
+        mut request: t::Request,
+        new_context: Option<t::Context>,
+        blob: Option<t::LazyLoadBlob>,
+    ) -> t::KernelMessage {
+        // if request chooses to inherit, it means to take the ID and lazy_load_blob,
+        // if any, from the last message it ingested. otherwise, id is generated randomly
+        let request_id = self.outgoing_request_id(request.inherit);
+
+        // if a blob is provided, it will be used; otherwise, if inherit is true,
+        // and a predecessor exists, its blob will be used; otherwise, no blob will be used.
+        let blob = match blob {
+            Some(blob) => Some(blob),
+            None => match request.inherit {
+                true => self.last_blob.clone(),
+                false => None,
+            },
+        };
+
+        if !request.capabilities.is_empty() {
+            request.capabilities = {
+                let (tx, rx) = tokio::sync::oneshot::channel();
+                self.caps_oracle
+                    .send(t::CapMessage::FilterCaps {
+                        on: self.metadata.our.process.clone(),
+                        caps: request
+                            .capabilities
+                            .into_iter()
+                            .map(|(cap, _)| cap)
+                            .collect(),
+                        responder: tx,
+                    })
+                    .await
+                    .expect("fatal: process couldn't access capabilities oracle");
+                rx.await
+                    .expect("fatal: process couldn't receive capabilities")
+            };
+        }
+
+        // bound the timeout. the effective timeout is echoed back in the
+        // request of a timeout error.
+        if let Some(timeout_secs) = request.expects_response {
+            request.expects_response = Some(self.timeouts.effective(timeout_secs));
+        }
+
+        // if the request expects a response, modify the process' context map as needed
+        // and set a timer.
+        // TODO optimize this SIGNIFICANTLY: stop spawning tasks
+        // and use a global clock + garbage collect step to check for timeouts
+        if let Some(timeout_secs) = request.expects_response {
+            let this_request = request.clone();
+            let this_blob = blob.clone();
+            let self_sender = self.self_sender.clone();
+            // a response to a request sent to "our" comes from our node's name
+            let original_target = resolve_our(target.clone(), &self.metadata.our.node);
+            // no response in time: we can't tell where the request or its
+            // response was lost, so name the furthest hop it was headed for
+            let hop = if original_target.node == self.metadata.our.node {
+                t::SendErrorHop::LocalKernel
+            } else {
+                t::SendErrorHop::RemoteKernel
+            };
+            let timed_out = original_target.clone();
+            let timeout_handle = tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_secs(timeout_secs)).await;
+                let _ = self_sender
+                    .send(Err(t::WrappedSendError {
+                        id: request_id,
+                        source: timed_out.clone(),
+                        error: t::SendError {
+                            kind: t::SendErrorKind::Timeout,
+                            target: timed_out,
+                            message: t::Message::Request(this_request),
+                            lazy_load_blob: this_blob,
+                            hop,
+                            cause: t::SendErrorCause::Timeout,
+                            resolution: None,
+                        },
+                    }))
+                    .await;
+            });
+            self.contexts.insert(
+                request_id,
+                original_target,
+                ProcessContext {
+                    prompting_message: self.prompting_message.clone(),
+                    context: new_context,
+                },
+                timeout_secs,
+                timeout_handle,
+            );
+        }
+
+        // rsvp is set based on this priority:
+        // 1. whether this request expects a response -- if so, rsvp = our address, always
+        // 2. whether this request inherits -- if so, rsvp = prompting message's response target
+        // 3. if neither, rsvp = None
+        let rsvp = self.outgoing_request_rsvp(request.expects_response.is_some(), request.inherit);
+        t::KernelMessage::builder()
+            .id(request_id)
+            .source(source)
+            .target(target)
+            .rsvp(rsvp)
+            .message(t::Message::Request(request))
+            .lazy_load_blob(blob)
+            .build()
+            .unwrap()
+    }
+}
+
+/// most seconds any request may wait for a response, whatever the node is
+/// configured with
+const MAX_REQUEST_TIMEOUT: u64 = 30 * 24 * 60 * 60;
+
+/// bounds on how long a process may wait for a response, set for the node with
+/// `--default-request-timeout` and `--max-request-timeout`, and for a process
+/// with `default_request_timeout` and `max_request_timeout` in its manifest entry.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestTimeouts {
+    /// seconds to wait for a request that expects a response in 0 seconds
+    pub default: u64,
+    /// most seconds to wait for any response
+    pub max: u64,
+}
+
+impl RequestTimeouts {
+    /// bounds clamped so that a request waits at least a second and at most
+    /// [`MAX_REQUEST_TIMEOUT`], and the default is no longer than the max
+    pub fn new(default: u64, max: u64) -> Self {
+        let max = max.clamp(1, MAX_REQUEST_TIMEOUT);
+        Self {
+            default: default.clamp(1, max),
+            max,
+        }
+    }
+
+    /// a manifest entry may lower the node's bounds, but never raise them
+    /// past `--max-request-timeout`
+    pub fn overridden_by(self, manifest_entry: Option<&t::PackageManifestEntry>) -> Self {
+        let Some(entry) = manifest_entry else {
+            return self;
+        };
+        Self::new(
+            entry.default_request_timeout.unwrap_or(self.default),
+            entry.max_request_timeout.unwrap_or(self.max).min(self.max),
+        )
+    }
+
+    /// the timeout a request asking for `requested` seconds gets
+    pub fn effective(&self, requested: u64) -> u64 {
+        match requested {
+            0 => self.default,
+            requested => requested.min(self.max),
+        }
+    }
+}
+
+/// measures the resources a process uses handling each message, when the node
+/// runs with `--bench`. fuel and time are counted from when `receive()` returns
+/// a message to when the process calls it again.
+pub struct Meter {
+    stats: Arc<Mutex<t::ProcessStats>>,
+    /// fuel remaining when the current message was received
+    fuel_mark: u64,
+    /// when the current message was received
+    started: Option<Instant>,
+}
+
+impl Meter {
+    pub fn new(stats: Arc<Mutex<t::ProcessStats>>) -> Self {
+        Self {
+            stats,
+            fuel_mark: 0,
+            started: None,
+        }
+    }
+
+    /// a meter for another instance of the same process, adding to its counters
+    pub fn share(&self) -> Self {
+        Self::new(self.stats.clone())
+    }
+
+    fn end_message(&mut self, fuel_remaining: u64) {
+        let Some(started) = self.started.take() else {
+            return;
+        };
+        let mut stats = self.stats.lock().unwrap();
+        stats.fuel_consumed += self.fuel_mark.saturating_sub(fuel_remaining);
+        stats.busy_micros += started.elapsed().as_micros() as u64;
+    }
+
+    fn start_message(&mut self, fuel_remaining: u64) {
+        self.fuel_mark = fuel_remaining;
+        self.started = Some(Instant::now());
+        self.stats.lock().unwrap().messages += 1;
+    }
+
+    pub fn blob_copied(&self, blob: &Option<t::LazyLoadBlob>) {
+        if let Some(blob) = blob {
+            let mut stats = self.stats.lock().unwrap();
+            stats.blob_copies += 1;
+            stats.blob_bytes_copied += blob.bytes.len() as u64;
+        }
+    }
+}
+
+impl ResourceLimiter for Meter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        let mut stats = self.stats.lock().unwrap();
+        stats.memory_grows += 1;
+        stats.memory_bytes_grown += desired.saturating_sub(current) as u64;
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        _desired: u32,
+        _maximum: Option<u32>,
+    ) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+}
+
+/// refuse a process memory past its limit, and in benchmark mode, count
+/// what it grows by
+impl ResourceLimiter for ProcessState {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        if let Some(memory_bytes) = self.limits.memory_bytes {
+            if desired as u64 > memory_bytes {
+                return Err(LimitReached(format!("memory limit of {memory_bytes} bytes")).into());
+            }
+        }
+        match &mut self.meter {
+            Some(meter) => meter.memory_growing(current, desired, maximum),
+            None => Ok(true),
+        }
+    }
+
+    fn table_growing(
+        &mut self,
+        current: u32,
+        desired: u32,
+        maximum: Option<u32>,
+    ) -> anyhow::Result<bool> {
+        match &mut self.meter {
+            Some(meter) => meter.table_growing(current, desired, maximum),
+            None => Ok(true),
+        }
+    }
+}
+
+/// the error a process ends with when it goes past one of its limits
+#[derive(Debug)]
+struct LimitReached(String);
+
+impl std::fmt::Display for LimitReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "reached its {}", self.0)
+    }
+}
+
+impl std::error::Error for LimitReached {}
+
+/// wait out a hibernating process's idle period: in simulation mode, on the
+/// simulation's clock, so that it is virtual time when timers are
+async fn idle_for(duration: Duration) {
+    #[cfg(feature = "simulation-mode")]
+    crate::sim::sleep(duration).await;
+    #[cfg(not(feature = "simulation-mode"))]
+    tokio::time::sleep(duration).await;
+}
+
+/// run `init` to its end, or until the process has run as long as it may
+async fn within_wall_time(
+    init: impl std::future::Future<Output = anyhow::Result<()>>,
+    wall_time_secs: Option<u64>,
+) -> anyhow::Result<()> {
+    let Some(secs) = wall_time_secs else {
+        return init.await;
+    };
+    tokio::time::timeout(Duration::from_secs(secs), init)
+        .await
+        .unwrap_or_else(|_| Err(LimitReached(format!("wall time limit of {secs}s")).into()))
+}
+
+/// if a process ended for going past one of its limits, say which
+async fn print_limit_reached(
+    our: &t::Address,
+    error: &anyhow::Error,
+    limits: &t::ProcessLimits,
+    send_to_terminal: &t::PrintSender,
+) {
+    let reached = if let Some(reached) = error.downcast_ref::<LimitReached>() {
+        reached.to_string()
+    } else if error.downcast_ref::<wasmtime::Trap>() == Some(&wasmtime::Trap::OutOfFuel) {
+        format!(
+            "reached its fuel limit of {}",
+            limits.fuel.unwrap_or(u64::MAX)
+        )
+    } else {
+        return;
+    };
+    t::Printout::new(0, format!("kernel: stopped process {our}: it {reached}"))
+        .send(send_to_terminal)
+        .await;
 }
 
 pub struct ProcessWasi {
@@ -119,22 +897,441 @@ async fn make_table_and_wasi(
         }
     }
 
+    mount_package_dir(&mut wasi, &home_directory_path, process_state).await;
+
     (table, wasi.stderr(wasi_stderr.clone()).build(), wasi_stderr)
 }
 
+/// if the process holds the `"mount-read"` or `"mount-write"` kernel capability,
+/// preopen its package's vfs directory at `/<package_id>`, so that libraries
+/// using std::fs see the same paths as the vfs, e.g. `/<package_id>/pkg/file`.
+/// the capability is requested in the manifest like any other.
+async fn mount_package_dir(
+    wasi: &mut WasiCtxBuilder,
+    home_directory_path: &str,
+    process_state: &ProcessState,
+) {
+    let (dir_perms, file_perms) = if has_kernel_cap(process_state, MOUNT_WRITE_CAP_PARAMS).await {
+        (DirPerms::all(), FilePerms::all())
+    } else if has_kernel_cap(process_state, MOUNT_READ_CAP_PARAMS).await {
+        (DirPerms::READ, FilePerms::READ)
+    } else {
+        return;
+    };
+    let package_id = format!(
+        "{}:{}",
+        process_state.metadata.our.process.package(),
+        process_state.metadata.our.process.publisher()
+    );
+    let package_path = format!("{home_directory_path}/vfs/{package_id}");
+    match Dir::open_ambient_dir(&package_path, wasi_common::sync::ambient_authority()) {
+        Ok(package_dir) => {
+            if dir_perms.contains(DirPerms::MUTATE) {
+                // its writes don't go through the vfs, so backups must copy it
+                crate::quiesce::mounted_writable(std::path::Path::new(&package_path));
+            }
+            wasi.preopened_dir(package_dir, dir_perms, file_perms, format!("/{package_id}"))
+                .env("PACKAGE_DIR", format!("/{package_id}"));
+        }
+        Err(e) => {
+            t::Printout::new(
+                1,
+                format!(
+                    "{}: couldn't mount package directory: {e}",
+                    process_state.metadata.our.process
+                ),
+            )
+            .send(&process_state.send_to_terminal)
+            .await;
+        }
+    }
+}
+
+/// in benchmark mode, replace the `receive` import with one that meters the
+/// message the process has just finished handling before fetching the next
+fn meter_receive(linker: &mut Linker<ProcessWasi>) -> anyhow::Result<()> {
+    linker.allow_shadowing(true);
+    linker
+        .instance("kinode:process/standard@0.7.0")?
+        .func_wrap_async(
+            "receive",
+            |mut store: StoreContextMut<'_, ProcessWasi>, _: ()| {
+                Box::new(async move {
+                    let fuel = store.get_fuel()?;
+                    if let Some(meter) = store.data_mut().process.meter.as_mut() {
+                        meter.end_message(fuel);
+                    }
+                    let message = lib::wit::Host::receive(store.data_mut()).await?;
+                    let fuel = store.get_fuel()?;
+                    if let Some(meter) = store.data_mut().process.meter.as_mut() {
+                        meter.start_message(fuel);
+                    }
+                    Ok((message,))
+                })
+            },
+        )
+}
+
+fn meter_receive_v0(linker: &mut Linker<ProcessWasiV0>) -> anyhow::Result<()> {
+    linker.allow_shadowing(true);
+    linker
+        .instance("kinode:process/standard@0.8.0")?
+        .func_wrap_async(
+            "receive",
+            |mut store: StoreContextMut<'_, ProcessWasiV0>, _: ()| {
+                Box::new(async move {
+                    let fuel = store.get_fuel()?;
+                    if let Some(meter) = store.data_mut().process.meter.as_mut() {
+                        meter.end_message(fuel);
+                    }
+                    let message = lib::v0::wit::Host::receive(store.data_mut()).await?;
+                    let fuel = store.get_fuel()?;
+                    if let Some(meter) = store.data_mut().process.meter.as_mut() {
+                        meter.start_message(fuel);
+                    }
+                    Ok((message,))
+                })
+            },
+        )
+}
+
+/// define the `log(level, target, message)` import. lines are tagged with the
+/// process ID and level, so the terminal can filter and color them per
+/// process.
+fn add_log<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    interface: &str,
+    state: fn(&mut T) -> &mut ProcessState,
+) -> anyhow::Result<()> {
+    linker.instance(interface)?.func_wrap_async(
+        "log",
+        move |mut store: StoreContextMut<'_, T>, (level, target, message): (u8, String, String)| {
+            Box::new(async move {
+                let process = state(store.data_mut());
+                let content = if target.is_empty() {
+                    message
+                } else {
+                    format!("{target}: {message}")
+                };
+                t::Printout::log(
+                    process.metadata.our.process.clone(),
+                    t::LogLevel::from_u8(level),
+                    content,
+                )
+                .send(&process.send_to_terminal)
+                .await;
+                Ok(())
+            })
+        },
+    )
+}
+
+/// define the `ready()` import: tell the kernel this process has finished
+/// setting up, so that processes that depend on it can run. a process that
+/// never calls it is ready when it first calls `receive()`.
+fn add_ready<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    interface: &str,
+    state: fn(&mut T) -> &mut ProcessState,
+) -> anyhow::Result<()> {
+    linker.instance(interface)?.func_wrap_async(
+        "ready",
+        move |mut store: StoreContextMut<'_, T>, _: ()| {
+            Box::new(async move {
+                state(store.data_mut()).report_ready().await;
+                Ok(())
+            })
+        },
+    )
+}
+
+/// cap on the bytes a single `random-bytes` call may ask for
+const MAX_RANDOM_BYTES: u64 = 1024 * 1024;
+
+/// define the `random-bytes(len)` import: cryptographically secure random
+/// bytes, or deterministic ones in simulation mode booted with a seed.
+fn add_random_bytes<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    interface: &str,
+    state: fn(&mut T) -> &mut ProcessState,
+) -> anyhow::Result<()> {
+    linker.instance(interface)?.func_wrap(
+        "random-bytes",
+        move |mut store: StoreContextMut<'_, T>, (len,): (u64,)| {
+            if len > MAX_RANDOM_BYTES {
+                return Err(anyhow::anyhow!(
+                    "random-bytes: asked for {len} bytes, max is {MAX_RANDOM_BYTES}"
+                ));
+            }
+            let mut bytes = vec![0u8; len as usize];
+            match state(store.data_mut()).rng.as_mut() {
+                Some(rng) => rand::RngCore::fill_bytes(rng, &mut bytes),
+                None => {
+                    ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes)
+                        .map_err(|_| anyhow::anyhow!("random-bytes: system entropy unavailable"))?
+                }
+            }
+            Ok((bytes,))
+        },
+    )
+}
+
+/// define the `last-send-error()` import: if the message last received was
+/// a send error, the hop it failed at and its cause, as the codes of
+/// [`t::SendErrorHop`] and [`t::SendErrorCause`]. the WIT send-error has only
+/// `offline` and `timeout`, so this tells, say, a peer that is offline apart
+/// from a target process that doesn't exist.
+///
+/// also define `last-send-error-trace()`: for a target node that couldn't be
+/// reached, how net tried to reach it, as a [`t::Resolution`] written out.
+fn add_send_error_detail<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    interface: &str,
+    state: fn(&mut T) -> &mut ProcessState,
+) -> anyhow::Result<()> {
+    let mut instance = linker.instance(interface)?;
+    instance.func_wrap(
+        "last-send-error",
+        move |mut store: StoreContextMut<'_, T>, _: ()| {
+            let detail = state(store.data_mut())
+                .last_send_error
+                .as_ref()
+                .map(|(hop, cause, _)| (*hop as u8, *cause as u8));
+            Ok((detail,))
+        },
+    )?;
+    instance.func_wrap(
+        "last-send-error-trace",
+        move |mut store: StoreContextMut<'_, T>, _: ()| {
+            let trace = state(store.data_mut())
+                .last_send_error
+                .as_ref()
+                .and_then(|(_, _, resolution)| resolution.as_ref())
+                .map(|resolution| resolution.to_string());
+            Ok((trace,))
+        },
+    )?;
+    Ok(())
+}
+
+/// define the clock imports:
+/// - `clock()`: the node's time in milliseconds since the unix epoch, the
+///   milliseconds added to the host's time to get it, and how many it may be
+///   off by, if the node's clock has been synced. see [`crate::clock`].
+/// - `message-time()`: when the message last received was sent, by the same
+///   clock, or 0 if it is not known.
+fn add_clock<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    interface: &str,
+    state: fn(&mut T) -> &mut ProcessState,
+) -> anyhow::Result<()> {
+    let mut instance = linker.instance(interface)?;
+    instance.func_wrap("clock", move |_: StoreContextMut<'_, T>, _: ()| {
+        let estimate = crate::clock::estimate();
+        Ok(((estimate.now, estimate.offset, estimate.error),))
+    })?;
+    instance.func_wrap(
+        "message-time",
+        move |mut store: StoreContextMut<'_, T>, _: ()| {
+            Ok((state(store.data_mut()).last_message_time,))
+        },
+    )
+}
+
+/// define the keyed state imports, which persist parts of a process's state
+/// without rewriting all of it:
+/// - `get-state-key(key)`: the bytes saved under `key`, if any.
+/// - `update-state(changes)`: set each key to its bytes, or delete it if it
+///   has none, all at once.
+///
+/// keys are kept beside the state saved with `set-state`, and `clear-state`
+/// deletes them with it.
+fn add_state_keys<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    interface: &str,
+    state: fn(&mut T) -> &mut ProcessState,
+) -> anyhow::Result<()> {
+    let mut instance = linker.instance(interface)?;
+    instance.func_wrap_async(
+        "get-state-key",
+        move |mut store: StoreContextMut<'_, T>, (key,): (String,)| {
+            Box::new(async move {
+                let process = state(store.data_mut());
+                let action = t::StateAction::GetStateKey {
+                    process: process.metadata.our.process.clone(),
+                    key,
+                };
+                match process.state_request(action, None).await? {
+                    (t::StateResponse::GetStateKey, bytes) => Ok((bytes,)),
+                    _ => Ok((None,)),
+                }
+            })
+        },
+    )?;
+    instance.func_wrap_async(
+        "update-state",
+        move |mut store: StoreContextMut<'_, T>, (changes,): (Vec<(String, Option<Vec<u8>>)>,)| {
+            Box::new(async move {
+                let process = state(store.data_mut());
+                let action = t::StateAction::UpdateState(process.metadata.our.process.clone());
+                let changes = rmp_serde::to_vec(&changes)?;
+                Ok((match process.state_request(action, Some(changes)).await? {
+                    (t::StateResponse::UpdateState, _) => Ok(()),
+                    (t::StateResponse::Err(e), _) => Err(e.to_string()),
+                    _ => Err("state gave the wrong kind of response".to_string()),
+                },))
+            })
+        },
+    )
+}
+
+/// whether a process holds a capability issued by our kernel
+async fn has_kernel_cap(process: &ProcessState, params: &str) -> bool {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let _ = process
+        .caps_oracle
+        .send(t::CapMessage::Has {
+            on: process.metadata.our.process.clone(),
+            cap: t::Capability::new(
+                (
+                    process.metadata.our.node.as_str(),
+                    KERNEL_PROCESS_ID.clone(),
+                ),
+                params,
+            ),
+            responder: tx,
+        })
+        .await;
+    rx.await.unwrap_or(false)
+}
+
+/// tell the kernel that `child` was spawned by this process, so that the child
+/// is killed when this process exits
+pub async fn adopt_child(process: &ProcessState, child: &t::ProcessId) {
+    t::KernelMessage::builder()
+        .id(rand::random())
+        .source((&process.metadata.our.node, KERNEL_PROCESS_ID.clone()))
+        .target((&process.metadata.our.node, KERNEL_PROCESS_ID.clone()))
+        .message(t::Message::Request(t::Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&t::KernelCommand::AdoptChild {
+                parent: process.metadata.our.process.clone(),
+                child: child.clone(),
+            })
+            .unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .unwrap()
+        .send(&process.send_to_loop)
+        .await;
+}
+
+/// define the crypto imports, which use our node's keys without exposing them:
+/// - `sign(message)`: sign with our networking key. requires the `"sign"` kernel capability.
+/// - `verify(from, message, signature)`: verify a signature made by another address.
+/// - `seal(node, message)`: encrypt a message that only `node` can open.
+/// - `open(sealed)`: decrypt a message sealed to our node. requires the `"decrypt"` kernel capability.
+fn add_crypto<T: Send + 'static>(
+    linker: &mut Linker<T>,
+    interface: &str,
+    state: fn(&mut T) -> &mut ProcessState,
+) -> anyhow::Result<()> {
+    let mut instance = linker.instance(interface)?;
+    instance.func_wrap_async(
+        "sign",
+        move |mut store: StoreContextMut<'_, T>, (message,): (Vec<u8>,)| {
+            Box::new(async move {
+                let process = state(store.data_mut());
+                if !has_kernel_cap(process, super::crypto::SIGN_CAP_PARAMS).await {
+                    return Ok((Err::<Vec<u8>, String>("missing sign capability".into()),));
+                }
+                Ok((Ok(super::crypto::sign(
+                    &process.keypair,
+                    &process.metadata.our,
+                    &message,
+                )),))
+            })
+        },
+    )?;
+    instance.func_wrap(
+        "verify",
+        move |mut store: StoreContextMut<'_, T>,
+              (from, message, signature): (String, Vec<u8>, Vec<u8>)| {
+            let Ok(from) = from.parse::<t::Address>() else {
+                return Ok((false,));
+            };
+            Ok((state(store.data_mut())
+                .keyring
+                .verify(&from, &message, &signature),))
+        },
+    )?;
+    instance.func_wrap(
+        "seal",
+        move |mut store: StoreContextMut<'_, T>, (node, message): (String, Vec<u8>)| {
+            Ok((state(store.data_mut()).keyring.seal(&node, &message),))
+        },
+    )?;
+    instance.func_wrap_async(
+        "open",
+        move |mut store: StoreContextMut<'_, T>, (sealed,): (Vec<u8>,)| {
+            Box::new(async move {
+                let process = state(store.data_mut());
+                if !has_kernel_cap(process, super::crypto::DECRYPT_CAP_PARAMS).await {
+                    return Ok((Err::<Vec<u8>, String>("missing decrypt capability".into()),));
+                }
+                Ok((process.keyring.open(&sealed),))
+            })
+        },
+    )
+}
+
+/// compile a process's wasm off the async runtime, waiting for a permit,
+/// unless it was compiled before
+async fn compile(engine: &Engine, wasm_bytes: &[u8]) -> anyhow::Result<Component> {
+    let hash = blake3::hash(wasm_bytes);
+    if let Some(component) = COMPONENT_CACHE.lock().unwrap().get(&hash) {
+        return Ok(component.clone());
+    }
+    let _permit = COMPILE_PERMITS.acquire().await?;
+    let engine = engine.clone();
+    let wasm_bytes = wasm_bytes.to_vec();
+    let component =
+        tokio::task::spawn_blocking(move || Component::new(&engine, wasm_bytes)).await??;
+    let mut cache = COMPONENT_CACHE.lock().unwrap();
+    if cache.len() >= COMPONENT_CACHE_SIZE {
+        // make room: any entry will do, since a miss only costs a compile
+        let evicted = *cache.keys().next().unwrap();
+        cache.remove(&evicted);
+    }
+    cache.insert(hash, component.clone());
+    Ok(component)
+}
+
 async fn make_component(
     engine: Engine,
-    wasm_bytes: &[u8],
+    component: &Component,
     home_directory_path: String,
     process_state: ProcessState,
 ) -> anyhow::Result<(Process, Store<ProcessWasi>, MemoryOutputPipe)> {
-    let component =
-        Component::new(&engine, wasm_bytes.to_vec()).expect("make_component: couldn't read file");
-
     let mut linker = Linker::new(&engine);
     Process::add_to_linker(&mut linker, |state: &mut ProcessWasi| state).unwrap();
     let (table, wasi, wasi_stderr) = make_table_and_wasi(home_directory_path, &process_state).await;
     wasmtime_wasi::command::add_to_linker(&mut linker).unwrap();
+    add_log(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_random_bytes(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_crypto(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_ready(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_send_error_detail(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_state_keys(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_clock(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    let metered = process_state.meter.is_some();
+    if metered {
+        meter_receive(&mut linker)?;
+    }
+    let limits = process_state.limits.clone();
 
     let our_process_id = process_state.metadata.our.process.clone();
     let send_to_terminal = process_state.send_to_terminal.clone();
@@ -147,9 +1344,21 @@ async fn make_component(
             wasi,
         },
     );
+    // the engine counts fuel only in benchmark mode or with --script-limits,
+    // and then every process needs some: what its limit allows, or all it
+    // could need. setting it fails if the engine doesn't count fuel.
+    let counts_fuel = store.set_fuel(limits.fuel.unwrap_or(u64::MAX)).is_ok();
+    if metered || limits.memory_bytes.is_some() {
+        store.limiter(|state| &mut state.process);
+    }
+    if counts_fuel && limits.wall_time_secs.is_some() {
+        // yield now and then, so that a process that never awaits can still
+        // be stopped when its time is up
+        store.fuel_async_yield_interval(Some(WALL_TIME_YIELD_FUEL))?;
+    }
 
     let (bindings, _bindings) =
-        match Process::instantiate_async(&mut store, &component, &linker).await {
+        match Process::instantiate_async(&mut store, component, &linker).await {
             Ok(b) => b,
             Err(e) => {
                 t::Printout::new(
@@ -167,17 +1376,26 @@ async fn make_component(
 
 async fn make_component_v0(
     engine: Engine,
-    wasm_bytes: &[u8],
+    component: &Component,
     home_directory_path: String,
     process_state: ProcessState,
 ) -> anyhow::Result<(ProcessV0, Store<ProcessWasiV0>, MemoryOutputPipe)> {
-    let component =
-        Component::new(&engine, wasm_bytes.to_vec()).expect("make_component: couldn't read file");
-
     let mut linker = Linker::new(&engine);
     ProcessV0::add_to_linker(&mut linker, |state: &mut ProcessWasiV0| state).unwrap();
     let (table, wasi, wasi_stderr) = make_table_and_wasi(home_directory_path, &process_state).await;
     wasmtime_wasi::command::add_to_linker(&mut linker).unwrap();
+    add_log(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_random_bytes(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_crypto(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_ready(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_send_error_detail(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_state_keys(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    add_clock(&mut linker, EXT_INTERFACE, |wasi| &mut wasi.process)?;
+    let metered = process_state.meter.is_some();
+    if metered {
+        meter_receive_v0(&mut linker)?;
+    }
+    let limits = process_state.limits.clone();
 
     let our_process_id = process_state.metadata.our.process.clone();
     let send_to_terminal = process_state.send_to_terminal.clone();
@@ -190,9 +1408,21 @@ async fn make_component_v0(
             wasi,
         },
     );
+    // the engine counts fuel only in benchmark mode or with --script-limits,
+    // and then every process needs some: what its limit allows, or all it
+    // could need. setting it fails if the engine doesn't count fuel.
+    let counts_fuel = store.set_fuel(limits.fuel.unwrap_or(u64::MAX)).is_ok();
+    if metered || limits.memory_bytes.is_some() {
+        store.limiter(|state| &mut state.process);
+    }
+    if counts_fuel && limits.wall_time_secs.is_some() {
+        // yield now and then, so that a process that never awaits can still
+        // be stopped when its time is up
+        store.fuel_async_yield_interval(Some(WALL_TIME_YIELD_FUEL))?;
+    }
 
     let (bindings, _bindings) =
-        match ProcessV0::instantiate_async(&mut store, &component, &linker).await {
+        match ProcessV0::instantiate_async(&mut store, component, &linker).await {
             Ok(b) => b,
             Err(e) => {
                 t::Printout::new(
@@ -208,9 +1438,97 @@ async fn make_component_v0(
     Ok((bindings, store, wasi_stderr))
 }
 
+/// print what a crashed process wrote to stderr, and return it
+async fn print_crash(
+    our: &t::Address,
+    wasi_stderr: &MemoryOutputPipe,
+    send_to_terminal: &t::PrintSender,
+) -> anyhow::Result<String> {
+    let stderr = String::from_utf8(wasi_stderr.contents().into())?;
+    t::Printout::new(
+        0,
+        format!("\x1b[38;5;196mprocess {our} ended with error:\x1b[0m\n{stderr}",),
+    )
+    .send(send_to_terminal)
+    .await;
+    Ok(stderr)
+}
+
+/// note a crash or exit of `process` in `log`, and return how many it has
+/// had within [`CRASH_LOOP_WINDOW`]
+fn record(
+    log: &dashmap::DashMap<t::ProcessId, VecDeque<Instant>>,
+    process: &t::ProcessId,
+) -> usize {
+    let now = Instant::now();
+    let mut events = log.entry(process.clone()).or_default();
+    while events
+        .front()
+        .is_some_and(|at| now.duration_since(*at) > CRASH_LOOP_WINDOW)
+    {
+        events.pop_front();
+    }
+    events.push_back(now);
+    events.len()
+}
+
+/// how long to wait before restarting a process after its `exits`th recent exit
+fn restart_backoff(exits: usize) -> Duration {
+    RESTART_BACKOFF_BASE
+        .saturating_mul(1 << exits.saturating_sub(1).min(16))
+        .min(RESTART_BACKOFF_MAX)
+}
+
+/// the last [`CRASH_EXCERPT_LEN`] bytes or so of `stderr`
+fn stderr_excerpt(stderr: &str) -> &str {
+    let stderr = stderr.trim_end();
+    let mut start = stderr.len().saturating_sub(CRASH_EXCERPT_LEN);
+    while !stderr.is_char_boundary(start) {
+        start += 1;
+    }
+    &stderr[start..]
+}
+
+/// tell the user that `our` is in a crash loop and won't be restarted: notify
+/// shows it in the terminal and on the homepage, and forwards it
+async fn notify_crash_loop(
+    our: &t::Address,
+    crashes: usize,
+    stderr: &str,
+    send_to_loop: &t::MessageSender,
+) {
+    let notification = t::Notification {
+        title: format!("{} is crash looping and was stopped", our.process),
+        body: format!(
+            "it crashed {crashes} times within {} minutes; reinstall its package to start it again. it last wrote to stderr:\n{}",
+            CRASH_LOOP_WINDOW.as_secs() / 60,
+            stderr_excerpt(stderr),
+        ),
+        urgency: t::Urgency::Critical,
+        action: None,
+    };
+    t::KernelMessage::builder()
+        .id(rand::random())
+        .source((&our.node, KERNEL_PROCESS_ID.clone()))
+        .target((&our.node, t::NOTIFY_PROCESS_ID.clone()))
+        .message(t::Message::Request(t::Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&t::NotifyRequest::Post(notification)).unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .unwrap()
+        .send(send_to_loop)
+        .await;
+}
+
 /// create a specific process, and generate a task that will run it.
 pub async fn make_process_loop(
     keypair: Arc<ring::signature::Ed25519KeyPair>,
+    keyring: Arc<super::crypto::Keyring>,
+    blob_store: Arc<crate::blobs::BlobStore>,
     metadata: t::ProcessMetadata,
     send_to_loop: t::MessageSender,
     send_to_terminal: t::PrintSender,
@@ -220,7 +1538,29 @@ pub async fn make_process_loop(
     caps_oracle: t::CapMessageSender,
     engine: Engine,
     home_directory_path: String,
+    meter: Option<Meter>,
+    timeouts: RequestTimeouts,
+    hibernate_after: Option<Duration>,
+    ledger: Ledger,
+    limits: t::ProcessLimits,
+    held: Option<super::pool::Held>,
 ) -> anyhow::Result<()> {
+    // compile while we wait to be run, so that processes started together,
+    // as at boot, compile in parallel. a hibernating process keeps this
+    // to start its new instances.
+    let component = match compile(&engine, &wasm_bytes).await {
+        Ok(component) => component,
+        Err(e) => {
+            t::Printout::new(
+                0,
+                format!("kernel: process {} failed to compile: {e:?}", metadata.our),
+            )
+            .send(&send_to_terminal)
+            .await;
+            return Err(e);
+        }
+    };
+
     // before process can be instantiated, need to await 'run' message from kernel
     let mut pre_boot_queue = Vec::<Result<t::KernelMessage, t::WrappedSendError>>::new();
     while let Some(message) = recv_in_process.recv().await {
@@ -258,8 +1598,15 @@ pub async fn make_process_loop(
     let our = metadata.our.clone();
     let wit_version = metadata.wit_version.clone();
 
-    let process_state = ProcessState {
+    #[cfg(feature = "simulation-mode")]
+    let rng = crate::sim::process_rng(&metadata.our.process);
+    #[cfg(not(feature = "simulation-mode"))]
+    let rng = None;
+
+    let mut process_state = ProcessState {
         keypair,
+        keyring,
+        blob_store,
         metadata,
         recv_in_process,
         self_sender: send_to_process,
@@ -267,73 +1614,152 @@ pub async fn make_process_loop(
         send_to_terminal: send_to_terminal.clone(),
         prompting_message: None,
         last_blob: None,
-        contexts: HashMap::new(),
+        contexts: Contexts::new(ledger),
         message_queue: VecDeque::new(),
         caps_oracle: caps_oracle.clone(),
+        meter,
+        limits,
+        timeouts,
+        hibernate_after,
+        hibernating: false,
+        held,
+        reported_ready: false,
+        last_send_error: None,
+        last_message_time: 0,
+        rng,
     };
 
-    let metadata = match wit_version {
-        // assume missing version is oldest wit version
-        None => {
-            let (bindings, mut store, wasi_stderr) =
-                make_component(engine, &wasm_bytes, home_directory_path, process_state).await?;
-
-            // the process will run until it returns from init() or crashes
-            match bindings.call_init(&mut store, &our.to_string()).await {
-                Ok(()) => {
-                    t::Printout::new(1, format!("process {our} returned without error"))
-                        .send(&send_to_terminal)
-                        .await;
-                }
-                Err(_) => {
-                    let stderr = wasi_stderr.contents().into();
-                    let stderr = String::from_utf8(stderr)?;
-                    t::Printout::new(
-                        0,
-                        format!("\x1b[38;5;196mprocess {our} ended with error:\x1b[0m\n{stderr}",),
-                    )
-                    .send(&send_to_terminal)
-                    .await;
-                }
-            };
+    // the process runs until it returns from init() or crashes. if instead it
+    // hibernates, run a new instance when a message wakes it.
+    let (mut metadata, crash) = loop {
+        let (ended, crash) = match wit_version {
+            // assume missing version is oldest wit version
+            None => {
+                let (bindings, mut store, wasi_stderr) = make_component(
+                    engine.clone(),
+                    &component,
+                    home_directory_path.clone(),
+                    process_state,
+                )
+                .await?;
 
-            // update metadata to what was mutated by process in store
-            store.data().process.metadata.to_owned()
-        }
-        // match version numbers
-        // assume higher uncovered version number is latest version
-        Some(0) | _ => {
-            let (bindings, mut store, wasi_stderr) =
-                make_component_v0(engine, &wasm_bytes, home_directory_path, process_state).await?;
-
-            // the process will run until it returns from init() or crashes
-            match bindings.call_init(&mut store, &our.to_string()).await {
-                Ok(()) => {
-                    t::Printout::new(1, format!("process {our} returned without error"))
-                        .send(&send_to_terminal)
-                        .await;
-                }
-                Err(_) => {
-                    let stderr = wasi_stderr.contents().into();
-                    let stderr = String::from_utf8(stderr)?;
-                    t::Printout::new(
-                        0,
-                        format!("\x1b[38;5;196mprocess {our} ended with error:\x1b[0m\n{stderr}",),
-                    )
-                    .send(&send_to_terminal)
-                    .await;
-                }
-            };
+                let limits = store.data().process.limits.clone();
+                let init = bindings.call_init(&mut store, &our.to_string());
+                #[cfg(feature = "simulation-mode")]
+                let init = crate::faults::crashable(&our.process, init);
+                let crash = match within_wall_time(init, limits.wall_time_secs).await {
+                    Ok(()) => {
+                        t::Printout::new(1, format!("process {our} returned without error"))
+                            .send(&send_to_terminal)
+                            .await;
+                        None
+                    }
+                    Err(_) if store.data().process.hibernating => None,
+                    Err(e) => {
+                        print_limit_reached(&our, &e, &limits, &send_to_terminal).await;
+                        Some(print_crash(&our, &wasi_stderr, &send_to_terminal).await?)
+                    }
+                };
+
+                // keep what was mutated by process in store
+                (store.into_data().process, crash)
+            }
+            // match version numbers
+            // assume higher uncovered version number is latest version
+            Some(0) | _ => {
+                let (bindings, mut store, wasi_stderr) = make_component_v0(
+                    engine.clone(),
+                    &component,
+                    home_directory_path.clone(),
+                    process_state,
+                )
+                .await?;
+
+                let limits = store.data().process.limits.clone();
+                let init = bindings.call_init(&mut store, &our.to_string());
+                #[cfg(feature = "simulation-mode")]
+                let init = crate::faults::crashable(&our.process, init);
+                let crash = match within_wall_time(init, limits.wall_time_secs).await {
+                    Ok(()) => {
+                        t::Printout::new(1, format!("process {our} returned without error"))
+                            .send(&send_to_terminal)
+                            .await;
+                        None
+                    }
+                    Err(_) if store.data().process.hibernating => None,
+                    Err(e) => {
+                        print_limit_reached(&our, &e, &limits, &send_to_terminal).await;
+                        Some(print_crash(&our, &wasi_stderr, &send_to_terminal).await?)
+                    }
+                };
 
-            // update metadata to what was mutated by process in store
-            store.data().process.metadata.to_owned()
+                // keep what was mutated by process in store
+                (store.into_data().process, crash)
+            }
+        };
+        if !ended.hibernating {
+            break (ended.metadata, crash);
         }
+        t::Printout::new(2, format!("process {our} hibernating"))
+            .send(&send_to_terminal)
+            .await;
+        let Some(woken) = ended.wake().await else {
+            return Ok(());
+        };
+        process_state = woken;
     };
 
     //
     // the process has completed, time to perform cleanup
     //
 
+    // it is no longer ready for those that depend on it
+    t::KernelMessage::builder()
+        .id(rand::random())
+        .source((&our.node, KERNEL_PROCESS_ID.clone()))
+        .target((&our.node, KERNEL_PROCESS_ID.clone()))
+        .message(t::Message::Request(t::Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&t::KernelCommand::ProcessExited(
+                metadata.our.process.clone(),
+            ))
+            .unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .unwrap()
+        .send(&send_to_loop)
+        .await;
+
+    // let anyone subscribed to crashes know about this one
+    if let Some(error) = &crash {
+        t::KernelMessage::builder()
+            .id(rand::random())
+            .source((&our.node, KERNEL_PROCESS_ID.clone()))
+            .target((&our.node, KERNEL_PROCESS_ID.clone()))
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: None,
+                body: serde_json::to_vec(&t::KernelCommand::ProcessCrashed {
+                    id: metadata.our.process.clone(),
+                    error: error.clone(),
+                })
+                .unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+            .send(&send_to_loop)
+            .await;
+    }
+
+    if let Some((_, on_exit)) = ON_EXIT_SET.remove(&metadata.our.process) {
+        metadata.on_exit = on_exit;
+    }
+
     t::Printout::new(
         1,
         format!(
@@ -368,6 +1794,77 @@ pub async fn make_process_loop(
         }
         // if restart, tell ourselves to init the app again, with same capabilities
         t::OnExit::Restart => {
+            // back off from restarting a process that keeps exiting, and give
+            // up on it once it crashes too often
+            let exits = record(&EXITS, &metadata.our.process);
+            let crashes = match &crash {
+                Some(_) => record(&CRASHES, &metadata.our.process),
+                None => {
+                    CRASHES.remove(&metadata.our.process);
+                    0
+                }
+            };
+            if crashes >= CRASH_LOOP_LIMIT {
+                CRASHES.remove(&metadata.our.process);
+                EXITS.remove(&metadata.our.process);
+                notify_crash_loop(
+                    &our,
+                    crashes,
+                    crash.as_deref().unwrap_or_default(),
+                    &send_to_loop,
+                )
+                .await;
+                // kill, **without** revoking capabilities from others, so that
+                // they still hold them if the package is reinstalled
+                t::KernelMessage::builder()
+                    .id(rand::random())
+                    .source((&our.node, KERNEL_PROCESS_ID.clone()))
+                    .target((&our.node, KERNEL_PROCESS_ID.clone()))
+                    .message(t::Message::Request(t::Request {
+                        inherit: false,
+                        expects_response: None,
+                        body: serde_json::to_vec(&t::KernelCommand::KillProcess(
+                            metadata.our.process.clone(),
+                        ))
+                        .unwrap(),
+                        metadata: Some("no-revoke".to_string()),
+                        capabilities: vec![],
+                    }))
+                    .build()
+                    .unwrap()
+                    .send(&send_to_loop)
+                    .await;
+                return Ok(());
+            }
+            let backoff = restart_backoff(exits);
+            if crashes > 0 {
+                t::Printout::new(
+                    0,
+                    format!(
+                        "kernel: process {} crashed ({crashes} of {CRASH_LOOP_LIMIT} allowed in {}m), restarting in {}s",
+                        metadata.our.process,
+                        CRASH_LOOP_WINDOW.as_secs() / 60,
+                        backoff.as_secs(),
+                    ),
+                )
+                .send(&send_to_terminal)
+                .await;
+            } else {
+                t::Printout::new(
+                    1,
+                    format!(
+                        "kernel: process {} exited, restarting in {}s",
+                        metadata.our.process,
+                        backoff.as_secs(),
+                    ),
+                )
+                .send(&send_to_terminal)
+                .await;
+            }
+            // after a crash, the kernel dropped our sender, so messages sent
+            // to the process meanwhile fail as if it didn't exist; killing it
+            // cancels this
+            tokio::time::sleep(backoff).await;
             // get caps before killing
             let (tx, rx) = tokio::sync::oneshot::channel();
             caps_oracle
@@ -488,3 +1985,408 @@ pub async fn make_process_loop(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(node: &str) -> t::Address {
+        t::Address::new(node, t::ProcessId::new(Some("hop"), "frames", "sys"))
+    }
+
+    fn blob(bytes: &[u8]) -> Option<t::LazyLoadBlob> {
+        Some(t::LazyLoadBlob {
+            mime: None,
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    fn last_bytes(state: &ProcessState) -> Option<Vec<u8>> {
+        state.last_blob.as_ref().map(|blob| blob.bytes.clone())
+    }
+
+    fn home() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("kinode-frames-{}", rand::random::<u64>()))
+    }
+
+    /// the state of a process on `node`, as far as its message frames go,
+    /// keeping its blobs in `home`
+    async fn state(node: &str, home: &std::path::Path) -> ProcessState {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let keypair = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let (self_sender, recv_in_process) = tokio::sync::mpsc::channel(1);
+        let (send_to_loop, _) = tokio::sync::mpsc::channel(1);
+        let (send_to_terminal, _) = tokio::sync::mpsc::channel(1);
+        let (caps_oracle, _) = tokio::sync::mpsc::channel(1);
+        ProcessState {
+            keypair: Arc::new(keypair),
+            keyring: Arc::new(super::super::crypto::Keyring::new(
+                [0; 32],
+                Arc::new(dashmap::DashMap::new()),
+            )),
+            blob_store: Arc::new(
+                crate::blobs::BlobStore::new(home.to_str().unwrap())
+                    .await
+                    .unwrap(),
+            ),
+            metadata: t::ProcessMetadata {
+                our: address(node),
+                wasm_bytes_handle: String::new(),
+                wit_version: None,
+                on_exit: t::OnExit::None,
+                public: false,
+            },
+            recv_in_process,
+            self_sender,
+            send_to_loop,
+            send_to_terminal,
+            prompting_message: None,
+            last_blob: None,
+            contexts: Contexts::new(Ledger::default()),
+            message_queue: VecDeque::new(),
+            caps_oracle,
+            meter: None,
+            limits: t::ProcessLimits::default(),
+            timeouts: RequestTimeouts::new(5, 60),
+            hibernate_after: None,
+            hibernating: false,
+            held: None,
+            reported_ready: false,
+            rng: None,
+            last_send_error: None,
+            last_message_time: 0,
+        }
+    }
+
+    fn request(
+        id: u64,
+        source: &t::Address,
+        target: &t::Address,
+        rsvp: Option<t::Address>,
+        expects_response: bool,
+        lazy_load_blob: Option<t::LazyLoadBlob>,
+    ) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(id)
+            .source(source.clone())
+            .target(target.clone())
+            .rsvp(rsvp)
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: expects_response.then_some(5),
+                body: vec![],
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .lazy_load_blob(lazy_load_blob)
+            .build()
+            .unwrap()
+    }
+
+    fn response(
+        id: u64,
+        source: &t::Address,
+        target: &t::Address,
+        lazy_load_blob: Option<t::LazyLoadBlob>,
+    ) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(id)
+            .source(source.clone())
+            .target(target.clone())
+            .message(t::Message::Response((
+                t::Response {
+                    inherit: false,
+                    body: vec![],
+                    metadata: None,
+                    capabilities: vec![],
+                },
+                None,
+            )))
+            .lazy_load_blob(lazy_load_blob)
+            .build()
+            .unwrap()
+    }
+
+    fn error(id: u64, target: &t::Address) -> t::WrappedSendError {
+        t::WrappedSendError {
+            id,
+            source: target.clone(),
+            error: t::SendError {
+                kind: t::SendErrorKind::Timeout,
+                target: target.clone(),
+                message: t::Message::Request(t::Request {
+                    inherit: false,
+                    expects_response: Some(5),
+                    body: vec![],
+                    metadata: None,
+                    capabilities: vec![],
+                }),
+                lazy_load_blob: blob(b"failed"),
+                hop: t::SendErrorHop::LocalKernel,
+                cause: t::SendErrorCause::Timeout,
+                resolution: None,
+            },
+        }
+    }
+
+    /// send a request from `state`'s current frame as the host functions do,
+    /// returning the ID and rsvp it goes out with
+    async fn send(
+        state: &mut ProcessState,
+        target: &t::Address,
+        inherit: bool,
+        expects_response: bool,
+    ) -> (u64, Option<t::Address>) {
+        let source = state.metadata.our.clone();
+        let request = t::Request {
+            inherit,
+            expects_response: expects_response.then_some(5),
+            body: vec![],
+            metadata: None,
+            capabilities: vec![],
+        };
+        let km = state
+            .outgoing_request(source, target.clone(), request, None, None)
+            .await;
+        (km.id, km.rsvp)
+    }
+
+    /// a -> b -> c, each expecting a response: c answers b, and b's answer
+    /// still goes to a
+    #[tokio::test]
+    async fn nested_requests_unwind_across_three_hops() {
+        let home = home();
+        let (a, b, c) = (address("a.os"), address("b.os"), address("c.os"));
+        let mut a_state = state("a.os", &home).await;
+        let mut b_state = state("b.os", &home).await;
+        let mut c_state = state("c.os", &home).await;
+
+        let (id, rsvp) = send(&mut a_state, &b, false, true).await;
+        b_state
+            .enter_message(Ok(request(id, &a, &b, rsvp, true, None)))
+            .unwrap();
+        let (nested_id, nested_rsvp) = send(&mut b_state, &c, false, true).await;
+        assert_ne!(nested_id, id);
+        assert_eq!(nested_rsvp, Some(b.clone()));
+
+        c_state
+            .enter_message(Ok(request(nested_id, &b, &c, nested_rsvp, true, None)))
+            .unwrap();
+        let (to_b, target) = c_state.response_id_target().unwrap();
+        assert_eq!((to_b, &target), (nested_id, &b));
+
+        b_state
+            .enter_message(Ok(response(to_b, &c, &b, blob(b"from c"))))
+            .unwrap();
+        assert_eq!(b_state.response_id_target(), Some((id, a.clone())));
+        assert_eq!(last_bytes(&b_state), Some(b"from c".to_vec()));
+
+        let (to_a, target) = b_state.response_id_target().unwrap();
+        a_state
+            .enter_message(Ok(response(to_a, &b, &target, None)))
+            .unwrap();
+        assert!(a_state.contexts.is_empty());
+        fs::remove_dir_all(&home).await.unwrap();
+    }
+
+    /// a -> b -> c where b inherits without expecting a response: c's
+    /// response skips b and goes straight to a, which matches it to its request
+    #[tokio::test]
+    async fn inherited_requests_answer_the_first_hop() {
+        let home = home();
+        let (a, b, c) = (address("a.os"), address("b.os"), address("c.os"));
+        let mut a_state = state("a.os", &home).await;
+        let mut b_state = state("b.os", &home).await;
+        let mut c_state = state("c.os", &home).await;
+
+        let (id, rsvp) = send(&mut a_state, &b, false, true).await;
+        b_state
+            .enter_message(Ok(request(id, &a, &b, rsvp, true, None)))
+            .unwrap();
+        let (inherited_id, inherited_rsvp) = send(&mut b_state, &c, true, false).await;
+        assert_eq!(inherited_id, id);
+        assert_eq!(inherited_rsvp, Some(a.clone()));
+
+        c_state
+            .enter_message(Ok(request(
+                inherited_id,
+                &b,
+                &c,
+                inherited_rsvp,
+                false,
+                None,
+            )))
+            .unwrap();
+        assert_eq!(c_state.response_id_target(), Some((id, a.clone())));
+
+        // the response comes from c, not b, but is the only one with its ID
+        a_state
+            .enter_message(Ok(response(id, &c, &a, None)))
+            .unwrap();
+        assert!(a_state.contexts.is_empty());
+        fs::remove_dir_all(&home).await.unwrap();
+    }
+
+    /// a -> b -> c, and while b waits on c, a notification arrives that
+    /// expects no response: b still owes a its response
+    #[tokio::test]
+    async fn a_notification_keeps_the_prompting_message() {
+        let home = home();
+        let (a, b, c) = (address("a.os"), address("b.os"), address("c.os"));
+        let mut b_state = state("b.os", &home).await;
+
+        b_state
+            .enter_message(Ok(request(1, &a, &b, Some(a.clone()), true, None)))
+            .unwrap();
+        let (nested_id, _) = send(&mut b_state, &c, false, true).await;
+        b_state
+            .enter_message(Ok(request(2, &c, &b, None, false, blob(b"note"))))
+            .unwrap();
+        assert_eq!(b_state.response_id_target(), Some((1, a.clone())));
+        assert_eq!(last_bytes(&b_state), Some(b"note".to_vec()));
+
+        b_state
+            .enter_message(Ok(response(nested_id, &c, &b, None)))
+            .unwrap();
+        assert_eq!(b_state.response_id_target(), Some((1, a)));
+        fs::remove_dir_all(&home).await.unwrap();
+    }
+
+    /// a -> b -> c, and c never answers: the error restores b's frame and
+    /// leaves the blob of the last message b received
+    #[tokio::test]
+    async fn an_error_keeps_the_last_blob() {
+        let home = home();
+        let (a, b, c) = (address("a.os"), address("b.os"), address("c.os"));
+        let mut b_state = state("b.os", &home).await;
+
+        b_state
+            .enter_message(Ok(request(
+                1,
+                &a,
+                &b,
+                Some(a.clone()),
+                true,
+                blob(b"from a"),
+            )))
+            .unwrap();
+        let (nested_id, _) = send(&mut b_state, &c, false, true).await;
+        assert!(b_state.enter_message(Err(error(nested_id, &c))).is_err());
+        assert_eq!(b_state.response_id_target(), Some((1, a)));
+        assert_eq!(last_bytes(&b_state), Some(b"from a".to_vec()));
+        fs::remove_dir_all(&home).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn response_to_our_matches_resolved_target() {
+        let mut contexts = Contexts::new(Ledger::default());
+        let context = || ProcessContext {
+            prompting_message: None,
+            context: Some(b"to us".to_vec()),
+        };
+        contexts.insert(
+            1,
+            resolve_our(address("our"), "node.os"),
+            context(),
+            5,
+            tokio::spawn(async {}),
+        );
+        contexts.insert(1, address("other.os"), context(), 5, tokio::spawn(async {}));
+
+        // the response comes from our node's name, never from "our"
+        let ours = address("node.os");
+        assert!(contexts.answers(1, &ours, &ours));
+        let removed = contexts.remove(1, &ours).unwrap();
+        assert_eq!(removed.context, Some(b"to us".to_vec()));
+        assert!(contexts.answers(1, &address("other.os"), &address("other.os")));
+    }
+
+    #[tokio::test]
+    async fn wall_time_limit_stops_a_slow_run() {
+        let slow = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        };
+        let error = within_wall_time(slow, Some(0)).await.unwrap_err();
+        assert!(error.downcast_ref::<LimitReached>().is_some());
+        assert!(within_wall_time(async { Ok(()) }, Some(5)).await.is_ok());
+        let failed = async { Err(anyhow::anyhow!("crashed")) };
+        let error = within_wall_time(failed, None).await.unwrap_err();
+        assert!(error.downcast_ref::<LimitReached>().is_none());
+    }
+
+    #[tokio::test]
+    async fn memory_is_refused_past_its_limit() {
+        let home = home();
+        let mut state = state("node.os", &home).await;
+        assert!(state.memory_growing(0, 1 << 30, None).unwrap());
+        state.limits.memory_bytes = Some(1 << 20);
+        assert!(state.memory_growing(0, 1 << 20, None).unwrap());
+        let error = state.memory_growing(1 << 20, 2 << 20, None).unwrap_err();
+        assert!(error.downcast_ref::<LimitReached>().is_some());
+        fs::remove_dir_all(&home).await.unwrap();
+    }
+
+    #[test]
+    fn request_timeouts_are_clamped() {
+        let node = RequestTimeouts::new(0, u64::MAX);
+        assert_eq!((node.default, node.max), (1, MAX_REQUEST_TIMEOUT));
+        let node = RequestTimeouts::new(600, 60);
+        assert_eq!((node.default, node.max), (60, 60));
+        assert_eq!(node.effective(0), 60);
+        assert_eq!(node.effective(u64::MAX), 60);
+        assert_eq!(node.effective(5), 5);
+
+        let entry: t::PackageManifestEntry = serde_json::from_value(serde_json::json!({
+            "process_name": "app",
+            "process_wasm_path": "/app.wasm",
+            "on_exit": "None",
+            "request_networking": false,
+            "request_capabilities": [],
+            "grant_capabilities": [],
+            "public": false,
+            "default_request_timeout": 120,
+            "max_request_timeout": 0,
+        }))
+        .unwrap();
+        let process = node.overridden_by(Some(&entry));
+        assert_eq!((process.default, process.max), (1, 1));
+    }
+
+    #[test]
+    fn restarts_back_off_with_each_exit() {
+        let log = dashmap::DashMap::new();
+        let process = t::ProcessId::new(Some("restarts"), "test", "sys");
+        let backoffs: Vec<u64> = (0..8)
+            .map(|_| restart_backoff(record(&log, &process)).as_secs())
+            .collect();
+        assert_eq!(backoffs, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(restart_backoff(usize::MAX), RESTART_BACKOFF_MAX);
+    }
+
+    #[test]
+    fn limits_parse_and_tighten() {
+        let script: t::ProcessLimits =
+            serde_json::from_str(r#"{"fuel": 1000, "wall_time_secs": 60}"#).unwrap();
+        assert_eq!(script.memory_bytes, None);
+        assert_eq!(
+            serde_json::from_str::<t::ProcessLimits>("{}").unwrap(),
+            t::ProcessLimits::default()
+        );
+        let node = t::ProcessLimits {
+            fuel: Some(5000),
+            memory_bytes: Some(1 << 20),
+            wall_time_secs: Some(30),
+        };
+        // a script may tighten the node's limits, but not loosen them
+        assert_eq!(
+            node.tightest(&script),
+            t::ProcessLimits {
+                fuel: Some(1000),
+                memory_bytes: Some(1 << 20),
+                wall_time_secs: Some(30),
+            }
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use lib::types::core as t;
+use std::collections::HashMap;
+
+/// the requests to each non-public process that any local process may send
+/// without a messaging capability, from its manifest entry's `public_methods`
+#[derive(Clone, Default)]
+pub struct PublicMethods(HashMap<t::ProcessId, Vec<t::PublicMethod>>);
+
+impl PublicMethods {
+    /// note the methods a process that is starting declares
+    pub fn declare(
+        &mut self,
+        process: &t::ProcessId,
+        manifest_entry: Option<&t::PackageManifestEntry>,
+    ) {
+        match manifest_entry.map(|entry| entry.public_methods.clone()) {
+            Some(methods) if !methods.is_empty() => {
+                self.0.insert(process.clone(), methods);
+            }
+            _ => {
+                self.0.remove(process);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, process: &t::ProcessId) {
+        self.0.remove(process);
+    }
+
+    /// whether `message` to `process` is a request for one of its public
+    /// methods. responses never are.
+    pub fn allow(&self, process: &t::ProcessId, message: &t::Message) -> bool {
+        let t::Message::Request(request) = message else {
+            return false;
+        };
+        let Some(methods) = self.0.get(process) else {
+            return false;
+        };
+        methods.iter().any(|method| match method {
+            t::PublicMethod::Metadata(name) => request.metadata.as_deref() == Some(name),
+            t::PublicMethod::Request(variant) => is_variant(&request.body, variant),
+        })
+    }
+}
+
+/// whether `body` is JSON for the enum variant `variant`: `"variant"`, or an
+/// object whose one key is `"variant"`. only as much of the body is read as
+/// it takes to tell, so a long body costs no more to check than a short one;
+/// whether the rest is valid is for the process to find out.
+fn is_variant(body: &[u8], variant: &str) -> bool {
+    fn skip_whitespace(bytes: &[u8]) -> &[u8] {
+        let start = bytes
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or(bytes.len());
+        &bytes[start..]
+    }
+    let quoted = format!("\"{variant}\"");
+    let body = skip_whitespace(body);
+    if let Some(rest) = body.strip_prefix(quoted.as_bytes()) {
+        return skip_whitespace(rest).is_empty();
+    }
+    body.strip_prefix(b"{")
+        .map(skip_whitespace)
+        .and_then(|rest| rest.strip_prefix(quoted.as_bytes()))
+        .map_or(false, |rest| skip_whitespace(rest).starts_with(b":"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variant_is_matched_whole() {
+        assert!(is_variant(br#"{"Get": {"key": 1}}"#, "Get"));
+        assert!(is_variant(br#" { "Get" :1}"#, "Get"));
+        assert!(is_variant(br#""Get""#, "Get"));
+        assert!(!is_variant(br#"{"GetAndDelete": {"key": 1}}"#, "Get"));
+        assert!(!is_variant(br#""GetAndDelete""#, "Get"));
+        assert!(!is_variant(br#""Get" trailing"#, "Get"));
+        assert!(!is_variant(br#"{"key": "Get"}"#, "Get"));
+        assert!(!is_variant(br#"["Get"]"#, "Get"));
+    }
+}
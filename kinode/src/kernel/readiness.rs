@@ -0,0 +1,190 @@
+use lib::types::core as t;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+/// how long a process is held back for its dependencies before it is run
+/// anyway: a dependency that was never installed, or that never gets as far
+/// as `receive()`, must not keep it from running forever
+pub const HOLD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// a process held back from running
+struct Held {
+    /// its dependencies not yet ready
+    pending: HashSet<t::ProcessId>,
+    since: Instant,
+    /// the `RunProcess` requests to answer once it runs: (id, requester)
+    replies: Vec<(u64, t::Address)>,
+}
+
+/// a process no longer held back, with the `RunProcess` requests to answer
+/// now that it can run
+pub struct Runnable {
+    pub process: t::ProcessId,
+    pub replies: Vec<(u64, t::Address)>,
+}
+
+/// which processes have reported ready, and which are held back from running
+/// until the processes their manifest entry lists in `depends_on` are.
+/// a process reports ready when it calls `ready()`, or else when it first
+/// calls `receive()`. runtime modules are always ready.
+#[derive(Default)]
+pub struct Readiness {
+    ready: HashSet<t::ProcessId>,
+    /// processes held back from running
+    waiting: HashMap<t::ProcessId, Held>,
+}
+
+impl Readiness {
+    /// hold `process` until its dependencies are ready. returns the ones it
+    /// waits on: if none, it can run now. a dependency that waits, however
+    /// indirectly, on `process` would never be ready, so it is skipped.
+    /// `reply`, if given, is answered when the process is run.
+    pub fn hold(
+        &mut self,
+        process: &t::ProcessId,
+        depends_on: Vec<t::ProcessId>,
+        reply: Option<(u64, t::Address)>,
+    ) -> HashSet<t::ProcessId> {
+        let pending: HashSet<t::ProcessId> = depends_on
+            .into_iter()
+            .filter(|dependency| {
+                dependency != process
+                    && !self.ready.contains(dependency)
+                    && !self.waits_on(dependency, process)
+            })
+            .collect();
+        if pending.is_empty() {
+            self.waiting.remove(process);
+            return pending;
+        }
+        let held = self.waiting.entry(process.clone()).or_insert_with(|| Held {
+            pending: HashSet::new(),
+            since: Instant::now(),
+            replies: vec![],
+        });
+        held.pending = pending.clone();
+        held.replies.extend(reply);
+        pending
+    }
+
+    /// note that `process` is ready. returns the processes that can now run.
+    pub fn mark_ready(&mut self, process: t::ProcessId) -> Vec<Runnable> {
+        let mut runnable = vec![];
+        self.waiting.retain(|waiter, held| {
+            held.pending.remove(&process);
+            if held.pending.is_empty() {
+                runnable.push(Runnable {
+                    process: waiter.clone(),
+                    replies: std::mem::take(&mut held.replies),
+                });
+                false
+            } else {
+                true
+            }
+        });
+        self.ready.insert(process);
+        runnable
+    }
+
+    /// stop holding the processes held for longer than [`HOLD_TIMEOUT`],
+    /// returning them with the dependencies they still wait on
+    pub fn expired(&mut self) -> Vec<(Runnable, HashSet<t::ProcessId>)> {
+        let mut expired = vec![];
+        self.waiting.retain(|waiter, held| {
+            if held.since.elapsed() < HOLD_TIMEOUT {
+                return true;
+            }
+            expired.push((
+                Runnable {
+                    process: waiter.clone(),
+                    replies: std::mem::take(&mut held.replies),
+                },
+                std::mem::take(&mut held.pending),
+            ));
+            false
+        });
+        expired
+    }
+
+    /// note that `process` has exited: it must report ready again when it
+    /// is next run. processes already let run on it keep running.
+    pub fn exited(&mut self, process: &t::ProcessId) {
+        self.ready.remove(process);
+    }
+
+    /// forget a process that has been stopped: it must report ready again
+    /// when it is next run, and it no longer waits on anything. returns the
+    /// `RunProcess` requests to answer, if it was held back.
+    pub fn forget(&mut self, process: &t::ProcessId) -> Vec<(u64, t::Address)> {
+        self.ready.remove(process);
+        self.waiting
+            .remove(process)
+            .map(|held| held.replies)
+            .unwrap_or_default()
+    }
+
+    /// whether `process` waits on `target`, directly or through others
+    fn waits_on(&self, process: &t::ProcessId, target: &t::ProcessId) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = vec![process];
+        while let Some(next) = stack.pop() {
+            if !seen.insert(next) {
+                continue;
+            }
+            for dependency in self.waiting.get(next).into_iter().flat_map(|h| &h.pending) {
+                if dependency == target {
+                    return true;
+                }
+                stack.push(dependency);
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str) -> t::ProcessId {
+        t::ProcessId::new(Some(name), "readiness", "sys")
+    }
+
+    fn requester() -> t::Address {
+        t::Address::new("our.os", process("terminal"))
+    }
+
+    #[test]
+    fn a_held_run_is_answered_when_its_dependency_is_ready() {
+        let mut readiness = Readiness::default();
+        let pending = readiness.hold(&process("app"), vec![process("db")], Some((1, requester())));
+        assert_eq!(pending, HashSet::from([process("db")]));
+        let runnable = readiness.mark_ready(process("db"));
+        assert_eq!(runnable.len(), 1);
+        assert_eq!(runnable[0].process, process("app"));
+        assert_eq!(runnable[0].replies, vec![(1, requester())]);
+    }
+
+    #[test]
+    fn an_exited_dependency_must_report_ready_again() {
+        let mut readiness = Readiness::default();
+        readiness.mark_ready(process("db"));
+        assert!(readiness
+            .hold(&process("app"), vec![process("db")], None)
+            .is_empty());
+        readiness.exited(&process("db"));
+        assert!(!readiness
+            .hold(&process("app"), vec![process("db")], None)
+            .is_empty());
+    }
+
+    #[test]
+    fn a_held_process_that_is_stopped_returns_its_replies() {
+        let mut readiness = Readiness::default();
+        readiness.hold(&process("app"), vec![process("db")], Some((7, requester())));
+        assert_eq!(readiness.forget(&process("app")), vec![(7, requester())]);
+        assert!(readiness.mark_ready(process("db")).is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use lib::types::core::{self as t, KERNEL_PROCESS_ID};
+
+/// the [`t::DeliveryReceipt`] to send back to the source of `km`, if it is a
+/// request made with a [`t::ReceiptRequest`]. `delivered` is false if the
+/// target doesn't exist. like a response, it goes to the request's rsvp if it
+/// has one, over the network if remote, as a message from this node's kernel.
+pub fn receipt(our_name: &str, km: &t::KernelMessage, delivered: bool) -> Option<t::KernelMessage> {
+    let t::Message::Request(request) = &km.message else {
+        return None;
+    };
+    if !t::ReceiptRequest::requested(request.metadata.as_deref()) {
+        return None;
+    }
+    let receipt = t::DeliveryReceipt {
+        id: km.id,
+        target: km.target.clone(),
+        delivered,
+    };
+    t::KernelMessage::builder()
+        .id(rand::random())
+        .source((our_name, KERNEL_PROCESS_ID.clone()))
+        .target(km.rsvp.clone().unwrap_or_else(|| km.source.clone()))
+        .message(t::Message::Request(t::Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&receipt).unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .ok()
+}
+
+/// the [`t::TargetMissing`] notice to send the kernel of the node `km` came
+/// from, if it is a request from another node that expects a response, and
+/// its target doesn't exist
+pub fn target_missing(our_name: &str, km: &t::KernelMessage) -> Option<t::KernelMessage> {
+    let t::Message::Request(request) = &km.message else {
+        return None;
+    };
+    if request.expects_response.is_none() || km.source.node == our_name {
+        return None;
+    }
+    let notice = t::TargetMissing {
+        id: km.id,
+        source: km.source.clone(),
+        target: km.target.clone(),
+        request: t::Request {
+            body: vec![],
+            ..request.clone()
+        },
+    };
+    t::KernelMessage::builder()
+        .id(rand::random())
+        .source((our_name, KERNEL_PROCESS_ID.clone()))
+        .target((km.source.node.as_str(), KERNEL_PROCESS_ID.clone()))
+        .message(t::Message::Request(t::Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&notice).unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .ok()
+}
+
+/// the request of ours a remote kernel's [`t::TargetMissing`] notice is
+/// about, to fail back to its source. a node may only speak for its own
+/// processes, and only of requests from ours.
+pub fn read_target_missing(our_name: &str, km: &t::KernelMessage) -> Option<t::KernelMessage> {
+    let t::Message::Request(request) = &km.message else {
+        return None;
+    };
+    if km.source.process != *KERNEL_PROCESS_ID {
+        return None;
+    }
+    let notice: t::TargetMissing = serde_json::from_slice(&request.body).ok()?;
+    if notice.target.node != km.source.node || notice.source.node != our_name {
+        return None;
+    }
+    t::KernelMessage::builder()
+        .id(notice.id)
+        .source(notice.source)
+        .target(notice.target)
+        .message(t::Message::Request(notice.request))
+        .build()
+        .ok()
+}
@@ -0,0 +1,628 @@
+use super::{
+    limits::MessageLimits, pending, public::PublicMethods, receipts, ProcessSender, Senders,
+};
+use lib::types::core::{self as t, KERNEL_PROCESS_ID, STATE_PROCESS_ID, VFS_PROCESS_ID};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+use tokio::sync::mpsc;
+
+/// messages a shard buffers before the event loop waits on it
+const SHARD_CHANNEL_CAPACITY: usize = 1_000;
+
+/// what a local process may do, as far as routing its messages goes
+struct Permits {
+    public: bool,
+    /// may send messages to, and receive them from, other nodes
+    networking: bool,
+    /// may ask the kernel what processes are waiting on
+    pending: bool,
+    /// the local processes it has the capability to message
+    messages: HashSet<t::ProcessId>,
+}
+
+/// the state of the event loop that messages are checked and routed by. the
+/// loop makes new routes whenever a process starts, exits or has its
+/// capabilities changed, and hands each message to a shard with the routes
+/// current when the loop took it, so that each is checked against the state
+/// the loop itself would have checked it against.
+pub struct Routes {
+    our_name: String,
+    permits: HashMap<t::ProcessId, Permits>,
+    public_methods: PublicMethods,
+    senders: Senders,
+    limits: MessageLimits,
+}
+
+/// why a message the event loop took can't be delivered
+#[derive(Debug)]
+pub enum Unrouted {
+    /// it is larger than the limits allow, for the reason given
+    TooLarge(String),
+    /// its source is a local process that doesn't exist
+    SourceMissing,
+    /// its local source lacks this capability
+    CapabilityDenied(t::Capability),
+    /// its local target doesn't exist, or exited before it was delivered
+    TargetMissing,
+    /// it is from another node, and its target can't receive networked messages
+    NotNetworked,
+}
+
+impl Routes {
+    pub fn new(
+        our_name: &str,
+        process_map: &t::ProcessMap,
+        public_methods: &PublicMethods,
+        senders: &Senders,
+        limits: &MessageLimits,
+    ) -> Self {
+        let network = t::Capability::new((our_name, KERNEL_PROCESS_ID.clone()), "\"network\"");
+        let pending = pending::capability(our_name);
+        let permits = process_map
+            .iter()
+            .map(|(process, persisted)| {
+                let messages = persisted
+                    .capabilities
+                    .keys()
+                    .filter(|cap| {
+                        cap.issuer.node == our_name
+                            && **cap == t::Capability::messaging(cap.issuer.clone())
+                    })
+                    .map(|cap| cap.issuer.process.clone())
+                    .collect();
+                let permits = Permits {
+                    public: persisted.public,
+                    networking: persisted.capabilities.contains_key(&network),
+                    pending: persisted.capabilities.contains_key(&pending),
+                    messages,
+                };
+                (process.clone(), permits)
+            })
+            .collect();
+        Self {
+            our_name: our_name.to_string(),
+            permits,
+            public_methods: public_methods.clone(),
+            senders: senders.clone(),
+            limits: limits.clone(),
+        }
+    }
+
+    /// whether a message fits the limits, its source may send it, and its
+    /// target may receive it. the kernel's own messages, such as persisted
+    /// state, are exempt from the limits.
+    pub fn check(&self, km: &t::KernelMessage) -> Result<(), Unrouted> {
+        if km.source.process != *KERNEL_PROCESS_ID {
+            if let Some(reason) = self.limits.exceeded_by(km) {
+                return Err(Unrouted::TooLarge(reason));
+            }
+        }
+        let our_name = self.our_name.as_str();
+        if km.source.node == our_name && km.target.node != our_name {
+            // a process needs the capability to send networked messages
+            let source = self
+                .permits
+                .get(&km.source.process)
+                .ok_or(Unrouted::SourceMissing)?;
+            if !source.networking {
+                return Err(Unrouted::CapabilityDenied(t::Capability::new(
+                    (our_name, KERNEL_PROCESS_ID.clone()),
+                    "\"network\"",
+                )));
+            }
+        } else if km.source.node != our_name {
+            // messaging restrictions only apply to *local* processes: a
+            // process can be messaged by any process remotely if it has
+            // networking capabilities.
+            let target = self
+                .permits
+                .get(&km.target.process)
+                .ok_or(Unrouted::TargetMissing)?;
+            if !target.networking {
+                return Err(Unrouted::NotNetworked);
+            }
+        } else if km.source.process != *KERNEL_PROCESS_ID
+            && km.source.process != *STATE_PROCESS_ID
+            && km.source.process != *VFS_PROCESS_ID
+        {
+            // a local process needs the capability to message a target
+            // process of this name. kernel and filesystem can ALWAYS message
+            // any local process
+            let source = self
+                .permits
+                .get(&km.source.process)
+                .ok_or(Unrouted::SourceMissing)?;
+            let target = self
+                .permits
+                .get(&km.target.process)
+                .ok_or(Unrouted::TargetMissing)?;
+            if !target.public
+                && !self.public_methods.allow(&km.target.process, &km.message)
+                && !(km.target.process == *KERNEL_PROCESS_ID
+                    && pending::is_query(&km.message)
+                    && source.pending)
+                && !source.messages.contains(&km.target.process)
+            {
+                return Err(Unrouted::CapabilityDenied(t::Capability::messaging((
+                    our_name,
+                    km.target.process.clone(),
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    /// check a message, and find where it goes: to another node, or to the
+    /// local process or runtime module it is for
+    fn route(&self, km: t::KernelMessage) -> Result<Delivery, (t::KernelMessage, Unrouted)> {
+        if let Err(unrouted) = self.check(&km) {
+            return Err((km, unrouted));
+        }
+        crate::metrics::handled(&KERNEL_PROCESS_ID, &km);
+        if km.target.node != self.our_name {
+            return Ok(Delivery::Network(km));
+        }
+        let receipt = receipts::receipt(&self.our_name, &km, true);
+        match self.senders.get(&km.target.process) {
+            Some(ProcessSender::Userspace(sender)) => {
+                Ok(Delivery::Process(sender.clone(), Ok(km), receipt))
+            }
+            Some(ProcessSender::Pool(pool)) => {
+                let sender = pool.route(&km).clone();
+                Ok(Delivery::Process(sender, Ok(km), receipt))
+            }
+            Some(ProcessSender::Runtime { sender, .. }) => Ok(Delivery::Runtime(
+                km.target.process.clone(),
+                sender.clone(),
+                km,
+                receipt,
+            )),
+            None => Err((km, Unrouted::TargetMissing)),
+        }
+    }
+}
+
+/// work for a shard
+enum Job {
+    /// a message the event loop took, to check and route by these routes
+    Route(Arc<Routes>, t::KernelMessage),
+    Deliver(Delivery),
+}
+
+/// a message for a shard to hand to its target, with the delivery receipt to
+/// send once it is handed over, if one was asked for. send errors go the same
+/// way, so they can't overtake messages queued before them.
+enum Delivery {
+    Network(t::KernelMessage),
+    Process(
+        t::ProcessMessageSender,
+        Result<t::KernelMessage, t::WrappedSendError>,
+        Option<t::KernelMessage>,
+    ),
+    Runtime(
+        t::ProcessId,
+        t::MessageSender,
+        t::KernelMessage,
+        Option<t::KernelMessage>,
+    ),
+    RuntimeError(t::NetworkErrorSender, t::WrappedSendError),
+}
+
+/// checks, routes and hands over messages from a set of worker tasks, so that
+/// neither the checks nor a target with a full queue hold up every message on
+/// the node, only those of the targets that share its shard. a target is
+/// always routed to by the same shard, so each target receives its messages
+/// in the order the event loop took them.
+pub struct Shards {
+    shards: Vec<mpsc::Sender<Job>>,
+}
+
+/// what the shards report back to the event loop
+pub struct Reports {
+    /// runtime modules that have died. the node can't run without them.
+    pub deaths: mpsc::UnboundedReceiver<t::ProcessId>,
+    /// messages that couldn't be delivered, for the event loop to tell
+    /// whoever needs to know
+    pub unrouted: mpsc::UnboundedReceiver<(t::KernelMessage, Unrouted)>,
+}
+
+impl Shards {
+    /// start `count` shards, at least one. they stop when this is dropped.
+    /// messages to other nodes are handed to `send_to_net`, and receipts are
+    /// sent back through the event loop.
+    pub fn new(
+        count: usize,
+        send_to_loop: t::MessageSender,
+        send_to_net: t::MessageSender,
+    ) -> (Self, Reports) {
+        let (send_deaths, deaths) = mpsc::unbounded_channel();
+        let (send_unrouted, unrouted) = mpsc::unbounded_channel();
+        let shards = (0..count.max(1))
+            .map(|_| {
+                let (send, mut recv) = mpsc::channel::<Job>(SHARD_CHANNEL_CAPACITY);
+                let send_to_loop = send_to_loop.clone();
+                let send_to_net = send_to_net.clone();
+                let send_deaths = send_deaths.clone();
+                let send_unrouted = send_unrouted.clone();
+                tokio::spawn(async move {
+                    while let Some(job) = recv.recv().await {
+                        let delivery = match job {
+                            Job::Route(routes, km) => match routes.route(km) {
+                                Ok(delivery) => delivery,
+                                Err(unrouted) => {
+                                    let _ = send_unrouted.send(unrouted);
+                                    continue;
+                                }
+                            },
+                            Job::Deliver(delivery) => delivery,
+                        };
+                        let receipt = match delivery {
+                            Delivery::Network(km) => {
+                                if send_to_net.send(km).await.is_err() {
+                                    let _ = send_deaths.send(t::ProcessId::new(
+                                        Some("net"),
+                                        "distro",
+                                        "sys",
+                                    ));
+                                }
+                                None
+                            }
+                            Delivery::Process(sender, message, receipt) => {
+                                sender.send(message).await.ok().and(receipt)
+                            }
+                            Delivery::Runtime(module, sender, km, receipt) => {
+                                if sender.send(km).await.is_err() {
+                                    let _ = send_deaths.send(module);
+                                    continue;
+                                }
+                                receipt
+                            }
+                            Delivery::RuntimeError(sender, error) => {
+                                sender.send(error).await.ok();
+                                None
+                            }
+                        };
+                        if let Some(receipt) = receipt {
+                            receipt.send(&send_to_loop).await;
+                        }
+                    }
+                });
+                send
+            })
+            .collect();
+        (Self { shards }, Reports { deaths, unrouted })
+    }
+
+    /// check and route a message the event loop took, by the routes current
+    /// when it took it, from the shard of its target
+    pub async fn route(&self, routes: Arc<Routes>, km: t::KernelMessage) -> anyhow::Result<()> {
+        let shard = self.shard(&km.target.process);
+        self.send(shard, Job::Route(routes, km)).await
+    }
+
+    /// fail a message back to `process`, which sent it
+    pub async fn error_to_process(
+        &self,
+        process: &t::ProcessId,
+        sender: &t::ProcessMessageSender,
+        error: t::WrappedSendError,
+    ) -> anyhow::Result<()> {
+        let shard = self.shard(process);
+        let delivery = Delivery::Process(sender.clone(), Err(error), None);
+        self.send(shard, Job::Deliver(delivery)).await
+    }
+
+    /// fail a message back to the runtime module `process`, which sent it
+    pub async fn error_to_runtime(
+        &self,
+        process: &t::ProcessId,
+        sender: &t::NetworkErrorSender,
+        error: t::WrappedSendError,
+    ) -> anyhow::Result<()> {
+        let shard = self.shard(process);
+        let delivery = Delivery::RuntimeError(sender.clone(), error);
+        self.send(shard, Job::Deliver(delivery)).await
+    }
+
+    /// the shard that routes to `process`
+    fn shard(&self, process: &t::ProcessId) -> usize {
+        let mut hasher = DefaultHasher::new();
+        process.hash(&mut hasher);
+        hasher.finish() as usize % self.shards.len()
+    }
+
+    /// the event loop only waits here once the target's shard is far behind
+    async fn send(&self, shard: usize, job: Job) -> anyhow::Result<()> {
+        self.shards[shard]
+            .send(job)
+            .await
+            .map_err(|_| anyhow::anyhow!("event loop: fatal: message shard {shard} died"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::limits::SizeLimit;
+    use std::time::{Duration, Instant};
+
+    const TARGETS: usize = 64;
+
+    fn source() -> t::ProcessId {
+        t::ProcessId::new(Some("source"), "bench", "sys")
+    }
+
+    fn target(i: usize) -> t::ProcessId {
+        t::ProcessId::new(Some(&format!("target{i}")), "bench", "sys")
+    }
+
+    fn message(to: &t::ProcessId, id: u64) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(id)
+            .source(("bench.os", source()))
+            .target(("bench.os", to.clone()))
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: None,
+                body: vec![],
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    fn persisted(public: bool, capabilities: Vec<t::Capability>) -> t::PersistedProcess {
+        t::PersistedProcess {
+            wasm_bytes_handle: String::new(),
+            wit_version: None,
+            on_exit: t::OnExit::None,
+            capabilities: capabilities.into_iter().map(|cap| (cap, vec![])).collect(),
+            public,
+        }
+    }
+
+    /// routes to `senders`, each a public process, from a source that holds
+    /// no capabilities
+    fn routes(senders: Senders) -> Arc<Routes> {
+        let mut process_map: t::ProcessMap = senders
+            .keys()
+            .map(|process| (process.clone(), persisted(true, vec![])))
+            .collect();
+        process_map.insert(source(), persisted(false, vec![]));
+        let limits = MessageLimits {
+            node: SizeLimit {
+                body: 1024,
+                blob: 1024,
+            },
+            modules: HashMap::new(),
+        };
+        Arc::new(Routes::new(
+            "bench.os",
+            &process_map,
+            &PublicMethods::default(),
+            &senders,
+            &limits,
+        ))
+    }
+
+    /// busy the current thread, as a process handling a message would
+    fn work(iterations: u64) {
+        let mut x = 0u64;
+        for i in 0..iterations {
+            x = std::hint::black_box(x.wrapping_mul(6364136223846793005).wrapping_add(i));
+        }
+    }
+
+    /// start a process for each target that works `iterations` per message,
+    /// and return their senders with handles that yield the IDs they received
+    fn processes(
+        iterations: u64,
+    ) -> Vec<(t::ProcessMessageSender, tokio::task::JoinHandle<Vec<u64>>)> {
+        (0..TARGETS)
+            .map(|_| {
+                let (send, mut recv) = mpsc::channel(SHARD_CHANNEL_CAPACITY);
+                let handle = tokio::spawn(async move {
+                    let mut received = vec![];
+                    while let Some(message) = recv.recv().await {
+                        let id = match message {
+                            Ok(km) => km.id,
+                            Err(error) => error.id,
+                        };
+                        received.push(id);
+                        work(iterations);
+                    }
+                    received
+                });
+                (send, handle)
+            })
+            .collect()
+    }
+
+    /// route `count` messages round-robin over the targets through
+    /// `shard_count` shards, and return how long it took them all to be received
+    async fn run(shard_count: usize, count: u64, iterations: u64) -> Duration {
+        let (send_to_loop, _recv_in_loop) = mpsc::channel(1);
+        let (send_to_net, _recv_in_net) = mpsc::channel(1);
+        let (shards, _reports) = Shards::new(shard_count, send_to_loop, send_to_net);
+        let processes = processes(iterations);
+        let routes = routes(
+            processes
+                .iter()
+                .enumerate()
+                .map(|(i, (sender, _))| (target(i), ProcessSender::Userspace(sender.clone())))
+                .collect(),
+        );
+        let started = Instant::now();
+        for id in 0..count {
+            let i = id as usize % TARGETS;
+            shards
+                .route(routes.clone(), message(&target(i), id))
+                .await
+                .unwrap();
+        }
+        drop(shards);
+        drop(routes);
+        for (sender, handle) in processes {
+            drop(sender);
+            handle.await.unwrap();
+        }
+        started.elapsed()
+    }
+
+    #[tokio::test]
+    async fn messages_and_errors_to_a_target_arrive_in_order() {
+        let (send_to_loop, _recv_in_loop) = mpsc::channel(1);
+        let (send_to_net, _recv_in_net) = mpsc::channel(1);
+        let (shards, _reports) = Shards::new(4, send_to_loop, send_to_net);
+        let mut processes = processes(0);
+        let (sender, handle) = processes.swap_remove(0);
+        let to = target(0);
+        let routes = routes(HashMap::from([(
+            to.clone(),
+            ProcessSender::Userspace(sender.clone()),
+        )]));
+        for id in 0..100 {
+            if id % 3 == 0 {
+                let error = t::WrappedSendError {
+                    id,
+                    source: t::Address::new("bench.os", to.clone()),
+                    error: t::SendError {
+                        kind: t::SendErrorKind::Timeout,
+                        target: t::Address::new("bench.os", to.clone()),
+                        message: message(&to, id).message,
+                        lazy_load_blob: None,
+                        hop: t::SendErrorHop::LocalKernel,
+                        cause: t::SendErrorCause::Unknown,
+                        resolution: None,
+                    },
+                };
+                shards.error_to_process(&to, &sender, error).await.unwrap();
+            } else {
+                shards
+                    .route(routes.clone(), message(&to, id))
+                    .await
+                    .unwrap();
+            }
+        }
+        drop(shards);
+        drop(routes);
+        drop(sender);
+        assert_eq!(handle.await.unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn unroutable_messages_are_reported() {
+        let (send_to_loop, _recv_in_loop) = mpsc::channel(1);
+        let (send_to_net, _recv_in_net) = mpsc::channel(1);
+        let (shards, mut reports) = Shards::new(2, send_to_loop, send_to_net);
+        let (sender, _recv) = mpsc::channel(1);
+        let private = target(1);
+        let mut process_map = HashMap::from([
+            (target(0), persisted(true, vec![])),
+            (private.clone(), persisted(false, vec![])),
+            (source(), persisted(false, vec![])),
+        ]);
+        let senders = HashMap::from([(target(0), ProcessSender::Userspace(sender))]);
+        let limits = MessageLimits {
+            node: SizeLimit { body: 4, blob: 4 },
+            modules: HashMap::new(),
+        };
+        let routes = |process_map: &t::ProcessMap| {
+            Arc::new(Routes::new(
+                "bench.os",
+                process_map,
+                &PublicMethods::default(),
+                &senders,
+                &limits,
+            ))
+        };
+
+        // not public, and the source may not message it
+        let denied = routes(&process_map);
+        assert!(matches!(
+            denied.check(&message(&private, 0)),
+            Err(Unrouted::CapabilityDenied(_))
+        ));
+        process_map.insert(
+            source(),
+            persisted(
+                false,
+                vec![t::Capability::messaging(("bench.os", private.clone()))],
+            ),
+        );
+        // routes are made anew when capabilities change
+        assert!(routes(&process_map).check(&message(&private, 0)).is_ok());
+        assert!(denied.check(&message(&private, 0)).is_err());
+
+        let mut too_large = message(&target(0), 1);
+        if let t::Message::Request(request) = &mut too_large.message {
+            request.body = b"too large".to_vec();
+        }
+        let mut remote = message(&target(0), 2);
+        remote.source.node = "other.os".to_string();
+        let mut networked = message(&target(0), 3);
+        networked.target.node = "other.os".to_string();
+        let routes = routes(&process_map);
+        assert!(matches!(
+            routes.check(&too_large),
+            Err(Unrouted::TooLarge(_))
+        ));
+        assert!(matches!(routes.check(&remote), Err(Unrouted::NotNetworked)));
+        assert!(matches!(
+            routes.check(&networked),
+            Err(Unrouted::CapabilityDenied(_))
+        ));
+
+        // routed in a shard: the process exists, but has no sender
+        shards
+            .route(routes.clone(), message(&private, 4))
+            .await
+            .unwrap();
+        let (km, unrouted) = reports.unrouted.recv().await.unwrap();
+        assert_eq!(km.id, 4);
+        assert!(matches!(unrouted, Unrouted::TargetMissing));
+    }
+
+    #[tokio::test]
+    async fn a_dead_runtime_module_is_reported() {
+        let (send_to_loop, _recv_in_loop) = mpsc::channel(1);
+        let (send_to_net, _recv_in_net) = mpsc::channel(1);
+        let (shards, mut reports) = Shards::new(2, send_to_loop, send_to_net);
+        let (sender, recv) = mpsc::channel(1);
+        drop(recv);
+        let module = target(0);
+        let routes = routes(HashMap::from([(
+            module.clone(),
+            ProcessSender::Runtime {
+                sender,
+                net_errors: None,
+            },
+        )]));
+        shards.route(routes, message(&module, 0)).await.unwrap();
+        assert_eq!(reports.deaths.recv().await, Some(module));
+    }
+
+    /// throughput of the shards as they are added, checking and routing
+    /// messages to targets that take some CPU time per message, as processes
+    /// do. run with
+    /// `cargo test --release shard_throughput -- --ignored --nocapture`
+    /// on a multicore host.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn shard_throughput() {
+        const COUNT: u64 = 20_000;
+        for iterations in [0, 20_000] {
+            for shard_count in [1, 2, 4, 8, 16] {
+                let elapsed = run(shard_count, COUNT, iterations).await;
+                println!(
+                    "{shard_count:>2} shards, {iterations:>6} iterations of work per message: {:>9.0} messages/s",
+                    COUNT as f64 / elapsed.as_secs_f64()
+                );
+            }
+        }
+    }
+}
@@ -3,7 +3,7 @@ use anyhow::Result;
 use lib::types::core::{self as t, KERNEL_PROCESS_ID, STATE_PROCESS_ID, VFS_PROCESS_ID};
 use lib::v0::wit;
 use lib::v0::wit::Host as StandardHost;
-use ring::signature::{self, KeyPair};
+use ring::signature::KeyPair;
 
 async fn print_debug(proc: &process::ProcessState, content: &str) {
     let _ = proc
@@ -11,6 +11,8 @@ async fn print_debug(proc: &process::ProcessState, content: &str) {
         .send(t::Printout {
             verbosity: 2,
             content: format!("{}: {}", proc.metadata.our.process, content),
+            source: None,
+            level: None,
         })
         .await;
 }
@@ -22,38 +24,40 @@ impl process::ProcessState {
         &mut self,
     ) -> Result<(wit::Address, wit::Message), (wit::SendError, Option<wit::Context>)> {
         let res = match self.message_queue.pop_front() {
-            Some(message_from_queue) => message_from_queue,
+            Some(message_from_queue) => {
+                self.contexts.note_set_aside(self.message_queue.len());
+                message_from_queue
+            }
             None => self.ingest_message_v0().await,
         };
         self.kernel_message_to_process_receive_v0(res)
     }
 
-    /// instead of ingesting latest, wait for a specific ID and queue all others
+    /// instead of ingesting latest, wait for the response to the request with
+    /// this ID sent to `awaited_target`, and queue all others
     async fn get_specific_message_for_process_v0(
         &mut self,
         awaited_message_id: u64,
+        awaited_target: &t::Address,
     ) -> Result<(wit::Address, wit::Message), (wit::SendError, Option<wit::Context>)> {
         // first, check if the awaited message is already in the queue and handle if so
-        for (i, message) in self.message_queue.iter().enumerate() {
-            match message {
-                Ok(ref km) if km.id == awaited_message_id => {
-                    let km = self.message_queue.remove(i).unwrap();
-                    return self.kernel_message_to_process_receive_v0(km);
-                }
-                _ => continue,
-            }
+        if let Some(i) = self
+            .message_queue
+            .iter()
+            .position(|message| self.answers(message, awaited_message_id, awaited_target))
+        {
+            let message = self.message_queue.remove(i).unwrap();
+            self.contexts.note_set_aside(self.message_queue.len());
+            return self.kernel_message_to_process_receive_v0(message);
         }
         // next, wait for the awaited message to arrive
         loop {
             let res = self.ingest_message_v0().await;
-            let id = match &res {
-                Ok(km) => km.id,
-                Err(e) => e.id,
-            };
-            if id == awaited_message_id {
+            if self.answers(&res, awaited_message_id, awaited_target) {
                 return self.kernel_message_to_process_receive_v0(res);
             } else {
                 self.message_queue.push_back(res);
+                self.contexts.note_set_aside(self.message_queue.len());
             }
         }
     }
@@ -63,27 +67,23 @@ impl process::ProcessState {
     /// if the message is a response, only enqueue if we have an outstanding request for it.
     async fn ingest_message_v0(&mut self) -> Result<t::KernelMessage, t::WrappedSendError> {
         loop {
-            let message = self
+            let mut message = self
                 .recv_in_process
                 .recv()
                 .await
                 .expect("fatal: process couldn't receive next message");
+            if let Ok(km) = &mut message {
+                if let Err(e) = self.blob_store.resolve(km).await {
+                    print_debug(self, &format!("couldn't read blob: {e}")).await;
+                }
+            }
 
-            match &message {
-                Ok(km) => match &km.message {
-                    t::Message::Response(_) => {
-                        if let Some((_context, timeout_handle)) = self.contexts.get_mut(&km.id) {
-                            timeout_handle.abort();
-                            return message;
-                        }
-                    }
-                    _ => {
-                        return message;
-                    }
-                },
-                Err(e) => {
-                    if let Some((_context, timeout_handle)) = self.contexts.get_mut(&e.id) {
-                        timeout_handle.abort();
+            match process::answer_key(&message) {
+                // requests are always delivered
+                None => return message,
+                // responses and errors only if they answer an outstanding request
+                Some((id, source)) => {
+                    if self.contexts.cancel_timeout(id, source) {
                         return message;
                     }
                 }
@@ -97,48 +97,12 @@ impl process::ProcessState {
         &mut self,
         incoming: Result<t::KernelMessage, t::WrappedSendError>,
     ) -> Result<(wit::Address, wit::Message), (wit::SendError, Option<wit::Context>)> {
-        let (mut km, context) = match incoming {
-            Ok(mut km) => match km.message {
-                t::Message::Request(t::Request {
-                    ref expects_response,
-                    ..
-                }) => {
-                    self.last_blob = km.lazy_load_blob;
-                    km.lazy_load_blob = None;
-                    if expects_response.is_some() || km.rsvp.is_some() {
-                        // update prompting_message iff there is someone to reply to
-                        self.prompting_message = Some(km.clone());
-                    }
-                    (km, None)
-                }
-                t::Message::Response(_) => match self.contexts.remove(&km.id) {
-                    Some((context, _timeout_handle)) => {
-                        self.last_blob = km.lazy_load_blob;
-                        km.lazy_load_blob = None;
-                        self.prompting_message = context.prompting_message;
-                        (km, context.context)
-                    }
-                    None => {
-                        self.last_blob = km.lazy_load_blob;
-                        km.lazy_load_blob = None;
-                        self.prompting_message = Some(km.clone());
-                        (km, None)
-                    }
-                },
-            },
-            Err(e) => match self.contexts.remove(&e.id) {
-                None => return Err((t::en_wit_send_error_v0(e.error), None)),
-                Some((context, _timeout_handle)) => {
-                    self.prompting_message = context.prompting_message;
-                    return Err((t::en_wit_send_error_v0(e.error), context.context));
-                }
-            },
+        let (mut km, context) = match self.enter_message(incoming) {
+            Ok(entered) => entered,
+            Err((error, context)) => return Err((t::en_wit_send_error_v0(error), context)),
         };
 
-        let pk = signature::UnparsedPublicKey::new(
-            &signature::ED25519,
-            self.keypair.as_ref().public_key(),
-        );
+        let public_key = self.keypair.as_ref().public_key().as_ref();
 
         // prune any invalid capabilities before handing to process
         // where invalid = supposedly issued by us, but not signed properly by us
@@ -150,10 +114,7 @@ impl process::ProcessState {
                     if km.source.node != self.metadata.our.node
                         && cap.issuer.node == self.metadata.our.node
                     {
-                        match pk.verify(&rmp_serde::to_vec(&cap).unwrap_or_default(), sig) {
-                            Ok(_) => true,
-                            Err(_) => false,
-                        }
+                        self.keyring.verify_cap(public_key, cap, sig)
                     } else {
                         return true;
                     }
@@ -166,10 +127,7 @@ impl process::ProcessState {
                     if km.source.node != self.metadata.our.node
                         && cap.issuer.node == self.metadata.our.node
                     {
-                        match pk.verify(&rmp_serde::to_vec(&cap).unwrap_or_default(), sig) {
-                            Ok(_) => true,
-                            Err(_) => false,
-                        }
+                        self.keyring.verify_cap(public_key, cap, sig)
                     } else {
                         return true;
                     }
@@ -205,125 +163,22 @@ impl process::ProcessState {
         blob: Option<wit::LazyLoadBlob>,
     ) -> Result<u64> {
         let source = fake_source.unwrap_or(self.metadata.our.clone());
-        let mut request = t::de_wit_request_v0(request);
-
-        // if request chooses to inherit, it means to take the ID and lazy_load_blob,
-        // if any, from the last message it ingested
-
-        // if request chooses to inherit, match id to precedessor
-        // otherwise, id is generated randomly
-        let request_id: u64 = if request.inherit && self.prompting_message.is_some() {
-            self.prompting_message.as_ref().unwrap().id
-        } else {
-            loop {
-                let id = rand::random();
-                if !self.contexts.contains_key(&id) {
-                    break id;
-                }
-            }
-        };
-
-        // if a blob is provided, it will be used; otherwise, if inherit is true,
-        // and a predecessor exists, its blob will be used; otherwise, no blob will be used.
-        let blob = match blob {
-            Some(p) => Some(t::LazyLoadBlob {
-                mime: p.mime,
-                bytes: p.bytes,
-            }),
-            None => match request.inherit {
-                true => self.last_blob.clone(),
-                false => None,
-            },
-        };
-
-        if !request.capabilities.is_empty() {
-            request.capabilities = {
-                let (tx, rx) = tokio::sync::oneshot::channel();
-                self.caps_oracle
-                    .send(t::CapMessage::FilterCaps {
-                        on: self.metadata.our.process.clone(),
-                        caps: request
-                            .capabilities
-                            .into_iter()
-                            .map(|(cap, _)| cap)
-                            .collect(),
-                        responder: tx,
-                    })
-                    .await
-                    .expect("fatal: process couldn't access capabilities oracle");
-                rx.await
-                    .expect("fatal: process couldn't receive capabilities")
-            };
-        }
-
-        // if the request expects a response, modify the process' context map as needed
-        // and set a timer.
-        // TODO optimize this SIGNIFICANTLY: stop spawning tasks
-        // and use a global clock + garbage collect step to check for timeouts
-        if let Some(timeout_secs) = request.expects_response {
-            let this_request = request.clone();
-            let this_blob = blob.clone();
-            let self_sender = self.self_sender.clone();
-            let original_target = t::Address::de_wit_v0(target.clone());
-            let timeout_handle = tokio::spawn(async move {
-                tokio::time::sleep(std::time::Duration::from_secs(timeout_secs)).await;
-                let _ = self_sender
-                    .send(Err(t::WrappedSendError {
-                        id: request_id,
-                        source: original_target.clone(),
-                        error: t::SendError {
-                            kind: t::SendErrorKind::Timeout,
-                            target: original_target,
-                            message: t::Message::Request(this_request),
-                            lazy_load_blob: this_blob,
-                        },
-                    }))
-                    .await;
-            });
-            self.contexts.insert(
-                request_id,
-                (
-                    process::ProcessContext {
-                        prompting_message: self.prompting_message.clone(),
-                        context: new_context,
-                    },
-                    timeout_handle,
-                ),
-            );
-        }
-
-        // rsvp is set based on this priority:
-        // 1. whether this request expects a response -- if so, rsvp = our address, always
-        // 2. whether this request inherits -- if so, rsvp = prompting message's rsvp
-        // 3. if neither, rsvp = None
-        let kernel_message = t::KernelMessage {
-            id: request_id,
-            source,
-            target: t::Address::de_wit_v0(target),
-            rsvp: match (
-                request.expects_response,
-                request.inherit,
-                &self.prompting_message,
-            ) {
-                (Some(_), _, _) => {
-                    // this request expects response, so receives any response
-                    // make sure to use the real source, not a fake injected-by-kernel source
-                    Some(self.metadata.our.clone())
-                }
-                (None, true, Some(ref prompt)) => {
-                    // this request inherits, so response will be routed to prompting message
-                    prompt.rsvp.clone()
-                }
-                _ => None,
-            },
-            message: t::Message::Request(request),
-            lazy_load_blob: blob,
-        };
-
-        self.send_to_loop
-            .send(kernel_message)
-            .await
-            .expect("fatal: kernel couldn't send request");
+        let request = t::de_wit_request_v0(request);
+        let blob = blob.map(|blob| t::LazyLoadBlob {
+            mime: blob.mime,
+            bytes: blob.bytes,
+        });
+        let kernel_message = self
+            .outgoing_request(
+                source,
+                t::Address::de_wit_v0(target),
+                request,
+                new_context,
+                blob,
+            )
+            .await;
+        let request_id = kernel_message.id;
+        kernel_message.send(&self.send_to_loop).await;
 
         Ok(request_id)
     }
@@ -333,7 +188,7 @@ impl process::ProcessState {
         let mut response = t::de_wit_response_v0(response);
 
         // the process requires a prompting_message in order to issue a response
-        let Some(ref prompting_message) = self.prompting_message else {
+        let Some((id, target)) = self.response_id_target() else {
             t::Printout::new(
                 0,
                 format!("kernel: need non-None prompting_message to handle Response {response:?}"),
@@ -343,16 +198,6 @@ impl process::ProcessState {
             return;
         };
 
-        // given the current process state, produce the id and target that
-        // a response it emits should have.
-        let (id, target) = (
-            prompting_message.id,
-            match &prompting_message.rsvp {
-                None => prompting_message.source.clone(),
-                Some(rsvp) => rsvp.clone(),
-            },
-        );
-
         let blob = match response.inherit {
             true => self.last_blob.clone(),
             false => t::de_wit_blob_v0(blob),
@@ -390,6 +235,8 @@ impl process::ProcessState {
                     None,
                 )),
                 lazy_load_blob: blob,
+                blob_handle: None,
+                sent_at: Some(std::time::Instant::now()),
             })
             .await
             .expect("fatal: kernel couldn't send response");
@@ -411,12 +258,12 @@ async fn send_and_await_response(
     }
     let id = process
         .process
-        .send_request_v0(source, target, request, None, blob)
+        .send_request_v0(source, target.clone(), request, None, blob)
         .await;
     match id {
         Ok(id) => match process
             .process
-            .get_specific_message_for_process_v0(id)
+            .get_specific_message_for_process_v0(id, &t::Address::de_wit_v0(target))
             .await
         {
             Ok((address, wit::Message::Response(response))) => {
@@ -453,6 +300,8 @@ impl StandardHost for process::ProcessWasiV0 {
                     self.process.metadata.our.process.publisher(),
                     content
                 ),
+                source: Some(self.process.metadata.our.process.clone()),
+                level: None,
             })
             .await
             .map_err(|e| anyhow::anyhow!("fatal: couldn't send to terminal: {e:?}"))
@@ -462,15 +311,20 @@ impl StandardHost for process::ProcessWasiV0 {
     // process management:
     //
 
-    /// TODO critical: move to kernel logic to enable persistence of choice made here
+    /// not persisted: to keep the choice across reboots, send the kernel a
+    /// `KernelCommand::SetOnExit` for this process
     async fn set_on_exit(&mut self, on_exit: wit::OnExit) -> Result<()> {
         self.process.metadata.on_exit = t::OnExit::de_wit_v0(on_exit);
+        // the latest choice wins over one the kernel set
+        process::forget_on_exit(&self.process.metadata.our.process);
         print_debug(&self.process, "set new on-exit behavior").await;
         Ok(())
     }
 
     async fn get_on_exit(&mut self) -> Result<wit::OnExit> {
-        Ok(self.process.metadata.on_exit.en_wit_v0())
+        Ok(process::on_exit_set(&self.process.metadata.our.process)
+            .unwrap_or_else(|| self.process.metadata.on_exit.clone())
+            .en_wit_v0())
     }
 
     /// create a message from the *kernel* to the filesystem,
@@ -600,6 +454,7 @@ impl StandardHost for process::ProcessWasiV0 {
     /// shortcut to spawn a new process. the child process will automatically
     /// be able to send messages to the parent process, and vice versa.
     /// the .wasm file for the process must already be in VFS.
+    /// the child is killed when the parent exits.
     async fn spawn(
         &mut self,
         name: Option<String>,
@@ -802,6 +657,9 @@ impl StandardHost for process::ProcessWasiV0 {
             .await
             .unwrap();
         rx.await.unwrap();
+
+        // child processes are killed when their parent exits
+        process::adopt_child(&self.process, &new_process_id).await;
         print_debug(&self.process, "spawned a new process").await;
         Ok(Ok(new_process_id.en_wit_v0().to_owned()))
     }
@@ -869,16 +727,25 @@ impl StandardHost for process::ProcessWasiV0 {
 
     /// from a process: receive the next incoming message. will wait async until a message is received.
     /// the incoming message can be a Request or a Response, or an Error of the Network variety.
+    /// a hibernating process that waits too long here is ended, and restarted on its next request.
     async fn receive(
         &mut self,
     ) -> Result<Result<(wit::Address, wit::Message), (wit::SendError, Option<wit::Context>)>> {
-        Ok(self.process.get_next_message_for_process_v0().await)
+        self.process.report_ready().await;
+        self.process.hibernate_if_idle().await?;
+        self.process.note_held(false);
+        let message = self.process.get_next_message_for_process_v0().await;
+        self.process.note_held(true);
+        Ok(message)
     }
 
     /// from a process: grab the blob part of the current prompting message.
     /// if the prompting message did not have a blob, will return None.
     /// will also return None if there is no prompting message.
     async fn get_blob(&mut self) -> Result<Option<wit::LazyLoadBlob>> {
+        if let Some(meter) = &self.process.meter {
+            meter.blob_copied(&self.process.last_blob);
+        }
         Ok(t::en_wit_blob_v0(self.process.last_blob.clone()))
     }
 
@@ -0,0 +1,66 @@
+use lib::types::core as t;
+use std::collections::{HashMap, HashSet};
+
+/// a package's processes as they were when a transaction over them, such as
+/// an install, was prepared: what is restored if it is aborted.
+pub struct Prepared {
+    /// the package's processes, with the capabilities they held
+    pub processes: t::ProcessMap,
+    /// capabilities issued by the package's processes, by the process
+    /// outside the package that holds them
+    pub issued: HashMap<t::ProcessId, HashSet<t::Capability>>,
+}
+
+/// the transactions over packages' processes not yet committed or aborted,
+/// by ID. they are not persisted: a reboot ends them as they stand.
+#[derive(Default)]
+pub struct Transactions {
+    prepared: HashMap<u64, Prepared>,
+}
+
+impl Transactions {
+    pub fn prepare(&mut self, id: u64, package_id: &t::PackageId, process_map: &t::ProcessMap) {
+        let processes = process_map
+            .iter()
+            .filter(|(process_id, _)| in_package(process_id, package_id))
+            .map(|(process_id, persisted)| (process_id.clone(), persisted.clone()))
+            .collect();
+        self.prepared.insert(
+            id,
+            Prepared {
+                processes,
+                issued: issued_by(package_id, process_map),
+            },
+        );
+    }
+
+    /// forget a transaction, returning what it kept, if it was prepared
+    pub fn end(&mut self, id: u64) -> Option<Prepared> {
+        self.prepared.remove(&id)
+    }
+}
+
+pub fn in_package(process_id: &t::ProcessId, package_id: &t::PackageId) -> bool {
+    t::PackageId::new(process_id.package(), process_id.publisher()) == *package_id
+}
+
+/// the capabilities issued by the processes of a package, by the process
+/// outside the package that holds them
+pub fn issued_by(
+    package_id: &t::PackageId,
+    process_map: &t::ProcessMap,
+) -> HashMap<t::ProcessId, HashSet<t::Capability>> {
+    process_map
+        .iter()
+        .filter(|(process_id, _)| !in_package(process_id, package_id))
+        .filter_map(|(process_id, persisted)| {
+            let caps: HashSet<t::Capability> = persisted
+                .capabilities
+                .keys()
+                .filter(|cap| in_package(&cap.issuer.process, package_id))
+                .cloned()
+                .collect();
+            (!caps.is_empty()).then(|| (process_id.clone(), caps))
+        })
+        .collect()
+}
@@ -65,6 +65,63 @@ pub fn encode_keyfile(
 }
 
 pub fn decode_keyfile(keyfile: &[u8], password: &str) -> Result<Keyfile, &'static str> {
+    let (username, routers, serialized_networking_keypair, jwt_secret_bytes, file_key) =
+        decrypt_keyfile(keyfile, password)?;
+
+    let networking_keypair = signature::Ed25519KeyPair::from_pkcs8(&serialized_networking_keypair)
+        .map_err(|_| "failed to parse networking keys")?;
+
+    let sealing_key = sealing_key(&serialized_networking_keypair)?;
+
+    Ok(Keyfile {
+        username,
+        routers,
+        networking_keypair,
+        sealing_key,
+        jwt_secret_bytes,
+        file_key,
+    })
+}
+
+/// re-encrypt a keyfile with a new password. the JWT secret is replaced,
+/// so that every login cookie made before stops working.
+pub fn rekey_keyfile(
+    keyfile: &[u8],
+    password: &str,
+    new_password: &str,
+) -> Result<Vec<u8>, &'static str> {
+    use ring::rand::SecureRandom;
+
+    let (username, routers, serialized_networking_keypair, _jwt_secret_bytes, file_key) =
+        decrypt_keyfile(keyfile, password)?;
+
+    let mut jwt_secret = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut jwt_secret)
+        .map_err(|_| "failed to generate jwt secret")?;
+
+    Ok(encode_keyfile(
+        new_password.to_string(),
+        username,
+        routers,
+        &serialized_networking_keypair,
+        &jwt_secret,
+        &file_key,
+    ))
+}
+
+/// the node password as keyfiles are sealed with
+pub fn password_hash(password: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("0x{}", hex::encode(Sha256::digest(password)))
+}
+
+/// the username, routers, serialized networking keypair, JWT secret and
+/// file key a keyfile holds
+fn decrypt_keyfile(
+    keyfile: &[u8],
+    password: &str,
+) -> Result<(String, Vec<String>, Vec<u8>, Vec<u8>, Vec<u8>), &'static str> {
     use generic_array::GenericArray;
 
     let (username, routers, salt, key_enc, jwt_enc, file_enc) =
@@ -99,9 +156,6 @@ pub fn decode_keyfile(keyfile: &[u8], password: &str) -> Result<Keyfile, &'stati
         .decrypt(net_nonce, &key_enc[12..])
         .map_err(|_| "failed to decrypt networking keys")?;
 
-    let networking_keypair = signature::Ed25519KeyPair::from_pkcs8(&serialized_networking_keypair)
-        .map_err(|_| "failed to parse networking keys")?;
-
     let jwt_secret_bytes: Vec<u8> = cipher
         .decrypt(jwt_nonce, &jwt_enc[12..])
         .map_err(|_| "failed to decrypt jwt secret")?;
@@ -110,19 +164,20 @@ pub fn decode_keyfile(keyfile: &[u8], password: &str) -> Result<Keyfile, &'stati
         .decrypt(file_nonce, &file_enc[12..])
         .map_err(|_| "failed to decrypt file key")?;
 
-    Ok(Keyfile {
+    Ok((
         username,
         routers,
-        networking_keypair,
+        serialized_networking_keypair,
         jwt_secret_bytes,
         file_key,
-    })
+    ))
 }
 
 pub fn generate_jwt(
     jwt_secret_bytes: &[u8],
     username: &str,
     subdomain: &Option<String>,
+    user: Option<&str>,
 ) -> Option<String> {
     use hmac::Hmac;
     use jwt::SignWithKey;
@@ -138,6 +193,7 @@ pub fn generate_jwt(
         username: username.to_string(),
         subdomain,
         expiration: 0,
+        user: user.map(|user| user.to_string()),
     };
 
     claims.sign_with_key(&jwt_secret).ok()
@@ -224,6 +280,32 @@ pub fn generate_file_key() -> Vec<u8> {
     key.to_vec()
 }
 
+/// the prefix of a PKCS#8 v2 Ed25519 document made by ring, before the seed
+const PKCS8_SEED_PREFIX: [u8; 16] = [
+    0x30, 0x53, 0x02, 0x01, 0x01, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// derive the X25519 secret used to open boxes sealed to our node from the
+/// seed of our networking keypair. peers seal to the Montgomery form of our
+/// networking public key, so no extra key needs to be published.
+pub fn sealing_key(serialized_networking_keypair: &[u8]) -> Result<[u8; 32], &'static str> {
+    use sha2::{Digest, Sha512};
+    let networking_keypair = signature::Ed25519KeyPair::from_pkcs8(serialized_networking_keypair)
+        .map_err(|_| "failed to parse networking keys")?;
+    let seed = serialized_networking_keypair
+        .strip_prefix(&PKCS8_SEED_PREFIX[..])
+        .and_then(|rest| rest.get(..32))
+        .ok_or("failed to read networking key seed")?;
+    let mut sealing_key = [0u8; 32];
+    sealing_key.copy_from_slice(&Sha512::digest(seed)[..32]);
+    // the secret must be the scalar behind our networking public key
+    let public = curve25519_dalek::EdwardsPoint::mul_base_clamped(sealing_key).compress();
+    if public.as_bytes() != networking_keypair.public_key().as_ref() {
+        return Err("networking key seed does not match public key");
+    }
+    Ok(sealing_key)
+}
+
 /// # Returns
 /// a pair of (public key (encoded as a hex string), serialized key as a pkcs8 Document)
 pub fn generate_networking_key() -> (String, ring::pkcs8::Document) {
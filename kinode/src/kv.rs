@@ -1,9 +1,11 @@
+use crate::{cap_feedback::Denials, kernel::flags::FeatureFlags};
 use dashmap::DashMap;
 use lib::types::core::{
     Address, CapMessage, CapMessageSender, Capability, KernelMessage, KvAction, KvError, KvRequest,
     KvResponse, LazyLoadBlob, Message, MessageReceiver, MessageSender, PackageId, PrintSender,
     Printout, ProcessId, Request, Response, KV_PROCESS_ID,
 };
+use lib::types::errors::ModuleError;
 use rocksdb::OptimisticTransactionDB;
 use std::{
     collections::{HashMap, VecDeque},
@@ -18,6 +20,7 @@ pub async fn kv(
     mut recv_from_loop: MessageReceiver,
     send_to_caps_oracle: CapMessageSender,
     home_directory_path: String,
+    feature_flags: Arc<FeatureFlags>,
 ) -> anyhow::Result<()> {
     let kv_path = Arc::new(format!("{home_directory_path}/kv"));
     if let Err(e) = fs::create_dir_all(&*kv_path).await {
@@ -31,6 +34,7 @@ pub async fn kv(
     let process_queues: HashMap<ProcessId, Arc<Mutex<VecDeque<KernelMessage>>>> = HashMap::new();
 
     while let Some(km) = recv_from_loop.recv().await {
+        crate::metrics::handled(&KV_PROCESS_ID, &km);
         if *our_node != km.source.node {
             Printout::new(
                 1,
@@ -62,6 +66,7 @@ pub async fn kv(
         let open_kvs = open_kvs.clone();
         let txs = txs.clone();
         let kv_path = kv_path.clone();
+        let feature_flags = feature_flags.clone();
 
         tokio::spawn(async move {
             let mut queue_lock = queue.lock().await;
@@ -77,12 +82,14 @@ pub async fn kv(
                     &send_to_loop,
                     &send_to_caps_oracle,
                     &kv_path,
+                    &feature_flags,
                 )
                 .await
                 {
                     Printout::new(1, format!("kv: {e}"))
                         .send(&send_to_terminal)
                         .await;
+                    let metadata = e.metadata();
                     KernelMessage::builder()
                         .id(km_id)
                         .source((our_node.as_str(), KV_PROCESS_ID.clone()))
@@ -91,7 +98,7 @@ pub async fn kv(
                             Response {
                                 inherit: false,
                                 body: serde_json::to_vec(&KvResponse::Err { error: e }).unwrap(),
-                                metadata: None,
+                                metadata,
                                 capabilities: vec![],
                             },
                             None,
@@ -115,7 +122,14 @@ async fn handle_request(
     send_to_loop: &MessageSender,
     send_to_caps_oracle: &CapMessageSender,
     kv_path: &str,
+    feature_flags: &FeatureFlags,
 ) -> Result<(), KvError> {
+    let denials = Denials::new(
+        feature_flags,
+        (our_node, KV_PROCESS_ID.clone()).into(),
+        &km,
+        send_to_loop,
+    );
     let KernelMessage {
         id,
         source,
@@ -152,6 +166,7 @@ async fn handle_request(
         send_to_caps_oracle,
         &request,
         kv_path,
+        &denials,
     )
     .await?;
 
@@ -164,6 +179,18 @@ async fn handle_request(
             // handled in check_caps.
             (serde_json::to_vec(&KvResponse::Ok).unwrap(), None)
         }
+        KvAction::RemoveAllDbs => {
+            // handled in check_caps
+            (serde_json::to_vec(&KvResponse::Ok).unwrap(), None)
+        }
+        KvAction::ListDbs => {
+            // permission checked in check_caps
+            let dbs = list_dbs(&format!("{}/{}", kv_path, request.package_id)).await?;
+            (
+                serde_json::to_vec(&KvResponse::Ok).unwrap(),
+                Some(serde_json::to_vec(&dbs).unwrap()),
+            )
+        }
         KvAction::Get { key } => {
             let db = match open_kvs.get(&(request.package_id, request.db)) {
                 None => {
@@ -208,6 +235,8 @@ async fn handle_request(
                     error: "no blob".into(),
                 });
             };
+            crate::disk::reserve((key.len() + blob.bytes.len()) as u64)
+                .map_err(|free| KvError::NoSpace { free })?;
 
             match tx_id {
                 None => {
@@ -333,6 +362,7 @@ async fn check_caps(
     send_to_caps_oracle: &CapMessageSender,
     request: &KvRequest,
     kv_path: &str,
+    denials: &Denials,
 ) -> Result<(), KvError> {
     let (send_cap_bool, recv_cap_bool) = tokio::sync::oneshot::channel();
     let src_package_id = PackageId::new(source.process.package(), source.process.publisher());
@@ -342,25 +372,27 @@ async fn check_caps(
         | KvAction::Set { .. }
         | KvAction::BeginTx
         | KvAction::Commit { .. } => {
+            let cap = Capability {
+                issuer: Address {
+                    node: our_node.to_string(),
+                    process: KV_PROCESS_ID.clone(),
+                },
+                params: serde_json::json!({
+                    "kind": "write",
+                    "db": request.db.to_string(),
+                })
+                .to_string(),
+            };
             send_to_caps_oracle
                 .send(CapMessage::Has {
                     on: source.process.clone(),
-                    cap: Capability {
-                        issuer: Address {
-                            node: our_node.to_string(),
-                            process: KV_PROCESS_ID.clone(),
-                        },
-                        params: serde_json::json!({
-                            "kind": "write",
-                            "db": request.db.to_string(),
-                        })
-                        .to_string(),
-                    },
+                    cap: cap.clone(),
                     responder: send_cap_bool,
                 })
                 .await?;
             let has_cap = recv_cap_bool.await?;
             if !has_cap {
+                denials.report(cap).await;
                 return Err(KvError::NoCap {
                     error: request.action.to_string(),
                 });
@@ -368,25 +400,27 @@ async fn check_caps(
             Ok(())
         }
         KvAction::Get { .. } => {
+            let cap = Capability {
+                issuer: Address {
+                    node: our_node.to_string(),
+                    process: KV_PROCESS_ID.clone(),
+                },
+                params: serde_json::json!({
+                    "kind": "read",
+                    "db": request.db.to_string(),
+                })
+                .to_string(),
+            };
             send_to_caps_oracle
                 .send(CapMessage::Has {
                     on: source.process.clone(),
-                    cap: Capability {
-                        issuer: Address {
-                            node: our_node.to_string(),
-                            process: KV_PROCESS_ID.clone(),
-                        },
-                        params: serde_json::json!({
-                            "kind": "read",
-                            "db": request.db.to_string(),
-                        })
-                        .to_string(),
-                    },
+                    cap: cap.clone(),
                     responder: send_cap_bool,
                 })
                 .await?;
             let has_cap = recv_cap_bool.await?;
             if !has_cap {
+                denials.report(cap).await;
                 return Err(KvError::NoCap {
                     error: request.action.to_string(),
                 });
@@ -442,10 +476,56 @@ async fn check_caps(
             fs::remove_dir_all(&db_path).await?;
             Ok(())
         }
+        KvAction::RemoveAllDbs | KvAction::ListDbs => {
+            if src_package_id != request.package_id {
+                let cap = Capability::new((our_node, KV_PROCESS_ID.clone()), "{\"root\":true}");
+                send_to_caps_oracle
+                    .send(CapMessage::Has {
+                        on: source.process.clone(),
+                        cap: cap.clone(),
+                        responder: send_cap_bool,
+                    })
+                    .await?;
+                if !recv_cap_bool.await? {
+                    denials.report(cap).await;
+                    return Err(KvError::NoCap {
+                        error: request.action.to_string(),
+                    });
+                }
+            }
+            if matches!(request.action, KvAction::ListDbs) {
+                return Ok(());
+            }
+
+            open_kvs.retain(|(package_id, _), _| package_id != &request.package_id);
+
+            let package_path = format!("{}/{}", kv_path, request.package_id);
+            if fs::metadata(&package_path).await.is_ok() {
+                fs::remove_dir_all(&package_path).await?;
+            }
+            Ok(())
+        }
         KvAction::Backup { .. } => Ok(()),
     }
 }
 
+/// the names of the databases in a package's directory, if it has one
+async fn list_dbs(package_path: &str) -> std::io::Result<Vec<String>> {
+    let mut dbs = vec![];
+    let mut entries = match fs::read_dir(package_path).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(dbs),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            dbs.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    dbs.sort();
+    Ok(dbs)
+}
+
 async fn add_capability(
     kind: &str,
     db: &str,
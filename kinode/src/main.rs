@@ -14,19 +14,39 @@ use std::env;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
+mod backup;
+mod blobs;
+mod cap_feedback;
+mod clock;
+mod disk;
 mod eth;
 #[cfg(feature = "simulation-mode")]
 mod fakenet;
+#[cfg(feature = "simulation-mode")]
+mod faults;
+#[cfg(feature = "fuzzing")]
+mod fuzz;
+mod groups;
 mod http;
+mod idempotency;
 mod kernel;
 mod keygen;
 mod kv;
+mod metrics;
 mod net;
+mod notify;
+mod password;
+mod progress;
+mod quiesce;
 #[cfg(not(feature = "simulation-mode"))]
 mod register;
+#[cfg(feature = "simulation-mode")]
+mod sim;
+mod socket;
 mod sol;
 mod sqlite;
 mod state;
+mod sync;
 mod terminal;
 mod timer;
 mod vfs;
@@ -34,6 +54,7 @@ mod vfs;
 const EVENT_LOOP_CHANNEL_CAPACITY: usize = 10_000;
 const EVENT_LOOP_DEBUG_CHANNEL_CAPACITY: usize = 50;
 const TERMINAL_CHANNEL_CAPACITY: usize = 32;
+const WEB_TERMINAL_CHANNEL_CAPACITY: usize = 32;
 const WEBSOCKET_SENDER_CHANNEL_CAPACITY: usize = 32;
 const HTTP_CHANNEL_CAPACITY: usize = 32;
 const HTTP_CLIENT_CHANNEL_CAPACITY: usize = 32;
@@ -42,6 +63,12 @@ const VFS_CHANNEL_CAPACITY: usize = 1_000;
 const CAP_CHANNEL_CAPACITY: usize = 1_000;
 const KV_CHANNEL_CAPACITY: usize = 1_000;
 const SQLITE_CHANNEL_CAPACITY: usize = 1_000;
+const SYNC_CHANNEL_CAPACITY: usize = 1_000;
+const NOTIFY_CHANNEL_CAPACITY: usize = 1_000;
+const GROUPS_CHANNEL_CAPACITY: usize = 1_000;
+const SMTP_CHANNEL_CAPACITY: usize = 1_000;
+const SOCKET_CHANNEL_CAPACITY: usize = 1_000;
+const PROGRESS_CHANNEL_CAPACITY: usize = 1_000;
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const WS_MIN_PORT: u16 = 9_000;
 const TCP_MIN_PORT: u16 = 10_000;
@@ -67,6 +94,82 @@ async fn main() {
         .get_one::<String>("home")
         .expect("home directory required");
     create_home_directory(&home_directory_path).await;
+
+    if let Some(archive) = matches.get_one::<String>("backup") {
+        let Some(password) = matches.get_one::<String>("password") else {
+            println!("backup: --password required to seal the backup");
+            std::process::exit(1);
+        };
+        let packages = matches
+            .get_many::<String>("backup-packages")
+            .map(|packages| packages.cloned().collect());
+        if let Err(e) = backup::backup(home_directory_path, archive, password, packages) {
+            println!("backup: {e:#}");
+            std::process::exit(1);
+        }
+        println!("backup: wrote {archive}");
+        return;
+    }
+    if let Some(archive) = matches.get_one::<String>("restore") {
+        let Some(password) = matches.get_one::<String>("password") else {
+            println!("restore: --password required to open the backup");
+            std::process::exit(1);
+        };
+        match backup::restore(home_directory_path, archive, password) {
+            Ok(node) => println!("restore: restored {node}"),
+            Err(e) => {
+                println!("restore: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        match matches.get_one::<String>("new-password") {
+            None => println!("restore: boot with the password the backup was sealed with"),
+            Some(new_password) => {
+                if let Err(e) =
+                    password::change_password(home_directory_path, password, new_password)
+                {
+                    println!("restore: couldn't change password: {e:#}");
+                    std::process::exit(1);
+                }
+                println!("restore: password changed; boot with the new password");
+            }
+        }
+        return;
+    }
+    if let Some(new_password) = matches.get_one::<String>("new-password") {
+        let Some(password) = matches.get_one::<String>("password") else {
+            println!("password: --password required to open the keyfile");
+            std::process::exit(1);
+        };
+        match password::change_password(home_directory_path, password, new_password) {
+            Ok(node) => println!(
+                "password: changed the password of {node}; every login has been signed out"
+            ),
+            Err(e) => {
+                println!("password: {e:#}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    #[cfg(feature = "fuzzing")]
+    if let Some(target) = matches.get_one::<String>("fuzz") {
+        if let Err(e) = fuzz::fuzz(
+            home_directory_path,
+            target,
+            matches.get_one::<String>("fuzz-corpus"),
+            *matches.get_one::<u64>("fuzz-runs").unwrap(),
+            *matches.get_one::<u64>("fuzz-seed").unwrap(),
+        )
+        .await
+        {
+            println!("fuzz: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let http_server_port = set_http_server_port(matches.get_one::<u16>("port")).await;
     let ws_networking_port = matches.get_one::<u16>("ws-port");
     #[cfg(not(feature = "simulation-mode"))]
@@ -79,6 +182,56 @@ async fn main() {
 
     // detached determines whether terminal is interactive
     let detached = *matches.get_one::<bool>("detached").unwrap();
+    // whether http_server offers the terminal over a websocket
+    let web_terminal = *matches.get_one::<bool>("web-terminal").unwrap();
+
+    // which IP family to advertise and to try first when a peer offers both
+    let prefer_ipv6 = matches.get_one::<String>("ip-preference").unwrap() == "v6";
+    // other hosts we may be reached at, to advertise in our `~endpoints` note
+    let endpoints: Vec<String> = matches
+        .get_many::<String>("endpoints")
+        .map(|hosts| hosts.cloned().collect())
+        .unwrap_or_default();
+    assert!(endpoints.len() <= 8, "--endpoints takes at most 8 hosts");
+
+    // which Noise cipher suites to speak with peers, and how often to rekey
+    let cipher = net::CipherConfig {
+        suites: matches
+            .get_many::<String>("cipher-suites")
+            .unwrap()
+            .map(|suite| suite.parse::<net::CipherSuite>())
+            .collect::<Result<_, _>>()
+            .expect("--cipher-suites takes cipher suite names"),
+        rekey_interval: std::time::Duration::from_secs(
+            *matches.get_one::<u64>("rekey-interval").unwrap(),
+        ),
+    };
+
+    // only show prints from these processes in the terminal; the log file gets all
+    let log_filter: Option<Vec<ProcessId>> = matches
+        .get_many::<String>("log-filter")
+        .map(|processes| {
+            processes
+                .map(|process| process.parse::<ProcessId>())
+                .collect::<Result<_, _>>()
+        })
+        .transpose()
+        .expect("--log-filter takes process IDs");
+
+    // feature flags gating kernel behaviors as they roll out: those set on
+    // this node, and those turned on for this boot only
+    let mut forced_flags: Vec<String> = matches
+        .get_many::<String>("feature")
+        .map(|flags| flags.cloned().collect())
+        .unwrap_or_default();
+    if *matches.get_one::<bool>("cap-feedback").unwrap() {
+        forced_flags.push("cap-feedback".to_string());
+    }
+    let feature_flags = Arc::new(
+        kernel::flags::FeatureFlags::load(&home_directory_path, forced_flags)
+            .await
+            .expect("failed to load feature flags"),
+    );
 
     #[cfg(feature = "simulation-mode")]
     let (fake_node_name, fakechain_port) = (
@@ -155,6 +308,24 @@ async fn main() {
     // sqlite sender and receiver
     let (sqlite_sender, sqlite_receiver): (MessageSender, MessageReceiver) =
         mpsc::channel(SQLITE_CHANNEL_CAPACITY);
+    // sync sender and receiver
+    let (sync_sender, sync_receiver): (MessageSender, MessageReceiver) =
+        mpsc::channel(SYNC_CHANNEL_CAPACITY);
+    // notify sender and receiver
+    let (notify_sender, notify_receiver): (MessageSender, MessageReceiver) =
+        mpsc::channel(NOTIFY_CHANNEL_CAPACITY);
+    // groups sender and receiver
+    let (groups_sender, groups_receiver): (MessageSender, MessageReceiver) =
+        mpsc::channel(GROUPS_CHANNEL_CAPACITY);
+    // smtp sender and receiver
+    let (smtp_sender, smtp_receiver): (MessageSender, MessageReceiver) =
+        mpsc::channel(SMTP_CHANNEL_CAPACITY);
+    // socket sender and receiver
+    let (socket_sender, socket_receiver): (MessageSender, MessageReceiver) =
+        mpsc::channel(SOCKET_CHANNEL_CAPACITY);
+    // progress sender and receiver
+    let (progress_sender, progress_receiver): (MessageSender, MessageReceiver) =
+        mpsc::channel(PROGRESS_CHANNEL_CAPACITY);
     // http server channel w/ websockets (eyre)
     let (http_server_sender, http_server_receiver): (MessageSender, MessageReceiver) =
         mpsc::channel(HTTP_CHANNEL_CAPACITY);
@@ -173,8 +344,13 @@ async fn main() {
     // terminal receives prints via this channel, all other modules send prints
     let (print_sender, print_receiver): (PrintSender, PrintReceiver) =
         mpsc::channel(TERMINAL_CHANNEL_CAPACITY);
+    // terminal receives commands from web terminals in http_server via this channel
+    let (web_terminal_sender, web_terminal_receiver): (
+        terminal::remote::WebTerminalSender,
+        terminal::remote::WebTerminalReceiver,
+    ) = mpsc::channel(WEB_TERMINAL_CHANNEL_CAPACITY);
 
-    let our_ip = find_public_ip().await;
+    let our_ip = find_public_ip(prefer_ipv6).await;
     let (ws_tcp_handle, ws_flag_used) = setup_networking("ws", ws_networking_port).await;
     #[cfg(not(feature = "simulation-mode"))]
     let (tcp_tcp_handle, tcp_flag_used) = setup_networking("tcp", tcp_networking_port).await;
@@ -190,6 +366,7 @@ async fn main() {
         ),
         // NOTE: fakenodes only using WS protocol at the moment
         fakechain_port,
+        &endpoints,
     )
     .await;
 
@@ -209,6 +386,8 @@ async fn main() {
                 http_server_port,
                 rpc.cloned(),
                 detached,
+                endpoints,
+                print_sender.clone(),
             )
             .await
         }
@@ -277,6 +456,42 @@ async fn main() {
             None,
             false,
         ),
+        (
+            ProcessId::new(Some("sync"), "distro", "sys"),
+            sync_sender,
+            None,
+            true,
+        ),
+        (
+            ProcessId::new(Some("notify"), "distro", "sys"),
+            notify_sender,
+            None,
+            true,
+        ),
+        (
+            ProcessId::new(Some("groups"), "distro", "sys"),
+            groups_sender,
+            None,
+            true,
+        ),
+        (
+            ProcessId::new(Some("smtp"), "distro", "sys"),
+            smtp_sender,
+            None,
+            false,
+        ),
+        (
+            ProcessId::new(Some("socket"), "distro", "sys"),
+            socket_sender,
+            None,
+            false,
+        ),
+        (
+            ProcessId::new(Some("progress"), "distro", "sys"),
+            progress_sender,
+            None,
+            true,
+        ),
     ];
 
     /*
@@ -288,19 +503,69 @@ async fn main() {
     let networking_keypair_arc = Arc::new(decoded_keyfile.networking_keypair);
     let our_name_arc = Arc::new(our.name.clone());
 
+    let release_hashes = match matches.get_one::<String>("distro-hashes") {
+        Some(path) => Some(
+            state::read_release_hashes(path)
+                .await
+                .expect("failed to read distro hashes"),
+        ),
+        None => None,
+    };
     let (kernel_process_map, db, reverse_cap_index) = state::load_state(
         our.name.clone(),
         networking_keypair_arc.clone(),
         home_directory_path.clone(),
         runtime_extensions.clone(),
+        release_hashes,
+        *matches
+            .get_one::<bool>("allow-unverified-packages")
+            .unwrap(),
     )
     .await
     .expect("state load failed!");
 
+    #[cfg(feature = "simulation-mode")]
+    sim::init(
+        matches.get_one::<u64>("sim-seed").cloned(),
+        *matches.get_one::<bool>("virtual-time").unwrap(),
+    );
+    #[cfg(feature = "simulation-mode")]
+    if let Some(fault_script) = matches.get_one::<String>("fault-script") {
+        faults::load(fault_script, print_sender.clone())
+            .await
+            .expect("failed to load fault script");
+    }
+
+    // the in-memory PKI, filled by net and read by the kernel on behalf of processes
+    let pki: net::OnchainPKI = Arc::new(dashmap::DashMap::new());
+
+    let blob_store = Arc::new(
+        blobs::BlobStore::new(&home_directory_path)
+            .await
+            .expect("failed to open blob store"),
+    );
+
     let mut tasks = tokio::task::JoinSet::<Result<()>>::new();
+    tasks.spawn(blobs::sweeper(blob_store.clone()));
+    tasks.spawn(disk::monitor(
+        home_directory_path.clone(),
+        print_sender.clone(),
+    ));
+    // the node's clock is the host's unless it is given something to sync with
+    let ntp_servers: Vec<String> = matches
+        .get_many::<String>("ntp-server")
+        .map(|servers| servers.cloned().collect())
+        .unwrap_or_default();
+    let clock_peers = *matches.get_one::<bool>("clock-peers").unwrap();
+    if !ntp_servers.is_empty() || clock_peers {
+        tasks.spawn(clock::sync(ntp_servers, clock_peers, print_sender.clone()));
+    }
     tasks.spawn(kernel::kernel(
         our.clone(),
         networking_keypair_arc.clone(),
+        decoded_keyfile.sealing_key,
+        pki.clone(),
+        blob_store.clone(),
         kernel_process_map.clone(),
         reverse_cap_index,
         caps_oracle_sender.clone(),
@@ -327,16 +592,51 @@ async fn main() {
                 }
             })
             .collect(),
+        *matches.get_one::<bool>("bench").unwrap(),
+        matches
+            .get_one::<lib::types::core::ProcessLimits>("script-limits")
+            .cloned(),
+        kernel::process::RequestTimeouts::new(
+            *matches.get_one::<u64>("default-request-timeout").unwrap(),
+            *matches.get_one::<u64>("max-request-timeout").unwrap(),
+        ),
+        matches
+            .get_one::<usize>("kernel-shards")
+            .cloned()
+            .unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+            }),
+        kernel::limits::MessageLimits {
+            node: kernel::limits::SizeLimit {
+                body: *matches.get_one::<usize>("max-body-size").unwrap(),
+                blob: *matches.get_one::<usize>("max-blob-size").unwrap(),
+            },
+            modules: matches
+                .get_many::<(ProcessId, kernel::limits::SizeLimit)>("module-size-limit")
+                .map(|limits| limits.cloned().collect())
+                .unwrap_or_default(),
+        },
+        feature_flags.clone(),
+        matches
+            .get_one::<u64>("dedup-window")
+            .map(|secs| std::time::Duration::from_secs(*secs)),
     ));
     tasks.spawn(net::networking(
         our.clone(),
         our_ip.to_string(),
         networking_keypair_arc.clone(),
+        pki,
         kernel_message_sender.clone(),
         network_error_sender,
         print_sender.clone(),
+        caps_oracle_sender.clone(),
         net_message_receiver,
+        blob_store.clone(),
+        home_directory_path.clone(),
         *matches.get_one::<bool>("reveal-ip").unwrap_or(&true),
+        prefer_ipv6,
+        cipher,
+        *matches.get_one::<bool>("lan-discovery").unwrap(),
     ));
     tasks.spawn(state::state_sender(
         our_name_arc.clone(),
@@ -345,6 +645,7 @@ async fn main() {
         state_receiver,
         db,
         home_directory_path.clone(),
+        caps_oracle_sender.clone(),
     ));
     tasks.spawn(kv::kv(
         our_name_arc.clone(),
@@ -353,6 +654,7 @@ async fn main() {
         kv_receiver,
         caps_oracle_sender.clone(),
         home_directory_path.clone(),
+        feature_flags.clone(),
     ));
     tasks.spawn(sqlite::sqlite(
         our_name_arc.clone(),
@@ -361,21 +663,28 @@ async fn main() {
         sqlite_receiver,
         caps_oracle_sender.clone(),
         home_directory_path.clone(),
+        feature_flags.clone(),
     ));
     tasks.spawn(http::server::http_server(
         our.name.clone(),
         http_server_port,
+        home_directory_path.clone(),
         encoded_keyfile,
         decoded_keyfile.jwt_secret_bytes.clone(),
         http_server_receiver,
         kernel_message_sender.clone(),
         print_sender.clone(),
+        caps_oracle_sender.clone(),
+        web_terminal.then_some(web_terminal_sender),
     ));
     tasks.spawn(http::client::http_client(
         our.name.clone(),
         kernel_message_sender.clone(),
         http_client_receiver,
         print_sender.clone(),
+        blob_store.clone(),
+        home_directory_path.clone(),
+        decoded_keyfile.file_key.clone(),
     ));
     tasks.spawn(timer::timer_service(
         our.name.clone(),
@@ -385,6 +694,7 @@ async fn main() {
     ));
     tasks.spawn(eth::provider(
         our.name.clone(),
+        our.networking_key.clone(),
         home_directory_path.clone(),
         eth_provider_config,
         kernel_message_sender.clone(),
@@ -393,6 +703,53 @@ async fn main() {
         caps_oracle_sender.clone(),
         print_sender.clone(),
     ));
+    tasks.spawn(sync::sync(
+        our_name_arc.clone(),
+        kernel_message_sender.clone(),
+        print_sender.clone(),
+        sync_receiver,
+        caps_oracle_sender.clone(),
+        home_directory_path.clone(),
+        blob_store.clone(),
+    ));
+    tasks.spawn(notify::notify(
+        our_name_arc.clone(),
+        kernel_message_sender.clone(),
+        print_sender.clone(),
+        notify_receiver,
+        caps_oracle_sender.clone(),
+        home_directory_path.clone(),
+    ));
+    tasks.spawn(groups::groups(
+        our_name_arc.clone(),
+        kernel_message_sender.clone(),
+        groups_receiver,
+        home_directory_path.clone(),
+    ));
+    tasks.spawn(notify::smtp::smtp(
+        our_name_arc.clone(),
+        kernel_message_sender.clone(),
+        print_sender.clone(),
+        smtp_receiver,
+        caps_oracle_sender.clone(),
+        home_directory_path.clone(),
+        decoded_keyfile.file_key.clone(),
+    ));
+    tasks.spawn(socket::socket(
+        our_name_arc.clone(),
+        kernel_message_sender.clone(),
+        print_sender.clone(),
+        socket_receiver,
+        caps_oracle_sender.clone(),
+        home_directory_path.clone(),
+    ));
+    tasks.spawn(progress::progress(
+        our_name_arc.clone(),
+        kernel_message_sender.clone(),
+        print_sender.clone(),
+        progress_receiver,
+        caps_oracle_sender.clone(),
+    ));
     tasks.spawn(vfs::vfs(
         our_name_arc,
         kernel_message_sender.clone(),
@@ -400,6 +757,9 @@ async fn main() {
         vfs_message_receiver,
         caps_oracle_sender.clone(),
         home_directory_path.clone(),
+        blob_store,
+        Arc::new(networking_keypair_arc.public_key().as_ref().to_vec()),
+        feature_flags,
     ));
 
     // if a runtime task exits, try to recover it,
@@ -421,8 +781,10 @@ async fn main() {
             kernel_debug_message_sender,
             print_sender.clone(),
             print_receiver,
+            web_terminal_receiver,
             detached,
             verbose_mode,
+            log_filter,
         ) => {
             match quit {
                 Ok(()) => {
@@ -525,6 +887,7 @@ pub async fn simulate_node(
     home_directory_path: &str,
     (ws_networking, _ws_used): (tokio::net::TcpListener, bool),
     fakechain_port: Option<u16>,
+    endpoints: &[String],
 ) -> (Identity, Vec<u8>, Keyfile) {
     match fake_node_name {
         None => {
@@ -569,7 +932,7 @@ pub async fn simulate_node(
             let fakechain_port: u16 = fakechain_port.unwrap_or(8545);
             let ws_port = ws_networking.local_addr().unwrap().port();
 
-            fakenet::mint_local(&name, ws_port, &pubkey, fakechain_port)
+            fakenet::mint_local(&name, ws_port, &pubkey, fakechain_port, endpoints)
                 .await
                 .unwrap();
 
@@ -589,6 +952,7 @@ pub async fn simulate_node(
                     networking_keypair.as_ref(),
                 )
                 .unwrap(),
+                sealing_key: keygen::sealing_key(networking_keypair.as_ref()).unwrap(),
                 jwt_secret_bytes: jwt_secret.to_vec(),
                 file_key: keygen::generate_file_key(),
             };
@@ -654,15 +1018,148 @@ fn build_command() -> Command {
                 .value_parser(value_parser!(bool)),
         )
         .arg(
-            arg!(--detached <IS_DETACHED> "Run in detached mode (don't accept input)")
+            arg!(--"ip-preference" <FAMILY> "IP family to advertise if the host has both, and to try first for peers that offer both: v4 or v6")
+                .default_value("v4")
+                .value_parser(["v4", "v6"]),
+        )
+        .arg(
+            arg!(--endpoints <HOSTS> "Other hosts, IP addresses or domains, this node can be reached at, for peers to also try. Written to the ~endpoints note when registering a direct node")
+                .num_args(1..),
+        )
+        .arg(
+            arg!(--"cipher-suites" <SUITES> "Noise cipher suites to accept from peers, most preferred first: chachapoly-blake2s, aesgcm-sha256, chachapoly-sha256. Nodes that predate negotiation speak only chachapoly-blake2s, and can be reached only while it is accepted")
+                .num_args(1..)
+                .default_values(["chachapoly-sha256", "aesgcm-sha256", "chachapoly-blake2s"]),
+        )
+        .arg(
+            arg!(--"rekey-interval" <SECS> "Seconds between rekeys of each peer connection that negotiated its cipher suite")
+                .default_value("3600")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"lan-discovery" "Find sibling nodes on the local network by multicast, and connect to them there rather than at their KNS addresses. Only direct nodes announce themselves")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--detached <IS_DETACHED> "Run in detached mode (don't accept keyboard input; take command lines from stdin if piped)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"web-terminal" "Offer the terminal over a websocket at /terminal to admins who give their password again")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            arg!(--"log-filter" <PROCESS_ID> "Only show prints from these processes in the terminal")
+                .num_args(1..),
+        )
         .arg(arg!(--rpc <RPC> "Add a WebSockets RPC URL at boot"))
-        .arg(arg!(--password <PASSWORD> "Node password (in double quotes)"));
+        .arg(
+            arg!(--bench "Meter the fuel, time, memory and blob copies used by processes for benchmarking")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"dedup-window" <SECS> "Drop a request from a remote node if the same one arrived this many seconds before, answering it with the first one's response")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"ntp-server" <HOST> "NTP servers to sync the node's clock with, as host or host:port, such as pool.ntp.org [default: none]")
+                .num_args(1..),
+        )
+        .arg(
+            arg!(--"clock-peers" "Also compare the node's clock with those of the nodes it is connected to")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"cap-feedback" "Tell a process which capability it lacked when the kernel drops its message, or the vfs, kv or sqlite module refuses its request (same as --feature cap-feedback)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--feature <FLAG> "Turn a feature flag on for this boot, whatever it is set to")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            arg!(--"distro-hashes" <PATH> "Check the bundled distro packages against the distro_hashes.txt a release published, refusing to boot on a mismatch"),
+        )
+        .arg(
+            arg!(--"allow-unverified-packages" "Boot even if a bundled distro package doesn't match the hash given with --distro-hashes")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--"default-request-timeout" <SECS> "Seconds a process waits for a response when a request asks for 0")
+                .default_value("30")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"max-request-timeout" <SECS> "Most seconds a process may wait for a response; longer timeouts are clamped")
+                .default_value("86400")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"kernel-shards" <SHARDS> "Number of tasks checking, routing and delivering messages (default: number of cores)")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"max-body-size" <BYTES> "Largest message body the kernel will pass on")
+                .default_value("67108864")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"max-blob-size" <BYTES> "Largest message blob the kernel will pass on")
+                .default_value("1073741824")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            arg!(--"module-size-limit" <LIMIT> "Tighter limit for messages to a runtime module, as <process_id>=<body bytes>,<blob bytes>")
+                .action(clap::ArgAction::Append)
+                .value_parser(kernel::limits::parse_module_limit),
+        )
+        .arg(
+            arg!(--"script-limits" <LIMITS> "Limits every script is held to, as any of fuel=<fuel>,memory=<bytes>,wall-time=<secs>. A script may set tighter ones in its scripts.json entry. Counting fuel slows every process down")
+                .value_parser(kernel::limits::parse_script_limits),
+        )
+        .arg(arg!(--password <PASSWORD> "Node password (in double quotes)"))
+        .arg(arg!(--backup <ARCHIVE> "Write an encrypted backup of this stopped node to ARCHIVE instead of booting"))
+        .arg(
+            arg!(--"backup-packages" <PACKAGE_ID> "Only back up the files and processes of these packages")
+                .num_args(1..)
+                .requires("backup"),
+        )
+        .arg(
+            arg!(--restore <ARCHIVE> "Restore a backup into this empty home directory instead of booting")
+                .conflicts_with("backup"),
+        )
+        .arg(
+            arg!(--"new-password" <PASSWORD> "Change the password of this stopped node, or of the node restored, instead of booting")
+                .conflicts_with("backup"),
+        );
+
+    #[cfg(feature = "fuzzing")]
+    let app = app
+        .arg(arg!(--fuzz <TARGET> "Fuzz a module instead of booting: vfs, state, kv, sqlite, http or kernel"))
+        .arg(arg!(--"fuzz-corpus" <DIR> "Directory of extra inputs to replay and mutate"))
+        .arg(
+            arg!(--"fuzz-runs" <RUNS> "Number of inputs to feed the module")
+                .default_value("10000")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"fuzz-seed" <SEED> "Seed for input mutation")
+                .default_value("0")
+                .value_parser(value_parser!(u64)),
+        );
 
     #[cfg(feature = "simulation-mode")]
     let app = app
         .arg(arg!(--"fake-node-name" <NAME> "Name of fake node to boot"))
+        .arg(arg!(--"fault-script" <PATH> "JSON script of faults to inject into message delivery"))
+        .arg(
+            arg!(--"sim-seed" <SEED> "Seed that determines the order in which waiting messages are delivered and the bytes processes get from random-bytes")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"virtual-time" "Drive timers by virtual time, advanced explicitly by a test harness")
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(
             arg!(--"fakechain-port" <FAKECHAIN_PORT> "Port to bind to for fakechain")
                 .value_parser(value_parser!(u16)),
@@ -670,26 +1167,45 @@ fn build_command() -> Command {
     app
 }
 
-/// Attempts to find the public IPv4 address of the node.
+/// Attempts to find the public IP address of the node, of the preferred
+/// family first, so that IPv6-only hosts can boot as direct nodes.
 /// If in simulation mode, it immediately returns localhost.
 /// Otherwise, it tries to find the public IP and defaults to localhost on failure.
-async fn find_public_ip() -> std::net::Ipv4Addr {
+async fn find_public_ip(prefer_ipv6: bool) -> std::net::IpAddr {
     #[cfg(feature = "simulation-mode")]
     {
-        std::net::Ipv4Addr::LOCALHOST
+        let _ = prefer_ipv6;
+        std::net::Ipv4Addr::LOCALHOST.into()
     }
 
     #[cfg(not(feature = "simulation-mode"))]
     {
         println!("Finding public IP address...");
-        match tokio::time::timeout(std::time::Duration::from_secs(5), public_ip::addr_v4()).await {
-            Ok(Some(ip)) => {
+        let timeout = std::time::Duration::from_secs(5);
+        let v4 = async {
+            tokio::time::timeout(timeout, public_ip::addr_v4())
+                .await
+                .ok()
+                .flatten()
+                .map(std::net::IpAddr::from)
+        };
+        let v6 = async {
+            tokio::time::timeout(timeout, public_ip::addr_v6())
+                .await
+                .ok()
+                .flatten()
+                .map(std::net::IpAddr::from)
+        };
+        let (v4, v6) = tokio::join!(v4, v6);
+        let found = if prefer_ipv6 { v6.or(v4) } else { v4.or(v6) };
+        match found {
+            Some(ip) => {
                 println!("Public IP found: {ip}");
                 ip
             }
-            _ => {
-                println!("Failed to find public IPv4 address: booting as a routed node.");
-                std::net::Ipv4Addr::LOCALHOST
+            None => {
+                println!("Failed to find public IP address: booting as a routed node.");
+                std::net::Ipv4Addr::LOCALHOST.into()
             }
         }
     }
@@ -715,6 +1231,8 @@ async fn serve_register_fe(
     http_server_port: u16,
     maybe_rpc: Option<String>,
     detached: bool,
+    endpoints: Vec<String>,
+    print_tx: PrintSender,
 ) -> (Identity, Vec<u8>, Keyfile) {
     let (kill_tx, kill_rx) = tokio::sync::oneshot::channel::<bool>();
 
@@ -733,7 +1251,9 @@ async fn serve_register_fe(
                 http_server_port,
                 disk_keyfile,
                 maybe_rpc,
-                detached) => {
+                detached,
+                endpoints,
+                print_tx) => {
             panic!("registration failed")
         }
         Some((our, decoded_keyfile, encoded_keyfile)) = rx.recv() => {
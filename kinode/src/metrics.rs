@@ -0,0 +1,135 @@
+//! runtime metrics, kept since boot and read by processes through the kernel
+//! with `KernelPrint::Latency`.
+//!
+//! each stage of message handling keeps a histogram of its latencies, from a
+//! message being sent, which is when it is stamped, to the stage handling it:
+//! the kernel's main loop, passing it on, and each runtime module. so that
+//! time spent queued is counted, latencies are measured from the stamps,
+//! which are by the host's monotonic clock, so to the microsecond.
+use dashmap::DashMap;
+use lib::types::core::{KernelMessage, LatencyHistogram, ProcessId};
+use std::time::Duration;
+
+/// upper bounds of the histogram buckets, in microseconds. a last bucket
+/// holds everything slower.
+const BOUNDS_MICROS: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+lazy_static::lazy_static! {
+    static ref LATENCIES: DashMap<ProcessId, Histogram> = DashMap::new();
+}
+
+#[derive(Default)]
+struct Histogram {
+    counts: [u64; BOUNDS_MICROS.len() + 1],
+    count: u64,
+    sum_micros: u64,
+    max_micros: u64,
+}
+
+/// record a latency of `stage`
+pub fn record(stage: &ProcessId, latency: Duration) {
+    let micros = latency.as_micros() as u64;
+    let bucket = BOUNDS_MICROS
+        .iter()
+        .position(|bound| micros <= *bound)
+        .unwrap_or(BOUNDS_MICROS.len());
+    let mut histogram = LATENCIES.entry(stage.clone()).or_default();
+    histogram.counts[bucket] += 1;
+    histogram.count += 1;
+    histogram.sum_micros += micros;
+    histogram.max_micros = histogram.max_micros.max(micros);
+}
+
+/// record that `module` is handling a message
+pub fn handled(module: &ProcessId, km: &KernelMessage) {
+    if let Some(sent_at) = km.sent_at {
+        record(module, sent_at.elapsed());
+    }
+}
+
+pub fn latencies() -> Vec<LatencyHistogram> {
+    let mut latencies: Vec<LatencyHistogram> = LATENCIES
+        .iter()
+        .map(|entry| LatencyHistogram {
+            stage: entry.key().clone(),
+            bounds_micros: BOUNDS_MICROS.to_vec(),
+            counts: entry.counts.to_vec(),
+            count: entry.count,
+            sum_micros: entry.sum_micros,
+            max_micros: entry.max_micros,
+        })
+        .collect();
+    latencies.sort_by_key(|histogram| histogram.stage.to_string());
+    latencies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::{Address, Message, Request};
+    use std::time::Instant;
+
+    fn histogram(stage: &ProcessId) -> LatencyHistogram {
+        latencies()
+            .into_iter()
+            .find(|histogram| &histogram.stage == stage)
+            .unwrap()
+    }
+
+    fn message(sent_at: Option<Instant>) -> KernelMessage {
+        let mut km = KernelMessage::builder()
+            .source(Address::new("our", ProcessId::new(Some("a"), "b", "c")))
+            .target(Address::new("our", ProcessId::new(Some("d"), "e", "f")))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: None,
+                body: vec![],
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap();
+        km.sent_at = sent_at;
+        km
+    }
+
+    #[test]
+    fn latencies_land_in_microsecond_buckets() {
+        let stage = ProcessId::new(Some("buckets"), "metrics", "test");
+        record(&stage, Duration::from_micros(40));
+        record(&stage, Duration::from_micros(100));
+        record(&stage, Duration::from_micros(101));
+        record(&stage, Duration::from_micros(700));
+        record(&stage, Duration::from_secs(60));
+        let histogram = histogram(&stage);
+        assert_eq!(histogram.bounds_micros, BOUNDS_MICROS.to_vec());
+        assert_eq!(histogram.counts.len(), BOUNDS_MICROS.len() + 1);
+        assert_eq!(histogram.counts[0], 2);
+        assert_eq!(histogram.counts[1], 1);
+        assert_eq!(histogram.counts[3], 1);
+        assert_eq!(histogram.counts[BOUNDS_MICROS.len()], 1);
+        assert_eq!(histogram.count, 5);
+        assert_eq!(histogram.sum_micros, 40 + 100 + 101 + 700 + 60_000_000);
+        assert_eq!(histogram.max_micros, 60_000_000);
+    }
+
+    #[test]
+    fn handled_measures_from_when_sent() {
+        let stage = ProcessId::new(Some("handled"), "metrics", "test");
+        let sent_at = Instant::now() - Duration::from_millis(30);
+        handled(&stage, &message(Some(sent_at)));
+        let histogram = histogram(&stage);
+        assert_eq!(histogram.count, 1);
+        assert!(histogram.max_micros >= 30_000);
+    }
+
+    #[test]
+    fn unstamped_messages_are_not_counted() {
+        let stage = ProcessId::new(Some("unstamped"), "metrics", "test");
+        handled(&stage, &message(None));
+        assert!(latencies().iter().all(|histogram| histogram.stage != stage));
+    }
+}
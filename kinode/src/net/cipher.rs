@@ -0,0 +1,257 @@
+//! which Noise parameters we speak with a peer, and how we agree on them.
+//!
+//! an initiator first sends a [`SuiteOffer`] naming the suites it accepts, in
+//! order of preference, and the responder answers with the first of them that
+//! it accepts too. both bind the offer and the answer into the handshake as
+//! its prologue, so that editing them in flight to force a weaker suite breaks
+//! the handshake instead. a peer that knows nothing of negotiation drops the
+//! connection on seeing an offer, leaving it [`Unanswered`]; if we still
+//! accept the legacy suite, we connect again and open the handshake as nodes
+//! always have, with a bare Noise 'e' message. a node that accepts only the
+//! legacy suite never offers.
+//!
+//! connections set up by negotiation also rekey each direction every rekey
+//! interval, or every [`REKEY_MESSAGES`] messages, whichever comes first.
+use serde::{Deserialize, Serialize};
+use snow::params::NoiseParams;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+pub const REKEY_MESSAGES: u64 = 1 << 20;
+const PROLOGUE: &[u8] = b"kinode-suite-negotiation";
+/// opens every [`SuiteOffer`], so that it can't be mistaken for a Noise 'e'
+/// message or a routing request, whose bytes might happen to parse as one
+const OFFER_MAGIC: &[u8] = b"kinode-suite-offer";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CipherSuite {
+    /// what every node spoke before negotiation
+    ChaChaPolyBlake2s,
+    AesGcmSha256,
+    ChaChaPolySha256,
+}
+
+pub const LEGACY: CipherSuite = CipherSuite::ChaChaPolyBlake2s;
+
+impl CipherSuite {
+    pub const ALL: [CipherSuite; 3] = [
+        CipherSuite::ChaChaPolyBlake2s,
+        CipherSuite::AesGcmSha256,
+        CipherSuite::ChaChaPolySha256,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CipherSuite::ChaChaPolyBlake2s => "chachapoly-blake2s",
+            CipherSuite::AesGcmSha256 => "aesgcm-sha256",
+            CipherSuite::ChaChaPolySha256 => "chachapoly-sha256",
+        }
+    }
+
+    fn params(&self) -> NoiseParams {
+        match self {
+            CipherSuite::ChaChaPolyBlake2s => "Noise_XX_25519_ChaChaPoly_BLAKE2s",
+            CipherSuite::AesGcmSha256 => "Noise_XX_25519_AESGCM_SHA256",
+            CipherSuite::ChaChaPolySha256 => "Noise_XX_25519_ChaChaPoly_SHA256",
+        }
+        .parse()
+        .expect("net: couldn't build noise params?")
+    }
+}
+
+impl std::str::FromStr for CipherSuite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CipherSuite::ALL
+            .into_iter()
+            .find(|suite| suite.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown cipher suite {s}, expected one of: {}",
+                    CipherSuite::ALL.map(|suite| suite.name()).join(", ")
+                )
+            })
+    }
+}
+
+/// the operator's choice of suites, most preferred first, and how often to
+/// rekey connections that negotiated
+#[derive(Clone, Debug)]
+pub struct CipherConfig {
+    pub suites: Vec<CipherSuite>,
+    pub rekey_interval: Duration,
+}
+
+/// Sent before the Noise 'e' message by a node that accepts more than the
+/// legacy suite. Suites are named, so that a peer can skip those it doesn't know.
+///
+/// Should always be serialized and deserialized using MessagePack, and sent
+/// after [`OFFER_MAGIC`].
+#[derive(Debug, Deserialize, Serialize)]
+struct SuiteOffer {
+    protocol_version: u8,
+    suites: Vec<String>,
+}
+
+/// what a connection's handshake was agreed to use
+#[derive(Clone, Debug)]
+pub struct Negotiated {
+    pub suite: CipherSuite,
+    prologue: Vec<u8>,
+    /// none if the handshake was legacy: the peer may not know how to rekey
+    pub rekey_interval: Option<Duration>,
+}
+
+impl Negotiated {
+    /// a legacy handshake, as long as the operator still accepts it
+    pub fn legacy(config: &CipherConfig) -> anyhow::Result<Self> {
+        if !config.suites.contains(&LEGACY) {
+            return Err(anyhow::anyhow!(
+                "peer only speaks {}, which we don't accept",
+                LEGACY.name()
+            ));
+        }
+        Ok(Negotiated {
+            suite: LEGACY,
+            prologue: vec![],
+            rekey_interval: None,
+        })
+    }
+
+    fn new(config: &CipherConfig, suite: CipherSuite, offer: &[u8], answer: &[u8]) -> Self {
+        Negotiated {
+            suite,
+            prologue: [PROLOGUE, offer, answer].concat(),
+            rekey_interval: Some(config.rekey_interval),
+        }
+    }
+
+    pub fn builder(&self) -> snow::Builder<'_> {
+        snow::Builder::new(self.suite.params()).prologue(&self.prologue)
+    }
+}
+
+/// the offer to send before our handshake, unless we accept only the legacy
+/// suite, when there is nothing to negotiate
+pub fn offer(config: &CipherConfig) -> Option<Vec<u8>> {
+    if config.suites.iter().all(|suite| *suite == LEGACY) {
+        return None;
+    }
+    let offer = rmp_serde::to_vec(&SuiteOffer {
+        protocol_version: 1,
+        suites: config.suites.iter().map(|s| s.name().to_string()).collect(),
+    })
+    .expect("failed to serialize suite offer");
+    Some([OFFER_MAGIC, &offer].concat())
+}
+
+fn parse_offer(message: &[u8]) -> Option<SuiteOffer> {
+    rmp_serde::from_slice(message.strip_prefix(OFFER_MAGIC)?).ok()
+}
+
+/// whether the first message of a connection is a [`SuiteOffer`]
+pub fn is_offer(message: &[u8]) -> bool {
+    parse_offer(message).is_some()
+}
+
+/// as the responder, pick the first suite the peer offers that we accept.
+/// returns the answer to send back, which names no suite if there is none, in
+/// which case the handshake is off.
+pub fn answer(config: &CipherConfig, offer: &[u8]) -> (Vec<u8>, anyhow::Result<Negotiated>) {
+    let chosen = parse_offer(offer).and_then(|offer| {
+        offer
+            .suites
+            .iter()
+            .filter_map(|name| name.parse::<CipherSuite>().ok())
+            .find(|suite| config.suites.contains(suite))
+    });
+    let answer = rmp_serde::to_vec(&chosen.map(|suite| suite.name()))
+        .expect("failed to serialize suite answer");
+    let negotiated = match chosen {
+        Some(suite) => Ok(Negotiated::new(config, suite, offer, &answer)),
+        None => Err(anyhow::anyhow!("no cipher suite in common with peer")),
+    };
+    (answer, negotiated)
+}
+
+/// the peer didn't answer our offer, and likely predates negotiation
+#[derive(Debug)]
+pub struct Unanswered;
+
+impl std::fmt::Display for Unanswered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "peer didn't answer our cipher suite offer")
+    }
+}
+
+impl std::error::Error for Unanswered {}
+
+/// whether a failed handshake is worth trying again without an offer
+pub fn retry_legacy(config: &CipherConfig, error: &anyhow::Error) -> bool {
+    error.is::<Unanswered>() && config.suites.contains(&LEGACY)
+}
+
+/// as the initiator, take up the suite the responder chose from our offer
+pub fn accept(config: &CipherConfig, offer: &[u8], answer: &[u8]) -> anyhow::Result<Negotiated> {
+    let chosen: Option<String> = rmp_serde::from_slice(answer).map_err(|_| Unanswered)?;
+    let suite = chosen
+        .and_then(|name| name.parse::<CipherSuite>().ok())
+        .filter(|suite| config.suites.contains(suite))
+        .ok_or(anyhow::anyhow!("no cipher suite in common with peer"))?;
+    Ok(Negotiated::new(config, suite, offer, answer))
+}
+
+/// what we tell diagnostics about a connection's encryption
+#[derive(Debug)]
+pub struct Session {
+    pub suite: CipherSuite,
+    pub negotiated: bool,
+    /// times either direction has rekeyed
+    pub rekeys: AtomicU64,
+}
+
+impl Session {
+    pub fn new(negotiated: &Negotiated) -> Self {
+        Session {
+            suite: negotiated.suite,
+            negotiated: negotiated.rekey_interval.is_some(),
+            rekeys: AtomicU64::new(0),
+        }
+    }
+
+    pub fn rekeyed(&self) {
+        self.rekeys.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// tracks when one direction of a connection is due to rekey
+pub struct Rekeying {
+    interval: Option<Duration>,
+    since: Instant,
+    messages: u64,
+}
+
+impl Rekeying {
+    pub fn new(negotiated: &Negotiated) -> Self {
+        Rekeying {
+            interval: negotiated.rekey_interval,
+            since: Instant::now(),
+            messages: 0,
+        }
+    }
+
+    /// count a message about to be sent, saying whether to rekey before it
+    pub fn due(&mut self) -> bool {
+        let Some(interval) = self.interval else {
+            return false;
+        };
+        self.messages += 1;
+        if self.messages <= REKEY_MESSAGES && self.since.elapsed() < interval {
+            return false;
+        }
+        self.since = Instant::now();
+        self.messages = 1;
+        true
+    }
+}
@@ -1,6 +1,6 @@
 use crate::net::types::{IdentityExt, NetData, Peer};
 use crate::net::{tcp, utils, ws};
-use lib::types::core::{Identity, KernelMessage, NodeRouting};
+use lib::types::core::{Identity, KernelMessage, Resolution, SendErrorCause, SendErrorHop};
 use rand::prelude::SliceRandom;
 use tokio::sync::mpsc;
 
@@ -11,31 +11,61 @@ pub async fn send_to_peer(ext: &IdentityExt, data: &NetData, km: KernelMessage)
         peer.sender.send(km).expect("net: peer sender was dropped");
     } else {
         let Some(peer_id) = data.pki.get(&km.target.node) else {
-            return utils::error_offline(km, &ext.network_error_tx).await;
+            return utils::error_offline(
+                km,
+                SendErrorHop::LocalNet,
+                SendErrorCause::PeerUnknown,
+                Some(Resolution::default()),
+                &ext.network_error_tx,
+            )
+            .await;
         };
-        let (peer_tx, peer_rx) = mpsc::unbounded_channel();
+        if data.reputation.is_banned(&peer_id.name) {
+            // they would only be dropped again
+            return utils::error_offline(
+                km,
+                SendErrorHop::LocalNet,
+                SendErrorCause::PeerOffline,
+                None,
+                &ext.network_error_tx,
+            )
+            .await;
+        }
         // send message to be routed
-        peer_tx.send(km).unwrap();
-        data.peers.insert(
-            peer_id.name.clone(),
-            Peer {
-                identity: peer_id.clone(),
-                routing_for: false,
-                sender: peer_tx.clone(),
-            },
-        );
-        tokio::spawn(connect_to_peer(
-            ext.clone(),
-            data.clone(),
-            peer_id.clone(),
-            peer_rx,
-        ));
+        connect(ext, data, &peer_id).send(km).unwrap();
     }
 }
 
+/// add `peer_id` to our peers and start connecting to it, returning the
+/// sender for messages to route to it once connected
+pub fn connect(
+    ext: &IdentityExt,
+    data: &NetData,
+    peer_id: &Identity,
+) -> mpsc::UnboundedSender<KernelMessage> {
+    let (peer_tx, peer_rx) = mpsc::unbounded_channel();
+    data.peers.insert(
+        peer_id.name.clone(),
+        Peer {
+            identity: peer_id.clone(),
+            routing_for: false,
+            sender: peer_tx.clone(),
+            session: None,
+        },
+    );
+    tokio::spawn(connect_to_peer(
+        ext.clone(),
+        data.clone(),
+        peer_id.clone(),
+        peer_rx,
+    ));
+    peer_tx
+}
+
 /// based on peer's identity, either use one of their
 /// protocols to connect directly, or loop through their
-/// routers to open a passthroughconnection for us
+/// routers to open a passthroughconnection for us.
+/// a peer advertising both is tried directly first.
 ///
 /// if we fail to connect, remove the peer from the map
 /// and return an offline error for each message in the receiver
@@ -43,71 +73,74 @@ async fn connect_to_peer(
     ext: IdentityExt,
     data: NetData,
     peer_id: Identity,
-    peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
+    mut peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
 ) {
-    if peer_id.is_direct() {
+    let mut resolution = Resolution {
+        kns_record: true,
+        ..Default::default()
+    };
+    if peer_id.get_ip().is_some() {
+        resolution.hosts = utils::hosts(&ext, &data, &peer_id);
         utils::print_debug(
             &ext.print_tx,
             &format!("net: attempting to connect to {} directly", peer_id.name),
         )
         .await;
-        if let Some((_ip, port)) = peer_id.tcp_routing() {
-            match tcp::init_direct(&ext, &data, &peer_id, *port, false, peer_rx).await {
-                Ok(()) => {
-                    utils::print_debug(
-                        &ext.print_tx,
-                        &format!("net: connected to {} directly", peer_id.name),
-                    )
-                    .await;
-                    return;
-                }
-                Err(peer_rx) => {
-                    return handle_failed_connection(&ext, &data, &peer_id, peer_rx).await;
-                }
-            }
-        }
-        if let Some((_ip, port)) = peer_id.ws_routing() {
-            match ws::init_direct(&ext, &data, &peer_id, *port, false, peer_rx).await {
-                Ok(()) => {
-                    utils::print_debug(
-                        &ext.print_tx,
-                        &format!("net: connected to {} directly", peer_id.name),
-                    )
-                    .await;
-                    return;
-                }
-                Err(peer_rx) => {
-                    return handle_failed_connection(&ext, &data, &peer_id, peer_rx).await;
-                }
+        let connected = if let Some((_ip, port)) = peer_id.tcp_routing() {
+            tcp::init_direct(&ext, &data, &peer_id, *port, false, peer_rx).await
+        } else if let Some((_ip, port)) = peer_id.ws_routing() {
+            ws::init_direct(&ext, &data, &peer_id, *port, false, peer_rx).await
+        } else {
+            Err(peer_rx)
+        };
+        match connected {
+            Ok(()) => {
+                utils::print_debug(
+                    &ext.print_tx,
+                    &format!("net: connected to {} directly", peer_id.name),
+                )
+                .await;
+                return;
             }
+            Err(rx) => peer_rx = rx,
         }
+    }
+    if peer_id.routers().is_some_and(|routers| !routers.is_empty()) {
+        connect_via_router(&ext, &data, &peer_id, peer_rx, resolution).await;
     } else {
-        connect_via_router(&ext, &data, &peer_id, peer_rx).await;
+        handle_failed_connection(&ext, &data, &peer_id, peer_rx, resolution).await;
     }
 }
 
-/// loop through the peer's routers, attempting to connect
+/// loop through the peer's routers, attempting to connect, and noting in
+/// `resolution` what became of each
 async fn connect_via_router(
     ext: &IdentityExt,
     data: &NetData,
     peer_id: &Identity,
     mut peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
+    mut resolution: Resolution,
 ) {
     let routers_shuffled = {
-        let mut routers = match peer_id.routing {
-            NodeRouting::Routers(ref routers) => routers.clone(),
-            _ => vec![],
-        };
+        let mut routers = peer_id.routers().cloned().unwrap_or_default();
         routers.shuffle(&mut rand::thread_rng());
         routers
     };
     for router_name in &routers_shuffled {
         if router_name.as_ref() == ext.our.name {
             // we can't route through ourselves
+            resolution
+                .routers
+                .push((router_name.clone(), "is us".to_string()));
             continue;
         }
         let router_id = match data.pki.get(router_name.as_str()) {
-            None => continue,
+            None => {
+                resolution
+                    .routers
+                    .push((router_name.clone(), "no KNS record".to_string()));
+                continue;
+            }
             Some(id) => id.clone(),
         };
         if let Some((_ip, port)) = router_id.tcp_routing() {
@@ -121,6 +154,9 @@ async fn connect_via_router(
                     return;
                 }
                 Err(e) => {
+                    resolution
+                        .routers
+                        .push((router_name.clone(), "unreachable over tcp".to_string()));
                     peer_rx = e;
                     continue;
                 }
@@ -137,13 +173,16 @@ async fn connect_via_router(
                     return;
                 }
                 Err(e) => {
+                    resolution
+                        .routers
+                        .push((router_name.clone(), "unreachable over ws".to_string()));
                     peer_rx = e;
                     continue;
                 }
             }
         }
     }
-    handle_failed_connection(ext, data, &peer_id, peer_rx).await;
+    handle_failed_connection(ext, data, &peer_id, peer_rx, resolution).await;
 }
 
 pub async fn handle_failed_connection(
@@ -151,15 +190,29 @@ pub async fn handle_failed_connection(
     data: &NetData,
     peer_id: &Identity,
     mut peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
+    resolution: Resolution,
 ) {
     utils::print_debug(
         &ext.print_tx,
-        &format!("net: failed to connect to {}", peer_id.name),
+        &format!("net: failed to connect to {}: {resolution}", peer_id.name),
     )
     .await;
     drop(data.peers.remove(&peer_id.name));
     peer_rx.close();
+    // an indirect peer is offline if none of its routers would take us to it
+    let hop = if peer_id.is_direct() {
+        SendErrorHop::LocalNet
+    } else {
+        SendErrorHop::RemoteRouter
+    };
     while let Some(km) = peer_rx.recv().await {
-        utils::error_offline(km, &ext.network_error_tx).await;
+        utils::error_offline(
+            km,
+            hop,
+            SendErrorCause::PeerOffline,
+            Some(resolution.clone()),
+            &ext.network_error_tx,
+        )
+        .await;
     }
 }
@@ -1,6 +1,6 @@
 use crate::net::types::{IdentityExt, NetData, Peer};
 use crate::net::{connect, tcp, utils, ws};
-use lib::types::core::{Identity, NodeRouting};
+use lib::types::core::{Identity, NodeRouting, Resolution};
 use tokio::{sync::mpsc, time};
 
 pub async fn maintain_routers(ext: IdentityExt, data: NetData) -> anyhow::Result<()> {
@@ -36,6 +36,7 @@ pub async fn connect_to_router(router_id: &Identity, ext: &IdentityExt, data: &N
             identity: router_id.clone(),
             routing_for: false,
             sender: peer_tx.clone(),
+            session: None,
         },
     );
     if let Some((_ip, port)) = router_id.tcp_routing() {
@@ -49,7 +50,18 @@ pub async fn connect_to_router(router_id: &Identity, ext: &IdentityExt, data: &N
                 return;
             }
             Err(peer_rx) => {
-                return connect::handle_failed_connection(ext, data, router_id, peer_rx).await;
+                return connect::handle_failed_connection(
+                    ext,
+                    data,
+                    router_id,
+                    peer_rx,
+                    Resolution {
+                        kns_record: true,
+                        hosts: utils::hosts(ext, data, router_id),
+                        routers: vec![],
+                    },
+                )
+                .await;
             }
         }
     }
@@ -64,7 +76,18 @@ pub async fn connect_to_router(router_id: &Identity, ext: &IdentityExt, data: &N
                 return;
             }
             Err(peer_rx) => {
-                return connect::handle_failed_connection(ext, data, router_id, peer_rx).await;
+                return connect::handle_failed_connection(
+                    ext,
+                    data,
+                    router_id,
+                    peer_rx,
+                    Resolution {
+                        kns_record: true,
+                        hosts: utils::hosts(ext, data, router_id),
+                        routers: vec![],
+                    },
+                )
+                .await;
             }
         }
     }
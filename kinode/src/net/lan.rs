@@ -0,0 +1,276 @@
+//! finding sibling nodes on the local network. with `--lan-discovery`, every
+//! node listens on a local multicast group, and a direct node also sends
+//! it a signed [`Beacon`] naming itself every [`BEACON_INTERVAL`]. a sibling
+//! we hear from is tried at the address its beacon came from before the hosts
+//! in its KNS record, and we connect to it as soon as we find it.
+//!
+//! a beacon carries the node's networking key and ports as well, so that
+//! siblings can find each other with no chain to read, as when the network
+//! is offline. a sibling whose name is in the PKI must sign with the key on
+//! record there, and a beacon then only says where it is. a name not in the
+//! PKI is taken on its beacon's word, which is all there is offline, until
+//! its KNS record arrives and replaces it. either way the handshake proves
+//! the sibling holds the key, and a replayed beacon can at worst have us try
+//! a wrong address first.
+use crate::net::types::{IdentityExt, NetData};
+use crate::net::{connect, utils};
+use dashmap::DashMap;
+use lib::types::core::{Identity, NodeId, NodeRouting};
+use ring::signature;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+/// administratively scoped, and beacons are sent with the default multicast
+/// TTL of 1, so they don't leave the local network
+const GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 78);
+const PORT: u16 = 9477;
+const BEACON_INTERVAL: Duration = Duration::from_secs(10);
+/// how long to wait before joining the group again after a socket error
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// how long after its last beacon a sibling is still tried at its LAN address
+pub const SIBLING_TTL: Duration = Duration::from_secs(35);
+/// how far a beacon's timestamp may be from our clock, in milliseconds
+const MAX_SKEW: u64 = 60_000;
+const MAX_BEACON_SIZE: usize = 1024;
+const DOMAIN: &[u8] = b"kinode-lan-beacon";
+
+/// Multicast by a direct node with `--lan-discovery` to say it is on the
+/// local network. It is found at the address the beacon came from, on the
+/// ports it gives.
+///
+/// Should always be serialized and deserialized using MessagePack.
+#[derive(Debug, Deserialize, Serialize)]
+struct Beacon {
+    protocol_version: u8,
+    name: NodeId,
+    /// milliseconds since the unix epoch, so that a beacon can't be replayed for long
+    timestamp: u64,
+    networking_key: String,
+    ports: BTreeMap<String, u16>,
+    /// by the node's networking key, of [`signed`]
+    signature: Vec<u8>,
+}
+
+pub struct Sibling {
+    pub host: String,
+    pub seen: Instant,
+}
+
+/// the nodes we have heard from on the local network
+pub type Siblings = Arc<DashMap<NodeId, Sibling>>;
+
+fn signed(
+    name: &str,
+    timestamp: u64,
+    networking_key: &str,
+    ports: &BTreeMap<String, u16>,
+) -> Vec<u8> {
+    let mut signed = [
+        DOMAIN,
+        name.as_bytes(),
+        &timestamp.to_be_bytes(),
+        networking_key.as_bytes(),
+    ]
+    .concat();
+    for (protocol, port) in ports {
+        signed.extend_from_slice(&[protocol.len() as u8]);
+        signed.extend_from_slice(protocol.as_bytes());
+        signed.extend_from_slice(&port.to_be_bytes());
+    }
+    signed
+}
+
+/// never returns: losing LAN discovery shouldn't end networking, so a socket
+/// error is printed and the group joined again after [`RETRY_INTERVAL`]
+pub async fn discover(ext: IdentityExt, data: NetData) -> anyhow::Result<()> {
+    // only direct nodes listen for connections, so only they announce themselves
+    let announce = ext.our.is_direct();
+    utils::print_loud(
+        &ext.print_tx,
+        &format!(
+            "looking for sibling nodes on the local network{}",
+            if announce {
+                " and announcing ourselves"
+            } else {
+                ""
+            }
+        ),
+    )
+    .await;
+    loop {
+        let error = match bind() {
+            Ok(socket) => listen(&ext, &data, &socket, announce).await,
+            Err(e) => e,
+        };
+        utils::print_loud(
+            &ext.print_tx,
+            &format!(
+                "net: LAN discovery failed, retrying in {}s: {error}",
+                RETRY_INTERVAL.as_secs()
+            ),
+        )
+        .await;
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+/// send and hear beacons on `socket` until it fails
+async fn listen(
+    ext: &IdentityExt,
+    data: &NetData,
+    socket: &UdpSocket,
+    announce: bool,
+) -> std::io::Error {
+    let mut interval = tokio::time::interval(BEACON_INTERVAL);
+    let mut buf = [0u8; MAX_BEACON_SIZE];
+    loop {
+        tokio::select! {
+            _ = interval.tick(), if announce => {
+                if let Err(e) = socket.send_to(&beacon(ext), (GROUP, PORT)).await {
+                    utils::print_debug(&ext.print_tx, &format!("net: couldn't send LAN beacon: {e}"))
+                        .await;
+                }
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = match received {
+                    Ok(received) => received,
+                    Err(e) => return e,
+                };
+                heard(ext, data, &buf[..len], from).await;
+            }
+        }
+    }
+}
+
+fn bind() -> std::io::Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    // so that several nodes on one machine can all listen
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, PORT)).into())?;
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    // and hear each other
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn beacon(ext: &IdentityExt) -> Vec<u8> {
+    let timestamp = crate::clock::now();
+    let ports = match &ext.our.routing {
+        NodeRouting::Direct { ports, .. } | NodeRouting::Both { ports, .. } => ports.clone(),
+        NodeRouting::Routers(_) => BTreeMap::new(),
+    };
+    let signature = ext
+        .keypair
+        .sign(&signed(
+            &ext.our.name,
+            timestamp,
+            &ext.our.networking_key,
+            &ports,
+        ))
+        .as_ref()
+        .to_vec();
+    rmp_serde::to_vec(&Beacon {
+        protocol_version: 1,
+        name: ext.our.name.clone(),
+        timestamp,
+        networking_key: ext.our.networking_key.clone(),
+        ports,
+        signature,
+    })
+    .expect("failed to serialize LAN beacon")
+}
+
+/// note a sibling's address from its beacon, connecting to it if it is new
+async fn heard(ext: &IdentityExt, data: &NetData, bytes: &[u8], from: SocketAddr) {
+    let Ok(beacon) = rmp_serde::from_slice::<Beacon>(bytes) else {
+        return;
+    };
+    if beacon.name == ext.our.name
+        || beacon.ports.is_empty()
+        || crate::clock::now().abs_diff(beacon.timestamp) > MAX_SKEW
+        || signature::UnparsedPublicKey::new(
+            &signature::ED25519,
+            utils::net_key_string_to_hex(&beacon.networking_key),
+        )
+        .verify(
+            &signed(
+                &beacon.name,
+                beacon.timestamp,
+                &beacon.networking_key,
+                &beacon.ports,
+            ),
+            &beacon.signature,
+        )
+        .is_err()
+    {
+        return;
+    }
+    let host = from.ip().to_string();
+    let peer_id = match data.pki.get(&beacon.name).map(|id| id.clone()) {
+        // on record: it must be the key on record that signed
+        Some(peer_id) => {
+            if utils::net_key_string_to_hex(&peer_id.networking_key)
+                != utils::net_key_string_to_hex(&beacon.networking_key)
+                || peer_id.get_ip().is_none()
+            {
+                return;
+            }
+            peer_id
+        }
+        // not on record, as when we can't reach the chain: take its word
+        None => {
+            let peer_id = Identity {
+                name: beacon.name.clone(),
+                networking_key: beacon.networking_key.clone(),
+                routing: NodeRouting::Direct {
+                    ip: host.clone(),
+                    ports: beacon.ports.clone(),
+                },
+            };
+            data.pki
+                .entry(beacon.name.clone())
+                .or_insert(peer_id)
+                .clone()
+        }
+    };
+    let found = data
+        .siblings
+        .insert(
+            beacon.name.clone(),
+            Sibling {
+                host: host.clone(),
+                seen: Instant::now(),
+            },
+        )
+        .map_or(true, |old| {
+            old.host != host || old.seen.elapsed() > SIBLING_TTL
+        });
+    if !found {
+        return;
+    }
+    utils::print_debug(
+        &ext.print_tx,
+        &format!("net: found sibling {} at {host}", beacon.name),
+    )
+    .await;
+    if !data.peers.contains_key(&beacon.name) && !data.reputation.is_banned(&beacon.name) {
+        connect::connect(ext, data, &peer_id);
+    }
+}
+
+/// the LAN address to try `name` at first, if we have heard from it lately
+pub fn sibling_host(data: &NetData, name: &str) -> Option<String> {
+    data.siblings
+        .get(name)
+        .filter(|sibling| sibling.seen.elapsed() <= SIBLING_TTL)
+        .map(|sibling| sibling.host.clone())
+}
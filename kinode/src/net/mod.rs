@@ -1,19 +1,25 @@
 use lib::types::core::{
-    Identity, KernelMessage, MessageReceiver, MessageSender, NetAction, NetResponse,
-    NetworkErrorSender, NodeRouting, PrintSender,
-};
-use types::{
-    IdentityExt, NetData, OnchainPKI, Peers, PendingPassthroughs, TCP_PROTOCOL, WS_PROTOCOL,
+    CapMessageSender, Identity, KernelMessage, MessageReceiver, MessageSender, NetAction,
+    NetResponse, NetworkErrorSender, NodeRouting, PrintSender, ProcessId,
 };
+use types::{IdentityExt, NetData, Peers, PendingPassthroughs, TCP_PROTOCOL, WS_PROTOCOL};
 use {dashmap::DashMap, ring::signature::Ed25519KeyPair, std::sync::Arc, tokio::task::JoinSet};
 
+mod cipher;
 mod connect;
 mod indirect;
+mod lan;
+mod reputation;
+mod router;
 mod tcp;
 mod types;
 mod utils;
 mod ws;
 
+pub use cipher::{CipherConfig, CipherSuite};
+pub use types::OnchainPKI;
+pub use utils::validate_signature;
+
 /// Entry point for all node to node networking. Manages the "working version" of the PKI,
 /// which may not be the complete PKI. Stateless: does not persist PKI information, only
 /// ingests it from [`NetAction::KnsUpdate`] and [`NetAction::KnsBatchUpdate`] requests.
@@ -23,15 +29,25 @@ mod ws;
 /// or direct networking in the PKI. If direct, it can be over a number of protocols.
 /// This implementation supports two: `"ws"` and `"tcp"`. These are keys associated
 /// with ports in the `ports` field of a node [`Identity`].
+///
+/// The PKI map is shared with the kernel, which uses it to verify signatures and
+/// seal messages on behalf of processes.
 pub async fn networking(
     our: Identity,
     our_ip: String,
     keypair: Arc<Ed25519KeyPair>,
+    pki: OnchainPKI,
     kernel_message_tx: MessageSender,
     network_error_tx: NetworkErrorSender,
     print_tx: PrintSender,
+    caps_oracle: CapMessageSender,
     kernel_message_rx: MessageReceiver,
+    blob_store: Arc<crate::blobs::BlobStore>,
+    home_directory_path: String,
     _reveal_ip: bool, // only used if indirect
+    prefer_ipv6: bool,
+    cipher: CipherConfig,
+    lan_discovery: bool,
 ) -> anyhow::Result<()> {
     let ext = IdentityExt {
         our: Arc::new(our),
@@ -40,11 +56,14 @@ pub async fn networking(
         kernel_message_tx,
         network_error_tx,
         print_tx,
+        caps_oracle,
+        blob_store,
         _reveal_ip,
+        prefer_ipv6,
+        cipher: Arc::new(cipher),
     };
-    // start by initializing the structs where we'll store PKI in memory
-    // and store a mapping of peers we have an active route for
-    let pki: OnchainPKI = Arc::new(DashMap::new());
+    // start by initializing the structs where we'll store
+    // a mapping of peers we have an active route for
     let peers: Peers = Arc::new(DashMap::new());
     // only used by routers
     let pending_passthroughs: PendingPassthroughs = Arc::new(DashMap::new());
@@ -53,6 +72,10 @@ pub async fn networking(
         pki,
         peers,
         pending_passthroughs,
+        endpoints: Arc::new(DashMap::new()),
+        router: Arc::new(router::Router::load(&home_directory_path).await),
+        reputation: Arc::new(reputation::Reputation::load(&home_directory_path).await),
+        siblings: Arc::new(DashMap::new()),
     };
 
     let mut tasks = JoinSet::<anyhow::Result<()>>::new();
@@ -61,10 +84,14 @@ pub async fn networking(
     // and depending on the ports in our identity, the tasks
     // for ws and/or tcp, or indirect routing.
     tasks.spawn(local_recv(ext.clone(), kernel_message_rx, net_data.clone()));
+    tasks.spawn(poll_peer_clocks(ext.clone(), net_data.clone()));
+    if lan_discovery {
+        tasks.spawn(lan::discover(ext.clone(), net_data.clone()));
+    }
 
     match &ext.our.routing {
         NodeRouting::Direct { ip, ports } => {
-            if *ext.our_ip != *ip {
+            if !utils::same_host(&ext.our_ip, ip) {
                 return Err(anyhow::anyhow!(
                     "net: fatal error: IP address mismatch: {} != {}, update your KNS identity",
                     ext.our_ip,
@@ -109,11 +136,17 @@ async fn local_recv(
     mut kernel_message_rx: MessageReceiver,
     data: NetData,
 ) -> anyhow::Result<()> {
-    while let Some(km) = kernel_message_rx.recv().await {
+    let net_process_id = ProcessId::new(Some("net"), "distro", "sys");
+    while let Some(mut km) = kernel_message_rx.recv().await {
+        crate::metrics::handled(&net_process_id, &km);
         if km.target.node == ext.our.name {
             // handle messages sent to us
             handle_message(&ext, km, &data).await;
         } else {
+            // peers can't read our blob store: send them the bytes
+            if let Err(e) = ext.blob_store.resolve(&mut km).await {
+                utils::print_debug(&ext.print_tx, &format!("net: couldn't read blob: {e}")).await;
+            }
             connect::send_to_peer(&ext, &data, km).await;
         }
     }
@@ -159,11 +192,11 @@ async fn handle_local_request(
             // we shouldn't get these locally, ignore
         }
         Ok(NetAction::KnsUpdate(log)) => {
-            utils::ingest_log(log, &data.pki);
+            utils::ingest_log(log, data);
         }
         Ok(NetAction::KnsBatchUpdate(logs)) => {
             for log in logs {
-                utils::ingest_log(log, &data.pki);
+                utils::ingest_log(log, data);
             }
         }
         Ok(gets) => {
@@ -181,6 +214,18 @@ async fn handle_local_request(
                     NetResponse::Peer(data.pki.get(&peer).map(|p| p.clone())),
                     None,
                 ),
+                NetAction::GetTime => (NetResponse::Time(crate::clock::now()), None),
+                NetAction::GetReputation => {
+                    (NetResponse::Reputation(data.reputation.table()), None)
+                }
+                NetAction::Router(action) => (
+                    NetResponse::Router(
+                        data.router
+                            .act(action, &km.source, &data.peers, &ext.caps_oracle)
+                            .await,
+                    ),
+                    None,
+                ),
                 NetAction::GetDiagnostics => {
                     let mut printout = String::new();
                     printout.push_str(&format!(
@@ -188,20 +233,89 @@ async fn handle_local_request(
                         crate::KIMAP_ADDRESS
                     ));
                     printout.push_str(&format!("our Identity: {:#?}\r\n", ext.our));
+                    printout.push_str(&format!(
+                        "our IP: {}, preferring IPv{} for peers that offer both\r\n",
+                        ext.our_ip,
+                        if ext.prefer_ipv6 { 6 } else { 4 },
+                    ));
+                    printout.push_str(&format!("our clock: {}\r\n", crate::clock::estimate()));
+                    printout.push_str(&format!(
+                        "our cipher suites: {}, rekeying every {}s\r\n",
+                        ext.cipher
+                            .suites
+                            .iter()
+                            .map(|suite| suite.name())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        ext.cipher.rekey_interval.as_secs(),
+                    ));
                     printout.push_str(&format!(
                         "we have connections with {} peers:\r\n",
                         data.peers.len()
                     ));
                     for peer in data.peers.iter() {
                         printout.push_str(&format!(
-                            "    {}, routing_for={}\r\n",
-                            peer.identity.name, peer.routing_for,
+                            "    {}, routing_for={}, hosts=[{}], cipher={}\r\n",
+                            peer.identity.name,
+                            peer.routing_for,
+                            utils::hosts(ext, data, &peer.identity).join(", "),
+                            match &peer.session {
+                                Some(session) if session.negotiated => format!(
+                                    "{}, rekeyed {} times",
+                                    session.suite.name(),
+                                    session.rekeys.load(std::sync::atomic::Ordering::Relaxed),
+                                ),
+                                Some(session) =>
+                                    format!("{} (legacy handshake)", session.suite.name()),
+                                None => "connecting".to_string(),
+                            },
+                        ));
+                    }
+                    if !data.siblings.is_empty() {
+                        printout.push_str(&format!(
+                            "we have heard from {} siblings on the local network:\r\n",
+                            data.siblings.len()
                         ));
+                        for sibling in data.siblings.iter() {
+                            printout.push_str(&format!(
+                                "    {} at {}, last seen {}s ago\r\n",
+                                sibling.key(),
+                                sibling.host,
+                                sibling.seen.elapsed().as_secs(),
+                            ));
+                        }
                     }
                     printout.push_str(&format!(
                         "we have {} entries in the PKI\r\n",
                         data.pki.len()
                     ));
+                    let router = data.router.stats(&data.peers);
+                    if router.clients_served > 0 {
+                        printout.push_str(&format!(
+                            "as a router, we have served {} clients and relayed {} bytes\r\n",
+                            router.clients_served, router.bytes_relayed
+                        ));
+                    }
+                    let reputation = data.reputation.table();
+                    if !reputation.is_empty() {
+                        printout
+                            .push_str(&format!("{} peers have misbehaved:\r\n", reputation.len()));
+                        for peer in reputation {
+                            printout.push_str(&format!(
+                                "    {}, score={:.1}, malformed={}, spoofed={}, floods={}{}\r\n",
+                                peer.name,
+                                peer.score,
+                                peer.malformed,
+                                peer.spoofed,
+                                peer.floods,
+                                match peer.banned_for_secs {
+                                    Some(secs) => format!(", banned for {secs}s"),
+                                    None if peer.throttled => ", throttled".to_string(),
+                                    None => String::new(),
+                                },
+                            ));
+                        }
+                    }
                     if !data.pending_passthroughs.is_empty() {
                         printout.push_str(&format!(
                             "we have {} pending passthroughs:\r\n",
@@ -331,6 +445,25 @@ async fn handle_remote_request(
                 },
             ));
         }
+        Ok(NetAction::GetTime) => {
+            KernelMessage::builder()
+                .id(km.id)
+                .source((ext.our.name.as_str(), "net", "distro", "sys"))
+                .target(km.rsvp.as_ref().unwrap_or(&km.source).clone())
+                .message(lib::core::Message::Response((
+                    lib::core::Response {
+                        inherit: false,
+                        body: rmp_serde::to_vec(&NetResponse::Time(crate::clock::now()))?,
+                        metadata: None,
+                        capabilities: vec![],
+                    },
+                    None,
+                )))
+                .build()
+                .unwrap()
+                .send(&ext.kernel_message_tx)
+                .await;
+        }
         _ => {
             // if we can't parse this to a NetAction, treat it as a hello and print it,
             // and respond with a simple "delivered" response
@@ -357,8 +490,42 @@ async fn handle_response(km: &KernelMessage, response_body: &[u8], data: &NetDat
             data.pending_passthroughs
                 .remove(&(to, km.source.node.to_owned()));
         }
+        Ok(lib::core::NetResponse::Time(their_now)) => {
+            crate::clock::peer_answered(km.id, &km.source.node, their_now);
+        }
         _ => {
             // ignore any other response, for now
         }
     }
 }
+
+/// with `--clock-peers`, ask each peer we are connected to for its time every
+/// [`crate::clock::PEER_POLL_INTERVAL`], for the clock to compare with its own
+async fn poll_peer_clocks(ext: IdentityExt, data: NetData) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(crate::clock::PEER_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        if !crate::clock::peer_consensus() {
+            continue;
+        }
+        for peer in data.peers.iter() {
+            let id = rand::random();
+            crate::clock::asked_peer(id);
+            let _ = peer.sender.send(
+                KernelMessage::builder()
+                    .id(id)
+                    .source((ext.our.name.as_str(), "net", "distro", "sys"))
+                    .target((peer.identity.name.as_str(), "net", "distro", "sys"))
+                    .message(lib::core::Message::Request(lib::core::Request {
+                        inherit: false,
+                        expects_response: Some(5),
+                        body: rmp_serde::to_vec(&NetAction::GetTime)?,
+                        metadata: None,
+                        capabilities: vec![],
+                    }))
+                    .build()
+                    .unwrap(),
+            );
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! how well each peer has behaved. a peer that sends malformed frames, spoofs
+//! the source of its messages, or floods us with them earns penalty points,
+//! which decay with a half-life of [`HALF_LIFE`]. past [`THROTTLE_SCORE`] we
+//! slow down reading from it; past [`BAN_SCORE`] we drop it and refuse it for
+//! a while, longer each time it is banned again. bans in force are kept in
+//! `.reputation` in the home directory, so a restart doesn't lift them.
+use dashmap::DashMap;
+use lib::types::core::{NodeId, PeerReputation};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const HALF_LIFE: Duration = Duration::from_secs(10 * 60);
+pub const THROTTLE_SCORE: f64 = 20.0;
+pub const BAN_SCORE: f64 = 100.0;
+/// how long we wait before reading each message from a throttled peer
+const THROTTLE_DELAY: Duration = Duration::from_millis(100);
+const BAN_BASE: Duration = Duration::from_secs(5 * 60);
+const BAN_MAX: Duration = Duration::from_secs(24 * 60 * 60);
+/// messages a peer may send in a second before it counts as a flood
+const FLOOD_LIMIT: u32 = 1_000;
+/// most peers tracked at once. past this, the best-behaved are forgotten.
+const MAX_PEERS: usize = 1 << 14;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Offense {
+    /// a frame that failed to decrypt or decode, or was the wrong size
+    Malformed,
+    /// a message from a source other than the peer that sent it
+    Spoofed,
+    /// more than [`FLOOD_LIMIT`] messages in a second
+    Flood,
+}
+
+impl Offense {
+    fn penalty(&self) -> f64 {
+        match self {
+            Offense::Malformed => 10.0,
+            Offense::Spoofed => 50.0,
+            Offense::Flood => 20.0,
+        }
+    }
+}
+
+/// what to do with a peer's next message
+pub enum Standing {
+    Good,
+    Throttled(Duration),
+    Banned,
+}
+
+pub struct Reputation {
+    /// where bans are persisted, if anywhere
+    path: Option<String>,
+    peers: DashMap<NodeId, Record>,
+}
+
+/// a ban as persisted
+#[derive(Serialize, Deserialize)]
+struct Ban {
+    bans: u32,
+    /// unix seconds
+    until: u64,
+}
+
+struct Record {
+    score: f64,
+    decayed_at: Instant,
+    messages: u64,
+    malformed: u64,
+    spoofed: u64,
+    floods: u64,
+    second_start: Instant,
+    this_second: u32,
+    bans: u32,
+    banned_until: Option<Instant>,
+}
+
+impl Record {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            score: 0.0,
+            decayed_at: now,
+            messages: 0,
+            malformed: 0,
+            spoofed: 0,
+            floods: 0,
+            second_start: now,
+            this_second: 0,
+            bans: 0,
+            banned_until: None,
+        }
+    }
+
+    fn decay(&mut self) -> f64 {
+        let elapsed = self.decayed_at.elapsed().as_secs_f64();
+        self.score *= 0.5f64.powf(elapsed / HALF_LIFE.as_secs_f64());
+        self.decayed_at = Instant::now();
+        self.score
+    }
+
+    fn banned(&self) -> bool {
+        self.banned_until
+            .is_some_and(|until| until > Instant::now())
+    }
+
+    /// returns whether this bans the peer
+    fn penalize(&mut self, offense: Offense) -> bool {
+        match offense {
+            Offense::Malformed => self.malformed += 1,
+            Offense::Spoofed => self.spoofed += 1,
+            Offense::Flood => self.floods += 1,
+        }
+        let ban = self.decay() + offense.penalty() >= BAN_SCORE && !self.banned();
+        if ban {
+            let ban = BAN_BASE
+                .saturating_mul(1u32 << self.bans.min(16))
+                .min(BAN_MAX);
+            self.banned_until = Some(Instant::now() + ban);
+            self.bans += 1;
+        }
+        self.score += offense.penalty();
+        ban
+    }
+
+    fn standing(&mut self) -> Standing {
+        if self.banned() {
+            Standing::Banned
+        } else if self.decay() >= THROTTLE_SCORE {
+            Standing::Throttled(THROTTLE_DELAY)
+        } else {
+            Standing::Good
+        }
+    }
+}
+
+impl Reputation {
+    /// with the bans still in force from `.reputation` in the home directory
+    pub async fn load(home_directory_path: &str) -> Self {
+        let path = format!("{home_directory_path}/.reputation");
+        let bans: HashMap<NodeId, Ban> = tokio::fs::read(&path)
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        let now = unix_now();
+        let peers = DashMap::new();
+        for (peer, ban) in bans {
+            if ban.until > now {
+                let mut record = Record::new();
+                record.bans = ban.bans;
+                record.banned_until = Some(Instant::now() + Duration::from_secs(ban.until - now));
+                peers.insert(peer, record);
+            }
+        }
+        Self {
+            path: Some(path),
+            peers,
+        }
+    }
+
+    pub fn offense(&self, peer: &str, offense: Offense) {
+        self.make_room(peer);
+        let banned = self
+            .peers
+            .entry(peer.to_string())
+            .or_insert_with(Record::new)
+            .penalize(offense);
+        if banned {
+            self.persist();
+        }
+    }
+
+    /// count a message received from `peer`, and say how to treat it
+    pub fn received(&self, peer: &str) -> Standing {
+        self.make_room(peer);
+        let mut record = self
+            .peers
+            .entry(peer.to_string())
+            .or_insert_with(Record::new);
+        record.messages += 1;
+        if record.second_start.elapsed() >= Duration::from_secs(1) {
+            record.second_start = Instant::now();
+            record.this_second = 0;
+        }
+        record.this_second += 1;
+        let banned = record.this_second == FLOOD_LIMIT + 1 && record.penalize(Offense::Flood);
+        let standing = record.standing();
+        drop(record);
+        if banned {
+            self.persist();
+        }
+        standing
+    }
+
+    /// if `peer` is new and we track as many peers as we may, forget those
+    /// whose penalties have all but decayed, or failing that, the one with
+    /// the least, banned peers last
+    fn make_room(&self, peer: &str) {
+        if self.peers.len() < MAX_PEERS || self.peers.contains_key(peer) {
+            return;
+        }
+        self.peers
+            .retain(|_, record| record.banned() || record.decay() >= 1.0);
+        if self.peers.len() < MAX_PEERS {
+            return;
+        }
+        let least = self
+            .peers
+            .iter()
+            .min_by(|a, b| {
+                (a.banned(), a.score)
+                    .partial_cmp(&(b.banned(), b.score))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|record| record.key().clone());
+        if let Some(least) = least {
+            self.peers.remove(&least);
+        }
+    }
+
+    /// write the bans in force to `.reputation`, in the background
+    fn persist(&self) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let now = Instant::now();
+        let unix = unix_now();
+        let bans: HashMap<NodeId, Ban> = self
+            .peers
+            .iter()
+            .filter_map(|record| {
+                let left = record.banned_until?.checked_duration_since(now)?;
+                Some((
+                    record.key().clone(),
+                    Ban {
+                        bans: record.bans,
+                        until: unix + left.as_secs(),
+                    },
+                ))
+            })
+            .collect();
+        let Ok(bytes) = serde_json::to_vec_pretty(&bans) else {
+            return;
+        };
+        tokio::spawn(async move {
+            let tmp = format!("{path}.tmp");
+            if tokio::fs::write(&tmp, bytes).await.is_ok() {
+                let _ = tokio::fs::rename(&tmp, &path).await;
+            }
+        });
+    }
+
+    /// whether to refuse connections with `peer`
+    pub fn is_banned(&self, peer: &str) -> bool {
+        self.peers.get(peer).is_some_and(|record| record.banned())
+    }
+
+    /// peers that have given offense, worst first
+    pub fn table(&self) -> Vec<PeerReputation> {
+        let mut table: Vec<PeerReputation> = self
+            .peers
+            .iter_mut()
+            .filter(|record| record.malformed + record.spoofed + record.floods > 0)
+            .map(|mut record| {
+                let score = record.decay();
+                let banned_for = record
+                    .banned_until
+                    .and_then(|until| until.checked_duration_since(Instant::now()));
+                PeerReputation {
+                    name: record.key().clone(),
+                    score,
+                    messages: record.messages,
+                    malformed: record.malformed,
+                    spoofed: record.spoofed,
+                    floods: record.floods,
+                    throttled: score >= THROTTLE_SCORE,
+                    banned_for_secs: banned_for.map(|ban| ban.as_secs()),
+                }
+            })
+            .collect();
+        table.sort_by(|a, b| b.score.total_cmp(&a.score));
+        table
+    }
+}
+
+/// whether a receive error means the peer sent us garbage, rather than the
+/// connection closing
+pub fn is_malformed(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<snow::Error>().is_some()
+        || e.downcast_ref::<rmp_serde::decode::Error>().is_some()
+        || e.to_string() == "message too large"
+        || e.to_string() == "protocol message too small!"
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpersisted() -> Reputation {
+        Reputation {
+            path: None,
+            peers: DashMap::new(),
+        }
+    }
+
+    #[test]
+    fn well_behaved_peers_are_forgotten_first() {
+        let reputation = unpersisted();
+        reputation.offense("spoofer.os", Offense::Spoofed);
+        reputation.offense("spoofer.os", Offense::Spoofed);
+        assert!(reputation.is_banned("spoofer.os"));
+        reputation.offense("sloppy.os", Offense::Malformed);
+        for i in 0..MAX_PEERS + 10 {
+            reputation.received(&format!("peer-{i}.os"));
+        }
+        assert!(reputation.peers.len() <= MAX_PEERS);
+        assert!(reputation.is_banned("spoofer.os"));
+        assert!(reputation.peers.contains_key("sloppy.os"));
+    }
+
+    #[tokio::test]
+    async fn bans_outlast_a_restart() {
+        let home =
+            std::env::temp_dir().join(format!("kinode-reputation-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&home).unwrap();
+        let home = home.to_str().unwrap().to_string();
+        let reputation = Reputation::load(&home).await;
+        reputation.offense("spoofer.os", Offense::Spoofed);
+        reputation.offense("spoofer.os", Offense::Spoofed);
+        reputation.offense("sloppy.os", Offense::Malformed);
+        // the write is in the background
+        for _ in 0..100 {
+            if std::path::Path::new(&format!("{home}/.reputation")).exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let reloaded = Reputation::load(&home).await;
+        assert!(reloaded.is_banned("spoofer.os"));
+        assert!(!reloaded.is_banned("sloppy.os"));
+        assert_eq!(reloaded.peers.get("spoofer.os").unwrap().bans, 1);
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+}
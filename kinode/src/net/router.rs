@@ -0,0 +1,379 @@
+//! what we do for the nodes that use us as a router. the operator can hold
+//! them to quotas, deny or disconnect them, and see what we relay for each.
+//!
+//! a client is a node we route for, or one that asks us for a passthrough.
+//! a passthrough is charged to the node that asked for it. quotas and denied
+//! nodes are kept in `.router` in the home directory.
+//!
+//! changing any of this takes the `"router-admin"` capability issued by net.
+use crate::net::types::Peers;
+use dashmap::DashMap;
+use lib::types::core::{
+    Address, CapMessage, CapMessageSender, Capability, NodeId, RouterAction, RouterClient,
+    RouterQuotas, RouterStats, KERNEL_PROCESS_ID,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// net capability to change what we route for, with a [`RouterAction`]
+pub const ROUTER_ADMIN_CAP_PARAMS: &str = "\"router-admin\"";
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Settings {
+    quotas: RouterQuotas,
+    denied: BTreeSet<NodeId>,
+}
+
+pub struct Router {
+    path: String,
+    settings: Mutex<Settings>,
+    clients: DashMap<NodeId, Client>,
+    bytes_relayed: AtomicU64,
+}
+
+struct Client {
+    passthroughs: u32,
+    bytes_relayed: u64,
+    bytes_today: u64,
+    day_start: Instant,
+    /// wakes the client's passthroughs to close them
+    disconnect: Arc<Notify>,
+}
+
+impl Client {
+    fn new() -> Self {
+        Self {
+            passthroughs: 0,
+            bytes_relayed: 0,
+            bytes_today: 0,
+            day_start: Instant::now(),
+            disconnect: Arc::new(Notify::new()),
+        }
+    }
+
+    fn bytes_today(&mut self) -> u64 {
+        if self.day_start.elapsed() >= DAY {
+            self.day_start = Instant::now();
+            self.bytes_today = 0;
+        }
+        self.bytes_today
+    }
+}
+
+/// a passthrough counted against its client, from [`Router::admit_passthrough`]
+pub struct PassthroughSlot {
+    router: Arc<Router>,
+    pub client: NodeId,
+    /// wakes the passthrough to close it
+    pub disconnect: Arc<Notify>,
+}
+
+impl Drop for PassthroughSlot {
+    fn drop(&mut self) {
+        if let Some(mut client) = self.router.clients.get_mut(&self.client) {
+            client.passthroughs = client.passthroughs.saturating_sub(1);
+        }
+    }
+}
+
+impl Router {
+    pub async fn load(home_directory_path: &str) -> Self {
+        let path = format!("{home_directory_path}/.router");
+        let settings = tokio::fs::read(&path)
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            settings: Mutex::new(settings),
+            clients: DashMap::new(),
+            bytes_relayed: AtomicU64::new(0),
+        }
+    }
+
+    async fn persist(&self) {
+        let settings = self.settings.lock().unwrap().clone();
+        if let Ok(bytes) = serde_json::to_vec_pretty(&settings) {
+            let _ = tokio::fs::write(&self.path, bytes).await;
+        }
+    }
+
+    fn is_denied(&self, node: &str) -> bool {
+        self.settings.lock().unwrap().denied.contains(node)
+    }
+
+    /// whether we will route for `client`, given those we already do
+    pub fn admit_routed(&self, client: &str, peers: &Peers) -> anyhow::Result<()> {
+        if self.is_denied(client) {
+            return Err(anyhow::anyhow!("net: {client} is denied routing"));
+        }
+        if let Some(max) = self.settings.lock().unwrap().quotas.max_routed {
+            let routed = peers
+                .iter()
+                .filter(|peer| peer.routing_for && peer.key() != client)
+                .count();
+            if routed >= max as usize {
+                return Err(anyhow::anyhow!(
+                    "net: already routing for {routed} nodes, refusing {client}"
+                ));
+            }
+        }
+        self.clients
+            .entry(client.to_string())
+            .or_insert_with(Client::new);
+        Ok(())
+    }
+
+    /// whether `from` may open a passthrough to `target` through us. if so,
+    /// the passthrough is counted against `from` until the slot returned is
+    /// dropped, whether it is ever opened or not.
+    pub fn admit_passthrough(
+        self: &Arc<Self>,
+        from: &str,
+        target: &str,
+    ) -> anyhow::Result<PassthroughSlot> {
+        if self.is_denied(from) || self.is_denied(target) {
+            return Err(anyhow::anyhow!(
+                "net: passthrough from {from} to {target} is denied"
+            ));
+        }
+        let quotas = self.settings.lock().unwrap().quotas.clone();
+        let mut client = self
+            .clients
+            .entry(from.to_string())
+            .or_insert_with(Client::new);
+        if quotas
+            .max_passthroughs
+            .is_some_and(|max| client.passthroughs >= max)
+        {
+            return Err(anyhow::anyhow!(
+                "net: {from} has too many passthroughs open"
+            ));
+        }
+        if quotas
+            .max_daily_bytes
+            .is_some_and(|max| client.bytes_today() >= max)
+        {
+            return Err(anyhow::anyhow!("net: {from} is over its daily quota"));
+        }
+        // counted while the entry is still held, so that passthroughs admitted
+        // at once can't all get under the quota
+        client.passthroughs += 1;
+        Ok(PassthroughSlot {
+            router: self.clone(),
+            client: from.to_string(),
+            disconnect: client.disconnect.clone(),
+        })
+    }
+
+    /// count `bytes` relayed for `client`, returning false if that puts it
+    /// over its daily quota, in which case its passthrough should close
+    pub fn relayed(&self, client: &str, bytes: usize) -> bool {
+        self.bytes_relayed
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        let max = self.settings.lock().unwrap().quotas.max_daily_bytes;
+        let mut client = self
+            .clients
+            .entry(client.to_string())
+            .or_insert_with(Client::new);
+        let today = client.bytes_today() + bytes as u64;
+        client.bytes_today = today;
+        client.bytes_relayed += bytes as u64;
+        max.map_or(true, |max| today <= max)
+    }
+
+    pub async fn set_quotas(&self, quotas: RouterQuotas) {
+        self.settings.lock().unwrap().quotas = quotas;
+        self.persist().await;
+    }
+
+    pub async fn deny(&self, node: &str, peers: &Peers) {
+        self.settings
+            .lock()
+            .unwrap()
+            .denied
+            .insert(node.to_string());
+        self.persist().await;
+        self.disconnect(node, peers);
+    }
+
+    pub async fn allow(&self, node: &str) {
+        self.settings.lock().unwrap().denied.remove(node);
+        self.persist().await;
+    }
+
+    /// close the passthroughs charged to `node`, and drop our connection to it
+    /// if we route for it: with its sender gone, the connection closes.
+    pub fn disconnect(&self, node: &str, peers: &Peers) {
+        if let Some(client) = self.clients.get(node) {
+            client.disconnect.notify_waiters();
+        }
+        peers.remove_if(node, |_, peer| peer.routing_for);
+    }
+
+    /// carry out `action` for `source`, returning the stats after it, or
+    /// why it was refused
+    pub async fn act(
+        &self,
+        action: RouterAction,
+        source: &Address,
+        peers: &Peers,
+        caps_oracle: &CapMessageSender,
+    ) -> Result<RouterStats, String> {
+        if action.is_admin() && !is_admin(source, caps_oracle).await {
+            return Err(format!(
+                "{source} does not have the router-admin capability"
+            ));
+        }
+        match action {
+            RouterAction::GetStats => {}
+            RouterAction::SetQuotas(quotas) => self.set_quotas(quotas).await,
+            RouterAction::DenyClient(node) => self.deny(&node, peers).await,
+            RouterAction::AllowClient(node) => self.allow(&node).await,
+            RouterAction::DisconnectClient(node) => self.disconnect(&node, peers),
+        }
+        Ok(self.stats(peers))
+    }
+
+    pub fn stats(&self, peers: &Peers) -> RouterStats {
+        let settings = self.settings.lock().unwrap().clone();
+        let mut clients: Vec<RouterClient> = self
+            .clients
+            .iter_mut()
+            .map(|mut client| RouterClient {
+                name: client.key().clone(),
+                routed: peers.get(client.key()).is_some_and(|peer| peer.routing_for),
+                passthroughs: client.passthroughs,
+                bytes_relayed: client.bytes_relayed,
+                bytes_today: client.bytes_today(),
+            })
+            .collect();
+        clients.sort_by(|a, b| b.bytes_relayed.cmp(&a.bytes_relayed));
+        RouterStats {
+            quotas: settings.quotas,
+            denied: settings.denied.into_iter().collect(),
+            clients_served: clients.len() as u64,
+            bytes_relayed: self.bytes_relayed.load(Ordering::Relaxed),
+            clients,
+        }
+    }
+}
+
+/// whether `source`, on our node, may change what we route for
+async fn is_admin(source: &Address, caps_oracle: &CapMessageSender) -> bool {
+    if source.process == *KERNEL_PROCESS_ID {
+        return true;
+    }
+    let (send_cap_bool, recv_cap_bool) = tokio::sync::oneshot::channel();
+    let cap = Capability::new(
+        (source.node.as_str(), "net", "distro", "sys"),
+        ROUTER_ADMIN_CAP_PARAMS,
+    );
+    if caps_oracle
+        .send(CapMessage::Has {
+            on: source.process.clone(),
+            cap,
+            responder: send_cap_bool,
+        })
+        .await
+        .is_err()
+    {
+        return false;
+    }
+    recv_cap_bool.await.unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::ProcessId;
+
+    fn router() -> Arc<Router> {
+        let home = std::env::temp_dir().join(format!("kinode-router-{}", rand::random::<u64>()));
+        Arc::new(Router {
+            path: home.to_str().unwrap().to_string(),
+            settings: Mutex::new(Settings::default()),
+            clients: DashMap::new(),
+            bytes_relayed: AtomicU64::new(0),
+        })
+    }
+
+    fn quotas(max_passthroughs: Option<u32>, max_daily_bytes: Option<u64>) -> RouterQuotas {
+        RouterQuotas {
+            max_routed: None,
+            max_passthroughs,
+            max_daily_bytes,
+        }
+    }
+
+    /// a caps oracle that answers every question with `has`
+    fn caps_oracle(has: bool) -> CapMessageSender {
+        let (send, mut recv) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            while let Some(message) = recv.recv().await {
+                if let CapMessage::Has { responder, .. } = message {
+                    let _ = responder.send(has);
+                }
+            }
+        });
+        send
+    }
+
+    fn script() -> Address {
+        Address::new("node.os", ProcessId::new(Some("router"), "terminal", "sys"))
+    }
+
+    #[test]
+    fn passthroughs_count_until_their_slot_drops() {
+        let router = router();
+        router.settings.lock().unwrap().quotas = quotas(Some(2), None);
+        let first = router.admit_passthrough("client.os", "target.os").unwrap();
+        let _second = router.admit_passthrough("client.os", "target.os").unwrap();
+        assert!(router.admit_passthrough("client.os", "target.os").is_err());
+        assert!(router.admit_passthrough("other.os", "target.os").is_ok());
+        drop(first);
+        assert!(router.admit_passthrough("client.os", "target.os").is_ok());
+    }
+
+    #[test]
+    fn daily_quota_closes_and_refuses_passthroughs() {
+        let router = router();
+        router.settings.lock().unwrap().quotas = quotas(None, Some(100));
+        assert!(router.relayed("client.os", 60));
+        assert!(!router.relayed("client.os", 60));
+        assert!(router.admit_passthrough("client.os", "target.os").is_err());
+        assert!(router.relayed("other.os", 60));
+    }
+
+    #[tokio::test]
+    async fn admin_actions_need_the_router_admin_cap() {
+        let router = router();
+        let peers: Peers = Arc::new(DashMap::new());
+        let deny = || RouterAction::DenyClient("client.os".into());
+
+        let without = caps_oracle(false);
+        assert!(router
+            .act(RouterAction::GetStats, &script(), &peers, &without)
+            .await
+            .is_ok());
+        assert!(router
+            .act(deny(), &script(), &peers, &without)
+            .await
+            .is_err());
+        assert!(router.admit_passthrough("client.os", "target.os").is_ok());
+
+        let with = caps_oracle(true);
+        let stats = router.act(deny(), &script(), &peers, &with).await.unwrap();
+        assert_eq!(stats.denied, vec!["client.os".to_string()]);
+        assert!(router.admit_passthrough("client.os", "target.os").is_err());
+        assert!(router.admit_passthrough("other.os", "client.os").is_err());
+        let _ = std::fs::remove_file(&router.path);
+    }
+}
@@ -1,14 +1,15 @@
 use crate::net::{
+    cipher::{self, Negotiated},
     types::{IdentityExt, NetData, Peer, PendingStream, RoutingRequest, TCP_PROTOCOL},
     utils::{
-        build_initiator, build_responder, create_passthrough, make_conn_url, print_debug,
-        validate_handshake, validate_routing_request, TIMEOUT,
+        build_initiator, build_responder, create_passthrough, hosts, make_conn_url, print_debug,
+        race, validate_handshake, validate_routing_request, TIMEOUT,
     },
 };
 use lib::types::core::{Identity, KernelMessage};
 use {
     anyhow::anyhow,
-    tokio::net::{TcpListener, TcpStream},
+    tokio::net::TcpStream,
     tokio::{sync::mpsc, time},
 };
 
@@ -16,6 +17,7 @@ pub mod utils;
 
 pub struct PeerConnection {
     pub noise: snow::TransportState,
+    pub negotiated: Negotiated,
     pub buf: [u8; 65535],
     pub stream: TcpStream,
 }
@@ -25,7 +27,7 @@ pub async fn receiver(ext: IdentityExt, data: NetData) -> anyhow::Result<()> {
         .our
         .get_protocol_port(TCP_PROTOCOL)
         .expect("tcp port not found");
-    let tcp = match TcpListener::bind(format!("0.0.0.0:{tcp_port}")).await {
+    let tcp = match crate::http::utils::bind_any(*tcp_port) {
         Ok(tcp) => tcp,
         Err(_e) => {
             return Err(anyhow::anyhow!(
@@ -85,17 +87,17 @@ pub async fn init_direct(
     proxy_request: bool,
     peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
 ) -> Result<(), mpsc::UnboundedReceiver<KernelMessage>> {
-    match time::timeout(
-        TIMEOUT,
-        connect_with_handshake(ext, peer_id, port, None, proxy_request),
-    )
-    .await
-    {
+    let attempts = race(&hosts(ext, data, peer_id), |host| {
+        let (ext, peer_id) = (ext.clone(), peer_id.clone());
+        async move { connect_with_handshake(&ext, &peer_id, &host, port, None, proxy_request).await }
+    });
+    match time::timeout(TIMEOUT, attempts).await {
         Ok(Ok(connection)) => {
             // maintain direct connection
             tokio::spawn(utils::maintain_connection(
                 peer_id.name.clone(),
                 data.peers.clone(),
+                data.reputation.clone(),
                 connection,
                 peer_rx,
                 ext.kernel_message_tx.clone(),
@@ -126,17 +128,20 @@ pub async fn init_routed(
     router_port: u16,
     peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
 ) -> Result<(), mpsc::UnboundedReceiver<KernelMessage>> {
-    match time::timeout(
-        TIMEOUT,
-        connect_with_handshake(ext, peer_id, router_port, Some(router_id), false),
-    )
-    .await
-    {
+    let attempts = race(&hosts(ext, data, router_id), |host| {
+        let (ext, peer_id, router_id) = (ext.clone(), peer_id.clone(), router_id.clone());
+        async move {
+            connect_with_handshake(&ext, &peer_id, &host, router_port, Some(&router_id), false)
+                .await
+        }
+    });
+    match time::timeout(TIMEOUT, attempts).await {
         Ok(Ok(connection)) => {
             // maintain direct connection
             tokio::spawn(utils::maintain_connection(
                 peer_id.name.clone(),
                 data.peers.clone(),
+                data.reputation.clone(),
                 connection,
                 peer_rx,
                 ext.kernel_message_tx.clone(),
@@ -166,8 +171,9 @@ async fn recv_connection(
     // if the first message contains a "routing request",
     // we see if the target is someone we are actively routing for,
     // and create a Passthrough connection if so.
-    // a Noise 'e' message with have len 32
-    if len != 32 {
+    // a Noise 'e' message with have len 32, and a peer that would negotiate
+    // a cipher suite sends its offer first
+    if len != 32 && !cipher::is_offer(&first_message) {
         let (from_id, target_id) =
             validate_routing_request(&ext.our.name, &first_message, &data.pki)?;
         return create_passthrough(
@@ -177,13 +183,17 @@ async fn recv_connection(
             target_id,
             &data.peers,
             &data.pending_passthroughs,
+            &data.router,
             PendingStream::Tcp(stream),
         )
         .await;
     }
 
+    let (negotiated, first_message) =
+        utils::respond_to_offer(&ext.cipher, first_message, &mut stream).await?;
+
     let mut buf = [0u8; 65535];
-    let (mut noise, our_static_key) = build_responder();
+    let (mut noise, our_static_key) = build_responder(&negotiated);
 
     // <- e
     noise.read_message(&first_message, &mut buf)?;
@@ -215,6 +225,13 @@ async fn recv_connection(
         &their_id,
     )?;
 
+    if data.reputation.is_banned(&their_id.name) {
+        return Err(anyhow!("{} is banned for misbehaving", their_id.name));
+    }
+    if their_handshake.proxy_request {
+        data.router.admit_routed(&their_id.name, &data.peers)?;
+    }
+
     let (peer_tx, peer_rx) = mpsc::unbounded_channel();
     data.peers.insert(
         their_id.name.clone(),
@@ -222,13 +239,16 @@ async fn recv_connection(
             identity: their_id.clone(),
             routing_for: their_handshake.proxy_request,
             sender: peer_tx,
+            session: None,
         },
     );
     tokio::spawn(utils::maintain_connection(
         their_handshake.name,
         data.peers,
+        data.reputation,
         PeerConnection {
             noise: noise.into_transport_mode()?,
+            negotiated,
             buf,
             stream,
         },
@@ -239,22 +259,34 @@ async fn recv_connection(
     Ok(())
 }
 
+/// connect offering our cipher suites, and again without an offer if the
+/// peer predates negotiation and we still accept the legacy suite
 async fn connect_with_handshake(
     ext: &IdentityExt,
     peer_id: &Identity,
+    host: &str,
     port: u16,
     use_router: Option<&Identity>,
     proxy_request: bool,
 ) -> anyhow::Result<PeerConnection> {
-    let ip = match use_router {
-        None => peer_id
-            .get_ip()
-            .ok_or(anyhow!("target has no IP address"))?,
-        Some(router_id) => router_id
-            .get_ip()
-            .ok_or(anyhow!("router has no IP address"))?,
-    };
-    let tcp_url = make_conn_url(&ext.our_ip, ip, &port, TCP_PROTOCOL)?;
+    match handshake(ext, peer_id, host, port, use_router, proxy_request, true).await {
+        Err(e) if cipher::retry_legacy(&ext.cipher, &e) => {
+            handshake(ext, peer_id, host, port, use_router, proxy_request, false).await
+        }
+        result => result,
+    }
+}
+
+async fn handshake(
+    ext: &IdentityExt,
+    peer_id: &Identity,
+    host: &str,
+    port: u16,
+    use_router: Option<&Identity>,
+    proxy_request: bool,
+    offer: bool,
+) -> anyhow::Result<PeerConnection> {
+    let tcp_url = make_conn_url(&ext.our_ip, host, &port, TCP_PROTOCOL)?;
     let Ok(mut stream) = tokio::net::TcpStream::connect(tcp_url.to_string()).await else {
         return Err(anyhow!("failed to connect to {tcp_url}"));
     };
@@ -282,8 +314,10 @@ async fn connect_with_handshake(
         .await?;
     }
 
+    let negotiated = utils::offer_suites(&ext.cipher, offer, &mut stream).await?;
+
     let mut buf = [0u8; 65535];
-    let (mut noise, our_static_key) = build_initiator();
+    let (mut noise, our_static_key) = build_initiator(&negotiated);
 
     // -> e
     let len = noise.write_message(&[], &mut buf)?;
@@ -314,6 +348,7 @@ async fn connect_with_handshake(
 
     Ok(PeerConnection {
         noise: noise.into_transport_mode()?,
+        negotiated,
         buf,
         stream,
     })
@@ -343,12 +378,14 @@ pub async fn recv_via_router(
                     identity: peer_id.clone(),
                     routing_for: false,
                     sender: peer_tx,
+                    session: None,
                 },
             );
             // maintain direct connection
             tokio::spawn(utils::maintain_connection(
                 peer_id.name,
                 data.peers.clone(),
+                data.reputation.clone(),
                 connection,
                 peer_rx,
                 ext.kernel_message_tx,
@@ -387,11 +424,15 @@ async fn connect_with_handshake_via_router(
     )
     .await?;
 
+    let first_message = utils::recv_raw(&mut stream).await?.1;
+    let (negotiated, first_message) =
+        utils::respond_to_offer(&ext.cipher, first_message, &mut stream).await?;
+
     let mut buf = [0u8; 65535];
-    let (mut noise, our_static_key) = build_responder();
+    let (mut noise, our_static_key) = build_responder(&negotiated);
 
     // <- e
-    noise.read_message(&utils::recv_raw(&mut stream).await?.1, &mut buf)?;
+    noise.read_message(&first_message, &mut buf)?;
 
     // -> e, ee, s, es
     utils::send_protocol_handshake(
@@ -418,6 +459,7 @@ async fn connect_with_handshake_via_router(
 
     Ok(PeerConnection {
         noise: noise.into_transport_mode()?,
+        negotiated,
         buf,
         stream,
     })
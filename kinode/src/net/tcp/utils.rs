@@ -1,10 +1,13 @@
 use crate::net::{
+    cipher::{self, CipherConfig, Negotiated, Rekeying, Session},
+    reputation::{is_malformed, Offense, Reputation, Standing},
     tcp::PeerConnection,
     types::{HandshakePayload, IdentityExt, Peers},
     utils::{print_debug, print_loud, MESSAGE_MAX_SIZE},
 };
 use lib::types::core::{KernelMessage, MessageSender, NodeId, PrintSender};
 use {
+    std::sync::Arc,
     tokio::io::{AsyncReadExt, AsyncWriteExt},
     tokio::net::{tcp::OwnedReadHalf, tcp::OwnedWriteHalf, TcpStream},
     tokio::sync::mpsc::UnboundedReceiver,
@@ -14,6 +17,7 @@ use {
 pub async fn maintain_connection(
     peer_name: NodeId,
     peers: Peers,
+    reputation: Arc<Reputation>,
     mut conn: PeerConnection,
     mut peer_rx: UnboundedReceiver<KernelMessage>,
     kernel_message_tx: MessageSender,
@@ -27,6 +31,12 @@ pub async fn maintain_connection(
         .set_tcp_keepalive(&ka)
         .expect("failed to set tcp keepalive");
 
+    let session = Arc::new(Session::new(&conn.negotiated));
+    if let Some(mut peer) = peers.get_mut(&peer_name) {
+        peer.session = Some(session.clone());
+    }
+    let mut rekeying = Rekeying::new(&conn.negotiated);
+
     let (mut read_stream, mut write_stream) = conn.stream.into_split();
     let initiator = conn.noise.is_initiator();
     let snow::CipherStates(c1, c2) = conn.noise.extract_cipherstates();
@@ -39,8 +49,15 @@ pub async fn maintain_connection(
     };
 
     let write_buf = &mut [0; 65536];
+    let write_session = session.clone();
     let write = async move {
         while let Some(km) = peer_rx.recv().await {
+            if rekeying.due() {
+                let Ok(()) = send_rekey(&mut our_cipher, write_buf, &mut write_stream).await else {
+                    break;
+                };
+                write_session.rekeyed();
+            }
             let Ok(()) =
                 send_protocol_message(&km, &mut our_cipher, write_buf, &mut write_stream).await
             else {
@@ -54,7 +71,9 @@ pub async fn maintain_connection(
     let read_print_tx = print_tx.clone();
     let read = async move {
         loop {
-            match recv_protocol_message(&mut their_cipher, read_buf, &mut read_stream).await {
+            match recv_protocol_message(&mut their_cipher, read_buf, &mut read_stream, &session)
+                .await
+            {
                 Ok(km) => {
                     if km.source.node != read_peer_name {
                         print_loud(
@@ -62,15 +81,30 @@ pub async fn maintain_connection(
                             &format!("net: got message with spoofed source from {read_peer_name}!"),
                         )
                         .await;
+                        reputation.offense(&read_peer_name, Offense::Spoofed);
                         break;
-                    } else {
-                        kernel_message_tx
-                            .send(km)
-                            .await
-                            .expect("net: fatal: kernel receiver died");
                     }
+                    match reputation.received(&read_peer_name) {
+                        Standing::Good => {}
+                        Standing::Throttled(delay) => tokio::time::sleep(delay).await,
+                        Standing::Banned => {
+                            print_loud(
+                                &read_print_tx,
+                                &format!("net: dropping {read_peer_name}, banned for misbehaving"),
+                            )
+                            .await;
+                            break;
+                        }
+                    }
+                    kernel_message_tx
+                        .send(km)
+                        .await
+                        .expect("net: fatal: kernel receiver died");
                 }
                 Err(e) => {
+                    if is_malformed(&e) {
+                        reputation.offense(&read_peer_name, Offense::Malformed);
+                    }
                     print_debug(
                         &read_print_tx,
                         &format!("net: error receiving message: {e}"),
@@ -114,14 +148,48 @@ async fn send_protocol_message(
     Ok(stream.flush().await?)
 }
 
+/// tell the peer we are rekeying our direction of the connection, then do so.
+/// the signal is an empty message: a zero length, then an encrypted empty
+/// chunk, so that only the peer can have sent it.
+async fn send_rekey(
+    cipher: &mut snow::CipherState,
+    buf: &mut [u8],
+    stream: &mut OwnedWriteHalf,
+) -> anyhow::Result<()> {
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    let len = cipher.encrypt(&[], buf)? as u16;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(&buf[..len as usize]).await?;
+    stream.flush().await?;
+    cipher.rekey();
+    Ok(())
+}
+
 /// any error in receiving a message will result in the connection being closed.
 async fn recv_protocol_message(
     cipher: &mut snow::CipherState,
     buf: &mut [u8],
     stream: &mut OwnedReadHalf,
+    session: &Session,
 ) -> anyhow::Result<KernelMessage> {
     stream.read_exact(&mut buf[..4]).await?;
-    let outer_len = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+    let mut outer_len = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+    while outer_len == 0 {
+        // the peer is rekeying its direction of the connection
+        let mut inner_len = [0; 2];
+        stream.read_exact(&mut inner_len).await?;
+        let inner_len = u16::from_be_bytes(inner_len) as usize;
+        let mut chunk = vec![0; inner_len];
+        stream.read_exact(&mut chunk).await?;
+        cipher.decrypt(&chunk, buf)?;
+        cipher.rekey();
+        session.rekeyed();
+        stream.read_exact(&mut buf[..4]).await?;
+        outer_len = u32::from_be_bytes(buf[..4].try_into().unwrap()) as usize;
+    }
+    if outer_len > MESSAGE_MAX_SIZE as usize {
+        return Err(anyhow::anyhow!("message too large"));
+    }
 
     let mut msg = vec![0; outer_len];
     let mut ptr = 0;
@@ -134,7 +202,11 @@ async fn recv_protocol_message(
         let read_len = cipher.decrypt(&buf[..inner_len as usize], &mut msg[ptr..])?;
         ptr += read_len;
     }
-    Ok(rmp_serde::from_slice(&msg)?)
+    let mut km: KernelMessage = rmp_serde::from_slice(&msg)?;
+    // blob handles only refer to our own blob store, so never accept one from a peer
+    km.blob_handle = None;
+    km.sent_at = Some(std::time::Instant::now());
+    Ok(km)
 }
 
 pub async fn send_protocol_handshake(
@@ -176,6 +248,42 @@ pub async fn recv_protocol_handshake(
     Ok(rmp_serde::from_slice(&buf[..len])?)
 }
 
+/// as the initiator, before our Noise 'e' message, offer the cipher suites
+/// we accept and take up the responder's choice, unless `offer` is false or
+/// there is nothing to negotiate, when the handshake is legacy
+pub async fn offer_suites(
+    config: &CipherConfig,
+    offer: bool,
+    stream: &mut TcpStream,
+) -> anyhow::Result<Negotiated> {
+    let Some(offer) = cipher::offer(config).filter(|_| offer) else {
+        return Negotiated::legacy(config);
+    };
+    send_raw(stream, &offer).await?;
+    let Ok((_len, answer)) = recv_raw(stream).await else {
+        return Err(cipher::Unanswered.into());
+    };
+    cipher::accept(config, &offer, &answer)
+}
+
+/// as the responder, settle on a cipher suite given the peer's first message,
+/// which is either its Noise 'e' message or an offer to answer before it.
+/// returns what was settled on, and the 'e' message.
+pub async fn respond_to_offer(
+    config: &CipherConfig,
+    first_message: Vec<u8>,
+    stream: &mut TcpStream,
+) -> anyhow::Result<(Negotiated, Vec<u8>)> {
+    if !cipher::is_offer(&first_message) {
+        return Ok((Negotiated::legacy(config)?, first_message));
+    }
+    let (answer, negotiated) = cipher::answer(config, &first_message);
+    send_raw(stream, &answer).await?;
+    let negotiated = negotiated?;
+    let (_len, first_message) = recv_raw(stream).await?;
+    Ok((negotiated, first_message))
+}
+
 /// make sure raw message is less than 65536 bytes
 pub async fn send_raw(stream: &mut TcpStream, msg: &[u8]) -> anyhow::Result<()> {
     let len = (msg.len() as u16).to_be_bytes();
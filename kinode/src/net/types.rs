@@ -1,5 +1,7 @@
+use crate::net::router::PassthroughSlot;
 use lib::types::core::{
-    Identity, KernelMessage, MessageSender, NetworkErrorSender, NodeId, PrintSender,
+    CapMessageSender, Identity, KernelMessage, MessageSender, NetworkErrorSender, NodeId,
+    PrintSender,
 };
 use {
     dashmap::DashMap,
@@ -56,9 +58,12 @@ pub struct RoutingRequest {
 
 pub type Peers = Arc<DashMap<String, Peer>>;
 pub type OnchainPKI = Arc<DashMap<String, Identity>>;
+/// every host a node advertises, whether an IPv4 or IPv6 address or a domain,
+/// in the order it would have them tried. the first is the IP in its [`Identity`].
+pub type Endpoints = Arc<DashMap<NodeId, Vec<String>>>;
 
-/// (from, target) -> from's socket
-pub type PendingPassthroughs = Arc<DashMap<(NodeId, NodeId), PendingStream>>;
+/// (from, target) -> from's socket, and the passthrough it is counted as
+pub type PendingPassthroughs = Arc<DashMap<(NodeId, NodeId), (PendingStream, PassthroughSlot)>>;
 pub enum PendingStream {
     WebSocket(WebSocketStream<MaybeTlsStream<TcpStream>>),
     Tcp(TcpStream),
@@ -80,6 +85,8 @@ pub struct Peer {
     /// associated with them. We can send them prompts to establish Passthroughs.
     pub routing_for: bool,
     pub sender: UnboundedSender<KernelMessage>,
+    /// the encryption of our connection with them, once it is up
+    pub session: Option<Arc<crate::net::cipher::Session>>,
 }
 
 /// [`Identity`], with additional fields for networking.
@@ -91,7 +98,15 @@ pub struct IdentityExt {
     pub kernel_message_tx: MessageSender,
     pub network_error_tx: NetworkErrorSender,
     pub print_tx: PrintSender,
+    /// to check the capabilities of processes changing what we route for
+    pub caps_oracle: CapMessageSender,
+    /// resolves blob handles in messages before they leave the node
+    pub blob_store: Arc<crate::blobs::BlobStore>,
     pub _reveal_ip: bool, // TODO use
+    /// try a peer's IPv6 addresses before its IPv4 ones, rather than after
+    pub prefer_ipv6: bool,
+    /// the cipher suites we speak with peers, and how often to rekey
+    pub cipher: Arc<crate::net::cipher::CipherConfig>,
 }
 
 #[derive(Clone)]
@@ -99,4 +114,9 @@ pub struct NetData {
     pub pki: OnchainPKI,
     pub peers: Peers,
     pub pending_passthroughs: PendingPassthroughs,
+    pub endpoints: Endpoints,
+    pub router: Arc<crate::net::router::Router>,
+    pub reputation: Arc<crate::net::reputation::Reputation>,
+    /// nodes heard from on the local network, with `--lan-discovery`
+    pub siblings: crate::net::lan::Siblings,
 }
@@ -1,32 +1,33 @@
+use crate::net::cipher::Negotiated;
+use crate::net::router::{PassthroughSlot, Router};
 use crate::net::types::{
-    HandshakePayload, OnchainPKI, Peers, PendingPassthroughs, PendingStream, RoutingRequest,
-    TCP_PROTOCOL, WS_PROTOCOL,
+    HandshakePayload, IdentityExt, NetData, OnchainPKI, Peers, PendingPassthroughs, PendingStream,
+    RoutingRequest, TCP_PROTOCOL, WS_PROTOCOL,
 };
 use lib::types::core::{
     Identity, KernelMessage, KnsUpdate, Message, MessageSender, NetAction, NetworkErrorSender,
-    NodeRouting, PrintSender, Printout, Request, Response, SendError, SendErrorKind,
-    WrappedSendError,
+    NodeRouting, PrintSender, Printout, Request, Resolution, Response, SendError, SendErrorCause,
+    SendErrorHop, SendErrorKind, WrappedSendError,
 };
 use {
     futures::{SinkExt, StreamExt},
     ring::signature::{self},
-    snow::params::NoiseParams,
+    std::sync::Arc,
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     tokio::time,
     tokio_tungstenite::connect_async,
 };
 
-lazy_static::lazy_static! {
-    pub static ref PARAMS: NoiseParams = "Noise_XX_25519_ChaChaPoly_BLAKE2s"
-                                        .parse()
-                                        .expect("net: couldn't build noise params?");
-}
-
 /// 10 MB -- TODO analyze as desired, apps can always chunk data into many messages
 /// note that this only applies to cross-network messages, not local ones.
 pub const MESSAGE_MAX_SIZE: u32 = 10_485_800;
 
 pub const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
 
+/// when a node advertises several hosts, each is tried this long after the
+/// one before it, unless a connection is made first
+pub const HAPPY_EYEBALLS_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
 pub async fn create_passthrough(
     our: &Identity,
     our_ip: &str,
@@ -34,16 +35,23 @@ pub async fn create_passthrough(
     target_id: Identity,
     peers: &Peers,
     pending_passthroughs: &PendingPassthroughs,
+    router: &Arc<Router>,
     socket_1: PendingStream,
 ) -> anyhow::Result<()> {
     // if the target has already generated a pending passthrough for this source,
-    // immediately match them
-    if let Some(((_target, _from), pending_stream)) =
+    // immediately match them. the target asked for it, and was admitted then.
+    if let Some(((_target, _from), (pending_stream, slot))) =
         pending_passthroughs.remove(&(target_id.name.clone(), from_id.name.clone()))
     {
-        tokio::spawn(maintain_passthrough(socket_1, pending_stream));
+        tokio::spawn(maintain_passthrough(
+            socket_1,
+            pending_stream,
+            router.clone(),
+            slot,
+        ));
         return Ok(());
     }
+    let slot = router.admit_passthrough(&from_id.name, &target_id.name)?;
     if socket_1.is_tcp() {
         if let Some((ip, tcp_port)) = target_id.tcp_routing() {
             // create passthrough to direct node over tcp
@@ -57,7 +65,12 @@ pub async fn create_passthrough(
                     from_id.name
                 ));
             };
-            tokio::spawn(maintain_passthrough(socket_1, PendingStream::Tcp(stream_2)));
+            tokio::spawn(maintain_passthrough(
+                socket_1,
+                PendingStream::Tcp(stream_2),
+                router.clone(),
+                slot,
+            ));
             return Ok(());
         }
     } else if socket_1.is_ws() {
@@ -75,6 +88,8 @@ pub async fn create_passthrough(
             tokio::spawn(maintain_passthrough(
                 socket_1,
                 PendingStream::WebSocket(socket_2),
+                router.clone(),
+                slot,
             ));
             return Ok(());
         }
@@ -113,22 +128,31 @@ pub async fn create_passthrough(
     // or if the target node connects to us with a matching passthrough.
     // TODO it is currently possible to have dangling passthroughs in the map
     // if the target is "connected" to us but nonresponsive.
-    pending_passthroughs.insert((from_id.name, target_id.name), socket_1);
+    pending_passthroughs.insert((from_id.name, target_id.name), (socket_1, slot));
     Ok(())
 }
 
-/// cross the streams -- spawn on own task
-pub async fn maintain_passthrough(socket_1: PendingStream, socket_2: PendingStream) {
+/// cross the streams, charging what is relayed to the slot's client -- spawn
+/// on own task
+pub async fn maintain_passthrough(
+    socket_1: PendingStream,
+    socket_2: PendingStream,
+    router: Arc<Router>,
+    slot: PassthroughSlot,
+) {
+    let client = &slot.client;
+    let disconnected = slot.disconnect.notified();
+    tokio::pin!(disconnected);
     match (socket_1, socket_2) {
         (PendingStream::Tcp(socket_1), PendingStream::Tcp(socket_2)) => {
             // do not use bidirectional because if one side closes,
             // we want to close the entire passthrough
-            use tokio::io::copy;
-            let (mut r1, mut w1) = tokio::io::split(socket_1);
-            let (mut r2, mut w2) = tokio::io::split(socket_2);
+            let (r1, w1) = tokio::io::split(socket_1);
+            let (r2, w2) = tokio::io::split(socket_2);
             tokio::select! {
-                _ = copy(&mut r1, &mut w2) => {},
-                _ = copy(&mut r2, &mut w1) => {},
+                _ = relay(r1, w2, &router, client) => {},
+                _ = relay(r2, w1, &router, client) => {},
+                _ = &mut disconnected => {},
             }
         }
         (PendingStream::WebSocket(mut socket_1), PendingStream::WebSocket(mut socket_2)) => {
@@ -138,6 +162,9 @@ pub async fn maintain_passthrough(socket_1: PendingStream, socket_2: PendingStre
                     maybe_recv = socket_1.next() => {
                         match maybe_recv {
                             Some(Ok(msg)) => {
+                                if !router.relayed(client, msg.len()) {
+                                    break
+                                }
                                 let Ok(()) = socket_2.send(msg).await else {
                                     break
                                 };
@@ -149,6 +176,9 @@ pub async fn maintain_passthrough(socket_1: PendingStream, socket_2: PendingStre
                     maybe_recv = socket_2.next() => {
                         match maybe_recv {
                             Some(Ok(msg)) => {
+                                if !router.relayed(client, msg.len()) {
+                                    break
+                                }
                                 let Ok(()) = socket_1.send(msg).await else {
                                     break
                                 };
@@ -157,6 +187,7 @@ pub async fn maintain_passthrough(socket_1: PendingStream, socket_2: PendingStre
                             _ => break,
                         }
                     },
+                    _ = &mut disconnected => break,
                     // if a message has not been sent or received in 2-4 hours, close the connection
                     _ = tokio::time::sleep(std::time::Duration::from_secs(7200)) => {
                         if last_message.elapsed().as_secs() > 7200 {
@@ -170,29 +201,126 @@ pub async fn maintain_passthrough(socket_1: PendingStream, socket_2: PendingStre
         }
         _ => {
             // these foolish combinations must never occur
-            return;
         }
     }
 }
 
-pub fn ingest_log(log: KnsUpdate, pki: &OnchainPKI) {
-    pki.insert(
+/// copy one way across a TCP passthrough until either side closes, or the
+/// client goes over its quota
+async fn relay(
+    mut from: impl AsyncRead + Unpin,
+    mut to: impl AsyncWrite + Unpin,
+    router: &Router,
+    client: &str,
+) {
+    let mut buf = vec![0u8; 65536];
+    loop {
+        let Ok(len) = from.read(&mut buf).await else {
+            break;
+        };
+        if len == 0 || !router.relayed(client, len) {
+            break;
+        }
+        if to.write_all(&buf[..len]).await.is_err() {
+            break;
+        }
+    }
+}
+
+pub fn ingest_log(log: KnsUpdate, data: &NetData) {
+    let routing = match log.ips.first() {
+        None => NodeRouting::Routers(log.routers),
+        Some(ip) if log.routers.is_empty() => NodeRouting::Direct {
+            ip: ip.clone(),
+            ports: log.ports,
+        },
+        // reached directly if it can be, and through its routers otherwise
+        Some(ip) => NodeRouting::Both {
+            ip: ip.clone(),
+            ports: log.ports,
+            routers: log.routers,
+        },
+    };
+    if log.ips.is_empty() {
+        data.endpoints.remove(&log.name);
+    } else {
+        data.endpoints.insert(log.name.clone(), log.ips);
+    }
+    data.pki.insert(
         log.name.clone(),
         Identity {
-            name: log.name.clone(),
+            name: log.name,
             networking_key: log.public_key,
-            routing: if log.ips.is_empty() {
-                NodeRouting::Routers(log.routers)
-            } else {
-                NodeRouting::Direct {
-                    ip: log.ips[0].clone(),
-                    ports: log.ports,
-                }
-            },
+            routing,
         },
     );
 }
 
+/// the hosts to try when connecting to `id` directly, in order: as it
+/// advertises them, but with addresses of the family we don't prefer last,
+/// and where we last heard from it on the local network first
+pub fn hosts(ext: &IdentityExt, data: &NetData, id: &Identity) -> Vec<String> {
+    let mut hosts = match data.endpoints.get(&id.name) {
+        Some(hosts) => hosts.clone(),
+        None => id.get_ip().map(str::to_string).into_iter().collect(),
+    };
+    hosts.sort_by_key(|host| match parse_ip(host) {
+        Some(ip) => ip.is_ipv6() != ext.prefer_ipv6,
+        None => false,
+    });
+    if let Some(sibling) = crate::net::lan::sibling_host(data, &id.name) {
+        hosts.retain(|host| !same_host(host, &sibling));
+        hosts.insert(0, sibling);
+    }
+    hosts
+}
+
+/// `host` as an IP address, if it is one, with or without brackets
+pub fn parse_ip(host: &str) -> Option<std::net::IpAddr> {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+/// whether `a` and `b` name the same host, compared as addresses if they are
+/// IP addresses, since an IPv6 address can be written many ways
+pub fn same_host(a: &str, b: &str) -> bool {
+    match (parse_ip(a), parse_ip(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// connect to the first of `hosts` that answers, happy-eyeballs style: each
+/// attempt starts [`HAPPY_EYEBALLS_DELAY`] after the one before, and the first
+/// to succeed wins, cancelling the rest
+pub async fn race<T, F, Fut>(hosts: &[String], connect: F) -> anyhow::Result<T>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    let mut attempts = tokio::task::JoinSet::new();
+    for (i, host) in hosts.iter().enumerate() {
+        let attempt = connect(host.clone());
+        attempts.spawn(async move {
+            time::sleep(HAPPY_EYEBALLS_DELAY * i as u32).await;
+            attempt.await
+        });
+    }
+    let mut last_error = anyhow::anyhow!("no address to connect to");
+    while let Some(result) = attempts.join_next().await {
+        match result {
+            // dropping the set aborts the attempts still going
+            Ok(Ok(connection)) => return Ok(connection),
+            Ok(Err(e)) => last_error = e,
+            Err(e) => last_error = e.into(),
+        }
+    }
+    Err(last_error)
+}
+
 pub fn validate_signature(from: &str, signature: &[u8], message: &[u8], pki: &OnchainPKI) -> bool {
     if let Some(peer_id) = pki.get(from) {
         let their_networking_key = signature::UnparsedPublicKey::new(
@@ -254,8 +382,8 @@ pub fn validate_handshake(
     Ok(())
 }
 
-pub fn build_responder() -> (snow::HandshakeState, Vec<u8>) {
-    let builder: snow::Builder<'_> = snow::Builder::new(PARAMS.clone());
+pub fn build_responder(negotiated: &Negotiated) -> (snow::HandshakeState, Vec<u8>) {
+    let builder: snow::Builder<'_> = negotiated.builder();
     let keypair = builder
         .generate_keypair()
         .expect("net: couldn't generate keypair?");
@@ -269,8 +397,8 @@ pub fn build_responder() -> (snow::HandshakeState, Vec<u8>) {
     )
 }
 
-pub fn build_initiator() -> (snow::HandshakeState, Vec<u8>) {
-    let builder: snow::Builder<'_> = snow::Builder::new(PARAMS.clone());
+pub fn build_initiator(negotiated: &Negotiated) -> (snow::HandshakeState, Vec<u8>) {
+    let builder: snow::Builder<'_> = negotiated.builder();
     let keypair = builder
         .generate_keypair()
         .expect("net: couldn't generate keypair?");
@@ -287,15 +415,34 @@ pub fn build_initiator() -> (snow::HandshakeState, Vec<u8>) {
 pub fn make_conn_url(our_ip: &str, ip: &str, port: &u16, protocol: &str) -> anyhow::Result<String> {
     // if we have the same public IP as target, route locally,
     // otherwise they will appear offline due to loopback stuff
-    let ip = if our_ip == ip { "localhost" } else { ip };
+    let ip = ip.trim_start_matches('[').trim_end_matches(']');
+    let ip = if same_host(our_ip, ip) {
+        "localhost"
+    } else {
+        ip
+    };
+    // IPv6 addresses are bracketed to set them apart from the port
+    let host = if ip.contains(':') {
+        format!("[{ip}]")
+    } else {
+        ip.to_string()
+    };
     match protocol {
-        TCP_PROTOCOL => Ok(format!("{ip}:{port}")),
-        WS_PROTOCOL => Ok(format!("ws://{ip}:{port}")),
+        TCP_PROTOCOL => Ok(format!("{host}:{port}")),
+        WS_PROTOCOL => Ok(format!("ws://{host}:{port}")),
         _ => Err(anyhow::anyhow!("unknown protocol: {}", protocol)),
     }
 }
 
-pub async fn error_offline(km: KernelMessage, network_error_tx: &NetworkErrorSender) {
+/// fail a message back to its source as offline, noting where and why, and
+/// how we tried to reach its target
+pub async fn error_offline(
+    km: KernelMessage,
+    hop: SendErrorHop,
+    cause: SendErrorCause,
+    resolution: Option<Resolution>,
+    network_error_tx: &NetworkErrorSender,
+) {
     network_error_tx
         .send(WrappedSendError {
             id: km.id,
@@ -305,6 +452,9 @@ pub async fn error_offline(km: KernelMessage, network_error_tx: &NetworkErrorSen
                 target: km.target,
                 message: km.message,
                 lazy_load_blob: km.lazy_load_blob,
+                hop,
+                cause,
+                resolution,
             },
         })
         .await
@@ -1,15 +1,16 @@
 use crate::net::{
+    cipher::{self, Negotiated},
     types::{IdentityExt, NetData, Peer, PendingStream, RoutingRequest, WS_PROTOCOL},
     utils::{
-        build_initiator, build_responder, create_passthrough, make_conn_url, print_debug,
-        validate_handshake, validate_routing_request, TIMEOUT,
+        build_initiator, build_responder, create_passthrough, hosts, make_conn_url, print_debug,
+        race, validate_handshake, validate_routing_request, TIMEOUT,
     },
 };
 use lib::types::core::{Identity, KernelMessage};
 use {
     anyhow::{anyhow, Result},
     futures::SinkExt,
-    tokio::net::{TcpListener, TcpStream},
+    tokio::net::TcpStream,
     tokio::{sync::mpsc, time},
     tokio_tungstenite::{
         accept_async, connect_async, tungstenite, MaybeTlsStream, WebSocketStream,
@@ -20,6 +21,7 @@ mod utils;
 
 pub struct PeerConnection {
     pub noise: snow::TransportState,
+    pub negotiated: Negotiated,
     pub buf: Vec<u8>,
     pub socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
 }
@@ -31,7 +33,7 @@ pub async fn receiver(ext: IdentityExt, data: NetData) -> Result<()> {
         .our
         .get_protocol_port(WS_PROTOCOL)
         .expect("ws port not found");
-    let ws = match TcpListener::bind(format!("0.0.0.0:{ws_port}")).await {
+    let ws = match crate::http::utils::bind_any(*ws_port) {
         Ok(ws) => ws,
         Err(_e) => {
             return Err(anyhow::anyhow!(
@@ -98,17 +100,17 @@ pub async fn init_direct(
     proxy_request: bool,
     peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
 ) -> Result<(), mpsc::UnboundedReceiver<KernelMessage>> {
-    match time::timeout(
-        TIMEOUT,
-        connect_with_handshake(ext, peer_id, port, None, proxy_request),
-    )
-    .await
-    {
+    let attempts = race(&hosts(ext, data, peer_id), |host| {
+        let (ext, peer_id) = (ext.clone(), peer_id.clone());
+        async move { connect_with_handshake(&ext, &peer_id, &host, port, None, proxy_request).await }
+    });
+    match time::timeout(TIMEOUT, attempts).await {
         Ok(Ok(connection)) => {
             // maintain direct connection
             tokio::spawn(utils::maintain_connection(
                 peer_id.name.clone(),
                 data.peers.clone(),
+                data.reputation.clone(),
                 connection,
                 peer_rx,
                 ext.kernel_message_tx.clone(),
@@ -139,17 +141,20 @@ pub async fn init_routed(
     router_port: u16,
     peer_rx: mpsc::UnboundedReceiver<KernelMessage>,
 ) -> Result<(), mpsc::UnboundedReceiver<KernelMessage>> {
-    match time::timeout(
-        TIMEOUT,
-        connect_with_handshake(ext, peer_id, router_port, Some(router_id), false),
-    )
-    .await
-    {
+    let attempts = race(&hosts(ext, data, router_id), |host| {
+        let (ext, peer_id, router_id) = (ext.clone(), peer_id.clone(), router_id.clone());
+        async move {
+            connect_with_handshake(&ext, &peer_id, &host, router_port, Some(&router_id), false)
+                .await
+        }
+    });
+    match time::timeout(TIMEOUT, attempts).await {
         Ok(Ok(connection)) => {
             // maintain direct connection
             tokio::spawn(utils::maintain_connection(
                 peer_id.name.clone(),
                 data.peers.clone(),
+                data.reputation.clone(),
                 connection,
                 peer_rx,
                 ext.kernel_message_tx.clone(),
@@ -194,12 +199,14 @@ pub async fn recv_via_router(
                     identity: peer_id.clone(),
                     routing_for: false,
                     sender: peer_tx,
+                    session: None,
                 },
             );
             // maintain direct connection
             tokio::spawn(utils::maintain_connection(
                 peer_id.name,
                 data.peers.clone(),
+                data.reputation.clone(),
                 connection,
                 peer_rx,
                 ext.kernel_message_tx,
@@ -218,15 +225,16 @@ async fn recv_connection(
     mut socket: WebSocket,
 ) -> anyhow::Result<()> {
     // before we begin XX handshake pattern, check first message over socket
-    let first_message = &utils::recv(&mut socket).await?;
+    let first_message = utils::recv(&mut socket).await?;
 
     // if the first message contains a "routing request",
     // we see if the target is someone we are actively routing for,
     // and create a Passthrough connection if so.
-    // a Noise 'e' message with have len 32
-    if first_message.len() != 32 {
+    // a Noise 'e' message with have len 32, and a peer that would negotiate
+    // a cipher suite sends its offer first
+    if first_message.len() != 32 && !cipher::is_offer(&first_message) {
         let (from_id, target_id) =
-            validate_routing_request(&ext.our.name, first_message, &data.pki)?;
+            validate_routing_request(&ext.our.name, &first_message, &data.pki)?;
         return create_passthrough(
             &ext.our,
             &ext.our_ip,
@@ -234,16 +242,20 @@ async fn recv_connection(
             target_id,
             &data.peers,
             &data.pending_passthroughs,
+            &data.router,
             PendingStream::WebSocket(socket),
         )
         .await;
     }
 
-    let (mut noise, our_static_key) = build_responder();
+    let (negotiated, first_message) =
+        utils::respond_to_offer(&ext.cipher, first_message, &mut socket).await?;
+
+    let (mut noise, our_static_key) = build_responder(&negotiated);
     let mut buf = vec![0u8; 65535];
 
     // <- e
-    noise.read_message(first_message, &mut buf)?;
+    noise.read_message(&first_message, &mut buf)?;
 
     // -> e, ee, s, es
     utils::send_protocol_handshake(
@@ -272,6 +284,13 @@ async fn recv_connection(
         &their_id,
     )?;
 
+    if data.reputation.is_banned(&their_id.name) {
+        return Err(anyhow!("{} is banned for misbehaving", their_id.name));
+    }
+    if their_handshake.proxy_request {
+        data.router.admit_routed(&their_id.name, &data.peers)?;
+    }
+
     let (peer_tx, peer_rx) = mpsc::unbounded_channel();
     data.peers.insert(
         their_id.name.clone(),
@@ -279,13 +298,16 @@ async fn recv_connection(
             identity: their_id.clone(),
             routing_for: their_handshake.proxy_request,
             sender: peer_tx,
+            session: None,
         },
     );
     tokio::spawn(utils::maintain_connection(
         their_handshake.name,
         data.peers,
+        data.reputation,
         PeerConnection {
             noise: noise.into_transport_mode()?,
+            negotiated,
             buf,
             socket,
         },
@@ -296,25 +318,34 @@ async fn recv_connection(
     Ok(())
 }
 
+/// connect offering our cipher suites, and again without an offer if the
+/// peer predates negotiation and we still accept the legacy suite
 async fn connect_with_handshake(
     ext: &IdentityExt,
     peer_id: &Identity,
+    host: &str,
     port: u16,
     use_router: Option<&Identity>,
     proxy_request: bool,
 ) -> anyhow::Result<PeerConnection> {
-    let mut buf = vec![0u8; 65535];
-    let (mut noise, our_static_key) = build_initiator();
-
-    let ip = match use_router {
-        None => peer_id
-            .get_ip()
-            .ok_or(anyhow!("target has no IP address"))?,
-        Some(router_id) => router_id
-            .get_ip()
-            .ok_or(anyhow!("router has no IP address"))?,
-    };
-    let ws_url = make_conn_url(&ext.our_ip, ip, &port, WS_PROTOCOL)?;
+    match handshake(ext, peer_id, host, port, use_router, proxy_request, true).await {
+        Err(e) if cipher::retry_legacy(&ext.cipher, &e) => {
+            handshake(ext, peer_id, host, port, use_router, proxy_request, false).await
+        }
+        result => result,
+    }
+}
+
+async fn handshake(
+    ext: &IdentityExt,
+    peer_id: &Identity,
+    host: &str,
+    port: u16,
+    use_router: Option<&Identity>,
+    proxy_request: bool,
+    offer: bool,
+) -> anyhow::Result<PeerConnection> {
+    let ws_url = make_conn_url(&ext.our_ip, host, &port, WS_PROTOCOL)?;
     let Ok((mut socket, _response)) = connect_async(ws_url).await else {
         return Err(anyhow!("failed to connect to target"));
     };
@@ -342,6 +373,11 @@ async fn connect_with_handshake(
             .await?;
     }
 
+    let negotiated = utils::offer_suites(&ext.cipher, offer, &mut socket).await?;
+
+    let mut buf = vec![0u8; 65535];
+    let (mut noise, our_static_key) = build_initiator(&negotiated);
+
     // -> e
     let len = noise.write_message(&[], &mut buf)?;
     socket
@@ -373,6 +409,7 @@ async fn connect_with_handshake(
 
     Ok(PeerConnection {
         noise: noise.into_transport_mode()?,
+        negotiated,
         buf,
         socket,
     })
@@ -404,11 +441,15 @@ async fn connect_with_handshake_via_router(
         )?))
         .await?;
 
+    let first_message = utils::recv(&mut socket).await?;
+    let (negotiated, first_message) =
+        utils::respond_to_offer(&ext.cipher, first_message, &mut socket).await?;
+
     let mut buf = vec![0u8; 65535];
-    let (mut noise, our_static_key) = build_responder();
+    let (mut noise, our_static_key) = build_responder(&negotiated);
 
     // <- e
-    noise.read_message(&utils::recv(&mut socket).await?, &mut buf)?;
+    noise.read_message(&first_message, &mut buf)?;
 
     // -> e, ee, s, es
     utils::send_protocol_handshake(
@@ -435,6 +476,7 @@ async fn connect_with_handshake_via_router(
 
     Ok(PeerConnection {
         noise: noise.into_transport_mode()?,
+        negotiated,
         buf,
         socket,
     })
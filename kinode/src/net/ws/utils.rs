@@ -1,4 +1,6 @@
 use crate::net::{
+    cipher::{self, CipherConfig, Negotiated, Rekeying, Session},
+    reputation::{is_malformed, Offense, Reputation, Standing},
     types::{HandshakePayload, IdentityExt, Peers},
     utils::{print_debug, print_loud, MESSAGE_MAX_SIZE},
     ws::{PeerConnection, WebSocket},
@@ -6,6 +8,7 @@ use crate::net::{
 use lib::core::{KernelMessage, MessageSender, NodeId, PrintSender};
 use {
     futures::{SinkExt, StreamExt},
+    std::sync::Arc,
     tokio::sync::mpsc::UnboundedReceiver,
     tokio_tungstenite::tungstenite,
 };
@@ -17,11 +20,18 @@ type WsReadHalf = futures::stream::SplitStream<WebSocket>;
 pub async fn maintain_connection(
     peer_name: NodeId,
     peers: Peers,
+    reputation: Arc<Reputation>,
     mut conn: PeerConnection,
     mut peer_rx: UnboundedReceiver<KernelMessage>,
     kernel_message_tx: MessageSender,
     print_tx: PrintSender,
 ) {
+    let session = Arc::new(Session::new(&conn.negotiated));
+    if let Some(mut peer) = peers.get_mut(&peer_name) {
+        peer.session = Some(session.clone());
+    }
+    let mut rekeying = Rekeying::new(&conn.negotiated);
+
     let (mut write_stream, mut read_stream) = conn.socket.split();
     let initiator = conn.noise.is_initiator();
     let snow::CipherStates(c1, c2) = conn.noise.extract_cipherstates();
@@ -35,10 +45,17 @@ pub async fn maintain_connection(
 
     let write_buf = &mut [0; 65536];
     let write_print_tx = print_tx.clone();
+    let write_session = session.clone();
     let write = async move {
         loop {
             tokio::select! {
                 Some(km) = peer_rx.recv() => {
+                    if rekeying.due() {
+                        let Ok(()) = send_rekey(&mut our_cipher, write_buf, &mut write_stream).await else {
+                            break;
+                        };
+                        write_session.rekeyed();
+                    }
                     if let Err(e) =
                         send_protocol_message(&km, &mut our_cipher, write_buf, &mut write_stream).await
                     {
@@ -75,7 +92,9 @@ pub async fn maintain_connection(
     let read_print_tx = print_tx.clone();
     let read = async move {
         loop {
-            match recv_protocol_message(&mut their_cipher, read_buf, &mut read_stream).await {
+            match recv_protocol_message(&mut their_cipher, read_buf, &mut read_stream, &session)
+                .await
+            {
                 Ok(km) => {
                     if km.source.node != read_peer_name {
                         print_loud(
@@ -83,15 +102,30 @@ pub async fn maintain_connection(
                             &format!("net: got message with spoofed source from {read_peer_name}!"),
                         )
                         .await;
+                        reputation.offense(&read_peer_name, Offense::Spoofed);
                         break;
-                    } else {
-                        kernel_message_tx
-                            .send(km)
-                            .await
-                            .expect("net: fatal: kernel receiver died");
                     }
+                    match reputation.received(&read_peer_name) {
+                        Standing::Good => {}
+                        Standing::Throttled(delay) => tokio::time::sleep(delay).await,
+                        Standing::Banned => {
+                            print_loud(
+                                &read_print_tx,
+                                &format!("net: dropping {read_peer_name}, banned for misbehaving"),
+                            )
+                            .await;
+                            break;
+                        }
+                    }
+                    kernel_message_tx
+                        .send(km)
+                        .await
+                        .expect("net: fatal: kernel receiver died");
                 }
                 Err(e) => {
+                    if is_malformed(&e) {
+                        reputation.offense(&read_peer_name, Offense::Malformed);
+                    }
                     print_debug(
                         &read_print_tx,
                         &format!("net: error receiving message: {e}"),
@@ -137,19 +171,42 @@ async fn send_protocol_message(
     Ok(())
 }
 
+/// tell the peer we are rekeying our direction of the connection, then do so.
+/// the signal is an empty message, encrypted so that only the peer can have
+/// sent it.
+async fn send_rekey(
+    cipher: &mut snow::CipherState,
+    buf: &mut [u8],
+    stream: &mut WsWriteHalf,
+) -> anyhow::Result<()> {
+    let len = cipher.encrypt(&0u32.to_be_bytes(), buf)?;
+    stream
+        .send(tungstenite::Message::binary(&buf[..len]))
+        .await?;
+    cipher.rekey();
+    Ok(())
+}
+
 /// any error in receiving a message will result in the connection being closed.
 async fn recv_protocol_message(
     cipher: &mut snow::CipherState,
     buf: &mut [u8],
     stream: &mut WsReadHalf,
+    session: &Session,
 ) -> anyhow::Result<KernelMessage> {
-    let outer_len = cipher.decrypt(&recv_read_only(stream).await?, buf)?;
-
-    if outer_len < 4 {
-        return Err(anyhow::anyhow!("protocol message too small!"));
-    }
-    let length_bytes = [buf[0], buf[1], buf[2], buf[3]];
-    let msg_len = u32::from_be_bytes(length_bytes);
+    let (outer_len, msg_len) = loop {
+        let outer_len = cipher.decrypt(&recv_read_only(stream).await?, buf)?;
+        if outer_len < 4 {
+            return Err(anyhow::anyhow!("protocol message too small!"));
+        }
+        let msg_len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        if msg_len != 0 {
+            break (outer_len, msg_len);
+        }
+        // the peer is rekeying its direction of the connection
+        cipher.rekey();
+        session.rekeyed();
+    };
     if msg_len > MESSAGE_MAX_SIZE {
         return Err(anyhow::anyhow!("message too large"));
     }
@@ -163,7 +220,11 @@ async fn recv_protocol_message(
         msg.extend_from_slice(&buf[..len]);
     }
 
-    Ok(rmp_serde::from_slice(&msg)?)
+    let mut km: KernelMessage = rmp_serde::from_slice(&msg)?;
+    // blob handles only refer to our own blob store, so never accept one from a peer
+    km.blob_handle = None;
+    km.sent_at = Some(std::time::Instant::now());
+    Ok(km)
 }
 
 pub async fn send_protocol_handshake(
@@ -198,6 +259,43 @@ pub async fn recv_protocol_handshake(
     Ok(rmp_serde::from_slice(&buf[..len])?)
 }
 
+/// as the initiator, before our Noise 'e' message, offer the cipher suites
+/// we accept and take up the responder's choice, unless `offer` is false or
+/// there is nothing to negotiate, when the handshake is legacy
+pub async fn offer_suites(
+    config: &CipherConfig,
+    offer: bool,
+    socket: &mut WebSocket,
+) -> anyhow::Result<Negotiated> {
+    let Some(offer) = cipher::offer(config).filter(|_| offer) else {
+        return Negotiated::legacy(config);
+    };
+    socket
+        .send(tungstenite::Message::binary(offer.clone()))
+        .await?;
+    let Ok(answer) = recv(socket).await else {
+        return Err(cipher::Unanswered.into());
+    };
+    cipher::accept(config, &offer, &answer)
+}
+
+/// as the responder, settle on a cipher suite given the peer's first message,
+/// which is either its Noise 'e' message or an offer to answer before it.
+/// returns what was settled on, and the 'e' message.
+pub async fn respond_to_offer(
+    config: &CipherConfig,
+    first_message: Vec<u8>,
+    socket: &mut WebSocket,
+) -> anyhow::Result<(Negotiated, Vec<u8>)> {
+    if !cipher::is_offer(&first_message) {
+        return Ok((Negotiated::legacy(config)?, first_message));
+    }
+    let (answer, negotiated) = cipher::answer(config, &first_message);
+    socket.send(tungstenite::Message::binary(answer)).await?;
+    let negotiated = negotiated?;
+    Ok((negotiated, recv(socket).await?))
+}
+
 /// Receive a byte array from a read stream. If this returns an error,
 /// we should close the connection.
 ///
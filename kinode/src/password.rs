@@ -0,0 +1,48 @@
+//! changing the node password, taken while the node is stopped: the keyfile
+//! is decrypted with the old password and encrypted again with the new one.
+//! the node's keys and file key are kept, so the node and its encrypted files
+//! are unchanged, but its JWT secret is replaced, so every login cookie, the
+//! owner's and every user's, stops working.
+//!
+//! a node whose password is lost is recovered from a backup: restoring it
+//! with `--restore` takes the password the backup was sealed with, and, if
+//! also given `--new-password`, sets a new one on the restored node. backups
+//! taken before a password change still open with the old password.
+use crate::keygen;
+use anyhow::{anyhow, Context, Result};
+use rocksdb::DB;
+use std::{fs, path::Path};
+
+/// change the password of the stopped node at `home_directory_path`,
+/// returning its name
+pub fn change_password(
+    home_directory_path: &str,
+    password: &str,
+    new_password: &str,
+) -> Result<String> {
+    let home = Path::new(home_directory_path);
+    let keyfile = fs::read(home.join(".keys")).context("no keyfile: is this a node's home?")?;
+    let keys = keygen::decode_keyfile(&keyfile, &keygen::password_hash(password))
+        .map_err(|e| anyhow!("wrong password: {e}"))?;
+    if new_password.is_empty() {
+        return Err(anyhow!("the new password must not be empty"));
+    }
+
+    // a running node would keep signing logins with the old JWT secret
+    drop(
+        DB::open_default(home.join("kernel"))
+            .context("couldn't open state DB: stop the node first")?,
+    );
+
+    let rekeyed = keygen::rekey_keyfile(
+        &keyfile,
+        &keygen::password_hash(password),
+        &keygen::password_hash(new_password),
+    )
+    .map_err(|e| anyhow!("{e}"))?;
+    // written aside and moved over, so a crash can't leave half a keyfile
+    let tmp = home.join(".keys.tmp");
+    fs::write(&tmp, rekeyed)?;
+    fs::rename(&tmp, home.join(".keys"))?;
+    Ok(keys.username)
+}
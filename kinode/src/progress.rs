@@ -0,0 +1,450 @@
+use lib::types::core::{
+    Address, CapMessage, CapMessageSender, Capability, KernelMessage, Message, MessageReceiver,
+    MessageSender, PrintSender, Printout, ProcessId, Progress, ProgressError, ProgressRequest,
+    ProgressResponse, ProgressUpdate, Request, Response, PROGRESS_PROCESS_ID,
+};
+use lib::types::errors::ModuleError;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::oneshot;
+
+/// an operation is shown in the terminal once it has run this long, so that
+/// quick ones don't clutter it
+const PRINT_AFTER: Duration = Duration::from_secs(1);
+/// and from then on at most this often, and once more when done
+const PRINT_EVERY: Duration = Duration::from_secs(5);
+/// an operation not reported on for this long is taken to be abandoned
+const STALE_AFTER: Duration = Duration::from_secs(10 * 60);
+const MAX_LABEL_LEN: usize = 256;
+const BAR_WIDTH: usize = 20;
+
+struct Operation {
+    update: ProgressUpdate,
+    started: Instant,
+    reported: Instant,
+    printed: Option<Instant>,
+}
+
+/// what the module keeps
+struct ProgressService {
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    send_to_terminal: PrintSender,
+    send_to_caps_oracle: CapMessageSender,
+    /// operations under way, by reporter and operation
+    operations: HashMap<(Address, String), Operation>,
+    /// processes sent reports on the operations they initiated
+    subscribers: HashSet<Address>,
+}
+
+/// The progress runtime module: runtime modules and processes report how far
+/// along their long-running operations are, which it prints to the terminal
+/// as progress bars, keeps for the homepage to show, and passes on to the
+/// processes that initiated them, if they subscribed.
+pub async fn progress(
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    send_to_terminal: PrintSender,
+    mut recv_from_loop: MessageReceiver,
+    send_to_caps_oracle: CapMessageSender,
+) -> anyhow::Result<()> {
+    let mut state = ProgressService {
+        our_node,
+        send_to_loop,
+        send_to_terminal,
+        send_to_caps_oracle,
+        operations: HashMap::new(),
+        subscribers: HashSet::new(),
+    };
+
+    while let Some(km) = recv_from_loop.recv().await {
+        crate::metrics::handled(&PROGRESS_PROCESS_ID, &km);
+        if let Message::Response(_) = km.message {
+            continue;
+        }
+        let id = km.id;
+        let target = km.rsvp.clone().or_else(|| match km.message {
+            Message::Request(Request {
+                expects_response: Some(_),
+                ..
+            }) => Some(km.source.clone()),
+            _ => None,
+        });
+        let (response, metadata) = match state.handle_request(km).await {
+            Ok(response) => (response, None),
+            Err(e) => {
+                let metadata = e.metadata();
+                (ProgressResponse::Err(e), metadata)
+            }
+        };
+        let Some(target) = target else {
+            continue;
+        };
+        KernelMessage::builder()
+            .id(id)
+            .source((state.our_node.as_str(), PROGRESS_PROCESS_ID.clone()))
+            .target(target)
+            .message(Message::Response((
+                Response {
+                    inherit: false,
+                    body: serde_json::to_vec(&response).unwrap(),
+                    metadata,
+                    capabilities: vec![],
+                },
+                None,
+            )))
+            .build()
+            .unwrap()
+            .send(&state.send_to_loop)
+            .await;
+    }
+    Err(anyhow::anyhow!("progress: loop channel closed"))
+}
+
+impl ProgressService {
+    async fn handle_request(
+        &mut self,
+        km: KernelMessage,
+    ) -> Result<ProgressResponse, ProgressError> {
+        let Message::Request(request) = km.message else {
+            unreachable!("responses are dropped before this");
+        };
+        if km.source.node != *self.our_node {
+            return Err(ProgressError::BadRequest {
+                error: "only processes on this node may report progress".into(),
+            });
+        }
+        let request: ProgressRequest =
+            serde_json::from_slice(&request.body).map_err(|e| ProgressError::BadRequest {
+                error: format!("didn't parse into ProgressRequest: {e}"),
+            })?;
+
+        match request {
+            ProgressRequest::Report(progress) => {
+                if progress.percent > 100 || progress.label.len() > MAX_LABEL_LEN {
+                    return Err(ProgressError::BadRequest {
+                        error: format!(
+                            "percent must be at most 100, label at most {MAX_LABEL_LEN} bytes"
+                        ),
+                    });
+                }
+                // reports go to the initiator's subscription, and show in the
+                // terminal as its own: only the runtime works for others
+                if progress.initiator != km.source && !is_runtime_module(&km.source.process) {
+                    return Err(ProgressError::BadRequest {
+                        error: "a process may only report on operations it initiated".into(),
+                    });
+                }
+                self.report(km.source, progress).await;
+                Ok(ProgressResponse::Ok)
+            }
+            ProgressRequest::Subscribe => {
+                self.subscribers.insert(km.source);
+                Ok(ProgressResponse::Ok)
+            }
+            ProgressRequest::Unsubscribe => {
+                self.subscribers.remove(&km.source);
+                Ok(ProgressResponse::Ok)
+            }
+            ProgressRequest::List => {
+                self.check_root(&km.source).await?;
+                self.operations
+                    .retain(|_, operation| operation.reported.elapsed() < STALE_AFTER);
+                let mut operations: Vec<&Operation> = self.operations.values().collect();
+                operations.sort_by_key(|operation| operation.started);
+                Ok(ProgressResponse::Operations(
+                    operations
+                        .into_iter()
+                        .map(|operation| operation.update.clone())
+                        .collect(),
+                ))
+            }
+        }
+    }
+
+    async fn report(&mut self, reporter: Address, progress: Progress) {
+        let now = Instant::now();
+        let key = (reporter.clone(), progress.operation.clone());
+        if !self.operations.contains_key(&key) {
+            self.operations
+                .retain(|_, operation| operation.reported.elapsed() < STALE_AFTER);
+        }
+        let update = ProgressUpdate {
+            reporter,
+            updated: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            progress,
+        };
+        let operation = self.operations.entry(key.clone()).or_insert(Operation {
+            update: update.clone(),
+            started: now,
+            reported: now,
+            printed: None,
+        });
+        operation.update = update.clone();
+        operation.reported = now;
+
+        let done = update.progress.percent == 100;
+        let print = match operation.printed {
+            None => !done && now - operation.started >= PRINT_AFTER,
+            Some(printed) => done || now - printed >= PRINT_EVERY,
+        };
+        if print {
+            operation.printed = Some(now);
+            Printout {
+                verbosity: 0,
+                content: bar(&update.progress),
+                source: Some(update.progress.initiator.process.clone()),
+                level: None,
+            }
+            .send(&self.send_to_terminal)
+            .await;
+        }
+        if done {
+            self.operations.remove(&key);
+        }
+
+        if self.subscribers.contains(&update.progress.initiator) {
+            KernelMessage::builder()
+                .id(rand::random())
+                .source((self.our_node.as_str(), PROGRESS_PROCESS_ID.clone()))
+                .target(update.progress.initiator.clone())
+                .message(Message::Request(Request {
+                    inherit: false,
+                    expects_response: None,
+                    body: serde_json::to_vec(&update).unwrap(),
+                    metadata: None,
+                    capabilities: vec![],
+                }))
+                .build()
+                .unwrap()
+                .send(&self.send_to_loop)
+                .await;
+        }
+    }
+
+    /// whether `source` holds the progress capability with params `{"root": true}`
+    async fn check_root(&self, source: &Address) -> Result<(), ProgressError> {
+        let (send_cap_bool, recv_cap_bool) = oneshot::channel();
+        let sent = self
+            .send_to_caps_oracle
+            .send(CapMessage::Has {
+                on: source.process.clone(),
+                cap: Capability::new(
+                    (self.our_node.as_str(), PROGRESS_PROCESS_ID.clone()),
+                    "{\"root\":true}",
+                ),
+                responder: send_cap_bool,
+            })
+            .await;
+        if sent.is_ok() && recv_cap_bool.await.unwrap_or(false) {
+            Ok(())
+        } else {
+            Err(ProgressError::NoCap)
+        }
+    }
+}
+
+/// whether `process` is one of the runtime's modules rather than a process
+fn is_runtime_module(process: &ProcessId) -> bool {
+    process.package() == "distro" && process.publisher() == "sys"
+}
+
+/// an operation as a line of the terminal: its label, a bar, and a percentage
+fn bar(progress: &Progress) -> String {
+    let filled = progress.percent as usize * BAR_WIDTH / 100;
+    format!(
+        "{} [\x1b[32m{}\x1b[39m{}] {:>3}%",
+        progress.label,
+        "#".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        progress.percent
+    )
+}
+
+/// One operation of a runtime module, reported to the progress module each
+/// time its percentage changes.
+pub struct Reporter {
+    our_node: String,
+    reporter: ProcessId,
+    send_to_loop: MessageSender,
+    progress: Progress,
+    reported: Option<u8>,
+}
+
+impl Reporter {
+    pub fn new(
+        our_node: &str,
+        reporter: ProcessId,
+        send_to_loop: &MessageSender,
+        operation: String,
+        initiator: Address,
+        label: String,
+    ) -> Self {
+        Self {
+            our_node: our_node.to_string(),
+            reporter,
+            send_to_loop: send_to_loop.clone(),
+            progress: Progress {
+                operation,
+                initiator,
+                percent: 0,
+                label,
+            },
+            reported: None,
+        }
+    }
+
+    /// report that `done` of `total` is done
+    pub async fn report(&mut self, done: u64, total: u64) {
+        let percent = if total == 0 {
+            100
+        } else {
+            (done.min(total) as u128 * 100 / total as u128) as u8
+        };
+        if self.reported == Some(percent) {
+            return;
+        }
+        self.reported = Some(percent);
+        self.progress.percent = percent;
+        KernelMessage::builder()
+            .id(rand::random())
+            .source((self.our_node.as_str(), self.reporter.clone()))
+            .target((self.our_node.as_str(), PROGRESS_PROCESS_ID.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: None,
+                body: serde_json::to_vec(&ProgressRequest::Report(self.progress.clone())).unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+            .send(&self.send_to_loop)
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::PrintReceiver;
+    use tokio::sync::mpsc;
+
+    fn service() -> (ProgressService, MessageReceiver, PrintReceiver) {
+        let (send_to_loop, recv_in_loop) = mpsc::channel(16);
+        let (send_to_terminal, recv_in_terminal) = mpsc::channel(16);
+        let (send_to_caps_oracle, _) = mpsc::channel(1);
+        let service = ProgressService {
+            our_node: Arc::new("node.os".to_string()),
+            send_to_loop,
+            send_to_terminal,
+            send_to_caps_oracle,
+            operations: HashMap::new(),
+            subscribers: HashSet::new(),
+        };
+        (service, recv_in_loop, recv_in_terminal)
+    }
+
+    fn address(process: &str) -> Address {
+        Address::new("node.os", process.parse::<ProcessId>().unwrap())
+    }
+
+    fn report(source: &Address, initiator: &Address, percent: u8) -> KernelMessage {
+        let progress = Progress {
+            operation: "op".into(),
+            initiator: initiator.clone(),
+            percent,
+            label: "copying".into(),
+        };
+        KernelMessage::builder()
+            .id(rand::random())
+            .source(source.clone())
+            .target(("node.os", PROGRESS_PROCESS_ID.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: None,
+                body: serde_json::to_vec(&ProgressRequest::Report(progress)).unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn processes_report_only_their_own_operations() {
+        let (mut service, _recv_in_loop, _recv_in_terminal) = service();
+        let app = address("app:app:sys");
+        let other = address("other:other:sys");
+        assert!(matches!(
+            service.handle_request(report(&app, &other, 10)).await,
+            Err(ProgressError::BadRequest { .. })
+        ));
+        assert!(service.operations.is_empty());
+        assert!(service.handle_request(report(&app, &app, 10)).await.is_ok());
+        // runtime modules report on operations processes asked them for
+        let vfs = address("vfs:distro:sys");
+        assert!(service
+            .handle_request(report(&vfs, &other, 10))
+            .await
+            .is_ok());
+        assert_eq!(service.operations.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn subscribed_initiator_is_sent_reports_until_done() {
+        let (mut service, mut recv_in_loop, _recv_in_terminal) = service();
+        let app = address("app:app:sys");
+        service.subscribers.insert(app.clone());
+        let vfs = address("vfs:distro:sys");
+        service
+            .handle_request(report(&vfs, &app, 40))
+            .await
+            .unwrap();
+        let km = recv_in_loop.recv().await.unwrap();
+        assert_eq!(km.target, app);
+        let Message::Request(request) = km.message else {
+            panic!("not a request");
+        };
+        let update: ProgressUpdate = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            (update.reporter, update.progress.percent),
+            (vfs.clone(), 40)
+        );
+
+        service
+            .handle_request(report(&vfs, &app, 100))
+            .await
+            .unwrap();
+        assert!(service.operations.is_empty());
+        assert!(service
+            .handle_request(report(&vfs, &app, 101))
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn bar_fills_with_percent() {
+        let progress = |percent| Progress {
+            operation: "op".into(),
+            initiator: address("app:app:sys"),
+            percent,
+            label: "copying".into(),
+        };
+        assert_eq!(
+            bar(&progress(50)),
+            format!(
+                "copying [\x1b[32m{}\x1b[39m{}]  50%",
+                "#".repeat(10),
+                " ".repeat(10)
+            )
+        );
+        assert!(bar(&progress(100)).ends_with("] 100%"));
+    }
+}
@@ -0,0 +1,186 @@
+//! the backup window. writes to state and the VFS each hold a [`write`]
+//! guard while under way; a backup holds the [`quiesce`] guard while it
+//! takes its snapshot of them, so that what it copies reflects one point in
+//! time. writes that start while a backup waits for those under way to finish
+//! wait behind it, so a steady stream of writes can't hold a backup off.
+//!
+//! the snapshot of the VFS drives is made of hard links, which are quick to
+//! make, and copied out once writes are let through again. the VFS writes to
+//! files in place, which would change a linked file under the backup, so a
+//! write to a file not yet copied out copies it first: see [`write_to`].
+//!
+//! some files can be written without the VFS knowing: those in a package
+//! directory mounted writable into a process, see [`mounted_writable`], and
+//! those linked to from elsewhere with `HardLink`, whose other paths the VFS
+//! doesn't know to copy out first. these are copied while writes are held
+//! off rather than linked.
+use std::{
+    collections::HashSet,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+lazy_static::lazy_static! {
+    static ref WRITES: RwLock<()> = RwLock::new(());
+    static ref STAGED: Mutex<Option<Staged>> = Mutex::new(None);
+    static ref MOUNTED_WRITABLE: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+/// note that the package directory at `path` is mounted writable into a
+/// process, which writes to it with neither [`write`] guard nor the VFS. kept
+/// until the node restarts, as processes can be restarted at any time.
+pub fn mounted_writable(path: &Path) {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    MOUNTED_WRITABLE.lock().unwrap().insert(path);
+}
+
+/// whether a file at `path` must be copied into a backup rather than linked
+fn must_copy(path: &Path, metadata: &std::fs::Metadata) -> bool {
+    metadata.nlink() > 1
+        || MOUNTED_WRITABLE
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|mounted| path.starts_with(mounted))
+}
+
+/// drives linked into a backup's staging directory, not yet copied out
+struct Staged {
+    /// the VFS directory the links were made from
+    vfs: PathBuf,
+    staging: PathBuf,
+    /// where the backup copies them to
+    copy_to: PathBuf,
+}
+
+/// wait out any backup under way, then hold it off until the guard is dropped
+pub async fn write() -> RwLockReadGuard<'static, ()> {
+    WRITES.read().await
+}
+
+/// as [`write`], for a VFS write to `paths`, files or directories: what of
+/// them a backup has staged but not yet copied out is copied out first
+pub async fn write_to(paths: Vec<PathBuf>) -> std::io::Result<RwLockReadGuard<'static, ()>> {
+    let guard = WRITES.read().await;
+    tokio::task::spawn_blocking(move || {
+        let staged = STAGED.lock().unwrap();
+        let Some(staged) = staged.as_ref() else {
+            return Ok(());
+        };
+        for path in paths {
+            let Ok(relative) = path.strip_prefix(&staged.vfs) else {
+                continue;
+            };
+            copy_out(staged, relative)?;
+        }
+        Ok(())
+    })
+    .await??;
+    Ok(guard)
+}
+
+/// wait for the writes under way to finish, then hold off new ones until
+/// the guard is dropped
+pub async fn quiesce() -> RwLockWriteGuard<'static, ()> {
+    WRITES.write().await
+}
+
+/// while holding the [`quiesce`] guard, link the `drives`, given relative to
+/// `vfs`, into `staging`, to be copied to `copy_to` by [`copy_staged`]. a
+/// file that can't be linked, as across filesystems, or that could be written
+/// around the VFS, is copied now.
+pub fn stage(
+    vfs: &Path,
+    drives: &[PathBuf],
+    staging: &Path,
+    copy_to: &Path,
+) -> std::io::Result<()> {
+    for drive in drives {
+        if vfs.join(drive).exists() {
+            link_dir(&vfs.join(drive), &staging.join(drive), &copy_to.join(drive))?;
+        }
+    }
+    *STAGED.lock().unwrap() = Some(Staged {
+        vfs: vfs.to_path_buf(),
+        staging: staging.to_path_buf(),
+        copy_to: copy_to.to_path_buf(),
+    });
+    Ok(())
+}
+
+/// copy out what [`stage`] linked, a file at a time so that writes to the
+/// VFS are only held up by the file they write, then remove the staging
+/// directory
+pub fn copy_staged() -> std::io::Result<()> {
+    let Some(staging) = STAGED.lock().unwrap().as_ref().map(|s| s.staging.clone()) else {
+        return Ok(());
+    };
+    let result = staged_files(&staging).and_then(|files| {
+        for file in files {
+            let staged = STAGED.lock().unwrap();
+            let Some(staged) = staged.as_ref() else {
+                break;
+            };
+            copy_out(staged, file.strip_prefix(&staging).unwrap())?;
+        }
+        Ok(())
+    });
+    *STAGED.lock().unwrap() = None;
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    result
+}
+
+/// copy the staged file or directory at `relative` to where the backup wants
+/// it, and unstage it. nothing to do if it was already copied out.
+fn copy_out(staged: &Staged, relative: &Path) -> std::io::Result<()> {
+    let from = staged.staging.join(relative);
+    if !from.exists() {
+        return Ok(());
+    }
+    for file in staged_files(&from)? {
+        let to = staged
+            .copy_to
+            .join(file.strip_prefix(&staged.staging).unwrap());
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&file, &to)?;
+        std::fs::remove_file(&file)?;
+    }
+    Ok(())
+}
+
+/// the files under `path`, or `path` itself if it is a file
+fn staged_files(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = vec![];
+    for entry in std::fs::read_dir(path)? {
+        files.extend(staged_files(&entry?.path())?);
+    }
+    Ok(files)
+}
+
+/// link the files under `from` into `to`, making each directory in `copy_to`
+/// too, so that empty ones are kept
+fn link_dir(from: &Path, to: &Path, copy_to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    std::fs::create_dir_all(copy_to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_dir() {
+            link_dir(&entry.path(), &to.join(&name), &copy_to.join(&name))?;
+        } else if must_copy(&entry.path(), &entry.metadata()?)
+            || std::fs::hard_link(entry.path(), to.join(&name)).is_err()
+        {
+            reflink_copy::reflink_or_copy(entry.path(), copy_to.join(&name))?;
+        }
+    }
+    Ok(())
+}
@@ -48,6 +48,11 @@ export const generateNetworkingKeys = async ({
 
     const ipAddress = ipToBytes(ip_address);
 
+    // other hosts the node was booted to advertise with --endpoints
+    const endpoints = (await fetch("/endpoints", { method: "GET" }).then(
+        (res) => res.json()
+    )) as string[];
+
     // why are we doing these? TODO
     setNetworkingKey(networking_key);
     // setIpAddress(ipAddress);
@@ -110,11 +115,22 @@ export const generateNetworkingKeys = async ({
                 )]
         });
 
+    const endpoints_call =
+        encodeFunctionData({
+            abi: kimapAbi,
+            functionName: 'note',
+            args: [
+                encodePacked(["bytes"], [stringToHex("~endpoints")]),
+                encodePacked(["bytes"], [stringToHex(endpoints.join(","))]),
+            ]
+        });
+
     const calls = direct ? [
         { target: KIMAP, callData: netkeycall },
         { target: KIMAP, callData: ws_port_call },
         { target: KIMAP, callData: tcp_port_call },
         { target: KIMAP, callData: ip_address_call },
+        ...(endpoints.length > 0 ? [{ target: KIMAP, callData: endpoints_call }] : []),
     ] : [
         { target: KIMAP, callData: netkeycall },
         { target: KIMAP, callData: router_call },
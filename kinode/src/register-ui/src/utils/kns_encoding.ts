@@ -1,9 +1,12 @@
 export function bytesToIp(bytes: Uint8Array): string {
+    if (bytes.length === 4) {
+        return Array.from(bytes).join('.');
+    }
     if (bytes.length !== 16) {
         throw new Error('Invalid byte length for IP address');
     }
 
-    const view = new DataView(bytes.buffer);
+    const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
     const ipNum = view.getBigUint64(0) * BigInt(2 ** 64) + view.getBigUint64(8);
 
     if (ipNum < BigInt(2 ** 32)) {
@@ -20,13 +23,25 @@ export function bytesToIp(bytes: Uint8Array): string {
 }
 
 export function ipToBytes(ip: string): Uint8Array {
+    // IPv6 literals may be written bracketed, as in a URL
+    ip = ip.replace(/^\[(.*)\]$/, '$1');
     if (ip.includes(':')) {
-        // IPv6: Create a 16-byte array
+        // IPv6: Create a 16-byte array, expanding a `::` run of zero groups
         const bytes = new Uint8Array(16);
         const view = new DataView(bytes.buffer);
-        const parts = ip.split(':');
+        const [head, tail] = ip.split('::');
+        const headParts = head ? head.split(':') : [];
+        const tailParts = tail ? tail.split(':') : [];
+        if (tail === undefined && headParts.length !== 8) {
+            throw new Error('Invalid IPv6 address');
+        }
+        const zeros = 8 - headParts.length - tailParts.length;
+        if (zeros < 0) {
+            throw new Error('Invalid IPv6 address');
+        }
+        const parts = [...headParts, ...Array(zeros).fill('0'), ...tailParts];
         for (let i = 0; i < 8; i++) {
-            view.setUint16(i * 2, parseInt(parts[i] || '0', 16));
+            view.setUint16(i * 2, parseInt(parts[i], 16));
         }
         return bytes;
     } else {
@@ -10,7 +10,7 @@ use alloy_sol_types::{eip712_domain, SolCall, SolStruct};
 use base64::{engine::general_purpose::STANDARD as base64_standard, Engine};
 use lib::types::core::{
     BootInfo, Identity, ImportKeyfileInfo, Keyfile, LoginAndResetInfo, LoginInfo, NodeRouting,
-    UnencryptedIdentity,
+    PrintSender, UnencryptedIdentity,
 };
 use ring::{rand::SystemRandom, signature, signature::KeyPair};
 use std::{
@@ -42,6 +42,8 @@ pub async fn register(
     keyfile: Option<Vec<u8>>,
     maybe_rpc: Option<String>,
     detached: bool,
+    endpoints: Vec<String>,
+    print_tx: PrintSender,
 ) {
     // Networking info is generated and passed to the UI, but not used until confirmed
     let (public_key, serialized_networking_keypair) = keygen::generate_networking_key();
@@ -93,6 +95,7 @@ pub async fn register(
     let ip = warp::any().map(move || ip.clone());
     let ws_port = warp::any().map(move || (ws_port, ws_flag_used));
     let tcp_port = warp::any().map(move || (tcp_port, tcp_flag_used));
+    let endpoints = Arc::new(endpoints);
 
     let static_files =
         warp::path("assets").and(static_dir::static_dir!("src/register-ui/build/assets/"));
@@ -149,6 +152,9 @@ pub async fn register(
                 warp::reply::html(String::new())
             },
         ))
+        .or(warp::path("endpoints")
+            .and(warp::get())
+            .map(move || warp::reply::json(endpoints.as_ref())))
         .or(warp::path("generate-networking-info").and(
             warp::post()
                 .and(our_temp_id.clone())
@@ -226,11 +232,14 @@ pub async fn register(
     if !detached {
         let _ = open::that(format!("http://localhost:{}/", http_port));
     }
+    let listener = crate::http::utils::bind_any(http_port).expect("register: couldn't bind port");
     warp::serve(routes)
-        .bind_with_graceful_shutdown(([0, 0, 0, 0], http_port), async {
-            kill_rx.await.ok();
-        })
-        .1
+        .serve_incoming_with_graceful_shutdown(
+            crate::http::utils::incoming(listener, print_tx),
+            async {
+                kill_rx.await.ok();
+            },
+        )
         .await;
 }
 
@@ -401,6 +410,7 @@ async fn handle_boot(
                         networking_keypair.as_ref(),
                     )
                     .unwrap(),
+                    sealing_key: keygen::sealing_key(networking_keypair.as_ref()).unwrap(),
                     jwt_secret_bytes: jwt_secret.to_vec(),
                     file_key: keygen::generate_file_key(),
                 };
@@ -602,6 +612,7 @@ async fn confirm_change_network_keys(
         routers: our.routers().unwrap_or(&vec![]).clone(),
         networking_keypair: signature::Ed25519KeyPair::from_pkcs8(networking_keypair.as_ref())
             .unwrap(),
+        sealing_key: keygen::sealing_key(networking_keypair.as_ref()).unwrap(),
         jwt_secret_bytes: old_decoded_keyfile.jwt_secret_bytes,
         file_key: old_decoded_keyfile.file_key,
     };
@@ -733,16 +744,17 @@ async fn success_response(
     encoded_keyfile: Vec<u8>,
 ) -> Result<warp::reply::Response, Rejection> {
     let encoded_keyfile_str = base64_standard.encode(&encoded_keyfile);
-    let token = match keygen::generate_jwt(&decoded_keyfile.jwt_secret_bytes, &our.name, &None) {
-        Some(token) => token,
-        None => {
-            return Ok(warp::reply::with_status(
-                warp::reply::json(&"Failed to generate JWT"),
-                StatusCode::SERVICE_UNAVAILABLE,
-            )
-            .into_response())
-        }
-    };
+    let token =
+        match keygen::generate_jwt(&decoded_keyfile.jwt_secret_bytes, &our.name, &None, None) {
+            Some(token) => token,
+            None => {
+                return Ok(warp::reply::with_status(
+                    warp::reply::json(&"Failed to generate JWT"),
+                    StatusCode::SERVICE_UNAVAILABLE,
+                )
+                .into_response())
+            }
+        };
 
     sender
         .send((our.clone(), decoded_keyfile, encoded_keyfile))
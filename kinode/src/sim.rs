@@ -0,0 +1,184 @@
+//! simulation-mode deterministic scheduling.
+//!
+//! booting a fake node with `--sim-seed <SEED>` makes the kernel pick the
+//! next message to deliver from all those waiting, using a random number
+//! generator seeded with SEED, rather than taking them first-come
+//! first-served. re-running a test with the same seed replays the same
+//! delivery order, so a race between processes that shows up once can be
+//! reproduced and debugged.
+//!
+//! booting with `--virtual-time` detaches the timer module from the wall
+//! clock: timers only pop when a test harness advances virtual time with
+//! `TimerAction::AdvanceTime`. processes that read the system clock directly
+//! are not affected.
+use lib::types::core as t;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::watch;
+
+static SEED: OnceLock<u64> = OnceLock::new();
+static VIRTUAL_TIME: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    /// milliseconds of virtual time elapsed, as advanced by the timer module
+    static ref VIRTUAL_NOW: watch::Sender<u64> = watch::Sender::new(0);
+}
+
+/// how many times to yield to other tasks before choosing a message, so that
+/// messages sent at the same moment are all waiting when the choice is made
+const SETTLE_YIELDS: usize = 8;
+
+/// configure the scheduler. must be called before the kernel and timer start.
+pub fn init(seed: Option<u64>, virtual_time: bool) {
+    if let Some(seed) = seed {
+        SEED.set(seed).ok();
+    }
+    VIRTUAL_TIME.store(virtual_time, Ordering::Relaxed);
+}
+
+/// whether timers are driven by virtual time instead of the wall clock
+pub fn virtual_time() -> bool {
+    VIRTUAL_TIME.load(Ordering::Relaxed)
+}
+
+/// move virtual time on to `now` milliseconds, waking what [`sleep`]s on it
+pub fn advance_to(now: u64) {
+    VIRTUAL_NOW.send_replace(now);
+}
+
+/// sleep for `duration`: of virtual time if timers are driven by it, so that
+/// the kernel's own timeouts keep step with a test harness, and otherwise of
+/// the wall clock
+pub async fn sleep(duration: Duration) {
+    if !virtual_time() {
+        return tokio::time::sleep(duration).await;
+    }
+    let mut now = VIRTUAL_NOW.subscribe();
+    let until = now
+        .borrow()
+        .saturating_add(duration.as_millis().try_into().unwrap_or(u64::MAX));
+    // the sender is static, so this never errors
+    let _ = now.wait_for(|now| *now >= until).await;
+}
+
+/// a generator for a process's random bytes, if booted with a seed
+pub fn process_rng(process: &t::ProcessId) -> Option<StdRng> {
+    use sha2::{Digest, Sha256};
+    let seed = SEED.get()?;
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_be_bytes());
+    hasher.update(process.to_string().as_bytes());
+    Some(StdRng::from_seed(hasher.finalize().into()))
+}
+
+/// the kernel's message queue, delivering waiting messages in an order
+/// chosen by the seed. without a seed, messages are delivered in the order
+/// they arrive, as normal. either way, messages from one process to another
+/// are delivered in the order they were sent, as they are without a seed:
+/// the seed only chooses which sender and target goes next.
+pub struct Scheduled {
+    receiver: t::MessageReceiver,
+    rng: Option<StdRng>,
+    /// messages waiting, by sender and target, in the order each first had
+    /// one waiting, so that the choice made from them depends on the seed
+    /// alone
+    pending: Vec<((t::Address, t::Address), VecDeque<t::KernelMessage>)>,
+}
+
+impl Scheduled {
+    pub fn new(receiver: t::MessageReceiver) -> Self {
+        Self {
+            receiver,
+            rng: SEED.get().map(|seed| StdRng::seed_from_u64(*seed)),
+            pending: vec![],
+        }
+    }
+
+    /// receive the next message. cancel-safe: a message received but not
+    /// yet delivered is held until the next call.
+    pub async fn recv(&mut self) -> Option<t::KernelMessage> {
+        if self.rng.is_none() {
+            return self.receiver.recv().await;
+        }
+        if self.pending.is_empty() {
+            let first = self.receiver.recv().await?;
+            self.hold(first);
+            for _ in 0..SETTLE_YIELDS {
+                tokio::task::yield_now().await;
+            }
+        }
+        while let Ok(km) = self.receiver.try_recv() {
+            self.hold(km);
+        }
+        let rng = self.rng.as_mut()?;
+        let index = rng.gen_range(0..self.pending.len());
+        let km = self.pending[index].1.pop_front();
+        if self.pending[index].1.is_empty() {
+            self.pending.remove(index);
+        }
+        km
+    }
+
+    fn hold(&mut self, km: t::KernelMessage) {
+        let stream = (km.source.clone(), km.target.clone());
+        match self.pending.iter_mut().find(|(key, _)| *key == stream) {
+            Some((_, queue)) => queue.push_back(km),
+            None => self.pending.push((stream, VecDeque::from([km]))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(from: &str, n: u64) -> t::KernelMessage {
+        t::KernelMessage::builder()
+            .id(n)
+            .source(("node.os", from.parse::<t::ProcessId>().unwrap()))
+            .target(("node.os", "chat:chat:sys".parse::<t::ProcessId>().unwrap()))
+            .message(t::Message::Request(t::Request {
+                inherit: false,
+                expects_response: None,
+                body: vec![],
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn each_sender_is_delivered_in_order() {
+        let (send, receiver) = tokio::sync::mpsc::channel(64);
+        let mut scheduled = Scheduled {
+            receiver,
+            rng: Some(StdRng::seed_from_u64(7)),
+            pending: vec![],
+        };
+        for n in 0..20 {
+            let from = if n % 2 == 0 {
+                "a:chat:sys"
+            } else {
+                "b:chat:sys"
+            };
+            send.send(message(from, n)).await.unwrap();
+        }
+        let mut delivered = vec![];
+        for _ in 0..20 {
+            delivered.push(scheduled.recv().await.unwrap());
+        }
+        for from in ["a:chat:sys", "b:chat:sys"] {
+            let ids: Vec<u64> = delivered
+                .iter()
+                .filter(|km| km.source.process.to_string() == from)
+                .map(|km| km.id)
+                .collect();
+            assert_eq!(ids.len(), 10);
+            assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+    }
+}
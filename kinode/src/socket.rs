@@ -0,0 +1,788 @@
+use dashmap::DashMap;
+use lib::types::core::{
+    Address, CapMessage, CapMessageSender, Capability, KernelMessage, LazyLoadBlob, LogLevel,
+    Message, MessageReceiver, MessageSender, PrintSender, Printout, ProcessId, Request, Response,
+    SocketAction, SocketError, SocketInfo, SocketProtocol, SocketRequest, SocketResponse,
+    SOCKET_PROCESS_ID,
+};
+use lib::types::errors::ModuleError;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf},
+    net::{TcpStream, UdpSocket},
+    sync::{mpsc, oneshot, Mutex},
+};
+use tokio_rustls::{
+    rustls::{self, pki_types::ServerName},
+    TlsConnector,
+};
+
+/// sockets a process may have open at once, unless set otherwise
+const DEFAULT_MAX_SOCKETS: u32 = 16;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// how long a process has to respond to a [`SocketRequest::Data`] before its
+/// socket is closed
+const READ_TIMEOUT: u64 = 60;
+/// most bytes sent to a process in one [`SocketRequest::Data`]; a UDP
+/// datagram can be no larger
+const READ_BUFFER: usize = 64 * 1024;
+
+lazy_static::lazy_static! {
+    static ref EXITED: std::sync::Mutex<Option<mpsc::UnboundedSender<ProcessId>>> =
+        std::sync::Mutex::new(None);
+}
+
+/// close the sockets of a process that has ended or been killed: it can't
+/// know of them when next run. called by the kernel.
+pub fn process_exited(process: &ProcessId) {
+    if let Some(exited) = EXITED.lock().unwrap().as_ref() {
+        let _ = exited.send(process.clone());
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Stream for T {}
+
+enum Reader {
+    Stream(ReadHalf<Box<dyn Stream>>),
+    Datagram(Arc<UdpSocket>),
+}
+
+enum Writer {
+    Stream(WriteHalf<Box<dyn Stream>>),
+    Datagram(Arc<UdpSocket>),
+}
+
+struct Socket {
+    /// tells this socket from one that later takes its channel_id
+    key: u64,
+    info: SocketInfo,
+    writer: Arc<Mutex<Writer>>,
+    reader: tokio::task::AbortHandle,
+}
+
+/// what every task of the module shares
+struct SocketService {
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    send_to_caps_oracle: CapMessageSender,
+    socket_path: PathBuf,
+    sockets: DashMap<(ProcessId, u32), Socket>,
+    /// the sockets being connected, each holding a slot against its
+    /// process's limit, with how many connects to the channel are underway
+    connecting: std::sync::Mutex<HashMap<(ProcessId, u32), u32>>,
+    /// data sent to processes, by message ID, waiting for a response
+    acks: DashMap<u64, oneshot::Sender<()>>,
+    /// as persisted in `{home}/socket/limits.json`; processes not here get the default
+    limits: Mutex<HashMap<ProcessId, u32>>,
+    /// times each process has exited, so that a socket it was connecting
+    /// when it did is not kept
+    exits: DashMap<ProcessId, u64>,
+    tls: TlsConnector,
+}
+
+/// The socket runtime module: opens outbound TCP connections and UDP
+/// sockets for processes, and passes what is read from them on, each read
+/// only once the process has taken the last.
+pub async fn socket(
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    send_to_terminal: PrintSender,
+    mut recv_from_loop: MessageReceiver,
+    send_to_caps_oracle: CapMessageSender,
+    home_directory_path: String,
+) -> anyhow::Result<()> {
+    let socket_path = PathBuf::from(format!("{home_directory_path}/socket"));
+    fs::create_dir_all(&socket_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed creating socket dir! {e:?}"))?;
+    let limits: HashMap<ProcessId, u32> = match fs::read(socket_path.join("limits.json")).await {
+        Ok(bytes) => match serde_json::from_slice::<Vec<(ProcessId, u32)>>(&bytes) {
+            Ok(limits) => limits.into_iter().collect(),
+            Err(e) => {
+                Printout::log(
+                    SOCKET_PROCESS_ID.clone(),
+                    LogLevel::Error,
+                    format!("socket: couldn't read the limits kept, using defaults: {e}"),
+                )
+                .send(&send_to_terminal)
+                .await;
+                HashMap::new()
+            }
+        },
+        Err(_) => HashMap::new(),
+    };
+
+    let state = Arc::new(SocketService::new(
+        our_node,
+        send_to_loop,
+        send_to_caps_oracle,
+        socket_path,
+        limits,
+    ));
+    let (exited, mut recv_exited) = mpsc::unbounded_channel();
+    *EXITED.lock().unwrap() = Some(exited);
+
+    loop {
+        let km = tokio::select! {
+            Some(process) = recv_exited.recv() => {
+                state.close_all(&process);
+                continue;
+            }
+            km = recv_from_loop.recv() => match km {
+                Some(km) => km,
+                None => break,
+            },
+        };
+        crate::metrics::handled(&SOCKET_PROCESS_ID, &km);
+        if let Message::Response(_) = km.message {
+            if let Some((_, ack)) = state.acks.remove(&km.id) {
+                let _ = ack.send(());
+            }
+            continue;
+        }
+        let state = state.clone();
+        tokio::spawn(async move {
+            let id = km.id;
+            let target = km.rsvp.clone().or_else(|| match km.message {
+                Message::Request(Request {
+                    expects_response: Some(_),
+                    ..
+                }) => Some(km.source.clone()),
+                _ => None,
+            });
+            let (response, metadata) = match state.clone().handle_request(km).await {
+                Ok(response) => (response, None),
+                Err(e) => {
+                    let metadata = e.metadata();
+                    (SocketResponse::Err(e), metadata)
+                }
+            };
+            let Some(target) = target else {
+                return;
+            };
+            KernelMessage::builder()
+                .id(id)
+                .source((state.our_node.as_str(), SOCKET_PROCESS_ID.clone()))
+                .target(target)
+                .message(Message::Response((
+                    Response {
+                        inherit: false,
+                        body: serde_json::to_vec(&response).unwrap(),
+                        metadata,
+                        capabilities: vec![],
+                    },
+                    None,
+                )))
+                .build()
+                .unwrap()
+                .send(&state.send_to_loop)
+                .await;
+        });
+    }
+    Err(anyhow::anyhow!("socket: loop channel closed"))
+}
+
+/// a slot held by a socket being connected; see [`SocketService::reserve`]
+struct Reservation<'a> {
+    service: &'a SocketService,
+    key: (ProcessId, u32),
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut connecting = self.service.connecting.lock().unwrap();
+        if let Some(count) = connecting.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                connecting.remove(&self.key);
+            }
+        }
+    }
+}
+
+impl SocketService {
+    fn new(
+        our_node: Arc<String>,
+        send_to_loop: MessageSender,
+        send_to_caps_oracle: CapMessageSender,
+        socket_path: PathBuf,
+        limits: HashMap<ProcessId, u32>,
+    ) -> Self {
+        let roots =
+            rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("socket: ring supports the default TLS versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        SocketService {
+            our_node,
+            send_to_loop,
+            send_to_caps_oracle,
+            socket_path,
+            sockets: DashMap::new(),
+            connecting: std::sync::Mutex::new(HashMap::new()),
+            acks: DashMap::new(),
+            limits: Mutex::new(limits),
+            exits: DashMap::new(),
+            tls: TlsConnector::from(Arc::new(tls)),
+        }
+    }
+
+    async fn handle_request(
+        self: Arc<Self>,
+        km: KernelMessage,
+    ) -> Result<SocketResponse, SocketError> {
+        let Message::Request(request) = km.message else {
+            unreachable!("responses are dropped before this");
+        };
+        if km.source.node != *self.our_node {
+            return Err(SocketError::BadRequest {
+                error: "only processes on this node may open sockets".into(),
+            });
+        }
+        let action: SocketAction =
+            serde_json::from_slice(&request.body).map_err(|e| SocketError::BadRequest {
+                error: format!("didn't parse into SocketAction: {e}"),
+            })?;
+        let process = km.source.process;
+
+        match action {
+            SocketAction::TcpConnect {
+                channel_id,
+                host,
+                port,
+                tls,
+            } => {
+                let _reserved = self.reserve(&process, channel_id).await?;
+                let exits = self.exits(&process);
+                let stream = tokio::time::timeout(
+                    CONNECT_TIMEOUT,
+                    TcpStream::connect((host.as_str(), port)),
+                )
+                .await
+                .map_err(|_| connect_failed("timed out"))?
+                .map_err(|e| connect_failed(&e.to_string()))?;
+                let local_addr = stream.local_addr()?.to_string();
+                let peer_addr = stream.peer_addr()?.to_string();
+                let stream: Box<dyn Stream> = if tls {
+                    let server_name = ServerName::try_from(host.clone())
+                        .map_err(|e| connect_failed(&e.to_string()))?;
+                    let stream = tokio::time::timeout(
+                        CONNECT_TIMEOUT,
+                        self.tls.connect(server_name, stream),
+                    )
+                    .await
+                    .map_err(|_| connect_failed("TLS handshake timed out"))?
+                    .map_err(|e| connect_failed(&e.to_string()))?;
+                    Box::new(stream)
+                } else {
+                    Box::new(stream)
+                };
+                let (reader, writer) = tokio::io::split(stream);
+                let protocol = if tls {
+                    SocketProtocol::Tls
+                } else {
+                    SocketProtocol::Tcp
+                };
+                self.open(
+                    process,
+                    exits,
+                    channel_id,
+                    protocol,
+                    peer_addr.clone(),
+                    Reader::Stream(reader),
+                    Writer::Stream(writer),
+                )?;
+                Ok(SocketResponse::Opened {
+                    local_addr,
+                    peer_addr,
+                })
+            }
+            SocketAction::UdpConnect {
+                channel_id,
+                host,
+                port,
+            } => {
+                let _reserved = self.reserve(&process, channel_id).await?;
+                let exits = self.exits(&process);
+                let peer = tokio::net::lookup_host((host.as_str(), port))
+                    .await
+                    .map_err(|e| connect_failed(&e.to_string()))?
+                    .next()
+                    .ok_or_else(|| connect_failed(&format!("{host} has no address")))?;
+                let local = if peer.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(local).await?;
+                socket
+                    .connect(peer)
+                    .await
+                    .map_err(|e| connect_failed(&e.to_string()))?;
+                let local_addr = socket.local_addr()?.to_string();
+                let socket = Arc::new(socket);
+                self.open(
+                    process,
+                    exits,
+                    channel_id,
+                    SocketProtocol::Udp,
+                    peer.to_string(),
+                    Reader::Datagram(socket.clone()),
+                    Writer::Datagram(socket),
+                )?;
+                Ok(SocketResponse::Opened {
+                    local_addr,
+                    peer_addr: peer.to_string(),
+                })
+            }
+            SocketAction::Write { channel_id } => {
+                let bytes = km.lazy_load_blob.map(|blob| blob.bytes).unwrap_or_default();
+                let writer = self
+                    .sockets
+                    .get(&(process.clone(), channel_id))
+                    .map(|socket| socket.writer.clone())
+                    .ok_or(SocketError::NoSocket { channel_id })?;
+                match &mut *writer.lock().await {
+                    Writer::Stream(writer) => {
+                        writer.write_all(&bytes).await?;
+                        writer.flush().await?;
+                    }
+                    Writer::Datagram(socket) => {
+                        socket.send(&bytes).await?;
+                    }
+                }
+                if let Some(mut socket) = self.sockets.get_mut(&(process, channel_id)) {
+                    socket.info.bytes_written += bytes.len() as u64;
+                }
+                Ok(SocketResponse::Ok)
+            }
+            SocketAction::Close { channel_id } => {
+                let (_, socket) = self
+                    .sockets
+                    .remove(&(process, channel_id))
+                    .ok_or(SocketError::NoSocket { channel_id })?;
+                close(socket);
+                Ok(SocketResponse::Ok)
+            }
+            SocketAction::List => {
+                let mut sockets: Vec<SocketInfo> = self
+                    .sockets
+                    .iter()
+                    .filter(|socket| socket.key().0 == process)
+                    .map(|socket| socket.info.clone())
+                    .collect();
+                sockets.sort_by_key(|info| info.channel_id);
+                Ok(SocketResponse::Sockets(sockets))
+            }
+            SocketAction::SetLimit {
+                process,
+                max_sockets,
+            } => {
+                self.check_root(&km.source).await?;
+                let mut limits = self.limits.lock().await;
+                match max_sockets {
+                    None => limits.remove(&process),
+                    Some(max_sockets) => limits.insert(process, max_sockets),
+                };
+                fs::write(
+                    self.socket_path.join("limits.json"),
+                    serde_json::to_vec(&limits.iter().collect::<Vec<_>>()).unwrap(),
+                )
+                .await?;
+                Ok(SocketResponse::Ok)
+            }
+        }
+    }
+
+    /// hold a slot against `process`'s limit while it connects a socket as
+    /// `channel_id`, if it may open another: those it has open and those it
+    /// is connecting all count, once per channel. the slot is freed when the
+    /// returned [`Reservation`] is dropped, by when the socket is kept.
+    async fn reserve(
+        &self,
+        process: &ProcessId,
+        channel_id: u32,
+    ) -> Result<Reservation<'_>, SocketError> {
+        let max_sockets = self
+            .limits
+            .lock()
+            .await
+            .get(process)
+            .copied()
+            .unwrap_or(DEFAULT_MAX_SOCKETS);
+        let mut connecting = self.connecting.lock().unwrap();
+        let mut channels: HashSet<u32> = self
+            .sockets
+            .iter()
+            .filter(|socket| &socket.key().0 == process)
+            .map(|socket| socket.key().1)
+            .collect();
+        channels.extend(
+            connecting
+                .keys()
+                .filter(|(connecting, _)| connecting == process)
+                .map(|(_, channel_id)| *channel_id),
+        );
+        channels.remove(&channel_id);
+        if channels.len() as u32 >= max_sockets {
+            return Err(SocketError::LimitReached { max_sockets });
+        }
+        let key = (process.clone(), channel_id);
+        *connecting.entry(key.clone()).or_insert(0) += 1;
+        Ok(Reservation { service: self, key })
+    }
+
+    /// how many times `process` has exited
+    fn exits(&self, process: &ProcessId) -> u64 {
+        self.exits.get(process).map_or(0, |exits| *exits)
+    }
+
+    /// close every socket of `process`, which has exited
+    fn close_all(&self, process: &ProcessId) {
+        // only a process connecting a socket needs its exits counted: one
+        // that starts connecting after this counts from here
+        let connecting = self
+            .connecting
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|(connecting, _)| connecting == process);
+        if connecting {
+            *self.exits.entry(process.clone()).or_insert(0) += 1;
+        } else {
+            self.exits.remove(process);
+        }
+        let channels: Vec<(ProcessId, u32)> = self
+            .sockets
+            .iter()
+            .filter(|socket| &socket.key().0 == process)
+            .map(|socket| socket.key().clone())
+            .collect();
+        for channel in channels {
+            if let Some((_, socket)) = self.sockets.remove(&channel) {
+                close(socket);
+            }
+        }
+    }
+
+    /// keep a socket connected for `process`, unless it has exited since it
+    /// began connecting, having exited `exits` times before
+    #[allow(clippy::too_many_arguments)]
+    fn open(
+        self: &Arc<Self>,
+        process: ProcessId,
+        exits: u64,
+        channel_id: u32,
+        protocol: SocketProtocol,
+        peer_addr: String,
+        reader: Reader,
+        writer: Writer,
+    ) -> Result<(), SocketError> {
+        // holding the entry keeps the process from being marked exited until
+        // the socket is kept, and so closed with the rest
+        let exited = self.exits.entry(process.clone()).or_insert(0);
+        if *exited != exits {
+            return Err(connect_failed("the process exited while connecting"));
+        }
+        let key = rand::random();
+        // the reader waits for the socket to be kept, so that it can't end
+        // before it is and leave it kept
+        let (kept, recv_kept) = oneshot::channel();
+        let state = self.clone();
+        let task_process = process.clone();
+        let reader = tokio::spawn(async move {
+            if recv_kept.await.is_ok() {
+                state.read(task_process, channel_id, key, reader).await;
+            }
+        });
+        let replaced = self.sockets.insert(
+            (process, channel_id),
+            Socket {
+                key,
+                info: SocketInfo {
+                    channel_id,
+                    protocol,
+                    peer_addr,
+                    opened: crate::clock::now(),
+                    bytes_read: 0,
+                    bytes_written: 0,
+                },
+                writer: Arc::new(Mutex::new(writer)),
+                reader: reader.abort_handle(),
+            },
+        );
+        drop(exited);
+        let _ = kept.send(());
+        if let Some(replaced) = replaced {
+            close(replaced);
+        }
+        Ok(())
+    }
+
+    /// pass what is read from a socket on to its process, until either closes it
+    async fn read(
+        self: Arc<Self>,
+        process: ProcessId,
+        channel_id: u32,
+        key: u64,
+        mut reader: Reader,
+    ) {
+        let mut buffer = vec![0u8; READ_BUFFER];
+        let error = loop {
+            let read = match &mut reader {
+                Reader::Stream(stream) => stream.read(&mut buffer).await,
+                Reader::Datagram(socket) => socket.recv(&mut buffer).await,
+            };
+            let read = match read {
+                Ok(0) if matches!(reader, Reader::Stream(_)) => break None,
+                Ok(read) => read,
+                Err(e) => break Some(e.to_string()),
+            };
+            if let Some(mut socket) = self
+                .sockets
+                .get_mut(&(process.clone(), channel_id))
+                .filter(|socket| socket.key == key)
+            {
+                socket.info.bytes_read += read as u64;
+            }
+            if !self
+                .send_data(&process, channel_id, buffer[..read].to_vec())
+                .await
+            {
+                break Some("process stopped taking data".to_string());
+            }
+        };
+        if self
+            .sockets
+            .remove_if(&(process.clone(), channel_id), |_, socket| {
+                socket.key == key
+            })
+            .is_some()
+        {
+            let request = SocketRequest::Closed { channel_id, error };
+            self.send(rand::random(), &process, request, vec![], None)
+                .await;
+        }
+    }
+
+    /// whether the process responded to the data in time
+    async fn send_data(&self, process: &ProcessId, channel_id: u32, bytes: Vec<u8>) -> bool {
+        let id: u64 = rand::random();
+        let (send_ack, recv_ack) = oneshot::channel();
+        self.acks.insert(id, send_ack);
+        self.send(
+            id,
+            process,
+            SocketRequest::Data { channel_id },
+            bytes,
+            Some(READ_TIMEOUT),
+        )
+        .await;
+        let acked = tokio::time::timeout(Duration::from_secs(READ_TIMEOUT), recv_ack)
+            .await
+            .is_ok_and(|ack| ack.is_ok());
+        self.acks.remove(&id);
+        acked
+    }
+
+    async fn send(
+        &self,
+        id: u64,
+        process: &ProcessId,
+        request: SocketRequest,
+        bytes: Vec<u8>,
+        expects_response: Option<u64>,
+    ) {
+        KernelMessage::builder()
+            .id(id)
+            .source((self.our_node.as_str(), SOCKET_PROCESS_ID.clone()))
+            .target((self.our_node.as_str(), process.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response,
+                body: serde_json::to_vec(&request).unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .lazy_load_blob((!bytes.is_empty()).then_some(LazyLoadBlob { mime: None, bytes }))
+            .build()
+            .unwrap()
+            .send(&self.send_to_loop)
+            .await;
+    }
+
+    /// whether `source` holds the socket capability with params `{"root": true}`
+    async fn check_root(&self, source: &Address) -> Result<(), SocketError> {
+        let (send_cap_bool, recv_cap_bool) = oneshot::channel();
+        let sent = self
+            .send_to_caps_oracle
+            .send(CapMessage::Has {
+                on: source.process.clone(),
+                cap: Capability::new(
+                    (self.our_node.as_str(), SOCKET_PROCESS_ID.clone()),
+                    "{\"root\":true}",
+                ),
+                responder: send_cap_bool,
+            })
+            .await;
+        if sent.is_ok() && recv_cap_bool.await.unwrap_or(false) {
+            Ok(())
+        } else {
+            Err(SocketError::NoCap)
+        }
+    }
+}
+
+/// stop reading from a socket, and end a TCP stream once any write under way is done
+fn close(socket: Socket) {
+    socket.reader.abort();
+    tokio::spawn(async move {
+        if let Writer::Stream(writer) = &mut *socket.writer.lock().await {
+            let _ = writer.shutdown().await;
+        }
+    });
+}
+
+fn connect_failed(error: &str) -> SocketError {
+    SocketError::ConnectFailed {
+        error: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn service() -> (Arc<SocketService>, MessageReceiver) {
+        let (send_to_loop, recv_in_loop) = mpsc::channel(16);
+        let (send_to_caps_oracle, _) = mpsc::channel(1);
+        let service = SocketService::new(
+            Arc::new("node.os".to_string()),
+            send_to_loop,
+            send_to_caps_oracle,
+            PathBuf::from("/nonexistent/socket"),
+            HashMap::from([(script(), 2)]),
+        );
+        (Arc::new(service), recv_in_loop)
+    }
+
+    fn script() -> ProcessId {
+        ProcessId::new(Some("sockets"), "terminal", "sys")
+    }
+
+    fn request(action: SocketAction) -> KernelMessage {
+        KernelMessage::builder()
+            .id(rand::random())
+            .source(("node.os", script()))
+            .target(("node.os", SOCKET_PROCESS_ID.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: Some(5),
+                body: serde_json::to_vec(&action).unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+    }
+
+    async fn tcp_connect(
+        service: &Arc<SocketService>,
+        channel_id: u32,
+        port: u16,
+    ) -> Result<SocketResponse, SocketError> {
+        let action = SocketAction::TcpConnect {
+            channel_id,
+            host: "127.0.0.1".into(),
+            port,
+            tls: false,
+        };
+        service.clone().handle_request(request(action)).await
+    }
+
+    #[tokio::test]
+    async fn limit_counts_open_and_connecting_sockets() {
+        let (service, _recv) = service();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tcp_connect(&service, 1, port).await.unwrap();
+        let _connecting = service.reserve(&script(), 2).await.unwrap();
+        assert!(matches!(
+            tcp_connect(&service, 3, port).await,
+            Err(SocketError::LimitReached { max_sockets: 2 })
+        ));
+        // reconnecting a channel replaces it rather than counting twice
+        tcp_connect(&service, 1, port).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn data_read_is_sent_to_the_process() {
+        let (service, mut recv_in_loop) = service();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tcp_connect(&service, 1, port).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        peer.write_all(b"hello").await.unwrap();
+        let km = recv_in_loop.recv().await.unwrap();
+        let Message::Request(request) = km.message else {
+            panic!("not a request");
+        };
+        assert!(matches!(
+            serde_json::from_slice::<SocketRequest>(&request.body).unwrap(),
+            SocketRequest::Data { channel_id: 1 }
+        ));
+        assert_eq!(km.lazy_load_blob.unwrap().bytes, b"hello");
+    }
+
+    #[tokio::test]
+    async fn sockets_are_closed_when_their_process_exits() {
+        let (service, _recv) = service();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tcp_connect(&service, 1, port).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        service.close_all(&script());
+        assert!(service.sockets.is_empty());
+        // the peer sees the connection end
+        let mut buffer = [0u8; 8];
+        let read = tokio::time::timeout(Duration::from_secs(5), peer.read(&mut buffer)).await;
+        assert!(matches!(read, Ok(Ok(0))));
+    }
+
+    #[tokio::test]
+    async fn socket_connected_across_an_exit_is_not_kept() {
+        let (service, _recv) = service();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let _reserved = service.reserve(&script(), 1).await.unwrap();
+        let exits = service.exits(&script());
+        service.close_all(&script());
+        let (reader, writer) = tokio::io::split(Box::new(stream) as Box<dyn Stream>);
+        assert!(service
+            .open(
+                script(),
+                exits,
+                1,
+                SocketProtocol::Tcp,
+                String::new(),
+                Reader::Stream(reader),
+                Writer::Stream(writer),
+            )
+            .is_err());
+        assert!(service.sockets.is_empty());
+    }
+}
@@ -1,3 +1,8 @@
+use crate::{
+    cap_feedback::Denials,
+    idempotency::{Keyed, Recent},
+    kernel::flags::FeatureFlags,
+};
 use base64::{engine::general_purpose::STANDARD as base64_standard, Engine};
 use dashmap::DashMap;
 use lib::types::core::{
@@ -5,6 +10,7 @@ use lib::types::core::{
     MessageReceiver, MessageSender, PackageId, PrintSender, Printout, ProcessId, Request, Response,
     SqlValue, SqliteAction, SqliteError, SqliteRequest, SqliteResponse, SQLITE_PROCESS_ID,
 };
+use lib::types::errors::ModuleError;
 use rusqlite::Connection;
 use std::{
     collections::{HashMap, HashSet, VecDeque},
@@ -27,6 +33,7 @@ pub async fn sqlite(
     mut recv_from_loop: MessageReceiver,
     send_to_caps_oracle: CapMessageSender,
     home_directory_path: String,
+    feature_flags: Arc<FeatureFlags>,
 ) -> anyhow::Result<()> {
     let sqlite_path = Arc::new(format!("{home_directory_path}/sqlite"));
     if let Err(e) = fs::create_dir_all(&*sqlite_path).await {
@@ -37,8 +44,10 @@ pub async fn sqlite(
     let txs: Arc<DashMap<u64, Vec<(String, Vec<SqlValue>)>>> = Arc::new(DashMap::new());
 
     let process_queues: HashMap<ProcessId, Arc<Mutex<VecDeque<KernelMessage>>>> = HashMap::new();
+    let recent = Arc::new(Recent::default());
 
     while let Some(km) = recv_from_loop.recv().await {
+        crate::metrics::handled(&SQLITE_PROCESS_ID, &km);
         if *our_node != km.source.node {
             Printout::new(
                 1,
@@ -70,46 +79,55 @@ pub async fn sqlite(
         let open_dbs = open_dbs.clone();
         let txs = txs.clone();
         let sqlite_path = sqlite_path.clone();
+        let recent = recent.clone();
+        let feature_flags = feature_flags.clone();
 
         tokio::spawn(async move {
             let mut queue_lock = queue.lock().await;
             if let Some(km) = queue_lock.pop_front() {
                 let (km_id, km_rsvp) =
                     (km.id.clone(), km.rsvp.clone().unwrap_or(km.source.clone()));
-
-                if let Err(e) = handle_request(
-                    &our_node,
-                    km,
-                    open_dbs,
-                    txs,
-                    &send_to_loop,
-                    &send_to_caps_oracle,
-                    &sqlite_path,
-                )
-                .await
-                {
-                    Printout::new(1, format!("sqlite: {e}"))
-                        .send(&send_to_terminal)
-                        .await;
-                    KernelMessage::builder()
-                        .id(km_id)
-                        .source((our_node.as_str(), SQLITE_PROCESS_ID.clone()))
-                        .target(km_rsvp)
-                        .message(Message::Response((
-                            Response {
-                                inherit: false,
-                                body: serde_json::to_vec(&SqliteResponse::Err { error: e })
-                                    .unwrap(),
-                                metadata: None,
-                                capabilities: vec![],
-                            },
-                            None,
-                        )))
-                        .build()
-                        .unwrap()
-                        .send(&send_to_loop)
-                        .await;
-                }
+                let keyed = Keyed::of(&km, mutates);
+
+                recent
+                    .handle(keyed, &send_to_loop, |send_to_loop| async move {
+                        if let Err(e) = handle_request(
+                            &our_node,
+                            km,
+                            open_dbs,
+                            txs,
+                            &send_to_loop,
+                            &send_to_caps_oracle,
+                            &sqlite_path,
+                            &feature_flags,
+                        )
+                        .await
+                        {
+                            Printout::new(1, format!("sqlite: {e}"))
+                                .send(&send_to_terminal)
+                                .await;
+                            let metadata = e.metadata();
+                            KernelMessage::builder()
+                                .id(km_id)
+                                .source((our_node.as_str(), SQLITE_PROCESS_ID.clone()))
+                                .target(km_rsvp)
+                                .message(Message::Response((
+                                    Response {
+                                        inherit: false,
+                                        body: serde_json::to_vec(&SqliteResponse::Err { error: e })
+                                            .unwrap(),
+                                        metadata,
+                                        capabilities: vec![],
+                                    },
+                                    None,
+                                )))
+                                .build()
+                                .unwrap()
+                                .send(&send_to_loop)
+                                .await;
+                        }
+                    })
+                    .await;
             }
         });
     }
@@ -124,7 +142,14 @@ async fn handle_request(
     send_to_loop: &MessageSender,
     send_to_caps_oracle: &CapMessageSender,
     sqlite_path: &str,
+    feature_flags: &FeatureFlags,
 ) -> Result<(), SqliteError> {
+    let denials = Denials::new(
+        feature_flags,
+        (our_node, SQLITE_PROCESS_ID.clone()).into(),
+        &km,
+        send_to_loop,
+    );
     let KernelMessage {
         id,
         source,
@@ -161,6 +186,7 @@ async fn handle_request(
         send_to_caps_oracle,
         &request,
         sqlite_path,
+        &denials,
     )
     .await?;
 
@@ -173,6 +199,18 @@ async fn handle_request(
             // handled in check_caps
             (serde_json::to_vec(&SqliteResponse::Ok).unwrap(), None)
         }
+        SqliteAction::RemoveAllDbs => {
+            // handled in check_caps
+            (serde_json::to_vec(&SqliteResponse::Ok).unwrap(), None)
+        }
+        SqliteAction::ListDbs => {
+            // permission checked in check_caps
+            let dbs = list_dbs(&format!("{}/{}", sqlite_path, request.package_id)).await?;
+            (
+                serde_json::to_vec(&SqliteResponse::Ok).unwrap(),
+                Some(serde_json::to_vec(&dbs).unwrap()),
+            )
+        }
         SqliteAction::Read { query } => {
             let db = match open_dbs.get(&(request.package_id, request.db)) {
                 Some(db) => db,
@@ -248,6 +286,9 @@ async fn handle_request(
                 return Err(SqliteError::NotAWriteKeyword);
             }
 
+            let len = statement.len() + blob.as_ref().map_or(0, |blob| blob.bytes.len());
+            crate::disk::reserve(len as u64).map_err(|free| SqliteError::NoSpace { free })?;
+
             let parameters = get_json_params(blob)?;
 
             match tx_id {
@@ -339,6 +380,13 @@ async fn handle_request(
     Ok(())
 }
 
+/// whether a request body asks for a change, so that a retry of it must get
+/// the first response rather than be carried out again
+fn mutates(body: &[u8]) -> bool {
+    serde_json::from_slice::<SqliteRequest>(body)
+        .is_ok_and(|request| !matches!(request.action, SqliteAction::Read { .. }))
+}
+
 async fn check_caps(
     our_node: &str,
     source: &Address,
@@ -346,28 +394,31 @@ async fn check_caps(
     send_to_caps_oracle: &CapMessageSender,
     request: &SqliteRequest,
     sqlite_path: &str,
+    denials: &Denials,
 ) -> Result<(), SqliteError> {
     let (send_cap_bool, recv_cap_bool) = tokio::sync::oneshot::channel();
     let src_package_id = PackageId::new(source.process.package(), source.process.publisher());
 
     match &request.action {
         SqliteAction::Write { .. } | SqliteAction::BeginTx | SqliteAction::Commit { .. } => {
+            let cap = Capability::new(
+                (our_node, SQLITE_PROCESS_ID.clone()),
+                serde_json::json!({
+                    "kind": "write",
+                    "db": request.db.to_string(),
+                })
+                .to_string(),
+            );
             send_to_caps_oracle
                 .send(CapMessage::Has {
                     on: source.process.clone(),
-                    cap: Capability::new(
-                        (our_node, SQLITE_PROCESS_ID.clone()),
-                        serde_json::json!({
-                            "kind": "write",
-                            "db": request.db.to_string(),
-                        })
-                        .to_string(),
-                    ),
+                    cap: cap.clone(),
                     responder: send_cap_bool,
                 })
                 .await?;
             let has_cap = recv_cap_bool.await?;
             if !has_cap {
+                denials.report(cap).await;
                 return Err(SqliteError::NoCap {
                     error: request.action.to_string(),
                 });
@@ -375,22 +426,24 @@ async fn check_caps(
             Ok(())
         }
         SqliteAction::Read { .. } => {
+            let cap = Capability::new(
+                (our_node, SQLITE_PROCESS_ID.clone()),
+                serde_json::json!({
+                    "kind": "read",
+                    "db": request.db.to_string(),
+                })
+                .to_string(),
+            );
             send_to_caps_oracle
                 .send(CapMessage::Has {
                     on: source.process.clone(),
-                    cap: Capability::new(
-                        (our_node, SQLITE_PROCESS_ID.clone()),
-                        serde_json::json!({
-                            "kind": "read",
-                            "db": request.db.to_string(),
-                        })
-                        .to_string(),
-                    ),
+                    cap: cap.clone(),
                     responder: send_cap_bool,
                 })
                 .await?;
             let has_cap = recv_cap_bool.await?;
             if !has_cap {
+                denials.report(cap).await;
                 return Err(SqliteError::NoCap {
                     error: request.action.to_string(),
                 });
@@ -452,6 +505,35 @@ async fn check_caps(
             fs::remove_dir_all(&db_path).await?;
             Ok(())
         }
+        SqliteAction::RemoveAllDbs | SqliteAction::ListDbs => {
+            if src_package_id != request.package_id {
+                let cap = Capability::new((our_node, SQLITE_PROCESS_ID.clone()), "{\"root\":true}");
+                send_to_caps_oracle
+                    .send(CapMessage::Has {
+                        on: source.process.clone(),
+                        cap: cap.clone(),
+                        responder: send_cap_bool,
+                    })
+                    .await?;
+                if !recv_cap_bool.await? {
+                    denials.report(cap).await;
+                    return Err(SqliteError::NoCap {
+                        error: request.action.to_string(),
+                    });
+                }
+            }
+            if matches!(request.action, SqliteAction::ListDbs) {
+                return Ok(());
+            }
+
+            open_dbs.retain(|(package_id, _), _| package_id != &request.package_id);
+
+            let package_path = format!("{}/{}", sqlite_path, request.package_id);
+            if fs::metadata(&package_path).await.is_ok() {
+                fs::remove_dir_all(&package_path).await?;
+            }
+            Ok(())
+        }
         SqliteAction::Backup => {
             // flushing WALs for backup
             Ok(())
@@ -459,6 +541,23 @@ async fn check_caps(
     }
 }
 
+/// the names of the databases in a package's directory, if it has one
+async fn list_dbs(package_path: &str) -> std::io::Result<Vec<String>> {
+    let mut dbs = vec![];
+    let mut entries = match fs::read_dir(package_path).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(dbs),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            dbs.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    dbs.sort();
+    Ok(dbs)
+}
+
 async fn add_capability(
     kind: &str,
     db: &str,
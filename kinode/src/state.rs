@@ -1,26 +1,50 @@
+use crate::idempotency::{Keyed, Recent};
 use lib::types::core::{
-    Address, Capability, Erc721Metadata, KernelMessage, LazyLoadBlob, Message, MessageReceiver,
-    MessageSender, NetworkErrorSender, OnExit, PackageManifestEntry, PersistedProcess, PrintSender,
-    Printout, ProcessId, ProcessMap, Request, Response, ReverseCapIndex, StateAction, StateError,
-    StateResponse, KERNEL_PROCESS_ID, STATE_PROCESS_ID, VFS_PROCESS_ID,
+    Address, BackupReport, BackupWindow, BootstrapRecord, BootstrappedPackage, CapMessage,
+    CapMessageSender, Capability, Erc721Metadata, KernelMessage, LazyLoadBlob, Message,
+    MessageReceiver, MessageSender, NetworkErrorSender, OnExit, PackageId, PackageManifestEntry,
+    PersistedProcess, PrintSender, Printout, ProcessId, ProcessMap, Request, Response,
+    ReverseCapIndex, StateAction, StateError, StateResponse, KERNEL_PROCESS_ID, STATE_PROCESS_ID,
+    VFS_PROCESS_ID,
 };
+use lib::types::errors::ModuleError;
 use ring::signature;
-use rocksdb::{checkpoint::Checkpoint, Options, DB};
+use rocksdb::{checkpoint::Checkpoint, IteratorMode, Options, WriteBatch, DB};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     io::Read,
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    fs,
+    sync::{oneshot, Mutex},
 };
-use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
 
 include!("../../target/bootstrapped_processes.rs");
 
+/// how long backup subscribers are given, once told a backup is opening,
+/// to write what they want in it before writes are held off
+const BACKUP_GRACE: Duration = Duration::from_secs(1);
+
+/// where the record of each boot's bootstrapped packages is kept: no
+/// process ID is without a `:`
+const BOOTSTRAP_RECORDS_KEY: &[u8] = b"bootstrap_records";
+/// how many boots' records are kept
+const MAX_BOOTSTRAP_RECORDS: usize = 32;
+
+/// the capability, issued by state, that [`StateAction::SubscribeToBackups`]
+/// needs
+pub const BACKUPS_CAP_PARAMS: &str = "\"backups\"";
+
 pub async fn load_state(
     our_name: String,
     keypair: Arc<signature::Ed25519KeyPair>,
     home_directory_path: String,
     runtime_extensions: Vec<(ProcessId, MessageSender, Option<NetworkErrorSender>, bool)>,
+    release_hashes: Option<HashMap<String, String>>,
+    allow_unverified_packages: bool,
 ) -> Result<(ProcessMap, DB, ReverseCapIndex), StateError> {
     let state_path = format!("{home_directory_path}/kernel");
     if let Err(e) = fs::create_dir_all(&state_path).await {
@@ -63,16 +87,19 @@ pub async fn load_state(
     // once we manage userspace sys packages onchain, stop
     // doing this and allow node operator to manually or auto-update
     // all their own userspace packages.
-    bootstrap(
+    let bootstrapped = bootstrap(
         &our_name,
         keypair,
         home_directory_path,
         runtime_extensions,
         &mut process_map,
         &mut reverse_cap_index,
+        release_hashes,
+        allow_unverified_packages,
     )
     .await
     .expect("bootstrapping filesystem failed!");
+    record_bootstrap(&db, bootstrapped)?;
 
     Ok((process_map, db, reverse_cap_index))
 }
@@ -84,11 +111,14 @@ pub async fn state_sender(
     mut recv_state: MessageReceiver,
     db: DB,
     home_directory_path: String,
+    send_to_caps_oracle: CapMessageSender,
 ) -> Result<(), anyhow::Error> {
     let db = Arc::new(db);
     let home_directory_path = Arc::new(home_directory_path);
 
     let process_queues: HashMap<ProcessId, Arc<Mutex<VecDeque<KernelMessage>>>> = HashMap::new();
+    let recent = Arc::new(Recent::default());
+    let backup_subscribers: Arc<Mutex<HashSet<ProcessId>>> = Arc::new(Mutex::new(HashSet::new()));
 
     while let Some(km) = recv_state.recv().await {
         if *our_node != km.source.node {
@@ -118,35 +148,52 @@ pub async fn state_sender(
         let db_clone = db.clone();
         let send_to_loop = send_to_loop.clone();
         let home_directory_path = home_directory_path.clone();
+        let recent = recent.clone();
+        let backup_subscribers = backup_subscribers.clone();
+        let send_to_caps_oracle = send_to_caps_oracle.clone();
 
         tokio::spawn(async move {
             let mut queue_lock = queue.lock().await;
             if let Some(km) = queue_lock.pop_front() {
+                crate::metrics::handled(&STATE_PROCESS_ID, &km);
                 let (km_id, km_rsvp) =
                     (km.id.clone(), km.rsvp.clone().unwrap_or(km.source.clone()));
+                let keyed = Keyed::of(&km, mutates);
 
-                if let Err(e) =
-                    handle_request(&our_node, km, db_clone, &send_to_loop, &home_directory_path)
+                recent
+                    .handle(keyed, &send_to_loop, |send_to_loop| async move {
+                        if let Err(e) = handle_request(
+                            &our_node,
+                            km,
+                            db_clone,
+                            &send_to_loop,
+                            &home_directory_path,
+                            &backup_subscribers,
+                            &send_to_caps_oracle,
+                        )
                         .await
-                {
-                    KernelMessage::builder()
-                        .id(km_id)
-                        .source((our_node.as_str(), STATE_PROCESS_ID.clone()))
-                        .target(km_rsvp)
-                        .message(Message::Response((
-                            Response {
-                                inherit: false,
-                                body: serde_json::to_vec(&StateResponse::Err(e)).unwrap(),
-                                metadata: None,
-                                capabilities: vec![],
-                            },
-                            None,
-                        )))
-                        .build()
-                        .unwrap()
-                        .send(&send_to_loop)
-                        .await;
-                }
+                        {
+                            let metadata = e.metadata();
+                            KernelMessage::builder()
+                                .id(km_id)
+                                .source((our_node.as_str(), STATE_PROCESS_ID.clone()))
+                                .target(km_rsvp)
+                                .message(Message::Response((
+                                    Response {
+                                        inherit: false,
+                                        body: serde_json::to_vec(&StateResponse::Err(e)).unwrap(),
+                                        metadata,
+                                        capabilities: vec![],
+                                    },
+                                    None,
+                                )))
+                                .build()
+                                .unwrap()
+                                .send(&send_to_loop)
+                                .await;
+                        }
+                    })
+                    .await;
             }
         });
     }
@@ -159,6 +206,8 @@ async fn handle_request(
     db: Arc<DB>,
     send_to_loop: &MessageSender,
     home_directory_path: &str,
+    backup_subscribers: &Mutex<HashSet<ProcessId>>,
+    send_to_caps_oracle: &CapMessageSender,
 ) -> Result<(), StateError> {
     let KernelMessage {
         id,
@@ -198,7 +247,10 @@ async fn handle_request(
                     action: "SetState".into(),
                 });
             };
+            crate::disk::reserve(blob.bytes.len() as u64)
+                .map_err(|free| StateError::NoSpace { free })?;
 
+            let _write = crate::quiesce::write().await;
             db.put(key, &blob.bytes)
                 .map_err(|e| StateError::RocksDBError {
                     action: "SetState".into(),
@@ -228,8 +280,12 @@ async fn handle_request(
             }
         }
         StateAction::DeleteState(process_id) => {
-            let key = process_to_vec(process_id);
-            match db.delete(key) {
+            let (start, end) = state_key_range(&process_id);
+            let mut batch = WriteBatch::default();
+            batch.delete(process_to_vec(process_id));
+            batch.delete_range(start, end);
+            let _write = crate::quiesce::write().await;
+            match db.write(batch) {
                 Ok(_) => (
                     serde_json::to_vec(&StateResponse::DeleteState).unwrap(),
                     None,
@@ -242,26 +298,112 @@ async fn handle_request(
                 }
             }
         }
-        StateAction::Backup => {
-            let checkpoint_dir = format!("{home_directory_path}/kernel/backup");
-
-            if Path::new(&checkpoint_dir).exists() {
-                fs::remove_dir_all(&checkpoint_dir).await?;
-            }
-            let checkpoint = Checkpoint::new(&db).map_err(|e| StateError::RocksDBError {
-                action: "BackupCheckpointNew".into(),
-                error: e.to_string(),
-            })?;
-
-            checkpoint.create_checkpoint(&checkpoint_dir).map_err(|e| {
-                StateError::RocksDBError {
-                    action: "BackupCheckpointCreate".into(),
+        StateAction::GetStateKey { process, key } => match db.get(state_key(&process, &key)) {
+            Ok(value) => (
+                serde_json::to_vec(&StateResponse::GetStateKey).unwrap(),
+                value,
+            ),
+            Err(e) => {
+                return Err(StateError::RocksDBError {
+                    action: "GetStateKey".into(),
                     error: e.to_string(),
+                });
+            }
+        },
+        StateAction::UpdateState(process_id) => {
+            let Some(ref blob) = blob else {
+                return Err(StateError::BadBytes {
+                    action: "UpdateState".into(),
+                });
+            };
+            crate::disk::reserve(blob.bytes.len() as u64)
+                .map_err(|free| StateError::NoSpace { free })?;
+            let changes: Vec<(String, Option<Vec<u8>>)> = rmp_serde::from_slice(&blob.bytes)
+                .map_err(|e| StateError::BadRequest {
+                    error: format!("UpdateState blob is not a list of changes: {e}"),
+                })?;
+            let mut batch = WriteBatch::default();
+            for (key, value) in changes {
+                let key = state_key(&process_id, &key);
+                match value {
+                    Some(value) => batch.put(key, value),
+                    None => batch.delete(key),
                 }
+            }
+            let _write = crate::quiesce::write().await;
+            db.write(batch).map_err(|e| StateError::RocksDBError {
+                action: "UpdateState".into(),
+                error: e.to_string(),
             })?;
 
+            (
+                serde_json::to_vec(&StateResponse::UpdateState).unwrap(),
+                None,
+            )
+        }
+        StateAction::Backup => {
+            backup(
+                our_node,
+                &db,
+                home_directory_path,
+                &[],
+                backup_subscribers,
+                send_to_loop,
+            )
+            .await?;
             (serde_json::to_vec(&StateResponse::Backup).unwrap(), None)
         }
+        StateAction::BackupWithDrives { packages } => {
+            backup(
+                our_node,
+                &db,
+                home_directory_path,
+                &packages,
+                backup_subscribers,
+                send_to_loop,
+            )
+            .await?;
+            (
+                serde_json::to_vec(&StateResponse::BackupWithDrives).unwrap(),
+                None,
+            )
+        }
+        StateAction::SubscribeToBackups => {
+            if !has_backups_cap(our_node, &source, send_to_caps_oracle).await {
+                return Err(StateError::NoCap);
+            }
+            backup_subscribers
+                .lock()
+                .await
+                .insert(source.process.clone());
+            (
+                serde_json::to_vec(&StateResponse::SubscribeToBackups).unwrap(),
+                None,
+            )
+        }
+        StateAction::VerifyBackup => {
+            let checkpoint_dir = format!("{home_directory_path}/kernel/backup");
+            let checksums_path = format!("{home_directory_path}/kernel/backup.checksums");
+
+            if !Path::new(&checkpoint_dir).exists() {
+                return Err(StateError::NoBackup);
+            }
+            let report = verify_backup(
+                &checkpoint_dir,
+                fs::read(&checksums_path)
+                    .await
+                    .ok()
+                    .and_then(|bytes| bincode::deserialize(&bytes).ok()),
+            );
+            (
+                serde_json::to_vec(&StateResponse::VerifyBackup(report)).unwrap(),
+                None,
+            )
+        }
+        StateAction::BootstrapRecords => (
+            serde_json::to_vec(&StateResponse::BootstrapRecords(bootstrap_records(&db)?)).unwrap(),
+            None,
+        ),
     };
 
     if let Some(target) = rsvp.or_else(|| expects_response.map(|_| source)) {
@@ -291,6 +433,21 @@ async fn handle_request(
     Ok(())
 }
 
+/// whether a request body asks for a change, so that a retry of it must get
+/// the first response rather than be carried out again
+fn mutates(body: &[u8]) -> bool {
+    let Ok(action) = serde_json::from_slice::<StateAction>(body) else {
+        return false;
+    };
+    !matches!(
+        action,
+        StateAction::GetState(_)
+            | StateAction::GetStateKey { .. }
+            | StateAction::VerifyBackup
+            | StateAction::BootstrapRecords
+    )
+}
+
 /// function run only upon fresh boot.
 ///
 /// for each included package.zip file, extracts the contents,
@@ -299,6 +456,10 @@ async fn handle_request(
 /// the manifest.json contains instructions for which processes to boot and what
 /// capabilities to give them. since we are inside runtime, can spawn those out of
 /// thin air.
+///
+/// given the hashes a release published, each package.zip is first checked
+/// against its own, and the node refuses to boot if one doesn't match, unless
+/// `allow_unverified_packages`. the packages, and what was found, are returned.
 async fn bootstrap(
     our_name: &str,
     keypair: Arc<signature::Ed25519KeyPair>,
@@ -306,7 +467,9 @@ async fn bootstrap(
     runtime_extensions: Vec<(ProcessId, MessageSender, Option<NetworkErrorSender>, bool)>,
     process_map: &mut ProcessMap,
     reverse_cap_index: &mut ReverseCapIndex,
-) -> anyhow::Result<()> {
+    release_hashes: Option<HashMap<String, String>>,
+    allow_unverified_packages: bool,
+) -> anyhow::Result<Vec<BootstrappedPackage>> {
     let mut runtime_caps: HashMap<Capability, Vec<u8>> = HashMap::new();
     // kernel is a special case
     let k_cap = Capability {
@@ -381,7 +544,8 @@ async fn bootstrap(
         current.capabilities.extend(runtime_caps.clone());
     }
 
-    let packages = get_zipped_packages().await;
+    let (packages, bootstrapped) =
+        get_zipped_packages(release_hashes.as_ref(), allow_unverified_packages)?;
 
     for (package_metadata, mut package) in packages.clone() {
         let package_name = package_metadata.properties.package_name.as_str();
@@ -409,56 +573,44 @@ async fn bootstrap(
         let drive_path = format!("/{}/pkg", &our_drive_name);
 
         // save the zip itself inside pkg folder, for sharing with others
-        let mut zip_file =
-            fs::File::create(format!("{}/{}.zip", &pkg_path, &our_drive_name)).await?;
         let package_zip_bytes = package.clone().into_inner().into_inner();
-        zip_file.write_all(package_zip_bytes).await?;
+        fs::write(
+            format!("{}/{}.zip", &pkg_path, &our_drive_name),
+            package_zip_bytes,
+        )
+        .await?;
 
-        // for each file in package.zip, write to vfs folder
+        // for each file in package.zip, write to vfs folder. a file that can't
+        // be written fails the boot, rather than leave the package half there:
+        // the next boot extracts every package again.
         for i in 0..package.len() {
-            let mut file = match package.by_index(i) {
-                Ok(f) => f,
-                Err(e) => {
-                    println!("Error accessing file by index: {}", e);
-                    continue;
-                }
-            };
+            let mut file = package.by_index(i)?;
 
-            let file_path = match file.enclosed_name() {
-                Some(path) => path.to_owned(),
-                None => {
-                    println!("Error getting the file name from the package");
-                    continue;
-                }
+            let Some(file_path) = file.enclosed_name().map(|path| path.to_owned()) else {
+                return Err(anyhow::anyhow!(
+                    "{our_drive_name}: file name {} escapes the package",
+                    file.name()
+                ));
             };
 
-            let file_path_str = file_path.to_string_lossy().to_string();
-            let full_path = Path::new(&pkg_path).join(&file_path_str);
+            let full_path = Path::new(&pkg_path).join(&file_path);
 
             if file.is_dir() {
                 // It's a directory, create it
-                if let Err(e) = fs::create_dir_all(&full_path).await {
-                    println!("Failed to create directory {}: {}", full_path.display(), e);
-                }
+                fs::create_dir_all(&full_path).await?;
             } else if file.is_file() {
                 // It's a file, ensure the parent directory exists and write the file
                 if let Some(parent) = full_path.parent() {
-                    if let Err(e) = fs::create_dir_all(parent).await {
-                        println!("Failed to create parent directory: {}", e);
-                        continue;
-                    }
+                    fs::create_dir_all(parent).await?;
                 }
 
                 let mut file_content = Vec::new();
-                if let Err(e) = file.read_to_end(&mut file_content) {
-                    println!("Error reading file contents: {}", e);
-                    continue;
-                }
+                file.read_to_end(&mut file_content)?;
 
                 // Write the file content
-                if let Err(e) = fs::write(&full_path, file_content).await {
-                    println!("Failed to write file {}: {}", full_path.display(), e);
-                }
+                fs::write(&full_path, file_content).await.map_err(|e| {
+                    anyhow::anyhow!("failed to write file {}: {e}", full_path.display())
+                })?;
             }
         }
 
@@ -702,7 +854,20 @@ async fn bootstrap(
             }
         }
     }
-    Ok(())
+    Ok(bootstrapped)
+}
+
+pub async fn read_release_hashes(path: &str) -> anyhow::Result<HashMap<String, String>> {
+    tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("couldn't read {path}: {e}"))?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match line.split_once(char::is_whitespace) {
+            Some((hash, filename)) => Ok((filename.trim().to_string(), hash.to_string())),
+            None => Err(anyhow::anyhow!("{path} has a malformed line: {line}")),
+        })
+        .collect()
 }
 
 fn sign_cap(cap: Capability, keypair: Arc<signature::Ed25519KeyPair>) -> Vec<u8> {
@@ -712,16 +877,51 @@ fn sign_cap(cap: Capability, keypair: Arc<signature::Ed25519KeyPair>) -> Vec<u8>
         .to_vec()
 }
 
-/// read in `include!()`ed .zip package files
-async fn get_zipped_packages() -> Vec<(
-    Erc721Metadata,
-    zip::ZipArchive<std::io::Cursor<&'static [u8]>>,
+/// read in `include!()`ed .zip package files, checking each against the blake3
+/// hash a release published for it, if given, so that a runtime built from
+/// other packages than the release's is caught
+fn get_zipped_packages(
+    release_hashes: Option<&HashMap<String, String>>,
+    allow_unverified_packages: bool,
+) -> anyhow::Result<(
+    Vec<(
+        Erc721Metadata,
+        zip::ZipArchive<std::io::Cursor<&'static [u8]>>,
+    )>,
+    Vec<BootstrappedPackage>,
 )> {
     let mut packages = Vec::new();
+    let mut bootstrapped = Vec::new();
 
     for (package_name, metadata_bytes, bytes) in BOOTSTRAPPED_PROCESSES.iter() {
+        let hash = blake3::hash(bytes).to_hex().to_string();
+        let expected_hash = release_hashes.map(|hashes| hashes.get(*package_name));
+        let verified = expected_hash.is_some_and(|expected| expected == Some(&hash));
+        if let (Some(expected_hash), false) = (expected_hash, verified) {
+            let expected_hash = expected_hash.map_or("none", String::as_str);
+            if !allow_unverified_packages {
+                return Err(anyhow::anyhow!(
+                    "distro package {package_name} has hash {hash}, but the release has \
+                     {expected_hash}: this runtime wasn't built from the release's packages. \
+                     boot with --allow-unverified-packages to run it anyway"
+                ));
+            }
+            println!(
+                "\x1b[31mfs: WARNING: distro package {package_name} has hash {hash}, but the \
+                 release has {expected_hash}! booting it anyway\x1b[0m\r"
+            );
+        }
         if let Ok(zip) = zip::ZipArchive::new(std::io::Cursor::new(*bytes)) {
             if let Ok(metadata) = serde_json::from_slice::<Erc721Metadata>(metadata_bytes) {
+                bootstrapped.push(BootstrappedPackage {
+                    package_id: PackageId::new(
+                        &metadata.properties.package_name,
+                        &metadata.properties.publisher,
+                    ),
+                    version: metadata.properties.current_version.clone(),
+                    hash,
+                    verified,
+                });
                 packages.push((metadata, zip));
             } else {
                 println!("fs: metadata for package {package_name} is not valid Erc721Metadata!\r",);
@@ -729,9 +929,301 @@ async fn get_zipped_packages() -> Vec<(
         }
     }
 
-    packages
+    Ok((packages, bootstrapped))
+}
+
+/// add this boot's bootstrapped packages to the record kept for audit,
+/// dropping the oldest boots' beyond [`MAX_BOOTSTRAP_RECORDS`]
+fn record_bootstrap(db: &DB, packages: Vec<BootstrappedPackage>) -> Result<(), StateError> {
+    let mut records = bootstrap_records(db)?;
+    records.push(BootstrapRecord {
+        booted: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        runtime_version: env!("CARGO_PKG_VERSION").to_string(),
+        packages,
+    });
+    let excess = records.len().saturating_sub(MAX_BOOTSTRAP_RECORDS);
+    records.drain(..excess);
+    db.put(BOOTSTRAP_RECORDS_KEY, bincode::serialize(&records).unwrap())
+        .map_err(|e| StateError::RocksDBError {
+            action: "RecordBootstrap".into(),
+            error: e.to_string(),
+        })
+}
+
+fn bootstrap_records(db: &DB) -> Result<Vec<BootstrapRecord>, StateError> {
+    match db.get(BOOTSTRAP_RECORDS_KEY) {
+        Ok(Some(value)) => Ok(bincode::deserialize(&value).unwrap_or_default()),
+        Ok(None) => Ok(vec![]),
+        Err(e) => Err(StateError::RocksDBError {
+            action: "BootstrapRecords".into(),
+            error: e.to_string(),
+        }),
+    }
+}
+
+/// checkpoint the state DB into `kernel/backup`, and copy the VFS drives of
+/// `packages` into `kernel/backup.vfs`, at one point in time. subscribers
+/// are told the backup is opening and given [`BACKUP_GRACE`] to write what
+/// they want in it; then writes to state and the VFS are held off only while
+/// the checkpoint and a snapshot of the drives are made. the drives are
+/// copied out of the snapshot after: see [`crate::quiesce`].
+async fn backup(
+    our_node: &str,
+    db: &DB,
+    home_directory_path: &str,
+    packages: &[PackageId],
+    backup_subscribers: &Mutex<HashSet<ProcessId>>,
+    send_to_loop: &MessageSender,
+) -> Result<(), StateError> {
+    let checkpoint_dir = format!("{home_directory_path}/kernel/backup");
+    let checksums_path = format!("{home_directory_path}/kernel/backup.checksums");
+    let drives_dir = PathBuf::from(format!("{home_directory_path}/kernel/backup.vfs"));
+    let staging_dir = PathBuf::from(format!("{home_directory_path}/kernel/backup.vfs.staging"));
+
+    if Path::new(&checkpoint_dir).exists() {
+        fs::remove_dir_all(&checkpoint_dir).await?;
+    }
+    if Path::new(&checksums_path).exists() {
+        fs::remove_file(&checksums_path).await?;
+    }
+    if drives_dir.exists() {
+        fs::remove_dir_all(&drives_dir).await?;
+    }
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir).await?;
+    }
+
+    announce_backup(
+        our_node,
+        BackupWindow::Opening,
+        backup_subscribers,
+        send_to_loop,
+    )
+    .await;
+    tokio::time::sleep(BACKUP_GRACE).await;
+    let snapshot = {
+        let _quiet = crate::quiesce::quiesce().await;
+        snapshot_for_backup(
+            db,
+            home_directory_path,
+            &checkpoint_dir,
+            packages,
+            staging_dir,
+            drives_dir,
+        )
+        .await
+    };
+    // writes go ahead again meanwhile, copying out what they would change first
+    let copied = match snapshot {
+        Ok(()) => tokio::task::spawn_blocking(crate::quiesce::copy_staged)
+            .await
+            .map_err(|e| StateError::IOError {
+                error: e.to_string(),
+            })
+            .and_then(|copied| copied.map_err(StateError::from)),
+        Err(e) => Err(e),
+    };
+    announce_backup(
+        our_node,
+        BackupWindow::Closed,
+        backup_subscribers,
+        send_to_loop,
+    )
+    .await;
+    copied?;
+
+    // taken from the checkpoint itself, so that they match it exactly
+    let checksums = checksum_backup(&open_backup(&checkpoint_dir)?)?;
+    fs::write(&checksums_path, bincode::serialize(&checksums).unwrap()).await?;
+    Ok(())
+}
+
+/// the checkpoint and snapshot [`backup`] makes while writes are held off
+async fn snapshot_for_backup(
+    db: &DB,
+    home_directory_path: &str,
+    checkpoint_dir: &str,
+    packages: &[PackageId],
+    staging_dir: PathBuf,
+    drives_dir: PathBuf,
+) -> Result<(), StateError> {
+    let checkpoint = Checkpoint::new(db).map_err(|e| StateError::RocksDBError {
+        action: "BackupCheckpointNew".into(),
+        error: e.to_string(),
+    })?;
+    checkpoint
+        .create_checkpoint(checkpoint_dir)
+        .map_err(|e| StateError::RocksDBError {
+            action: "BackupCheckpointCreate".into(),
+            error: e.to_string(),
+        })?;
+
+    // as the VFS names its paths, so that its writes find them in the snapshot
+    let vfs_path = fs::canonicalize(format!("{home_directory_path}/vfs")).await?;
+    let drives: Vec<PathBuf> = packages
+        .iter()
+        .map(|package_id| PathBuf::from(package_id.to_string()))
+        .collect();
+    tokio::task::spawn_blocking(move || {
+        crate::quiesce::stage(&vfs_path, &drives, &staging_dir, &drives_dir)
+    })
+    .await
+    .map_err(|e| StateError::IOError {
+        error: e.to_string(),
+    })??;
+    Ok(())
+}
+
+/// tell every backup subscriber that a backup is opening or closed
+async fn announce_backup(
+    our_node: &str,
+    window: BackupWindow,
+    backup_subscribers: &Mutex<HashSet<ProcessId>>,
+    send_to_loop: &MessageSender,
+) {
+    let body = serde_json::to_vec(&window).unwrap();
+    for subscriber in backup_subscribers.lock().await.iter() {
+        KernelMessage::builder()
+            .id(rand::random())
+            .source((our_node, STATE_PROCESS_ID.clone()))
+            .target((our_node, subscriber.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: None,
+                body: body.clone(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+            .send(send_to_loop)
+            .await;
+    }
+}
+
+/// whether `source` may subscribe to backups: the kernel always may
+async fn has_backups_cap(
+    our_node: &str,
+    source: &Address,
+    send_to_caps_oracle: &CapMessageSender,
+) -> bool {
+    if source.process == *KERNEL_PROCESS_ID {
+        return true;
+    }
+    let (send_cap_bool, recv_cap_bool) = oneshot::channel();
+    let sent = send_to_caps_oracle
+        .send(CapMessage::Has {
+            on: source.process.clone(),
+            cap: Capability::new((our_node, STATE_PROCESS_ID.clone()), BACKUPS_CAP_PARAMS),
+            responder: send_cap_bool,
+        })
+        .await;
+    sent.is_ok() && recv_cap_bool.await.unwrap_or(false)
+}
+
+/// open the backup made by [`StateAction::Backup`], without writing to it
+fn open_backup(checkpoint_dir: &str) -> Result<DB, StateError> {
+    DB::open_for_read_only(&Options::default(), checkpoint_dir, false).map_err(|e| {
+        StateError::RocksDBError {
+            action: "OpenBackup".into(),
+            error: e.to_string(),
+        }
+    })
+}
+
+/// a checksum of every value in a backup, by key
+fn checksum_backup(backup: &DB) -> Result<BTreeMap<Vec<u8>, [u8; 32]>, StateError> {
+    backup
+        .iterator(IteratorMode::Start)
+        .map(|item| {
+            let (key, value) = item.map_err(|e| StateError::RocksDBError {
+                action: "BackupChecksum".into(),
+                error: e.to_string(),
+            })?;
+            Ok((key.to_vec(), *blake3::hash(&value).as_bytes()))
+        })
+        .collect()
+}
+
+/// read every key of a backup, checking values against the checksums taken
+/// when it was made, if they could be read
+fn verify_backup(
+    checkpoint_dir: &str,
+    mut checksums: Option<BTreeMap<Vec<u8>, [u8; 32]>>,
+) -> BackupReport {
+    let mut report = BackupReport {
+        keys: 0,
+        problems: vec![],
+    };
+    if checksums.is_none() {
+        report
+            .problems
+            .push("checksums missing or unreadable: values not checked".into());
+    }
+    let backup = match open_backup(checkpoint_dir) {
+        Ok(backup) => backup,
+        Err(e) => {
+            report.problems.push(format!("can't open backup: {e}"));
+            return report;
+        }
+    };
+    let kernel_id_vec = process_to_vec(KERNEL_PROCESS_ID.clone());
+    let mut has_process_map = false;
+    for item in backup.iterator(IteratorMode::Start) {
+        let (key, value) = match item {
+            Ok(item) => item,
+            Err(e) => {
+                report
+                    .problems
+                    .push(format!("reading stopped after {} keys: {e}", report.keys));
+                break;
+            }
+        };
+        report.keys += 1;
+        let name = String::from_utf8_lossy(&key).escape_debug().to_string();
+        if *key == *kernel_id_vec {
+            has_process_map = true;
+            if let Err(e) = bincode::deserialize::<ProcessMap>(&value) {
+                report
+                    .problems
+                    .push(format!("kernel process map doesn't deserialize: {e}"));
+            }
+        }
+        if let Some(ref mut checksums) = checksums {
+            match checksums.remove(&*key) {
+                Some(checksum) if checksum == *blake3::hash(&value).as_bytes() => {}
+                Some(_) => report.problems.push(format!("{name}: checksum mismatch")),
+                None => report.problems.push(format!("{name}: has no checksum")),
+            }
+        }
+    }
+    if !has_process_map {
+        report.problems.push("kernel process map missing".into());
+    }
+    for key in checksums.unwrap_or_default().keys() {
+        let name = String::from_utf8_lossy(key).escape_debug().to_string();
+        report.problems.push(format!("{name}: missing"));
+    }
+    report
 }
 
 fn process_to_vec(process: ProcessId) -> Vec<u8> {
     process.to_string().as_bytes().to_vec()
 }
+
+/// where a key of a process's state is saved: after its whole state, under
+/// a separator no process ID contains
+fn state_key(process: &ProcessId, key: &str) -> Vec<u8> {
+    format!("{process}\0{key}").into_bytes()
+}
+
+/// the range holding every key of a process's state
+fn state_key_range(process: &ProcessId) -> (Vec<u8>, Vec<u8>) {
+    (
+        format!("{process}\0").into_bytes(),
+        format!("{process}\u{1}").into_bytes(),
+    )
+}
@@ -0,0 +1,874 @@
+use crate::blobs::BlobStore;
+use dashmap::DashMap;
+use lib::types::core::{
+    Address, CapMessage, CapMessageSender, Capability, ConflictPolicy, KernelMessage, LazyLoadBlob,
+    Message, MessageReceiver, MessageSender, MirrorInfo, NetAction, NetResponse, NodeId, PackageId,
+    PrintSender, Printout, ProcessId, Request, Response, SyncError, SyncRequest, SyncResponse,
+    SyncedFile, VfsAction, VfsRequest, VfsResponse, SYNC_PROCESS_ID, VFS_PROCESS_ID,
+};
+use lib::types::errors::ModuleError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt, SeekFrom},
+    sync::{oneshot, Mutex},
+};
+
+/// how often every mirrored drive is synced with its peer
+const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+/// how long to wait on a peer before giving up on this round
+const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+/// most bytes of a file sent in one response
+const CHUNK_LEN: u64 = 256 * 1024;
+/// files with this in their name are conflict copies, which are not mirrored
+const CONFLICT_MARKER: &str = ".conflict-";
+/// files with this in their name are being fetched, and are not mirrored
+const PARTIAL_MARKER: &str = ".sync-partial-";
+/// sync capability to mirror, stop mirroring or sync any drive, as the
+/// node's owner does from the terminal
+pub const MIRROR_CAP_PARAMS: &str = "\"mirror\"";
+
+/// a drive mirrored with a peer, as persisted in `{home}/sync/mirrors.json`
+#[derive(Serialize, Deserialize)]
+struct Mirror {
+    drive: String,
+    peer: NodeId,
+    /// the peer's networking key when the mirror was added. a node of the
+    /// same name with another key, such as one registered after the name
+    /// lapsed, is refused until the mirror is added again.
+    #[serde(default)]
+    peer_key: Option<String>,
+    policy: ConflictPolicy,
+    /// the hash of each file as both nodes last had it, to tell which side
+    /// changed a file since
+    base: BTreeMap<String, String>,
+    last_sync: Option<u64>,
+    #[serde(skip)]
+    last_error: Option<String>,
+}
+
+impl Mirror {
+    fn info(&self) -> MirrorInfo {
+        MirrorInfo {
+            drive: self.drive.clone(),
+            peer: self.peer.clone(),
+            peer_key: self.peer_key.clone(),
+            policy: self.policy,
+            last_sync: self.last_sync,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+/// what every task of the module shares
+struct SyncService {
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    send_to_terminal: PrintSender,
+    send_to_caps_oracle: CapMessageSender,
+    blob_store: Arc<BlobStore>,
+    vfs_path: PathBuf,
+    sync_path: PathBuf,
+    mirrors: Mutex<Vec<Mirror>>,
+    /// the (drive, peer) pairs being synced now, so that rounds don't overlap
+    syncing: std::sync::Mutex<HashSet<(String, NodeId)>>,
+    /// our requests to peers awaiting a response, by ID
+    awaiting: DashMap<u64, oneshot::Sender<KernelMessage>>,
+    /// hashes of local files by path, valid while their length and
+    /// modification time are unchanged
+    hashes: DashMap<PathBuf, (u64, u64, String)>,
+}
+
+/// The sync runtime module: keeps chosen VFS drives mirrored with the same
+/// drives on other nodes of the same owner.
+///
+/// Each node pulls what changed on its peer: it lists the peer's files with
+/// their hashes, compares them with its own and with the hashes both had
+/// when they last synced, and fetches changed files in chunks. A drive is
+/// mirrored only once both nodes are told to mirror it with each other,
+/// which only their owner can do, and only with the node that had the peer's
+/// name when it was added.
+///
+/// Files are written through our vfs, as a process would write them, so that
+/// drive hooks see the change and a backup is not written to mid-snapshot.
+pub async fn sync(
+    our_node: Arc<String>,
+    send_to_loop: MessageSender,
+    send_to_terminal: PrintSender,
+    mut recv_from_loop: MessageReceiver,
+    send_to_caps_oracle: CapMessageSender,
+    home_directory_path: String,
+    blob_store: Arc<BlobStore>,
+) -> anyhow::Result<()> {
+    let sync_path = PathBuf::from(format!("{home_directory_path}/sync"));
+    fs::create_dir_all(&sync_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed creating sync dir! {e:?}"))?;
+    let mirrors: Vec<Mirror> = match fs::read(sync_path.join("mirrors.json")).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(_) => vec![],
+    };
+
+    let state = Arc::new(SyncService {
+        our_node,
+        send_to_loop,
+        send_to_terminal,
+        send_to_caps_oracle,
+        blob_store,
+        vfs_path: PathBuf::from(format!("{home_directory_path}/vfs")),
+        sync_path,
+        mirrors: Mutex::new(mirrors),
+        syncing: std::sync::Mutex::new(HashSet::new()),
+        awaiting: DashMap::new(),
+        hashes: DashMap::new(),
+    });
+
+    let mut interval = tokio::time::interval(SYNC_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let pairs: Vec<(String, NodeId)> = state
+                    .mirrors
+                    .lock()
+                    .await
+                    .iter()
+                    .map(|mirror| (mirror.drive.clone(), mirror.peer.clone()))
+                    .collect();
+                for (drive, peer) in pairs {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        state.sync_mirror(&drive, &peer).await;
+                    });
+                }
+            }
+            Some(km) = recv_from_loop.recv() => {
+                crate::metrics::handled(&SYNC_PROCESS_ID, &km);
+                if let Message::Response(_) = km.message {
+                    if let Some((_, sender)) = state.awaiting.remove(&km.id) {
+                        let _ = sender.send(km);
+                    }
+                    continue;
+                }
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let id = km.id;
+                    let target = km.rsvp.clone().or_else(|| match km.message {
+                        Message::Request(Request { expects_response: Some(_), .. }) => {
+                            Some(km.source.clone())
+                        }
+                        _ => None,
+                    });
+                    let (response, blob, metadata) = match state.handle_request(km).await {
+                        Ok((response, blob)) => (response, blob, None),
+                        Err(e) => {
+                            let metadata = e.metadata();
+                            (SyncResponse::Err(e), None, metadata)
+                        }
+                    };
+                    let Some(target) = target else {
+                        return;
+                    };
+                    KernelMessage::builder()
+                        .id(id)
+                        .source((state.our_node.as_str(), SYNC_PROCESS_ID.clone()))
+                        .target(target)
+                        .message(Message::Response((
+                            Response {
+                                inherit: false,
+                                body: serde_json::to_vec(&response).unwrap(),
+                                metadata,
+                                capabilities: vec![],
+                            },
+                            None,
+                        )))
+                        .lazy_load_blob(blob)
+                        .build()
+                        .unwrap()
+                        .send(&state.send_to_loop)
+                        .await;
+                });
+            }
+        }
+    }
+}
+
+impl SyncService {
+    async fn handle_request(
+        &self,
+        km: KernelMessage,
+    ) -> Result<(SyncResponse, Option<LazyLoadBlob>), SyncError> {
+        let Message::Request(request) = km.message else {
+            unreachable!("responses are routed to their awaiting requests");
+        };
+        let request: SyncRequest =
+            serde_json::from_slice(&request.body).map_err(|e| SyncError::BadRequest {
+                error: format!("didn't parse into SyncRequest: {e}"),
+            })?;
+
+        if km.source.node != *self.our_node {
+            // peers may only read the drives we mirror with them
+            let drive = match &request {
+                SyncRequest::Files { drive } | SyncRequest::Chunk { drive, .. } => drive,
+                _ => {
+                    return Err(SyncError::BadRequest {
+                        error: "peers may only list and fetch files".into(),
+                    })
+                }
+            };
+            let mirrored = km.source.process == *SYNC_PROCESS_ID
+                && self
+                    .mirrors
+                    .lock()
+                    .await
+                    .iter()
+                    .any(|mirror| &mirror.drive == drive && mirror.peer == km.source.node);
+            if !mirrored {
+                return Err(SyncError::NotMirrored {
+                    drive: drive.clone(),
+                    node: km.source.node,
+                });
+            }
+            self.verify_peer(drive, &km.source.node).await?;
+        }
+
+        match request {
+            SyncRequest::AddMirror {
+                drive,
+                peer,
+                policy,
+            } => {
+                self.drive_path(&drive)?;
+                self.check_owner(&drive, &km.source).await?;
+                if peer == *self.our_node {
+                    return Err(SyncError::BadRequest {
+                        error: "can't mirror a drive with ourselves".into(),
+                    });
+                }
+                // adding a mirror again takes the peer's key as it is now
+                let peer_key = self.peer_key(&peer).await?;
+                let mut mirrors = self.mirrors.lock().await;
+                if let Some(mirror) = mirrors
+                    .iter_mut()
+                    .find(|mirror| mirror.drive == drive && mirror.peer == peer)
+                {
+                    mirror.policy = policy;
+                    mirror.peer_key = Some(peer_key);
+                } else {
+                    mirrors.push(Mirror {
+                        drive: drive.clone(),
+                        peer: peer.clone(),
+                        peer_key: Some(peer_key),
+                        policy,
+                        base: BTreeMap::new(),
+                        last_sync: None,
+                        last_error: None,
+                    });
+                }
+                self.persist(&mirrors).await?;
+                Ok((SyncResponse::Ok, None))
+            }
+            SyncRequest::RemoveMirror { drive, peer } => {
+                self.check_write(&drive, &km.source).await?;
+                let mut mirrors = self.mirrors.lock().await;
+                mirrors.retain(|mirror| !(mirror.drive == drive && mirror.peer == peer));
+                self.persist(&mirrors).await?;
+                Ok((SyncResponse::Ok, None))
+            }
+            SyncRequest::ListMirrors => Ok((
+                SyncResponse::Mirrors(self.mirrors.lock().await.iter().map(Mirror::info).collect()),
+                None,
+            )),
+            SyncRequest::SyncNow { drive, peer } => {
+                // syncing writes the peer's changes into the drive
+                self.check_write(&drive, &km.source).await?;
+                let known = self
+                    .mirrors
+                    .lock()
+                    .await
+                    .iter()
+                    .any(|mirror| mirror.drive == drive && mirror.peer == peer);
+                if !known {
+                    return Err(SyncError::NotMirrored { drive, node: peer });
+                }
+                self.sync_mirror(&drive, &peer).await;
+                let mirrors = self.mirrors.lock().await;
+                match mirrors
+                    .iter()
+                    .find(|mirror| mirror.drive == drive && mirror.peer == peer)
+                    .and_then(|mirror| mirror.last_error.clone())
+                {
+                    None => Ok((SyncResponse::Ok, None)),
+                    Some(error) => Err(SyncError::PeerError { peer, error }),
+                }
+            }
+            SyncRequest::Files { drive } => {
+                let drive_path = self.drive_path(&drive)?;
+                // an empty list would tell the peer every file was removed
+                if !fs::metadata(&drive_path).await.is_ok_and(|m| m.is_dir()) {
+                    return Err(SyncError::BadRequest {
+                        error: format!("no drive {drive}"),
+                    });
+                }
+                Ok((
+                    SyncResponse::Files(self.list_files(&drive_path).await?),
+                    None,
+                ))
+            }
+            SyncRequest::Chunk {
+                drive,
+                path,
+                offset,
+            } => {
+                let path = file_path(&self.drive_path(&drive)?, &path)?;
+                let mut file = fs::File::open(&path).await?;
+                let len = file.metadata().await?.len();
+                file.seek(SeekFrom::Start(offset)).await?;
+                let mut bytes = vec![];
+                file.take(CHUNK_LEN).read_to_end(&mut bytes).await?;
+                Ok((
+                    SyncResponse::Chunk { len },
+                    Some(LazyLoadBlob { mime: None, bytes }),
+                ))
+            }
+        }
+    }
+
+    /// whether `source` holds the vfs write capability for `drive`, the vfs
+    /// root capability, or our mirror capability
+    async fn check_write(&self, drive: &str, source: &Address) -> Result<(), SyncError> {
+        if self.has_mirror_cap(source).await {
+            return Ok(());
+        }
+        self.has_any_cap(
+            &[
+                format!("{{\"kind\": \"write\", \"drive\": \"{drive}\"}}"),
+                "{\"root\":true}".to_string(),
+            ],
+            drive,
+            source,
+        )
+        .await
+    }
+
+    /// whether `source` is a process of the package that owns `drive`, or
+    /// holds the vfs root capability or our mirror capability. a write
+    /// capability is not enough to mirror a drive: that would send its
+    /// contents to another node.
+    async fn check_owner(&self, drive: &str, source: &Address) -> Result<(), SyncError> {
+        let owner = drive
+            .strip_prefix('/')
+            .and_then(|drive| drive.split('/').next());
+        let package = format!(
+            "{}:{}",
+            source.process.package(),
+            source.process.publisher()
+        );
+        if owner == Some(package.as_str()) || self.has_mirror_cap(source).await {
+            return Ok(());
+        }
+        self.has_any_cap(&["{\"root\":true}".to_string()], drive, source)
+            .await
+    }
+
+    async fn has_mirror_cap(&self, source: &Address) -> bool {
+        let (send_cap_bool, recv_cap_bool) = oneshot::channel();
+        let sent = self
+            .send_to_caps_oracle
+            .send(CapMessage::Has {
+                on: source.process.clone(),
+                cap: Capability::new(
+                    (self.our_node.as_str(), SYNC_PROCESS_ID.clone()),
+                    MIRROR_CAP_PARAMS,
+                ),
+                responder: send_cap_bool,
+            })
+            .await;
+        sent.is_ok() && recv_cap_bool.await.unwrap_or(false)
+    }
+
+    /// the networking key our PKI has for `peer`
+    async fn peer_key(&self, peer: &str) -> Result<String, SyncError> {
+        let net = ProcessId::new(Some("net"), "distro", "sys");
+        let body = rmp_serde::to_vec(&NetAction::GetPeer(peer.to_string())).unwrap();
+        let Some(km) = self.ask(&self.our_node, &net, body, None).await else {
+            return Err(SyncError::IOError {
+                error: "net did not respond".into(),
+            });
+        };
+        let Message::Response((response, _)) = km.message else {
+            unreachable!("only responses are routed to awaiting requests");
+        };
+        match rmp_serde::from_slice(&response.body) {
+            Ok(NetResponse::Peer(Some(identity))) => Ok(identity.networking_key),
+            _ => Err(SyncError::BadRequest {
+                error: format!("{peer} is not a node we know of"),
+            }),
+        }
+    }
+
+    /// whether `peer` has the networking key it had when the mirror of
+    /// `drive` with it was added. a mirror kept from before keys were noted
+    /// takes the key the peer has now.
+    async fn verify_peer(&self, drive: &str, peer: &str) -> Result<(), SyncError> {
+        let key = self.peer_key(peer).await?;
+        let mut mirrors = self.mirrors.lock().await;
+        let Some(mirror) = mirrors
+            .iter_mut()
+            .find(|mirror| mirror.drive == drive && mirror.peer == peer)
+        else {
+            return Err(SyncError::NotMirrored {
+                drive: drive.to_string(),
+                node: peer.to_string(),
+            });
+        };
+        match &mirror.peer_key {
+            Some(pinned) if *pinned != key => Err(SyncError::PeerKeyChanged {
+                peer: peer.to_string(),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                mirror.peer_key = Some(key);
+                self.persist(&mirrors).await
+            }
+        }
+    }
+
+    /// whether `source` holds any of the vfs capabilities with `params`
+    async fn has_any_cap(
+        &self,
+        params: &[String],
+        drive: &str,
+        source: &Address,
+    ) -> Result<(), SyncError> {
+        for params in params {
+            let (send_cap_bool, recv_cap_bool) = oneshot::channel();
+            let sent = self
+                .send_to_caps_oracle
+                .send(CapMessage::Has {
+                    on: source.process.clone(),
+                    cap: Capability::new(
+                        (self.our_node.as_str(), VFS_PROCESS_ID.clone()),
+                        params.clone(),
+                    ),
+                    responder: send_cap_bool,
+                })
+                .await;
+            if sent.is_ok() && recv_cap_bool.await.unwrap_or(false) {
+                return Ok(());
+            }
+        }
+        Err(SyncError::NoCap {
+            drive: drive.to_string(),
+        })
+    }
+
+    /// the directory of a drive given as `/package_id/drive`
+    fn drive_path(&self, drive: &str) -> Result<PathBuf, SyncError> {
+        let bad = || SyncError::BadRequest {
+            error: format!("{drive} is not a drive: expected /package_id/drive"),
+        };
+        let mut parts = drive.strip_prefix('/').ok_or_else(bad)?.split('/');
+        let (Some(package_id), Some(name), None) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(bad());
+        };
+        package_id.parse::<PackageId>().map_err(|_| bad())?;
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(bad());
+        }
+        Ok(self.vfs_path.join(package_id).join(name))
+    }
+
+    async fn persist(&self, mirrors: &[Mirror]) -> Result<(), SyncError> {
+        fs::write(
+            self.sync_path.join("mirrors.json"),
+            serde_json::to_vec_pretty(mirrors).unwrap(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// every file of a drive, but conflict copies
+    async fn list_files(&self, drive_path: &Path) -> Result<Vec<SyncedFile>, SyncError> {
+        let mut files = vec![];
+        let mut dirs = vec![drive_path.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(mut entries) = fs::read_dir(&dir).await else {
+                continue;
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                    continue;
+                }
+                if !metadata.is_file() {
+                    continue;
+                }
+                let path = entry.path();
+                let relative = path
+                    .strip_prefix(drive_path)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/");
+                if relative.contains(CONFLICT_MARKER) || relative.contains(PARTIAL_MARKER) {
+                    continue;
+                }
+                let modified = metadata
+                    .modified()?
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                let hash = self.hash(&path, metadata.len(), modified).await?;
+                files.push(SyncedFile {
+                    path: relative,
+                    hash,
+                    len: metadata.len(),
+                    modified,
+                });
+            }
+        }
+        Ok(files)
+    }
+
+    async fn hash(&self, path: &Path, len: u64, modified: u64) -> Result<String, SyncError> {
+        if let Some(cached) = self.hashes.get(path) {
+            if cached.0 == len && cached.1 == modified {
+                return Ok(cached.2.clone());
+            }
+        }
+        let mut file = fs::File::open(path).await?;
+        let mut hasher = blake3::Hasher::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        let hash = hasher.finalize().to_hex().to_string();
+        self.hashes
+            .insert(path.to_path_buf(), (len, modified, hash.clone()));
+        Ok(hash)
+    }
+
+    /// send a request to `process` on `node`, and wait for its response
+    async fn ask(
+        &self,
+        node: &str,
+        process: &ProcessId,
+        body: Vec<u8>,
+        blob: Option<LazyLoadBlob>,
+    ) -> Option<KernelMessage> {
+        let id: u64 = rand::random();
+        let (sender, receiver) = oneshot::channel();
+        self.awaiting.insert(id, sender);
+        KernelMessage::builder()
+            .id(id)
+            .source((self.our_node.as_str(), SYNC_PROCESS_ID.clone()))
+            .target((node, process.clone()))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: Some(PEER_TIMEOUT.as_secs()),
+                body,
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .lazy_load_blob(blob)
+            .build()
+            .unwrap()
+            .send(&self.send_to_loop)
+            .await;
+        let response = tokio::time::timeout(PEER_TIMEOUT, receiver).await;
+        self.awaiting.remove(&id);
+        response.ok()?.ok()
+    }
+
+    /// have our vfs carry out `action` on `path`, given as `/package_id/drive/...`
+    async fn vfs(
+        &self,
+        path: String,
+        action: VfsAction,
+        blob: Option<LazyLoadBlob>,
+    ) -> Result<(), SyncError> {
+        let body = serde_json::to_vec(&VfsRequest { path, action }).unwrap();
+        let Some(km) = self.ask(&self.our_node, &VFS_PROCESS_ID, body, blob).await else {
+            return Err(SyncError::IOError {
+                error: "vfs did not respond".into(),
+            });
+        };
+        let Message::Response((response, _)) = km.message else {
+            unreachable!("only responses are routed to awaiting requests");
+        };
+        match serde_json::from_slice(&response.body) {
+            Ok(VfsResponse::Err(e)) => Err(SyncError::IOError {
+                error: e.to_string(),
+            }),
+            Ok(_) => Ok(()),
+            Err(e) => Err(SyncError::IOError {
+                error: format!("unreadable vfs response: {e}"),
+            }),
+        }
+    }
+
+    async fn ask_peer(
+        &self,
+        peer: &str,
+        request: &SyncRequest,
+    ) -> Result<(SyncResponse, Option<LazyLoadBlob>), SyncError> {
+        let body = serde_json::to_vec(request).unwrap();
+        let Some(mut km) = self.ask(peer, &SYNC_PROCESS_ID, body, None).await else {
+            return Err(SyncError::Timeout {
+                peer: peer.to_string(),
+            });
+        };
+        self.blob_store.resolve(&mut km).await?;
+        let Message::Response((response, _)) = km.message else {
+            unreachable!("only responses are routed to awaiting requests");
+        };
+        match serde_json::from_slice(&response.body) {
+            Ok(SyncResponse::Err(e)) => Err(SyncError::PeerError {
+                peer: peer.to_string(),
+                error: e.to_string(),
+            }),
+            Ok(response) => Ok((response, km.lazy_load_blob)),
+            Err(e) => Err(SyncError::PeerError {
+                peer: peer.to_string(),
+                error: format!("unreadable response: {e}"),
+            }),
+        }
+    }
+
+    /// pull what changed on `peer` into our copy of `drive`, noting the
+    /// outcome on the mirror
+    async fn sync_mirror(&self, drive: &str, peer: &str) {
+        let key = (drive.to_string(), peer.to_string());
+        if !self.syncing.lock().unwrap().insert(key.clone()) {
+            return;
+        }
+        let result = self.pull(drive, peer).await;
+        self.syncing.lock().unwrap().remove(&key);
+
+        let mut mirrors = self.mirrors.lock().await;
+        let Some(mirror) = mirrors
+            .iter_mut()
+            .find(|mirror| mirror.drive == drive && mirror.peer == peer)
+        else {
+            return;
+        };
+        match result {
+            Ok(base) => {
+                mirror.base = base;
+                mirror.last_sync = Some(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs(),
+                );
+                mirror.last_error = None;
+            }
+            Err(e) => {
+                Printout::new(2, format!("sync: {drive} with {peer}: {e}"))
+                    .send(&self.send_to_terminal)
+                    .await;
+                mirror.last_error = Some(e.to_string());
+            }
+        }
+        if let Err(e) = self.persist(&mirrors).await {
+            Printout::new(1, format!("sync: couldn't save mirrors: {e}"))
+                .send(&self.send_to_terminal)
+                .await;
+        }
+    }
+
+    /// one round of pulling a drive from a peer. returns the new base.
+    async fn pull(&self, drive: &str, peer: &str) -> Result<BTreeMap<String, String>, SyncError> {
+        let (policy, mut base) = {
+            let mirrors = self.mirrors.lock().await;
+            let Some(mirror) = mirrors
+                .iter()
+                .find(|mirror| mirror.drive == drive && mirror.peer == peer)
+            else {
+                return Err(SyncError::NotMirrored {
+                    drive: drive.to_string(),
+                    node: peer.to_string(),
+                });
+            };
+            (mirror.policy, mirror.base.clone())
+        };
+        self.verify_peer(drive, peer).await?;
+        let drive_path = self.drive_path(drive)?;
+        let request = SyncRequest::Files {
+            drive: drive.to_string(),
+        };
+        let (SyncResponse::Files(theirs), _) = self.ask_peer(peer, &request).await? else {
+            return Err(SyncError::PeerError {
+                peer: peer.to_string(),
+                error: "expected Files".into(),
+            });
+        };
+        let theirs: BTreeMap<String, SyncedFile> = theirs
+            .into_iter()
+            .filter(|file| {
+                !file.path.contains(CONFLICT_MARKER) && !file.path.contains(PARTIAL_MARKER)
+            })
+            .map(|file| (file.path.clone(), file))
+            .collect();
+        let ours: BTreeMap<String, SyncedFile> = self
+            .list_files(&drive_path)
+            .await?
+            .into_iter()
+            .map(|file| (file.path.clone(), file))
+            .collect();
+
+        let paths: BTreeSet<String> = ours.keys().chain(theirs.keys()).cloned().collect();
+        for path in paths {
+            let last = base.get(&path).cloned();
+            match (ours.get(&path), theirs.get(&path)) {
+                (Some(our), Some(their)) if our.hash == their.hash => {
+                    base.insert(path, our.hash.clone());
+                }
+                (Some(our), Some(their)) => {
+                    if last.as_ref() == Some(&their.hash) {
+                        // only we changed it: the peer will pull it
+                        continue;
+                    }
+                    if last.as_ref() != Some(&our.hash) {
+                        // both changed it
+                        let take_theirs = match policy {
+                            ConflictPolicy::Newest => {
+                                (their.modified, peer) > (our.modified, self.our_node.as_str())
+                            }
+                            ConflictPolicy::Ours => false,
+                            ConflictPolicy::Theirs => true,
+                            ConflictPolicy::KeepBoth => {
+                                let copy = format!("{path}{CONFLICT_MARKER}{peer}");
+                                self.fetch(drive, &drive_path, peer, their, &copy).await?;
+                                false
+                            }
+                        };
+                        if !take_theirs {
+                            if policy != ConflictPolicy::Newest {
+                                // settled: don't take it up again until theirs changes
+                                base.insert(path, their.hash.clone());
+                            }
+                            continue;
+                        }
+                    }
+                    self.fetch(drive, &drive_path, peer, their, &path).await?;
+                    base.insert(path, their.hash.clone());
+                }
+                (Some(our), None) => {
+                    if last.as_ref() == Some(&our.hash) {
+                        // unchanged here, removed there
+                        file_path(&drive_path, &path)?;
+                        self.vfs(format!("{drive}/{path}"), VfsAction::RemoveFile, None)
+                            .await?;
+                    }
+                    // otherwise it is new or changed here, and the peer will pull it
+                    base.remove(&path);
+                }
+                (None, Some(their)) => {
+                    if last.as_ref() == Some(&their.hash) {
+                        // removed here, unchanged there: the peer will remove it
+                        continue;
+                    }
+                    self.fetch(drive, &drive_path, peer, their, &path).await?;
+                    base.insert(path, their.hash.clone());
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+        base.retain(|path, _| ours.contains_key(path) || theirs.contains_key(path));
+        Ok(base)
+    }
+
+    /// fetch a peer's file in chunks, beside `to` in our drive, and put it
+    /// at `to` once all of it has arrived and matches its hash
+    async fn fetch(
+        &self,
+        drive: &str,
+        drive_path: &Path,
+        peer: &str,
+        file: &SyncedFile,
+        to: &str,
+    ) -> Result<(), SyncError> {
+        file_path(drive_path, to)?;
+        let partial = format!("{drive}/{to}{PARTIAL_MARKER}{:016x}", rand::random::<u64>());
+        if let Some((parent, _)) = to.rsplit_once('/') {
+            self.vfs(format!("{drive}/{parent}"), VfsAction::CreateDirAll, None)
+                .await?;
+        }
+        let result = async {
+            self.vfs(partial.clone(), VfsAction::CreateFile, None)
+                .await?;
+            let mut hasher = blake3::Hasher::new();
+            let mut offset = 0;
+            loop {
+                let request = SyncRequest::Chunk {
+                    drive: drive.to_string(),
+                    path: file.path.clone(),
+                    offset,
+                };
+                let (SyncResponse::Chunk { len }, blob) = self.ask_peer(peer, &request).await?
+                else {
+                    return Err(SyncError::PeerError {
+                        peer: peer.to_string(),
+                        error: "expected Chunk".into(),
+                    });
+                };
+                let bytes = blob.map(|blob| blob.bytes).unwrap_or_default();
+                let read = bytes.len() as u64;
+                hasher.update(&bytes);
+                if !bytes.is_empty() {
+                    let blob = LazyLoadBlob { mime: None, bytes };
+                    self.vfs(partial.clone(), VfsAction::Append, Some(blob))
+                        .await?;
+                }
+                offset += read;
+                if offset >= len || read == 0 {
+                    break;
+                }
+            }
+            self.vfs(partial.clone(), VfsAction::CloseFile, None)
+                .await?;
+            if hasher.finalize().to_hex().as_str() != file.hash {
+                // changed on the peer while we fetched it: try again next round
+                return Err(SyncError::PeerError {
+                    peer: peer.to_string(),
+                    error: format!("{} changed while fetched", file.path),
+                });
+            }
+            let new_path = format!("{drive}/{to}");
+            self.vfs(partial.clone(), VfsAction::Rename { new_path }, None)
+                .await
+        }
+        .await;
+        if result.is_err() {
+            let _ = self.vfs(partial, VfsAction::RemoveFile, None).await;
+        }
+        result
+    }
+}
+
+/// a file in a drive, by a path within it that may not leave it
+fn file_path(drive_path: &Path, path: &str) -> Result<PathBuf, SyncError> {
+    let relative = Path::new(path);
+    if path.is_empty()
+        || !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(SyncError::BadRequest {
+            error: format!("{path} is not a path within a drive"),
+        });
+    }
+    Ok(drive_path.join(relative))
+}
@@ -1,24 +1,36 @@
 use chrono::{Datelike, Local, Timelike};
 use crossterm::{
     cursor,
-    event::{Event, EventStream, KeyCode, KeyEvent, KeyModifiers},
+    event::{
+        Event, EventStream, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     execute, style,
     style::Print,
     terminal::{self, ClearType},
 };
 use futures::{future::FutureExt, StreamExt};
 use lib::types::core::{
-    DebugCommand, DebugSender, Identity, KernelMessage, Message, MessageSender, PrintReceiver,
-    PrintSender, Printout, Request, TERMINAL_PROCESS_ID,
+    DebugCommand, DebugSender, Identity, KernelMessage, LogLevel, Message, MessageSender,
+    PrintReceiver, PrintSender, Printout, ProcessId, Request, TERMINAL_PROCESS_ID,
 };
 use std::{
     fs::{read_to_string, OpenOptions},
-    io::{BufWriter, Write},
+    io::{BufWriter, IsTerminal, Write},
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
 };
-use tokio::signal::unix::{signal, SignalKind};
 
+pub mod remote;
+mod repeats;
+pub mod secret;
 pub mod utils;
 
+/// how many printed rows the terminal keeps to scroll back through
+const SCROLLBACK_ROWS: usize = 10_000;
+
 pub struct State {
     pub stdout: std::io::Stdout,
     pub log_writer: BufWriter<std::fs::File>,
@@ -34,6 +46,27 @@ pub struct State {
     pub search_depth: usize,
     pub logging_mode: bool,
     pub verbose_mode: u8,
+    /// if set, only prints from these processes are shown
+    pub log_filter: Option<Vec<ProcessId>>,
+    /// where prints go while a command from the socket runs
+    pub remote_output: Option<mpsc::UnboundedSender<String>>,
+    /// web terminals connected through http_server, which get every print
+    pub web_terminals: Vec<mpsc::Sender<Printout>>,
+    pub scrollback: utils::Scrollback,
+    /// where CTRL+O dumps the scrollback: the scrollback drive of
+    /// terminal:sys in the VFS
+    pub scrollback_dir: std::path::PathBuf,
+    /// the window title, which also shows how many notifications are unread
+    pub title: String,
+    pub unread_shown: usize,
+    /// whether stdout is a file or a pipe rather than a terminal, in which
+    /// case prints are written without their styles
+    pub plain_output: bool,
+    /// prints held back from the terminal for repeating or coming too fast
+    pub repeats: repeats::Repeats,
+    /// a line asked for with `KernelCommand::ReadSecret`, being typed in
+    /// place of the input line
+    pub secret: Option<secret::Reading>,
 }
 
 /*
@@ -47,17 +80,20 @@ pub async fn terminal(
     mut debug_event_loop: DebugSender,
     mut print_tx: PrintSender,
     mut print_rx: PrintReceiver,
+    mut web_terminal: remote::WebTerminalReceiver,
     is_detached: bool,
     verbose_mode: u8,
+    log_filter: Option<Vec<ProcessId>>,
 ) -> anyhow::Result<()> {
     let (stdout, _maybe_raw_mode) = utils::startup(&our, version, is_detached)?;
 
-    let (win_cols, win_rows) = crossterm::terminal::size().expect("terminal: couldn't fetch size");
+    // a detached node may have no tty at all
+    let (win_cols, win_rows) = crossterm::terminal::size().unwrap_or((80, 24));
 
     let current_line = format!("{} > ", our.name);
-    let prompt_len: usize = our.name.len() + 3;
-    let cursor_col: u16 = prompt_len as u16;
-    let line_col: usize = cursor_col as usize;
+    let prompt_len: usize = current_line.len();
+    let cursor_col: u16 = utils::width(&current_line) as u16;
+    let line_col: usize = prompt_len;
 
     let in_step_through: bool = false;
 
@@ -93,6 +129,12 @@ pub async fn terminal(
         .expect("terminal: could not open/create .terminal_log");
     let log_writer = BufWriter::new(log_handle);
 
+    let scrollback_dir = std::fs::canonicalize(&home_directory_path)
+        .expect("terminal: could not get path for scrollback drive")
+        .join("vfs")
+        .join("terminal:sys")
+        .join("scrollback");
+
     let mut state = State {
         stdout,
         log_writer,
@@ -108,8 +150,34 @@ pub async fn terminal(
         search_depth,
         logging_mode,
         verbose_mode,
+        log_filter,
+        remote_output: None,
+        web_terminals: vec![],
+        scrollback: utils::Scrollback::new(SCROLLBACK_ROWS),
+        scrollback_dir,
+        title: format!("kinode {}", our.name),
+        unread_shown: 0,
+        plain_output: !std::io::stdout().is_terminal(),
+        repeats: repeats::Repeats::default(),
+        secret: None,
     };
 
+    // command lines can also come from a socket in the home directory, and,
+    // if detached with stdin piped in, from stdin
+    let (remote_tx, mut remote_rx) = mpsc::channel::<remote::RemoteCommand>(32);
+    let socket_path = std::fs::canonicalize(&home_directory_path)
+        .expect("terminal: could not get path for terminal socket")
+        .join(remote::SOCKET_NAME);
+    if let Err(e) = remote::listen(&socket_path, remote_tx.clone()) {
+        Printout::new(0, format!("terminal: could not open {socket_path:?}: {e}"))
+            .send(&print_tx)
+            .await;
+    }
+    if is_detached && !std::io::stdin().is_terminal() {
+        remote::read_stdin(remote_tx);
+    }
+    let mut secret_asks = secret::listen();
+
     // use to trigger cleanup if receive signal to kill process
     let mut sigalrm =
         signal(SignalKind::alarm()).expect("terminal: failed to set up SIGALRM handler");
@@ -138,6 +206,9 @@ pub async fn terminal(
             .expect("failed to toggle full event loop off");
     }
 
+    // counts of repeated prints are shown once they have been held back a while
+    let mut repeats_tick = tokio::time::interval(repeats::REPEAT_WINDOW);
+
     // only create event stream if not in detached mode
     if !is_detached {
         let mut reader = EventStream::new();
@@ -145,11 +216,37 @@ pub async fn terminal(
             tokio::select! {
                 Some(printout) = print_rx.recv() => {
                     handle_printout(printout, &mut state)?;
+                    show_unread(&mut state)?;
+                }
+                _ = repeats_tick.tick() => {
+                    for printout in state.repeats.flush() {
+                        show_printout(printout, &mut state)?;
+                    }
                 }
                 Some(Ok(event)) = reader.next().fuse() => {
                     if handle_event(&our, event, &mut state, &mut event_loop, &mut debug_event_loop, &mut print_tx).await? {
                         break;
                     }
+                    show_unread(&mut state)?;
+                }
+                Some(command) = remote_rx.recv() => {
+                    // a command from stdin prints to stdout as usual, leaving
+                    // one from the socket that's running its output
+                    if command.output.is_some() {
+                        state.remote_output = command.output;
+                    }
+                    send_command(&our, command.line, &mut state, &event_loop).await?;
+                }
+                Some(request) = web_terminal.recv() => {
+                    handle_web_request(&our, request, &mut state, &event_loop).await?;
+                }
+                Some(mut ask) = secret_asks.recv() => {
+                    ask.prompt = utils::sanitize(&ask.prompt);
+                    // a script asking again cancels what was asked before
+                    if let Some(cancelled) = state.secret.replace(secret::Reading::new(ask)) {
+                        secret::answer(&our.name, cancelled.requester, None, &event_loop).await;
+                    }
+                    show_secret_prompt(&mut state)?;
                 }
                 _ = sigalrm.recv() => return Err(anyhow::anyhow!("exiting due to SIGALRM")),
                 _ = sighup.recv() =>  return Err(anyhow::anyhow!("exiting due to SIGHUP")),
@@ -167,6 +264,29 @@ pub async fn terminal(
                 Some(printout) = print_rx.recv() => {
                     handle_printout(printout, &mut state)?;
                 }
+                _ = repeats_tick.tick() => {
+                    for printout in state.repeats.flush() {
+                        show_printout(printout, &mut state)?;
+                    }
+                }
+                Some(command) = remote_rx.recv() => {
+                    // a command from stdin prints to stdout as usual, leaving
+                    // one from the socket that's running its output
+                    if command.output.is_some() {
+                        state.remote_output = command.output;
+                    }
+                    send_command(&our, command.line, &mut state, &event_loop).await?;
+                }
+                Some(request) = web_terminal.recv() => {
+                    handle_web_request(&our, request, &mut state, &event_loop).await?;
+                }
+                Some(ask) = secret_asks.recv() => {
+                    // with no keyboard, there is no one to type the line
+                    Printout::new(0, "terminal: can't read a secret line while detached")
+                        .send(&print_tx)
+                        .await;
+                    secret::answer(&our.name, ask.requester, None, &event_loop).await;
+                }
                 _ = sigalrm.recv() => return Err(anyhow::anyhow!("exiting due to SIGALRM")),
                 _ = sighup.recv() =>  return Err(anyhow::anyhow!("exiting due to SIGHUP")),
                 _ = sigint.recv() =>  return Err(anyhow::anyhow!("exiting due to SIGINT")),
@@ -181,63 +301,299 @@ pub async fn terminal(
     Ok(())
 }
 
-fn handle_printout(printout: Printout, state: &mut State) -> anyhow::Result<()> {
-    // lock here so that runtime can still use println! without freezing..
-    // can lock before loop later if we want to reduce overhead
-    let mut stdout = state.stdout.lock();
-    let now = Local::now();
-    // always write print to log if in logging mode
-    if state.logging_mode {
+/// show how many notifications are unread in the window title, if that
+/// changed since last shown
+fn show_unread(state: &mut State) -> anyhow::Result<()> {
+    let unread = crate::notify::unread();
+    if unread == state.unread_shown {
+        return Ok(());
+    }
+    state.unread_shown = unread;
+    let title = match unread {
+        0 => state.title.clone(),
+        1 => format!("{} (1 notification)", state.title),
+        n => format!("{} ({n} notifications)", state.title),
+    };
+    execute!(state.stdout, terminal::SetTitle(title))?;
+    Ok(())
+}
+
+/// show the prompt of the secret line being read in place of the input line,
+/// with nothing typed shown
+fn show_secret_prompt(state: &mut State) -> anyhow::Result<()> {
+    let Some(reading) = &state.secret else {
+        return Ok(());
+    };
+    execute!(
+        state.stdout,
+        cursor::MoveTo(0, state.win_rows),
+        terminal::Clear(ClearType::CurrentLine),
+        Print(&reading.prompt),
+    )?;
+    Ok(())
+}
+
+/// send a command line to the terminal process
+async fn send_command(
+    our: &Identity,
+    command: String,
+    state: &mut State,
+    event_loop: &MessageSender,
+) -> anyhow::Result<()> {
+    for printout in state.repeats.reset() {
+        show_printout(printout, state)?;
+    }
+    KernelMessage::builder()
+        .id(rand::random())
+        .source((our.name.as_str(), TERMINAL_PROCESS_ID.clone()))
+        .target((our.name.as_str(), TERMINAL_PROCESS_ID.clone()))
+        .message(Message::Request(Request {
+            inherit: false,
+            expects_response: None,
+            body: command.into_bytes(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .unwrap()
+        .send(event_loop)
+        .await;
+    Ok(())
+}
+
+async fn handle_web_request(
+    our: &Identity,
+    request: remote::WebTerminalRequest,
+    state: &mut State,
+    event_loop: &MessageSender,
+) -> anyhow::Result<()> {
+    match request {
+        remote::WebTerminalRequest::Attach(printouts) => state.web_terminals.push(printouts),
+        remote::WebTerminalRequest::Command(command) => {
+            state.command_history.add(command.clone());
+            send_command(our, command, state, event_loop).await?;
+        }
+        remote::WebTerminalRequest::History(result) => {
+            let _ = result.send(state.command_history.recent());
+        }
+        remote::WebTerminalRequest::Search {
+            query,
+            depth,
+            result,
+        } => {
+            let found = state.command_history.search(&query, depth);
+            let _ = result.send(found.map(str::to_string));
+        }
+    }
+    Ok(())
+}
+
+/// the process and level a print is tagged with, for the log file
+fn tag(printout: &Printout) -> String {
+    match (&printout.source, printout.level) {
+        (Some(source), Some(level)) => format!(" [{source} {level}]"),
+        (Some(source), None) => format!(" [{source}]"),
+        (None, _) => String::new(),
+    }
+}
+
+fn handle_printout(mut printout: Printout, state: &mut State) -> anyhow::Result<()> {
+    // a process may style what it prints, but not move the cursor, clear the
+    // screen or otherwise take over the terminal
+    printout.content = utils::sanitize(&printout.content);
+    // always write print to log if in logging mode, unless disk space is low
+    if state.logging_mode && !crate::disk::is_low() {
         writeln!(
             state.log_writer,
-            "[{}] {}",
-            now.to_rfc2822(),
-            printout.content
+            "[{}]{} {}",
+            Local::now().to_rfc2822(),
+            tag(&printout),
+            utils::strip_escapes(&printout.content)
         )?;
     }
+    // web terminals get every print, to filter as they like
+    state.web_terminals.retain(|web_terminal| {
+        !matches!(
+            web_terminal.try_send(printout.clone()),
+            Err(mpsc::error::TrySendError::Closed(_))
+        )
+    });
+    // pass prints on to the command from the socket that's running, if any
+    if printout.verbosity == 0 {
+        if let Some(output) = &state.remote_output {
+            if output
+                .send(utils::strip_escapes(&printout.content))
+                .is_err()
+            {
+                state.remote_output = None;
+            }
+        }
+    }
     // skip writing print to terminal if it's of a greater
     // verbosity level than our current mode
     if printout.verbosity > state.verbose_mode {
         return Ok(());
     }
+    // or if it's from a process we've filtered out
+    if let (Some(filter), Some(source)) = (&state.log_filter, &printout.source) {
+        if !filter.contains(source) {
+            return Ok(());
+        }
+    }
+    // a print repeated, or one of a flood, is held back
+    for printout in state.repeats.admit(printout) {
+        show_printout(printout, state)?;
+    }
+    Ok(())
+}
+
+/// write a print to the terminal, above the input line
+fn show_printout(printout: Printout, state: &mut State) -> anyhow::Result<()> {
+    // lock here so that runtime can still use println! without freezing..
+    // can lock before loop later if we want to reduce overhead
+    let mut stdout = state.stdout.lock();
+    let now = Local::now();
+    let time = format!("{} {:02}:{:02} ", now.weekday(), now.hour(), now.minute());
+    let level = printout
+        .level
+        .map_or(String::new(), |level| format!("{level} "));
+    let prefix = format!("{time}{level}");
+    // wrap lines ourselves, so that a wide character is never split across rows
+    let width = state.win_cols as usize;
+    let mut rows = vec![];
+    let mut col = utils::width(&prefix);
+    for line in printout.content.lines() {
+        rows.extend(utils::wrap(line, width.saturating_sub(col), width));
+        col = 0;
+    }
+    for (i, row) in rows.iter().enumerate() {
+        state.scrollback.push(if i == 0 {
+            format!("{prefix}{row}")
+        } else {
+            row.to_string()
+        });
+    }
+    // while scrolled back, the view stays where it is
+    if state.scrollback.is_scrolled() {
+        return Ok(());
+    }
     execute!(
         stdout,
         // print goes immediately above the dedicated input line at bottom
         cursor::MoveTo(0, state.win_rows - 1),
         terminal::Clear(ClearType::CurrentLine),
-        Print(format!(
-            "{} {:02}:{:02} ",
-            now.weekday(),
-            now.hour(),
-            now.minute(),
-        )),
-        style::SetForegroundColor(match printout.verbosity {
-            0 => style::Color::Reset,
-            1 => style::Color::Green,
-            2 => style::Color::Magenta,
-            _ => style::Color::Red,
+        Print(time),
+        style::SetForegroundColor(match (printout.level, printout.verbosity) {
+            (Some(LogLevel::Error), _) => style::Color::Red,
+            (Some(LogLevel::Warn), _) => style::Color::Yellow,
+            (Some(LogLevel::Info), _) => style::Color::Reset,
+            (Some(LogLevel::Debug), _) => style::Color::Green,
+            (Some(LogLevel::Trace), _) => style::Color::Magenta,
+            (None, 0) => style::Color::Reset,
+            (None, 1) => style::Color::Green,
+            (None, 2) => style::Color::Magenta,
+            (None, _) => style::Color::Red,
         }),
+        Print(level),
     )?;
-    for line in printout.content.lines() {
-        execute!(stdout, Print(format!("{}\r\n", line)),)?;
+    for row in rows {
+        let row = if state.plain_output {
+            utils::strip_escapes(&row)
+        } else {
+            row
+        };
+        execute!(stdout, Print(format!("{}\r\n", row)),)?;
+    }
+    // while a secret line is read, its prompt stands in for the input line
+    if let Some(reading) = &state.secret {
+        execute!(
+            stdout,
+            style::ResetColor,
+            cursor::MoveTo(0, state.win_rows),
+            Print(&reading.prompt),
+        )?;
+        return Ok(());
     }
     // reset color and re-display the current input line
     // re-place cursor where user had it at input line
+    let line;
+    (line, state.cursor_col) = utils::truncate_in_place(
+        &state.current_line,
+        state.prompt_len,
+        state.win_cols,
+        (state.line_col, state.cursor_col),
+    );
     execute!(
         stdout,
         style::ResetColor,
         cursor::MoveTo(0, state.win_rows),
-        Print(utils::truncate_in_place(
-            &state.current_line,
-            state.prompt_len,
-            state.win_cols,
-            (state.line_col, state.cursor_col)
-        )),
+        Print(line),
         cursor::MoveTo(state.cursor_col, state.win_rows),
     )?;
     Ok(())
 }
 
+/// move and select in copy mode, returning true if the event was one for it.
+/// keys with CTRL are left to work as they do outside of it.
+fn handle_copy_mode(
+    event: &Event,
+    stdout: &mut std::io::StdoutLock,
+    scrollback: &mut utils::Scrollback,
+    win_rows: u16,
+) -> anyhow::Result<bool> {
+    let page = win_rows.saturating_sub(3) as isize;
+    match event {
+        Event::Key(KeyEvent {
+            code, modifiers, ..
+        }) if !modifiers.contains(KeyModifiers::CONTROL) => match code {
+            KeyCode::Up | KeyCode::Char('k') => scrollback.move_cursor(-1, 0, win_rows),
+            KeyCode::Down | KeyCode::Char('j') => scrollback.move_cursor(1, 0, win_rows),
+            KeyCode::Left | KeyCode::Char('h') => scrollback.move_cursor(0, -1, win_rows),
+            KeyCode::Right | KeyCode::Char('l') => scrollback.move_cursor(0, 1, win_rows),
+            KeyCode::PageUp => scrollback.move_cursor(-page, 0, win_rows),
+            KeyCode::PageDown => scrollback.move_cursor(page, 0, win_rows),
+            KeyCode::Char(' ') | KeyCode::Char('v') => scrollback.select(),
+            KeyCode::Enter | KeyCode::Char('y') => {
+                if let Some(selected) = scrollback.selection() {
+                    utils::copy_to_clipboard(stdout, &selected)?;
+                }
+                exit_copy_mode(stdout, scrollback)?;
+            }
+            KeyCode::Esc | KeyCode::Char('q') => exit_copy_mode(stdout, scrollback)?,
+            _ => {}
+        },
+        Event::Mouse(MouseEvent {
+            kind, column, row, ..
+        }) => match kind {
+            MouseEventKind::Down(MouseButton::Left) => scrollback.press((*column, *row), win_rows),
+            MouseEventKind::Drag(MouseButton::Left) => scrollback.drag((*column, *row), win_rows),
+            MouseEventKind::Up(MouseButton::Left) => {
+                // a click selects nothing; a drag copies what it selected
+                if let Some(selected) = scrollback.selection() {
+                    if utils::width(&selected) > 1 {
+                        utils::copy_to_clipboard(stdout, &selected)?;
+                        exit_copy_mode(stdout, scrollback)?;
+                    }
+                }
+            }
+            MouseEventKind::ScrollUp => scrollback.move_cursor(-3, 0, win_rows),
+            MouseEventKind::ScrollDown => scrollback.move_cursor(3, 0, win_rows),
+            _ => {}
+        },
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn exit_copy_mode(
+    stdout: &mut std::io::StdoutLock,
+    scrollback: &mut utils::Scrollback,
+) -> anyhow::Result<()> {
+    scrollback.exit_copy_mode();
+    execute!(stdout, crossterm::event::DisableMouseCapture)?;
+    Ok(())
+}
+
 /// returns True if runtime should exit due to CTRL+C or CTRL+D
 async fn handle_event(
     our: &Identity,
@@ -247,6 +603,38 @@ async fn handle_event(
     debug_event_loop: &mut DebugSender,
     print_tx: &mut PrintSender,
 ) -> anyhow::Result<bool> {
+    // keys typed while a secret line is read go to it alone, and are not shown
+    if let Some(reading) = &mut state.secret {
+        let done = match &event {
+            Event::Key(key) => reading.key(key),
+            Event::Paste(pasted) => {
+                reading.paste(pasted);
+                None
+            }
+            _ => None,
+        };
+        if let Some(input) = done {
+            let reading = state.secret.take().unwrap();
+            secret::answer(&our.name, reading.requester, input, event_loop).await;
+            let line;
+            (line, state.cursor_col) = utils::truncate_in_place(
+                &state.current_line,
+                state.prompt_len,
+                state.win_cols,
+                (state.line_col, state.cursor_col),
+            );
+            execute!(
+                state.stdout,
+                cursor::MoveTo(0, state.win_rows),
+                terminal::Clear(ClearType::CurrentLine),
+                Print(line),
+                cursor::MoveTo(state.cursor_col, state.win_rows),
+            )?;
+        }
+        if matches!(event, Event::Key(_) | Event::Paste(_)) {
+            return Ok(false);
+        }
+    }
     let State {
         stdout,
         command_history,
@@ -261,11 +649,17 @@ async fn handle_event(
         search_depth,
         logging_mode,
         verbose_mode,
+        scrollback,
+        scrollback_dir,
         ..
     } = state;
     // lock here so that runtime can still use println! without freezing..
     // can lock before loop later if we want to reduce overhead
     let mut stdout = stdout.lock();
+    if scrollback.is_copying() && handle_copy_mode(&event, &mut stdout, scrollback, *win_rows)? {
+        scrollback.draw(&mut stdout, (*win_cols, *win_rows), *cursor_col)?;
+        return Ok(false);
+    }
     match event {
         //
         // RESIZE: resize is super annoying because this event trigger often
@@ -289,19 +683,20 @@ async fn handle_event(
                 .collect::<String>();
             current_line.insert_str(*line_col, &pasted);
             *line_col = *line_col + pasted.len();
-            *cursor_col = std::cmp::min(
-                line_col.to_owned().try_into().unwrap_or(*win_cols),
+            let line;
+            (line, *cursor_col) = utils::truncate_in_place(
+                &current_line,
+                *prompt_len,
                 *win_cols,
+                (
+                    *line_col,
+                    cursor_col.saturating_add(utils::width(&pasted) as u16),
+                ),
             );
             execute!(
                 stdout,
                 cursor::MoveTo(0, *win_rows),
-                Print(utils::truncate_in_place(
-                    &current_line,
-                    *prompt_len,
-                    *win_cols,
-                    (*line_col, *cursor_col)
-                )),
+                Print(line),
                 cursor::MoveTo(*cursor_col, *win_rows),
             )?;
         }
@@ -442,16 +837,14 @@ async fn handle_event(
                     print!("\x07");
                 }
             }
-            *cursor_col = std::cmp::min(current_line.len() as u16, *win_cols);
+            let line;
+            (line, *cursor_col) = utils::truncate_rightward(&current_line, *prompt_len, *win_cols);
             execute!(
                 stdout,
                 cursor::MoveTo(0, *win_rows),
                 terminal::Clear(ClearType::CurrentLine),
-                Print(utils::truncate_rightward(
-                    current_line,
-                    *prompt_len,
-                    *win_cols
-                )),
+                Print(line),
+                cursor::MoveTo(*cursor_col, *win_rows),
             )?;
         }
         //
@@ -477,16 +870,14 @@ async fn handle_event(
                     print!("\x07");
                 }
             }
-            *cursor_col = std::cmp::min(current_line.len() as u16, *win_cols);
+            let line;
+            (line, *cursor_col) = utils::truncate_rightward(&current_line, *prompt_len, *win_cols);
             execute!(
                 stdout,
                 cursor::MoveTo(0, *win_rows),
                 terminal::Clear(ClearType::CurrentLine),
-                Print(utils::truncate_rightward(
-                    &current_line,
-                    *prompt_len,
-                    *win_cols
-                )),
+                Print(line),
+                cursor::MoveTo(*cursor_col, *win_rows),
             )?;
         }
         //
@@ -498,16 +889,14 @@ async fn handle_event(
             ..
         }) => {
             *line_col = *prompt_len;
-            *cursor_col = *prompt_len as u16;
+            let line;
+            (line, *cursor_col) =
+                utils::truncate_from_left(&current_line, *prompt_len, *win_cols, *line_col);
             execute!(
                 stdout,
                 cursor::MoveTo(0, *win_rows),
-                Print(utils::truncate_from_left(
-                    &current_line,
-                    *prompt_len,
-                    *win_cols,
-                    *line_col
-                )),
+                terminal::Clear(ClearType::CurrentLine),
+                Print(line),
                 cursor::MoveTo(*cursor_col, *win_rows),
             )?;
         }
@@ -520,22 +909,91 @@ async fn handle_event(
             ..
         }) => {
             *line_col = current_line.len();
-            *cursor_col = std::cmp::min(
-                line_col.to_owned().try_into().unwrap_or(*win_cols),
-                *win_cols,
-            );
+            let line;
+            (line, *cursor_col) =
+                utils::truncate_from_right(&current_line, *prompt_len, *win_cols, *line_col);
             execute!(
                 stdout,
                 cursor::MoveTo(0, *win_rows),
-                Print(utils::truncate_from_right(
-                    &current_line,
-                    *prompt_len,
-                    *win_cols,
-                    *line_col
-                )),
+                terminal::Clear(ClearType::CurrentLine),
+                Print(line),
+                cursor::MoveTo(*cursor_col, *win_rows),
             )?;
         }
         //
+        //  PAGE UP, PAGE DOWN: scroll back through printed output
+        //
+        Event::Key(KeyEvent {
+            code: code @ (KeyCode::PageUp | KeyCode::PageDown),
+            ..
+        }) => {
+            let page = win_rows.saturating_sub(2) as usize;
+            if code == KeyCode::PageUp {
+                scrollback.page_up(page);
+            } else {
+                scrollback.page_down(page);
+            }
+            scrollback.draw(&mut stdout, (*win_cols, *win_rows), *cursor_col)?;
+        }
+        //
+        //  ESC: stop scrolling back, returning to the latest output
+        //
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) => {
+            if scrollback.is_scrolled() {
+                scrollback.to_bottom();
+                scrollback.draw(&mut stdout, (*win_cols, *win_rows), *cursor_col)?;
+            }
+        }
+        //
+        //  CTRL+F: scroll back to the next printed row containing the input line
+        //
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('f'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        }) => {
+            if !scrollback.search(&current_line[*prompt_len..]) {
+                // bell: no more matches
+                print!("\x07");
+            }
+            scrollback.draw(&mut stdout, (*win_cols, *win_rows), *cursor_col)?;
+        }
+        //
+        //  CTRL+B: enter copy mode, where the mouse can select too
+        //
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('b'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        }) => {
+            scrollback.enter_copy_mode();
+            if scrollback.is_copying() {
+                execute!(stdout, crossterm::event::EnableMouseCapture)?;
+                scrollback.draw(&mut stdout, (*win_cols, *win_rows), *cursor_col)?;
+            }
+        }
+        //
+        //  CTRL+O: dump the scrollback to a file in the VFS
+        //
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('o'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        }) => {
+            let name = format!("{}.txt", Local::now().format("%Y-%m-%d_%H-%M-%S"));
+            Printout::new(
+                0,
+                match scrollback.dump(&scrollback_dir.join(&name)).await {
+                    Ok(()) => format!("scrollback saved to /terminal:sys/scrollback/{name}"),
+                    Err(e) => format!("couldn't save scrollback: {e}"),
+                },
+            )
+            .send(&print_tx)
+            .await;
+        }
+        //
         //  CTRL+R: enter search mode
         //  if already in search mode, increase search depth
         //
@@ -570,16 +1028,18 @@ async fn handle_event(
             // just show true current line as usual
             *search_mode = false;
             *search_depth = 0;
+            let line;
+            (line, *cursor_col) = utils::truncate_in_place(
+                &format!("{} > {}", our.name, &current_line[*prompt_len..]),
+                *prompt_len,
+                *win_cols,
+                (*line_col, *cursor_col),
+            );
             execute!(
                 stdout,
                 cursor::MoveTo(0, *win_rows),
                 terminal::Clear(ClearType::CurrentLine),
-                Print(utils::truncate_in_place(
-                    &format!("{} > {}", our.name, &current_line[*prompt_len..]),
-                    *prompt_len,
-                    *win_cols,
-                    (*line_col, *cursor_col)
-                )),
+                Print(line),
                 cursor::MoveTo(*cursor_col, *win_rows),
             )?;
         }
@@ -593,10 +1053,10 @@ async fn handle_event(
                 //
                 KeyCode::Char(c) => {
                     current_line.insert(*line_col, c);
-                    if cursor_col < win_cols {
-                        *cursor_col += 1;
-                    }
-                    *line_col += 1;
+                    *line_col += c.len_utf8();
+                    *cursor_col = cursor_col.saturating_add(
+                        unicode_width::UnicodeWidthChar::width(c).unwrap_or(0) as u16,
+                    );
                     if *search_mode {
                         utils::execute_search(
                             &our,
@@ -610,16 +1070,18 @@ async fn handle_event(
                         )?;
                         return Ok(false);
                     }
+                    let line;
+                    (line, *cursor_col) = utils::truncate_in_place(
+                        &current_line,
+                        *prompt_len,
+                        *win_cols,
+                        (*line_col, *cursor_col),
+                    );
                     execute!(
                         stdout,
                         cursor::MoveTo(0, *win_rows),
                         terminal::Clear(ClearType::CurrentLine),
-                        Print(utils::truncate_in_place(
-                            &current_line,
-                            *prompt_len,
-                            *win_cols,
-                            (*line_col, *cursor_col)
-                        )),
+                        Print(line),
                         cursor::MoveTo(*cursor_col, *win_rows),
                     )?;
                 }
@@ -630,11 +1092,11 @@ async fn handle_event(
                     if line_col == prompt_len {
                         return Ok(false);
                     }
-                    if *cursor_col as usize == *line_col {
-                        *cursor_col -= 1;
-                    }
-                    *line_col -= 1;
-                    current_line.remove(*line_col);
+                    let prev = utils::prev_grapheme(current_line, *line_col);
+                    let removed = utils::width(&current_line[prev..*line_col]);
+                    current_line.replace_range(prev..*line_col, "");
+                    *line_col = prev;
+                    *cursor_col = cursor_col.saturating_sub(removed as u16);
                     if *search_mode {
                         utils::execute_search(
                             &our,
@@ -648,16 +1110,18 @@ async fn handle_event(
                         )?;
                         return Ok(false);
                     }
+                    let line;
+                    (line, *cursor_col) = utils::truncate_in_place(
+                        &current_line,
+                        *prompt_len,
+                        *win_cols,
+                        (*line_col, *cursor_col),
+                    );
                     execute!(
                         stdout,
                         cursor::MoveTo(0, *win_rows),
                         terminal::Clear(ClearType::CurrentLine),
-                        Print(utils::truncate_in_place(
-                            &current_line,
-                            *prompt_len,
-                            *win_cols,
-                            (*line_col, *cursor_col)
-                        )),
+                        Print(line),
                         cursor::MoveTo(*cursor_col, *win_rows),
                     )?;
                 }
@@ -668,7 +1132,8 @@ async fn handle_event(
                     if *line_col == current_line.len() {
                         return Ok(false);
                     }
-                    current_line.remove(*line_col);
+                    let next = utils::next_grapheme(current_line, *line_col);
+                    current_line.replace_range(*line_col..next, "");
                     if *search_mode {
                         utils::execute_search(
                             &our,
@@ -682,76 +1147,76 @@ async fn handle_event(
                         )?;
                         return Ok(false);
                     }
+                    let line;
+                    (line, *cursor_col) = utils::truncate_in_place(
+                        &current_line,
+                        *prompt_len,
+                        *win_cols,
+                        (*line_col, *cursor_col),
+                    );
                     execute!(
                         stdout,
                         cursor::MoveTo(0, *win_rows),
                         terminal::Clear(ClearType::CurrentLine),
-                        Print(utils::truncate_in_place(
-                            &current_line,
-                            *prompt_len,
-                            *win_cols,
-                            (*line_col, *cursor_col)
-                        )),
+                        Print(line),
                         cursor::MoveTo(*cursor_col, *win_rows),
                     )?;
                 }
                 //
-                //  LEFT: move cursor one spot left
+                //  LEFT: move cursor one grapheme left, scrolling the line
+                //  if the cursor is at the prompt
                 //
                 KeyCode::Left => {
-                    if *cursor_col as usize == *prompt_len {
-                        if line_col == prompt_len {
-                            // at the very beginning of the current typed line
-                            return Ok(false);
-                        } else {
-                            // virtual scroll leftward through line
-                            *line_col -= 1;
-                            execute!(
-                                stdout,
-                                cursor::MoveTo(0, *win_rows),
-                                Print(utils::truncate_from_left(
-                                    &current_line,
-                                    *prompt_len,
-                                    *win_cols,
-                                    *line_col
-                                )),
-                                cursor::MoveTo(*cursor_col, *win_rows),
-                            )?;
-                        }
-                    } else {
-                        // simply move cursor and line position left
-                        execute!(stdout, cursor::MoveLeft(1),)?;
-                        *cursor_col -= 1;
-                        *line_col -= 1;
+                    if line_col == prompt_len {
+                        // at the very beginning of the current typed line
+                        return Ok(false);
                     }
+                    let prev = utils::prev_grapheme(current_line, *line_col);
+                    let moved = utils::width(&current_line[prev..*line_col]);
+                    *line_col = prev;
+                    *cursor_col = cursor_col.saturating_sub(moved as u16);
+                    let line;
+                    (line, *cursor_col) = utils::truncate_in_place(
+                        &current_line,
+                        *prompt_len,
+                        *win_cols,
+                        (*line_col, *cursor_col),
+                    );
+                    execute!(
+                        stdout,
+                        cursor::MoveTo(0, *win_rows),
+                        terminal::Clear(ClearType::CurrentLine),
+                        Print(line),
+                        cursor::MoveTo(*cursor_col, *win_rows),
+                    )?;
                 }
                 //
-                //  RIGHT: move cursor one spot right
+                //  RIGHT: move cursor one grapheme right, scrolling the line
+                //  if the cursor is at the edge of the terminal
                 //
                 KeyCode::Right => {
                     if *line_col == current_line.len() {
                         // at the very end of the current typed line
                         return Ok(false);
                     };
-                    if *cursor_col < (*win_cols - 1) {
-                        // simply move cursor and line position right
-                        execute!(stdout, cursor::MoveRight(1))?;
-                        *cursor_col += 1;
-                        *line_col += 1;
-                    } else {
-                        // virtual scroll rightward through line
-                        *line_col += 1;
-                        execute!(
-                            stdout,
-                            cursor::MoveTo(0, *win_rows),
-                            Print(utils::truncate_from_right(
-                                &current_line,
-                                *prompt_len,
-                                *win_cols,
-                                *line_col
-                            )),
-                        )?;
-                    }
+                    let next = utils::next_grapheme(current_line, *line_col);
+                    let moved = utils::width(&current_line[*line_col..next]);
+                    *line_col = next;
+                    *cursor_col = cursor_col.saturating_add(moved as u16);
+                    let line;
+                    (line, *cursor_col) = utils::truncate_in_place(
+                        &current_line,
+                        *prompt_len,
+                        *win_cols,
+                        (*line_col, *cursor_col),
+                    );
+                    execute!(
+                        stdout,
+                        cursor::MoveTo(0, *win_rows),
+                        terminal::Clear(ClearType::CurrentLine),
+                        Print(line),
+                        cursor::MoveTo(*cursor_col, *win_rows),
+                    )?;
                 }
                 //
                 //  ENTER: send current input to terminal process, clearing input line
@@ -779,23 +1244,9 @@ async fn handle_event(
                     *search_depth = 0;
                     *current_line = next;
                     command_history.add(command.clone());
-                    *cursor_col = *prompt_len as u16;
+                    *cursor_col = utils::width(current_line) as u16;
                     *line_col = *prompt_len;
-                    KernelMessage::builder()
-                        .id(rand::random())
-                        .source((our.name.as_str(), TERMINAL_PROCESS_ID.clone()))
-                        .target((our.name.as_str(), TERMINAL_PROCESS_ID.clone()))
-                        .message(Message::Request(Request {
-                            inherit: false,
-                            expects_response: None,
-                            body: command.into_bytes(),
-                            metadata: None,
-                            capabilities: vec![],
-                        }))
-                        .build()
-                        .unwrap()
-                        .send(&event_loop)
-                        .await;
+                    send_command(our, command, state, event_loop).await?;
                 }
                 _ => {
                     // some keycode we don't care about, yet
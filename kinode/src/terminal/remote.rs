@@ -0,0 +1,114 @@
+use lib::types::core::Printout;
+use std::{os::unix::fs::PermissionsExt, path::Path, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, oneshot, Mutex},
+};
+
+/// the socket in the home directory that accepts command lines
+pub const SOCKET_NAME: &str = ".terminal.sock";
+/// a command is done if it prints nothing for this long after it's entered,
+const FIRST_PRINT_TIMEOUT: Duration = Duration::from_secs(5);
+/// or for this long after its last print,
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+/// or at the latest this long after it's entered
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// a command line entered from outside the interactive terminal, and where
+/// to send what gets printed while it runs, if not to stdout
+pub struct RemoteCommand {
+    pub line: String,
+    pub output: Option<mpsc::UnboundedSender<String>>,
+}
+
+/// a request from a web terminal, passed on by http_server
+pub enum WebTerminalRequest {
+    /// send every printout here from now on, until it closes. printouts
+    /// that don't fit are dropped rather than held for a slow client.
+    Attach(mpsc::Sender<Printout>),
+    /// run a command line, adding it to the history
+    Command(String),
+    /// the command history, most recent first
+    History(oneshot::Sender<Vec<String>>),
+    /// search the history as CTRL+R does
+    Search {
+        query: String,
+        depth: usize,
+        result: oneshot::Sender<Option<String>>,
+    },
+}
+
+pub type WebTerminalSender = mpsc::Sender<WebTerminalRequest>;
+pub type WebTerminalReceiver = mpsc::Receiver<WebTerminalRequest>;
+
+/// accept command lines, one per line, on a unix socket at `path`, and write
+/// back what gets printed while each runs. prints are not tagged with the
+/// command that caused them, so commands run one at a time, and any print
+/// in the meantime counts as output.
+pub fn listen(path: &Path, commands: mpsc::Sender<RemoteCommand>) -> anyhow::Result<()> {
+    // a socket left over from a previous run
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    let one_at_a_time = Arc::new(Mutex::new(()));
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, commands.clone(), one_at_a_time.clone()));
+        }
+    });
+    Ok(())
+}
+
+async fn serve(
+    stream: UnixStream,
+    commands: mpsc::Sender<RemoteCommand>,
+    one_at_a_time: Arc<Mutex<()>>,
+) -> anyhow::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _running = one_at_a_time.lock().await;
+        let (output, mut printed) = mpsc::unbounded_channel();
+        commands
+            .send(RemoteCommand {
+                line,
+                output: Some(output),
+            })
+            .await?;
+        let deadline = tokio::time::Instant::now() + COMMAND_TIMEOUT;
+        let mut wait = FIRST_PRINT_TIMEOUT;
+        loop {
+            let quiet = tokio::time::Instant::now() + wait;
+            wait = QUIET_PERIOD;
+            match tokio::time::timeout_at(quiet.min(deadline), printed.recv()).await {
+                Ok(Some(print)) => {
+                    write.write_all(print.as_bytes()).await?;
+                    write.write_all(b"\n").await?;
+                }
+                _ => break,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// read command lines from stdin, for a detached node whose stdin is a pipe.
+/// their output goes to stdout as usual.
+pub fn read_stdin(commands: mpsc::Sender<RemoteCommand>) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let command = RemoteCommand { line, output: None };
+            if commands.send(command).await.is_err() {
+                break;
+            }
+        }
+    });
+}
@@ -0,0 +1,237 @@
+//! coalescing of the prints a process repeats, and rate limiting of those it
+//! floods the terminal with, so that the terminal stays usable while, say, a
+//! process crash-loops. only what the terminal shows is coalesced: the log
+//! file and web terminals get every print.
+use lib::types::core::{LogLevel, Printout, ProcessId};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// prints a process may show at once, and per second after that. prints
+/// from the runtime itself are never dropped, only coalesced.
+const BURST: f64 = 20.0;
+const PER_SEC: f64 = 5.0;
+/// how long repeats of a print are held back before their count is shown
+pub const REPEAT_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct Repeats(HashMap<Option<ProcessId>, Source>);
+
+struct Source {
+    /// the last print shown, and its level and verbosity
+    last: Option<(String, Option<LogLevel>, u8)>,
+    /// times the last print came again since it, or its count, was shown
+    repeated: u64,
+    repeated_since: Instant,
+    tokens: f64,
+    refilled: Instant,
+    /// prints dropped for coming too fast, since that was last shown
+    dropped: u64,
+}
+
+impl Repeats {
+    /// what to show for a print: what was held back from its source, then
+    /// the print itself, unless it repeats the last or comes too fast
+    pub fn admit(&mut self, printout: Printout) -> Vec<Printout> {
+        let now = Instant::now();
+        let source = self
+            .0
+            .entry(printout.source.clone())
+            .or_insert_with(|| Source {
+                last: None,
+                repeated: 0,
+                repeated_since: now,
+                tokens: BURST,
+                refilled: now,
+                dropped: 0,
+            });
+        source.tokens = (source.tokens
+            + now.duration_since(source.refilled).as_secs_f64() * PER_SEC)
+            .min(BURST);
+        source.refilled = now;
+
+        let key = (printout.content, printout.level, printout.verbosity);
+        if source.last.as_ref() == Some(&key) {
+            if source.repeated == 0 {
+                source.repeated_since = now;
+            }
+            source.repeated += 1;
+            return vec![];
+        }
+        let mut shown = vec![];
+        if let Some(summary) = source.repeats(&printout.source) {
+            shown.push(summary);
+        }
+        if printout.source.is_some() {
+            if source.tokens < 1.0 {
+                source.dropped += 1;
+                return shown;
+            }
+            source.tokens -= 1.0;
+        }
+        if let Some(summary) = source.drops(&printout.source) {
+            shown.push(summary);
+        }
+        shown.push(Printout {
+            verbosity: key.2,
+            content: key.0.clone(),
+            source: printout.source,
+            level: key.1,
+        });
+        source.last = Some(key);
+        shown
+    }
+
+    /// the counts of repeats held back long enough, and of prints dropped
+    /// from sources that have slowed down, to show now. sources with nothing
+    /// held back that have been quiet for [`REPEAT_WINDOW`] are forgotten:
+    /// by then their tokens have refilled, so only their last print is lost.
+    pub fn flush(&mut self) -> Vec<Printout> {
+        let now = Instant::now();
+        let mut shown = vec![];
+        for (process, source) in self.0.iter_mut() {
+            if source.repeated > 0 && now.duration_since(source.repeated_since) >= REPEAT_WINDOW {
+                shown.extend(source.repeats(process));
+            }
+            if source.dropped > 0
+                && source.tokens + now.duration_since(source.refilled).as_secs_f64() * PER_SEC
+                    >= BURST
+            {
+                shown.extend(source.drops(process));
+            }
+        }
+        self.0.retain(|_, source| {
+            source.repeated > 0
+                || source.dropped > 0
+                || now.duration_since(source.refilled) < REPEAT_WINDOW
+        });
+        shown
+    }
+
+    /// forget the last print of every source, and give each a full burst,
+    /// so that the output of a command the user runs is neither taken for a
+    /// repeat nor dropped for what came before it. returns what was held
+    /// back, to show first.
+    pub fn reset(&mut self) -> Vec<Printout> {
+        let now = Instant::now();
+        let mut shown = vec![];
+        for (process, source) in self.0.iter_mut() {
+            shown.extend(source.repeats(process));
+            shown.extend(source.drops(process));
+            source.last = None;
+            source.tokens = BURST;
+            source.refilled = now;
+        }
+        shown
+    }
+}
+
+impl Source {
+    /// how often the last print was repeated, if it was
+    fn repeats(&mut self, process: &Option<ProcessId>) -> Option<Printout> {
+        if self.repeated == 0 {
+            return None;
+        }
+        let (_, level, verbosity) = self.last.as_ref()?;
+        let summary = Printout {
+            verbosity: *verbosity,
+            content: match self.repeated {
+                1 => "last message repeated once".to_string(),
+                n => format!("last message repeated {n} times"),
+            },
+            source: process.clone(),
+            level: *level,
+        };
+        self.repeated = 0;
+        Some(summary)
+    }
+
+    /// how many prints were dropped for coming too fast, if any were
+    fn drops(&mut self, process: &Option<ProcessId>) -> Option<Printout> {
+        if self.dropped == 0 {
+            return None;
+        }
+        let summary = Printout {
+            verbosity: 0,
+            content: format!(
+                "{} message{} dropped: printing faster than {PER_SEC} a second",
+                self.dropped,
+                if self.dropped == 1 { "" } else { "s" }
+            ),
+            source: process.clone(),
+            level: Some(LogLevel::Warn),
+        };
+        self.dropped = 0;
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(source: Option<&str>, content: &str) -> Printout {
+        Printout {
+            verbosity: 0,
+            content: content.to_string(),
+            source: source.map(|source| source.parse().unwrap()),
+            level: None,
+        }
+    }
+
+    fn contents(printouts: Vec<Printout>) -> Vec<String> {
+        printouts
+            .into_iter()
+            .map(|printout| printout.content)
+            .collect()
+    }
+
+    #[test]
+    fn repeats_are_counted_before_the_next_print() {
+        let mut repeats = Repeats::default();
+        let chat = Some("chat:chat:sys");
+        assert_eq!(contents(repeats.admit(print(chat, "crashed"))), ["crashed"]);
+        assert!(repeats.admit(print(chat, "crashed")).is_empty());
+        assert!(repeats.admit(print(chat, "crashed")).is_empty());
+        assert_eq!(
+            contents(repeats.admit(print(chat, "restarted"))),
+            ["last message repeated 2 times", "restarted"]
+        );
+    }
+
+    #[test]
+    fn floods_are_dropped_but_not_from_the_runtime() {
+        let mut repeats = Repeats::default();
+        let shown: usize = (0..BURST as usize * 2)
+            .map(|i| {
+                repeats
+                    .admit(print(Some("chat:chat:sys"), &i.to_string()))
+                    .len()
+            })
+            .sum();
+        assert!(shown < BURST as usize * 2);
+        let shown: usize = (0..BURST as usize * 2)
+            .map(|i| repeats.admit(print(None, &i.to_string())).len())
+            .sum();
+        assert_eq!(shown, BURST as usize * 2);
+    }
+
+    #[test]
+    fn reset_shows_what_was_held_back_and_refills() {
+        let mut repeats = Repeats::default();
+        let chat = Some("chat:chat:sys");
+        for i in 0..BURST as usize * 2 {
+            repeats.admit(print(chat, &i.to_string()));
+        }
+        repeats.admit(print(chat, "again"));
+        repeats.admit(print(chat, "again"));
+        let held_back = contents(repeats.reset());
+        assert!(held_back.iter().any(|line| line.contains("dropped")));
+        // the output of the command run next is shown in full
+        assert_eq!(contents(repeats.admit(print(chat, "again"))), ["again"]);
+        for i in 1..BURST as usize {
+            assert_eq!(repeats.admit(print(chat, &format!("line {i}"))).len(), 1);
+        }
+    }
+}
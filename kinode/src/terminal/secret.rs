@@ -0,0 +1,174 @@
+//! reading a line at the terminal that is neither shown as it is typed nor
+//! kept, such as a password, for a script of `terminal:sys` that asks for one
+//! with `KernelCommand::ReadSecret`. the kernel passes the ask on here, and
+//! the terminal sends the line back to the script in a [`SecretInput`] request.
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use lib::types::core::{
+    Address, KernelMessage, Message, MessageSender, Request, SecretInput, TERMINAL_PROCESS_ID,
+};
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+/// the longest line read; a longer one is cut short
+const MAX_LEN: usize = 1024;
+
+lazy_static::lazy_static! {
+    static ref ASKS: Mutex<Option<mpsc::UnboundedSender<Ask>>> = Mutex::new(None);
+}
+
+/// a process waiting on a secret line
+#[derive(Debug)]
+pub struct Ask {
+    pub requester: Address,
+    pub prompt: String,
+}
+
+/// take the asks the kernel passes on. called once, by the terminal.
+pub fn listen() -> mpsc::UnboundedReceiver<Ask> {
+    let (asks, recv_asks) = mpsc::unbounded_channel();
+    *ASKS.lock().unwrap() = Some(asks);
+    recv_asks
+}
+
+/// pass an ask on to the terminal, or give it back if none is listening
+pub fn ask(ask: Ask) -> Result<(), Ask> {
+    match ASKS.lock().unwrap().as_ref() {
+        Some(asks) => asks.send(ask).map_err(|e| e.0),
+        None => Err(ask),
+    }
+}
+
+/// send the line read, or `None` if there is none, to the process that asked
+pub async fn answer(
+    our: &str,
+    requester: Address,
+    input: Option<String>,
+    send_to_loop: &MessageSender,
+) {
+    KernelMessage::builder()
+        .id(rand::random())
+        .source((our, TERMINAL_PROCESS_ID.clone()))
+        .target(requester)
+        .message(Message::Request(Request {
+            inherit: false,
+            expects_response: None,
+            body: serde_json::to_vec(&SecretInput { input }).unwrap(),
+            metadata: None,
+            capabilities: vec![],
+        }))
+        .build()
+        .unwrap()
+        .send(send_to_loop)
+        .await;
+}
+
+/// a secret line being typed
+#[derive(Debug)]
+pub struct Reading {
+    pub requester: Address,
+    pub prompt: String,
+    line: String,
+}
+
+impl Reading {
+    pub fn new(ask: Ask) -> Self {
+        Reading {
+            requester: ask.requester,
+            prompt: ask.prompt,
+            line: String::new(),
+        }
+    }
+
+    /// take a key typed. returns the answer once the line is done: the line
+    /// on ENTER, or `None` on ESC, CTRL+C or CTRL+D.
+    pub fn key(&mut self, key: &KeyEvent) -> Option<Option<String>> {
+        match key.code {
+            KeyCode::Char('c') | KeyCode::Char('d')
+                if key.modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                Some(None)
+            }
+            KeyCode::Esc => Some(None),
+            KeyCode::Enter => Some(Some(std::mem::take(&mut self.line))),
+            KeyCode::Backspace => {
+                self.line.pop();
+                None
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.paste(&c.to_string());
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// take text pasted in, without its control characters
+    pub fn paste(&mut self, pasted: &str) {
+        for c in pasted.chars().filter(|c| !c.is_control()) {
+            if self.line.len() + c.len_utf8() > MAX_LEN {
+                break;
+            }
+            self.line.push(c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::types::core::ProcessId;
+
+    fn reading() -> Reading {
+        Reading::new(Ask {
+            requester: Address::new(
+                "node.os",
+                "users:terminal:sys".parse::<ProcessId>().unwrap(),
+            ),
+            prompt: "password: ".to_string(),
+        })
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn line_is_answered_on_enter() {
+        let mut reading = reading();
+        for c in "hunter2".chars() {
+            assert_eq!(reading.key(&key(KeyCode::Char(c))), None);
+        }
+        reading.key(&key(KeyCode::Backspace));
+        reading.paste("3\n");
+        assert_eq!(
+            reading.key(&key(KeyCode::Enter)),
+            Some(Some("hunter3".to_string()))
+        );
+    }
+
+    #[test]
+    fn escape_and_ctrl_c_cancel() {
+        let mut reading = reading();
+        reading.paste("secret");
+        assert_eq!(reading.key(&key(KeyCode::Esc)), Some(None));
+        let mut reading = self::reading();
+        reading.paste("secret");
+        assert_eq!(
+            reading.key(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn line_is_cut_short() {
+        let mut reading = reading();
+        reading.paste(&"a".repeat(MAX_LEN + 10));
+        assert_eq!(
+            reading
+                .key(&key(KeyCode::Enter))
+                .flatten()
+                .map(|line| line.len()),
+            Some(MAX_LEN)
+        );
+    }
+}
@@ -5,6 +5,8 @@ use std::{
     fs::File,
     io::{BufWriter, Stdout, Write},
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
 
 pub struct RawMode;
 impl RawMode {
@@ -36,7 +38,7 @@ pub fn startup(
         crossterm::terminal::SetTitle(format!("kinode {}", our.name))
     )?;
 
-    let (win_cols, _) = crossterm::terminal::size().expect("terminal: couldn't fetch size");
+    let (win_cols, _) = crossterm::terminal::size().unwrap_or((80, 24));
 
     // print initial splash screen, large if there's room, small otherwise
     if win_cols >= 90 {
@@ -129,6 +131,7 @@ pub fn cleanup(quit_msg: &str) {
     crossterm::execute!(
         stdout,
         crossterm::event::DisableBracketedPaste,
+        crossterm::event::DisableMouseCapture,
         crossterm::terminal::SetTitle(""),
         crossterm::style::SetForegroundColor(crossterm::style::Color::Red),
         crossterm::style::Print(format!("\r\n{quit_msg}\r\n")),
@@ -200,6 +203,11 @@ impl CommandHistory {
         Some(self.lines[self.index - 1].clone())
     }
 
+    /// every line in history, most recent first
+    pub fn recent(&self) -> Vec<String> {
+        self.lines.iter().cloned().collect()
+    }
+
     /// if depth = 0, find most recent command in history that contains the
     /// provided string. otherwise, skip the first <depth> matches.
     /// yes this is O(n) to provide desired ordering, can revisit if slow
@@ -224,6 +232,354 @@ impl CommandHistory {
     }
 }
 
+/// the rows printed to the terminal, most recent last, kept so that output
+/// that has scrolled past can be paged back through, searched and copied
+#[derive(Debug)]
+pub struct Scrollback {
+    rows: VecDeque<String>,
+    max_size: usize,
+    /// how many rows up from the most recent the view is scrolled, 0 if
+    /// following output as it's printed
+    offset: usize,
+    /// the query the row at the bottom of the view was found by, if any
+    found: Option<String>,
+    /// set while in copy mode
+    copy: Option<CopyMode>,
+}
+
+/// a place in the scrollback: a row, counted from the oldest kept, and the
+/// byte index of a grapheme in it
+type Position = (usize, usize);
+
+/// copy mode: a cursor moved over the scrollback, and where the selection
+/// it extends was started, if one has been
+#[derive(Debug)]
+struct CopyMode {
+    cursor: Position,
+    anchor: Option<Position>,
+}
+
+impl Scrollback {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            rows: VecDeque::with_capacity(max_size),
+            max_size,
+            offset: 0,
+            found: None,
+            copy: None,
+        }
+    }
+
+    pub fn push(&mut self, row: String) {
+        // colors and such are dropped, so that rows are kept as plain text
+        self.rows.push_back(strip_escapes(&row));
+        if self.rows.len() > self.max_size {
+            self.rows.pop_front();
+            if let Some(copy) = &mut self.copy {
+                for position in std::iter::once(&mut copy.cursor).chain(copy.anchor.as_mut()) {
+                    if position.0 == 0 {
+                        position.1 = 0;
+                    }
+                    position.0 = position.0.saturating_sub(1);
+                }
+            }
+        }
+        // keep a held view where it is as rows come in
+        if self.is_scrolled() {
+            self.offset = (self.offset + 1).min(self.rows.len().saturating_sub(1));
+        }
+    }
+
+    /// whether the view is held rather than following output, as it is
+    /// while scrolled back or in copy mode
+    pub fn is_scrolled(&self) -> bool {
+        self.offset > 0 || self.copy.is_some()
+    }
+
+    pub fn page_up(&mut self, page: usize) {
+        self.found = None;
+        self.offset = (self.offset + page).min(self.rows.len().saturating_sub(page));
+    }
+
+    pub fn page_down(&mut self, page: usize) {
+        self.found = None;
+        self.offset = self.offset.saturating_sub(page);
+    }
+
+    pub fn to_bottom(&mut self) {
+        self.found = None;
+        self.offset = 0;
+    }
+
+    /// scroll to the next row, older than the one at the bottom of the view,
+    /// that contains `query`, putting it at the bottom of the view. returns
+    /// false if there is none.
+    pub fn search(&mut self, query: &str) -> bool {
+        if query.is_empty() {
+            return false;
+        }
+        let bottom = self.rows.len().saturating_sub(self.offset + 1);
+        let Some(found) = self
+            .rows
+            .range(..bottom)
+            .rposition(|row| row.contains(query))
+        else {
+            return false;
+        };
+        self.offset = self.rows.len() - 1 - found;
+        self.found = Some(query.to_string());
+        if let Some(copy) = &mut self.copy {
+            copy.cursor = (found, self.rows[found].find(query).unwrap_or(0));
+        }
+        true
+    }
+
+    /// write every row kept to the file at `path`
+    pub async fn dump(&self, path: &std::path::Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut contents = String::new();
+        for row in &self.rows {
+            contents.push_str(row);
+            contents.push('\n');
+        }
+        tokio::fs::write(path, contents).await
+    }
+
+    /// enter copy mode, with the cursor at the start of the bottom row in view
+    pub fn enter_copy_mode(&mut self) {
+        if self.rows.is_empty() || self.copy.is_some() {
+            return;
+        }
+        self.copy = Some(CopyMode {
+            cursor: (self.rows.len() - 1 - self.offset, 0),
+            anchor: None,
+        });
+    }
+
+    pub fn exit_copy_mode(&mut self) {
+        self.copy = None;
+    }
+
+    pub fn is_copying(&self) -> bool {
+        self.copy.is_some()
+    }
+
+    /// move the copy mode cursor `rows` rows down, or up if negative, then
+    /// `graphemes` graphemes right, or left if negative, scrolling the view
+    /// to keep it in sight
+    pub fn move_cursor(&mut self, rows: isize, graphemes: isize, win_rows: u16) {
+        let Some(copy) = &mut self.copy else {
+            return;
+        };
+        let (mut row, mut col) = copy.cursor;
+        if rows != 0 {
+            // keep to the same column, as near as the new row allows
+            let column = width(&self.rows[row][..col]);
+            row = row
+                .saturating_add_signed(rows)
+                .min(self.rows.len().saturating_sub(1));
+            col = byte_at_column(&self.rows[row], column);
+        }
+        for _ in 0..graphemes.unsigned_abs() {
+            col = if graphemes < 0 {
+                prev_grapheme(&self.rows[row], col)
+            } else {
+                next_grapheme(&self.rows[row], col)
+            };
+        }
+        copy.cursor = (row, col);
+        self.follow(win_rows);
+    }
+
+    /// start a selection at the copy mode cursor, or drop the one started
+    pub fn select(&mut self) {
+        if let Some(copy) = &mut self.copy {
+            copy.anchor = match copy.anchor {
+                Some(_) => None,
+                None => Some(copy.cursor),
+            };
+        }
+    }
+
+    /// mouse button pressed: enter copy mode if not in it, and start a
+    /// selection where the mouse is
+    pub fn press(&mut self, at: (u16, u16), win_rows: u16) {
+        self.enter_copy_mode();
+        let Some(position) = self.position_at(at, win_rows) else {
+            return;
+        };
+        if let Some(copy) = &mut self.copy {
+            copy.cursor = position;
+            copy.anchor = Some(position);
+        }
+    }
+
+    /// mouse dragged: extend the selection to where the mouse is
+    pub fn drag(&mut self, at: (u16, u16), win_rows: u16) {
+        let Some(position) = self.position_at(at, win_rows) else {
+            return;
+        };
+        if let Some(copy) = &mut self.copy {
+            copy.cursor = position;
+        }
+    }
+
+    /// the text selected in copy mode, from the anchor to the cursor, both
+    /// included
+    pub fn selection(&self) -> Option<String> {
+        let copy = self.copy.as_ref()?;
+        let (start, end) = if copy.anchor? <= copy.cursor {
+            (copy.anchor?, copy.cursor)
+        } else {
+            (copy.cursor, copy.anchor?)
+        };
+        let mut selected = vec![];
+        for row in start.0..=end.0 {
+            let (from, to) = self.selected_in(row, start, end);
+            selected.push(&self.rows[row][from..to]);
+        }
+        Some(selected.join("\n"))
+    }
+
+    /// the byte range of `row` between `start` and `end`
+    fn selected_in(&self, row: usize, start: Position, end: Position) -> (usize, usize) {
+        let text = &self.rows[row];
+        let from = if row == start.0 { start.1 } else { 0 };
+        let to = if row == end.0 {
+            next_grapheme(text, end.1)
+        } else {
+            text.len()
+        };
+        (from, to.max(from))
+    }
+
+    /// the range of rows in view, and the screen row the first is drawn on.
+    /// the input line takes the bottom screen row, and a held view shows its
+    /// status on the one above.
+    fn view(&self, win_rows: u16) -> (std::ops::Range<usize>, usize) {
+        let page = win_rows.saturating_sub(1) as usize;
+        let shown = page.saturating_sub(self.is_scrolled() as usize);
+        let end = self.rows.len() - self.offset;
+        let start = end.saturating_sub(shown);
+        (start..end, shown - (end - start))
+    }
+
+    /// the place in the scrollback drawn at a screen column and row, if any
+    fn position_at(&self, (col, row): (u16, u16), win_rows: u16) -> Option<Position> {
+        let (rows, top) = self.view(win_rows);
+        let index = (row as usize).checked_sub(top)? + rows.start;
+        if !rows.contains(&index) {
+            return None;
+        }
+        Some((index, byte_at_column(&self.rows[index], col as usize)))
+    }
+
+    /// scroll the view so that the copy mode cursor is in it
+    fn follow(&mut self, win_rows: u16) {
+        let Some(copy) = &self.copy else {
+            return;
+        };
+        let (rows, _) = self.view(win_rows);
+        let last = self.rows.len() - 1;
+        if copy.cursor.0 >= rows.end {
+            self.offset = last - copy.cursor.0;
+        } else if copy.cursor.0 < rows.start {
+            self.offset = last - (copy.cursor.0 + rows.len().max(1) - 1).min(last);
+        }
+    }
+
+    /// draw the rows in view above the input line, with a status row at the
+    /// bottom of the view if it's held, then put the terminal cursor on the
+    /// copy mode cursor or, if not in copy mode, at `input_col` of the input
+    /// line
+    pub fn draw(
+        &self,
+        stdout: &mut std::io::StdoutLock,
+        (win_cols, win_rows): (u16, u16),
+        input_col: u16,
+    ) -> Result<(), std::io::Error> {
+        let (rows, top) = self.view(win_rows);
+        let selected = self.copy.as_ref().and_then(|copy| {
+            let anchor = copy.anchor?;
+            Some((anchor.min(copy.cursor), anchor.max(copy.cursor)))
+        });
+        let mut view: Vec<String> = vec![];
+        for index in rows.clone() {
+            let mut row =
+                wrap(&self.rows[index], win_cols as usize, win_cols as usize)[0].to_string();
+            if let Some((start, end)) = selected {
+                if (start.0..=end.0).contains(&index) {
+                    let (from, to) = self.selected_in(index, start, end);
+                    let (from, to) = (from.min(row.len()), to.min(row.len()));
+                    row.insert_str(to, "\x1b[27m");
+                    row.insert_str(from, "\x1b[7m");
+                }
+            }
+            view.push(row);
+        }
+        if let (Some(query), Some(last)) = (&self.found, view.last_mut()) {
+            if last.contains(query.as_str()) {
+                *last = underline(last, query).0;
+            }
+        }
+        if self.is_copying() {
+            view.push(
+                "\x1b[7m-- copy mode: arrows to move, SPACE to select, ENTER to copy, ESC to quit --\x1b[27m"
+                    .to_string(),
+            );
+        } else if self.is_scrolled() {
+            view.push(match &self.found {
+                Some(query) => format!(
+                    "\x1b[7m-- found \"{query}\" {} rows up: CTRL+F for the next match, ESC to return --\x1b[27m",
+                    self.offset
+                ),
+                None => format!(
+                    "\x1b[7m-- {} rows up: PAGE DOWN or ESC to return --\x1b[27m",
+                    self.offset
+                ),
+            });
+        }
+        let page = win_rows.saturating_sub(1) as usize;
+        for row in 0..page {
+            crossterm::execute!(
+                stdout,
+                crossterm::cursor::MoveTo(0, row as u16),
+                crossterm::terminal::Clear(crossterm::terminal::ClearType::CurrentLine),
+            )?;
+            if row >= top {
+                crossterm::execute!(stdout, crossterm::style::Print(&view[row - top]))?;
+            }
+        }
+        let (col, row) = match &self.copy {
+            Some(CopyMode {
+                cursor: (index, col),
+                ..
+            }) if rows.contains(index) => (
+                width(&self.rows[*index][..*col]).min(win_cols as usize) as u16,
+                (top + index - rows.start) as u16,
+            ),
+            _ => (input_col, win_rows),
+        };
+        crossterm::execute!(stdout, crossterm::cursor::MoveTo(col, row))
+    }
+}
+
+/// put `text` on the host's clipboard by way of the terminal, with an OSC 52
+/// escape sequence. terminals that don't support it ignore it.
+pub fn copy_to_clipboard(
+    stdout: &mut std::io::StdoutLock,
+    text: &str,
+) -> Result<(), std::io::Error> {
+    use base64::Engine;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    crossterm::execute!(
+        stdout,
+        crossterm::style::Print(format!("\x1b]52;c;{encoded}\x07"))
+    )
+}
+
 pub fn execute_search(
     our: &Identity,
     stdout: &mut std::io::StdoutLock,
@@ -237,91 +593,368 @@ pub fn execute_search(
     let search_query = &current_line[prompt_len..];
     if let Some(result) = command_history.search(search_query, search_depth) {
         let (result_underlined, u_end) = underline(result, search_query);
-        let search_cursor_col = u_end + prompt_len as u16;
+        let (line, search_cursor_col) = truncate_in_place(
+            &format!("{} * {}", our.name, result_underlined),
+            prompt_len,
+            win_cols,
+            (prompt_len + u_end, win_cols),
+        );
         crossterm::execute!(
             stdout,
             crossterm::cursor::MoveTo(0, win_rows),
             crossterm::terminal::Clear(crossterm::terminal::ClearType::CurrentLine),
-            crossterm::style::Print(truncate_in_place(
-                &format!("{} * {}", our.name, result_underlined),
-                prompt_len,
-                win_cols,
-                (line_col, search_cursor_col)
-            )),
+            crossterm::style::Print(line),
             crossterm::cursor::MoveTo(search_cursor_col, win_rows),
         )
     } else {
+        let (line, cursor_col) = truncate_in_place(
+            &format!("{} * {}: no results", our.name, &current_line[prompt_len..]),
+            prompt_len,
+            win_cols,
+            (line_col, cursor_col),
+        );
         crossterm::execute!(
             stdout,
             crossterm::cursor::MoveTo(0, win_rows),
             crossterm::terminal::Clear(crossterm::terminal::ClearType::CurrentLine),
-            crossterm::style::Print(truncate_in_place(
-                &format!("{} * {}: no results", our.name, &current_line[prompt_len..]),
-                prompt_len,
-                win_cols,
-                (line_col, cursor_col)
-            )),
+            crossterm::style::Print(line),
             crossterm::cursor::MoveTo(cursor_col, win_rows),
         )
     }
 }
 
-pub fn underline(s: &str, to_underline: &str) -> (String, u16) {
+/// underline the first match of `to_underline` in `s`, returning the result
+/// and the byte index in it of the end of the match
+pub fn underline(s: &str, to_underline: &str) -> (String, usize) {
     // format result string to have query portion underlined
     let mut result = s.to_string();
     let u_start = s.find(to_underline).unwrap();
     let u_end = u_start + to_underline.len();
     result.insert_str(u_end, "\x1b[24m");
     result.insert_str(u_start, "\x1b[4m");
-    (result, u_end as u16)
+    (result, u_end + "\x1b[4m".len())
+}
+
+/// a piece of a line as the terminal draws it: a grapheme, such as a letter,
+/// a CJK character or an emoji, or an ANSI escape sequence, which takes up
+/// no columns. given as its byte range in the line and its width in columns.
+struct Cell {
+    start: usize,
+    end: usize,
+    width: usize,
 }
 
-pub fn truncate_rightward(s: &str, prompt_len: usize, width: u16) -> String {
-    if s.len() <= width as usize {
-        // no adjustment to be made
-        return s.to_string();
+fn cells(s: &str) -> Vec<Cell> {
+    let mut cells = vec![];
+    let mut start = 0;
+    while start < s.len() {
+        let rest = &s[start..];
+        let (len, width) = match escape_len(rest) {
+            Some(len) => (len, 0),
+            None => {
+                let grapheme = rest.graphemes(true).next().unwrap();
+                (grapheme.len(), grapheme_width(grapheme))
+            }
+        };
+        cells.push(Cell {
+            start,
+            end: start + len,
+            width,
+        });
+        start += len;
     }
-    let sans_prompt = &s[prompt_len..];
-    s[..prompt_len].to_string() + &sans_prompt[(s.len() - width as usize)..]
+    cells
 }
 
-/// print prompt, then as many chars as will fit in term starting from line_col
-pub fn truncate_from_left(s: &str, prompt_len: usize, width: u16, line_col: usize) -> String {
-    if s.len() <= width as usize {
-        // no adjustment to be made
-        return s.to_string();
+/// the length of the CSI escape sequence, such as a color, `s` starts with
+fn escape_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 3 || bytes[0] != 0x1b || bytes[1] != b'[' {
+        return None;
     }
-    s[..prompt_len].to_string() + &s[line_col..(width as usize - prompt_len + line_col)]
+    bytes[2..]
+        .iter()
+        .position(|b| (0x40..=0x7e).contains(b))
+        .map(|i| i + 3)
 }
 
-/// print prompt, then as many chars as will fit in term leading up to line_col
-pub fn truncate_from_right(s: &str, prompt_len: usize, width: u16, line_col: usize) -> String {
-    if s.len() <= width as usize {
-        // no adjustment to be made
-        return s.to_string();
+/// columns taken up by a grapheme. a sequence of emoji joined into one is
+/// drawn as one, two columns wide.
+fn grapheme_width(grapheme: &str) -> usize {
+    let width: usize = grapheme.chars().map(|c| c.width().unwrap_or(0)).sum();
+    width.min(2)
+}
+
+/// columns `s` takes up in the terminal
+pub fn width(s: &str) -> usize {
+    cells(s).iter().map(|cell| cell.width).sum()
+}
+
+/// `s` without its escape sequences
+pub fn strip_escapes(s: &str) -> String {
+    cells(s)
+        .into_iter()
+        .filter(|cell| escape_len(&s[cell.start..]).is_none())
+        .map(|cell| &s[cell.start..cell.end])
+        .collect()
+}
+
+/// `s` with only the escape sequences that style text, such as colors, kept.
+/// any other, such as one moving the cursor, clearing the screen or setting
+/// the clipboard, and control characters other than newlines and tabs, are
+/// dropped, so that what a process prints can't take over the terminal.
+pub fn sanitize(s: &str) -> String {
+    let mut sanitized = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        if c == '\x1b' {
+            if let Some(len) = escape_len(rest) {
+                // styles are CSI sequences ending in `m`, with numeric parameters
+                let sequence = &rest[..len];
+                if sequence.ends_with('m')
+                    && sequence[2..len - 1]
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || c == ';')
+                {
+                    sanitized.push_str(sequence);
+                }
+                rest = &rest[len..];
+                continue;
+            }
+            if rest[1..].starts_with(']') {
+                // an operating system command runs to BEL or ESC \
+                let end = rest
+                    .find('\x07')
+                    .map(|i| i + 1)
+                    .or_else(|| rest.find("\x1b\\").map(|i| i + 2))
+                    .unwrap_or(rest.len());
+                rest = &rest[end..];
+                continue;
+            }
+            // any other escape is ESC and one more character
+            let mut chars = rest.chars();
+            chars.next();
+            chars.next();
+            rest = chars.as_str();
+            continue;
+        }
+        if !c.is_control() || c == '\n' || c == '\t' {
+            sanitized.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
     }
-    s[..prompt_len].to_string() + &s[(prompt_len + (line_col - width as usize))..line_col]
+    sanitized
 }
 
-/// if line is wider than the terminal, truncate it intelligently,
-/// keeping the cursor in the same relative position.
+/// the byte index in `s` of the grapheme drawn at column `col`, or the end
+/// of `s` if it's narrower than that
+fn byte_at_column(s: &str, col: usize) -> usize {
+    let mut at = 0;
+    for cell in cells(s) {
+        if at + cell.width > col {
+            return cell.start;
+        }
+        at += cell.width;
+    }
+    s.len()
+}
+
+/// the byte index of the grapheme before `i` in `s`
+pub fn prev_grapheme(s: &str, i: usize) -> usize {
+    s[..i]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(start, _)| start)
+}
+
+/// the byte index of the grapheme after the one at `i` in `s`
+pub fn next_grapheme(s: &str, i: usize) -> usize {
+    s[i..]
+        .graphemes(true)
+        .next()
+        .map_or(i, |grapheme| i + grapheme.len())
+}
+
+/// split a line into rows that fit the terminal, the first having only
+/// `first_width` columns and the rest `width`, without splitting a grapheme
+pub fn wrap(s: &str, first_width: usize, width: usize) -> Vec<&str> {
+    let mut rows = vec![];
+    let mut row_start = 0;
+    let mut row_width = 0;
+    let mut max = first_width;
+    for cell in cells(s) {
+        if row_width + cell.width > max && row_width > 0 {
+            rows.push(&s[row_start..cell.start]);
+            row_start = cell.start;
+            row_width = 0;
+            max = width;
+        }
+        row_width += cell.width;
+    }
+    rows.push(&s[row_start..]);
+    rows
+}
+
+/// print prompt, then as much of the end of the line as will fit in term,
+/// returning it and the column of the cursor at the end
+pub fn truncate_rightward(s: &str, prompt_len: usize, width: u16) -> (String, u16) {
+    truncate_from_right(s, prompt_len, width, s.len())
+}
+
+/// print prompt, then as much as will fit in term starting from line_col
+pub fn truncate_from_left(
+    s: &str,
+    prompt_len: usize,
+    width: u16,
+    line_col: usize,
+) -> (String, u16) {
+    truncate_in_place(s, prompt_len, width, (line_col, 0))
+}
+
+/// print prompt, then as much as will fit in term leading up to line_col
+pub fn truncate_from_right(
+    s: &str,
+    prompt_len: usize,
+    width: u16,
+    line_col: usize,
+) -> (String, u16) {
+    truncate_in_place(s, prompt_len, width, (line_col, width))
+}
+
+/// if line is wider than the terminal, truncate it intelligently, keeping
+/// the cursor, at byte `line_col` of the line, as near to `cursor_col` as
+/// whole graphemes allow. returns the line to print and the column the
+/// cursor is then at.
+///
+/// the last column is left empty, so that the terminal never wraps the line.
 pub fn truncate_in_place(
     s: &str,
     prompt_len: usize,
     width: u16,
     (line_col, cursor_col): (usize, u16),
-) -> String {
-    if s.len() <= width as usize {
-        // no adjustment to be made
-        return s.to_string();
-    }
+) -> (String, u16) {
+    let max = (width as usize).saturating_sub(1);
     // always keep prompt at left
-    let prompt = &s[..prompt_len];
-    // print as much of the command fits left of col_in_command before cursor_col,
-    // then fill out the rest up to width
-    let end = width as usize + line_col - cursor_col as usize;
-    if end > s.len() {
-        return s.to_string();
-    }
-    prompt.to_string() + &s[(prompt_len + line_col - cursor_col as usize)..end]
+    let (prompt, line) = s.split_at(prompt_len);
+    let prompt_width = self::width(prompt);
+    let cells = cells(line);
+    let line_col = line_col - prompt_len;
+    let split = cells.partition_point(|cell| cell.start < line_col);
+    let (before, after) = cells.split_at(split);
+
+    // print as much of the line fits left of the cursor before cursor_col,
+    // then fill out the rest up to width, then any room left with more of
+    // the line left of the cursor
+    let wanted_left =
+        (cursor_col as usize).clamp(prompt_width, max.max(prompt_width)) - prompt_width;
+    let room = max.saturating_sub(prompt_width);
+    let mut shown_before = 0;
+    let mut left = 0;
+    for cell in before.iter().rev() {
+        if left + cell.width > wanted_left {
+            break;
+        }
+        left += cell.width;
+        shown_before += 1;
+    }
+    let mut end = line_col;
+    let mut used = left;
+    for cell in after {
+        if used + cell.width > room {
+            break;
+        }
+        used += cell.width;
+        end = cell.end;
+    }
+    for cell in before.iter().rev().skip(shown_before) {
+        if used + cell.width > room {
+            break;
+        }
+        used += cell.width;
+        left += cell.width;
+        shown_before += 1;
+    }
+    let start = before
+        .len()
+        .checked_sub(shown_before)
+        .and_then(|i| before.get(i))
+        .map_or(line_col, |cell| cell.start);
+    (
+        prompt.to_string() + &line[start..end],
+        (prompt_width + left) as u16,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_keeps_only_styles() {
+        assert_eq!(
+            sanitize("\x1b[1mbold\x1b[22m \x1b[38;5;208morange\x1b[39m\ttab\n"),
+            "\x1b[1mbold\x1b[22m \x1b[38;5;208morange\x1b[39m\ttab\n"
+        );
+        // cursor moves, screen clears and private modes are dropped
+        assert_eq!(sanitize("a\x1b[2J\x1b[H\x1b[?25lb"), "ab");
+        // as are OSC sequences, such as setting the title or the clipboard,
+        // ended by BEL or by ESC \
+        assert_eq!(sanitize("a\x1b]0;title\x07b"), "ab");
+        assert_eq!(sanitize("a\x1b]52;c;aGk=\x1b\\b"), "ab");
+        // and lone escapes and other control characters
+        assert_eq!(sanitize("a\x1bcb\r\x08\x07c"), "abc");
+        assert_eq!(sanitize("trailing\x1b"), "trailing");
+    }
+
+    #[test]
+    fn strip_escapes_keeps_text() {
+        assert_eq!(strip_escapes("\x1b[4mnode\x1b[24m.os 日本"), "node.os 日本");
+        assert_eq!(width("\x1b[4mnode\x1b[24m.os 日本"), 12);
+    }
+
+    #[test]
+    fn graphemes_are_measured_and_stepped_over_whole() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(width("日本語"), 6);
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width(family), 2);
+        assert_eq!(width("a👍b"), 4);
+
+        let accented = "ae\u{301}";
+        assert_eq!(prev_grapheme(accented, accented.len()), 1);
+        assert_eq!(next_grapheme(accented, 1), accented.len());
+        let line = format!("x{family}");
+        assert_eq!(next_grapheme(&line, 1), line.len());
+        assert_eq!(prev_grapheme(&line, line.len()), 1);
+        assert_eq!(prev_grapheme(&line, 1), 0);
+    }
+
+    #[test]
+    fn wide_characters_are_not_split_across_rows() {
+        assert_eq!(wrap("ab日本c", 3, 4), ["ab", "日本", "c"]);
+        assert_eq!(wrap("日本", 1, 2), ["日", "本"]);
+        assert_eq!(wrap("", 3, 4), [""]);
+    }
+
+    #[test]
+    fn truncation_keeps_the_cursor_on_a_grapheme() {
+        let line = "> 日本語日本語";
+        // cursor at the end: as much of the end as fits, leaving the last
+        // column empty
+        assert_eq!(truncate_rightward(line, 2, 8), ("> 本語".to_string(), 6));
+        // cursor after "日本", kept at column 4
+        assert_eq!(
+            truncate_in_place(line, 2, 8, (2 + "日本".len(), 4)),
+            ("> 本語".to_string(), 4)
+        );
+        // a line that fits is left as it is
+        assert_eq!(
+            truncate_in_place("> 日本", 2, 80, (2, 2)),
+            ("> 日本".to_string(), 2)
+        );
+
+        let (underlined, end) = underline("a日b", "日");
+        assert_eq!(width(&underlined), 3);
+        assert!(underlined[..end].ends_with('日'));
+    }
 }
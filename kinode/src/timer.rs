@@ -24,6 +24,28 @@ impl TimerMap {
     fn remove(&mut self, pop_time: u64) -> Option<Vec<(u64, Address)>> {
         self.timers.remove(&pop_time)
     }
+
+    /// remove every timer set by `addr`
+    fn cancel(&mut self, addr: &Address) {
+        self.timers.retain(|_, timers| {
+            timers.retain(|(_, set_by)| set_by != addr);
+            !timers.is_empty()
+        });
+    }
+
+    /// remove every timer due at or before `now`, earliest first
+    fn remove_due(&mut self, now: u64) -> Vec<(u64, Address)> {
+        let mut due: Vec<u64> = self
+            .timers
+            .keys()
+            .filter(|pop_time| **pop_time <= now)
+            .cloned()
+            .collect();
+        due.sort();
+        due.into_iter()
+            .flat_map(|pop_time| self.timers.remove(&pop_time).unwrap_or_default())
+            .collect()
+    }
 }
 
 /// A runtime module that allows processes to set timers. Interacting with the
@@ -35,11 +57,16 @@ impl TimerMap {
 /// One kind of request is accepted: TimerAction::SetTimer(u64), where the u64 is the
 /// time to wait in milliseconds. This request should always expect a Response.
 /// If the request does not expect a Response, the timer will not be set.
+/// TimerAction::CancelTimers forgets every timer the requester has set.
 ///
 /// A proper Request will trigger the timer module to send a Response. The Response will be
 /// empty, so the user should either `send_and_await` the Request, or attach a `context` so
 /// they can match the Response with their purpose.
 ///
+/// In simulation mode, a node booted with `--virtual-time` keeps its own clock,
+/// starting at 0, instead of reading the system time. Timers set on it only pop
+/// when a test harness sends TimerAction::AdvanceTime(u64).
+///
 pub async fn timer_service(
     our: String,
     kernel_message_sender: MessageSender,
@@ -51,9 +78,16 @@ pub async fn timer_service(
     };
     // joinset holds 1 active timer per expiration-time
     let mut timer_tasks = tokio::task::JoinSet::<u64>::new();
+    #[cfg(not(feature = "simulation-mode"))]
+    let virtual_time = false;
+    #[cfg(feature = "simulation-mode")]
+    let virtual_time = crate::sim::virtual_time();
+    // milliseconds of virtual time elapsed, if timers are driven by it
+    let mut virtual_now: u64 = 0;
     loop {
         tokio::select! {
             Some(km) = timer_message_receiver.recv() => {
+                crate::metrics::handled(&TIMER_PROCESS_ID, &km);
                 // ignore Requests sent from other nodes
                 if km.source.node != our { continue };
                 // we only handle Requests
@@ -64,6 +98,9 @@ pub async fn timer_service(
                 };
                 match timer_action {
                     TimerAction::Debug => {
+                        if virtual_time {
+                            Printout::new(0, format!("timer service virtual time: {virtual_now}ms")).send(&print_tx).await;
+                        }
                         Printout::new(0, format!("timer service active timers ({}):", timer_map.timers.len())).send(&print_tx).await;
                         for (k, v) in timer_map.timers.iter() {
                             Printout::new(0, format!("{k}: {v:?}")).send(&print_tx).await;
@@ -74,10 +111,14 @@ pub async fn timer_service(
                         // if the timer is set to pop in 0 millis, we immediately respond
                         // otherwise, store in our persisted map, and spawn a task that
                         // sleeps for the given time, then sends the response
-                        let now = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64;
+                        let now = if virtual_time {
+                            virtual_now
+                        } else {
+                            std::time::SystemTime::now()
+                                .duration_since(std::time::UNIX_EPOCH)
+                                .unwrap()
+                                .as_millis() as u64
+                        };
                         let pop_time = now + timer_millis;
                         if timer_millis == 0 {
                             KernelMessage::builder()
@@ -99,7 +140,7 @@ pub async fn timer_service(
                             continue
                         }
                         Printout::new(3, format!("set timer to pop in {timer_millis}ms")).send(&print_tx).await;
-                        if !timer_map.contains(pop_time) {
+                        if !virtual_time && !timer_map.contains(pop_time) {
                             timer_tasks.spawn(async move {
                                 tokio::time::sleep(std::time::Duration::from_millis(timer_millis - 1)).await;
                                 pop_time
@@ -107,6 +148,38 @@ pub async fn timer_service(
                         }
                         timer_map.insert(pop_time, km.id, km.rsvp.unwrap_or(km.source));
                     }
+                    TimerAction::CancelTimers => {
+                        timer_map.cancel(&km.rsvp.unwrap_or(km.source));
+                    }
+                    TimerAction::AdvanceTime(millis) => {
+                        if virtual_time {
+                            virtual_now += millis;
+                            #[cfg(feature = "simulation-mode")]
+                            crate::sim::advance_to(virtual_now);
+                            Printout::new(3, format!("advanced virtual time to {virtual_now}ms")).send(&print_tx).await;
+                            for (id, addr) in timer_map.remove_due(virtual_now) {
+                                pop_timer(&our, id, addr, &kernel_message_sender).await;
+                            }
+                        }
+                        if req.expects_response.is_some() {
+                            KernelMessage::builder()
+                                .id(km.id)
+                                .source((our.as_str(), TIMER_PROCESS_ID.clone()))
+                                .target(km.rsvp.unwrap_or(km.source))
+                                .message(Message::Response((
+                                    Response {
+                                        inherit: false,
+                                        body: serde_json::to_vec(&virtual_time).unwrap(),
+                                        metadata: None,
+                                        capabilities: vec![],
+                                    },
+                                    None,
+                                )))
+                                .build()
+                                .unwrap()
+                                .send(&kernel_message_sender).await;
+                        }
+                    }
                 }
             }
             Some(Ok(time)) = timer_tasks.join_next() => {
@@ -114,24 +187,30 @@ pub async fn timer_service(
                 // the timer(s), and then remove it from our persisted map
                 let Some(timers) = timer_map.remove(time) else { continue };
                 for (id, addr) in timers {
-                    KernelMessage::builder()
-                        .id(id)
-                        .source((our.as_str(), TIMER_PROCESS_ID.clone()))
-                        .target(addr)
-                        .message(Message::Response((
-                            Response {
-                                inherit: false,
-                                body: vec![],
-                                metadata: None,
-                                capabilities: vec![],
-                            },
-                            None,
-                        )))
-                        .build()
-                        .unwrap()
-                        .send(&kernel_message_sender).await;
+                    pop_timer(&our, id, addr, &kernel_message_sender).await;
                 }
             }
         }
     }
 }
+
+/// send the empty Response that tells a process its timer has popped
+async fn pop_timer(our: &str, id: u64, addr: Address, kernel_message_sender: &MessageSender) {
+    KernelMessage::builder()
+        .id(id)
+        .source((our, TIMER_PROCESS_ID.clone()))
+        .target(addr)
+        .message(Message::Response((
+            Response {
+                inherit: false,
+                body: vec![],
+                metadata: None,
+                capabilities: vec![],
+            },
+            None,
+        )))
+        .build()
+        .unwrap()
+        .send(kernel_message_sender)
+        .await;
+}
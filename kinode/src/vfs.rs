@@ -1,16 +1,22 @@
+use crate::blobs::{BlobStore, HANDLE_THRESHOLD};
+use crate::cap_feedback::Denials;
+use crate::idempotency::{Keyed, Recent};
+use crate::kernel::flags::FeatureFlags;
 use dashmap::DashMap;
 use lib::types::core::{
-    Address, CapMessage, CapMessageSender, Capability, DirEntry, FileMetadata, FileType,
-    KernelMessage, LazyLoadBlob, Message, MessageReceiver, MessageSender, PackageId, PrintSender,
-    Printout, ProcessId, Request, Response, VfsAction, VfsError, VfsRequest, VfsResponse,
-    KERNEL_PROCESS_ID, VFS_PROCESS_ID,
+    remote_grant_bytes, Address, CapMessage, CapMessageSender, Capability, DirEntry, DirSort,
+    DriveEvent, FileMetadata, FileType, KernelMessage, LazyLoadBlob, Message, MessageReceiver,
+    MessageSender, PackageId, PrintSender, Printout, ProcessId, Request, Response, TransactionStep,
+    TreeEntry, TreeManifest, VfsAction, VfsError, VfsRequest, VfsResponse, KERNEL_PROCESS_ID,
+    SYNC_PROCESS_ID, VFS_PROCESS_ID,
 };
+use lib::types::errors::ModuleError;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     io::Read,
     path::{Component, Path, PathBuf},
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 use tokio::{
     fs,
@@ -23,6 +29,33 @@ use tokio::{
 const FILE_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
 const FILE_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
 
+/// the processes to tell of changes to each drive, by drive path
+type DriveHooks = Arc<DashMap<String, HashSet<ProcessId>>>;
+/// hashes of files by path, for [`VfsAction::TreeHash`]: valid while their
+/// length and modification time are unchanged, and dropped when the vfs
+/// writes to them or a `TreeHash` finds them gone
+type TreeHashes = Arc<DashMap<PathBuf, (u64, u128, [u8; 32])>>;
+/// most file hashes kept at once; past it, any are forgotten to make room
+const MAX_TREE_HASHES: usize = 1 << 18;
+/// how far apart two writes to a file must be to be sure of different
+/// modification times. a hash is only kept for a file modified longer ago
+/// than this, so that a write of the same length in the same tick as the one
+/// hashed is not missed.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+lazy_static::lazy_static! {
+    static ref EXITED: std::sync::Mutex<Option<tokio::sync::mpsc::UnboundedSender<ProcessId>>> =
+        std::sync::Mutex::new(None);
+}
+
+/// drop the drive hooks of a process that has ended or been killed: it adds
+/// them again if it starts again. called by the kernel.
+pub fn process_exited(process: &ProcessId) {
+    if let Some(exited) = EXITED.lock().unwrap().as_ref() {
+        let _ = exited.send(process.clone());
+    }
+}
+
 /// The main VFS service function.
 ///
 /// This function sets up the VFS, handles incoming requests, and manages file operations.
@@ -35,6 +68,8 @@ const FILE_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
 /// * `recv_from_loop` - Receiver for incoming messages
 /// * `send_to_caps_oracle` - Sender for capability messages
 /// * `home_directory_path` - Path to the home directory
+/// * `our_public_key` - Our networking key, to verify the drive capabilities
+///   remote nodes attach to their requests
 ///
 /// # Returns
 /// * `anyhow::Result<()>` - Should never return Ok, but will return fatal errors.
@@ -45,6 +80,9 @@ pub async fn vfs(
     mut recv_from_loop: MessageReceiver,
     send_to_caps_oracle: CapMessageSender,
     home_directory_path: String,
+    blob_store: Arc<BlobStore>,
+    our_public_key: Arc<Vec<u8>>,
+    feature_flags: Arc<FeatureFlags>,
 ) -> anyhow::Result<()> {
     let vfs_path = format!("{home_directory_path}/vfs");
 
@@ -53,10 +91,19 @@ pub async fn vfs(
         .map_err(|e| anyhow::anyhow!("failed creating vfs dir! {e:?}"))?;
     let vfs_path = Arc::new(fs::canonicalize(&vfs_path).await?);
 
+    // drives kept for transactions left open by a previous run: the
+    // processes that would have committed or aborted them are gone
+    let _ = fs::remove_dir_all(transactions_path(&vfs_path)).await;
+
     let open_files: Arc<DashMap<PathBuf, (Arc<Mutex<fs::File>>, Instant)>> =
         Arc::new(DashMap::new());
+    let drive_hooks: DriveHooks = Arc::new(DashMap::new());
+    let tree_hashes: TreeHashes = Arc::new(DashMap::new());
+    let recent = Arc::new(Recent::default());
 
-    let process_queues: HashMap<ProcessId, Arc<Mutex<VecDeque<KernelMessage>>>> =
+    // one queue per process, so each process's requests are handled one at a
+    // time in the order sent
+    let mut process_queues: HashMap<ProcessId, Arc<Mutex<VecDeque<KernelMessage>>>> =
         HashMap::default();
 
     // Start the file cleanup task
@@ -70,24 +117,34 @@ pub async fn vfs(
         }
     });
 
-    while let Some(km) = recv_from_loop.recv().await {
+    let (exited, mut recv_exited) = tokio::sync::mpsc::unbounded_channel();
+    *EXITED.lock().unwrap() = Some(exited);
+
+    loop {
+        let km = tokio::select! {
+            Some(process) = recv_exited.recv() => {
+                drive_hooks.retain(|_, hooks| {
+                    hooks.remove(&process);
+                    !hooks.is_empty()
+                });
+                process_queues.remove(&process);
+                continue;
+            }
+            km = recv_from_loop.recv() => match km {
+                Some(km) => km,
+                None => break,
+            },
+        };
         if *our_node != km.source.node {
-            Printout::new(
-                1,
-                format!(
-                    "vfs: got request from {}, but requests must come from our node {our_node}",
-                    km.source.node
-                ),
-            )
-            .send(&send_to_terminal)
-            .await;
-            continue;
+            Printout::new(2, format!("vfs: got request from remote {}", km.source))
+                .send(&send_to_terminal)
+                .await;
         }
 
         let queue = process_queues
-            .get(&km.source.process)
-            .cloned()
-            .unwrap_or_else(|| Arc::new(Mutex::new(VecDeque::new())));
+            .entry(km.source.process.clone())
+            .or_insert_with(|| Arc::new(Mutex::new(VecDeque::new())))
+            .clone();
 
         {
             let mut queue_lock = queue.lock().await;
@@ -99,42 +156,60 @@ pub async fn vfs(
         let send_to_loop = send_to_loop.clone();
         let send_to_caps_oracle = send_to_caps_oracle.clone();
         let open_files = open_files.clone();
+        let drive_hooks = drive_hooks.clone();
+        let tree_hashes = tree_hashes.clone();
         let vfs_path = vfs_path.clone();
+        let blob_store = blob_store.clone();
+        let recent = recent.clone();
+        let our_public_key = our_public_key.clone();
+        let feature_flags = feature_flags.clone();
 
         tokio::spawn(async move {
             let mut queue_lock = queue.lock().await;
             if let Some(km) = queue_lock.pop_front() {
+                crate::metrics::handled(&VFS_PROCESS_ID, &km);
                 let (km_id, km_rsvp) =
                     (km.id.clone(), km.rsvp.clone().unwrap_or(km.source.clone()));
+                let keyed = Keyed::of(&km, mutates);
 
-                if let Err(e) = handle_request(
-                    &our_node,
-                    km,
-                    open_files,
-                    &send_to_loop,
-                    &send_to_caps_oracle,
-                    &vfs_path,
-                )
-                .await
-                {
-                    KernelMessage::builder()
-                        .id(km_id)
-                        .source((our_node.as_str(), VFS_PROCESS_ID.clone()))
-                        .target(km_rsvp)
-                        .message(Message::Response((
-                            Response {
-                                inherit: false,
-                                body: serde_json::to_vec(&VfsResponse::Err(e)).unwrap(),
-                                metadata: None,
-                                capabilities: vec![],
-                            },
-                            None,
-                        )))
-                        .build()
-                        .unwrap()
-                        .send(&send_to_loop)
-                        .await;
-                }
+                recent
+                    .handle(keyed, &send_to_loop, |send_to_loop| async move {
+                        if let Err(e) = handle_request(
+                            &our_node,
+                            km,
+                            open_files,
+                            &drive_hooks,
+                            &tree_hashes,
+                            &send_to_loop,
+                            &send_to_caps_oracle,
+                            &vfs_path,
+                            &blob_store,
+                            &our_public_key,
+                            &feature_flags,
+                        )
+                        .await
+                        {
+                            let metadata = e.metadata();
+                            KernelMessage::builder()
+                                .id(km_id)
+                                .source((our_node.as_str(), VFS_PROCESS_ID.clone()))
+                                .target(km_rsvp)
+                                .message(Message::Response((
+                                    Response {
+                                        inherit: false,
+                                        body: serde_json::to_vec(&VfsResponse::Err(e)).unwrap(),
+                                        metadata,
+                                        capabilities: vec![],
+                                    },
+                                    None,
+                                )))
+                                .build()
+                                .unwrap()
+                                .send(&send_to_loop)
+                                .await;
+                        }
+                    })
+                    .await;
             }
         });
     }
@@ -149,9 +224,12 @@ pub async fn vfs(
 /// * `our_node` - The identifier for the current node
 /// * `km` - The incoming kernel message
 /// * `open_files` - A map of currently open files
+/// * `drive_hooks` - The processes to tell of changes to each drive
+/// * `tree_hashes` - The file hashes kept for `TreeHash`
 /// * `send_to_loop` - Sender for kernel messages
 /// * `send_to_caps_oracle` - Sender for capability messages
 /// * `vfs_path` - The base path for the VFS
+/// * `our_public_key` - Our networking key, to verify remote capabilities
 ///
 /// # Returns
 /// * `Result<(), VfsError>` - Result indicating success or a VFS-specific error
@@ -159,14 +237,26 @@ async fn handle_request(
     our_node: &str,
     km: KernelMessage,
     open_files: Arc<DashMap<PathBuf, (Arc<Mutex<fs::File>>, Instant)>>,
+    drive_hooks: &DriveHooks,
+    tree_hashes: &TreeHashes,
     send_to_loop: &MessageSender,
     send_to_caps_oracle: &CapMessageSender,
     vfs_path: &PathBuf,
+    blob_store: &BlobStore,
+    our_public_key: &[u8],
+    feature_flags: &FeatureFlags,
 ) -> Result<(), VfsError> {
+    let denials = Denials::new(
+        feature_flags,
+        (our_node, VFS_PROCESS_ID.clone()).into(),
+        &km,
+        send_to_loop,
+    );
     let Message::Request(Request {
         body,
         expects_response,
         metadata,
+        capabilities,
         ..
     }) = km.message
     else {
@@ -179,26 +269,97 @@ async fn handle_request(
         error: e.to_string(),
     })?;
 
-    // special case for root reading list of all drives.
-    if request.action == VfsAction::ReadDir && request.path == "/" {
+    // a remote node has no package or root here: it may only read a drive
+    // it attached our capability to, as granted to it with `caps`
+    let remote = km.source.node != our_node;
+    if remote {
+        check_remote_caps(
+            &request,
+            &capabilities,
+            &km.source.node,
+            our_node,
+            our_public_key,
+            vfs_path,
+        )
+        .await?;
+    }
+
+    // listing a package's drives, whose path is the package ID alone
+    if let VfsAction::ListDrives = request.action {
+        let package_id = request
+            .path
+            .trim_matches('/')
+            .parse::<PackageId>()
+            .map_err(|e| VfsError::ParseError {
+                error: e.to_string(),
+                path: request.path.clone(),
+            })?;
+        let src_package_id =
+            PackageId::new(km.source.process.package(), km.source.process.publisher());
+        if src_package_id != package_id
+            && !read_capability("", "", true, our_node, &km.source, send_to_caps_oracle).await
+        {
+            denials.report(vfs_capability("", "", true, our_node)).await;
+            return Err(VfsError::NoCap {
+                action: request.action.to_string(),
+                path: request.path,
+            });
+        }
+        let mut drives = vec![];
+        // a package that never created a drive has none
+        if let Ok(mut dir) = fs::read_dir(vfs_path.join(package_id.to_string())).await {
+            while let Some(entry) = dir.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    drives.push(format!(
+                        "/{package_id}/{}",
+                        entry.file_name().to_string_lossy()
+                    ));
+                }
+            }
+        }
+        drives.sort();
+
+        KernelMessage::builder()
+            .id(km.id)
+            .source((our_node, VFS_PROCESS_ID.clone()))
+            .target(km.rsvp.unwrap_or(km.source))
+            .message(Message::Response((
+                Response {
+                    inherit: false,
+                    body: serde_json::to_vec(&VfsResponse::ListDrives(drives)).unwrap(),
+                    metadata,
+                    capabilities: vec![],
+                },
+                None,
+            )))
+            .build()
+            .unwrap()
+            .send(send_to_loop)
+            .await;
+        return Ok(());
+    }
+
+    // special case for root reading list of all packages, or of all
+    // drives belonging to one package (a path with a single component).
+    if matches!(
+        request.action,
+        VfsAction::ReadDir | VfsAction::ReadDirPage { .. } | VfsAction::CountDir
+    ) && request.path.trim_matches('/').split('/').count() <= 1
+    {
         // check if src has root
         let has_root_cap =
             read_capability("", "", true, our_node, &km.source, send_to_caps_oracle).await;
         if has_root_cap {
-            let mut dir = fs::read_dir(&vfs_path).await?;
-            let mut entries = Vec::new();
-            while let Some(entry) = dir.next_entry().await? {
-                let entry_path = entry.path();
-                let relative_path = entry_path.strip_prefix(&vfs_path).unwrap_or(&entry_path);
-
-                let metadata = entry.metadata().await?;
-                let file_type = get_file_type(&metadata);
-                let dir_entry = DirEntry {
-                    path: relative_path.display().to_string(),
-                    file_type,
-                };
-                entries.push(dir_entry);
+            let listed_path = join_paths_safely(&vfs_path, &request.path);
+            if !normalize_path(&listed_path).starts_with(&vfs_path) {
+                return Err(VfsError::BadRequest {
+                    error: format!(
+                        "input path tries to escape parent vfs directory: {}",
+                        request.path
+                    ),
+                });
             }
+            let response = list_dir(&listed_path, vfs_path, &request.action).await?;
 
             KernelMessage::builder()
                 .id(km.id)
@@ -207,7 +368,7 @@ async fn handle_request(
                 .message(Message::Response((
                     Response {
                         inherit: false,
-                        body: serde_json::to_vec(&VfsResponse::ReadDir(entries)).unwrap(),
+                        body: serde_json::to_vec(&response).unwrap(),
                         metadata,
                         capabilities: vec![],
                     },
@@ -219,6 +380,7 @@ async fn handle_request(
                 .await;
             return Ok(());
         } else {
+            denials.report(vfs_capability("", "", true, our_node)).await;
             return Err(VfsError::NoCap {
                 action: request.action.to_string(),
                 path: request.path,
@@ -226,17 +388,69 @@ async fn handle_request(
         }
     }
 
+    // special case for root reading a file or directory from the host filesystem
+    if let VfsAction::ReadHostPath { host_path } | VfsAction::HostPathModified { host_path } =
+        &request.action
+    {
+        if !read_capability("", "", true, our_node, &km.source, send_to_caps_oracle).await {
+            denials.report(vfs_capability("", "", true, our_node)).await;
+            return Err(VfsError::NoCap {
+                action: request.action.to_string(),
+                path: host_path.clone(),
+            });
+        }
+        let read = matches!(request.action, VfsAction::ReadHostPath { .. });
+        let host_path = PathBuf::from(host_path);
+        let (response, blob) = tokio::task::spawn_blocking(move || -> Result<_, VfsError> {
+            if !read {
+                return Ok((VfsResponse::Modified(host_path_modified(&host_path)?), None));
+            }
+            let blob = LazyLoadBlob {
+                mime: Some("application/zip".into()),
+                bytes: read_host_path(&host_path)?,
+            };
+            Ok((VfsResponse::Read, Some(blob)))
+        })
+        .await
+        .map_err(|e| VfsError::IOError {
+            error: e.to_string(),
+            path: request.path.clone(),
+        })??;
+
+        KernelMessage::builder()
+            .id(km.id)
+            .source((our_node, VFS_PROCESS_ID.clone()))
+            .target(km.rsvp.unwrap_or(km.source))
+            .message(Message::Response((
+                Response {
+                    inherit: false,
+                    body: serde_json::to_vec(&response).unwrap(),
+                    metadata,
+                    capabilities: vec![],
+                },
+                None,
+            )))
+            .lazy_load_blob(blob)
+            .build()
+            .unwrap()
+            .send(send_to_loop)
+            .await;
+        return Ok(());
+    }
+
     // current prepend to filepaths needs to be: /package_id/drive/path
     let (package_id, drive, rest) = parse_package_and_drive(&request.path, &vfs_path).await?;
     let drive = format!("/{package_id}/{drive}");
     let action = request.action;
     let path = PathBuf::from(&request.path);
 
-    if km.source.process != *KERNEL_PROCESS_ID {
+    // the sync module checks the capabilities of those who have it mirror a drive
+    if !remote && km.source.process != *KERNEL_PROCESS_ID && km.source.process != *SYNC_PROCESS_ID {
         check_caps(
             our_node,
             &km.source,
             &send_to_caps_oracle,
+            &denials,
             &action,
             &path,
             &drive,
@@ -249,10 +463,169 @@ async fn handle_request(
     let base_drive = join_paths_safely(&vfs_path, &drive);
     let path = join_paths_safely(&base_drive, &rest);
 
+    // refuse writes that would run the disk out, rather than fail partway
+    let growth = match &action {
+        VfsAction::Write | VfsAction::WriteAll | VfsAction::Append | VfsAction::AddZip => km
+            .lazy_load_blob
+            .as_ref()
+            .map_or(0, |blob| blob.bytes.len() as u64),
+        // only what it adds past the file's current end
+        VfsAction::SetLen(len) => {
+            let current_len = fs::metadata(&path).await.map_or(0, |meta| meta.len());
+            len.saturating_sub(current_len)
+        }
+        VfsAction::CopyFile { .. } | VfsAction::CloneFile { .. } => {
+            fs::metadata(&path).await.map_or(0, |meta| meta.len())
+        }
+        _ => 0,
+    };
+    if growth > 0 {
+        crate::disk::reserve(growth).map_err(|free| VfsError::NoSpace {
+            path: request.path.clone(),
+            free,
+        })?;
+    }
+
+    // large files are handed to the blob store rather than read into memory
+    let mut blob_handle = None;
+
+    // found before the action, which may remove hooks, and sent once it succeeds
+    let drive_events =
+        drive_events(&action, &km.source, &request.path, drive_hooks, vfs_path).await;
+
+    let writes = changes_drive(&action) || matches!(action, VfsAction::DriveTransaction { .. });
+    // the paths whose hashes the action makes stale, to drop once it is done
+    let written: Vec<PathBuf> = match &action {
+        _ if !writes => vec![],
+        VfsAction::DeleteDrive => vec![base_drive.clone()],
+        VfsAction::DriveTransaction { .. } => {
+            vec![join_paths_safely(vfs_path, &format!("/{package_id}"))]
+        }
+        VfsAction::Rename { new_path }
+        | VfsAction::CopyFile { new_path }
+        | VfsAction::CloneFile { new_path }
+        | VfsAction::HardLink { new_path } => {
+            vec![path.clone(), join_paths_safely(vfs_path, new_path)]
+        }
+        _ => vec![path.clone()],
+    };
+    // held off while a backup snapshots the drives, so that it sees one point
+    // in time, and after, the files written are copied out of the snapshot first
+    let _write = if writes {
+        Some(crate::quiesce::write_to(written.clone()).await?)
+    } else {
+        None
+    };
+
     let (response_body, bytes) = match action {
         VfsAction::CreateDrive => {
             let drive_path = join_paths_safely(vfs_path, &drive);
             fs::create_dir_all(drive_path).await?;
+            // the kernel needs no capabilities
+            if km.source.process != *KERNEL_PROCESS_ID {
+                update_drive_caps(true, &drive, our_node, &km.source, send_to_caps_oracle).await?;
+            }
+            (VfsResponse::Ok, None)
+        }
+        VfsAction::DeleteDrive => {
+            // a package's code lives in its pkg drive, which is only removed
+            // with the package
+            if drive.ends_with("/pkg") {
+                return Err(VfsError::BadRequest {
+                    error: format!("can't delete the pkg drive {drive}"),
+                });
+            }
+            let drive_path = join_paths_safely(vfs_path, &drive);
+            // files left open in the drive would keep writing to removed inodes
+            open_files.retain(|open_path, _| !open_path.starts_with(&drive_path));
+            match fs::remove_dir_all(&drive_path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+            drive_hooks.remove(&drive);
+            // caps to the drive, whoever they were given to, would carry over
+            // to a drive later made at the same path
+            let (send_cap_bool, recv_cap_bool) = tokio::sync::oneshot::channel();
+            send_to_caps_oracle
+                .send(CapMessage::DropEverywhere {
+                    caps: drive_caps(&drive, our_node),
+                    responder: Some(send_cap_bool),
+                })
+                .await?;
+            recv_cap_bool.await?;
+            (VfsResponse::Ok, None)
+        }
+        VfsAction::ListDrives => {
+            unreachable!("vfs: ListDrives is handled before path parsing")
+        }
+        VfsAction::AddDriveHook { process } => {
+            // events name what changed in the drive: a process may not have
+            // them sent to another
+            if process != km.source.process {
+                return Err(VfsError::BadRequest {
+                    error: format!("{} may not hook {process} to a drive", km.source.process),
+                });
+            }
+            drive_hooks.entry(drive).or_default().insert(process);
+            (VfsResponse::Ok, None)
+        }
+        VfsAction::RemoveDriveHook { process } => {
+            if let Some(mut hooks) = drive_hooks.get_mut(&drive) {
+                hooks.remove(&process);
+            }
+            drive_hooks.remove_if(&drive, |_, hooks| hooks.is_empty());
+            (VfsResponse::Ok, None)
+        }
+        VfsAction::DriveTransaction { id, step } => {
+            // every drive of the package, not only the one the path is in,
+            // since installing it may change any of them
+            let drive_path = join_paths_safely(vfs_path, &format!("/{package_id}"));
+            // holds a copy of the package's drives, if it had any when prepared
+            let prepared = transactions_path(vfs_path)
+                .join(id.to_string())
+                .join(package_id.to_string());
+            let io_error = |e: std::io::Error| VfsError::IOError {
+                error: e.to_string(),
+                path: request.path.clone(),
+            };
+            match step {
+                TransactionStep::Prepare => {
+                    let _ = fs::remove_dir_all(&prepared).await;
+                    fs::create_dir_all(&prepared).await.map_err(io_error)?;
+                    if fs::try_exists(&drive_path).await.map_err(io_error)? {
+                        let copy = prepared.join("drive");
+                        tokio::task::spawn_blocking(move || copy_dir(&drive_path, &copy))
+                            .await
+                            .map_err(|e| VfsError::IOError {
+                                error: e.to_string(),
+                                path: request.path.clone(),
+                            })?
+                            .map_err(io_error)?;
+                    }
+                }
+                TransactionStep::Commit => {}
+                TransactionStep::Abort => {
+                    if fs::try_exists(&prepared).await.map_err(io_error)? {
+                        open_files.retain(|open_path, _| !open_path.starts_with(&drive_path));
+                        match fs::remove_dir_all(&drive_path).await {
+                            Ok(()) => {}
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                            Err(e) => return Err(io_error(e)),
+                        }
+                        let copy = prepared.join("drive");
+                        if fs::try_exists(&copy).await.map_err(io_error)? {
+                            fs::rename(&copy, &drive_path).await.map_err(io_error)?;
+                        }
+                    }
+                }
+            }
+            if step != TransactionStep::Prepare {
+                let _ = fs::remove_dir_all(&prepared).await;
+                // gone once the last of its drives is
+                let _ = fs::remove_dir(prepared.parent().unwrap()).await;
+                let _ = fs::remove_dir(transactions_path(vfs_path).join(id.to_string())).await;
+            }
             (VfsResponse::Ok, None)
         }
         VfsAction::CreateDir => {
@@ -320,8 +693,19 @@ async fn handle_request(
             (VfsResponse::Ok, None)
         }
         VfsAction::Read => {
-            let contents = fs::read(&path).await?;
-            (VfsResponse::Read, Some(contents))
+            // the blob store holds a copy, so is skipped while disk space is low
+            if fs::metadata(&path).await?.len() >= HANDLE_THRESHOLD as u64 && !crate::disk::is_low()
+            {
+                blob_handle = Some(
+                    blob_store
+                        .put_file(&path, Some("application/octet-stream".into()))
+                        .await?,
+                );
+                (VfsResponse::Read, None)
+            } else {
+                let contents = fs::read(&path).await?;
+                (VfsResponse::Read, Some(contents))
+            }
         }
         VfsAction::ReadToEnd => {
             let file = open_file(open_files, &path, false, false).await?;
@@ -337,22 +721,8 @@ async fn handle_request(
             file.read_exact(&mut contents).await?;
             (VfsResponse::Read, Some(contents))
         }
-        VfsAction::ReadDir => {
-            let mut dir = fs::read_dir(&path).await?;
-            let mut entries = Vec::new();
-            while let Some(entry) = dir.next_entry().await? {
-                let entry_path = entry.path();
-                let relative_path = entry_path.strip_prefix(vfs_path).unwrap_or(&entry_path);
-
-                let metadata = entry.metadata().await?;
-                let file_type = get_file_type(&metadata);
-                let dir_entry = DirEntry {
-                    path: relative_path.display().to_string(),
-                    file_type,
-                };
-                entries.push(dir_entry);
-            }
-            (VfsResponse::ReadDir(entries), None)
+        VfsAction::ReadDir | VfsAction::ReadDirPage { .. } | VfsAction::CountDir => {
+            (list_dir(&path, vfs_path, &action).await?, None)
         }
         VfsAction::ReadToString => {
             let file = open_file(open_files, &path, false, false).await?;
@@ -405,6 +775,40 @@ async fn handle_request(
                 })?;
             (VfsResponse::Ok, None)
         }
+        VfsAction::CloneFile { new_path } => {
+            let new_path = join_paths_safely(vfs_path, &new_path);
+            let from = path.clone();
+            tokio::task::spawn_blocking(move || clone_tree(&from, &new_path))
+                .await
+                .map_err(|e| VfsError::IOError {
+                    error: e.to_string(),
+                    path: request.path.clone(),
+                })?
+                .map_err(|e| VfsError::IOError {
+                    error: e.to_string(),
+                    path: request.path,
+                })?;
+            (VfsResponse::Ok, None)
+        }
+        VfsAction::HardLink { new_path } => {
+            // a link to another drive would let a write there change a file
+            // here, and keep it past this drive's deletion
+            let (new_package_id, new_drive, _) =
+                parse_package_and_drive(&new_path, vfs_path).await?;
+            if format!("/{new_package_id}/{new_drive}") != drive {
+                return Err(VfsError::BadRequest {
+                    error: format!("can't link {} to another drive: {new_path}", request.path),
+                });
+            }
+            let new_path = join_paths_safely(vfs_path, &new_path);
+            fs::hard_link(&path, new_path)
+                .await
+                .map_err(|e| VfsError::IOError {
+                    error: e.to_string(),
+                    path: request.path,
+                })?;
+            (VfsResponse::Ok, None)
+        }
         VfsAction::Metadata => {
             let metadata = fs::metadata(&path).await.map_err(|e| VfsError::IOError {
                 error: e.to_string(),
@@ -456,6 +860,24 @@ async fn handle_request(
             let hash: [u8; 32] = hasher.finalize().into();
             (VfsResponse::Hash(hash), None)
         }
+        VfsAction::TreeHash => {
+            let dir = path.clone();
+            let tree_hashes = tree_hashes.clone();
+            let manifest = tokio::task::spawn_blocking(move || tree_manifest(&dir, &tree_hashes))
+                .await
+                .map_err(|e| VfsError::IOError {
+                    error: e.to_string(),
+                    path: request.path.clone(),
+                })?
+                .map_err(|e| VfsError::IOError {
+                    error: e.to_string(),
+                    path: request.path,
+                })?;
+            (VfsResponse::TreeHash(manifest), None)
+        }
+        VfsAction::ReadHostPath { .. } | VfsAction::HostPathModified { .. } => {
+            unreachable!("vfs: host paths are handled before path parsing")
+        }
         VfsAction::AddZip => {
             let Some(blob) = km.lazy_load_blob else {
                 return Err(VfsError::BadRequest {
@@ -475,6 +897,14 @@ async fn handle_request(
 
             fs::create_dir_all(path.clone()).await?;
 
+            let mut progress = crate::progress::Reporter::new(
+                our_node,
+                VFS_PROCESS_ID.clone(),
+                send_to_loop,
+                km.id.to_string(),
+                km.source.clone(),
+                format!("unzipping into {}", request.path),
+            );
             // loop through items in archive; recursively add to root
             for i in 0..zip.len() {
                 // must destruct the zip file created in zip.by_index()
@@ -504,11 +934,34 @@ async fn handle_request(
                         error: "vfs: zip with non-file non-dir".into(),
                     });
                 };
+                progress.report(i as u64 + 1, zip.len() as u64).await;
             }
             (VfsResponse::Ok, None)
         }
     };
 
+    for written in written {
+        tree_hashes.retain(|hashed, _| !hashed.starts_with(&written));
+    }
+
+    for (process, event) in drive_events {
+        KernelMessage::builder()
+            .id(rand::random())
+            .source((our_node, VFS_PROCESS_ID.clone()))
+            .target((our_node, process))
+            .message(Message::Request(Request {
+                inherit: false,
+                expects_response: None,
+                body: serde_json::to_vec(&event).unwrap(),
+                metadata: None,
+                capabilities: vec![],
+            }))
+            .build()
+            .unwrap()
+            .send(send_to_loop)
+            .await;
+    }
+
     if let Some(target) = km.rsvp.or_else(|| expects_response.map(|_| km.source)) {
         KernelMessage::builder()
             .id(km.id)
@@ -527,6 +980,7 @@ async fn handle_request(
                 mime: Some("application/octet-stream".into()),
                 bytes,
             }))
+            .blob_handle(blob_handle)
             .build()
             .unwrap()
             .send(send_to_loop)
@@ -587,6 +1041,103 @@ async fn parse_package_and_drive(
     Ok((package_id, drive, remaining_path))
 }
 
+/// whether a request body asks for a change, to a file, an open file's
+/// position or the drives and their hooks, so that a retry of it must get the
+/// first response rather than be carried out again
+fn mutates(body: &[u8]) -> bool {
+    let Ok(request) = serde_json::from_slice::<VfsRequest>(body) else {
+        return false;
+    };
+    !matches!(
+        request.action,
+        VfsAction::ListDrives
+            | VfsAction::Read
+            | VfsAction::ReadDir
+            | VfsAction::ReadDirPage { .. }
+            | VfsAction::CountDir
+            | VfsAction::Metadata
+            | VfsAction::Len
+            | VfsAction::Hash
+            | VfsAction::TreeHash
+            | VfsAction::ReadHostPath { .. }
+            | VfsAction::HostPathModified { .. }
+    )
+}
+
+/// whether `action` changes what is on disk in its drive
+fn changes_drive(action: &VfsAction) -> bool {
+    matches!(
+        action,
+        VfsAction::CreateDrive
+            | VfsAction::DeleteDrive
+            | VfsAction::CreateDir
+            | VfsAction::CreateDirAll
+            | VfsAction::CreateFile
+            | VfsAction::OpenFile { create: true }
+            | VfsAction::Write
+            | VfsAction::WriteAll
+            | VfsAction::Append
+            | VfsAction::SetLen(_)
+            | VfsAction::RemoveFile
+            | VfsAction::RemoveDir
+            | VfsAction::RemoveDirAll
+            | VfsAction::Rename { .. }
+            | VfsAction::CopyFile { .. }
+            | VfsAction::CloneFile { .. }
+            | VfsAction::HardLink { .. }
+            | VfsAction::AddZip
+    )
+}
+
+/// the events for the processes hooked to the drives `action` changes,
+/// other than the actor itself
+async fn drive_events(
+    action: &VfsAction,
+    actor: &Address,
+    path: &str,
+    drive_hooks: &DriveHooks,
+    vfs_path: &PathBuf,
+) -> Vec<(ProcessId, DriveEvent)> {
+    if drive_hooks.is_empty() || !changes_drive(action) {
+        return vec![];
+    }
+    // the path changed in each drive, and where it came from: where a rename,
+    // copy, clone or link lands, then, for a rename, where it left
+    let paths = match action {
+        VfsAction::Rename { new_path } => vec![(new_path.as_str(), Some(path)), (path, None)],
+        VfsAction::CopyFile { new_path }
+        | VfsAction::CloneFile { new_path }
+        | VfsAction::HardLink { new_path } => vec![(new_path.as_str(), Some(path))],
+        _ => vec![(path, None)],
+    };
+    let mut drives = HashSet::new();
+    let mut events = vec![];
+    for (changed, from) in paths {
+        let Ok((package_id, drive, _)) = parse_package_and_drive(changed, vfs_path).await else {
+            continue;
+        };
+        let drive = format!("/{package_id}/{drive}");
+        if !drives.insert(drive.clone()) {
+            continue;
+        }
+        let Some(hooks) = drive_hooks.get(&drive) else {
+            continue;
+        };
+        for process in hooks.iter().filter(|process| **process != actor.process) {
+            events.push((
+                process.clone(),
+                DriveEvent {
+                    path: changed.to_string(),
+                    from: from.map(str::to_string),
+                    action: action.clone(),
+                    actor: actor.clone(),
+                },
+            ));
+        }
+    }
+    events
+}
+
 async fn open_file<P: AsRef<Path>>(
     open_files: Arc<DashMap<PathBuf, (Arc<Mutex<fs::File>>, Instant)>>,
     path: P,
@@ -620,6 +1171,7 @@ async fn check_caps(
     our_node: &str,
     source: &Address,
     send_to_caps_oracle: &CapMessageSender,
+    denials: &Denials,
     action: &VfsAction,
     path: &PathBuf,
     drive: &str,
@@ -656,6 +1208,9 @@ async fn check_caps(
                 if read_capability("", "", true, our_node, source, send_to_caps_oracle).await {
                     return Ok(());
                 }
+                denials
+                    .report(vfs_capability("write", drive, false, our_node))
+                    .await;
                 return Err(VfsError::NoCap {
                     action: action.to_string(),
                     path: path.display().to_string(),
@@ -665,11 +1220,14 @@ async fn check_caps(
         }
         VfsAction::Read
         | VfsAction::ReadDir
+        | VfsAction::ReadDirPage { .. }
+        | VfsAction::CountDir
         | VfsAction::ReadExact(_)
         | VfsAction::ReadToEnd
         | VfsAction::ReadToString
         | VfsAction::Seek { .. }
         | VfsAction::Hash
+        | VfsAction::TreeHash
         | VfsAction::Metadata
         | VfsAction::Len => {
             if &src_package_id == package_id {
@@ -682,6 +1240,9 @@ async fn check_caps(
                 if read_capability("", "", true, our_node, source, send_to_caps_oracle).await {
                     return Ok(());
                 }
+                denials
+                    .report(vfs_capability("read", drive, false, our_node))
+                    .await;
                 return Err(VfsError::NoCap {
                     action: action.to_string(),
                     path: path.display().to_string(),
@@ -689,7 +1250,10 @@ async fn check_caps(
             }
             Ok(())
         }
-        VfsAction::CopyFile { new_path } | VfsAction::Rename { new_path } => {
+        VfsAction::CopyFile { new_path }
+        | VfsAction::CloneFile { new_path }
+        | VfsAction::HardLink { new_path }
+        | VfsAction::Rename { new_path } => {
             // these have 2 paths to validate
             let (new_package_id, new_drive, _rest) =
                 parse_package_and_drive(new_path, &vfs_path).await?;
@@ -715,6 +1279,9 @@ async fn check_caps(
                 if read_capability("", "", true, our_node, source, send_to_caps_oracle).await {
                     return Ok(());
                 }
+                denials
+                    .report(vfs_capability("write", drive, false, our_node))
+                    .await;
                 return Err(VfsError::NoCap {
                     action: action.to_string(),
                     path: path.display().to_string(),
@@ -740,6 +1307,9 @@ async fn check_caps(
                 if read_capability("", "", true, our_node, source, send_to_caps_oracle).await {
                     return Ok(());
                 }
+                denials
+                    .report(vfs_capability("write", &new_drive, false, our_node))
+                    .await;
                 return Err(VfsError::NoCap {
                     action: action.to_string(),
                     path: path.display().to_string(),
@@ -747,23 +1317,87 @@ async fn check_caps(
             }
             Ok(())
         }
-        VfsAction::CreateDrive => {
+        VfsAction::ReadHostPath { .. } | VfsAction::HostPathModified { .. } => {
+            // handled before path parsing, only reachable with root
+            if !read_capability("", "", true, our_node, source, send_to_caps_oracle).await {
+                denials.report(vfs_capability("", "", true, our_node)).await;
+                return Err(VfsError::NoCap {
+                    action: action.to_string(),
+                    path: path.display().to_string(),
+                });
+            }
+            Ok(())
+        }
+        VfsAction::CreateDrive
+        | VfsAction::DeleteDrive
+        | VfsAction::ListDrives
+        | VfsAction::AddDriveHook { .. }
+        | VfsAction::RemoveDriveHook { .. }
+        | VfsAction::DriveTransaction { .. } => {
             if &src_package_id != package_id {
                 // check for root cap
                 if !read_capability("", "", true, our_node, source, send_to_caps_oracle).await {
+                    denials.report(vfs_capability("", "", true, our_node)).await;
                     return Err(VfsError::NoCap {
                         action: action.to_string(),
                         path: path.display().to_string(),
                     });
                 }
             }
-            add_capability("read", &drive, &our_node, &source, send_to_caps_oracle).await?;
-            add_capability("write", &drive, &our_node, &source, send_to_caps_oracle).await?;
             Ok(())
         }
     }
 }
 
+/// check that a request from `from_node` carries a read capability to the
+/// drive it names, issued by us and granted to that node. remote access is
+/// read-only: every other action, and anything outside a drive, is refused.
+async fn check_remote_caps(
+    request: &VfsRequest,
+    capabilities: &[(Capability, Vec<u8>)],
+    from_node: &str,
+    our_node: &str,
+    our_public_key: &[u8],
+    vfs_path: &PathBuf,
+) -> Result<(), VfsError> {
+    let no_cap = || VfsError::NoCap {
+        action: request.action.to_string(),
+        path: request.path.clone(),
+    };
+    match request.action {
+        VfsAction::Read
+        | VfsAction::ReadDir
+        | VfsAction::ReadDirPage { .. }
+        | VfsAction::CountDir
+        | VfsAction::ReadExact(_)
+        | VfsAction::ReadToEnd
+        | VfsAction::ReadToString
+        | VfsAction::Seek { .. }
+        | VfsAction::Hash
+        | VfsAction::TreeHash
+        | VfsAction::Metadata
+        | VfsAction::Len => {}
+        _ => return Err(no_cap()),
+    }
+    // a path naming no drive fails here, so remote nodes never reach the
+    // listings of packages and drives
+    let (package_id, drive, _rest) = parse_package_and_drive(&request.path, vfs_path).await?;
+    let drive = format!("/{package_id}/{drive}");
+    let wanted = serde_json::json!({ "kind": "read", "drive": drive });
+    let attached = capabilities.iter().any(|(cap, sig)| {
+        cap.issuer.node == our_node
+            && cap.issuer.process == *VFS_PROCESS_ID
+            && serde_json::from_str::<serde_json::Value>(&cap.params).ok() == Some(wanted.clone())
+            && ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, our_public_key)
+                .verify(&remote_grant_bytes(cap, from_node), sig)
+                .is_ok()
+    });
+    if !attached {
+        return Err(no_cap());
+    }
+    Ok(())
+}
+
 async fn read_capability(
     kind: &str,
     drive: &str,
@@ -773,18 +1407,10 @@ async fn read_capability(
     send_to_caps_oracle: &CapMessageSender,
 ) -> bool {
     let (send_cap_bool, recv_cap_bool) = tokio::sync::oneshot::channel();
-    let cap = Capability::new(
-        (our_node, VFS_PROCESS_ID.clone()),
-        if root {
-            "{\"root\":true}".to_string()
-        } else {
-            format!("{{\"kind\": \"{kind}\", \"drive\": \"{drive}\"}}")
-        },
-    );
     if let Err(_) = send_to_caps_oracle
         .send(CapMessage::Has {
             on: source.process.clone(),
-            cap,
+            cap: vfs_capability(kind, drive, root, our_node),
             responder: send_cap_bool,
         })
         .await
@@ -794,34 +1420,402 @@ async fn read_capability(
     recv_cap_bool.await.unwrap_or(false)
 }
 
-async fn add_capability(
-    kind: &str,
+/// the vfs capability of `kind` to `drive`, or the root one if `root`
+fn vfs_capability(kind: &str, drive: &str, root: bool, our_node: &str) -> Capability {
+    Capability::new(
+        (our_node, VFS_PROCESS_ID.clone()),
+        if root {
+            "{\"root\":true}".to_string()
+        } else {
+            format!("{{\"kind\": \"{kind}\", \"drive\": \"{drive}\"}}")
+        },
+    )
+}
+
+/// give `source` read and write capabilities to `drive`, or take them back
+async fn update_drive_caps(
+    add: bool,
     drive: &str,
     our_node: &str,
     source: &Address,
     send_to_caps_oracle: &CapMessageSender,
 ) -> Result<(), VfsError> {
-    let cap = Capability::new(
-        (our_node, VFS_PROCESS_ID.clone()),
-        format!("{{\"kind\": \"{kind}\", \"drive\": \"{drive}\"}}"),
-    );
+    let caps = drive_caps(drive, our_node);
+    let on = source.process.clone();
     let (send_cap_bool, recv_cap_bool) = tokio::sync::oneshot::channel();
+    let responder = Some(send_cap_bool);
     send_to_caps_oracle
-        .send(CapMessage::Add {
-            on: source.process.clone(),
-            caps: vec![cap],
-            responder: Some(send_cap_bool),
+        .send(if add {
+            CapMessage::Add {
+                on,
+                caps,
+                responder,
+            }
+        } else {
+            CapMessage::Drop {
+                on,
+                caps,
+                responder,
+            }
         })
         .await?;
     match recv_cap_bool.await? {
         true => Ok(()),
         false => Err(VfsError::NoCap {
-            action: "add_capability".to_string(),
+            action: if add {
+                "add_capability"
+            } else {
+                "drop_capability"
+            }
+            .to_string(),
             path: drive.to_string(),
         }),
     }
 }
 
+/// the read and write caps to `drive`
+fn drive_caps(drive: &str, our_node: &str) -> Vec<Capability> {
+    ["read", "write"]
+        .into_iter()
+        .map(|kind| {
+            Capability::new(
+                (our_node, VFS_PROCESS_ID.clone()),
+                format!("{{\"kind\": \"{kind}\", \"drive\": \"{drive}\"}}"),
+            )
+        })
+        .collect()
+}
+
+/// where drives are kept while transactions over them are open, by
+/// transaction ID and then drive path
+fn transactions_path(vfs_path: &Path) -> PathBuf {
+    vfs_path.with_file_name("vfs_transactions")
+}
+
+/// copy a directory and everything in it, sharing blocks with the original
+/// where the host filesystem can
+pub fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            reflink_copy::reflink_or_copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+/// read a file from the host filesystem, or zip up a host directory.
+/// directories are zipped with sorted entries and a fixed timestamp so that
+/// unchanged contents always produce identical bytes, and so identical hashes.
+fn read_host_path(host_path: &Path) -> Result<Vec<u8>, VfsError> {
+    let io_error = |e: std::io::Error| VfsError::IOError {
+        error: e.to_string(),
+        path: host_path.display().to_string(),
+    };
+    if !host_path.is_dir() {
+        return std::fs::read(host_path).map_err(io_error);
+    }
+
+    fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                collect_files(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+    let mut files = vec![];
+    collect_files(host_path, &mut files).map_err(io_error)?;
+    files.sort();
+
+    let zip_error = |e: zip::result::ZipError| VfsError::IOError {
+        error: e.to_string(),
+        path: host_path.display().to_string(),
+    };
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default())
+        .unix_permissions(0o755);
+    let mut writer = std::io::Cursor::new(Vec::new());
+    {
+        let mut zip = zip::ZipWriter::new(&mut writer);
+        for file in files {
+            let name = file
+                .strip_prefix(host_path)
+                .unwrap_or(&file)
+                .to_string_lossy()
+                .to_string();
+            zip.start_file(name, options).map_err(zip_error)?;
+            std::io::Write::write_all(&mut zip, &std::fs::read(&file).map_err(io_error)?)
+                .map_err(io_error)?;
+        }
+        zip.finish().map_err(zip_error)?;
+    }
+    Ok(writer.into_inner())
+}
+
+/// when `host_path`, or anything in it, was last modified, in milliseconds
+/// since the unix epoch. a file removed from a directory modifies it.
+fn host_path_modified(host_path: &Path) -> Result<u64, VfsError> {
+    fn newest(path: &Path) -> std::io::Result<std::time::SystemTime> {
+        let metadata = std::fs::metadata(path)?;
+        let mut modified = metadata.modified()?;
+        if metadata.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                modified = modified.max(newest(&entry?.path())?);
+            }
+        }
+        Ok(modified)
+    }
+    let modified = newest(host_path).map_err(|e| VfsError::IOError {
+        error: e.to_string(),
+        path: host_path.display().to_string(),
+    })?;
+    Ok(modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64)
+}
+
+/// clone the file or directory at `from` to `to`, sharing blocks where the
+/// host filesystem can
+fn clone_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    if !std::fs::metadata(from)?.is_dir() {
+        return reflink_copy::reflink_or_copy(from, to).map(|_| ());
+    }
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        // not followed through symlinks
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() || file_type.is_file() {
+            clone_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// the [`TreeManifest`] of the directory at `dir`, reading only the files
+/// changed since they were last hashed
+fn tree_manifest(dir: &Path, tree_hashes: &TreeHashes) -> std::io::Result<TreeManifest> {
+    if !std::fs::metadata(dir)?.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "TreeHash path must be a directory",
+        ));
+    }
+    let mut entries = vec![];
+    let (root, _) = hash_dir(dir, dir, tree_hashes, &mut entries)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    // forget the hashes of files removed from outside the vfs
+    let files: HashSet<PathBuf> = entries
+        .iter()
+        .filter(|entry| matches!(entry.file_type, FileType::File))
+        .map(|entry| dir.join(&entry.path))
+        .collect();
+    tree_hashes.retain(|hashed, _| !hashed.starts_with(dir) || files.contains(hashed));
+    Ok(TreeManifest { root, entries })
+}
+
+/// the hash of a directory, over the type, name and hash of each entry in it
+/// in name order, and the length of every file in it. its entries are added
+/// to `entries`, by path relative to `root`.
+fn hash_dir(
+    dir: &Path,
+    root: &Path,
+    tree_hashes: &TreeHashes,
+    entries: &mut Vec<TreeEntry>,
+) -> std::io::Result<([u8; 32], u64)> {
+    use sha2::{Digest, Sha256};
+    let mut children = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        // not followed through symlinks
+        let metadata = entry.metadata()?;
+        let path = entry.path();
+        let (file_type, hash, len) = if metadata.is_dir() {
+            let (hash, len) = hash_dir(&path, root, tree_hashes, entries)?;
+            (FileType::Directory, hash, len)
+        } else if metadata.is_file() {
+            let hash = hash_file(&path, &metadata, tree_hashes)?;
+            (FileType::File, hash, metadata.len())
+        } else {
+            continue;
+        };
+        children.push((entry.file_name(), path, file_type, hash, len));
+    }
+    children.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut hasher = Sha256::new();
+    let mut total_len = 0;
+    for (name, path, file_type, hash, len) in children {
+        hasher.update(match file_type {
+            FileType::Directory => b"d",
+            _ => b"f",
+        });
+        hasher.update(name.to_string_lossy().as_bytes());
+        hasher.update([0u8]);
+        hasher.update(hash);
+        total_len += len;
+        entries.push(TreeEntry {
+            path: path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .display()
+                .to_string(),
+            file_type,
+            len,
+            hash,
+        });
+    }
+    Ok((hasher.finalize().into(), total_len))
+}
+
+/// the hash of a file, as [`VfsAction::Hash`] gives it, from `tree_hashes`
+/// if the file is unchanged since it was kept there
+fn hash_file(
+    path: &Path,
+    metadata: &std::fs::Metadata,
+    tree_hashes: &TreeHashes,
+) -> std::io::Result<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    if let Some(cached) = tree_hashes.get(path) {
+        let (len, cached_modified, hash) = *cached;
+        if len == metadata.len() && cached_modified == modified {
+            return Ok(hash);
+        }
+    }
+    let hashed_at = std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    let hash: [u8; 32] = hasher.finalize().into();
+    if modified + MTIME_GRANULARITY.as_nanos() < hashed_at.as_nanos() {
+        if tree_hashes.len() >= MAX_TREE_HASHES {
+            let any = tree_hashes.iter().next().map(|entry| entry.key().clone());
+            if let Some(any) = any {
+                tree_hashes.remove(&any);
+            }
+        }
+        tree_hashes.insert(path.to_path_buf(), (metadata.len(), modified, hash));
+    }
+    Ok(hash)
+}
+
+/// list a directory for a [`VfsAction::ReadDir`], [`VfsAction::ReadDirPage`]
+/// or [`VfsAction::CountDir`]
+async fn list_dir(
+    dir_path: &Path,
+    vfs_path: &Path,
+    action: &VfsAction,
+) -> Result<VfsResponse, VfsError> {
+    let mut dir = fs::read_dir(dir_path).await?;
+    if let VfsAction::CountDir = action {
+        let mut count = 0;
+        while dir.next_entry().await?.is_some() {
+            count += 1;
+        }
+        return Ok(VfsResponse::CountDir(count));
+    }
+    let VfsAction::ReadDirPage {
+        cursor,
+        limit,
+        sort,
+        descending,
+    } = action
+    else {
+        let mut entries = Vec::new();
+        while let Some(entry) = dir.next_entry().await? {
+            entries.push(dir_entry(&entry, vfs_path, &entry.metadata().await?));
+        }
+        return Ok(VfsResponse::ReadDir(entries));
+    };
+    if *limit == 0 {
+        return Err(VfsError::BadRequest {
+            error: "ReadDirPage limit must be at least 1".into(),
+        });
+    }
+
+    // entries are ordered by their sort key, then by path, so that a cursor,
+    // naming the last entry of a page, places the next page exactly even as
+    // entries come and go between pages
+    let mut entries = Vec::new();
+    while let Some(entry) = dir.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        let key = match sort {
+            DirSort::Name => 0,
+            DirSort::Modified => metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_millis() as u64),
+            DirSort::Size => metadata.len(),
+        };
+        entries.push((key, dir_entry(&entry, vfs_path, &metadata)));
+    }
+    entries.sort_by(|(a_key, a), (b_key, b)| a_key.cmp(b_key).then_with(|| a.path.cmp(&b.path)));
+    if *descending {
+        entries.reverse();
+    }
+
+    let start = match cursor {
+        None => 0,
+        Some(cursor) => {
+            // a cursor is `{key}:{path}`; paths may hold colons, keys don't
+            let Some((Ok(key), path)) = cursor
+                .split_once(':')
+                .map(|(key, path)| (key.parse::<u64>(), path))
+            else {
+                return Err(VfsError::BadRequest {
+                    error: format!("bad ReadDirPage cursor {cursor}"),
+                });
+            };
+            entries.partition_point(|(entry_key, entry)| {
+                let ordering = entry_key
+                    .cmp(&key)
+                    .then_with(|| entry.path.as_str().cmp(path));
+                if *descending {
+                    ordering.is_ge()
+                } else {
+                    ordering.is_le()
+                }
+            })
+        }
+    };
+    let end = start.saturating_add(*limit as usize).min(entries.len());
+    // a page that isn't the last holds at least one entry
+    let next_cursor = (end < entries.len()).then(|| {
+        let (key, entry) = &entries[end - 1];
+        format!("{key}:{}", entry.path)
+    });
+    Ok(VfsResponse::ReadDirPage {
+        entries: entries.drain(start..end).map(|(_, entry)| entry).collect(),
+        next_cursor,
+    })
+}
+
+fn dir_entry(entry: &fs::DirEntry, vfs_path: &Path, metadata: &std::fs::Metadata) -> DirEntry {
+    let entry_path = entry.path();
+    let relative_path = entry_path.strip_prefix(vfs_path).unwrap_or(&entry_path);
+    DirEntry {
+        path: relative_path.display().to_string(),
+        file_type: get_file_type(metadata),
+    }
+}
+
 fn get_file_type(metadata: &std::fs::Metadata) -> FileType {
     if metadata.is_file() {
         FileType::File
@@ -872,3 +1866,238 @@ fn join_paths_safely(base: &PathBuf, extension: &str) -> PathBuf {
     let extension_path = Path::new(extension_str);
     base.join(extension_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kinode-vfs-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// write a file, dated a minute back so that its hash may be kept
+    fn write_old(path: &Path, contents: &[u8]) {
+        std::fs::write(path, contents).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+    }
+
+    #[test]
+    fn tree_hash_covers_names_and_contents() {
+        let (a, b) = (temp_dir(), temp_dir());
+        std::fs::create_dir(a.join("sub")).unwrap();
+        std::fs::write(a.join("sub/one"), b"1").unwrap();
+        std::fs::write(a.join("two"), b"22").unwrap();
+        // the same tree, made in another order
+        std::fs::write(b.join("two"), b"22").unwrap();
+        std::fs::create_dir(b.join("sub")).unwrap();
+        std::fs::write(b.join("sub/one"), b"1").unwrap();
+
+        let hashes = TreeHashes::default();
+        let manifest = tree_manifest(&a, &hashes).unwrap();
+        assert_eq!(manifest.root, tree_manifest(&b, &hashes).unwrap().root);
+        let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["sub", "sub/one", "two"]);
+        assert_eq!(manifest.entries[0].len, 1);
+
+        std::fs::write(b.join("sub/one"), b"2").unwrap();
+        assert_ne!(manifest.root, tree_manifest(&b, &hashes).unwrap().root);
+        std::fs::rename(b.join("sub/one"), b.join("sub/three")).unwrap();
+        std::fs::write(b.join("sub/three"), b"1").unwrap();
+        assert_ne!(manifest.root, tree_manifest(&b, &hashes).unwrap().root);
+
+        std::fs::remove_dir_all(a).unwrap();
+        std::fs::remove_dir_all(b).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drive_events_name_the_path_changed_in_each_drive() {
+        let vfs_path = PathBuf::from("/home/vfs");
+        let hooks = DriveHooks::default();
+        let hooked = |process: &str| HashSet::from([process.parse::<ProcessId>().unwrap()]);
+        hooks.insert("/a:sys/data".to_string(), hooked("watch:a:sys"));
+        hooks.insert("/b:sys/data".to_string(), hooked("watch:b:sys"));
+        let actor = Address::new("node.os", "mover:a:sys".parse::<ProcessId>().unwrap());
+        let rename = |new_path: &str| VfsAction::Rename {
+            new_path: new_path.to_string(),
+        };
+
+        let events = drive_events(
+            &rename("/b:sys/data/y"),
+            &actor,
+            "/a:sys/data/x",
+            &hooks,
+            &vfs_path,
+        )
+        .await;
+        let events: Vec<(String, String, Option<String>)> = events
+            .into_iter()
+            .map(|(process, event)| (process.to_string(), event.path, event.from))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                (
+                    "watch:b:sys".to_string(),
+                    "/b:sys/data/y".to_string(),
+                    Some("/a:sys/data/x".to_string())
+                ),
+                ("watch:a:sys".to_string(), "/a:sys/data/x".to_string(), None),
+            ]
+        );
+
+        let events = drive_events(
+            &rename("/a:sys/data/z"),
+            &actor,
+            "/a:sys/data/x",
+            &hooks,
+            &vfs_path,
+        )
+        .await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].1.path, "/a:sys/data/z");
+        assert_eq!(events[0].1.from.as_deref(), Some("/a:sys/data/x"));
+    }
+
+    #[test]
+    fn directories_are_cloned_whole() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(dir.join("from/assets")).unwrap();
+        std::fs::write(dir.join("from/manifest.json"), b"[]").unwrap();
+        std::fs::write(dir.join("from/assets/big.bin"), vec![7u8; 4096]).unwrap();
+        clone_tree(&dir.join("from"), &dir.join("to")).unwrap();
+        let hashes = TreeHashes::default();
+        assert_eq!(
+            tree_manifest(&dir.join("from"), &hashes).unwrap().root,
+            tree_manifest(&dir.join("to"), &hashes).unwrap().root,
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_settled_files_are_kept_and_gone_ones_forgotten() {
+        let dir = temp_dir();
+        write_old(&dir.join("old"), b"old");
+        std::fs::write(dir.join("new"), b"new").unwrap();
+        let hashes = TreeHashes::default();
+        tree_manifest(&dir, &hashes).unwrap();
+        // a file just written may be written again within its mtime tick
+        assert!(hashes.contains_key(&dir.join("old")));
+        assert!(!hashes.contains_key(&dir.join("new")));
+
+        let kept = hashes.get(&dir.join("old")).unwrap().2;
+        let metadata = std::fs::metadata(dir.join("old")).unwrap();
+        assert_eq!(
+            hash_file(&dir.join("old"), &metadata, &hashes).unwrap(),
+            kept
+        );
+        // changed from outside the vfs
+        write_old(&dir.join("old"), b"changed");
+        let metadata = std::fs::metadata(dir.join("old")).unwrap();
+        assert_ne!(
+            hash_file(&dir.join("old"), &metadata, &hashes).unwrap(),
+            kept
+        );
+
+        std::fs::remove_file(dir.join("old")).unwrap();
+        tree_manifest(&dir, &hashes).unwrap();
+        assert!(hashes.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// read a directory page by page, returning the paths of each page
+    async fn pages(dir: &Path, limit: u64, sort: DirSort, descending: bool) -> Vec<Vec<String>> {
+        let mut pages = vec![];
+        let mut cursor = None;
+        loop {
+            let page = VfsAction::ReadDirPage {
+                cursor,
+                limit,
+                sort,
+                descending,
+            };
+            let Ok(VfsResponse::ReadDirPage {
+                entries,
+                next_cursor,
+            }) = list_dir(dir, dir, &page).await
+            else {
+                panic!("couldn't read page");
+            };
+            pages.push(entries.into_iter().map(|entry| entry.path).collect());
+            cursor = next_cursor;
+            if cursor.is_none() {
+                return pages;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dir_is_read_in_pages_in_order() {
+        let dir = temp_dir();
+        for (name, len) in [("c", 1), ("a", 3), ("d", 2), ("b", 1), ("e:f", 0)] {
+            std::fs::write(dir.join(name), vec![0; len]).unwrap();
+        }
+        assert_eq!(
+            pages(&dir, 2, DirSort::Name, false).await,
+            [vec!["a", "b"], vec!["c", "d"], vec!["e:f"]]
+        );
+        // equal sizes are ordered by name, and reversed with them
+        assert_eq!(
+            pages(&dir, 3, DirSort::Size, true).await,
+            [vec!["a", "d", "c"], vec!["b", "e:f"]]
+        );
+        assert!(matches!(
+            list_dir(&dir, &dir, &VfsAction::CountDir).await,
+            Ok(VfsResponse::CountDir(5))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cursor_holds_its_place_as_entries_change() {
+        let dir = temp_dir();
+        for name in ["a", "b", "c", "d"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let page = |cursor: Option<String>, limit| VfsAction::ReadDirPage {
+            cursor,
+            limit,
+            sort: DirSort::Name,
+            descending: false,
+        };
+        let Ok(VfsResponse::ReadDirPage { next_cursor, .. }) =
+            list_dir(&dir, &dir, &page(None, 2)).await
+        else {
+            panic!("couldn't read page");
+        };
+        // the last entry of the first page goes, and one is added before it
+        std::fs::remove_file(dir.join("b")).unwrap();
+        std::fs::write(dir.join("aa"), b"").unwrap();
+        let Ok(VfsResponse::ReadDirPage { entries, .. }) =
+            list_dir(&dir, &dir, &page(next_cursor, 2)).await
+        else {
+            panic!("couldn't read page");
+        };
+        let paths: Vec<String> = entries.into_iter().map(|entry| entry.path).collect();
+        assert_eq!(paths, ["c", "d"]);
+
+        assert!(matches!(
+            list_dir(&dir, &dir, &page(None, 0)).await,
+            Err(VfsError::BadRequest { .. })
+        ));
+        assert!(matches!(
+            list_dir(&dir, &dir, &page(Some("b".to_string()), 2)).await,
+            Err(VfsError::BadRequest { .. })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
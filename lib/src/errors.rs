@@ -0,0 +1,601 @@
+//! One shape for the errors of every runtime module.
+//!
+//! Each module answers a failed request with its own error type in the
+//! response body, as it always has, so that processes built against
+//! process_lib keep parsing it. Alongside it, in the response's metadata, the
+//! module sends a [`RuntimeError`]: the same error as a stable numeric code,
+//! the module it came from, and whether it is worth retrying.
+//!
+//! Codes are `module * 1000 + n`, with modules numbered as in
+//! [`ErrorModule`]. A code, once given, is never reused for another error:
+//! new variants take the next free number, and retired ones leave a gap.
+use crate::core::{
+    GroupsError, KvError, NotifyError, ProgressError, SmtpError, SocketError, SqliteError,
+    StateError, SyncError, VfsError,
+};
+use crate::eth::EthError;
+use crate::http::client_types::HttpClientError;
+use crate::http::server_types::HttpServerError;
+use serde::{Deserialize, Serialize};
+
+/// The runtime module a [`RuntimeError`] came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorModule {
+    State = 1,
+    Vfs = 2,
+    Kv = 3,
+    Sqlite = 4,
+    Sync = 5,
+    HttpServer = 6,
+    HttpClient = 7,
+    Eth = 8,
+    Notify = 9,
+    Groups = 10,
+    Smtp = 11,
+    Socket = 12,
+    Progress = 13,
+}
+
+/// An error from a runtime module, sent as the JSON metadata of its error
+/// response.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeError {
+    pub code: u32,
+    pub module: ErrorModule,
+    /// the name of the module's error variant, e.g. `NoCap`
+    pub kind: String,
+    pub message: String,
+    /// whether the same request may succeed if sent again later
+    pub retryable: bool,
+}
+
+impl RuntimeError {
+    /// parse the error from the metadata of a runtime module's response
+    pub fn from_metadata(metadata: Option<&str>) -> Option<Self> {
+        serde_json::from_str(metadata?).ok()
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?} error {}: {}", self.module, self.code, self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// An error type of a runtime module, with its place in the registry.
+pub trait ModuleError {
+    const MODULE: ErrorModule;
+
+    /// the error's number within its module: see the module docs
+    fn number(&self) -> u32;
+    fn kind(&self) -> &'static str;
+    fn message(&self) -> String;
+    fn retryable(&self) -> bool {
+        false
+    }
+
+    fn code(&self) -> u32 {
+        Self::MODULE as u32 * 1000 + self.number()
+    }
+
+    fn envelope(&self) -> RuntimeError {
+        RuntimeError {
+            code: self.code(),
+            module: Self::MODULE,
+            kind: self.kind().to_string(),
+            message: self.message(),
+            retryable: self.retryable(),
+        }
+    }
+
+    /// the envelope, as the metadata of the module's error response
+    fn metadata(&self) -> Option<String> {
+        Some(serde_json::to_string(&self.envelope()).unwrap())
+    }
+}
+
+impl ModuleError for StateError {
+    const MODULE: ErrorModule = ErrorModule::State;
+
+    fn number(&self) -> u32 {
+        match self {
+            StateError::RocksDBError { .. } => 1,
+            StateError::StartupError { .. } => 2,
+            StateError::BadBytes { .. } => 3,
+            StateError::BadRequest { .. } => 4,
+            StateError::BadJson { .. } => 5,
+            StateError::NotFound { .. } => 6,
+            StateError::IOError { .. } => 7,
+            StateError::NoBackup => 8,
+            StateError::NoSpace { .. } => 9,
+            StateError::NoCap => 10,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            StateError::RocksDBError { .. } => "RocksDBError",
+            StateError::StartupError { .. } => "StartupError",
+            StateError::BadBytes { .. } => "BadBytes",
+            StateError::BadRequest { .. } => "BadRequest",
+            // named as `StateError::kind` has always named it
+            StateError::BadJson { .. } => "NoJson",
+            StateError::NotFound { .. } => "NotFound",
+            StateError::IOError { .. } => "IOError",
+            StateError::NoBackup => "NoBackup",
+            StateError::NoSpace { .. } => "NoSpace",
+            StateError::NoCap => "NoCap",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            StateError::RocksDBError { .. }
+                | StateError::IOError { .. }
+                | StateError::NoSpace { .. }
+        )
+    }
+}
+
+impl ModuleError for VfsError {
+    const MODULE: ErrorModule = ErrorModule::Vfs;
+
+    fn number(&self) -> u32 {
+        match self {
+            VfsError::NoCap { .. } => 1,
+            VfsError::BadBytes { .. } => 2,
+            VfsError::BadRequest { .. } => 3,
+            VfsError::ParseError { .. } => 4,
+            VfsError::IOError { .. } => 5,
+            VfsError::CapChannelFail { .. } => 6,
+            VfsError::BadJson { .. } => 7,
+            VfsError::NotFound { .. } => 8,
+            VfsError::CreateDirError { .. } => 9,
+            VfsError::NoSpace { .. } => 10,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            VfsError::NoCap { .. } => "NoCap",
+            VfsError::BadBytes { .. } => "BadBytes",
+            VfsError::BadRequest { .. } => "BadRequest",
+            VfsError::ParseError { .. } => "ParseError",
+            VfsError::IOError { .. } => "IOError",
+            VfsError::CapChannelFail { .. } => "CapChannelFail",
+            // named as `VfsError::kind` has always named it
+            VfsError::BadJson { .. } => "NoJson",
+            VfsError::NotFound { .. } => "NotFound",
+            VfsError::CreateDirError { .. } => "CreateDirError",
+            VfsError::NoSpace { .. } => "NoSpace",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            VfsError::IOError { .. } | VfsError::CapChannelFail { .. } | VfsError::NoSpace { .. }
+        )
+    }
+}
+
+impl ModuleError for KvError {
+    const MODULE: ErrorModule = ErrorModule::Kv;
+
+    fn number(&self) -> u32 {
+        match self {
+            KvError::NoDb => 1,
+            KvError::KeyNotFound => 2,
+            KvError::NoTx => 3,
+            KvError::NoCap { .. } => 4,
+            KvError::RocksDBError { .. } => 5,
+            KvError::InputError { .. } => 6,
+            KvError::IOError { .. } => 7,
+            KvError::NoSpace { .. } => 8,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            KvError::NoDb => "NoDb",
+            KvError::KeyNotFound => "KeyNotFound",
+            KvError::NoTx => "NoTx",
+            KvError::NoCap { .. } => "NoCap",
+            KvError::RocksDBError { .. } => "RocksDBError",
+            KvError::InputError { .. } => "InputError",
+            KvError::IOError { .. } => "IOError",
+            KvError::NoSpace { .. } => "NoSpace",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            KvError::RocksDBError { .. } | KvError::IOError { .. } | KvError::NoSpace { .. }
+        )
+    }
+}
+
+impl ModuleError for SqliteError {
+    const MODULE: ErrorModule = ErrorModule::Sqlite;
+
+    fn number(&self) -> u32 {
+        match self {
+            SqliteError::NoDb => 1,
+            SqliteError::NoTx => 2,
+            SqliteError::NoCap { .. } => 3,
+            SqliteError::UnexpectedResponse => 4,
+            SqliteError::NotAWriteKeyword => 5,
+            SqliteError::NotAReadKeyword => 6,
+            SqliteError::InvalidParameters => 7,
+            SqliteError::IOError { .. } => 8,
+            SqliteError::RusqliteError { .. } => 9,
+            SqliteError::InputError { .. } => 10,
+            SqliteError::NoSpace { .. } => 11,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            SqliteError::NoDb => "NoDb",
+            SqliteError::NoTx => "NoTx",
+            SqliteError::NoCap { .. } => "NoCap",
+            SqliteError::UnexpectedResponse => "UnexpectedResponse",
+            SqliteError::NotAWriteKeyword => "NotAWriteKeyword",
+            SqliteError::NotAReadKeyword => "NotAReadKeyword",
+            SqliteError::InvalidParameters => "InvalidParameters",
+            SqliteError::IOError { .. } => "IOError",
+            SqliteError::RusqliteError { .. } => "RusqliteError",
+            SqliteError::InputError { .. } => "InputError",
+            SqliteError::NoSpace { .. } => "NoSpace",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            SqliteError::IOError { .. } | SqliteError::NoSpace { .. }
+        )
+    }
+}
+
+impl ModuleError for SyncError {
+    const MODULE: ErrorModule = ErrorModule::Sync;
+
+    fn number(&self) -> u32 {
+        match self {
+            SyncError::NoCap { .. } => 1,
+            SyncError::NotMirrored { .. } => 2,
+            SyncError::BadRequest { .. } => 3,
+            SyncError::IOError { .. } => 4,
+            SyncError::Timeout { .. } => 5,
+            SyncError::PeerError { .. } => 6,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            SyncError::NoCap { .. } => "NoCap",
+            SyncError::NotMirrored { .. } => "NotMirrored",
+            SyncError::BadRequest { .. } => "BadRequest",
+            SyncError::IOError { .. } => "IOError",
+            SyncError::Timeout { .. } => "Timeout",
+            SyncError::PeerError { .. } => "PeerError",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            SyncError::IOError { .. } | SyncError::Timeout { .. } | SyncError::PeerError { .. }
+        )
+    }
+}
+
+impl ModuleError for HttpServerError {
+    const MODULE: ErrorModule = ErrorModule::HttpServer;
+
+    fn number(&self) -> u32 {
+        match self {
+            HttpServerError::BadRequest { .. } => 1,
+            HttpServerError::NoBlob => 2,
+            HttpServerError::PathBindError { .. } => 3,
+            HttpServerError::WebSocketPushError { .. } => 4,
+            HttpServerError::NoCap { .. } => 5,
+            HttpServerError::UserError { .. } => 6,
+            HttpServerError::StreamError { .. } => 7,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            HttpServerError::BadRequest { .. } => "BadRequest",
+            HttpServerError::NoBlob => "NoBlob",
+            HttpServerError::PathBindError { .. } => "PathBindError",
+            HttpServerError::WebSocketPushError { .. } => "WebSocketPushError",
+            HttpServerError::NoCap { .. } => "NoCap",
+            HttpServerError::UserError { .. } => "UserError",
+            HttpServerError::StreamError { .. } => "StreamError",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ModuleError for HttpClientError {
+    const MODULE: ErrorModule = ErrorModule::HttpClient;
+
+    fn number(&self) -> u32 {
+        match self {
+            HttpClientError::BadRequest { .. } => 1,
+            HttpClientError::BadMethod { .. } => 2,
+            HttpClientError::BadUrl { .. } => 3,
+            HttpClientError::BadVersion { .. } => 4,
+            HttpClientError::RequestFailed { .. } => 5,
+            HttpClientError::WsOpenFailed { .. } => 6,
+            HttpClientError::WsPushFailed { .. } => 7,
+            HttpClientError::WsCloseFailed { .. } => 8,
+            HttpClientError::OAuthFailed { .. } => 9,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            HttpClientError::BadRequest { .. } => "BadRequest",
+            HttpClientError::BadMethod { .. } => "BadMethod",
+            HttpClientError::BadUrl { .. } => "BadUrl",
+            HttpClientError::BadVersion { .. } => "BadVersion",
+            HttpClientError::RequestFailed { .. } => "RequestFailed",
+            HttpClientError::WsOpenFailed { .. } => "WsOpenFailed",
+            HttpClientError::WsPushFailed { .. } => "WsPushFailed",
+            HttpClientError::WsCloseFailed { .. } => "WsCloseFailed",
+            HttpClientError::OAuthFailed { .. } => "OAuthFailed",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            HttpClientError::RequestFailed { .. }
+                | HttpClientError::WsOpenFailed { .. }
+                | HttpClientError::WsPushFailed { .. }
+        )
+    }
+}
+
+impl ModuleError for EthError {
+    const MODULE: ErrorModule = ErrorModule::Eth;
+
+    fn number(&self) -> u32 {
+        match self {
+            EthError::RpcError(_) => 1,
+            EthError::MalformedRequest => 2,
+            EthError::NoRpcForChain => 3,
+            EthError::SubscriptionClosed(_) => 4,
+            EthError::InvalidMethod(_) => 5,
+            EthError::InvalidParams => 6,
+            EthError::PermissionDenied => 7,
+            EthError::RpcTimeout => 8,
+            EthError::RpcMalformedResponse => 9,
+            EthError::QuotaExceeded => 10,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            EthError::RpcError(_) => "RpcError",
+            EthError::MalformedRequest => "MalformedRequest",
+            EthError::NoRpcForChain => "NoRpcForChain",
+            EthError::SubscriptionClosed(_) => "SubscriptionClosed",
+            EthError::InvalidMethod(_) => "InvalidMethod",
+            EthError::InvalidParams => "InvalidParams",
+            EthError::PermissionDenied => "PermissionDenied",
+            EthError::RpcTimeout => "RpcTimeout",
+            EthError::RpcMalformedResponse => "RpcMalformedResponse",
+            EthError::QuotaExceeded => "QuotaExceeded",
+        }
+    }
+
+    /// `EthError` has no `Display`: its debug form is what the eth module prints
+    fn message(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            EthError::RpcError(_)
+                | EthError::NoRpcForChain
+                | EthError::RpcTimeout
+                | EthError::RpcMalformedResponse
+                | EthError::QuotaExceeded
+        )
+    }
+}
+
+impl ModuleError for NotifyError {
+    const MODULE: ErrorModule = ErrorModule::Notify;
+
+    fn number(&self) -> u32 {
+        match self {
+            NotifyError::NoCap => 1,
+            NotifyError::BadRequest { .. } => 2,
+            NotifyError::NotFound { .. } => 3,
+            NotifyError::IOError { .. } => 4,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            NotifyError::NoCap => "NoCap",
+            NotifyError::BadRequest { .. } => "BadRequest",
+            NotifyError::NotFound { .. } => "NotFound",
+            NotifyError::IOError { .. } => "IOError",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(self, NotifyError::IOError { .. })
+    }
+}
+
+impl ModuleError for GroupsError {
+    const MODULE: ErrorModule = ErrorModule::Groups;
+
+    fn number(&self) -> u32 {
+        match self {
+            GroupsError::BadRequest { .. } => 1,
+            GroupsError::NoGroup { .. } => 2,
+            GroupsError::GroupExists { .. } => 3,
+            GroupsError::IOError { .. } => 4,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            GroupsError::BadRequest { .. } => "BadRequest",
+            GroupsError::NoGroup { .. } => "NoGroup",
+            GroupsError::GroupExists { .. } => "GroupExists",
+            GroupsError::IOError { .. } => "IOError",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(self, GroupsError::IOError { .. })
+    }
+}
+
+impl ModuleError for SmtpError {
+    const MODULE: ErrorModule = ErrorModule::Smtp;
+
+    fn number(&self) -> u32 {
+        match self {
+            SmtpError::NoCap => 1,
+            SmtpError::BadRequest { .. } => 2,
+            SmtpError::NoAccount => 3,
+            SmtpError::QuotaExceeded { .. } => 4,
+            SmtpError::SendFailed { .. } => 5,
+            SmtpError::IOError { .. } => 6,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            SmtpError::NoCap => "NoCap",
+            SmtpError::BadRequest { .. } => "BadRequest",
+            SmtpError::NoAccount => "NoAccount",
+            SmtpError::QuotaExceeded { .. } => "QuotaExceeded",
+            SmtpError::SendFailed { .. } => "SendFailed",
+            SmtpError::IOError { .. } => "IOError",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            SmtpError::QuotaExceeded { .. }
+                | SmtpError::SendFailed { .. }
+                | SmtpError::IOError { .. }
+        )
+    }
+}
+
+impl ModuleError for SocketError {
+    const MODULE: ErrorModule = ErrorModule::Socket;
+
+    fn number(&self) -> u32 {
+        match self {
+            SocketError::NoCap => 1,
+            SocketError::BadRequest { .. } => 2,
+            SocketError::LimitReached { .. } => 3,
+            SocketError::NoSocket { .. } => 4,
+            SocketError::ConnectFailed { .. } => 5,
+            SocketError::IOError { .. } => 6,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            SocketError::NoCap => "NoCap",
+            SocketError::BadRequest { .. } => "BadRequest",
+            SocketError::LimitReached { .. } => "LimitReached",
+            SocketError::NoSocket { .. } => "NoSocket",
+            SocketError::ConnectFailed { .. } => "ConnectFailed",
+            SocketError::IOError { .. } => "IOError",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+
+    fn retryable(&self) -> bool {
+        matches!(
+            self,
+            SocketError::LimitReached { .. } | SocketError::ConnectFailed { .. }
+        )
+    }
+}
+
+impl ModuleError for ProgressError {
+    const MODULE: ErrorModule = ErrorModule::Progress;
+
+    fn number(&self) -> u32 {
+        match self {
+            ProgressError::NoCap => 1,
+            ProgressError::BadRequest { .. } => 2,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ProgressError::NoCap => "NoCap",
+            ProgressError::BadRequest { .. } => "BadRequest",
+        }
+    }
+
+    fn message(&self) -> String {
+        self.to_string()
+    }
+}
@@ -80,6 +80,9 @@ pub enum EthError {
     RpcTimeout,
     /// RPC gave garbage back
     RpcMalformedResponse,
+    /// the process has used its [`RequestBudget`] of upstream calls, and no
+    /// more would come before the request timed out
+    QuotaExceeded,
 }
 
 /// The action type used for configuring eth:distro:sys. Only processes which have the "root"
@@ -112,6 +115,20 @@ pub enum EthConfigAction {
     GetAccessSettings,
     /// Get the state of calls and subscriptions. Used for debugging.
     GetState,
+    /// Set how many upstream calls each process may make, and how many may
+    /// be in flight at once.
+    SetBudget(RequestBudget),
+    /// Get the budget, and how much of it each process has used.
+    GetUsage,
+    /// Get what we have served each node that uses us as its provider.
+    GetServed,
+    /// Set whether nodes using us as their provider need the `"serve"`
+    /// capability as well as our access settings' leave.
+    RequireCap(bool),
+    /// Give a node the `"serve"` capability, issued by us to that node alone.
+    /// Its eth module keeps it and attaches it to what it asks of us. It
+    /// can't be taken back: deny the node instead.
+    GrantServe(String),
 }
 
 /// Response type from an [`EthConfigAction`] request.
@@ -131,6 +148,64 @@ pub enum EthConfigResponse {
         active_subscriptions: HashMap<crate::core::Address, HashMap<u64, Option<String>>>, // None if local, Some(node_provider_name) if remote
         outstanding_requests: HashSet<u64>,
     },
+    /// Response from a GetUsage request
+    Usage {
+        budget: RequestBudget,
+        /// responses held in the shared cache
+        cached: usize,
+        /// processes by how many upstream calls they have made, most first
+        processes: Vec<ProcessUsage>,
+    },
+    /// Response from a GetServed request, nodes that have asked most first
+    Served(Vec<ServedNode>),
+}
+
+/// Limits on the upstream RPC calls made for [`EthAction::Request`]s, so that
+/// one busy process can't use up a provider's rate limits for the rest.
+/// Requests answered from the cache, and subscriptions, don't count. A remote
+/// node using us as a provider counts as one process.
+///
+/// There are no limits unless set with [`EthConfigAction::SetBudget`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RequestBudget {
+    /// upstream calls each process may make per minute, in bursts of up to as
+    /// many; a process over budget waits its turn, if it can before its
+    /// request times out. None for no limit.
+    pub calls_per_minute: Option<u32>,
+    /// upstream calls that may be in flight at once, shared out among the
+    /// processes waiting for one in turn. None for no limit.
+    pub max_concurrent: Option<u32>,
+}
+
+/// What one process has asked of eth:distro:sys since boot.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ProcessUsage {
+    pub process: crate::core::Address,
+    pub requests: u64,
+    /// requests answered from the shared cache
+    pub cache_hits: u64,
+    pub upstream_calls: u64,
+    /// upstream calls that had to wait for the process's budget or a turn
+    pub queued: u64,
+    /// requests failed with [`EthError::QuotaExceeded`]
+    pub rejected: u64,
+}
+
+/// What we have served one node that uses us as its provider, since boot.
+/// We serve other nodes only from our own RPC URLs, never by asking our own
+/// node providers in turn.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ServedNode {
+    pub node: String,
+    /// requests answered, including those answered with an error
+    pub requests: u64,
+    pub errors: u64,
+    /// requests refused by our [`AccessSettings`]
+    pub denied: u64,
+    pub subscriptions: u64,
+    /// subscriptions of theirs we are relaying updates for now
+    pub active_subscriptions: u64,
+    pub updates_relayed: u64,
 }
 
 /// Settings for our ETH provider
@@ -139,6 +214,10 @@ pub struct AccessSettings {
     pub public: bool,           // whether or not other nodes can access through us
     pub allow: HashSet<String>, // whitelist for access (only used if public == false)
     pub deny: HashSet<String>,  // blacklist for access (always used)
+    /// whether nodes let in must also attach the `"serve"` capability given
+    /// them with [`EthConfigAction::GrantServe`]
+    #[serde(default)]
+    pub require_cap: bool,
 }
 
 pub type SavedConfigs = HashSet<ProviderConfig>;
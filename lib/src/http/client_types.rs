@@ -21,6 +21,62 @@ pub enum HttpClientAction {
     WebSocketClose {
         channel_id: u32,
     },
+    /// Get how requests to each host have gone since boot, answered with
+    /// [`HttpClientResponse::PoolMetrics`].
+    GetPoolMetrics,
+    /// Have http_client get and keep OAuth2 tokens for the process.
+    OAuth(OAuthAction),
+}
+
+/// OAuth2 flows run by http_client on behalf of a process. Each process names
+/// its own providers; tokens are kept encrypted on disk, and refreshed when
+/// asked for near their expiry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum OAuthAction {
+    /// Add provider `name`, or change it, forgetting any token it had.
+    Register { name: String, config: OAuthConfig },
+    /// Begin the authorization-code flow for `name`, answered with
+    /// [`HttpClientResponse::OAuthAuthorizeUrl`] for the user to open. Once they
+    /// have, the process gets an [`OAuthAuthorized`] request.
+    Authorize { name: String },
+    /// Get a token for `name` that has not expired, answered with
+    /// [`HttpClientResponse::OAuthToken`]. Under the client-credentials flow,
+    /// one is fetched if need be.
+    Token { name: String },
+    /// Forget provider `name` and its token.
+    Forget { name: String },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OAuthConfig {
+    pub flow: OAuthFlow,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// Where the user is sent to authorize, for [`OAuthFlow::AuthorizationCode`].
+    pub authorize_url: Option<String>,
+    pub token_url: String,
+    pub scopes: Vec<String>,
+    /// For [`OAuthFlow::AuthorizationCode`]: this node's URL for
+    /// [`OAUTH_CALLBACK_PATH`], as registered with the provider.
+    pub redirect_uri: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OAuthFlow {
+    AuthorizationCode,
+    ClientCredentials,
+}
+
+/// The path, bound by http_client on http_server, that OAuth2 providers
+/// send users back to.
+pub const OAUTH_CALLBACK_PATH: &str = "/http_client:distro:sys/oauth/callback";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub token_type: String,
+    /// Milliseconds since the unix epoch, if the provider said.
+    pub expires_at: Option<u64>,
 }
 
 /// HTTP Request type that can be shared over Wasm boundary to apps.
@@ -54,12 +110,43 @@ pub enum HttpClientRequest {
     },
 }
 
+/// Request from the `http_client:distro:sys` service once the user came back
+/// from authorizing provider `name`, begun with [`OAuthAction::Authorize`]:
+/// a token is kept unless `error` is set.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OAuthAuthorized {
+    pub name: String,
+    pub error: Option<String>,
+}
+
 /// Response type received from the `http_client:distro:sys` service after
 /// sending a successful [`HttpClientAction`] to it.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum HttpClientResponse {
     Http(HttpResponse),
     WebSocketAck,
+    /// By host, most requests first.
+    PoolMetrics(Vec<HostMetrics>),
+    OAuthAck,
+    OAuthAuthorizeUrl(String),
+    OAuthToken(OAuthToken),
+}
+
+/// How HTTP requests to one host have gone. Connections to a host are pooled
+/// and kept alive between requests, and requests over HTTP/2 share one.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HostMetrics {
+    /// The scheme, host and port requests went to.
+    pub origin: String,
+    pub requests: u64,
+    /// Requests that got no response at all.
+    pub errors: u64,
+    /// Requests answered over HTTP/2.
+    pub http2: u64,
+    pub in_flight: u32,
+    pub peak_in_flight: u32,
+    /// Time to the response head, averaged over the requests answered.
+    pub mean_latency_ms: u64,
 }
 
 #[derive(Error, Debug, Serialize, Deserialize)]
@@ -83,4 +170,8 @@ pub enum HttpClientError {
     WsPushFailed { req: String },
     #[error("websocket_client: failed to close connection {channel_id}.")]
     WsCloseFailed { channel_id: u32 },
+
+    // OAuth errors
+    #[error("http_client: oauth: {error}.")]
+    OAuthFailed { error: String },
 }
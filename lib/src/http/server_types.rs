@@ -1,4 +1,4 @@
-use crate::core::LazyLoadBlob;
+use crate::core::{LazyLoadBlob, PackageId, Printout, ProcessId, TransactionStep};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use thiserror::Error;
@@ -14,6 +14,15 @@ pub enum HttpServerRequest {
     WebSocketOpen {
         path: String,
         channel_id: u32,
+        /// The logged-in user that opened the connection, if it was authenticated.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<User>,
+        /// will parse to SocketAddr
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source_socket_addr: Option<String>,
+        /// The headers of the upgrade request. Will parse to http::HeaderMap.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        headers: HashMap<String, String>,
     },
     /// Processes can both SEND and RECEIVE this kind of request
     /// (send as [`HttpServerAction::WebSocketPush`]).
@@ -25,6 +34,17 @@ pub enum HttpServerRequest {
     /// Receiving will indicate that the client closed the socket. Can be sent to close
     /// from the server-side, as [`type@HttpServerAction::WebSocketClose`].
     WebSocketClose(u32),
+    /// The next part of the body of the request with [`IncomingHttpRequest::stream_id`],
+    /// in the lazy_load_blob; `done` is set on the last, which has no bytes. The next
+    /// chunk isn't sent until the process responds to this one.
+    BodyChunk {
+        stream_id: u64,
+        done: bool,
+    },
+    /// The client stopped sending the body of the request with `stream_id` before its end.
+    BodyAborted {
+        stream_id: u64,
+    },
 }
 
 /// An HTTP request routed to a process as a result of a binding.
@@ -44,6 +64,82 @@ pub struct IncomingHttpRequest {
     pub headers: HashMap<String, String>,
     pub url_params: HashMap<String, String>,
     pub query_params: HashMap<String, String>,
+    /// The logged-in user that made the request, if it carried a valid login
+    /// cookie. Processes can use this to authorize per user on a shared node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<User>,
+    /// Set if the path was bound with `stream`: the body then comes in
+    /// [`HttpServerRequest::BodyChunk`]s with this ID rather than in the lazy_load_blob,
+    /// and the response body may be sent in [`HttpServerAction::ResponseChunk`]s.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<u64>,
+}
+
+/// A user that can log in to the node over HTTP. The node's owner logs in
+/// with the keyfile password as an [`UserRole::Admin`] named after the node;
+/// other users are added with [`HttpServerAction::AddUser`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub role: UserRole,
+}
+
+/// What a [`User`] may reach. Roles are ordered: a path bound with a
+/// `min_role` is open to users of that role and above. Paths bound without
+/// one are open to members, as they were before roles could be set.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum UserRole {
+    /// Only paths bound with `min_role` set to `Guest`.
+    Guest,
+    /// Every authenticated path, unless bound for admins only.
+    #[default]
+    Member,
+    /// Everything, including the web terminal and managing users.
+    Admin,
+}
+
+/// How long the access log of a path keeps its entries, set with
+/// [`HttpServerAction::SetAccessLog`]. Entries are only kept in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessLogSettings {
+    /// Entries older than this are dropped.
+    pub retention_secs: u64,
+    /// Past this many entries, the oldest are dropped.
+    pub max_entries: usize,
+}
+
+impl Default for AccessLogSettings {
+    fn default() -> Self {
+        AccessLogSettings {
+            retention_secs: 60 * 60,
+            max_entries: 1000,
+        }
+    }
+}
+
+/// A request to a path with an access log, as it was answered.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccessLogEntry {
+    /// Milliseconds since the unix epoch, when the request came in.
+    pub timestamp: u64,
+    pub path: String,
+    /// The path the request matched, as bound.
+    pub bound_path: String,
+    pub method: String,
+    pub status: u16,
+    pub latency_ms: u64,
+    /// The size of the response body, or 0 if it was streamed.
+    pub bytes: u64,
+    /// The socket address the request came from, if known.
+    pub client: Option<String>,
+    /// The logged-in user that made the request, if any.
+    pub user: Option<String>,
+    /// Whether the request was refused for want of a login, and answered
+    /// with the login page instead.
+    #[serde(default)]
+    pub denied: bool,
 }
 
 /// HTTP Response type that can be shared over Wasm boundary to apps.
@@ -53,6 +149,12 @@ pub struct HttpResponse {
     pub status: u16,
     pub headers: HashMap<String, String>,
     // BODY is stored in the lazy_load_blob, as bytes
+    /// For a request with a `stream_id`: the lazy_load_blob, if any, only begins the
+    /// body, which goes on in [`HttpServerAction::ResponseChunk`]s until one is `done`.
+    /// The status and headers are sent to the client at once, before any chunk, so
+    /// an error found partway through the body can only cut it short.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -79,6 +181,15 @@ pub enum HttpServerAction {
         /// Set whether to bind the lazy_load_blob statically to this path. That is, take the
         /// lazy_load_blob bytes and serve them as the response to any request to this path.
         cache: bool,
+        /// If authenticated, the least role a logged-in user needs to access this path.
+        /// Defaults to [`UserRole::Member`].
+        #[serde(default)]
+        min_role: UserRole,
+        /// Set whether request bodies come to the process in chunks, and responses may be
+        /// sent back in chunks, rather than each in one lazy_load_blob.
+        /// See [`IncomingHttpRequest::stream_id`].
+        #[serde(default)]
+        stream: bool,
     },
     /// SecureBind expects a lazy_load_blob if and only if `cache` is TRUE. The lazy_load_blob should
     /// be the static file to serve at this path.
@@ -94,6 +205,13 @@ pub enum HttpServerAction {
         /// Set whether to bind the lazy_load_blob statically to this path. That is, take the
         /// lazy_load_blob bytes and serve them as the response to any request to this path.
         cache: bool,
+        /// The least role a logged-in user needs to access this path.
+        /// Defaults to [`UserRole::Member`].
+        #[serde(default)]
+        min_role: UserRole,
+        /// See [`HttpServerAction::Bind`].
+        #[serde(default)]
+        stream: bool,
     },
     /// Unbind a previously-bound HTTP path
     Unbind { path: String },
@@ -104,6 +222,10 @@ pub enum HttpServerAction {
         authenticated: bool,
         encrypted: bool,
         extension: bool,
+        /// If authenticated, the least role a logged-in user needs to connect.
+        /// Defaults to [`UserRole::Member`].
+        #[serde(default)]
+        min_role: UserRole,
     },
     /// SecureBind is the same as Bind, except that it forces new connections to be made
     /// from the unique subdomain of the process that bound the path. These are *always*
@@ -113,9 +235,53 @@ pub enum HttpServerAction {
         path: String,
         encrypted: bool,
         extension: bool,
+        /// The least role a logged-in user needs to connect.
+        /// Defaults to [`UserRole::Member`].
+        #[serde(default)]
+        min_role: UserRole,
     },
     /// Unbind a previously-bound WebSocket path
     WebSocketUnbind { path: String },
+    /// Unbind every HTTP and WebSocket path bound by `process`, and close its
+    /// open WebSocket connections. Requires the http_server root capability.
+    /// Used by the app store to clean up after an uninstalled package.
+    UnbindAll { process: ProcessId },
+    /// Take part in a transaction over the paths bound by the processes of
+    /// `package_id`: aborting unbinds those bound since it was prepared and
+    /// restores those bound then. Requires the http_server root capability.
+    BindingsTransaction {
+        id: u64,
+        package_id: PackageId,
+        step: TransactionStep,
+    },
+    /// List the HTTP and WebSocket paths bound by the processes of `package_id`.
+    /// The response's lazy_load_blob holds them as a JSON `Vec<BindingInfo>`.
+    /// Requires the http_server root capability.
+    ListBindings { package_id: PackageId },
+    /// Add a user that can log in to the node with `password_hash`, hashed as the
+    /// login page hashes passwords, or change an existing user's role and password.
+    /// Requires the http_server root capability.
+    AddUser {
+        name: String,
+        role: UserRole,
+        password_hash: String,
+    },
+    /// Remove a user: their login cookies stop working at once.
+    /// Requires the http_server root capability.
+    RemoveUser { name: String },
+    /// List the users that can log in. The response's lazy_load_blob holds them
+    /// as a JSON `Vec<User>`, the owner first. Requires the http_server root capability.
+    ListUsers,
+    /// Keep and log an [`AccessLogEntry`] for every request to `path`, which the
+    /// process must have bound, as set by `settings`; None stops logging it. Each
+    /// entry is also printed to the node's log as JSON, at debug level, from the process.
+    SetAccessLog {
+        path: String,
+        settings: Option<AccessLogSettings>,
+    },
+    /// Get the entries kept for `path`, which the process must have bound. The
+    /// response's lazy_load_blob holds them as a JSON `Vec<AccessLogEntry>`, oldest first.
+    GetAccessLog { path: String },
     /// Processes will RECEIVE this kind of request when a client connects to them.
     /// If a process does not want this websocket open, they should issue a *request*
     /// containing a [`HttpServerAction::WebSocketClose`] message and this channel ID.
@@ -125,6 +291,30 @@ pub enum HttpServerAction {
         channel_id: u32,
         message_type: WsMessageType,
     },
+    /// Add a connection the process holds to `topic`, so that it gets what is
+    /// broadcast there. Topics are the process's own: others can't broadcast to them.
+    WebSocketSubscribe { channel_id: u32, topic: String },
+    /// Take a connection out of `topic`.
+    WebSocketUnsubscribe { channel_id: u32, topic: String },
+    /// Push the lazy_load_blob to every connection the process holds in `topic`,
+    /// or to every connection it holds if None.
+    WebSocketBroadcast {
+        topic: Option<String>,
+        message_type: WsMessageType,
+    },
+    /// Set limits on what a client may send over a connection the process holds.
+    /// A client that breaks them is disconnected.
+    WebSocketSetLimits {
+        channel_id: u32,
+        limits: WsConnectionLimits,
+    },
+    /// List the connections the process holds. The response's lazy_load_blob holds
+    /// them as a JSON `Vec<WsConnectionInfo>`.
+    WebSocketList,
+    /// The next part of a streamed response body, in the lazy_load_blob; `done` ends
+    /// it. Send each chunk only once the last has been responded to: the response
+    /// comes when the client has room for it.
+    ResponseChunk { stream_id: u64, done: bool },
     /// When sent, expects a `lazy_load_blob` containing the WebSocket message bytes to send.
     /// Modifies the `lazy_load_blob` by placing into `WebSocketExtPushData` with id taken from
     /// this `KernelMessage` and `kinode_message_type` set to `desired_reply_type`.
@@ -149,6 +339,49 @@ pub enum HttpServerAction {
     WebSocketClose(u32),
 }
 
+/// A path bound by a process, as listed by [`HttpServerAction::ListBindings`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BindingInfo {
+    pub process: ProcessId,
+    /// The full path, starting with the process ID.
+    pub path: String,
+    pub websocket: bool,
+    /// Only served on this subdomain, if bound with a secure bind.
+    pub secure_subdomain: Option<String>,
+    pub authenticated: bool,
+    pub min_role: UserRole,
+    /// Only served to clients on this machine.
+    pub local_only: bool,
+    /// Serves content cached when bound, rather than asking the process.
+    pub cached: bool,
+}
+
+/// An open WebSocket connection, as listed by [`HttpServerAction::WebSocketList`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WsConnectionInfo {
+    pub channel_id: u32,
+    /// The path the connection was opened on, as given in [`HttpServerRequest::WebSocketOpen`].
+    pub path: String,
+    pub source_socket_addr: Option<String>,
+    pub user: Option<User>,
+    /// Milliseconds since the unix epoch.
+    pub opened: u64,
+    pub topics: Vec<String>,
+    pub messages_received: u64,
+    pub messages_sent: u64,
+    pub limits: WsConnectionLimits,
+}
+
+/// Limits on what a client may send over a WebSocket connection, set with
+/// [`HttpServerAction::WebSocketSetLimits`]. None is no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WsConnectionLimits {
+    /// Past this, the connection is closed with code 1009.
+    pub max_message_bytes: Option<u64>,
+    /// Past this, the connection is closed with code 1008.
+    pub max_messages_per_minute: Option<u32>,
+}
+
 /// Whether the WebSocketPush is a request or a response.
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum MessageType {
@@ -181,6 +414,12 @@ pub enum HttpServerError {
     PathBindError { error: String },
     #[error("WebSocket error: {error}")]
     WebSocketPushError { error: String },
+    #[error("no capability: {error}")]
+    NoCap { error: String },
+    #[error("user error: {error}")]
+    UserError { error: String },
+    #[error("stream error: {error}")]
+    StreamError { error: String },
 }
 
 /// Structure sent from client websocket to this server upon opening a new connection.
@@ -201,9 +440,38 @@ pub struct WsRegisterResponse {
     // TODO symmetric key exchange here
 }
 
+/// A message from a browser to the web terminal at `/terminal`, as a JSON text
+/// frame. The web terminal is only served if the node was booted with
+/// `--web-terminal`, and only to a client with a terminal session: `POST` the
+/// node's owner's or an admin's [`crate::core::LoginInfo`] to `/terminal/session`
+/// for a session cookie scoped to `/terminal` and a `{"csrf": <token>}` body,
+/// then connect to `/terminal?csrf=<token>`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WebTerminalInput {
+    /// Run a command line, adding it to the history, as if typed in the terminal.
+    Command(String),
+    /// Get the command history, most recent first.
+    History,
+    /// Search the history as CTRL+R does: the most recent line containing
+    /// `query`, after skipping `depth` matches.
+    Search { query: String, depth: usize },
+}
+
+/// A message from the web terminal to a browser, as a JSON text frame. Every
+/// printout is streamed, whatever its verbosity, for the client to filter.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WebTerminalOutput {
+    Printout(Printout),
+    History(Vec<String>),
+    Search(Option<String>),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JwtClaims {
     pub username: String,
     pub subdomain: Option<String>,
     pub expiration: u64,
+    /// the user logged in, if not the node's owner
+    #[serde(default)]
+    pub user: Option<String>,
 }
@@ -1,11 +1,14 @@
 #![feature(let_chains)]
 
 pub mod core;
+pub mod errors;
 pub mod eth;
 mod http;
+pub mod router;
 
 pub mod types {
     pub use crate::core;
+    pub use crate::errors;
     pub use crate::eth;
     pub use crate::http::client_types as http_client;
     pub use crate::http::server_types as http_server;
@@ -0,0 +1,67 @@
+//! the parts of the networking protocol a node's router operator uses. these
+//! depend on serde alone, unlike the rest of this crate, so that processes,
+//! which can't build this crate for wasm, can include this file as it is.
+use serde::{Deserialize, Serialize};
+
+/// Sent inside [`crate::core::NetAction::Router`]. **only accepted from our
+/// own node.** every action but [`RouterAction::GetStats`] changes what we
+/// route for, and needs the `"router-admin"` capability issued by net.
+/// Each is answered with the stats after the change.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RouterAction {
+    /// as a router, get the nodes we route for or relay passthroughs for,
+    /// what we have relayed, and our quotas
+    GetStats,
+    /// as a router, set the limits each client is held to. persisted.
+    SetQuotas(RouterQuotas),
+    /// as a router, stop routing for or relaying passthroughs for a node,
+    /// dropping those it has open. persisted.
+    DenyClient(String),
+    /// undo a [`RouterAction::DenyClient`]
+    AllowClient(String),
+    /// as a router, drop a node's passthroughs and, if we route for it, its
+    /// connection. it may reconnect.
+    DisconnectClient(String),
+}
+
+impl RouterAction {
+    /// whether this needs the `"router-admin"` capability
+    pub fn is_admin(&self) -> bool {
+        !matches!(self, RouterAction::GetStats)
+    }
+}
+
+/// limits a router holds each of its clients to. `None` is unlimited.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RouterQuotas {
+    /// indirect nodes we will route for at once
+    pub max_routed: Option<u32>,
+    /// passthroughs a node may have open through us at once
+    pub max_passthroughs: Option<u32>,
+    /// bytes we will relay for a node in a day
+    pub max_daily_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RouterStats {
+    pub quotas: RouterQuotas,
+    pub denied: Vec<String>,
+    /// distinct nodes we have routed for or relayed for since boot
+    pub clients_served: u64,
+    /// bytes relayed through passthroughs since boot
+    pub bytes_relayed: u64,
+    pub clients: Vec<RouterClient>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RouterClient {
+    pub name: String,
+    /// whether we are routing for it right now
+    pub routed: bool,
+    /// passthroughs it has open
+    pub passthroughs: u32,
+    /// bytes relayed for it since boot
+    pub bytes_relayed: u64,
+    /// bytes relayed for it in the current day, against its quota
+    pub bytes_today: u64,
+}
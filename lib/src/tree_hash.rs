@@ -0,0 +1,40 @@
+//! the manifest the vfs gives for [`crate::core::VfsAction::TreeHash`].
+//! like `router.rs`, this depends on serde alone, so that processes, which
+//! can't build this crate for wasm, can include this file as it is, with
+//! process_lib's `vfs::FileType` in scope where they do.
+use serde::{Deserialize, Serialize};
+
+/// The hashes of a directory tree, from [`crate::core::VfsAction::TreeHash`].
+/// A directory's hash covers the name, type and hash of each entry in it, so
+/// two trees can be compared by their roots, and where those differ,
+/// narrowed down through the directories whose hashes differ.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeManifest {
+    pub root: [u8; 32],
+    /// every file and directory in the tree, by path relative to its root,
+    /// in path order. symlinks are left out.
+    pub entries: Vec<TreeEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeEntry {
+    pub path: String,
+    pub file_type: super::FileType,
+    /// for a directory, the length of every file in it
+    pub len: u64,
+    pub hash: [u8; 32],
+}
+
+/// a request to the vfs for the manifest of the directory at `path`, encoded
+/// as a `VfsRequest` is, for processes whose process_lib has no `TreeHash`.
+/// answered with a `VfsResponse::TreeHash(TreeManifest)`.
+#[derive(Debug, Serialize)]
+pub struct TreeHashRequest<'a> {
+    pub path: &'a str,
+    pub action: TreeHashAction,
+}
+
+#[derive(Debug, Serialize)]
+pub enum TreeHashAction {
+    TreeHash,
+}
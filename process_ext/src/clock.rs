@@ -0,0 +1,36 @@
+//! the node's clock: the host's time, corrected by what the node has
+//! learned from the NTP servers or peers it was booted to sync with. a node
+//! given neither keeps the host's time, and says it isn't synced.
+use crate::kinode::runtime::ext;
+
+/// the node's time, and how far it may be off
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clock {
+    /// milliseconds since the unix epoch
+    pub now: u64,
+    /// milliseconds added to the host's time to get `now`
+    pub offset: i64,
+    /// milliseconds `now` may be off by, if the node's clock has been synced
+    pub error: Option<u64>,
+}
+
+impl Clock {
+    pub fn is_synced(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+/// the node's time now
+pub fn clock() -> Clock {
+    let (now, offset, error) = ext::clock();
+    Clock { now, offset, error }
+}
+
+/// when the message we last received was sent, in milliseconds since the
+/// unix epoch by the node's clock, if it is known
+pub fn message_time() -> Option<u64> {
+    match ext::message_time() {
+        0 => None,
+        sent => Some(sent),
+    }
+}
@@ -0,0 +1,26 @@
+//! cryptography with our node's keys, which the runtime uses for us without
+//! giving them out. signing and opening sealed messages each need a
+//! capability issued by the kernel: `"sign"` and `"decrypt"`.
+use crate::kinode::runtime::ext;
+use kinode_process_lib::Address;
+
+/// sign `message` with our node's networking key. the signature covers our
+/// address as well, so it only verifies as ours.
+pub fn sign(message: &[u8]) -> anyhow::Result<Vec<u8>> {
+    ext::sign(message).map_err(|e| anyhow::anyhow!(e))
+}
+
+/// whether `signature` is one `from` made of `message` with [`sign`]
+pub fn verify(from: &Address, message: &[u8], signature: &[u8]) -> bool {
+    ext::verify(&from.to_string(), message, signature)
+}
+
+/// encrypt `message` so that only `node` can open it
+pub fn seal(node: &str, message: &[u8]) -> anyhow::Result<Vec<u8>> {
+    ext::seal(node, message).map_err(|e| anyhow::anyhow!(e))
+}
+
+/// decrypt a message sealed to our node with [`seal`]
+pub fn open(sealed: &[u8]) -> anyhow::Result<Vec<u8>> {
+    ext::open(sealed).map_err(|e| anyhow::anyhow!(e))
+}
@@ -0,0 +1,139 @@
+//! streamed HTTP bodies, for paths bound with `stream`: see
+//! `HttpServerAction::Bind` in the runtime. process_lib can't bind a path
+//! with `stream`, so bind it with [`bind_stream_path`].
+//!
+//! a request to such a path comes with a `stream_id` in its
+//! `IncomingHttpRequest`, and its body in [`StreamRequest::BodyChunk`]s, each
+//! of which must be answered with [`take_chunk`] before the next is sent.
+//!
+//! the response may be streamed too: [`start_response`] sends its status and
+//! headers, and [`send_chunk`] the rest of the body. the status and headers
+//! reach the client before the rest of the body is known, so they can't be
+//! changed after: a process that fails partway through can only cut the body
+//! short, with a last chunk that is `done`.
+use kinode_process_lib::{Message, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// how long http_server has to answer a bind
+const BIND_TIMEOUT: u64 = 5;
+
+/// the requests http_server sends for a streamed request body, as
+/// `HttpServerRequest` has them
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamRequest {
+    /// the next part of the body, in the blob. `done` is set on the last,
+    /// which has no bytes.
+    BodyChunk { stream_id: u64, done: bool },
+    /// the client stopped sending the body before its end
+    BodyAborted { stream_id: u64 },
+}
+
+impl StreamRequest {
+    /// the request from http_server in `body`, if it is about a streamed body
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        serde_json::from_slice(body).ok()
+    }
+}
+
+/// bind `path` as process_lib's `bind_http_path` does, streaming request and
+/// response bodies
+pub fn bind_stream_path(path: &str, authenticated: bool, local_only: bool) -> anyhow::Result<()> {
+    let request = serde_json::json!({
+        "Bind": {
+            "path": path,
+            "authenticated": authenticated,
+            "local_only": local_only,
+            "cache": false,
+            "stream": true,
+        }
+    });
+    let Message::Response { body, .. } = Request::to(("our", "http_server", "distro", "sys"))
+        .body(serde_json::to_vec(&request)?)
+        .send_and_await_response(BIND_TIMEOUT)??
+    else {
+        return Err(anyhow::anyhow!(
+            "http_server sent a request, not a response"
+        ));
+    };
+    parse_result(&body)
+}
+
+/// answer a [`StreamRequest::BodyChunk`] just received, so that the next is sent
+pub fn take_chunk() -> anyhow::Result<()> {
+    Response::new().body(vec![]).send()
+}
+
+/// answer the request just received, which has a `stream_id`, with `status`
+/// and `headers`, and a body beginning with `first` and going on in
+/// [`send_chunk`]s. the status and headers are sent at once.
+pub fn start_response(
+    status: u16,
+    headers: HashMap<String, String>,
+    first: Vec<u8>,
+) -> anyhow::Result<()> {
+    let response = serde_json::json!({
+        "status": status,
+        "headers": headers,
+        "stream": true,
+    });
+    Response::new()
+        .body(serde_json::to_vec(&response)?)
+        .blob_bytes(first)
+        .send()
+}
+
+/// send the next part of the response body of request `stream_id`. returns
+/// once the client has room for it, or fails if it has gone or `timeout`
+/// seconds pass. the body ends with the chunk that is `done`.
+pub fn send_chunk(stream_id: u64, bytes: Vec<u8>, done: bool, timeout: u64) -> anyhow::Result<()> {
+    let action = serde_json::json!({
+        "ResponseChunk": { "stream_id": stream_id, "done": done }
+    });
+    let Message::Response { body, .. } = Request::to(("our", "http_server", "distro", "sys"))
+        .body(serde_json::to_vec(&action)?)
+        .blob_bytes(bytes)
+        .send_and_await_response(timeout)??
+    else {
+        return Err(anyhow::anyhow!(
+            "http_server sent a request, not a response"
+        ));
+    };
+    parse_result(&body)
+}
+
+/// http_server answers actions with a `Result<(), HttpServerError>`
+pub(crate) fn parse_result(body: &[u8]) -> anyhow::Result<()> {
+    match serde_json::from_slice::<Result<(), serde_json::Value>>(body)? {
+        Ok(()) => Ok(()),
+        Err(e) => Err(anyhow::anyhow!("http_server: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_requests_parse_as_http_server_sends_them() {
+        assert_eq!(
+            StreamRequest::parse(br#"{"BodyChunk":{"stream_id":7,"done":false}}"#),
+            Some(StreamRequest::BodyChunk {
+                stream_id: 7,
+                done: false
+            })
+        );
+        assert_eq!(
+            StreamRequest::parse(br#"{"BodyAborted":{"stream_id":7}}"#),
+            Some(StreamRequest::BodyAborted { stream_id: 7 })
+        );
+        assert_eq!(StreamRequest::parse(br#"{"WebSocketClose":3}"#), None);
+    }
+
+    #[test]
+    fn results_are_parsed() {
+        assert!(parse_result(br#"{"Ok":null}"#).is_ok());
+        assert!(parse_result(br#"{"Err":{"StreamError":{"error":"gone"}}}"#).is_err());
+        assert!(parse_result(b"not json").is_err());
+    }
+}
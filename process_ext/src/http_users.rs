@@ -0,0 +1,142 @@
+//! users with roles, for paths bound with a `min_role`: see `UserRole` and
+//! `HttpServerAction::Bind` in the runtime. process_lib can't bind a path
+//! with `min_role`, so bind it with [`bind_path_for`] or
+//! [`bind_ws_path_for`]; a path bound without one is open to members.
+//!
+//! requests to authenticated paths carry the logged-in [`User`] that made
+//! them, which [`request_user`] reads, for a process that authorizes per user.
+use crate::http_stream::parse_result;
+use kinode_process_lib::{Message, Request};
+use serde::{Deserialize, Serialize};
+
+/// how long http_server has to answer a bind
+const BIND_TIMEOUT: u64 = 5;
+
+/// a user that can log in to the node over HTTP, as `User` in the runtime
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    pub role: UserRole,
+}
+
+/// what a [`User`] may reach, as `UserRole` in the runtime. a path bound with
+/// a `min_role` is open to users of that role and above.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum UserRole {
+    Guest,
+    #[default]
+    Member,
+    Admin,
+}
+
+/// bind `path` as process_lib's `bind_http_path` does, open only to users of
+/// `min_role` and above if `authenticated`
+pub fn bind_path_for(
+    path: &str,
+    authenticated: bool,
+    local_only: bool,
+    min_role: UserRole,
+) -> anyhow::Result<()> {
+    bind(serde_json::json!({
+        "Bind": {
+            "path": path,
+            "authenticated": authenticated,
+            "local_only": local_only,
+            "cache": false,
+            "min_role": min_role,
+        }
+    }))
+}
+
+/// bind `path` as process_lib's `bind_ws_path` does, open only to users of
+/// `min_role` and above if `authenticated`
+pub fn bind_ws_path_for(
+    path: &str,
+    authenticated: bool,
+    encrypted: bool,
+    min_role: UserRole,
+) -> anyhow::Result<()> {
+    bind(serde_json::json!({
+        "WebSocketBind": {
+            "path": path,
+            "authenticated": authenticated,
+            "encrypted": encrypted,
+            "extension": false,
+            "min_role": min_role,
+        }
+    }))
+}
+
+fn bind(action: serde_json::Value) -> anyhow::Result<()> {
+    let Message::Response { body, .. } = Request::to(("our", "http_server", "distro", "sys"))
+        .body(serde_json::to_vec(&action)?)
+        .send_and_await_response(BIND_TIMEOUT)??
+    else {
+        return Err(anyhow::anyhow!(
+            "http_server sent a request, not a response"
+        ));
+    };
+    parse_result(&body)
+}
+
+/// the logged-in user that made an HTTP request or opened a WebSocket, from
+/// the body of the request http_server sent, if there was one
+pub fn request_user(body: &[u8]) -> Option<User> {
+    #[derive(Deserialize)]
+    struct WithUser {
+        #[serde(default)]
+        user: Option<User>,
+    }
+    #[derive(Deserialize)]
+    enum HttpServerRequest {
+        Http(WithUser),
+        WebSocketOpen(WithUser),
+    }
+    match serde_json::from_slice(body).ok()? {
+        HttpServerRequest::Http(request) | HttpServerRequest::WebSocketOpen(request) => {
+            request.user
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_go_over_the_wire_as_the_runtime_has_them() {
+        assert_eq!(
+            serde_json::to_string(&UserRole::Guest).unwrap(),
+            r#""Guest""#
+        );
+        assert_eq!(UserRole::default(), UserRole::Member);
+        assert!(UserRole::Guest < UserRole::Member && UserRole::Member < UserRole::Admin);
+    }
+
+    #[test]
+    fn user_is_read_from_requests() {
+        let alice = User {
+            name: "alice".to_string(),
+            role: UserRole::Member,
+        };
+        assert_eq!(
+            request_user(
+                br#"{"Http":{"method":"GET","url":"/","user":{"name":"alice","role":"Member"}}}"#
+            ),
+            Some(alice.clone())
+        );
+        assert_eq!(
+            request_user(
+                br#"{"WebSocketOpen":{"path":"/","channel_id":1,"user":{"name":"alice","role":"Member"}}}"#
+            ),
+            Some(alice)
+        );
+        assert_eq!(
+            request_user(br#"{"Http":{"method":"GET","url":"/"}}"#),
+            None
+        );
+        assert_eq!(request_user(br#"{"WebSocketClose":1}"#), None);
+    }
+}
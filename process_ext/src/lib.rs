@@ -0,0 +1,22 @@
+//! process_ext: bindings for processes to what this runtime offers beyond the
+//! process_lib release its packages are built against. the types here mirror
+//! those of the runtime's `lib` crate, which processes can't build for wasm,
+//! and go over the wire the same way.
+//!
+//! host functions the runtime adds are declared in `wit/runtime.wit`, apart
+//! from the `kinode:process/standard` interface process_lib binds.
+wit_bindgen::generate!({
+    path: "wit",
+    world: "runtime-ext",
+});
+
+pub mod clock;
+pub mod crypto;
+pub mod http_stream;
+pub mod http_users;
+pub mod log;
+pub mod progress;
+pub mod random;
+pub mod ready;
+pub mod send_error;
+pub mod state_keys;
@@ -0,0 +1,20 @@
+//! leveled logging with the `log` host function. unlike `print_to_terminal`,
+//! each line reaches the terminal tagged with our process ID and its level,
+//! so the terminal can filter and color it per process.
+use crate::kinode::runtime::ext;
+
+/// the level of a line, as the runtime's `LogLevel` has them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// log `message` at `level`, under `target`, such as a module of the process,
+/// if it isn't empty
+pub fn log(level: Level, target: &str, message: &str) {
+    ext::log(level as u8, target, message);
+}
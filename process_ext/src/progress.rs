@@ -0,0 +1,98 @@
+//! reporting how far along a long-running operation is to the runtime's
+//! progress module, which shows it in the terminal and on the homepage, and
+//! following the operations the process asked runtime modules for: see
+//! `ProgressRequest` in the runtime.
+//!
+//! a process reports only on its own operations, with [`report`]. once
+//! [`subscribe`]d, it is sent each report on an operation it initiated, such
+//! as a large unzip into the VFS, as a request that [`ProgressUpdate::parse`]
+//! reads.
+use kinode_process_lib::{our, Address, Request};
+use serde::{Deserialize, Serialize};
+
+/// an operation, as the runtime's `Progress` has it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Progress {
+    /// tells the operation apart from the reporter's others
+    pub operation: String,
+    /// the process that asked for the operation
+    pub initiator: Address,
+    /// 0 to 100
+    pub percent: u8,
+    /// what the operation is doing, e.g. `downloading chess:sys`
+    pub label: String,
+}
+
+/// a report on an operation, as the progress module sends it to subscribers
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgressUpdate {
+    pub reporter: Address,
+    /// milliseconds since the unix epoch
+    pub updated: u64,
+    pub progress: Progress,
+}
+
+impl ProgressUpdate {
+    /// the update in a request from the progress module, if it is one
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        serde_json::from_slice(body).ok()
+    }
+}
+
+#[derive(Serialize)]
+enum ProgressRequest {
+    Report(Progress),
+    Subscribe,
+    Unsubscribe,
+}
+
+fn send(request: &ProgressRequest) -> anyhow::Result<()> {
+    Request::to(("our", "progress", "distro", "sys"))
+        .body(serde_json::to_vec(request)?)
+        .send()
+}
+
+/// report that `operation` of this process is `percent` done, at most 100,
+/// which ends it
+pub fn report(operation: &str, percent: u8, label: &str) -> anyhow::Result<()> {
+    send(&ProgressRequest::Report(Progress {
+        operation: operation.to_string(),
+        initiator: our(),
+        percent: percent.min(100),
+        label: label.to_string(),
+    }))
+}
+
+/// be sent each report on an operation this process initiated
+pub fn subscribe() -> anyhow::Result<()> {
+    send(&ProgressRequest::Subscribe)
+}
+
+pub fn unsubscribe() -> anyhow::Result<()> {
+    send(&ProgressRequest::Unsubscribe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_parse_as_the_progress_module_sends_them() {
+        let update = ProgressUpdate::parse(
+            br#"{"reporter":"node.os@vfs:distro:sys","updated":7,"progress":{"operation":"unzip","initiator":"node.os@app:app:sys","percent":40,"label":"unzipping"}}"#,
+        )
+        .unwrap();
+        assert_eq!(update.reporter.process.to_string(), "vfs:distro:sys");
+        assert_eq!(update.progress.initiator.node, "node.os");
+        assert_eq!(update.progress.percent, 40);
+        assert_eq!(ProgressUpdate::parse(br#"{"Ok":null}"#), None);
+    }
+
+    #[test]
+    fn requests_go_over_the_wire_as_the_runtime_has_them() {
+        assert_eq!(
+            serde_json::to_string(&ProgressRequest::Subscribe).unwrap(),
+            r#""Subscribe""#
+        );
+    }
+}
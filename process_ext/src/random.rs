@@ -0,0 +1,28 @@
+//! randomness from the `random-bytes` host function: the host's secure
+//! entropy, or, in a simulation booted with `--sim-seed`, bytes that are the
+//! same on every run, so that tests of a process using them are reproducible.
+use crate::kinode::runtime::ext;
+
+/// most bytes the host gives in one call
+pub const MAX_BYTES: usize = 1024 * 1024;
+
+/// fill `buffer` with random bytes
+pub fn fill(buffer: &mut [u8]) {
+    for chunk in buffer.chunks_mut(MAX_BYTES) {
+        chunk.copy_from_slice(&ext::random_bytes(chunk.len() as u64));
+    }
+}
+
+/// `len` random bytes
+pub fn bytes(len: usize) -> Vec<u8> {
+    let mut buffer = vec![0; len];
+    fill(&mut buffer);
+    buffer
+}
+
+/// a random `u64`
+pub fn next_u64() -> u64 {
+    let mut buffer = [0; 8];
+    fill(&mut buffer);
+    u64::from_le_bytes(buffer)
+}
@@ -0,0 +1,12 @@
+//! readiness, for processes others depend on. a manifest entry may list
+//! processes in `depends_on`, and the kernel starts it only once they are
+//! ready: when they call [`ready`], or else when they first wait for a
+//! message. a process with setup to do before it can serve, such as
+//! binding its paths, should call [`ready`] once that is done.
+use crate::kinode::runtime::ext;
+
+/// tell the kernel we have finished setting up. calls after the first do
+/// nothing.
+pub fn ready() {
+    ext::ready();
+}
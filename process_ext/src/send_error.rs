@@ -0,0 +1,91 @@
+//! what went wrong with a message we sent, beyond the `Offline` and
+//! `Timeout` of a `SendError`: call [`last_send_error`] right after
+//! receiving one to tell, say, a peer that is offline apart from a target
+//! process that doesn't exist, and [`last_send_error_trace`] to learn how
+//! a peer that couldn't be reached was tried.
+use crate::kinode::runtime::ext;
+
+/// the hop at which a message failed, or for a timeout, the furthest hop it
+/// was headed for, as the runtime's `SendErrorHop` has them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hop {
+    LocalKernel,
+    LocalNet,
+    RemoteRouter,
+    RemoteKernel,
+}
+
+/// why a message failed, as the runtime's `SendErrorCause` has them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cause {
+    Unknown,
+    /// no response came in time
+    Timeout,
+    /// the target node couldn't be reached, directly or through its routers
+    PeerOffline,
+    /// the target node isn't in our view of the PKI
+    PeerUnknown,
+    /// the target process doesn't exist, or has exited
+    TargetMissing,
+    /// we lack the capability to send the message
+    CapabilityDenied,
+    /// the message was larger than allowed
+    TooLarge,
+    /// we are a dry run, so the message was reported instead of sent
+    DryRun,
+}
+
+impl Hop {
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => Hop::LocalNet,
+            2 => Hop::RemoteRouter,
+            3 => Hop::RemoteKernel,
+            _ => Hop::LocalKernel,
+        }
+    }
+}
+
+impl Cause {
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => Cause::Timeout,
+            2 => Cause::PeerOffline,
+            3 => Cause::PeerUnknown,
+            4 => Cause::TargetMissing,
+            5 => Cause::CapabilityDenied,
+            6 => Cause::TooLarge,
+            7 => Cause::DryRun,
+            _ => Cause::Unknown,
+        }
+    }
+}
+
+/// if the message we last received was a send error, the hop it failed at
+/// and why
+pub fn last_send_error() -> Option<(Hop, Cause)> {
+    let (hop, cause) = ext::last_send_error()?;
+    Some((Hop::from_code(hop), Cause::from_code(cause)))
+}
+
+/// if the message we last received was a send error for a node that
+/// couldn't be reached, how it was tried, written out for people: whether a
+/// KNS record was found for it, and the hosts and routers tried
+pub fn last_send_error_trace() -> Option<String> {
+    ext::last_send_error_trace()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_match_the_runtime() {
+        assert_eq!(Hop::from_code(0), Hop::LocalKernel);
+        assert_eq!(Hop::from_code(3), Hop::RemoteKernel);
+        assert_eq!(Cause::from_code(0), Cause::Unknown);
+        assert_eq!(Cause::from_code(4), Cause::TargetMissing);
+        assert_eq!(Cause::from_code(7), Cause::DryRun);
+        assert_eq!(Cause::from_code(200), Cause::Unknown);
+    }
+}
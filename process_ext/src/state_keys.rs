@@ -0,0 +1,38 @@
+//! keyed state: parts of a process's state persisted under their own keys,
+//! so that changing one doesn't rewrite all of it as `set_state` does. keys
+//! are kept beside the state saved with `set_state`, and `clear_state`
+//! deletes them with it.
+use crate::kinode::runtime::ext;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// the bytes saved under `key`, if any
+pub fn get(key: &str) -> Option<Vec<u8>> {
+    ext::get_state_key(key)
+}
+
+/// the value saved as JSON under `key`, if any and it parses
+pub fn get_json<T: DeserializeOwned>(key: &str) -> Option<T> {
+    serde_json::from_slice(&get(key)?).ok()
+}
+
+/// save `bytes` under `key`
+pub fn set(key: &str, bytes: Vec<u8>) -> anyhow::Result<()> {
+    update([(key.to_string(), Some(bytes))])
+}
+
+/// save `value` as JSON under `key`
+pub fn set_json<T: Serialize>(key: &str, value: &T) -> anyhow::Result<()> {
+    set(key, serde_json::to_vec(value)?)
+}
+
+/// delete what is saved under `key`
+pub fn remove(key: &str) -> anyhow::Result<()> {
+    update([(key.to_string(), None)])
+}
+
+/// set each key to its bytes, or delete it if it has none, all at once:
+/// either every change is saved or none is
+pub fn update(changes: impl IntoIterator<Item = (String, Option<Vec<u8>>)>) -> anyhow::Result<()> {
+    let changes: Vec<(String, Option<Vec<u8>>)> = changes.into_iter().collect();
+    ext::update_state(&changes).map_err(|e| anyhow::anyhow!(e))
+}
@@ -0,0 +1,249 @@
+//! script_args: split the line a terminal script is run with into arguments
+//! and flags, the same way for every script.
+//!
+//! arguments are separated by whitespace. single quotes take everything up to
+//! the closing quote literally; double quotes do too, except that `\"` and `\\`
+//! are escapes; and outside quotes, `\` escapes the next character.
+//!
+//! flags are `--name`, `-n`, or `--name=value`. a flag the script names as
+//! taking a value also takes the argument after it, as in `-a 5`. everything
+//! after `--` is positional.
+//!
+//! the terminal runs `a | b` by passing the output of `a` to `b` as its stdin:
+//! the blob of the request carrying `b`'s arguments, with a mime type. output
+//! that isn't a blob is passed on as `text/plain`.
+//!
+//! scripts may style what they print with [`style`].
+use std::collections::HashMap;
+
+pub mod style;
+
+#[derive(Debug, Default)]
+pub struct Args {
+    /// the line as given, whitespace, quotes and all
+    pub raw: String,
+    /// arguments that aren't flags, in order
+    pub positional: Vec<String>,
+    /// flags by name, without dashes, with their value if they took one
+    pub flags: HashMap<String, Option<String>>,
+    /// the output of the previous stage of a pipeline, if any
+    pub stdin: Option<Stdin>,
+}
+
+#[derive(Debug)]
+pub struct Stdin {
+    pub mime: Option<String>,
+    pub bytes: Vec<u8>,
+}
+
+impl Stdin {
+    /// the bytes as text, if they are any kind of text
+    pub fn text(&self) -> Option<&str> {
+        match &self.mime {
+            Some(mime) if !mime.starts_with("text/") && mime != "application/json" => None,
+            _ => std::str::from_utf8(&self.bytes).ok(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArgsError {
+    UnclosedQuote(char),
+    TrailingEscape,
+    MissingValue(String),
+}
+
+impl std::fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgsError::UnclosedQuote(quote) => write!(f, "unclosed {quote} in arguments"),
+            ArgsError::TrailingEscape => write!(f, "arguments end with an unfinished \\"),
+            ArgsError::MissingValue(flag) => write!(f, "flag {flag} needs a value"),
+        }
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+impl Args {
+    /// parse `line`, where the flags named in `value_flags` take a value
+    pub fn parse(line: &str, value_flags: &[&str]) -> Result<Self, ArgsError> {
+        let mut args = Args {
+            raw: line.to_string(),
+            ..Default::default()
+        };
+        let mut tokens = tokenize(line)?.into_iter();
+        while let Some(token) = tokens.next() {
+            if token == "--" {
+                args.positional.extend(tokens);
+                break;
+            }
+            let Some(name) = flag_name(&token) else {
+                args.positional.push(token);
+                continue;
+            };
+            let value = match name.split_once('=') {
+                Some((name, value)) => {
+                    args.flags.insert(name.to_string(), Some(value.to_string()));
+                    continue;
+                }
+                None if value_flags.contains(&name) => Some(
+                    tokens
+                        .next()
+                        .ok_or_else(|| ArgsError::MissingValue(token.clone()))?,
+                ),
+                None => None,
+            };
+            args.flags.insert(name.to_string(), value);
+        }
+        Ok(args)
+    }
+
+    /// whether any of these flags was given
+    pub fn has(&self, names: &[&str]) -> bool {
+        names.iter().any(|name| self.flags.contains_key(*name))
+    }
+
+    /// the value of the first of these flags given with one
+    pub fn value(&self, names: &[&str]) -> Option<&str> {
+        names
+            .iter()
+            .find_map(|name| self.flags.get(*name)?.as_deref())
+    }
+}
+
+/// `name` for `--name` or `-n`. a lone `-`, and negative numbers, aren't flags.
+fn flag_name(token: &str) -> Option<&str> {
+    let name = token
+        .strip_prefix("--")
+        .or_else(|| token.strip_prefix('-'))?;
+    match name.chars().next() {
+        Some(c) if !c.is_ascii_digit() => Some(name),
+        _ => None,
+    }
+}
+
+/// split a line into the commands of a pipeline at each `|` outside quotes
+pub fn split_pipeline(line: &str) -> Result<Vec<&str>, ArgsError> {
+    let mut stages = vec![];
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some('"'), '\\') | (None, '\\') => {
+                chars.next();
+            }
+            (Some(open), c) if c == open => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '|') => {
+                stages.push(line[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if let Some(open) = quote {
+        return Err(ArgsError::UnclosedQuote(open));
+    }
+    stages.push(line[start..].trim());
+    Ok(stages)
+}
+
+/// split a line into arguments, honoring quotes and escapes
+pub fn tokenize(line: &str) -> Result<Vec<String>, ArgsError> {
+    let mut tokens = vec![];
+    // the argument being built, if one has started: `""` starts an empty one
+    let mut current: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                tokens.extend(current.take());
+            }
+            '\'' => {
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => token.push(c),
+                        None => return Err(ArgsError::UnclosedQuote('\'')),
+                    }
+                }
+            }
+            '"' => {
+                let token = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => token.push(c),
+                            Some(c) => {
+                                token.push('\\');
+                                token.push(c);
+                            }
+                            None => return Err(ArgsError::UnclosedQuote('"')),
+                        },
+                        Some(c) => token.push(c),
+                        None => return Err(ArgsError::UnclosedQuote('"')),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => current.get_or_insert_with(String::new).push(c),
+                None => return Err(ArgsError::TrailingEscape),
+            },
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    tokens.extend(current);
+    Ok(tokens)
+}
+
+/// like `kinode_process_lib::script!`, but `init` gets parsed [`Args`], with
+/// any stdin, instead of the raw line. name the flags that take a value after
+/// `init`:
+///
+/// ```ignore
+/// script_args::script!(init, ["a", "await"]);
+/// fn init(our: Address, args: Args) -> String { ... }
+/// ```
+///
+/// if the line can't be parsed, the script replies with why instead. a
+/// script that takes its line as given, such as `echo`, is declared with
+/// `script!(init, raw)`: the line is not parsed, and is only in [`Args::raw`].
+#[macro_export]
+macro_rules! script {
+    ($init_func:ident) => {
+        $crate::script!($init_func, []);
+    };
+    ($init_func:ident, raw) => {
+        fn __init_with_args(our: kinode_process_lib::Address, line: String) -> String {
+            let args = $crate::Args {
+                raw: line,
+                stdin: kinode_process_lib::get_blob().map(|blob| $crate::Stdin {
+                    mime: blob.mime,
+                    bytes: blob.bytes,
+                }),
+                ..Default::default()
+            };
+            $init_func(our, args)
+        }
+        kinode_process_lib::script!(__init_with_args);
+    };
+    ($init_func:ident, [$($value_flag:expr),* $(,)?]) => {
+        fn __init_with_args(our: kinode_process_lib::Address, line: String) -> String {
+            match $crate::Args::parse(&line, &[$($value_flag),*]) {
+                Ok(mut args) => {
+                    args.stdin = kinode_process_lib::get_blob().map(|blob| $crate::Stdin {
+                        mime: blob.mime,
+                        bytes: blob.bytes,
+                    });
+                    $init_func(our, args)
+                }
+                Err(e) => format!("{e}"),
+            }
+        }
+        kinode_process_lib::script!(__init_with_args);
+    };
+}
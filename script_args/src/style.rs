@@ -0,0 +1,165 @@
+//! styles for what scripts print: bold, colors and the like, as the ANSI
+//! escape sequences the terminal shows. the terminal strips them from output
+//! piped to another script or written to a file, and drops any other escape
+//! sequence a script prints, such as one moving the cursor.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Gray,
+}
+
+impl Color {
+    fn code(self) -> u8 {
+        match self {
+            Color::Black => 30,
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Blue => 34,
+            Color::Magenta => 35,
+            Color::Cyan => 36,
+            Color::White => 37,
+            Color::Gray => 90,
+        }
+    }
+}
+
+pub fn bold(text: &str) -> String {
+    format!("\x1b[1m{text}\x1b[22m")
+}
+
+pub fn dim(text: &str) -> String {
+    format!("\x1b[2m{text}\x1b[22m")
+}
+
+pub fn italic(text: &str) -> String {
+    format!("\x1b[3m{text}\x1b[23m")
+}
+
+pub fn underline(text: &str) -> String {
+    format!("\x1b[4m{text}\x1b[24m")
+}
+
+pub fn color(text: &str, color: Color) -> String {
+    format!("\x1b[{}m{text}\x1b[39m", color.code())
+}
+
+/// `text` without its styles
+pub fn strip(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            // parameters, then one final character in `@` to `~`
+            chars.next();
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+            continue;
+        }
+        stripped.push(c);
+    }
+    stripped
+}
+
+/// the characters `text` shows once its styles are stripped
+pub fn width(text: &str) -> usize {
+    strip(text).chars().count()
+}
+
+/// rows of cells as a table under a bold header, each column padded to its
+/// widest cell. cells may be styled.
+pub fn table<S: AsRef<str>>(header: &[&str], rows: &[Vec<S>]) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|cell| width(cell)).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            match widths.get_mut(i) {
+                Some(widest) => *widest = (*widest).max(width(cell.as_ref())),
+                None => widths.push(width(cell.as_ref())),
+            }
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        let last = cells.len().saturating_sub(1);
+        cells
+            .into_iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                // the last column isn't padded, so that rows don't end in spaces
+                let padding = if i == last {
+                    0
+                } else {
+                    widths[i] - width(cell)
+                };
+                format!("{cell}{}", " ".repeat(padding))
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    let mut table = bold(&line(header.to_vec()));
+    for row in rows {
+        table.push_str("\r\n");
+        table.push_str(&line(row.iter().map(|cell| cell.as_ref()).collect()));
+    }
+    table
+}
+
+/// a bar `width` characters wide, filled in the proportion `done` is of
+/// `total`, followed by that as a percentage
+pub fn progress_bar(done: u64, total: u64, width: usize) -> String {
+    let fraction = if total == 0 {
+        1.0
+    } else {
+        done.min(total) as f64 / total as f64
+    };
+    let filled = (fraction * width as f64).round() as usize;
+    format!(
+        "[{}{}] {:>3}%",
+        color(&"#".repeat(filled), Color::Green),
+        " ".repeat(width - filled),
+        (fraction * 100.0).round() as u64
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_removes_styles_only() {
+        let styled = format!("{} and {}", bold("bold"), color("red", Color::Red));
+        assert_eq!(strip(&styled), "bold and red");
+        assert_eq!(width(&styled), 12);
+        assert_eq!(strip("plain [text] 100%"), "plain [text] 100%");
+    }
+
+    #[test]
+    fn table_pads_columns_to_widest_cell() {
+        let rows = vec![
+            vec![color("ok", Color::Green), "1".to_string()],
+            vec!["missing".to_string(), "22".to_string()],
+        ];
+        let table = table(&["name", "n"], &rows);
+        let lines: Vec<String> = table.split("\r\n").map(strip).collect();
+        assert_eq!(lines, vec!["name     n", "ok       1", "missing  22"]);
+        assert!(table.starts_with("\x1b[1m"));
+    }
+
+    #[test]
+    fn progress_bar_fills_in_proportion() {
+        assert_eq!(strip(&progress_bar(1, 4, 8)), "[##      ]  25%");
+        assert_eq!(strip(&progress_bar(9, 4, 4)), "[####] 100%");
+        assert_eq!(strip(&progress_bar(0, 0, 2)), "[##] 100%");
+        assert_eq!(strip(&progress_bar(0, 10, 3)), "[   ]   0%");
+    }
+}